@@ -1,10 +1,25 @@
-use crate::decoding::Read;
+use crate::decoding::{CheckedCursor, DecodeError, DecoderOptions, Read};
 use crate::encoding::Write;
-use std::cmp::PartialEq;
+use crate::json_parser::{self, JsonParseError, JsonParser};
+use crate::number::{F64_MAX_SAFE_INTEGER, F64_MIN_SAFE_INTEGER};
+use std::cmp::{Ordering, PartialEq};
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::iter::FromIterator;
 
-#[derive(Debug, Clone, PartialEq)]
+/// Maximum depth of nested arrays and maps accepted by [Any::decode_checked] and
+/// [Any::from_json_str].
+pub(crate) const MAX_NESTING_DEPTH: usize = 256;
+
+/// A dynamically typed value, compatible with Yjs `Any` type.
+///
+/// Two values are equal only if they are of the same variant and have equal contents, therefore
+/// `Any::Number(1.0) != Any::BigInt(1)` - use [Any::loosely_eq] to compare numbers regardless of
+/// their representation. Unlike `f64`, `Any::Number(f64::NAN)` is equal to itself, which makes
+/// [Any] usable as a key of sorted collections. For the same reason, [Any] also implements [Ord]:
+/// values of different variants are ordered by their variant (in order of declaration), while
+/// values of the same variant are ordered by their contents.
+#[derive(Debug, Clone)]
 pub enum Any {
     Null,
     Undefined,
@@ -18,6 +33,220 @@ pub enum Any {
 }
 
 impl Any {
+    /// Returns a human-readable name of a variant of current [Any] value, ie. `"string"` for
+    /// [Any::String]. Used mostly for error reporting.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Any::Null => "null",
+            Any::Undefined => "undefined",
+            Any::Bool(_) => "bool",
+            Any::Number(_) => "number",
+            Any::BigInt(_) => "bigint",
+            Any::String(_) => "string",
+            Any::Buffer(_) => "buffer",
+            Any::Array(_) => "array",
+            Any::Map(_) => "map",
+        }
+    }
+
+    /// Checks if current value is equal to `other`, treating [Any::Number] and [Any::BigInt]
+    /// representing the same integer as equal, ie. `Any::Number(1.0)` and `Any::BigInt(1)`.
+    /// Nested arrays and maps are compared the same way.
+    pub fn loosely_eq(&self, other: &Any) -> bool {
+        match (self, other) {
+            (Any::Number(a), Any::BigInt(b)) | (Any::BigInt(b), Any::Number(a)) => {
+                // 2^63 itself is not a valid i64, but `i64::MAX as f64` rounds up to it
+                a.fract() == 0.0 && *a >= i64::MIN as f64 && *a < i64::MAX as f64 && *a as i64 == *b
+            }
+            (Any::Array(a), Any::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.loosely_eq(b))
+            }
+            (Any::Map(a), Any::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, a)| b.get(key).map(|b| a.loosely_eq(b)).unwrap_or(false))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Returns a string slice if current value is an [Any::String].
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Any::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns a boolean if current value is an [Any::Bool].
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Any::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns a floating point number if current value is an [Any::Number] or an [Any::BigInt],
+    /// which can be represented by `f64` without loss of precision.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Any::Number(value) => Some(*value),
+            Any::BigInt(value)
+                if (*value as f64) >= F64_MIN_SAFE_INTEGER
+                    && (*value as f64) <= F64_MAX_SAFE_INTEGER =>
+            {
+                Some(*value as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns an integer if current value is an [Any::BigInt] or an [Any::Number] without
+    /// fractional part, which lies within a safe integer range (±2^53-1).
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Any::BigInt(value) => Some(*value),
+            Any::Number(value) if value.fract() == 0.0 && value.abs() <= F64_MAX_SAFE_INTEGER => {
+                Some(*value as i64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a slice of elements if current value is an [Any::Array].
+    pub fn as_array(&self) -> Option<&[Any]> {
+        match self {
+            Any::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Returns map entries if current value is an [Any::Map].
+    pub fn as_map(&self) -> Option<&HashMap<String, Any>> {
+        match self {
+            Any::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Returns a value nested under a given dot-separated `path`, ie. `"users.0.name"`. Every path
+    /// segment is either a key of an [Any::Map] or an index of an [Any::Array] element. Returns
+    /// `None` if any of the path segments could not be resolved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lib0::any::Any;
+    ///
+    /// let value = Any::from_json_str(r#"{"users":[{"name":"Alice"}]}"#).unwrap();
+    /// assert_eq!(value.get("users.0.name").and_then(Any::as_str), Some("Alice"));
+    /// assert_eq!(value.get("users.1.name"), None);
+    /// ```
+    pub fn get(&self, path: &str) -> Option<&Any> {
+        let mut current = self;
+        for segment in path.split('.') {
+            current = match current {
+                Any::Map(entries) => entries.get(segment)?,
+                Any::Array(values) => values.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Position of a current value variant, used to order values of different variants.
+    fn rank(&self) -> u8 {
+        match self {
+            Any::Null => 0,
+            Any::Undefined => 1,
+            Any::Bool(_) => 2,
+            Any::Number(_) => 3,
+            Any::BigInt(_) => 4,
+            Any::String(_) => 5,
+            Any::Buffer(_) => 6,
+            Any::Array(_) => 7,
+            Any::Map(_) => 8,
+        }
+    }
+
+    /// Serializes current value into a JSON string. [Any::Null] and [Any::Undefined] become
+    /// `null`, [Any::Buffer] becomes an array of byte values and map entries are ordered by their
+    /// keys. Non-finite numbers are serialized as `null`, just like `JSON.stringify` does.
+    pub fn to_json_string(&self) -> String {
+        let mut buf = String::new();
+        json_parser::write_json(self, &mut buf);
+        buf
+    }
+
+    /// Parses a given JSON string into an [Any] value. Integer literals fitting into `i64` are
+    /// parsed as [Any::BigInt], while all other numbers are parsed as [Any::Number].
+    pub fn from_json_str(src: &str) -> Result<Any, JsonParseError> {
+        JsonParser::new(src).parse()
+    }
+
+    /// Decodes an [Any] value from an untrusted `buf`, checking it against given `options`
+    /// before allocating any memory. Returns an error if a payload is malformed or it declares
+    /// lengths exceeding the configured limits.
+    pub fn try_decode(buf: &[u8], options: &DecoderOptions) -> Result<Self, DecodeError> {
+        Self::decode_checked(&mut CheckedCursor::new(buf, *options))
+    }
+
+    /// Decodes the next [Any] value from a given `cursor`. Unlike [Any::decode] it never panics,
+    /// returning an error if a payload is malformed or exceeds limits configured by a `cursor`.
+    pub fn decode_checked(cursor: &mut CheckedCursor) -> Result<Self, DecodeError> {
+        Self::decode_nested(cursor, 0)
+    }
+
+    fn decode_nested(cursor: &mut CheckedCursor, depth: usize) -> Result<Self, DecodeError> {
+        // decoding is recursive, deeply nested payloads could overflow the stack
+        if depth > MAX_NESTING_DEPTH {
+            return Err(DecodeError::InvalidData("values are nested too deeply"));
+        }
+        let value = match cursor.read_u8()? {
+            127 => Any::Undefined,
+            126 => Any::Null,
+            125 => Any::Number(cursor.read_ivar()? as f64),
+            124 => {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(cursor.read(4)?);
+                Any::Number(f32::from_be_bytes(buf) as f64)
+            }
+            123 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(cursor.read(8)?);
+                Any::Number(f64::from_be_bytes(buf))
+            }
+            122 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(cursor.read(8)?);
+                Any::BigInt(i64::from_be_bytes(buf))
+            }
+            121 => Any::Bool(false),
+            120 => Any::Bool(true),
+            119 => Any::String(cursor.read_string()?.to_owned()),
+            118 => {
+                let len = cursor.read_collection_len()? as usize;
+                let mut map = HashMap::with_capacity(len);
+                for _ in 0..len {
+                    let key = cursor.read_string()?.to_owned();
+                    map.insert(key, Self::decode_nested(cursor, depth + 1)?);
+                }
+                Any::Map(map)
+            }
+            117 => {
+                let len = cursor.read_collection_len()? as usize;
+                let mut arr = Vec::with_capacity(len);
+                for _ in 0..len {
+                    arr.push(Self::decode_nested(cursor, depth + 1)?);
+                }
+                Any::Array(arr)
+            }
+            116 => Any::Buffer(Box::from(cursor.read_buf()?)),
+            _ => return Err(DecodeError::InvalidData("unknown Any type tag")),
+        };
+        Ok(value)
+    }
+
     pub fn decode<R: Read>(decoder: &mut R) -> Self {
         match decoder.read_u8() {
             // CASE 127: undefined
@@ -205,6 +434,67 @@ impl std::fmt::Display for Any {
     }
 }
 
+impl PartialEq for Any {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Any {}
+
+impl PartialOrd for Any {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Any {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Any::Null, Any::Null) | (Any::Undefined, Any::Undefined) => Ordering::Equal,
+            (Any::Bool(a), Any::Bool(b)) => a.cmp(b),
+            (Any::Number(a), Any::Number(b)) => cmp_f64(*a, *b),
+            (Any::BigInt(a), Any::BigInt(b)) => a.cmp(b),
+            (Any::String(a), Any::String(b)) => a.cmp(b),
+            (Any::Buffer(a), Any::Buffer(b)) => a.cmp(b),
+            (Any::Array(a), Any::Array(b)) => a.cmp(b),
+            (Any::Map(a), Any::Map(b)) => {
+                // maps are compared as arrays of their entries sorted by key
+                let mut a: Vec<_> = a.iter().collect();
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort_by(|x, y| x.0.cmp(y.0));
+                b.sort_by(|x, y| x.0.cmp(y.0));
+                a.cmp(&b)
+            }
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+/// Total ordering of floating point numbers, where `0.0` and `-0.0` are equal and all `NaN`
+/// values are equal to each other and greater than any other number.
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    match a.partial_cmp(&b) {
+        Some(ordering) => ordering,
+        None => a.is_nan().cmp(&b.is_nan()),
+    }
+}
+
+impl FromIterator<Any> for Any {
+    /// Collects values into an [Any::Array].
+    fn from_iter<I: IntoIterator<Item = Any>>(iter: I) -> Self {
+        Any::Array(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<(String, Any)> for Any {
+    /// Collects key-value pairs into an [Any::Map]. If the same key occurs more than once, the
+    /// last value wins.
+    fn from_iter<I: IntoIterator<Item = (String, Any)>>(iter: I) -> Self {
+        Any::Map(iter.into_iter().collect())
+    }
+}
+
 impl Into<Any> for bool {
     fn into(self) -> Any {
         Any::Bool(self)
@@ -235,6 +525,32 @@ impl Into<Any> for i32 {
     }
 }
 
+impl Into<Any> for i16 {
+    fn into(self) -> Any {
+        Any::Number(self as f64)
+    }
+}
+
+impl Into<Any> for u16 {
+    fn into(self) -> Any {
+        Any::Number(self as f64)
+    }
+}
+
+impl Into<Any> for i8 {
+    fn into(self) -> Any {
+        Any::Number(self as f64)
+    }
+}
+
+impl Into<Any> for i64 {
+    /// 64-bit integers don't fit into a JavaScript number, therefore they're always represented
+    /// as [Any::BigInt].
+    fn into(self) -> Any {
+        Any::BigInt(self)
+    }
+}
+
 impl Into<Any> for String {
     fn into(self) -> Any {
         Any::String(self)
@@ -327,3 +643,122 @@ impl TryInto<Any> for usize {
         (self as u64).try_into()
     }
 }
+
+/// Error returned when a value could not be converted into a requested type, because it either
+/// contained a different kind of data or because conversion would be lossy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMismatch {
+    /// Name of the type, conversion was requested for.
+    pub expected: &'static str,
+    /// Description of the value, that was found instead.
+    pub found: &'static str,
+}
+
+impl TypeMismatch {
+    pub fn new(expected: &'static str, found: &'static str) -> Self {
+        TypeMismatch { expected, found }
+    }
+}
+
+impl std::fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {} but found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for TypeMismatch {}
+
+impl TryFrom<Any> for bool {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::Bool(v) => Ok(v),
+            other => Err(TypeMismatch::new("bool", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for String {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::String(v) => Ok(v),
+            other => Err(TypeMismatch::new("string", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for f64 {
+    type Error = TypeMismatch;
+
+    /// Converts [Any::Number] directly. [Any::BigInt] is accepted only if it fits within
+    /// a range of integers, which can be represented by `f64` without loss of precision.
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::Number(v) => Ok(v),
+            Any::BigInt(v)
+                if (v as f64) >= F64_MIN_SAFE_INTEGER && (v as f64) <= F64_MAX_SAFE_INTEGER =>
+            {
+                Ok(v as f64)
+            }
+            Any::BigInt(_) => Err(TypeMismatch::new("f64", "bigint outside of f64 precision")),
+            other => Err(TypeMismatch::new("f64", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for i64 {
+    type Error = TypeMismatch;
+
+    /// Converts [Any::BigInt] directly. [Any::Number] is accepted only if it has no fractional
+    /// part and it lies within a safe integer range (±2^53-1).
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::BigInt(v) => Ok(v),
+            Any::Number(v) if v.fract() != 0.0 || !v.is_finite() => {
+                Err(TypeMismatch::new("i64", "number with fractional part"))
+            }
+            Any::Number(v) if v.abs() > F64_MAX_SAFE_INTEGER => Err(TypeMismatch::new(
+                "i64",
+                "number outside of safe integer range",
+            )),
+            Any::Number(v) => Ok(v as i64),
+            other => Err(TypeMismatch::new("i64", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for Vec<u8> {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::Buffer(v) => Ok(v.into_vec()),
+            other => Err(TypeMismatch::new("buffer", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for Vec<Any> {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::Array(v) => Ok(v),
+            other => Err(TypeMismatch::new("array", other.kind())),
+        }
+    }
+}
+
+impl TryFrom<Any> for HashMap<String, Any> {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        match value {
+            Any::Map(v) => Ok(v),
+            other => Err(TypeMismatch::new("map", other.kind())),
+        }
+    }
+}
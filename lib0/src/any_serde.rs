@@ -0,0 +1,174 @@
+use crate::any::{Any, TypeMismatch};
+use serde::de::{Error, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Formatter;
+
+/// [Any::Buffer] is serialized as bytes, so it's deserialized back into [Any::Buffer] by formats
+/// supporting binary data. Formats without binary type (like JSON) serialize it as a sequence of
+/// byte values instead, which is deserialized into [Any::Array].
+impl Serialize for Any {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Any::Null | Any::Undefined => serializer.serialize_unit(),
+            Any::Bool(v) => serializer.serialize_bool(*v),
+            Any::Number(v) => serializer.serialize_f64(*v),
+            Any::BigInt(v) => serializer.serialize_i64(*v),
+            Any::String(v) => serializer.serialize_str(v.as_str()),
+            Any::Buffer(v) => serializer.serialize_bytes(v),
+            Any::Array(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values.iter() {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Any::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries.iter() {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Any {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AnyVisitor)
+    }
+}
+
+struct AnyVisitor;
+
+impl<'de> Visitor<'de> for AnyVisitor {
+    type Value = Any;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "any lib0 compatible value")
+    }
+
+    fn visit_bool<E: Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Any::Bool(v))
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Any::BigInt(v))
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+        match i64::try_from(v) {
+            Ok(v) => Ok(Any::BigInt(v)),
+            Err(_) => Ok(Any::Number(v as f64)),
+        }
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Any::Number(v))
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Any::String(v.to_owned()))
+    }
+
+    fn visit_string<E: Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Any::String(v))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Any::Buffer(v.into()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(Any::Buffer(v.into_boxed_slice()))
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(Any::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Any::deserialize(deserializer)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+        Ok(Any::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Any::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        Ok(Any::Map(entries))
+    }
+}
+
+impl From<serde_json::Value> for Any {
+    /// Converts JSON value into [Any]. Integers fitting into `i64` are converted into
+    /// [Any::BigInt], while all other numbers become [Any::Number].
+    fn from(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => Any::Null,
+            Value::Bool(v) => Any::Bool(v),
+            Value::Number(v) => match v.as_i64() {
+                Some(i) => Any::BigInt(i),
+                None => Any::Number(v.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(v) => Any::String(v),
+            Value::Array(values) => Any::Array(values.into_iter().map(Any::from).collect()),
+            Value::Object(entries) => Any::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, Any::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl TryFrom<Any> for serde_json::Value {
+    type Error = TypeMismatch;
+
+    /// Converts [Any] into JSON value, using the same rules as [Any::to_json_string]. Conversion
+    /// fails if any of the numbers is not finite, since they cannot be represented in JSON.
+    fn try_from(value: Any) -> Result<Self, Self::Error> {
+        use serde_json::{Number, Value};
+        Ok(match value {
+            Any::Null | Any::Undefined => Value::Null,
+            Any::Bool(v) => Value::Bool(v),
+            Any::Number(v) => match Number::from_f64(v) {
+                Some(n) => Value::Number(n),
+                None => return Err(TypeMismatch::new("JSON number", "non-finite number")),
+            },
+            Any::BigInt(v) => Value::Number(v.into()),
+            Any::String(v) => Value::String(v),
+            Any::Buffer(v) => Value::Array(v.iter().map(|b| Value::from(*b)).collect()),
+            Any::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Any::Map(entries) => {
+                let mut map = serde_json::Map::with_capacity(entries.len());
+                for (key, value) in entries {
+                    map.insert(key, Value::try_from(value)?);
+                }
+                Value::Object(map)
+            }
+        })
+    }
+}
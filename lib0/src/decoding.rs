@@ -158,6 +158,279 @@ pub trait Read {
     }
 }
 
+/// Limits applied to the lengths declared by a decoded payload. Decoders allocate memory upfront,
+/// based on the length prefixes they read, so untrusted payloads should be validated against
+/// these limits before being decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderOptions {
+    /// Maximum number of elements of a single collection (eg. an array, a map, a list of blocks
+    /// or clients).
+    pub max_collection_len: u32,
+    /// Maximum length (in bytes) of a single buffer or string.
+    pub max_buf_len: u32,
+    /// Maximum number of all elements (including nested ones) declared by an entire payload.
+    pub max_total_items: u32,
+}
+
+impl DecoderOptions {
+    /// Options, which don't limit declared lengths at all. Payloads are still checked for
+    /// being well-formed.
+    pub fn unlimited() -> Self {
+        DecoderOptions {
+            max_collection_len: u32::MAX,
+            max_buf_len: u32::MAX,
+            max_total_items: u32::MAX,
+        }
+    }
+}
+
+impl Default for DecoderOptions {
+    fn default() -> Self {
+        DecoderOptions {
+            max_collection_len: 1 << 20,
+            max_buf_len: 1 << 26,
+            max_total_items: 1 << 24,
+        }
+    }
+}
+
+/// Error returned when a payload could not be decoded, either because it's malformed or because
+/// it exceeds limits defined by [DecoderOptions].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Payload ended before all of the declared data could be read.
+    UnexpectedEnd,
+    /// Variable length integer doesn't fit into its expected type.
+    VarIntOverflow,
+    /// String is not a valid UTF-8 sequence.
+    InvalidUtf8,
+    /// Declared collection length exceeds [DecoderOptions::max_collection_len].
+    CollectionTooLong { len: u64, max: u32 },
+    /// Declared buffer or string length exceeds [DecoderOptions::max_buf_len].
+    BufferTooLong { len: u64, max: u32 },
+    /// Total number of elements exceeds [DecoderOptions::max_total_items].
+    TooManyItems { max: u32 },
+    /// Payload contains data not recognized by a decoder.
+    InvalidData(&'static str),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of payload"),
+            DecodeError::VarIntOverflow => write!(f, "variable length integer overflow"),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::CollectionTooLong { len, max } => write!(
+                f,
+                "declared collection length {} exceeds the limit of {}",
+                len, max
+            ),
+            DecodeError::BufferTooLong { len, max } => write!(
+                f,
+                "declared buffer length {} exceeds the limit of {}",
+                len, max
+            ),
+            DecodeError::TooManyItems { max } => {
+                write!(f, "payload declares more than {} items", max)
+            }
+            DecodeError::InvalidData(msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A cursor used to validate untrusted payloads before decoding them. Unlike [Cursor], it never
+/// panics: all reads return an error if a payload ends prematurely, is malformed or declares
+/// lengths exceeding configured [DecoderOptions].
+pub struct CheckedCursor<'a> {
+    buf: &'a [u8],
+    next: usize,
+    options: DecoderOptions,
+    items: u32,
+}
+
+impl<'a> From<&'a [u8]> for CheckedCursor<'a> {
+    /// Creates a cursor, which doesn't limit declared lengths (see: [DecoderOptions::unlimited]),
+    /// but still returns errors for truncated or malformed payloads.
+    fn from(buf: &'a [u8]) -> Self {
+        Self::new(buf, DecoderOptions::unlimited())
+    }
+}
+
+impl<'a> CheckedCursor<'a> {
+    pub fn new(buf: &'a [u8], options: DecoderOptions) -> Self {
+        CheckedCursor {
+            buf,
+            next: 0,
+            options,
+            items: 0,
+        }
+    }
+
+    /// Returns a number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.next
+    }
+
+    /// Returns an offset of the next byte to read, counting from the start of the payload.
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    /// Consume the rest of the payload and return it without parsing.
+    pub fn read_to_end(&mut self) -> &'a [u8] {
+        let rest = &self.buf[self.next..];
+        self.next = self.buf.len();
+        rest
+    }
+
+    /// Read a single byte.
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        let b = *self.buf.get(self.next).ok_or(DecodeError::UnexpectedEnd)?;
+        self.next += 1;
+        Ok(b)
+    }
+
+    /// Take a slice of the next `len` bytes and advance the position by `len`.
+    pub fn read(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.remaining() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let slice = &self.buf[self.next..(self.next + len)];
+        self.next += len;
+        Ok(slice)
+    }
+
+    /// Read unsigned integer with variable length, up to 64 bits.
+    pub fn read_uvar(&mut self) -> Result<u64, DecodeError> {
+        let mut num: u64 = 0;
+        let mut len: u32 = 0;
+        loop {
+            let r = self.read_u8()?;
+            let bits = (r & binary::BITS7) as u64;
+            if len >= 64 || (len > 57 && bits >> (64 - len) != 0) {
+                return Err(DecodeError::VarIntOverflow);
+            }
+            num |= bits << len;
+            len += 7;
+            if r < binary::BIT8 {
+                return Ok(num);
+            }
+        }
+    }
+
+    /// Read unsigned integer with variable length, which must fit into 32 bits.
+    pub fn read_uvar_u32(&mut self) -> Result<u32, DecodeError> {
+        let num = self.read_uvar()?;
+        if num > u32::MAX as u64 {
+            Err(DecodeError::VarIntOverflow)
+        } else {
+            Ok(num as u32)
+        }
+    }
+
+    /// Read signed integer with variable length, up to 64 bits.
+    pub fn read_ivar(&mut self) -> Result<i64, DecodeError> {
+        let mut r = self.read_u8()?;
+        let mut num = (r & binary::BITS6) as i64;
+        let mut len: u32 = 6;
+        let is_negative = r & binary::BIT7 != 0;
+        while r & binary::BIT8 != 0 {
+            if len >= 64 {
+                return Err(DecodeError::VarIntOverflow);
+            }
+            r = self.read_u8()?;
+            num |= ((r & binary::BITS7) as i64) << len;
+            len += 7;
+        }
+        Ok(if is_negative { -num } else { num })
+    }
+
+    /// Read a length of a collection and check it against [DecoderOptions::max_collection_len]
+    /// and the number of remaining bytes.
+    /// Declared elements are also accounted against [DecoderOptions::max_total_items].
+    pub fn read_collection_len(&mut self) -> Result<u32, DecodeError> {
+        let len = self.read_uvar()?;
+        if len > self.options.max_collection_len as u64 {
+            return Err(DecodeError::CollectionTooLong {
+                len,
+                max: self.options.max_collection_len,
+            });
+        }
+        // every element takes at least one byte, so the declared length can be checked against
+        // the size of a payload before anything gets allocated
+        if len > self.remaining() as u64 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let len = len as u32;
+        self.add_items(len)?;
+        Ok(len)
+    }
+
+    /// Account given number of elements against [DecoderOptions::max_total_items].
+    pub fn add_items(&mut self, count: u32) -> Result<(), DecodeError> {
+        match self.items.checked_add(count) {
+            Some(items) if items <= self.options.max_total_items => {
+                self.items = items;
+                Ok(())
+            }
+            _ => Err(DecodeError::TooManyItems {
+                max: self.options.max_total_items,
+            }),
+        }
+    }
+
+    /// Read a variable length buffer and check its length against
+    /// [DecoderOptions::max_buf_len].
+    pub fn read_buf(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.read_uvar()?;
+        if len > self.options.max_buf_len as u64 {
+            return Err(DecodeError::BufferTooLong {
+                len,
+                max: self.options.max_buf_len,
+            });
+        }
+        self.read(len as usize)
+    }
+
+    /// Read string of variable length.
+    pub fn read_string(&mut self) -> Result<&'a str, DecodeError> {
+        let buf = self.read_buf()?;
+        std::str::from_utf8(buf).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+/// Reads an unsigned variable length integer written by
+/// [write_var_u64](crate::encoding::write_var_u64). Returns [DecodeError::VarIntOverflow] if
+/// it doesn't fit into 64 bits.
+pub fn read_var_u64(cursor: &mut CheckedCursor) -> Result<u64, DecodeError> {
+    cursor.read_uvar()
+}
+
+/// Reads an unsigned variable length integer, which must fit into 32 bits.
+pub fn read_var_u32(cursor: &mut CheckedCursor) -> Result<u32, DecodeError> {
+    cursor.read_uvar_u32()
+}
+
+/// Reads a signed variable length integer written by
+/// [write_var_i64](crate::encoding::write_var_i64).
+pub fn read_var_i64(cursor: &mut CheckedCursor) -> Result<i64, DecodeError> {
+    cursor.read_ivar()
+}
+
+/// Reads a buffer written by [write_var_buf](crate::encoding::write_var_buf), returning
+/// a slice of the underlying payload.
+pub fn read_var_buf<'a>(cursor: &mut CheckedCursor<'a>) -> Result<&'a [u8], DecodeError> {
+    cursor.read_buf()
+}
+
+/// Reads a string written by [write_var_string](crate::encoding::write_var_string), returning
+/// [DecodeError::InvalidUtf8] if it's not a valid UTF-8 sequence.
+pub fn read_var_string<'a>(cursor: &mut CheckedCursor<'a>) -> Result<&'a str, DecodeError> {
+    cursor.read_string()
+}
+
 /// Create non-zeroed fixed array of 8-bytes and returns it.
 /// Since it's not zeroed it should be filled before use.
 #[inline(always)]
@@ -1,3 +1,34 @@
+//! Binary encoding compatible with the `lib0/encoding` module of lib0 JavaScript library, which
+//! is used by Yjs and Yrs to encode document updates. Its variable length formats are stable and
+//! can be reused by third party formats embedding Yrs data, ie. using [write_var_u64] and
+//! [write_var_string] together with their [decoding](crate::decoding) counterparts:
+//!
+//! - **Unsigned variable length integer** is written in little endian groups of 7 bits. The 8th
+//!   (most significant) bit of each byte is set if more bytes follow. Numbers below `2^7` take
+//!   a single byte, numbers below `2^14` take two bytes and so on, up to 10 bytes for
+//!   [u64::MAX]: `0 => [0x00]`, `127 => [0x7f]`, `128 => [0x80, 0x01]`.
+//! - **Signed variable length integer** uses the same layout, except that the first byte holds
+//!   only 6 bits of an absolute value, while its 7th bit is set for negative numbers:
+//!   `-1 => [0x41]`, `64 => [0x80, 0x01]`.
+//! - **Buffer** is written as its length (unsigned variable length integer) followed by its bytes.
+//! - **String** is written as a buffer of its UTF-8 bytes: `"hi" => [0x02, 0x68, 0x69]`.
+//!
+//! # Example
+//!
+//! ```
+//! use lib0::decoding::{read_var_string, read_var_u64, CheckedCursor};
+//! use lib0::encoding::{write_var_string, write_var_u64};
+//!
+//! let mut buf = Vec::new();
+//! write_var_u64(&mut buf, 128);
+//! write_var_string(&mut buf, "hi");
+//! assert_eq!(buf, vec![0x80, 0x01, 0x02, b'h', b'i']);
+//!
+//! let mut cursor = CheckedCursor::from(buf.as_slice());
+//! assert_eq!(read_var_u64(&mut cursor), Ok(128));
+//! assert_eq!(read_var_string(&mut cursor), Ok("hi"));
+//! ```
+
 use crate::binary;
 use crate::number::Uint;
 
@@ -11,6 +42,43 @@ impl Write for Vec<u8> {
     }
 }
 
+impl<W: Write + ?Sized> Write for &mut W {
+    #[inline]
+    fn write_u8(&mut self, value: u8) {
+        (**self).write_u8(value)
+    }
+
+    #[inline]
+    fn write(&mut self, buf: &[u8]) {
+        (**self).write(buf)
+    }
+}
+
+/// Writing into a mutable slice copies data at the beginning of it and then advances the slice
+/// past written bytes, so that after writing its length describes the remaining free space.
+///
+/// # Panics
+///
+/// Panics if there's not enough space left in the slice to fit written data.
+impl Write for &mut [u8] {
+    fn write_u8(&mut self, value: u8) {
+        self.write(&[value])
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        if buf.len() > self.len() {
+            panic!(
+                "buffer too small: cannot write {} bytes into {} bytes of free space",
+                buf.len(),
+                self.len()
+            );
+        }
+        let (head, tail) = std::mem::take(self).split_at_mut(buf.len());
+        head.copy_from_slice(buf);
+        *self = tail;
+    }
+}
+
 pub trait Write {
     fn write_u8(&mut self, value: u8);
     fn write(&mut self, buf: &[u8]);
@@ -114,3 +182,27 @@ pub trait Write {
         self.write(&num.to_be_bytes());
     }
 }
+
+/// Writes an unsigned variable length integer. See: [module documentation](self).
+pub fn write_var_u64<W: Write + ?Sized>(writer: &mut W, num: u64) {
+    writer.write_uvar(num)
+}
+
+/// Writes a signed variable length integer. See: [module documentation](self).
+///
+/// # Panics
+///
+/// Panics in debug builds if `num` is [i64::MIN], as its absolute value cannot be represented.
+pub fn write_var_i64<W: Write + ?Sized>(writer: &mut W, num: i64) {
+    writer.write_ivar(num)
+}
+
+/// Writes a buffer prefixed with its length. See: [module documentation](self).
+pub fn write_var_buf<W: Write + ?Sized>(writer: &mut W, buf: &[u8]) {
+    writer.write_buf(buf)
+}
+
+/// Writes a UTF-8 string prefixed with its length in bytes. See: [module documentation](self).
+pub fn write_var_string<W: Write + ?Sized>(writer: &mut W, str: &str) {
+    writer.write_string(str)
+}
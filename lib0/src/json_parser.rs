@@ -0,0 +1,366 @@
+use crate::any::{Any, MAX_NESTING_DEPTH};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Error returned when a JSON string could not be parsed into an [Any] value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonParseError {
+    /// Byte offset within parsed string at which the error occurred.
+    pub index: usize,
+    /// Description of an error.
+    pub message: &'static str,
+}
+
+impl std::fmt::Display for JsonParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at index {}", self.message, self.index)
+    }
+}
+
+impl std::error::Error for JsonParseError {}
+
+/// Serializes given `value` as a JSON string into a provided `buf`. Rules are:
+///
+/// - [Any::Null] and [Any::Undefined] are serialized as `null`.
+/// - [Any::Number] is serialized as a JSON number with a fractional part (ie. `1.0`), so that it's
+///   not parsed back as [Any::BigInt]. Non-finite numbers are serialized as `null`.
+/// - [Any::BigInt] is serialized as a JSON integer.
+/// - [Any::Buffer] is serialized as an array of byte values.
+/// - [Any::Array] and [Any::Map] are serialized recursively. Map entries are written in order
+///   of their keys, so that the output is deterministic.
+pub(crate) fn write_json(value: &Any, buf: &mut String) {
+    match value {
+        Any::Null | Any::Undefined => buf.push_str("null"),
+        Any::Bool(v) => buf.push_str(if *v { "true" } else { "false" }),
+        Any::Number(v) if v.is_finite() => {
+            write!(buf, "{}", v).unwrap();
+            if v.fract() == 0.0 {
+                buf.push_str(".0");
+            }
+        }
+        Any::Number(_) => buf.push_str("null"),
+        Any::BigInt(v) => write!(buf, "{}", v).unwrap(),
+        Any::String(v) => write_json_string(v, buf),
+        Any::Buffer(v) => {
+            buf.push('[');
+            for (i, b) in v.iter().enumerate() {
+                if i != 0 {
+                    buf.push(',');
+                }
+                write!(buf, "{}", b).unwrap();
+            }
+            buf.push(']');
+        }
+        Any::Array(values) => {
+            buf.push('[');
+            for (i, v) in values.iter().enumerate() {
+                if i != 0 {
+                    buf.push(',');
+                }
+                write_json(v, buf);
+            }
+            buf.push(']');
+        }
+        Any::Map(entries) => {
+            let mut keys: Vec<_> = entries.keys().collect();
+            keys.sort();
+            buf.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i != 0 {
+                    buf.push(',');
+                }
+                write_json_string(key, buf);
+                buf.push(':');
+                write_json(&entries[key], buf);
+            }
+            buf.push('}');
+        }
+    }
+}
+
+fn write_json_string(value: &str, buf: &mut String) {
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\u{08}' => buf.push_str("\\b"),
+            '\u{0c}' => buf.push_str("\\f"),
+            c if (c as u32) < 0x20 => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+/// A simple recursive descent parser of JSON strings. Integer literals are parsed into
+/// [Any::BigInt] (as long as they fit into `i64`), while all other numbers are parsed into
+/// [Any::Number]. Arrays and objects nested deeper than [MAX_NESTING_DEPTH] are rejected.
+pub(crate) struct JsonParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        JsonParser {
+            input,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    /// Parses an entire input as a single JSON value. Trailing non-whitespace characters are
+    /// considered an error.
+    pub fn parse(mut self) -> Result<Any, JsonParseError> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos != self.input.len() {
+            Err(self.error("unexpected trailing characters"))
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn error(&self, message: &'static str) -> JsonParseError {
+        JsonParseError {
+            index: self.pos,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\n') | Some(b'\r') | Some(b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &'static str, value: Any) -> Result<Any, JsonParseError> {
+        if self.input[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected token"))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Any, JsonParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.expect("null", Any::Null),
+            Some(b't') => self.expect("true", Any::Bool(true)),
+            Some(b'f') => self.expect("false", Any::Bool(false)),
+            Some(b'"') => Ok(Any::String(self.parse_string()?)),
+            Some(b'[') => self.parse_nested(Self::parse_array),
+            Some(b'{') => self.parse_nested(Self::parse_map),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("unexpected token")),
+        }
+    }
+
+    fn parse_nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Any, JsonParseError>,
+    ) -> Result<Any, JsonParseError> {
+        // parsing is recursive, deeply nested inputs could overflow the stack
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(self.error("values are nested too deeply"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_array(&mut self) -> Result<Any, JsonParseError> {
+        self.pos += 1; // skip '['
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Any::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Any::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_map(&mut self) -> Result<Any, JsonParseError> {
+        self.pos += 1; // skip '{'
+        let mut entries = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Any::Map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected string key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            self.pos += 1;
+            let value = self.parse_value()?;
+            entries.insert(key, value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Any::Map(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonParseError> {
+        self.pos += 1; // skip opening '"'
+        let mut result = String::new();
+        loop {
+            let rest = &self.input[self.pos..];
+            let mut chars = rest.char_indices();
+            match chars.next() {
+                None => return Err(self.error("unterminated string")),
+                Some((_, '"')) => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some((_, '\\')) => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{08}',
+                        Some(b'f') => '\u{0c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let c = self.parse_unicode_escape()?;
+                            result.push(c);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    self.pos += 1;
+                    result.push(c);
+                }
+                Some((_, c)) if (c as u32) < 0x20 => {
+                    return Err(self.error("control character in string"))
+                }
+                Some((_, c)) => {
+                    self.pos += c.len_utf8();
+                    result.push(c);
+                }
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonParseError> {
+        let hex = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code =
+            u32::from_str_radix(hex, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonParseError> {
+        let hi = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&hi) {
+            // surrogate pair: expect another \uXXXX escape with a low surrogate
+            if !self.input[self.pos..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let lo = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&lo) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+        } else {
+            hi
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    /// Skips a non-empty sequence of decimal digits.
+    fn parse_digits(&mut self) -> Result<(), JsonParseError> {
+        if !matches!(self.peek(), Some(b'0'..=b'9')) {
+            return Err(self.error("invalid number"));
+        }
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    fn parse_number(&mut self) -> Result<Any, JsonParseError> {
+        let start = self.pos;
+        let mut is_float = false;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+            if let Some(b'0'..=b'9') = self.peek() {
+                return Err(self.error("leading zeros are not allowed"));
+            }
+        } else {
+            self.parse_digits()?;
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.parse_digits()?;
+            is_float = true;
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.parse_digits()?;
+            is_float = true;
+        }
+        let literal = &self.input[start..self.pos];
+        if !is_float {
+            if let Ok(v) = literal.parse::<i64>() {
+                return Ok(Any::BigInt(v));
+            }
+        }
+        match literal.parse::<f64>() {
+            Ok(v) => Ok(Any::Number(v)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+}
@@ -1,5 +1,10 @@
 pub mod any;
+#[cfg(feature = "serde")]
+mod any_serde;
 pub mod binary;
 pub mod decoding;
 pub mod encoding;
+pub mod json_parser;
+#[cfg(feature = "macros")]
+mod macros;
 pub mod number;
@@ -0,0 +1,81 @@
+/// Constructs an [Any](crate::any::Any) value using JSON-like syntax. `null` and `undefined`
+/// literals map to their [Any](crate::any::Any) counterparts, `[...]` to an array and `{...}`
+/// to a map with string literal keys. Any other expression is converted using its
+/// `Into<Any>` implementation. Requires `macros` feature.
+///
+/// # Example
+///
+/// ```
+/// use lib0::any;
+/// use lib0::any::Any;
+///
+/// let name = "Alice";
+/// let value = any!({
+///     "name": name,
+///     "age": 30,
+///     "tags": ["admin", null, -1.5],
+///     "address": { "city": "Berlin" }
+/// });
+/// assert_eq!(value.get("tags.2"), Some(&Any::Number(-1.5)));
+/// assert_eq!(value.get("address.city").and_then(Any::as_str), Some("Berlin"));
+/// ```
+#[macro_export]
+macro_rules! any {
+    // array elements are munched one by one, so that nested literals can be recognized
+    (@array [$($elems:expr,)*]) => {
+        ::std::vec![$($elems,)*]
+    };
+    (@array [$($elems:expr,)*] null $(, $($rest:tt)*)?) => {
+        $crate::any!(@array [$($elems,)* $crate::any!(null),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] undefined $(, $($rest:tt)*)?) => {
+        $crate::any!(@array [$($elems,)* $crate::any!(undefined),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::any!(@array [$($elems,)* $crate::any!([$($array)*]),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] {$($map:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::any!(@array [$($elems,)* $crate::any!({$($map)*}),] $($($rest)*)?)
+    };
+    (@array [$($elems:expr,)*] $value:expr $(, $($rest:tt)*)?) => {
+        $crate::any!(@array [$($elems,)* $crate::any!($value),] $($($rest)*)?)
+    };
+
+    // map entries are munched the same way as array elements
+    (@map [$(($key:expr, $value:expr),)*]) => {{
+        let mut map = ::std::collections::HashMap::new();
+        $(map.insert(::std::string::String::from($key), $value);)*
+        map
+    }};
+    (@map [$($entries:tt)*] $key:literal : null $(, $($rest:tt)*)?) => {
+        $crate::any!(@map [$($entries)* ($key, $crate::any!(null)),] $($($rest)*)?)
+    };
+    (@map [$($entries:tt)*] $key:literal : undefined $(, $($rest:tt)*)?) => {
+        $crate::any!(@map [$($entries)* ($key, $crate::any!(undefined)),] $($($rest)*)?)
+    };
+    (@map [$($entries:tt)*] $key:literal : [$($array:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::any!(@map [$($entries)* ($key, $crate::any!([$($array)*])),] $($($rest)*)?)
+    };
+    (@map [$($entries:tt)*] $key:literal : {$($map:tt)*} $(, $($rest:tt)*)?) => {
+        $crate::any!(@map [$($entries)* ($key, $crate::any!({$($map)*})),] $($($rest)*)?)
+    };
+    (@map [$($entries:tt)*] $key:literal : $value:expr $(, $($rest:tt)*)?) => {
+        $crate::any!(@map [$($entries)* ($key, $crate::any!($value)),] $($($rest)*)?)
+    };
+
+    (null) => {
+        $crate::any::Any::Null
+    };
+    (undefined) => {
+        $crate::any::Any::Undefined
+    };
+    ([ $($tt:tt)* ]) => {
+        $crate::any::Any::Array($crate::any!(@array [] $($tt)*))
+    };
+    ({ $($tt:tt)* }) => {
+        $crate::any::Any::Map($crate::any!(@map [] $($tt)*))
+    };
+    ($value:expr) => {
+        ::std::convert::Into::<$crate::any::Any>::into($value)
+    };
+}
@@ -0,0 +1,443 @@
+use lib0::any::{Any, TypeMismatch};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+
+#[test]
+fn try_from_any_primitives() {
+    assert_eq!(bool::try_from(Any::Bool(true)), Ok(true));
+    assert_eq!(
+        String::try_from(Any::String("hello".to_owned())),
+        Ok("hello".to_owned())
+    );
+    assert_eq!(
+        Vec::<u8>::try_from(Any::Buffer(vec![1, 2, 3].into_boxed_slice())),
+        Ok(vec![1, 2, 3])
+    );
+    assert_eq!(
+        Vec::<Any>::try_from(Any::Array(vec![Any::Null])),
+        Ok(vec![Any::Null])
+    );
+    let mut map = HashMap::new();
+    map.insert("key".to_owned(), Any::Bool(false));
+    assert_eq!(
+        HashMap::<String, Any>::try_from(Any::Map(map.clone())),
+        Ok(map)
+    );
+}
+
+#[test]
+fn try_from_any_type_mismatch() {
+    let err = String::try_from(Any::Number(1.0)).unwrap_err();
+    assert_eq!(err, TypeMismatch::new("string", "number"));
+    assert_eq!(err.to_string(), "expected string but found number");
+
+    assert_eq!(
+        bool::try_from(Any::Null),
+        Err(TypeMismatch::new("bool", "null"))
+    );
+    assert_eq!(
+        Vec::<u8>::try_from(Any::Array(vec![])),
+        Err(TypeMismatch::new("buffer", "array"))
+    );
+}
+
+#[test]
+fn try_from_any_numbers() {
+    // integral numbers can be safely converted into i64
+    assert_eq!(i64::try_from(Any::Number(42.0)), Ok(42));
+    assert_eq!(i64::try_from(Any::Number(-42.0)), Ok(-42));
+    assert_eq!(i64::try_from(Any::BigInt(i64::MAX)), Ok(i64::MAX));
+
+    // but fractions or numbers outside of safe integer range are not
+    assert_eq!(
+        i64::try_from(Any::Number(1.5)),
+        Err(TypeMismatch::new("i64", "number with fractional part"))
+    );
+    assert_eq!(
+        i64::try_from(Any::Number(f64::NAN)),
+        Err(TypeMismatch::new("i64", "number with fractional part"))
+    );
+    assert_eq!(
+        i64::try_from(Any::Number(2f64.powi(60))),
+        Err(TypeMismatch::new(
+            "i64",
+            "number outside of safe integer range"
+        ))
+    );
+
+    // bigints can be converted into f64 as long as they don't lose precision
+    assert_eq!(f64::try_from(Any::Number(1.5)), Ok(1.5));
+    assert_eq!(f64::try_from(Any::BigInt(1 << 40)), Ok((1u64 << 40) as f64));
+    assert_eq!(
+        f64::try_from(Any::BigInt(i64::MAX)),
+        Err(TypeMismatch::new("f64", "bigint outside of f64 precision"))
+    );
+}
+
+fn nested_any(depth: usize) -> Any {
+    let mut value = Any::Array(vec![
+        Any::Null,
+        Any::Bool(true),
+        Any::Number(-1.25),
+        Any::BigInt(i64::MIN),
+        Any::String("quote \" backslash \\ newline \n tab \t unicode ąę 🦀".to_owned()),
+    ]);
+    for i in 0..depth {
+        let mut map = HashMap::new();
+        map.insert(format!("level-{}", i), value);
+        map.insert("empty".to_owned(), Any::Map(HashMap::new()));
+        value = Any::Array(vec![Any::Map(map), Any::Array(vec![])]);
+    }
+    value
+}
+
+#[test]
+fn json_string_roundtrip() {
+    let value = nested_any(32);
+    let json = value.to_json_string();
+    assert_eq!(Any::from_json_str(&json), Ok(value));
+}
+
+#[test]
+fn json_string_format() {
+    let mut map = HashMap::new();
+    map.insert("b".to_owned(), Any::Number(1.0));
+    map.insert("a".to_owned(), Any::Undefined);
+    map.insert(
+        "c".to_owned(),
+        Any::Array(vec![
+            Any::Buffer(vec![1, 2].into_boxed_slice()),
+            Any::Number(f64::NAN),
+            Any::String("\u{1}".to_owned()),
+        ]),
+    );
+    assert_eq!(
+        Any::Map(map).to_json_string(),
+        r#"{"a":null,"b":1.0,"c":[[1,2],null,"\u0001"]}"#
+    );
+}
+
+#[test]
+fn json_string_parse() {
+    let value = Any::from_json_str(r#" { "a" : [ 1, 1.5, -2e3, "🦀\n" ], "b": {} } "#);
+    let mut map = HashMap::new();
+    map.insert(
+        "a".to_owned(),
+        Any::Array(vec![
+            Any::BigInt(1),
+            Any::Number(1.5),
+            Any::Number(-2000.0),
+            Any::String("🦀\n".to_owned()),
+        ]),
+    );
+    map.insert("b".to_owned(), Any::Map(HashMap::new()));
+    assert_eq!(value, Ok(Any::Map(map)));
+
+    assert!(Any::from_json_str("").is_err());
+    assert!(Any::from_json_str("[1,]").is_err());
+    assert!(Any::from_json_str("{\"a\" 1}").is_err());
+    assert!(Any::from_json_str("\"unterminated").is_err());
+    assert!(Any::from_json_str("[] []").is_err());
+    let err = Any::from_json_str("[nul]").unwrap_err();
+    assert_eq!(err.index, 1);
+}
+
+#[test]
+fn json_string_numbers() {
+    // numbers with integer values keep their type after a round trip
+    for value in [
+        Any::Number(1.0),
+        Any::Number(-0.0),
+        Any::Number(1e21),
+        Any::Number(-2.5e-8),
+        Any::BigInt(1),
+        Any::BigInt(-42),
+        Any::BigInt(i64::MAX),
+    ] {
+        let json = value.to_json_string();
+        assert_eq!(Any::from_json_str(&json), Ok(value), "{}", json);
+    }
+    assert_eq!(Any::Number(3.0).to_json_string(), "3.0");
+    assert_eq!(Any::from_json_str("0"), Ok(Any::BigInt(0)));
+    assert_eq!(Any::from_json_str("-0.5"), Ok(Any::Number(-0.5)));
+    assert_eq!(Any::from_json_str("1E+2"), Ok(Any::Number(100.0)));
+
+    for invalid in ["01", "-01", "00.5", "1.", ".5", "-", "1e", "1e+", "+1", "1.2.3"] {
+        assert!(Any::from_json_str(invalid).is_err(), "{}", invalid);
+    }
+    let err = Any::from_json_str("[1, 007]").unwrap_err();
+    assert_eq!(err.index, 5);
+}
+
+#[test]
+fn json_string_nesting_limit() {
+    // the same limit as for decoding: a root value and up to 256 levels of nesting
+    let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(Any::from_json_str(&nested(257)).is_ok());
+    let err = Any::from_json_str(&nested(258)).unwrap_err();
+    assert_eq!(err.message, "values are nested too deeply");
+    assert_eq!(err.index, 257);
+
+    let err = Any::from_json_str(&format!("{}[]", r#"{"a":"#.repeat(257))).unwrap_err();
+    assert_eq!(err.message, "values are nested too deeply");
+
+    // a depth limit must not overflow the stack before it's reached
+    let deep = "[".repeat(1_000_000);
+    assert!(Any::from_json_str(&deep).is_err());
+}
+
+#[test]
+fn any_equality() {
+    // equality is exact - numbers of different representations are not equal
+    assert_ne!(Any::Number(1.0), Any::BigInt(1));
+    assert!(Any::Number(1.0).loosely_eq(&Any::BigInt(1)));
+    assert!(Any::BigInt(-3).loosely_eq(&Any::Number(-3.0)));
+    assert!(!Any::Number(1.5).loosely_eq(&Any::BigInt(1)));
+    assert!(!Any::Number(2f64.powi(63)).loosely_eq(&Any::BigInt(i64::MAX)));
+    assert!(Any::Number(-(2f64.powi(63))).loosely_eq(&Any::BigInt(i64::MIN)));
+    assert!(!Any::Number(f64::NAN).loosely_eq(&Any::BigInt(0)));
+
+    // loose equality applies to nested values as well
+    let a = Any::from_json_str(r#"{"a":[1,{"b":2}]}"#).unwrap();
+    let b: Any = vec![(
+        "a".to_owned(),
+        Any::Array(vec![Any::Number(1.0), {
+            let mut map = HashMap::new();
+            map.insert("b".to_owned(), Any::Number(2.0));
+            Any::Map(map)
+        }]),
+    )]
+    .into_iter()
+    .collect();
+    assert_ne!(a, b);
+    assert!(a.loosely_eq(&b));
+    assert!(!a.loosely_eq(&Any::Map(HashMap::new())));
+
+    // NaN is equal to itself, so that Eq contract holds
+    assert_eq!(Any::Number(f64::NAN), Any::Number(f64::NAN));
+    assert_eq!(Any::Number(f64::NAN), Any::Number(-f64::NAN));
+    assert_ne!(Any::Number(f64::NAN), Any::Number(0.0));
+    assert_eq!(Any::Number(0.0), Any::Number(-0.0));
+    assert_eq!(
+        Any::Array(vec![Any::Number(f64::NAN)]),
+        Any::Array(vec![Any::Number(f64::NAN)])
+    );
+}
+
+#[test]
+fn any_ordering() {
+    let values = vec![
+        Any::Map(HashMap::new()),
+        Any::Number(f64::NAN),
+        Any::String("b".to_owned()),
+        Any::Number(f64::INFINITY),
+        Any::BigInt(-1),
+        Any::Number(-0.5),
+        Any::Undefined,
+        Any::String("a".to_owned()),
+        Any::Null,
+        Any::Array(vec![Any::Null]),
+        Any::Bool(true),
+        Any::Number(f64::NAN),
+        Any::Array(vec![]),
+        Any::Buffer(vec![1].into_boxed_slice()),
+        Any::Bool(false),
+    ];
+    let set: BTreeSet<_> = values.into_iter().collect();
+    let sorted: Vec<_> = set.into_iter().collect();
+    assert_eq!(
+        sorted,
+        vec![
+            Any::Null,
+            Any::Undefined,
+            Any::Bool(false),
+            Any::Bool(true),
+            Any::Number(-0.5),
+            Any::Number(f64::INFINITY),
+            Any::Number(f64::NAN),
+            Any::BigInt(-1),
+            Any::String("a".to_owned()),
+            Any::String("b".to_owned()),
+            Any::Buffer(vec![1].into_boxed_slice()),
+            Any::Array(vec![]),
+            Any::Array(vec![Any::Null]),
+            Any::Map(HashMap::new()),
+        ]
+    );
+
+    // maps are ordered by their entries sorted by key, regardless of insertion order
+    let a = Any::from_json_str(r#"{"x":1,"y":2}"#).unwrap();
+    let b = Any::from_json_str(r#"{"y":2,"x":1}"#).unwrap();
+    let c = Any::from_json_str(r#"{"x":1,"y":3}"#).unwrap();
+    assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+    assert!(a < c);
+}
+
+#[test]
+fn any_from_iter() {
+    let array: Any = (0..3).map(Any::BigInt).collect();
+    assert_eq!(
+        array,
+        Any::Array(vec![Any::BigInt(0), Any::BigInt(1), Any::BigInt(2)])
+    );
+
+    let map = Any::from_iter(vec![
+        ("a".to_owned(), Any::Bool(true)),
+        ("a".to_owned(), Any::Bool(false)),
+    ]);
+    assert_eq!(map.get("a"), Some(&Any::Bool(false)));
+    assert_eq!(map.as_map().map(|m| m.len()), Some(1));
+}
+
+#[test]
+fn any_accessors() {
+    let value =
+        Any::from_json_str(r#"{"users":[{"name":"Alice","age":30.5,"admin":true}],"a.b":1}"#)
+            .unwrap();
+    assert_eq!(
+        value.get("users.0.name").and_then(Any::as_str),
+        Some("Alice")
+    );
+    assert_eq!(value.get("users.0.age").and_then(Any::as_f64), Some(30.5));
+    assert_eq!(value.get("users.0.age").and_then(Any::as_i64), None);
+    assert_eq!(
+        value.get("users.0.admin").and_then(Any::as_bool),
+        Some(true)
+    );
+    assert_eq!(
+        value.get("users").and_then(Any::as_array).map(|a| a.len()),
+        Some(1)
+    );
+
+    // unresolved paths
+    assert_eq!(value.get("users.1"), None);
+    assert_eq!(value.get("users.first"), None);
+    assert_eq!(value.get("users.0.name.0"), None);
+    assert_eq!(value.get("a.b"), None);
+    assert_eq!(value.get(""), None);
+
+    // accessors don't convert between unrelated variants
+    assert_eq!(Any::Number(1.0).as_str(), None);
+    assert_eq!(Any::String("1".to_owned()).as_f64(), None);
+    assert_eq!(Any::BigInt(7).as_f64(), Some(7.0));
+    assert_eq!(Any::BigInt(i64::MAX).as_f64(), None);
+    assert_eq!(Any::Number(7.0).as_i64(), Some(7));
+    assert_eq!(Any::Number(f64::NAN).as_i64(), None);
+    assert!(Any::Number(f64::NAN).as_f64().unwrap().is_nan());
+}
+
+#[cfg(feature = "macros")]
+mod macros {
+    use lib0::any;
+    use lib0::any::Any;
+    use std::collections::HashMap;
+
+    #[test]
+    fn any_macro() {
+        assert_eq!(any!(null), Any::Null);
+        assert_eq!(any!(undefined), Any::Undefined);
+        assert_eq!(any!(-1.5), Any::Number(-1.5));
+        assert_eq!(any!("a"), Any::String("a".to_owned()));
+        assert_eq!(any!([]), Any::Array(vec![]));
+        assert_eq!(any!({}), Any::Map(HashMap::new()));
+
+        let name = "Alice".to_owned();
+        let value = any!({
+            "name": name.clone(),
+            "nested": { "empty": [], "deep": [[null, undefined], { "x": -2 }] },
+            "list": [1, "two", true, 2 + 2, i64::MAX,],
+            "missing": null,
+        });
+        assert_eq!(value.get("nested.deep.0.1"), Some(&Any::Undefined));
+        assert_eq!(value.get("nested.deep.1.x"), Some(&Any::Number(-2.0)));
+        assert_eq!(value.get("list.3"), Some(&Any::Number(4.0)));
+        assert_eq!(value.get("list.4"), Some(&Any::BigInt(i64::MAX)));
+        assert_eq!(value.get("name").and_then(Any::as_str), Some("Alice"));
+        assert_eq!(value.get("missing"), Some(&Any::Null));
+        assert_eq!(
+            value.get("list").and_then(Any::as_array).map(|a| a.len()),
+            Some(5)
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde {
+    use super::nested_any;
+    use lib0::any::Any;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn serde_json_roundtrip() {
+        let value = nested_any(32);
+        let json = serde_json::to_string(&value).unwrap();
+        let parsed: Any = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, value);
+    }
+
+    #[test]
+    fn serde_json_numbers() {
+        let values = [
+            Any::Number(1.0),
+            Any::Number(-0.0),
+            Any::Number(1e21),
+            Any::Number(0.1),
+            Any::Number(-2.5e-8),
+            Any::BigInt(0),
+            Any::BigInt(i64::MIN),
+        ];
+        for value in values.iter() {
+            let json = value.to_json_string();
+            let parsed: Any = serde_json::from_str(&json).unwrap();
+            assert_eq!(&parsed, value, "{}", json);
+
+            let json = serde_json::to_string(value).unwrap();
+            assert_eq!(Any::from_json_str(&json).as_ref(), Ok(value), "{}", json);
+        }
+
+        for invalid in ["01", "-01", "1.", ".5", "1e", "-"].iter() {
+            assert!(serde_json::from_str::<serde_json::Value>(invalid).is_err());
+            assert!(Any::from_json_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn serde_buffer_roundtrip() {
+        let value = Any::Array(vec![
+            Any::Buffer(vec![0, 1, 255].into_boxed_slice()),
+            Any::Buffer(Box::default()),
+            nested_any(8),
+        ]);
+        let cbor = serde_cbor::to_vec(&value).unwrap();
+        let parsed: Any = serde_cbor::from_slice(&cbor).unwrap();
+        assert_eq!(parsed, value);
+
+        // JSON has no binary type, buffers are serialized as arrays of byte values
+        let buffer = Any::Buffer(vec![0, 1, 255].into_boxed_slice());
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(json, buffer.to_json_string());
+        let parsed: Any = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            parsed,
+            Any::Array(vec![Any::BigInt(0), Any::BigInt(1), Any::BigInt(255)])
+        );
+    }
+
+    #[test]
+    fn serde_json_value_conversion() {
+        let value = nested_any(8);
+        let json = serde_json::Value::try_from(value.clone()).unwrap();
+        assert_eq!(Any::from(json.clone()), value);
+        assert_eq!(
+            Any::from_json_str(&serde_json::to_string(&json).unwrap()),
+            Ok(value)
+        );
+
+        assert!(serde_json::Value::try_from(Any::Number(f64::INFINITY)).is_err());
+        assert_eq!(
+            serde_json::Value::try_from(Any::Undefined).unwrap(),
+            serde_json::Value::Null
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use lib0::any::Any;
+use lib0::decoding::{Cursor, DecodeError, DecoderOptions};
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+pub fn arb_any() -> impl Strategy<Value = Any> {
+    let leaf = prop_oneof![
+        Just(Any::Null),
+        Just(Any::Undefined),
+        any::<bool>().prop_map(Any::Bool),
+        any::<f64>().prop_map(Any::Number),
+        any::<i64>().prop_map(|i| Any::Number(i as f64)),
+        any::<String>().prop_map(Any::String),
+        any::<Box<[u8]>>().prop_map(Any::Buffer),
+    ]
+    .boxed();
+
+    leaf.prop_recursive(8, 256, 10, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..10).prop_map(Any::Array),
+            prop::collection::hash_map(".*", inner, 0..10).prop_map(Any::Map),
+        ]
+    })
+}
+
+fn encode(any: &Any) -> Vec<u8> {
+    let mut buf = Vec::new();
+    any.encode(&mut buf);
+    buf
+}
+
+#[test]
+fn huge_declared_array() {
+    // array declaring 2^32-1 elements
+    let payload = [117, 0xff, 0xff, 0xff, 0xff, 0x0f, 126];
+    let err = Any::try_decode(&payload, &DecoderOptions::default()).unwrap_err();
+    assert_eq!(
+        err,
+        DecodeError::CollectionTooLong {
+            len: u32::MAX as u64,
+            max: DecoderOptions::default().max_collection_len
+        }
+    );
+
+    // without limits the payload is still rejected without allocating the declared capacity
+    let err = Any::try_decode(&payload, &DecoderOptions::unlimited()).unwrap_err();
+    assert_eq!(err, DecodeError::UnexpectedEnd);
+}
+
+#[test]
+fn huge_declared_string() {
+    // string declaring 1GB of data
+    let payload = [119, 0x80, 0x80, 0x80, 0x80, 0x04, b'a'];
+    let err = Any::try_decode(&payload, &DecoderOptions::default()).unwrap_err();
+    assert_eq!(
+        err,
+        DecodeError::BufferTooLong {
+            len: 1 << 30,
+            max: DecoderOptions::default().max_buf_len
+        }
+    );
+    let err = Any::try_decode(&payload, &DecoderOptions::unlimited()).unwrap_err();
+    assert_eq!(err, DecodeError::UnexpectedEnd);
+}
+
+#[test]
+fn relaxed_and_tightened_limits() {
+    let value = Any::Array(vec![
+        Any::Array(vec![Any::Null, Any::Null]),
+        Any::Array(vec![Any::Null, Any::Null]),
+    ]);
+    let payload = encode(&value);
+    assert_eq!(
+        Any::try_decode(&payload, &DecoderOptions::default()),
+        Ok(value.clone())
+    );
+
+    let options = DecoderOptions {
+        max_collection_len: 1,
+        ..DecoderOptions::default()
+    };
+    assert_eq!(
+        Any::try_decode(&payload, &options),
+        Err(DecodeError::CollectionTooLong { len: 2, max: 1 })
+    );
+
+    let options = DecoderOptions {
+        max_total_items: 5,
+        ..DecoderOptions::default()
+    };
+    assert_eq!(
+        Any::try_decode(&payload, &options),
+        Err(DecodeError::TooManyItems { max: 5 })
+    );
+
+    let mut map = HashMap::new();
+    map.insert("key".to_string(), Any::String("value".to_string()));
+    let payload = encode(&Any::Map(map));
+    let options = DecoderOptions {
+        max_buf_len: 4,
+        ..DecoderOptions::default()
+    };
+    assert_eq!(
+        Any::try_decode(&payload, &options),
+        Err(DecodeError::BufferTooLong { len: 5, max: 4 })
+    );
+}
+
+#[test]
+fn malformed_payloads() {
+    let options = DecoderOptions::default();
+    assert_eq!(
+        Any::try_decode(&[], &options),
+        Err(DecodeError::UnexpectedEnd)
+    );
+    assert!(matches!(
+        Any::try_decode(&[1], &options),
+        Err(DecodeError::InvalidData(_))
+    ));
+    // varint longer than 64 bits
+    let payload = [
+        117, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+    ];
+    assert_eq!(
+        Any::try_decode(&payload, &options),
+        Err(DecodeError::VarIntOverflow)
+    );
+    // invalid UTF-8 string
+    assert_eq!(
+        Any::try_decode(&[119, 2, 0xc3, 0x28], &options),
+        Err(DecodeError::InvalidUtf8)
+    );
+    // deeply nested arrays would overflow the stack of a recursive decoder
+    let mut payload = Vec::new();
+    for _ in 0..100_000 {
+        payload.extend_from_slice(&[117, 1]);
+    }
+    payload.push(126);
+    assert!(matches!(
+        Any::try_decode(&payload, &DecoderOptions::unlimited()),
+        Err(DecodeError::InvalidData(_))
+    ));
+}
+
+proptest! {
+    #[test]
+    fn try_decode_roundtrip_prop(any in arb_any()) {
+        let payload = encode(&any);
+        let decoded = Any::try_decode(&payload, &DecoderOptions::default()).unwrap();
+        let expected = Any::decode(&mut Cursor::new(&payload));
+        // NaN values are not equal to themselves, compare JSON representations instead
+        prop_assert_eq!(decoded.to_json_string(), expected.to_json_string());
+        for i in 0..payload.len() {
+            prop_assert!(Any::try_decode(&payload[..i], &DecoderOptions::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn try_decode_random_bytes_prop(payload: Vec<u8>) {
+        // must never panic or allocate based on declared lengths
+        let _ = Any::try_decode(&payload, &DecoderOptions::default());
+    }
+}
@@ -201,3 +201,25 @@ proptest! {
         val.read(&mut decoder)
     }
 }
+
+proptest! {
+    #[test]
+    fn encoding_into_slice_prop(any in arb_any()) {
+        let mut expected = Vec::new();
+        any.encode(&mut expected);
+
+        let mut buf = vec![0u8; expected.len() + 3];
+        let mut free = buf.as_mut_slice();
+        any.encode(&mut free);
+        prop_assert_eq!(free.len(), 3);
+        prop_assert_eq!(&buf[..expected.len()], expected.as_slice());
+    }
+}
+
+#[test]
+#[should_panic(expected = "buffer too small")]
+fn encoding_into_too_small_slice() {
+    let mut buf = [0u8; 4];
+    let mut free = &mut buf[..];
+    free.write_string("hello");
+}
@@ -0,0 +1,125 @@
+use lib0::decoding::{
+    read_var_buf, read_var_i64, read_var_string, read_var_u32, read_var_u64, CheckedCursor,
+    DecodeError,
+};
+use lib0::encoding::{write_var_buf, write_var_i64, write_var_string, write_var_u64};
+
+fn encode_u64(num: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_var_u64(&mut buf, num);
+    buf
+}
+
+#[test]
+fn var_u64_boundaries() {
+    let cases: Vec<(u64, Vec<u8>)> = vec![
+        (0, vec![0x00]),
+        (1, vec![0x01]),
+        (127, vec![0x7f]),
+        (128, vec![0x80, 0x01]),
+        (300, vec![0xac, 0x02]),
+        (16383, vec![0xff, 0x7f]),
+        (16384, vec![0x80, 0x80, 0x01]),
+        (u32::MAX as u64, vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+        (
+            u64::MAX,
+            vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+    ];
+    for (num, expected) in cases {
+        let buf = encode_u64(num);
+        assert_eq!(buf, expected, "encoding of {}", num);
+        let mut cursor = CheckedCursor::from(buf.as_slice());
+        assert_eq!(read_var_u64(&mut cursor), Ok(num));
+        assert_eq!(cursor.remaining(), 0);
+    }
+}
+
+#[test]
+fn var_u64_errors() {
+    // truncated: continuation bit set on the last byte
+    for buf in [vec![], vec![0x80], vec![0xff, 0xff]] {
+        let mut cursor = CheckedCursor::from(buf.as_slice());
+        assert_eq!(read_var_u64(&mut cursor), Err(DecodeError::UnexpectedEnd));
+    }
+    // 65 bits
+    let buf = vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+    let mut cursor = CheckedCursor::from(buf.as_slice());
+    assert_eq!(read_var_u64(&mut cursor), Err(DecodeError::VarIntOverflow));
+    // too many bytes
+    let buf = vec![0x80; 11];
+    let mut cursor = CheckedCursor::from(buf.as_slice());
+    assert_eq!(read_var_u64(&mut cursor), Err(DecodeError::VarIntOverflow));
+    // doesn't fit into 32 bits
+    let buf = encode_u64(u32::MAX as u64 + 1);
+    let mut cursor = CheckedCursor::from(buf.as_slice());
+    assert_eq!(read_var_u32(&mut cursor), Err(DecodeError::VarIntOverflow));
+}
+
+#[test]
+fn var_i64_boundaries() {
+    let cases: Vec<(i64, Vec<u8>)> = vec![
+        (0, vec![0x00]),
+        (-1, vec![0x41]),
+        (63, vec![0x3f]),
+        (64, vec![0x80, 0x01]),
+        (-64, vec![0xc0, 0x01]),
+        (i64::MAX, {
+            let mut buf = vec![0xbf];
+            buf.extend_from_slice(&[0xff; 8]);
+            buf.push(0x01);
+            buf
+        }),
+        (-i64::MAX, {
+            let mut buf = vec![0xff];
+            buf.extend_from_slice(&[0xff; 8]);
+            buf.push(0x01);
+            buf
+        }),
+    ];
+    for (num, expected) in cases {
+        let mut buf = Vec::new();
+        write_var_i64(&mut buf, num);
+        assert_eq!(buf, expected, "encoding of {}", num);
+        let mut cursor = CheckedCursor::from(buf.as_slice());
+        assert_eq!(read_var_i64(&mut cursor), Ok(num));
+        assert_eq!(cursor.remaining(), 0);
+    }
+    let mut cursor = CheckedCursor::from(&[0xc0u8][..]);
+    assert_eq!(read_var_i64(&mut cursor), Err(DecodeError::UnexpectedEnd));
+}
+
+#[test]
+fn var_buf_and_string() {
+    let mut buf = Vec::new();
+    write_var_buf(&mut buf, &[]);
+    write_var_string(&mut buf, "");
+    write_var_string(&mut buf, "zażółć");
+    write_var_buf(&mut buf, &[0xff; 200]);
+    assert_eq!(&buf[..3], &[0x00, 0x00, 0x0a]);
+
+    let mut cursor = CheckedCursor::from(buf.as_slice());
+    assert_eq!(read_var_buf(&mut cursor), Ok(&[][..]));
+    assert_eq!(read_var_string(&mut cursor), Ok(""));
+    assert_eq!(read_var_string(&mut cursor), Ok("zażółć"));
+    assert_eq!(read_var_buf(&mut cursor), Ok(&[0xff; 200][..]));
+    assert_eq!(cursor.remaining(), 0);
+    assert_eq!(read_var_buf(&mut cursor), Err(DecodeError::UnexpectedEnd));
+}
+
+#[test]
+fn var_buf_and_string_errors() {
+    // declared length exceeds remaining bytes
+    let mut cursor = CheckedCursor::from(&[0x03u8, b'a', b'b'][..]);
+    assert_eq!(
+        read_var_string(&mut cursor),
+        Err(DecodeError::UnexpectedEnd)
+    );
+    // invalid UTF-8: a lone continuation byte and a truncated 2-byte sequence
+    for payload in [vec![0x01, 0x80], vec![0x01, 0xc5]] {
+        let mut cursor = CheckedCursor::from(payload.as_slice());
+        assert_eq!(read_var_string(&mut cursor), Err(DecodeError::InvalidUtf8));
+        let mut cursor = CheckedCursor::from(payload.as_slice());
+        assert_eq!(read_var_buf(&mut cursor), Ok(&payload[1..]));
+    }
+}
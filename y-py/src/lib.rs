@@ -1,15 +1,56 @@
+use lib0::decoding::DecoderOptions;
+use pyo3::basic::CompareOp;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyAny;
 use pyo3::wrap_pyfunction;
+use pyo3::{PyMappingProtocol, PyObjectProtocol, PySequenceProtocol};
 use pythonize::{depythonize, pythonize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use yrs;
 
+create_exception!(y_py, DecodingError, PyValueError);
+create_exception!(y_py, LimitExceededError, DecodingError);
+create_exception!(y_py, YError, PyException);
+
+fn decoding_error(e: yrs::Error) -> PyErr {
+    match e {
+        yrs::Error::LimitExceeded(_) => LimitExceededError::new_err(e.to_string()),
+        _ => DecodingError::new_err(e.to_string()),
+    }
+}
+
+/// Runs a given function, turning a panic raised by it into a [YError], so that it's reported as
+/// a regular Python exception instead of unwinding across the Python boundary. Unwind safety can be
+/// asserted, as functions passed here only operate on values owned by them.
+fn guard<T, F>(f: F) -> PyResult<T>
+where
+    F: FnOnce() -> PyResult<T>,
+{
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+                msg.to_string()
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                msg.clone()
+            } else {
+                "unknown error".to_string()
+            };
+            Err(YError::new_err(format!("internal error: {}", msg)))
+        }
+    }
+}
+
 #[pyfunction]
 pub fn merge_updates(updates: Vec<Vec<u8>>) -> PyResult<Py<PyAny>> {
     // Converts a Vec<Vec<u8>>  into a   [&[u8]]
     let updates_u8: Vec<&[u8]> = updates.iter().map(|x| &x[..]).collect();
 
-    let result = yrs::merge_updates(&updates_u8);
+    let result = guard(|| yrs::merge_updates(&updates_u8).map_err(decoding_error))?;
 
     let gil = Python::acquire_gil();
     let py = gil.python();
@@ -18,7 +59,7 @@ pub fn merge_updates(updates: Vec<Vec<u8>>) -> PyResult<Py<PyAny>> {
 
 #[pyfunction]
 pub fn encode_state_vector_from_update(update: Vec<u8>) -> PyResult<Py<PyAny>> {
-    let result = yrs::encode_state_vector_from_update(&update);
+    let result = guard(|| yrs::encode_state_vector_from_update(&update).map_err(decoding_error))?;
 
     let gil = Python::acquire_gil();
     let py = gil.python();
@@ -27,18 +68,123 @@ pub fn encode_state_vector_from_update(update: Vec<u8>) -> PyResult<Py<PyAny>> {
 
 #[pyfunction]
 pub fn diff_updates(update: Vec<u8>, state_vector: Vec<u8>) -> PyResult<Py<PyAny>> {
-    let result = yrs::diff_updates(&update, &state_vector);
+    let result = guard(|| yrs::diff_updates(&update, &state_vector).map_err(decoding_error))?;
 
     let gil = Python::acquire_gil();
     let py = gil.python();
     Ok(pythonize(py, &result)?)
 }
 
+/// Splits an update into a list of updates, each one taking no more than `max_size` bytes.
+/// Applying them in order integrates every one of them right away. A single block bigger than
+/// `max_size` is put into an update of its own, which exceeds the limit.
+#[pyfunction]
+pub fn split_update_v1(update: Vec<u8>, max_size: usize) -> PyResult<Py<PyAny>> {
+    let result = guard(|| yrs::split_update_v1(&update, max_size).map_err(decoding_error))?;
+
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    Ok(pythonize(py, &result)?)
+}
+
+/// Compact representation of all updates observed by a document replica. Behaves like a read-only
+/// dictionary of client ids and their clock values. State vectors can be compared with each other:
+/// `a >= b` means that `a` has observed all updates known to `b`, while vectors which are neither
+/// less, greater nor equal to each other are concurrent.
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct StateVector {
+    inner: yrs::StateVector,
+}
+
+#[pymethods]
+impl StateVector {
+    #[new]
+    fn new() -> Self {
+        StateVector::default()
+    }
+
+    /// Decodes a state vector from its lib0 ver. 1 binary representation.
+    #[staticmethod]
+    fn decode(data: Vec<u8>) -> PyResult<Self> {
+        let sv = guard(|| {
+            yrs::StateVector::try_decode_v1(&data, &DecoderOptions::default())
+                .map_err(decoding_error)
+        })?;
+        Ok(StateVector { inner: sv })
+    }
+
+    /// Returns a clock value observed for a given client or 0 if a client is unknown.
+    fn get(&self, client: u64) -> u32 {
+        self.inner.get(&client)
+    }
+
+    /// Returns a dictionary of all client ids and their observed clock values.
+    fn to_dict(&self) -> HashMap<u64, u32> {
+        self.inner
+            .iter()
+            .map(|(&client, &clock)| (client, clock))
+            .collect()
+    }
+
+    /// Checks if current state vector has observed all updates described by the other one.
+    fn includes(&self, other: PyRef<StateVector>) -> bool {
+        self.inner.includes(&other.inner)
+    }
+
+    /// Merges other state vector into current one, taking the highest clock value of each client.
+    fn merge(&mut self, other: PyRef<StateVector>) {
+        self.inner.merge(other.inner.clone())
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for StateVector {
+    fn __richcmp__(&self, other: PyRef<StateVector>, op: CompareOp) -> bool {
+        let ordering = self.inner.compare(&other.inner);
+        match op {
+            CompareOp::Lt => ordering == Some(Ordering::Less),
+            CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            CompareOp::Eq => ordering == Some(Ordering::Equal),
+            CompareOp::Ne => ordering != Some(Ordering::Equal),
+            CompareOp::Gt => ordering == Some(Ordering::Greater),
+            CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("StateVector({:?})", self.to_dict())
+    }
+}
+
+#[pyproto]
+impl PyMappingProtocol for StateVector {
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __getitem__(&self, client: u64) -> u32 {
+        self.inner.get(&client)
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for StateVector {
+    fn __contains__(&self, client: u64) -> bool {
+        self.inner.get(&client) != 0
+    }
+}
+
 #[pymodule(y_py)]
 fn y_py(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(merge_updates, m)?)?;
     m.add_function(wrap_pyfunction!(encode_state_vector_from_update, m)?)?;
     m.add_function(wrap_pyfunction!(diff_updates, m)?)?;
+    m.add_function(wrap_pyfunction!(split_update_v1, m)?)?;
+    m.add_class::<StateVector>()?;
+    m.add("DecodingError", py.get_type::<DecodingError>())?;
+    m.add("LimitExceededError", py.get_type::<LimitExceededError>())?;
+    m.add("YError", py.get_type::<YError>())?;
 
     Ok(())
 }
@@ -0,0 +1,41 @@
+import y_py
+from y_py import StateVector
+
+
+def test_dict_access():
+    sv = StateVector.decode([2, 1, 2, 2, 3])
+    assert len(sv) == 2
+    assert sv[1] == 2
+    assert sv.get(2) == 3
+    assert sv[5] == 0
+    assert 1 in sv
+    assert 5 not in sv
+    assert sv.to_dict() == {1: 2, 2: 3}
+
+
+def test_comparison():
+    a = StateVector.decode([2, 1, 2, 2, 3])
+    b = StateVector.decode([1, 1, 2])
+    assert a > b and a >= b and b < a
+    assert a.includes(b) and not b.includes(a)
+    assert a == StateVector.decode([2, 2, 3, 1, 2])
+
+    # concurrent vectors are neither less, greater nor equal
+    c = StateVector.decode([1, 3, 1])
+    assert not (a < c) and not (a > c) and a != c
+
+    a.merge(c)
+    assert a.to_dict() == {1: 2, 2: 3, 3: 1}
+
+
+def test_decoding_errors():
+    try:
+        StateVector.decode([5, 1])
+        assert False
+    except y_py.DecodingError:
+        pass
+    try:
+        StateVector.decode([0xFF, 0xFF, 0xFF, 0xFF, 0x0F])
+        assert False
+    except y_py.LimitExceededError:
+        pass
@@ -0,0 +1,59 @@
+import y_py
+
+# const ydoc1 = new Y.Doc()
+# ydoc1.getArray('array_doc1').insert(0, ['example 2, array doc 1, 0th value'])
+# ydoc1.getArray('array_doc1').insert(1, ['example 2, array doc 1, 0th value'])
+# ydoc1.getArray('array2_doc1').insert(0, ['example 2, array 2 doc 1, 0th value'])
+# let currentState1 = Y.encodeStateAsUpdate(ydoc1)
+update = [
+    1, 2, 242, 143, 191, 196, 7, 0, 8, 1, 10, 97, 114, 114, 97, 121, 95, 100, 111,
+    99, 49, 2, 119, 33, 101, 120, 97, 109, 112, 108, 101, 32, 50, 44, 32, 97, 114, 114,
+    97, 121, 32, 100, 111, 99, 32, 49, 44, 32, 48, 116, 104, 32, 118, 97, 108, 117, 101,
+    119, 33, 101, 120, 97, 109, 112, 108, 101, 32, 50, 44, 32, 97, 114, 114, 97, 121, 32,
+    100, 111, 99, 32, 49, 44, 32, 48, 116, 104, 32, 118, 97, 108, 117, 101, 8, 1, 11, 97,
+    114, 114, 97, 121, 50, 95, 100, 111, 99, 49, 1, 119, 35, 101, 120, 97, 109, 112, 108,
+    101, 32, 50, 44, 32, 97, 114, 114, 97, 121, 32, 50, 32, 100, 111, 99, 32, 49, 44, 32,
+    48, 116, 104, 32, 118, 97, 108, 117, 101, 0,
+]
+
+
+def test_split_update():
+    # each block exceeds the limit, so it's put into an update of its own
+    chunks = y_py.split_update_v1(update, 100)
+    assert len(chunks) == 2
+    merged = y_py.merge_updates(chunks)
+    assert y_py.encode_state_vector_from_update(
+        merged
+    ) == y_py.encode_state_vector_from_update(update)
+
+    # update fitting within the limit is not split
+    assert len(y_py.split_update_v1(update, 1024)) == 1
+
+
+def test_malformed_update():
+    # malformed input is reported as a regular exception, never as an internal error
+    malformed = update[:40]
+    for f in [
+        lambda: y_py.merge_updates([malformed]),
+        lambda: y_py.encode_state_vector_from_update(malformed),
+        lambda: y_py.diff_updates(malformed, [0]),
+        lambda: y_py.split_update_v1(malformed, 100),
+    ]:
+        try:
+            f()
+            assert False
+        except y_py.DecodingError:
+            pass
+    assert issubclass(y_py.YError, Exception)
+    assert not issubclass(y_py.DecodingError, y_py.YError)
+
+
+def test_internal_error():
+    # a decodable update with an empty list of blocks for a client makes yrs panic, which is
+    # reported as an exception instead of crashing the interpreter
+    empty_client = [1, 0, 1, 0, 0]
+    try:
+        y_py.encode_state_vector_from_update(empty_client)
+        assert False
+    except y_py.YError as e:
+        assert str(e).startswith("internal error: ")
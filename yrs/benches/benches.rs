@@ -1,12 +1,180 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use yrs::*;
 
+/// Global allocator counting the number of performed allocations and the peak number of
+/// allocated bytes.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+fn track_allocated(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        track_allocated(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        track_allocated(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Criterion measurement of the number of allocations performed by a benchmarked routine.
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, before: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - before
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocationsFormatter
+    }
+}
+
+struct AllocationsFormatter;
+
+impl ValueFormatter for AllocationsFormatter {
+    fn scale_values(&self, _typical: f64, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        _: &Throughput,
+        _values: &mut [f64],
+    ) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+/// Criterion measurement of the peak number of bytes allocated on top of already allocated ones.
+/// Since criterion reports values averaged over all iterations of a sample, routines must be
+/// benchmarked with [peak_allocated], which reports the same peak for each iteration.
+struct PeakAllocated;
+
+impl Measurement for PeakAllocated {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        PEAK_ALLOCATED.store(before, Ordering::Relaxed);
+        before
+    }
+
+    fn end(&self, before: usize) -> usize {
+        PEAK_ALLOCATED.load(Ordering::Relaxed) - before
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical < 1024.0 {
+            (1.0, "B")
+        } else if typical < 1024.0 * 1024.0 {
+            (1024.0, "KiB")
+        } else {
+            (1024.0 * 1024.0, "MiB")
+        };
+        for value in values.iter_mut() {
+            *value /= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(&self, typical: f64, _: &Throughput, values: &mut [f64]) -> &'static str {
+        self.scale_values(typical, values)
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+/// Runs `f` a given number of `iterations`, returning the peak number of bytes allocated by
+/// a single run multiplied by the number of iterations.
+fn peak_allocated<F: FnMut()>(iterations: u64, mut f: F) -> usize {
+    let mut peak = 0;
+    for _ in 0..iterations {
+        let before = PeakAllocated.start();
+        f();
+        peak = peak.max(PeakAllocated.end(before));
+    }
+    peak * iterations as usize
+}
+
 const ITERATIONS: u32 = 1000000;
 
 fn ytext_prepend() {
     let doc = Doc::new();
     let tr = &mut doc.transact();
-    let t = doc.get_text(tr, "");
+    let t = tr.get_text("");
     for _ in 0..ITERATIONS {
         t.insert(tr, 0, "a")
     }
@@ -15,7 +183,7 @@ fn ytext_prepend() {
 fn ytext_append() {
     let doc = Doc::new();
     let tr = &mut doc.transact();
-    let t = doc.get_text(tr, "");
+    let t = tr.get_text("");
     for i in 0..6000 {
         t.insert(tr, i, "a")
     }
@@ -37,7 +205,277 @@ fn gen_vec_perf_pred_optimal() {
     }
 }
 
+const DIFF_CLIENTS: usize = 100;
+
+fn diff_source() -> Doc {
+    let doc = Doc::with_client_id(1);
+    let mut tr = doc.transact();
+    let t = tr.get_text("text");
+    for i in 0..1000 {
+        t.insert(&mut tr, i, "a");
+    }
+    drop(tr);
+    doc
+}
+
+/// Encodes a diff for a number of remote clients, each time allocating a new buffer.
+fn encode_diff_fresh(doc: &Doc, sv: &StateVector) -> usize {
+    let tr = doc.transact();
+    let mut len = 0;
+    for _ in 0..DIFF_CLIENTS {
+        len += doc.encode_delta_as_update_v1(&tr, sv).len();
+    }
+    len
+}
+
+/// Encodes a diff for a number of remote clients, reusing the same buffer.
+fn encode_diff_reused(doc: &Doc, sv: &StateVector, buf: &mut Vec<u8>) -> usize {
+    let tr = doc.transact();
+    let mut len = 0;
+    for _ in 0..DIFF_CLIENTS {
+        buf.clear();
+        tr.encode_diff_v1_into(sv, buf);
+        len += buf.len();
+    }
+    len
+}
+
+/// Creates a document edited by `clients` peers, each one inserting `blocks` elements stored in
+/// separate blocks, together with a state vector of a remote peer which is missing only the last
+/// element inserted by one of them.
+fn near_identical_diff_source(clients: u64, blocks: u32) -> (Doc, StateVector) {
+    let doc = Doc::with_client_id(clients + 1);
+    doc.apply_update_v1(&mut doc.transact(), &large_update(clients, blocks))
+        .unwrap();
+    let mut tr = doc.transact();
+    let sv = doc.get_state_vector(&tr);
+    tr.get_text("text-1").insert(&mut tr, 0, "b");
+    drop(tr);
+    (doc, sv)
+}
+
+/// Creates a text of `chunks` separate blocks, each one holding `chunk_len` characters.
+fn chunked_text(chunks: usize, chunk_len: usize) -> (Doc, Text) {
+    let doc = Doc::with_client_id(1);
+    let mut tr = doc.transact();
+    let t = tr.get_text("text");
+    let chunk = "a".repeat(chunk_len);
+    for _ in 0..chunks {
+        t.insert(&mut tr, 0, &chunk);
+    }
+    drop(tr);
+    (doc, t)
+}
+
+/// Feeds written text into a hasher, without buffering it.
+struct HashWriter<H: Hasher>(H);
+
+impl<H: Hasher> std::fmt::Write for HashWriter<H> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn hash_text(doc: &Doc, text: &Text, streamed: bool) -> u64 {
+    let tr = doc.transact();
+    let mut writer = HashWriter(DefaultHasher::new());
+    if streamed {
+        text.write_to(&tr, &mut writer).unwrap();
+    } else {
+        writer.0.write(text.to_string(&tr).as_bytes());
+    }
+    writer.0.finish()
+}
+
+/// Creates an array of `len` elements, each one stored in a separate block.
+fn fragmented_array(len: u32) -> Doc {
+    let doc = Doc::with_client_id(1);
+    let mut tr = doc.transact();
+    let a = tr.get_array("array");
+    for i in 0..len {
+        a.insert(&mut tr, 0, i);
+    }
+    drop(tr);
+    doc
+}
+
+/// Inserts a single element into an array at a position computed from its current length.
+fn array_insert<F: Fn(u32) -> u32>(doc: &Doc, index: F) {
+    let mut tr = doc.transact();
+    let a = tr.get_array("array");
+    let i = index(a.len());
+    a.insert(&mut tr, i, i);
+}
+
+/// Reads all elements of an array one by one, either by their indexes or with a cursor.
+fn array_read_each(doc: &Doc, cursor: bool) -> usize {
+    let mut tr = doc.transact();
+    let a = tr.get_array("array");
+    let mut count = 0;
+    if cursor {
+        let mut c = a.cursor(&tr);
+        while !c.read(&tr, 1).is_empty() {
+            count += 1;
+        }
+    } else {
+        for i in 0..a.len() {
+            if a.get(&tr, i).is_some() {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Walks over a whole text made of 8-character blocks, either by index or with a cursor, inserting
+/// a character after each block and removing every other block that follows it. Edits are made at
+/// block boundaries, so that they don't split blocks.
+fn text_edit_script(doc: &Doc, text: &Text, cursor: bool) -> u32 {
+    let mut tr = doc.transact();
+    let mut len = text.len();
+    let mut edits = 0;
+    if cursor {
+        let mut c = text.cursor(&tr);
+        c.forward(8);
+        while c.index() + 8 < len {
+            c.insert(&mut tr, "x");
+            len += 1;
+            edits += 1;
+            if edits % 2 == 0 {
+                c.delete(&mut tr, 8);
+                len -= 8;
+            } else {
+                c.forward(8);
+            }
+        }
+        c.index()
+    } else {
+        let mut index = 8;
+        while index + 8 < len {
+            text.insert(&mut tr, index, "x");
+            index += 1;
+            len += 1;
+            edits += 1;
+            if edits % 2 == 0 {
+                text.remove_range(&mut tr, index, 8);
+                len -= 8;
+            } else {
+                index += 8;
+            }
+        }
+        index
+    }
+}
+
+/// Creates a full state update of a document edited by `clients` peers, each one inserting
+/// `blocks` elements stored in separate blocks into its own text.
+fn large_update(clients: u64, blocks: u32) -> Vec<u8> {
+    let doc = Doc::with_client_id(0);
+    let mut tr = doc.transact();
+    for client in 1..=clients {
+        let peer = Doc::with_client_id(client);
+        let mut ptr = peer.transact();
+        let t = ptr.get_text(&format!("text-{}", client));
+        for _ in 0..blocks {
+            t.insert(&mut ptr, 0, "a");
+        }
+        let update = peer.encode_state_as_update_v1(&ptr);
+        doc.apply_update_v1(&mut tr, &update).unwrap();
+    }
+    doc.encode_state_as_update_v1(&tr)
+}
+
+fn apply_update(update: &[u8], lazy: bool) {
+    let doc = Doc::new();
+    let mut tr = doc.transact();
+    if lazy {
+        doc.apply_update_v1_lazy(&mut tr, update).unwrap();
+    } else {
+        doc.apply_update_v1(&mut tr, update).unwrap();
+    }
+}
+
+fn load_update(update: &[u8], fresh: bool) {
+    if fresh {
+        Doc::from_update_v1(update, Options::default()).unwrap();
+    } else {
+        let doc = Doc::with_options(Options::default());
+        let mut tr = doc.transact();
+        doc.apply_update_v1(&mut tr, update).unwrap();
+    }
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
+    let doc = diff_source();
+    let sv = StateVector::default();
+    let mut buf = Vec::with_capacity(doc.transact().diff_size_hint(&sv));
+    c.bench_function("encode diff fresh buffers", |b| {
+        b.iter(|| encode_diff_fresh(&doc, &sv))
+    });
+    c.bench_function("encode diff reused buffer", |b| {
+        b.iter(|| encode_diff_reused(&doc, &sv, &mut buf))
+    });
+
+    let (doc, sv) = near_identical_diff_source(2000, 50);
+    c.bench_function("encode diff near-identical state vector", |b| {
+        b.iter(|| encode_diff_reused(&doc, &sv, &mut buf))
+    });
+
+    let (doc, text) = chunked_text(10_000, 1000);
+    c.bench_function("hash text via to_string", |b| {
+        b.iter(|| hash_text(&doc, &text, false))
+    });
+    c.bench_function("hash text via write_to", |b| {
+        b.iter(|| hash_text(&doc, &text, true))
+    });
+
+    for &len in [10_000u32, 100_000].iter() {
+        let doc = fragmented_array(len);
+        c.bench_function(&format!("array append ({} blocks)", len), |b| {
+            b.iter(|| array_insert(&doc, |len| len))
+        });
+        let doc = fragmented_array(len);
+        c.bench_function(
+            &format!("array insert in the middle ({} blocks)", len),
+            |b| b.iter(|| array_insert(&doc, |len| len / 2)),
+        );
+    }
+
+    let doc = fragmented_array(100_000);
+    c.bench_function("array read each element by index (100000 blocks)", |b| {
+        b.iter(|| array_read_each(&doc, false))
+    });
+    c.bench_function("array read each element with cursor (100000 blocks)", |b| {
+        b.iter(|| array_read_each(&doc, true))
+    });
+
+    for &cursor in [false, true].iter() {
+        let name = if cursor { "with cursor" } else { "by index" };
+        c.bench_function(&format!("text edit script {} (10000 blocks)", name), |b| {
+            b.iter_batched(
+                || chunked_text(10_000, 8),
+                |(doc, text)| text_edit_script(&doc, &text, cursor),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    let update = large_update(50, 2000);
+    c.bench_function("apply large update eagerly", |b| {
+        b.iter(|| apply_update(&update, false))
+    });
+    c.bench_function("apply large update lazily", |b| {
+        b.iter(|| apply_update(&update, true))
+    });
+
+    c.bench_function("load large update into a new document", |b| {
+        b.iter(|| load_update(&update, false))
+    });
+    c.bench_function("load large update with Doc::from_update_v1", |b| {
+        b.iter(|| load_update(&update, true))
+    });
+
     c.bench_function("ytext prepend", |b| b.iter(|| ytext_prepend()));
     c.bench_function("ytext append", |b| b.iter(|| ytext_append()));
     c.bench_function("gen vec perf optimal", |b| {
@@ -48,5 +486,65 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
+fn allocations_benchmark(c: &mut Criterion<Allocations>) {
+    let doc = diff_source();
+    let sv = StateVector::default();
+    let mut buf = Vec::with_capacity(doc.transact().diff_size_hint(&sv));
+    c.bench_function("allocations: encode diff fresh buffers", |b| {
+        b.iter(|| encode_diff_fresh(&doc, &sv))
+    });
+    c.bench_function("allocations: encode diff reused buffer", |b| {
+        b.iter(|| encode_diff_reused(&doc, &sv, &mut buf))
+    });
+}
+
+fn peak_allocated_benchmark(c: &mut Criterion<PeakAllocated>) {
+    let (doc, text) = chunked_text(10_000, 1000);
+    c.bench_function("peak allocated: hash text via to_string", |b| {
+        b.iter_custom(|iters| {
+            peak_allocated(iters, || {
+                hash_text(&doc, &text, false);
+            })
+        })
+    });
+    c.bench_function("peak allocated: hash text via write_to", |b| {
+        b.iter_custom(|iters| {
+            peak_allocated(iters, || {
+                hash_text(&doc, &text, true);
+            })
+        })
+    });
+
+    let update = large_update(50, 2000);
+    c.bench_function("peak allocated: apply large update eagerly", |b| {
+        b.iter_custom(|iters| peak_allocated(iters, || apply_update(&update, false)))
+    });
+    c.bench_function("peak allocated: apply large update lazily", |b| {
+        b.iter_custom(|iters| peak_allocated(iters, || apply_update(&update, true)))
+    });
+}
+
 criterion_group!(benches, criterion_benchmark);
-criterion_main!(benches);
+
+// Allocation counts are deterministic, so all samples are equal and criterion fails to plot their
+// distribution. Plots are disabled after applying command line arguments, which would re-enable
+// them if set in a `criterion_group!` config.
+fn allocation_benches() {
+    let mut c = Criterion::default()
+        .with_measurement(Allocations)
+        .sample_size(10)
+        .configure_from_args()
+        .without_plots();
+    allocations_benchmark(&mut c);
+}
+
+fn peak_allocated_benches() {
+    let mut c = Criterion::default()
+        .with_measurement(PeakAllocated)
+        .sample_size(10)
+        .configure_from_args()
+        .without_plots();
+    peak_allocated_benchmark(&mut c);
+}
+
+criterion_main!(benches, allocation_benches, peak_allocated_benches);
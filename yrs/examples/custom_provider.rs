@@ -9,7 +9,7 @@ fn main() {
     let update = tr.encode_update_v1();
 
     let tr2 = &mut doc2.transact();
-    doc2.apply_update_v1(tr2, &update);
+    doc2.apply_update_v1(tr2, &update).unwrap();
     let ytext2 = tr2.get_text("mytext");
     let txt2 = ytext2.to_string(tr);
     println!("synced document state: {}", txt2);
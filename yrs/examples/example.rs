@@ -43,7 +43,7 @@ fn main() {
     let doc2 = Doc::new();
     let tr2 = &mut doc2.transact();
     let t2 = tr2.get_text("");
-    doc2.apply_update_v1(tr2, &update);
+    doc2.apply_update_v1(tr2, &update).unwrap();
     println!(
         "doc2 content (this is manually synced from doc1) {}",
         t2.to_string(tr2)
@@ -1,24 +1,24 @@
 use crate::id_set::DeleteSet;
 use crate::update::Update;
 use crate::updates::decoder::{Decode, Decoder, DecoderV1};
-use crate::updates::encoder::{Encode, Encoder, EncoderV1};
-use crate::StateVector;
+use crate::updates::encoder::{Encode, EncoderV1};
+use crate::{Error, StateVector, ID};
 use lib0::decoding::Cursor;
 
-pub fn merge_updates(updates: &[&[u8]]) -> Vec<u8> {
+pub fn merge_updates(updates: &[&[u8]]) -> Result<Vec<u8>, Error> {
     match updates.len() {
-        0 => vec![0, 0],
-        1 => updates[0].to_vec(),
+        0 => Ok(vec![0, 0]),
+        1 => Ok(updates[0].to_vec()),
         _ => {
             let mut iter = updates.iter();
             let mut decoder = DecoderV1::new(Cursor::new(iter.next().unwrap()));
-            let mut update = Update::decode(&mut decoder);
-            let mut ds = DeleteSet::decode(&mut decoder);
+            let mut update = Update::decode(&mut decoder)?;
+            let mut ds = DeleteSet::decode(&mut decoder)?;
 
             while let Some(data) = iter.next() {
                 let mut decoder = DecoderV1::new(Cursor::new(data));
-                let u = Update::decode(&mut decoder);
-                let d = DeleteSet::decode(&mut decoder);
+                let u = Update::decode(&mut decoder)?;
+                let d = DeleteSet::decode(&mut decoder)?;
 
                 update.merge(u);
                 ds.merge(d);
@@ -27,23 +27,23 @@ pub fn merge_updates(updates: &[&[u8]]) -> Vec<u8> {
             let mut encoder = EncoderV1::new();
             update.encode_diff(&StateVector::default(), &mut encoder);
             ds.encode(&mut encoder);
-            encoder.to_vec()
+            Ok(encoder.to_vec())
         }
     }
 }
 
 // Computes the state vector from a document update
-pub fn encode_state_vector_from_update(update: &[u8]) -> Vec<u8> {
-    let update = Update::decode_v1(update);
-    update.state_vector().encode_v1()
+pub fn encode_state_vector_from_update(update: &[u8]) -> Result<Vec<u8>, Error> {
+    let update = Update::decode_v1(update)?;
+    Ok(update.state_vector().encode_v1())
 }
 
 // Encode the missing differences to another document update.
-pub fn diff_updates(update: &[u8], state_vector: &[u8]) -> Vec<u8> {
-    let sv = StateVector::decode_v1(state_vector);
+pub fn diff_updates(update: &[u8], state_vector: &[u8]) -> Result<Vec<u8>, Error> {
+    let sv = StateVector::decode_v1(state_vector)?;
     let cursor = Cursor::new(update);
     let mut decoder = DecoderV1::new(cursor);
-    let update = Update::decode(&mut decoder);
+    let update = Update::decode(&mut decoder)?;
 
     let mut encoder = EncoderV1::new();
     update.encode_diff(&sv, &mut encoder);
@@ -51,12 +51,148 @@ pub fn diff_updates(update: &[u8], state_vector: &[u8]) -> Vec<u8> {
     // for delete set, don't decode/encode it - just copy the remaining part from the decoder
     let mut result = encoder.to_vec();
     result.extend_from_slice(decoder.read_to_end());
-    result
+    Ok(result)
+}
+
+/// Returns a copy of a given document `update`, which contains only blocks (together with the
+/// information about their deletion) authored by clients for which a `keep` predicate returned
+/// true.
+pub fn filter_update_clients<F>(update: &[u8], keep: F) -> Result<Vec<u8>, Error>
+where
+    F: Fn(u64) -> bool,
+{
+    let mut decoder = DecoderV1::new(Cursor::new(update));
+    let update = Update::decode(&mut decoder)?;
+    let ds = DeleteSet::decode(&mut decoder)?;
+
+    let update = update.filter_clients(&keep);
+    let mut filtered = DeleteSet::new();
+    for (&client, range) in ds.iter() {
+        if keep(client) {
+            for r in range.iter() {
+                filtered.insert(ID::new(client, r.start), r.end - r.start);
+            }
+        }
+    }
+
+    let mut encoder = EncoderV1::new();
+    update.encode(&mut encoder);
+    filtered.encode(&mut encoder);
+    Ok(encoder.to_vec())
+}
+
+/// Encodes a standalone delete set using lib0 ver. 1 encoding. It's the same format, which is used
+/// to store a delete set at the end of a document update.
+pub fn encode_delete_set_v1(ds: &DeleteSet) -> Vec<u8> {
+    ds.encode_v1()
+}
+
+/// Decodes a standalone delete set encoded using lib0 ver. 1 encoding.
+pub fn decode_delete_set_v1(data: &[u8]) -> Result<DeleteSet, Error> {
+    DeleteSet::decode_v1(data)
+}
+
+/// Returns a copy of a given document `update`, in which all blocks authored by a client `from`
+/// (together with the information about their deletion) have been reassigned to a client `to`.
+/// Fails if remapped blocks would collide with blocks already authored by a client `to` within
+/// the same update.
+pub fn remap_update_client(update: &[u8], from: u64, to: u64) -> Result<Vec<u8>, Error> {
+    let mut decoder = DecoderV1::new(Cursor::new(update));
+    let update = Update::decode(&mut decoder)?;
+    let ds = DeleteSet::decode(&mut decoder)?;
+
+    let update = update.remap_client(from, to)?;
+    let mut remapped = DeleteSet::new();
+    for (&client, range) in ds.iter() {
+        let client = if client == from { to } else { client };
+        for r in range.iter() {
+            remapped.insert(ID::new(client, r.start), r.end - r.start);
+        }
+    }
+    remapped.squash();
+
+    let mut encoder = EncoderV1::new();
+    update.encode(&mut encoder);
+    remapped.encode(&mut encoder);
+    Ok(encoder.to_vec())
+}
+
+/// Splits a binary document `update` into a sequence of updates, each one taking no more than
+/// `max_size` bytes. Applying all of them, in any order, produces the same document state as
+/// applying the original `update`, but only chunks applied in order are guaranteed to be
+/// integrated right away. A single block bigger than `max_size` is put into a chunk of its own,
+/// which exceeds the limit.
+pub fn split_update_v1(update: &[u8], max_size: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let mut decoder = DecoderV1::new(Cursor::new(update));
+    let update = Update::decode(&mut decoder)?;
+    let ds = DeleteSet::decode(&mut decoder)?;
+    Ok(update.split(&ds, max_size))
+}
+
+/// Returns a delete set stored within a given binary document `update`, which describes all blocks
+/// deleted by it. Use [encode_state_vector_from_update] to learn which blocks have been inserted.
+pub fn delete_set_from_update(update: &[u8]) -> Result<DeleteSet, Error> {
+    let mut decoder = DecoderV1::new(Cursor::new(update));
+    Update::decode(&mut decoder)?;
+    DeleteSet::decode(&mut decoder)
+}
+
+/// Returns a copy of a given binary document `update`, which contains only its blocks, without
+/// information about deleted ones. See: [deletions_only_update].
+///
+/// Keep in mind that the content of blocks garbage collected before an `update` was created is
+/// gone, so such blocks are still integrated as deleted.
+pub fn blocks_only_update(update: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = DecoderV1::new(Cursor::new(update));
+    let update = Update::decode(&mut decoder)?;
+    // make sure that a delete set is valid as well, even though it's dropped
+    DeleteSet::decode(&mut decoder)?;
+
+    let mut encoder = EncoderV1::new();
+    update.encode(&mut encoder);
+    DeleteSet::new().encode(&mut encoder);
+    Ok(encoder.to_vec())
+}
+
+/// Returns a copy of a given binary document `update`, which contains only information about
+/// deleted blocks. Together with [blocks_only_update] it can be used to apply deletions
+/// separately from the new content, ie. after they have been moderated.
+///
+/// Both halves can be applied in any order and end up in the same document state as applying
+/// an original `update`: deletions of blocks which have not been integrated yet are kept aside
+/// until these blocks arrive.
+pub fn deletions_only_update(update: &[u8]) -> Result<Vec<u8>, Error> {
+    let ds = delete_set_from_update(update)?;
+    let mut encoder = EncoderV1::new();
+    Update::default().encode(&mut encoder);
+    ds.encode(&mut encoder);
+    Ok(encoder.to_vec())
+}
+
+/// Compresses a binary document `update` using deflate algorithm. Compressed payload starts with
+/// a small header identifying compression algorithm used.
+#[cfg(feature = "compression")]
+pub fn compress_update(update: &[u8]) -> Vec<u8> {
+    Update::compress(update)
+}
+
+/// Decompresses a binary document update created using [compress_update]. Returns an error if
+/// `data` doesn't start with a known compression header or could not be decompressed.
+#[cfg(feature = "compression")]
+pub fn decompress_update(data: &[u8]) -> Result<Vec<u8>, Error> {
+    Update::decompress(data)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{diff_updates, encode_state_vector_from_update, merge_updates};
+    use crate::{
+        blocks_only_update, delete_set_from_update, deletions_only_update, diff_updates,
+        encode_state_vector_from_update, filter_update_clients, merge_updates, remap_update_client,
+        split_update_v1, Doc, Error, Options, PrelimMap,
+    };
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
 
     #[test]
     fn merge_updates_compatibility() {
@@ -71,7 +207,7 @@ mod test {
             139, 250, 201, 1, 0, 4, 1, 4, 116, 101, 115, 116, 2, 100, 101, 0,
         ];
 
-        let actual = merge_updates(&[a, b]);
+        let actual = merge_updates(&[a, b]).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -82,7 +218,7 @@ mod test {
             139, 250, 201, 1, 0, 4, 1, 4, 116, 101, 115, 116, 2, 100, 101, 0,
         ];
         let expected = &[2, 220, 240, 237, 172, 15, 3, 201, 139, 250, 201, 1, 2];
-        let actual = encode_state_vector_from_update(update);
+        let actual = encode_state_vector_from_update(update).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -96,7 +232,184 @@ mod test {
         let expected = &[
             1, 1, 148, 189, 145, 162, 9, 3, 68, 148, 189, 145, 162, 9, 0, 2, 100, 101, 0,
         ];
-        let actual = diff_updates(update, state_vector);
+        let actual = diff_updates(update, state_vector).unwrap();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn filter_and_remap_update_clients() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt = t1.get_text("text");
+        txt.insert(&mut t1, 0, "abc");
+        txt.remove_range(&mut t1, 1, 1);
+        let u1 = t1.encode_update_v1();
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &u1).unwrap();
+        t2.get_array("array").push_back(&mut t2, 1);
+        let update = d2.encode_state_as_update_v1(&t2);
+
+        let filtered = filter_update_clients(&update, |client| client == 1).unwrap();
+        let remapped = remap_update_client(&filtered, 1, 3).unwrap();
+
+        let d3 = Doc::with_client_id(4);
+        let mut t3 = d3.transact();
+        d3.apply_update_v1(&mut t3, &remapped).unwrap();
+        assert_eq!(t3.get_text("text").to_string(&t3), "ac");
+        assert_eq!(t3.get_array("array").len(), 0);
+        let sv = d3.get_state_vector(&t3);
+        assert_eq!(sv.get(&1), 0);
+        assert_eq!(sv.get(&2), 0);
+        assert_eq!(sv.get(&3), 3);
+
+        let err = remap_update_client(&update, 1, 2).unwrap_err();
+        assert!(matches!(err, Error::ClientRemap(e) if e.client == 2));
+    }
+
+    #[test]
+    fn split_update() {
+        // three peers making interleaved edits, each one depending on edits of the others
+        let docs: Vec<_> = (1..=3).map(Doc::with_client_id).collect();
+        for round in 0..30u32 {
+            for (i, doc) in docs.iter().enumerate() {
+                let mut txn = doc.transact();
+                let txt = txn.get_text("text");
+                let index = (round * 7 + i as u32) % (txt.len() + 1);
+                txt.insert(&mut txn, index, "hello ");
+                if txt.len() > 10 && round % 3 == 0 {
+                    txt.remove_range(&mut txn, index / 2, 3);
+                }
+                let map = txn.get_map("map");
+                map.insert(&mut txn, format!("key{}", round % 4), round);
+                if round % 5 == 0 {
+                    let mut nested = std::collections::HashMap::new();
+                    nested.insert("round".to_owned(), round);
+                    map.insert(&mut txn, "nested".to_owned(), PrelimMap::from(nested));
+                }
+            }
+            let updates: Vec<_> = docs
+                .iter()
+                .map(|doc| doc.encode_state_as_update_v1(&doc.transact()))
+                .collect();
+            for doc in docs.iter() {
+                let mut txn = doc.transact();
+                for update in updates.iter() {
+                    doc.apply_update_v1(&mut txn, update).unwrap();
+                }
+            }
+        }
+        // big block exceeding the size limit on its own
+        {
+            let mut txn = docs[0].transact();
+            txn.get_text("text").insert(&mut txn, 0, &"x".repeat(300));
+        }
+
+        let mut t1 = docs[0].transact();
+        let update = docs[0].encode_state_as_update_v1(&t1);
+        let chunks = split_update_v1(&update, 200).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().filter(|chunk| chunk.len() > 200).count(), 1);
+
+        let expected_text = t1.get_text("text").to_string(&t1);
+        let expected_map = t1.get_map("map").to_json(&t1);
+        let expected_sv = docs[0].get_state_vector(&t1);
+        let mut expected_ds = docs[0].delete_set(&t1);
+        expected_ds.squash();
+        let assert_converged = |doc: &Doc| {
+            let mut txn = doc.transact();
+            assert_eq!(txn.get_text("text").to_string(&txn), expected_text);
+            assert_eq!(txn.get_map("map").to_json(&txn), expected_map);
+            assert_eq!(doc.get_state_vector(&txn), expected_sv);
+            let mut ds = doc.delete_set(&txn);
+            ds.squash();
+            assert_eq!(ds, expected_ds);
+        };
+
+        // chunks applied in order are integrated right away
+        let doc = Doc::with_client_id(4);
+        for chunk in chunks.iter() {
+            let mut txn = doc.transact();
+            doc.apply_update_v1(&mut txn, chunk).unwrap();
+            assert_eq!(txn.store_stats().pending_blocks, 0);
+        }
+        assert_converged(&doc);
+
+        // chunks applied out of order converge once all of them arrive
+        let mut rng = StdRng::seed_from_u64(0x5b1);
+        for _ in 0..5 {
+            let mut shuffled = chunks.clone();
+            shuffled.shuffle(&mut rng);
+            let doc = Doc::with_client_id(4);
+            for chunk in shuffled.iter() {
+                let mut txn = doc.transact();
+                doc.apply_update_v1(&mut txn, chunk).unwrap();
+            }
+            assert_converged(&doc);
+        }
+    }
+
+    #[test]
+    fn split_blocks_and_deletions() {
+        // deleted content must not be garbage collected to be restored by blocks-only update
+        let d1 = Doc::with_options(Options {
+            skip_gc: true,
+            ..Options::with_client_id(1)
+        });
+        {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, "hello world");
+            let array = txn.get_array("array");
+            array.insert_range(&mut txn, 0, vec![1, 2, 3]);
+        }
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").remove_range(&mut txn, 0, 6);
+            txn.get_array("array").remove(&mut txn, 1);
+            txn.get_text("text").push(&mut txn, "!");
+        }
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        let blocks = blocks_only_update(&update).unwrap();
+        let deletions = deletions_only_update(&update).unwrap();
+
+        let ds = delete_set_from_update(&update).unwrap();
+        assert_eq!(ds, d1.delete_set(&d1.transact()));
+        assert!(delete_set_from_update(&blocks).unwrap().is_empty());
+        assert_eq!(delete_set_from_update(&deletions).unwrap(), ds);
+        assert_eq!(
+            encode_state_vector_from_update(&deletions).unwrap(),
+            vec![0]
+        );
+
+        let content = |doc: &Doc| {
+            let mut txn = doc.transact();
+            (
+                txn.get_text("text").to_string(&txn),
+                txn.get_array("array").to_json(&txn),
+                doc.get_state_vector(&txn),
+                doc.delete_set(&txn),
+            )
+        };
+        let expected = content(&d1);
+        assert_eq!(expected.0, "world!");
+
+        let d2 = Doc::with_client_id(2);
+        d2.apply_update_v1(&mut d2.transact(), &blocks).unwrap();
+        {
+            let mut txn = d2.transact();
+            assert_eq!(txn.get_text("text").to_string(&txn), "hello world!");
+        }
+        d2.apply_update_v1(&mut d2.transact(), &deletions).unwrap();
+        assert_eq!(content(&d2), expected);
+
+        let d3 = Doc::with_client_id(3);
+        d3.apply_update_v1(&mut d3.transact(), &deletions).unwrap();
+        d3.apply_update_v1(&mut d3.transact(), &blocks).unwrap();
+        assert_eq!(content(&d3), expected);
+
+        assert!(blocks_only_update(&[1]).is_err());
+        assert!(deletions_only_update(&[1]).is_err());
+    }
 }
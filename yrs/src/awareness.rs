@@ -0,0 +1,422 @@
+//! Implementation of the [y-protocols](https://github.com/yjs/y-protocols) awareness protocol,
+//! used to share non-persistent information about peers working on the same document, like
+//! their names, colors or cursor positions.
+//!
+//! Every peer owns a single state - a JSON-like [Any] value - identified by its client id.
+//! Each change of that state increments its clock, so that peers can tell which of the states
+//! they received is the most recent one. Awareness updates are binary compatible with Yjs
+//! `encodeAwarenessUpdate`/`applyAwarenessUpdate`.
+//!
+//! States are not persisted, so peers which went offline without clearing their state are
+//! expected to be timed out (see: [Awareness::remove_outdated]). To avoid being timed out
+//! itself, a local peer should periodically renew its state (see: [Awareness::renew_local_clock]).
+
+use crate::updates::decoder::{Decode, Decoder};
+use crate::updates::encoder::{Encode, Encoder};
+use crate::Error;
+use lib0::any::Any;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Awareness states of all peers known to a local one, including its own.
+///
+/// # Example
+///
+/// ```
+/// use lib0::any::Any;
+/// use yrs::awareness::Awareness;
+///
+/// let mut local = Awareness::new(1);
+/// local.set_local_state(Any::from_json_str(r#"{"user":"alice"}"#).unwrap());
+///
+/// let mut remote = Awareness::new(2);
+/// let change = remote.apply_update(&local.encode_update(&[1])).unwrap();
+/// assert_eq!(change.added, vec![1]);
+/// assert_eq!(remote.states()[&1], *local.local_state().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Awareness {
+    client_id: u64,
+    states: HashMap<u64, Any>,
+    clocks: HashMap<u64, u32>,
+    /// Times at which clocks of corresponding clients have been changed for the last time.
+    last_updated: HashMap<u64, Instant>,
+}
+
+impl Awareness {
+    /// Creates a new awareness instance for a local peer identified by a given `client_id`,
+    /// which should be the same as the client id of a document it's used with.
+    pub fn new(client_id: u64) -> Self {
+        Awareness {
+            client_id,
+            states: HashMap::new(),
+            clocks: HashMap::new(),
+            last_updated: HashMap::new(),
+        }
+    }
+
+    /// Returns a client id of a local peer.
+    pub fn client_id(&self) -> u64 {
+        self.client_id
+    }
+
+    /// Returns states of all peers with a non-empty state, keyed by their client ids.
+    pub fn states(&self) -> &HashMap<u64, Any> {
+        &self.states
+    }
+
+    /// Returns a state of a local peer or `None` if it has not been set or it has been cleared.
+    pub fn local_state(&self) -> Option<&Any> {
+        self.states.get(&self.client_id)
+    }
+
+    /// Returns a clock of the most recent state of a given `client` or `None`, if no state of
+    /// that client has ever been observed.
+    pub fn clock(&self, client: u64) -> Option<u32> {
+        self.clocks.get(&client).copied()
+    }
+
+    /// Returns clocks of the most recent states of all clients observed so far. A copy of them can
+    /// be passed later to [Awareness::encode_update_since] to encode only the states changed in
+    /// the meantime.
+    pub fn clocks(&self) -> &HashMap<u64, u32> {
+        &self.clocks
+    }
+
+    /// Sets a new state of a local peer. Setting it to [Any::Null] is equivalent to calling
+    /// [Awareness::clean_local_state].
+    pub fn set_local_state(&mut self, state: Any) {
+        self.tick(Instant::now());
+        if state == Any::Null {
+            self.states.remove(&self.client_id);
+        } else {
+            self.states.insert(self.client_id, state);
+        }
+    }
+
+    /// Clears a state of a local peer, ie. to notify others that it went offline.
+    pub fn clean_local_state(&mut self) {
+        self.set_local_state(Any::Null)
+    }
+
+    /// Increments a clock of a local peer without changing its state. Once broadcasted, it lets
+    /// remote peers know that a local peer is still online, so that they don't remove its state
+    /// with [Awareness::remove_outdated]. It should be called in intervals shorter than a timeout
+    /// used by remote peers. Does nothing if a local state is not set.
+    pub fn renew_local_clock(&mut self) {
+        if self.local_state().is_some() {
+            self.tick(Instant::now());
+        }
+    }
+
+    /// Removes states of remote clients, which have not been updated (or renewed) for longer than
+    /// a given `timeout`. Returns ids of clients which states have been removed, in ascending
+    /// order. A state of a local peer is never removed.
+    pub fn remove_outdated(&mut self, timeout: Duration) -> Vec<u64> {
+        self.remove_outdated_at(Instant::now(), timeout)
+    }
+
+    fn remove_outdated_at(&mut self, now: Instant, timeout: Duration) -> Vec<u64> {
+        let mut removed: Vec<u64> = self
+            .states
+            .keys()
+            .copied()
+            .filter(|&client| client != self.client_id)
+            .filter(|client| match self.last_updated.get(client) {
+                Some(&last_updated) => now.saturating_duration_since(last_updated) >= timeout,
+                None => true,
+            })
+            .collect();
+        removed.sort_unstable();
+        for client in removed.iter() {
+            self.states.remove(client);
+        }
+        removed
+    }
+
+    /// Increments a clock of a local peer, marking it as updated at a given time.
+    fn tick(&mut self, now: Instant) {
+        *self.clocks.entry(self.client_id).or_default() += 1;
+        self.last_updated.insert(self.client_id, now);
+    }
+
+    /// Encodes states of given `clients` as a binary update, which can be applied by remote
+    /// peers using [Awareness::apply_update]. Clients without a known clock are skipped, while
+    /// clients which state was removed are encoded as `null`.
+    pub fn encode_update(&self, clients: &[u64]) -> Vec<u8> {
+        let entries = clients
+            .iter()
+            .filter_map(|client| {
+                let clock = *self.clocks.get(client)?;
+                let json = match self.states.get(client) {
+                    Some(state) => state.to_json_string(),
+                    None => "null".to_string(),
+                };
+                Some(AwarenessEntry {
+                    client: *client,
+                    clock,
+                    json,
+                })
+            })
+            .collect();
+        AwarenessUpdate { entries }.encode_v1()
+    }
+
+    /// Encodes states of all known clients. See: [Awareness::encode_update].
+    pub fn encode_full_update(&self) -> Vec<u8> {
+        let mut clients: Vec<_> = self.clocks.keys().copied().collect();
+        clients.sort_unstable();
+        self.encode_update(&clients)
+    }
+
+    /// Encodes states of clients, which have changed (or have been renewed) since given `clocks`
+    /// were observed, ie. ones returned by [Awareness::clocks] at the time of a previous
+    /// broadcast. This way local changes can be debounced: instead of broadcasting every change
+    /// separately, they can be sent in batches. Returns `None` if no state has changed.
+    pub fn encode_update_since(&self, clocks: &HashMap<u64, u32>) -> Option<Vec<u8>> {
+        let mut clients: Vec<_> = self
+            .clocks
+            .iter()
+            .filter(|(client, &clock)| !matches!(clocks.get(client), Some(&c) if c >= clock))
+            .map(|(&client, _)| client)
+            .collect();
+        if clients.is_empty() {
+            None
+        } else {
+            clients.sort_unstable();
+            Some(self.encode_update(&clients))
+        }
+    }
+
+    /// Applies an update produced by [Awareness::encode_update] or Yjs `encodeAwarenessUpdate`,
+    /// returning ids of clients which states have been changed by it. Entries older than
+    /// already known states are ignored.
+    ///
+    /// If an update tries to remove a state of a local peer, its state is kept and its clock is
+    /// incremented instead, so that it takes precedence once broadcasted again.
+    pub fn apply_update(&mut self, update: &[u8]) -> Result<AwarenessChange, Error> {
+        self.apply_update_at(update, Instant::now())
+    }
+
+    fn apply_update_at(&mut self, update: &[u8], now: Instant) -> Result<AwarenessChange, Error> {
+        let update = AwarenessUpdate::decode_v1(update)?;
+        let mut change = AwarenessChange::default();
+        for entry in update.entries {
+            let state = Any::from_json_str(&entry.json)
+                .map_err(|_| Error::InvalidData("invalid awareness state"))?;
+            let current_clock = self.clock(entry.client).unwrap_or(0);
+            let is_removal = state == Any::Null;
+            let known = self.states.contains_key(&entry.client);
+            if current_clock < entry.clock || (current_clock == entry.clock && is_removal && known)
+            {
+                let mut clock = entry.clock;
+                if is_removal {
+                    if entry.client == self.client_id && known {
+                        // remote peer removed our own state - override it
+                        clock += 1;
+                    } else {
+                        self.states.remove(&entry.client);
+                        if known {
+                            change.removed.push(entry.client);
+                        }
+                    }
+                } else {
+                    match self.states.insert(entry.client, state.clone()) {
+                        None => change.added.push(entry.client),
+                        Some(prev) if prev != state => change.updated.push(entry.client),
+                        Some(_) => change.renewed.push(entry.client),
+                    }
+                }
+                self.clocks.insert(entry.client, clock);
+                self.last_updated.insert(entry.client, now);
+            }
+        }
+        Ok(change)
+    }
+}
+
+/// Ids of clients which awareness states have been changed by [Awareness::apply_update].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwarenessChange {
+    /// Clients which states were observed for the first time.
+    pub added: Vec<u64>,
+    /// Clients which states have been changed.
+    pub updated: Vec<u64>,
+    /// Clients which states have been removed.
+    pub removed: Vec<u64>,
+    /// Clients which clocks have been renewed without changing their states, ie. by
+    /// [Awareness::renew_local_clock]. These are not considered changes.
+    pub renewed: Vec<u64>,
+}
+
+impl AwarenessChange {
+    /// Checks if no state has been changed. Renewed states are not taken into account.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    /// Returns ids of all clients which states have been added, updated or removed.
+    pub fn all(&self) -> Vec<u64> {
+        let mut all =
+            Vec::with_capacity(self.added.len() + self.updated.len() + self.removed.len());
+        all.extend_from_slice(&self.added);
+        all.extend_from_slice(&self.updated);
+        all.extend_from_slice(&self.removed);
+        all
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AwarenessEntry {
+    client: u64,
+    clock: u32,
+    json: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AwarenessUpdate {
+    entries: Vec<AwarenessEntry>,
+}
+
+impl Encode for AwarenessUpdate {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        encoder.write_uvar(self.entries.len());
+        for entry in self.entries.iter() {
+            encoder.write_uvar(entry.client);
+            encoder.write_uvar(entry.clock);
+            encoder.write_string(&entry.json);
+        }
+    }
+}
+
+impl Decode for AwarenessUpdate {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let len = decoder.read_collection_len()? as usize;
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            entries.push(AwarenessEntry {
+                client: decoder.read_uvar()?,
+                clock: decoder.read_uvar()?,
+                json: decoder.read_string()?.to_string(),
+            });
+        }
+        Ok(AwarenessUpdate { entries })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::awareness::{Awareness, AwarenessChange};
+    use lib0::any::Any;
+    use std::time::{Duration, Instant};
+
+    fn state(json: &str) -> Any {
+        Any::from_json_str(json).unwrap()
+    }
+
+    #[test]
+    fn awareness_update_compatibility() {
+        // awarenessProtocol.encodeAwarenessUpdate(awareness, [1]) after
+        // awareness.setLocalState({"x":1}) on a doc with client id 1
+        let mut a = Awareness::new(1);
+        a.set_local_state(state(r#"{"x":1}"#));
+        let update = a.encode_update(&[1]);
+        let mut expected = vec![1, 1, 1, 7];
+        expected.extend_from_slice(br#"{"x":1}"#);
+        assert_eq!(update, expected);
+    }
+
+    #[test]
+    fn awareness_apply_update() {
+        let mut a1 = Awareness::new(1);
+        let mut a2 = Awareness::new(2);
+        a1.set_local_state(state(r#"{"name":"alice"}"#));
+        a2.set_local_state(state(r#"{"name":"bob"}"#));
+
+        let change = a2.apply_update(&a1.encode_full_update()).unwrap();
+        assert_eq!(change.added, vec![1]);
+        assert_eq!(a2.states().len(), 2);
+
+        // outdated or repeated states are ignored
+        let stale = a1.encode_update(&[1]);
+        a1.set_local_state(state(r#"{"name":"alice","cursor":3}"#));
+        let change = a2.apply_update(&a1.encode_update(&[1])).unwrap();
+        assert_eq!(change.updated, vec![1]);
+        assert!(a2.apply_update(&stale).unwrap().is_empty());
+        assert_eq!(a2.states()[&1], *a1.local_state().unwrap());
+
+        // removal of a remote state
+        a1.clean_local_state();
+        let change = a2.apply_update(&a1.encode_update(&[1])).unwrap();
+        assert_eq!(change.removed, vec![1]);
+        assert!(!a2.states().contains_key(&1));
+
+        // removal of own state coming from a remote peer is overridden
+        let clock = a2.clock(2).unwrap();
+        let mut removal = vec![1, 2, clock as u8, 4];
+        removal.extend_from_slice(b"null");
+        let change = a2.apply_update(&removal).unwrap();
+        assert_eq!(change, AwarenessChange::default());
+        assert!(a2.local_state().is_some());
+        assert!(a2.clock(2).unwrap() > clock);
+    }
+
+    #[test]
+    fn awareness_encode_update_since() {
+        let mut a1 = Awareness::new(1);
+        let mut a3 = Awareness::new(3);
+        a1.set_local_state(state(r#"{"name":"alice"}"#));
+        a3.set_local_state(state(r#"{"name":"carol"}"#));
+        a1.apply_update(&a3.encode_full_update()).unwrap();
+
+        let sent = a1.clocks().clone();
+        assert_eq!(a1.encode_update_since(&sent), None);
+
+        // changes made in the meantime are sent together
+        a1.set_local_state(state(r#"{"name":"alice","cursor":1}"#));
+        a1.set_local_state(state(r#"{"name":"alice","cursor":2}"#));
+        let update = a1.encode_update_since(&sent).unwrap();
+        assert_eq!(update, a1.encode_update(&[1]));
+
+        // peers which have not seen any state get all of them
+        let update = a1.encode_update_since(&Default::default()).unwrap();
+        assert_eq!(update, a1.encode_full_update());
+    }
+
+    #[test]
+    fn awareness_remove_outdated() {
+        let timeout = Duration::from_secs(30);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut a1 = Awareness::new(1);
+        let mut a2 = Awareness::new(2);
+        let mut a3 = Awareness::new(3);
+        a1.set_local_state(state(r#"{"name":"alice"}"#));
+        a2.set_local_state(state(r#"{"name":"bob"}"#));
+        a3.set_local_state(state(r#"{"name":"carol"}"#));
+        a2.apply_update_at(&a1.encode_full_update(), at(0)).unwrap();
+        a2.apply_update_at(&a3.encode_full_update(), at(0)).unwrap();
+
+        // peer 1 keeps sending heartbeats, while peer 3 went silent
+        a1.renew_local_clock();
+        let change = a2.apply_update_at(&a1.encode_update(&[1]), at(20)).unwrap();
+        assert!(change.is_empty());
+        assert_eq!(change.renewed, vec![1]);
+        assert_eq!(a2.remove_outdated_at(at(20), timeout), Vec::<u64>::new());
+
+        a1.renew_local_clock();
+        a2.apply_update_at(&a1.encode_update(&[1]), at(40)).unwrap();
+        assert_eq!(a2.remove_outdated_at(at(40), timeout), vec![3]);
+        assert_eq!(a2.remove_outdated_at(at(60), timeout), Vec::<u64>::new());
+
+        // a local state is never removed
+        let mut clients: Vec<_> = a2.states().keys().copied().collect();
+        clients.sort_unstable();
+        assert_eq!(clients, vec![1, 2]);
+
+        // a peer which comes back online is added again
+        a3.renew_local_clock();
+        let change = a2.apply_update_at(&a3.encode_update(&[3]), at(60)).unwrap();
+        assert_eq!(change.added, vec![3]);
+    }
+}
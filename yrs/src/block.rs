@@ -1,7 +1,8 @@
 use crate::store::Store;
 use crate::types::{
-    Branch, BranchRef, TypePtr, Value, TYPE_REFS_ARRAY, TYPE_REFS_MAP, TYPE_REFS_TEXT,
-    TYPE_REFS_XML_ELEMENT, TYPE_REFS_XML_FRAGMENT, TYPE_REFS_XML_HOOK, TYPE_REFS_XML_TEXT,
+    kind_name, Branch, BranchRef, Path, TypePtr, Value, TYPE_REFS_ARRAY, TYPE_REFS_MAP,
+    TYPE_REFS_TEXT, TYPE_REFS_XML_ELEMENT, TYPE_REFS_XML_FRAGMENT, TYPE_REFS_XML_HOOK,
+    TYPE_REFS_XML_TEXT,
 };
 use crate::updates::decoder::Decoder;
 use crate::updates::encoder::Encoder;
@@ -9,9 +10,54 @@ use crate::*;
 use lib0::any::Any;
 use std::cell::Cell;
 use std::collections::HashSet;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::panic;
 
+/// Information about a single element stored within a document, resolved from its unique [ID]
+/// using [Transaction::get_item].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemInfo {
+    /// Identifier of an element, this information refers to.
+    pub id: ID,
+    /// Shared type, which contains current element.
+    pub parent: Value,
+    /// Key under which current element is stored, if it's an entry of a map component.
+    pub parent_sub: Option<String>,
+    /// Index of current element within a sequence component of its parent. It's `None` for map
+    /// entries and deleted elements.
+    pub index: Option<u32>,
+    /// Checks if current element has been deleted.
+    pub deleted: bool,
+    /// Kind of a content of a block containing current element (see: [ItemContent::kind]).
+    pub kind: &'static str,
+    /// Primitive value of current element. It's `None` for deleted elements and non-primitive
+    /// contents, like nested shared types.
+    pub value: Option<Any>,
+}
+
+/// Information about a single block inserted by a client, returned by
+/// [Transaction::client_blocks].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockInfo {
+    /// Range of clock values of elements stored within current block.
+    pub clock: std::ops::Range<u32>,
+    /// Name of a root type containing current block, either directly or through nested types.
+    /// It's `None` for garbage collected blocks and blocks which parent could not be resolved.
+    pub root: Option<String>,
+    /// Path from a [BlockInfo::root] type to a shared type directly containing current block.
+    pub path: Path,
+    /// Key of a map entry, current block is stored under.
+    pub parent_sub: Option<String>,
+    /// Kind of a content of current block (see: [ItemContent::kind]) or `"GC"` for garbage
+    /// collected blocks.
+    pub kind: &'static str,
+    /// Short, human-readable representation of a content of current block. It's `None` for
+    /// garbage collected blocks and removed content.
+    pub preview: Option<String>,
+    /// Checks if current block has been deleted.
+    pub deleted: bool,
+}
+
 /// Bit flag used to identify [Block::GC].
 pub const BLOCK_GC_REF_NUMBER: u8 = 0;
 
@@ -81,7 +127,7 @@ impl ID {
 
 /// A logical block pointer. It contains a unique block [ID], but also contains a helper metadata
 /// which allows to faster locate block it points to within a block store.
-#[derive(Debug, Clone, Copy, Hash)]
+#[derive(Debug, Clone, Copy)]
 pub struct BlockPtr {
     /// Unique identifier of a corresponding block.
     pub id: ID,
@@ -118,6 +164,13 @@ impl BlockPtr {
 
 impl Eq for BlockPtr {}
 
+impl Hash for BlockPtr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // pivot is not a part of block identity, see: PartialEq implementation
+        self.id.hash(state)
+    }
+}
+
 impl PartialEq for BlockPtr {
     fn eq(&self, other: &Self) -> bool {
         // BlockPtr.pivot may differ, but logically it doesn't affect block equality
@@ -214,7 +267,11 @@ impl Block {
             } else {
                 item.origin
             };
-            let info = item.info();
+            let info = if origin.is_some() {
+                item.info() | HAS_ORIGIN
+            } else {
+                item.info()
+            };
             let cant_copy_parent_info = info & (HAS_ORIGIN | HAS_RIGHT_ORIGIN) == 0;
             encoder.write_info(info);
             if let Some(origin_id) = origin {
@@ -495,6 +552,12 @@ impl Item {
         self.info.get() & ITEM_FLAG_COUNTABLE == ITEM_FLAG_COUNTABLE
     }
 
+    /// Returns a number of bytes of user data stored by current item: size of its content (see:
+    /// [ItemContent::content_size]) and a length of a map entry key it's stored under.
+    pub(crate) fn content_size(&self) -> usize {
+        self.content.content_size() + self.parent_sub.as_ref().map_or(0, String::len)
+    }
+
     pub(crate) fn mark_as_deleted(&self) {
         self.info.set(self.info.get() | ITEM_FLAG_DELETED);
     }
@@ -566,26 +629,27 @@ impl Item {
     /// If it returns true, it means that the block should be deleted after being added to a block store.
     pub fn integrate(&mut self, txn: &mut Transaction<'_>, pivot: u32, offset: u32) -> bool {
         if offset > 0 {
+            // part of this block has been integrated already: skip it and attach the remaining
+            // part to the end of the previously integrated one
             self.id.clock += offset;
-            let (left, _) = txn
-                .store
-                .blocks
-                .split_block(&BlockPtr::from(ID::new(self.id.client, self.id.clock - 1)));
-            if let Some(mut left) = left {
-                if let Some(origin) = txn.store.blocks.get_item(&left) {
-                    self.origin = Some(origin.last_id());
-                    left.id = origin.last_id();
-                    self.left = Some(left);
+            let origin_id = ID::new(self.id.client, self.id.clock - 1);
+            let ptr = BlockPtr::from(origin_id);
+            self.left = match txn.store.blocks.get_item(&ptr) {
+                Some(item) if item.last_id() == origin_id => Some(ptr),
+                Some(_) => {
+                    let ptr = BlockPtr::from(ID::new(origin_id.client, origin_id.clock + 1));
+                    txn.store.blocks.split_block(&ptr).0
                 }
-            } else {
-                self.left = None;
-                self.origin = None;
+                None => None,
+            };
+            self.origin = self.left.as_ref().map(|_| origin_id);
+            if let Some(right) = self.content.splice(offset as usize) {
+                self.content = right;
             }
-            self.content.splice(offset as usize);
         }
 
         let parent = match txn.store.get_type(&self.parent).cloned() {
-            None => txn.store.init_type_from_ptr(&self.parent, &self.content),
+            None => txn.store.init_type_from_ptr(&self.parent),
             parent => parent,
         };
 
@@ -644,9 +708,9 @@ impl Item {
                         break;
                     }
 
-                    items_before_origin.insert(ptr.id.clone());
-                    conflicting_items.insert(ptr.id.clone());
                     if let Some(Block::Item(item)) = txn.store.blocks.get_block(&ptr) {
+                        items_before_origin.insert(item.id);
+                        conflicting_items.insert(item.id);
                         if self.origin == item.origin {
                             // case 1
                             if ptr.id.client < self.id.client {
@@ -659,7 +723,13 @@ impl Item {
                                 break;
                             }
                         } else {
-                            if let Some(item_origin) = item.origin {
+                            // origin may point into the middle of a block: compare blocks
+                            // containing it instead
+                            let item_origin = item
+                                .origin
+                                .and_then(|id| txn.store.blocks.get_item(&BlockPtr::from(id)))
+                                .map(|origin| origin.id);
+                            if let Some(item_origin) = item_origin {
                                 if items_before_origin.contains(&item_origin) {
                                     if !conflicting_items.contains(&item_origin) {
                                         left = Some(ptr.clone());
@@ -716,23 +786,46 @@ impl Item {
                 }
             } else if let Some(parent_sub) = &self.parent_sub {
                 // set as current parent value if right === null and this is parentSub
-                parent_ref
+                let prev = parent_ref
                     .map
                     .insert(parent_sub.clone(), BlockPtr::new(self.id, pivot));
+                if let Some(prev) = prev.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+                    if !prev.is_deleted() {
+                        parent_ref.map_len.set(parent_ref.map_len.get() - 1);
+                    }
+                }
                 if let Some(left) = self.left {
                     // this is the current attribute value of parent. delete right
                     txn.delete(&left);
                 }
             }
 
-            if self.parent_sub.is_none() && self.is_countable() && !self.is_deleted() {
-                parent_ref.len += self.len();
+            if self.parent_sub.is_none() {
+                if self.is_countable() && !self.is_deleted() {
+                    parent_ref.len += self.len();
+                }
+                parent_ref.search_markers.clear();
             }
 
             self.integrate_content(txn, pivot, &mut *parent_ref);
+            if !self.is_deleted() {
+                let size = &txn.store.content_size;
+                size.set(size.get() + self.content_size());
+            }
+            txn.store.invalidate_content_size(&*parent_ref);
+            if self.parent_sub.is_some() && self.right.is_none() && !self.is_deleted() {
+                // this is the current value of parent map entry
+                parent_ref.map_len.set(parent_ref.map_len.get() + 1);
+            }
             txn.add_changed_type(&mut *parent_ref, self.parent_sub.as_ref());
-            let parent_deleted = self.is_deleted();
-            if parent_deleted || (self.parent_sub.is_some() && self.right.is_some()) {
+            let parent_deleted = parent_ref
+                .item
+                .and_then(|ptr| txn.store.blocks.get_item(&ptr))
+                .map_or(false, |item| item.is_deleted());
+            if self.is_deleted()
+                || parent_deleted
+                || (self.parent_sub.is_some() && self.right.is_some())
+            {
                 // delete if parent is deleted or if this is not the current attribute value of parent
                 true
             } else {
@@ -820,7 +913,7 @@ impl Item {
         let info = if self.origin.is_some() { HAS_ORIGIN } else { 0 } // is left null
             | if self.right_origin.is_some() { HAS_RIGHT_ORIGIN } else { 0 } // is right null
             | if self.parent_sub.is_some() { HAS_PARENT_SUB } else { 0 }
-            | (self.content.get_ref_number() & 0b1111);
+            | (self.content.get_ref_number() & 0b11111);
         info
     }
 
@@ -828,20 +921,19 @@ impl Item {
         match &mut self.content {
             ItemContent::Deleted(len) => {
                 txn.delete_set.insert(self.id, *len);
+                txn.store.deletions.set(txn.store.deletions.get() + 1);
                 self.mark_as_deleted();
             }
-            ItemContent::Doc(_, _) => {
-                //// this needs to be reflected in doc.destroy as well
-                //this.doc._item = item
-                //transaction.subdocsAdded.add(this.doc)
-                //if (this.doc.shouldLoad) {
-                //    transaction.subdocsLoaded.add(this.doc)
-                //}
-                todo!()
+            ItemContent::Doc(guid, options) => {
+                let subdoc = SubDoc::with_options(guid.clone(), options.clone());
+                if subdoc.should_load() {
+                    txn.subdocs_loaded.insert(guid.clone());
+                }
+                txn.subdocs_removed.remove(guid);
+                txn.subdocs_added.insert(guid.clone(), subdoc);
             }
             ItemContent::Format(_, _) => {
-                // @todo searchmarker are currently unsupported for rich text documents
-                // /** @type {AbstractType<any>} */ (item.parent)._searchMarker = null
+                // search markers of a parent have been already cleared
             }
             ItemContent::Type(inner) => {
                 // this.type._integrate(transaction.doc, item)
@@ -888,7 +980,128 @@ pub enum ItemContent {
     Type(BranchRef),
 }
 
+/// Returns an estimated number of bytes allocated on the heap by a given `any` value.
+fn any_heap_size(any: &Any) -> usize {
+    match any {
+        Any::String(s) => s.len(),
+        Any::Buffer(b) => b.len(),
+        Any::Array(v) => {
+            v.capacity() * std::mem::size_of::<Any>() + v.iter().map(any_heap_size).sum::<usize>()
+        }
+        Any::Map(m) => m
+            .iter()
+            .map(|(k, v)| k.capacity() + std::mem::size_of::<Any>() + any_heap_size(v))
+            .sum(),
+        _ => 0,
+    }
+}
+
 impl ItemContent {
+    /// Returns a human-readable name of a current content variant, ie. `"String"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ItemContent::Any(_) => "Any",
+            ItemContent::Binary(_) => "Binary",
+            ItemContent::Deleted(_) => "Deleted",
+            ItemContent::Doc(_, _) => "Doc",
+            ItemContent::JSON(_) => "JSON",
+            ItemContent::Embed(_) => "Embed",
+            ItemContent::Format(_, _) => "Format",
+            ItemContent::String(_) => "String",
+            ItemContent::Type(_) => "Type",
+        }
+    }
+
+    /// Returns a short, human-readable representation of current content. Longer content is
+    /// truncated to `max_chars` characters followed by an ellipsis. Returns `None` for
+    /// [ItemContent::Deleted].
+    pub(crate) fn preview(&self, max_chars: usize) -> Option<String> {
+        let preview = match self {
+            ItemContent::Any(values) => values
+                .iter()
+                .map(Any::to_json_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            ItemContent::Binary(buf) => format!("<{} bytes>", buf.len()),
+            ItemContent::Deleted(_) => return None,
+            ItemContent::Doc(guid, _) => format!("<doc {}>", guid),
+            ItemContent::JSON(values) => values.join(","),
+            ItemContent::Embed(s) | ItemContent::String(s) => s.clone(),
+            ItemContent::Format(key, value) => format!("{}={}", key, value),
+            ItemContent::Type(branch) => format!("<{}>", kind_name(branch.borrow().type_ref())),
+        };
+        if preview.chars().count() > max_chars {
+            let mut truncated: String = preview.chars().take(max_chars).collect();
+            truncated.push('…');
+            Some(truncated)
+        } else {
+            Some(preview)
+        }
+    }
+
+    /// Returns a primitive value of an element found at a given `offset` within current content.
+    /// Returns `None` for non-primitive contents (like nested shared types) or when `offset` is
+    /// outside of content's bounds.
+    pub fn get_any_at(&self, offset: usize) -> Option<Any> {
+        match self {
+            ItemContent::Any(v) => v.get(offset).cloned(),
+            ItemContent::JSON(v) => v.get(offset).map(|s| Any::String(s.clone())),
+            ItemContent::String(s) => s
+                .get(offset..)
+                .and_then(|s| s.chars().next())
+                .map(|c| Any::String(c.to_string())),
+            ItemContent::Binary(v) if offset == 0 => {
+                Some(Any::Buffer(v.clone().into_boxed_slice()))
+            }
+            ItemContent::Embed(v) if offset == 0 => Some(Any::String(v.clone())),
+            _ => None,
+        }
+    }
+
+    /// Returns an estimated number of bytes allocated on the heap by current content.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            ItemContent::Any(v) => {
+                v.capacity() * std::mem::size_of::<Any>()
+                    + v.iter().map(any_heap_size).sum::<usize>()
+            }
+            ItemContent::Binary(v) => v.capacity(),
+            ItemContent::Deleted(_) => 0,
+            ItemContent::Doc(guid, options) => guid.capacity() + any_heap_size(options),
+            ItemContent::JSON(v) => {
+                v.capacity() * std::mem::size_of::<String>()
+                    + v.iter().map(|s| s.capacity()).sum::<usize>()
+            }
+            ItemContent::Embed(s) => s.capacity(),
+            ItemContent::Format(key, value) => key.capacity() + value.capacity(),
+            ItemContent::String(s) => s.capacity(),
+            ItemContent::Type(_) => std::mem::size_of::<Branch>(),
+        }
+    }
+
+    /// Returns a number of bytes of user data stored by current content: UTF-8 length of strings,
+    /// length of binary buffers and lib0 v1 encoded size of other values. Content of nested
+    /// shared types is not included.
+    pub fn content_size(&self) -> usize {
+        fn any_size(any: &Any) -> usize {
+            let mut buf = Vec::new();
+            any.encode(&mut buf);
+            buf.len()
+        }
+
+        match self {
+            ItemContent::Any(v) => v.iter().map(any_size).sum(),
+            ItemContent::Binary(v) => v.len(),
+            ItemContent::Deleted(_) => 0,
+            ItemContent::Doc(guid, options) => guid.len() + any_size(options),
+            ItemContent::JSON(v) => v.iter().map(String::len).sum(),
+            ItemContent::Embed(s) => s.len(),
+            ItemContent::Format(key, value) => key.len() + value.len(),
+            ItemContent::String(s) => s.len(),
+            ItemContent::Type(_) => 0,
+        }
+    }
+
     /// Returns a reference number used to determine a content type.
     /// It's used during encoding/decoding of a containing block.
     pub fn get_ref_number(&self) -> u8 {
@@ -956,7 +1169,10 @@ impl ItemContent {
             ItemContent::Any(v) => v.iter().map(|a| Value::Any(a.clone())).collect(),
             ItemContent::Binary(v) => vec![Value::Any(Any::Buffer(v.clone().into_boxed_slice()))],
             ItemContent::Deleted(_) => Vec::default(),
-            ItemContent::Doc(_, v) => vec![Value::Any(v.clone())],
+            ItemContent::Doc(guid, options) => vec![Value::YDoc(SubDoc::with_options(
+                guid.clone(),
+                options.clone(),
+            ))],
             ItemContent::JSON(v) => v
                 .iter()
                 .map(|v| Value::Any(Any::String(v.clone())))
@@ -980,7 +1196,10 @@ impl ItemContent {
             ItemContent::Any(v) => v.last().map(|a| Value::Any(a.clone())),
             ItemContent::Binary(v) => Some(Value::Any(Any::Buffer(v.clone().into_boxed_slice()))),
             ItemContent::Deleted(_) => None,
-            ItemContent::Doc(_, v) => Some(Value::Any(v.clone())),
+            ItemContent::Doc(guid, options) => Some(Value::YDoc(SubDoc::with_options(
+                guid.clone(),
+                options.clone(),
+            ))),
             ItemContent::JSON(v) => v.last().map(|v| Value::Any(Any::String(v.clone()))),
             ItemContent::Embed(v) => Some(Value::Any(Any::String(v.clone()))),
             ItemContent::Format(_, _) => None,
@@ -1065,31 +1284,39 @@ impl ItemContent {
         }
     }
 
-    pub fn decode<D: Decoder>(decoder: &mut D, ref_num: u8, ptr: block::BlockPtr) -> Self {
-        match ref_num & 0b1111 {
-            BLOCK_ITEM_DELETED_REF_NUMBER => ItemContent::Deleted(decoder.read_len()),
+    pub fn decode<D: Decoder>(
+        decoder: &mut D,
+        ref_num: u8,
+        ptr: block::BlockPtr,
+    ) -> Result<Self, Error> {
+        // content ref occupies lower 5 bits of info byte, like in Yjs: masking out less would
+        // alias newer content types onto known ones and decode them as garbage
+        let content = match ref_num & 0b11111 {
+            BLOCK_ITEM_DELETED_REF_NUMBER => ItemContent::Deleted(decoder.read_len()?),
             BLOCK_ITEM_JSON_REF_NUMBER => {
-                let mut remaining = decoder.read_len() as i32;
-                let mut buf = Vec::with_capacity(remaining as usize);
-                while remaining >= 0 {
-                    buf.push(decoder.read_string().to_owned());
-                    remaining -= 1;
+                let len = decoder.read_collection_len()?;
+                let mut buf = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    buf.push(decoder.read_string()?.to_owned());
                 }
                 ItemContent::JSON(buf)
             }
-            BLOCK_ITEM_BINARY_REF_NUMBER => ItemContent::Binary(decoder.read_buf().to_owned()),
-            BLOCK_ITEM_STRING_REF_NUMBER => ItemContent::String(decoder.read_string().to_owned()),
-            BLOCK_ITEM_EMBED_REF_NUMBER => ItemContent::Embed(decoder.read_string().to_owned()),
+            BLOCK_ITEM_BINARY_REF_NUMBER => ItemContent::Binary(decoder.read_buf()?.to_owned()),
+            BLOCK_ITEM_STRING_REF_NUMBER => ItemContent::String(decoder.read_string()?.to_owned()),
+            BLOCK_ITEM_EMBED_REF_NUMBER => ItemContent::Embed(decoder.read_json()?.to_owned()),
             BLOCK_ITEM_FORMAT_REF_NUMBER => ItemContent::Format(
-                decoder.read_string().to_owned(),
-                decoder.read_string().to_owned(),
+                decoder.read_key()?.to_owned(),
+                decoder.read_json()?.to_owned(),
             ),
             BLOCK_ITEM_TYPE_REF_NUMBER => {
-                let type_ref = decoder.read_type_ref();
+                let type_ref = decoder.read_type_ref()?;
+                if type_ref > types::TYPE_REFS_UNDEFINED {
+                    return Err(Error::InvalidTypeRef(type_ref));
+                }
                 let name = if type_ref == types::TYPE_REFS_XML_ELEMENT
                     || type_ref == types::TYPE_REFS_XML_HOOK
                 {
-                    Some(decoder.read_key().to_owned())
+                    Some(decoder.read_key()?.to_owned())
                 } else {
                     None
                 };
@@ -1098,19 +1325,59 @@ impl ItemContent {
                 ItemContent::Type(BranchRef::new(inner))
             }
             BLOCK_ITEM_ANY_REF_NUMBER => {
-                let len = decoder.read_len() as usize;
+                let len = decoder.read_collection_len()? as usize;
                 let mut values = Vec::with_capacity(len);
                 let mut i = 0;
                 while i < len {
-                    values.push(decoder.read_any());
+                    values.push(decoder.read_any()?);
                     i += 1;
                 }
                 ItemContent::Any(values)
             }
             BLOCK_ITEM_DOC_REF_NUMBER => {
-                ItemContent::Doc(decoder.read_string().to_owned(), decoder.read_any())
+                ItemContent::Doc(decoder.read_string()?.to_owned(), decoder.read_any()?)
             }
-            info => panic!("ItemContent::decode unrecognized info flag: {}", info),
+            // like in Yjs, content types unknown to this version cannot be skipped, as there's no
+            // way to tell where their payload ends
+            tag => return Err(Error::UnsupportedContent(tag)),
+        };
+        Ok(content)
+    }
+
+    /// Compares `len` elements of current content starting at a given `offset` with the ones of
+    /// `other` content starting at `other_offset`, returning an index of the first element which
+    /// differs between them. Returns `None` if compared elements are equal or if that cannot be
+    /// told, ie. because any of the contents has been deleted.
+    pub(crate) fn find_mismatch(
+        &self,
+        offset: u32,
+        other: &ItemContent,
+        other_offset: u32,
+        len: u32,
+    ) -> Option<u32> {
+        fn mismatch<T: PartialEq>(
+            a: &[T],
+            offset: u32,
+            b: &[T],
+            b_offset: u32,
+            len: u32,
+        ) -> Option<u32> {
+            let a = a.iter().skip(offset as usize).take(len as usize);
+            let b = b.iter().skip(b_offset as usize).take(len as usize);
+            a.zip(b).position(|(x, y)| x != y).map(|i| i as u32)
+        }
+        match (self, other) {
+            (ItemContent::Deleted(_), _) | (_, ItemContent::Deleted(_)) => None,
+            (ItemContent::Type(_), ItemContent::Type(_)) => None,
+            (ItemContent::String(a), ItemContent::String(b)) => {
+                mismatch(a.as_bytes(), offset, b.as_bytes(), other_offset, len)
+            }
+            (ItemContent::Any(a), ItemContent::Any(b)) => mismatch(a, offset, b, other_offset, len),
+            (ItemContent::JSON(a), ItemContent::JSON(b)) => {
+                mismatch(a, offset, b, other_offset, len)
+            }
+            (a, b) if a == b => None,
+            _ => Some(0),
         }
     }
 
@@ -1299,6 +1566,43 @@ pub trait Prelim: Sized {
     fn integrate(self, txn: &mut Transaction, inner_ref: BranchRef);
 }
 
+/// Every value, which can be converted into [Any] can be used as a preliminary content. This
+/// includes strings, booleans, numbers, `Option`s, vectors and hash maps of such values, and -
+/// when `serde` feature is enabled - `serde_json::Value`. Integers up to 32 bits are stored as
+/// [Any::Number], while `i64` values are stored as [Any::BigInt].
+///
+/// # Example
+///
+/// ```
+/// use lib0::any::Any;
+/// use std::collections::HashMap;
+/// use yrs::Doc;
+///
+/// let doc = Doc::new();
+/// let mut txn = doc.transact();
+/// let array = txn.get_array("array");
+///
+/// array.push_back(&mut txn, 42);
+/// array.push_back(&mut txn, 42i64);
+/// array.push_back(&mut txn, "hello");
+/// array.push_back(&mut txn, vec![true, false]);
+/// array.push_back(&mut txn, None as Option<String>);
+///
+/// let mut map = HashMap::new();
+/// map.insert("key".to_owned(), 1.5);
+/// array.push_back(&mut txn, map);
+///
+/// let mut expected_map = HashMap::new();
+/// expected_map.insert("key".to_owned(), Any::Number(1.5));
+/// assert_eq!(array.to_json(&txn), Any::Array(vec![
+///     Any::Number(42.0),
+///     Any::BigInt(42),
+///     Any::String("hello".to_owned()),
+///     Any::Array(vec![Any::Bool(true), Any::Bool(false)]),
+///     Any::Null,
+///     Any::Map(expected_map),
+/// ]));
+/// ```
 impl<T> Prelim for T
 where
     T: Into<Any>,
@@ -1348,6 +1652,7 @@ impl std::fmt::Display for Block {
 mod test {
     use crate::block::BlockPtr;
     use crate::ID;
+    use std::collections::HashSet;
 
     #[test]
     fn block_ptr_pivot() {
@@ -1356,4 +1661,11 @@ mod test {
         ptr.fix_pivot(4);
         assert_eq!(ptr.pivot(), 4);
     }
+
+    #[test]
+    fn block_ptr_hash_ignores_pivot() {
+        let mut set = HashSet::new();
+        set.insert(BlockPtr::new(ID::new(1, 2), 3));
+        assert!(set.contains(&BlockPtr::new(ID::new(1, 2), 0)));
+    }
 }
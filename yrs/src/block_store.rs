@@ -1,9 +1,11 @@
 use crate::block::{Block, BlockPtr, Item, ID};
 use crate::types::TypePtr;
-use crate::updates::decoder::{Decode, Decoder};
+use crate::updates::decoder::{Decode, Decoder, DecoderV1};
 use crate::updates::encoder::{Encode, Encoder};
 use crate::utils::client_hasher::ClientHasher;
 use crate::*;
+use lib0::decoding::DecoderOptions;
+use std::cmp::Ordering;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
@@ -17,7 +19,12 @@ use std::vec::Vec;
 ///
 /// Another popular name for the concept represented by state vector is
 /// [Version Vector](https://en.wikipedia.org/wiki/Version_vector).
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+///
+/// State vectors are compared using a partial ordering: one state vector is greater than another
+/// if it has observed all of its updates and some more. Two state vectors, which have both
+/// observed updates unknown to the other, are concurrent and cannot be ordered. Clients with
+/// a zero clock value are treated as if they were absent.
+#[derive(Default, Debug, Clone)]
 pub struct StateVector(HashMap<u64, u32, BuildHasherDefault<ClientHasher>>);
 
 impl StateVector {
@@ -105,20 +112,84 @@ impl StateVector {
             *e = (*e).max(clock);
         }
     }
+
+    /// Checks if current state vector has observed all updates described by the `other` state
+    /// vector. If so, a peer described by the `other` state vector has nothing to send to us.
+    pub fn includes(&self, other: &StateVector) -> bool {
+        other
+            .0
+            .iter()
+            .all(|(client, &clock)| self.get(client) >= clock)
+    }
+
+    /// Compares current state vector with the `other` one. Returns `None` if they are concurrent,
+    /// meaning that each of them has observed some updates unknown to the other.
+    pub fn compare(&self, other: &StateVector) -> Option<Ordering> {
+        match (self.includes(other), other.includes(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+
+    /// Decodes an untrusted state vector encoded using lib0 ver. 1 encoding. A payload is
+    /// rejected if it declares lengths exceeding limits configured by `options`.
+    pub fn try_decode_v1(data: &[u8], options: &DecoderOptions) -> Result<Self, Error> {
+        Self::decode(&mut DecoderV1::with_options(data, *options))
+    }
+}
+
+impl PartialEq for StateVector {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+}
+
+impl Eq for StateVector {}
+
+impl PartialOrd for StateVector {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare(other)
+    }
+}
+
+impl std::iter::FromIterator<(u64, u32)> for StateVector {
+    /// Builds a state vector out of `(client_id, clock)` pairs. If the same client appears more
+    /// than once, a highest clock value is used.
+    fn from_iter<T: IntoIterator<Item = (u64, u32)>>(iter: T) -> Self {
+        let mut sv = StateVector::default();
+        for (client, clock) in iter {
+            sv.set_max(client, clock);
+        }
+        sv
+    }
+}
+
+impl From<HashMap<u64, u32>> for StateVector {
+    fn from(map: HashMap<u64, u32>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl From<StateVector> for HashMap<u64, u32> {
+    fn from(sv: StateVector) -> Self {
+        sv.0.into_iter().collect()
+    }
 }
 
 impl Decode for StateVector {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
-        let len = decoder.read_uvar::<u32>() as usize;
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let len = decoder.read_collection_len()? as usize;
         let mut sv = HashMap::with_capacity_and_hasher(len, BuildHasherDefault::default());
         let mut i = 0;
         while i < len {
-            let client = decoder.read_uvar();
-            let clock = decoder.read_uvar();
+            let client = decoder.read_uvar()?;
+            let clock = decoder.read_uvar()?;
             sv.insert(client, clock);
             i += 1;
         }
-        StateVector(sv)
+        Ok(StateVector(sv))
     }
 }
 
@@ -175,13 +246,6 @@ impl ClientBlockList {
         &self.list[0]
     }
 
-    /// Returns last block on the list - since we only initialize [ClientBlockList]
-    /// when we're sure, we're about to add new elements to it, it always should
-    /// stay non-empty.
-    pub(crate) fn last(&self) -> &Block {
-        &self.list[self.integrated_len - 1]
-    }
-
     /// Returns a mutable block reference, given a pointer identifier of that block.
     /// Returns `None` if no block with such reference could be found.
     pub(crate) fn find(&mut self, ptr: &BlockPtr) -> Option<&mut Block> {
@@ -205,8 +269,8 @@ impl ClientBlockList {
             //todo: does it even make sense to pivot the search?
             // If a good split misses, it might actually increase the time to find the correct item.
             // Currently, the only advantage is that search with pivoting might find the item on the first try.
-            let div = current_clock + block.len() - 1;
-            let mut mid = ((clock / div) * right as u32) as usize;
+            let div = (current_clock + block.len()).max(1) as u64;
+            let mut mid = ((clock as u64 * right as u64) / div).min(right as u64) as usize;
             while left <= right {
                 block = &self.list[mid];
                 current_clock = block.id().clock;
@@ -252,6 +316,16 @@ impl ClientBlockList {
         self.list.len()
     }
 
+    /// Shrinks the capacity of current block list as much as possible.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.list.shrink_to_fit()
+    }
+
+    /// Returns a number of blocks, current list has allocated space for.
+    pub fn capacity(&self) -> usize {
+        self.list.capacity()
+    }
+
     /// Returns a number of blocks successfully integrated within this list.
     pub fn integrated_len(&self) -> usize {
         self.integrated_len
@@ -346,13 +420,6 @@ pub(crate) struct BlockStore {
 pub(crate) type Iter<'a> = std::collections::hash_map::Iter<'a, u64, ClientBlockList>;
 
 impl BlockStore {
-    /// Creates a new block store instance from a given collection.
-    pub(crate) fn from(
-        clients: HashMap<u64, ClientBlockList, BuildHasherDefault<ClientHasher>>,
-    ) -> Self {
-        Self { clients }
-    }
-
     /// Creates a new empty block store instance.
     pub fn new() -> Self {
         Self {
@@ -366,11 +433,6 @@ impl BlockStore {
         self.clients.is_empty()
     }
 
-    /// Checks if the were any blocks integrated from a given `client`.
-    pub fn contains_client(&self, client: &u64) -> bool {
-        self.clients.contains_key(client)
-    }
-
     /// Returns an immutable reference to a block list for a particular `client`. Returns `None` if
     /// no block list existed for provided `client` in current block store.
     pub fn get(&self, client: &u64) -> Option<&ClientBlockList> {
@@ -400,7 +462,8 @@ impl BlockStore {
     /// could be found.
     pub(crate) fn get_item_mut(&mut self, ptr: &block::BlockPtr) -> Option<&mut block::Item> {
         let blocks = self.clients.get_mut(&ptr.id.client)?;
-        let block = blocks.list.get_mut(ptr.pivot())?;
+        // ptr.pivot may be outdated after blocks have been split - find() falls back to slow path
+        let block = blocks.find(ptr)?;
         block.as_item_mut()
     }
 
@@ -494,16 +557,17 @@ impl BlockStore {
                         let right_split_id = right_split.id.clone();
                         let right_ptr = right_split.right.clone();
                         if let Some(right_ptr) = right_ptr {
-                            blocks = if right_ptr.id.client == ptr.id.client {
+                            let right_blocks = if right_ptr.id.client == ptr.id.client {
                                 blocks
                             } else {
                                 self.clients.get_mut(&right_ptr.id.client).unwrap()
                             };
-                            let right = blocks.find(&right_ptr).unwrap();
+                            let right = right_blocks.find(&right_ptr).unwrap();
                             if let Some(right_item) = right.as_item_mut() {
                                 right_item.left =
                                     Some(BlockPtr::new(right_split.id.clone(), index as u32));
                             }
+                            blocks = self.clients.get_mut(&ptr.id.client).unwrap();
                         }
                         blocks.insert(index, Block::Item(right_split));
                         Some(BlockPtr::new(right_split_id, index as u32))
@@ -546,3 +610,121 @@ impl std::fmt::Display for BlockStore {
         writeln!(f, "}}")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::exchange_updates;
+    use crate::updates::decoder::Decode;
+    use crate::updates::encoder::Encode;
+    use crate::{Doc, StateVector};
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
+
+    fn sv(entries: &[(u64, u32)]) -> StateVector {
+        let mut sv = StateVector::default();
+        for &(client, clock) in entries {
+            sv.set_max(client, clock);
+        }
+        sv
+    }
+
+    #[test]
+    fn state_vector_equal() {
+        let a = sv(&[(1, 2), (2, 3)]);
+        let b = sv(&[(2, 3), (1, 2), (3, 0)]);
+        assert_eq!(a, b);
+        assert_eq!(a.compare(&b), Some(Ordering::Equal));
+        assert!(a.includes(&b) && b.includes(&a));
+        assert_eq!(StateVector::default(), sv(&[(1, 0)]));
+    }
+
+    #[test]
+    fn state_vector_ordered() {
+        let a = sv(&[(1, 2), (2, 3)]);
+        let b = sv(&[(1, 2), (2, 4)]);
+        let c = sv(&[(1, 2), (2, 3), (3, 1)]);
+        assert!(a < b && b > a);
+        assert!(a < c && a <= c && c >= a);
+        assert!(b.includes(&a));
+        assert!(!a.includes(&b));
+        assert_eq!(StateVector::default().compare(&a), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn state_vector_concurrent() {
+        // disjoint client sets
+        let a = sv(&[(1, 2)]);
+        let b = sv(&[(2, 3)]);
+        assert_eq!(a.compare(&b), None);
+        assert!(!(a < b) && !(a > b) && a != b);
+
+        // same clients, each ahead on a different one
+        let c = sv(&[(1, 3), (2, 1)]);
+        let d = sv(&[(1, 1), (2, 3)]);
+        assert_eq!(c.partial_cmp(&d), None);
+        assert!(!c.includes(&d) && !d.includes(&c));
+
+        let mut merged = c.clone();
+        merged.merge(d.clone());
+        assert_eq!(merged, sv(&[(1, 3), (2, 3)]));
+        assert!(merged > c && merged > d);
+
+        let mut merged = a.clone();
+        merged.merge(b.clone());
+        assert_eq!(merged, sv(&[(1, 2), (2, 3)]));
+        assert_eq!(merged.get(&1), 2);
+        assert_eq!(merged.get(&3), 0);
+        let mut entries: Vec<_> = merged.iter().map(|(&c, &k)| (c, k)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![(1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn state_vector_map_conversion() {
+        let mut map = HashMap::new();
+        map.insert(1, 2);
+        map.insert(2, 5);
+        let sv: StateVector = map.clone().into();
+        let decoded = StateVector::decode_v1(&sv.encode_v1()).unwrap();
+        assert_eq!(HashMap::from(decoded), map);
+
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, "hello");
+        }
+        let text = d2.transact().get_text("text");
+        text.insert(&mut d2.transact(), 0, "world");
+        let remote: HashMap<u64, u32> = d1.get_state_vector(&d1.transact()).into();
+        let binary = d1.get_state_vector(&d1.transact()).encode_v1();
+
+        let txn = d2.transact();
+        let from_map = d2.encode_delta_as_update_v1(&txn, &remote.into());
+        let from_binary =
+            d2.encode_delta_as_update_v1(&txn, &StateVector::decode_v1(&binary).unwrap());
+        assert_eq!(from_map, from_binary);
+    }
+
+    #[test]
+    fn split_block_followed_by_other_client() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "abc");
+        exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text").insert(&mut txn, 3, "x");
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        // "abc" is split, while its right neighbour belongs to client 2
+        let mut txn = d1.transact();
+        text.insert(&mut txn, 1, "y");
+        assert_eq!(text.to_string(&txn), "aybcx");
+        assert_eq!(txn.store.blocks.get(&1).unwrap().len(), 3);
+        assert_eq!(txn.store.blocks.get(&2).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,346 @@
+use crate::block::ItemContent;
+use crate::types::{kind_name, BranchRef, Path, PathSegment, TypeRefs, TYPE_REFS_UNDEFINED};
+use crate::Transaction;
+use lib0::any::Any;
+use std::collections::BTreeSet;
+use std::fmt::Formatter;
+
+/// A single difference found when comparing logical content of two documents with
+/// [Doc::diff_report](crate::Doc::diff_report).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Name of a root type, in which a difference has been found.
+    pub root: String,
+    /// Path to a differing element, starting from a root type: indexes of sequence elements or
+    /// keys of map entries. Empty if a root type itself differs (eg. by its kind).
+    pub path: Path,
+    /// Value found in the first document or `None` if it has no corresponding element. Nested
+    /// shared types are represented by their JSON equivalent.
+    pub left: Option<Any>,
+    /// Value found in the second document or `None` if it has no corresponding element. Nested
+    /// shared types are represented by their JSON equivalent.
+    pub right: Option<Any>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.root)?;
+        for segment in self.path.iter() {
+            match segment {
+                PathSegment::Key(key) => write!(f, ".{}", key)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        let left = self.left.as_ref().map(Any::to_json_string);
+        let right = self.right.as_ref().map(Any::to_json_string);
+        write!(
+            f,
+            ": {} != {}",
+            left.as_deref().unwrap_or("<none>"),
+            right.as_deref().unwrap_or("<none>")
+        )
+    }
+}
+
+/// Compares logical content of all root types of two documents. Root types missing in one of
+/// the documents are treated as empty.
+pub(crate) fn diff_content(left: &Transaction, right: &Transaction) -> Vec<Mismatch> {
+    let names: BTreeSet<_> = left
+        .store
+        .types
+        .keys()
+        .chain(right.store.types.keys())
+        .cloned()
+        .collect();
+    let mut diff = ContentDiff {
+        left,
+        right,
+        mismatches: Vec::new(),
+    };
+    for name in names {
+        let l = left.store.types.get(&name);
+        let r = right.store.types.get(&name);
+        diff.compare_branches(name.as_str(), &mut Path::new(), l, r);
+    }
+    diff.mismatches
+}
+
+/// Element of a sequence or map component of a shared type.
+#[derive(Clone)]
+enum Element {
+    Value(Any),
+    Branch(BranchRef),
+}
+
+struct ContentDiff<'a, 'l, 'r> {
+    left: &'a Transaction<'l>,
+    right: &'a Transaction<'r>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<'a, 'l, 'r> ContentDiff<'a, 'l, 'r> {
+    fn compare_branches(
+        &mut self,
+        root: &str,
+        path: &mut Path,
+        left: Option<&BranchRef>,
+        right: Option<&BranchRef>,
+    ) {
+        let (l_kind, l_name) = left.map(branch_kind).unwrap_or((TYPE_REFS_UNDEFINED, None));
+        let (r_kind, r_name) = right
+            .map(branch_kind)
+            .unwrap_or((TYPE_REFS_UNDEFINED, None));
+        // kinds of root types are not known until they are accessed, even if they have content
+        if l_kind != r_kind && l_kind != TYPE_REFS_UNDEFINED && r_kind != TYPE_REFS_UNDEFINED {
+            self.report(
+                root,
+                path,
+                Some(Any::String(kind_name(l_kind).to_string())),
+                Some(Any::String(kind_name(r_kind).to_string())),
+            );
+            return;
+        }
+        if l_name != r_name {
+            self.report(root, path, l_name.map(Any::String), r_name.map(Any::String));
+            return;
+        }
+
+        let l_seq = left.map(|b| sequence(self.left, b)).unwrap_or_default();
+        let r_seq = right.map(|b| sequence(self.right, b)).unwrap_or_default();
+        let mut l_iter = l_seq.into_iter();
+        let mut r_iter = r_seq.into_iter();
+        let mut index = 0;
+        loop {
+            let (l, r) = (l_iter.next(), r_iter.next());
+            if l.is_none() && r.is_none() {
+                break;
+            }
+            path.push(PathSegment::Index(index));
+            self.compare_elements(root, path, l, r);
+            path.pop();
+            index += 1;
+        }
+
+        let l_map = left.map(|b| entries(self.left, b)).unwrap_or_default();
+        let r_map = right.map(|b| entries(self.right, b)).unwrap_or_default();
+        let keys: BTreeSet<_> = l_map.iter().chain(r_map.iter()).map(|(k, _)| k).collect();
+        for key in keys {
+            let l = l_map.iter().find(|(k, _)| k == key).map(|(_, e)| e);
+            let r = r_map.iter().find(|(k, _)| k == key).map(|(_, e)| e);
+            path.push(PathSegment::Key(key.clone()));
+            self.compare_elements(root, path, l.cloned(), r.cloned());
+            path.pop();
+        }
+    }
+
+    fn compare_elements(
+        &mut self,
+        root: &str,
+        path: &mut Path,
+        left: Option<Element>,
+        right: Option<Element>,
+    ) {
+        match (left, right) {
+            (Some(Element::Branch(l)), Some(Element::Branch(r))) => {
+                self.compare_branches(root, path, Some(&l), Some(&r))
+            }
+            (Some(Element::Value(l)), Some(Element::Value(r))) if l == r => {}
+            (l, r) => {
+                let l = l.map(|e| e.to_json(self.left));
+                let r = r.map(|e| e.to_json(self.right));
+                self.report(root, path, l, r);
+            }
+        }
+    }
+
+    fn report(&mut self, root: &str, path: &Path, left: Option<Any>, right: Option<Any>) {
+        self.mismatches.push(Mismatch {
+            root: root.to_string(),
+            path: path.clone(),
+            left,
+            right,
+        });
+    }
+}
+
+impl Element {
+    fn to_json(self, txn: &Transaction) -> Any {
+        match self {
+            Element::Value(value) => value,
+            Element::Branch(branch) => {
+                if branch.borrow().type_ref() == TYPE_REFS_UNDEFINED {
+                    Any::Null
+                } else {
+                    branch.into_value(txn).to_json(txn)
+                }
+            }
+        }
+    }
+}
+
+fn branch_kind(branch: &BranchRef) -> (TypeRefs, Option<String>) {
+    let inner = branch.borrow();
+    (inner.type_ref(), inner.name.clone())
+}
+
+/// Returns all visible elements of a sequence component of a given `branch`.
+fn sequence(txn: &Transaction, branch: &BranchRef) -> Vec<Element> {
+    let mut result = Vec::new();
+    let mut current = branch.borrow().start;
+    while let Some(item) = current.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+        if !item.is_deleted() && item.is_countable() {
+            match &item.content {
+                ItemContent::Type(inner) => result.push(Element::Branch(inner.clone())),
+                content => result.extend(
+                    content
+                        .get_content(txn)
+                        .into_iter()
+                        .map(|value| Element::Value(value.to_json(txn))),
+                ),
+            }
+        }
+        current = item.right;
+    }
+    result
+}
+
+/// Returns all visible entries of a map component of a given `branch`.
+fn entries(txn: &Transaction, branch: &BranchRef) -> Vec<(String, Element)> {
+    let mut result = Vec::new();
+    for (key, ptr) in branch.borrow().map.iter() {
+        if let Some(item) = txn.store.blocks.get_item(ptr) {
+            if item.is_deleted() {
+                continue;
+            }
+            let element = match &item.content {
+                ItemContent::Type(inner) => Element::Branch(inner.clone()),
+                content => match content.get_content_last(txn) {
+                    Some(value) => Element::Value(value.to_json(txn)),
+                    None => continue,
+                },
+            };
+            result.push((key.clone(), element));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compare::Mismatch;
+    use crate::types::PathSegment;
+    use crate::{Doc, PrelimMap};
+    use lib0::any::Any;
+    use std::collections::HashMap;
+
+    #[test]
+    fn equal_content_different_history() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt = t1.get_text("text");
+        txt.insert(&mut t1, 0, "hello");
+        txt.insert(&mut t1, 5, " world");
+        let map = t1.get_map("map");
+        map.insert(&mut t1, "a".to_string(), 1);
+        map.insert(&mut t1, "b".to_string(), "x");
+        map.remove(&mut t1, "b");
+
+        // same content built in a different order by a different client
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let txt = t2.get_text("text");
+        txt.insert(&mut t2, 0, "world");
+        txt.insert(&mut t2, 0, "hello ");
+        let map = t2.get_map("map");
+        map.insert(&mut t2, "a".to_string(), 2);
+        map.insert(&mut t2, "a".to_string(), 1);
+        t2.get_array("empty");
+
+        assert!(d1.eq_content(&t1, &d2, &t2));
+        assert_ne!(
+            d1.encode_state_as_update_v1(&t1),
+            d2.encode_state_as_update_v1(&t2)
+        );
+    }
+
+    #[test]
+    fn nested_mismatch() {
+        let build = |client_id, value: &str| {
+            let doc = Doc::with_client_id(client_id);
+            {
+                let mut txn = doc.transact();
+                let root = txn.get_map("root");
+                let mut inner = HashMap::new();
+                inner.insert("name".to_string(), Any::String(value.to_string()));
+                inner.insert("size".to_string(), Any::Number(1.0));
+                let mut outer = HashMap::new();
+                outer.insert("inner".to_string(), PrelimMap::from(inner));
+                root.insert(&mut txn, "outer".to_string(), PrelimMap::from(outer));
+                txn.get_text("text").push(&mut txn, "same");
+            }
+            doc
+        };
+        let d1 = build(1, "hello");
+        let d2 = build(2, "hellp");
+        let t1 = d1.transact();
+        let t2 = d2.transact();
+
+        assert!(!d1.eq_content(&t1, &d2, &t2));
+        let report = d1.diff_report(&t1, &d2, &t2);
+        assert_eq!(
+            report,
+            vec![Mismatch {
+                root: "root".to_string(),
+                path: vec![
+                    PathSegment::Key("outer".to_string()),
+                    PathSegment::Key("inner".to_string()),
+                    PathSegment::Key("name".to_string()),
+                ],
+                left: Some(Any::String("hello".to_string())),
+                right: Some(Any::String("hellp".to_string())),
+            }]
+        );
+        assert_eq!(
+            report[0].to_string(),
+            r#"root.outer.inner.name: "hello" != "hellp""#
+        );
+    }
+
+    #[test]
+    fn text_and_kind_mismatch() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        t1.get_text("text").push(&mut t1, "abcd");
+        t1.get_array("kind");
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        t2.get_text("text").push(&mut t2, "abXde");
+        t2.get_map("kind");
+
+        let report = d1.diff_report(&t1, &d2, &t2);
+        assert_eq!(
+            report,
+            vec![
+                Mismatch {
+                    root: "kind".to_string(),
+                    path: vec![],
+                    left: Some(Any::String("YArray".to_string())),
+                    right: Some(Any::String("YMap".to_string())),
+                },
+                Mismatch {
+                    root: "text".to_string(),
+                    path: vec![PathSegment::Index(2)],
+                    left: Some(Any::String("c".to_string())),
+                    right: Some(Any::String("X".to_string())),
+                },
+                Mismatch {
+                    root: "text".to_string(),
+                    path: vec![PathSegment::Index(4)],
+                    left: None,
+                    right: Some(Any::String("e".to_string())),
+                },
+            ]
+        );
+    }
+}
@@ -5,9 +5,10 @@ use crate::types::{Branch, BranchRef, TypePtr, TYPE_REFS_XML_ELEMENT, TYPE_REFS_
 use crate::update::Update;
 use crate::updates::decoder::Decode;
 use crate::updates::encoder::Encode;
-use crate::{Doc, StateVector, ID};
+use crate::{Diff, Doc, Error, StateVector, Value, ID};
 use lib0::any::Any;
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 #[test]
@@ -107,11 +108,96 @@ fn text_insert_delete() {
     });
     let mut txn = doc.transact();
     let txt = txn.get_text("type");
-    doc.apply_update_v1(&mut txn, update);
+    doc.apply_update_v1(&mut txn, update).unwrap();
     assert_eq!(txt.to_string(&txn), "abhi".to_string());
     assert!(visited.get());
 }
 
+#[test]
+fn text_insert_with_attributes() {
+    /* Hand-encoded following Yjs v1 update format (`Item.write`, `ContentFormat.write`) for:
+        ```js
+           const doc = new Y.Doc()
+           doc.clientID = 1
+           const ytext = doc.getText('text')
+           ytext.insert(0, 'abc', { bold: true })
+           const update = Y.encodeStateAsUpdate(doc)
+           ytext.toDelta() // => [{ insert: 'abc', attributes: { bold: true } }]
+        ```
+        Formatting attribute values are encoded with `JSON.stringify`, while inserted text is
+        followed by a format item with `null` value, which ends formatted range.
+    */
+    let payload = &[
+        1, 3, 1, 0, 6, 1, 4, 116, 101, 120, 116, 4, 98, 111, 108, 100, 4, 116, 114, 117, 101, 132,
+        1, 0, 3, 97, 98, 99, 134, 1, 3, 4, 98, 111, 108, 100, 4, 110, 117, 108, 108, 0,
+    ];
+    let expected = &[
+        &Block::Item(Item::new(
+            ID::new(1, 0),
+            None,
+            None,
+            None,
+            None,
+            TypePtr::Named(Rc::new("text".to_string())),
+            None,
+            ItemContent::Format("bold".to_string(), "true".to_string()),
+        )),
+        &Block::Item(Item::new(
+            ID::new(1, 1),
+            None,
+            Some(ID::new(1, 0)),
+            None,
+            None,
+            TypePtr::Unknown,
+            None,
+            ItemContent::String("abc".to_string()),
+        )),
+        &Block::Item(Item::new(
+            ID::new(1, 4),
+            None,
+            Some(ID::new(1, 3)),
+            None,
+            None,
+            TypePtr::Unknown,
+            None,
+            ItemContent::Format("bold".to_string(), "null".to_string()),
+        )),
+    ];
+    roundtrip(payload, expected);
+
+    let mut bold = HashMap::new();
+    bold.insert("bold".to_string(), Any::Bool(true));
+    let doc = Doc::new();
+    let mut txn = doc.transact();
+    let text = txn.get_text("text");
+    doc.apply_update_v1(&mut txn, payload).unwrap();
+    assert_eq!(
+        text.diff(&txn),
+        vec![Diff::new(
+            Value::Any(Any::String("abc".into())),
+            Some(Box::new(bold.clone()))
+        )]
+    );
+
+    // the same change made by yrs is encoded the same way
+    let doc = Doc::with_client_id(1);
+    let mut txn = doc.transact();
+    let text = txn.get_text("text");
+    text.insert_with_attributes(&mut txn, 0, "abc", bold);
+    assert_eq!(doc.encode_state_as_update_v1(&txn).as_slice(), payload);
+
+    // Yjs fails to parse malformed JSON of a formatting attribute
+    let mut malformed = payload.to_vec();
+    malformed.splice(17..21, b"tru".iter().cloned());
+    malformed[16] = 3;
+    let doc = Doc::new();
+    let mut txn = doc.transact();
+    assert_eq!(
+        doc.apply_update_v1(&mut txn, &malformed),
+        Err(Error::InvalidData("malformed JSON value"))
+    );
+}
+
 #[test]
 fn map_set() {
     /* Generated via:
@@ -155,6 +241,35 @@ fn map_set() {
     roundtrip(payload, expected);
 }
 
+#[test]
+fn map_to_json_string() {
+    /* Generated via:
+        ```js
+           const doc = new Y.Doc()
+           const x = doc.getMap('test')
+           x.set('k1', 'v1')
+           x.set('k2', 'v2')
+           const update = Y.encodeStateAsUpdate(doc)
+           console.log(update);
+           console.log(JSON.stringify(x.toJSON()));
+        ```
+    */
+    let payload = &[
+        1, 2, 183, 229, 212, 163, 3, 0, 40, 1, 4, 116, 101, 115, 116, 2, 107, 49, 1, 119, 2, 118,
+        49, 40, 1, 4, 116, 101, 115, 116, 2, 107, 50, 1, 119, 2, 118, 50, 0,
+    ];
+    let expected = r#"{"k1":"v1","k2":"v2"}"#;
+
+    let doc = Doc::new();
+    let mut txn = doc.transact();
+    let map = txn.get_map("test");
+    doc.apply_update_v1(&mut txn, payload).unwrap();
+
+    let json = map.to_json(&txn);
+    assert_eq!(json.to_json_string(), expected);
+    assert_eq!(Any::from_json_str(expected), Ok(json));
+}
+
 #[test]
 fn array_insert() {
     /* Generated via:
@@ -262,7 +377,7 @@ fn state_vector() {
     expected.inc_by(14182974, 2);
     expected.inc_by(93760946, 3);
 
-    let sv = StateVector::decode_v1(payload);
+    let sv = StateVector::decode_v1(payload).unwrap();
     assert_eq!(sv, expected);
 
     let serialized = sv.encode_v1();
@@ -273,7 +388,7 @@ fn state_vector() {
 /// of `expected` blocks, then serialize them back and check
 /// if produced binary is equivalent to `payload`.
 fn roundtrip(payload: &[u8], expected: &[&Block]) {
-    let u = Update::decode_v1(payload);
+    let u = Update::decode_v1(payload).unwrap();
     let blocks: Vec<&Block> = u.blocks().collect();
     assert_eq!(blocks.as_slice(), expected);
 
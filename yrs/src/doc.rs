@@ -1,14 +1,28 @@
+use crate::block::{Block, Item, ItemContent, Prelim, BLOCK_SKIP_REF_NUMBER};
 use crate::block_store::StateVector;
-use crate::event::{Subscription, UpdateEvent};
+use crate::compare::Mismatch;
+use crate::event::{
+    AfterTransactionEvent, BeforeTransactionEvent, CommitEvent, DestroyEvent, EventHandler,
+    RootTypesEvent, SubdocsEvent, Subscription, UpdateEvent,
+};
 use crate::id_set::DeleteSet;
-use crate::store::Store;
+use crate::integrity::IntegrityViolation;
+use crate::persistence::DocStore;
+use crate::store::{CompactionReport, Store, StoreStats};
+use crate::timestamps::unix_millis;
 use crate::transaction::Transaction;
+use crate::types::{kind_type_ref, root_node_name, BranchRef, TypePtr, TYPE_REFS_ARRAY};
 use crate::update::Update;
 use crate::updates::decoder::{Decode, DecoderV1};
-use crate::updates::encoder::{Encode, Encoder, EncoderV1};
+use crate::updates::encoder::{Encode, EncoderV1};
 use crate::*;
+use lib0::any::Any;
+use lib0::decoding::DecoderOptions;
 use rand::Rng;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
 
 /// A Yrs document type. Documents are most important units of collaborative resources management.
 /// All shared collections live within a scope of their corresponding documents. All updates are
@@ -38,27 +52,171 @@ use std::cell::RefCell;
 ///
 /// // both update and state vector are serializable, we can pass the over the wire
 /// // now apply update to a remote document
-/// remote_doc.apply_update_v1(&mut remote_txn, update.as_slice());
+/// remote_doc.apply_update_v1(&mut remote_txn, update.as_slice()).unwrap();
 /// ```
 pub struct Doc {
     /// A unique client identifier, that's also a unique identifier of current document replica.
+    /// This is a client identifier a document has been created with - if it has been replaced
+    /// since then (see: [Options::auto_new_client_id_on_conflict]), a current one is returned by
+    /// [Transaction::client_id] and used by [Doc::options].
     pub client_id: u64,
+    /// A globally unique identifier of a current document, shared by all of its replicas. It's
+    /// used to identify a document when it's embedded as a subdocument of another one.
+    pub guid: String,
+    /// An identifier of a collection, a current document belongs to. It's not used by the document
+    /// itself, but it can be used by providers and persistence layers to group documents.
+    pub collection_id: Option<String>,
+    /// Custom metadata attached to a current document. It's passed along with a document guid,
+    /// when a document is embedded as a subdocument.
+    pub meta: Option<Any>,
+    /// If true, content of a current document is requested to be loaded as soon as it's embedded
+    /// as a subdocument into another document.
+    pub auto_load: bool,
+    /// If true, deleted blocks are never garbage collected, so their content stays available
+    /// after deletion.
+    pub skip_gc: bool,
+    /// Maximum number of bytes of content a current document can hold, as measured by
+    /// [Doc::content_size]. Updates and local changes which could make a document exceed it are
+    /// rejected.
+    pub max_content_size: Option<usize>,
+    /// If true, a client id of a current document is replaced with a new one, once another
+    /// replica using the same client id is detected.
+    pub auto_new_client_id_on_conflict: bool,
+    /// If true, commit times of local changes are stored within a current document.
+    pub record_timestamps: bool,
+    /// Names and kinds of root types declared up front for a current document.
+    pub schema: Option<Vec<(String, &'static str)>>,
     store: RefCell<Store>,
+    current_client_id: Rc<Cell<u64>>,
 }
 
 impl Doc {
-    /// Creates a new document with a randomized client identifier.
+    /// Creates a new document with a randomized client identifier and guid.
     pub fn new() -> Self {
-        let client_id: u64 = rand::thread_rng().gen();
-        Self::with_client_id(client_id)
+        Self::with_options(Options::default())
     }
 
-    /// Creates a new document with a specified `client_id`. It's up to a caller to guarantee that
-    /// this identifier is unique across all communicating replicas of that document.
+    /// Creates a new document with a specified `client_id` and a randomized guid. It's up to
+    /// a caller to guarantee that this identifier is unique across all communicating replicas of
+    /// that document.
     pub fn with_client_id(client_id: u64) -> Self {
+        Self::with_options(Options::with_client_id(client_id))
+    }
+
+    /// Creates a new document with a specified `options`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::{Doc, Options};
+    ///
+    /// let doc = Doc::with_options(Options {
+    ///     guid: "my-document".to_string(),
+    ///     collection_id: Some("my-collection".to_string()),
+    ///     ..Options::default()
+    /// });
+    /// assert_eq!(doc.guid, "my-document");
+    /// ```
+    pub fn with_options(options: Options) -> Self {
+        let mut store = Store::with_gc(options.client_id, options.guid.clone(), options.skip_gc);
+        store.auto_new_client_id = options.auto_new_client_id_on_conflict;
+        store.record_timestamps = options.record_timestamps;
+        store.max_content_size = options.max_content_size;
+        if let Some(schema) = options.schema.as_ref() {
+            for (name, kind) in schema.iter() {
+                let type_ref = match kind_type_ref(kind) {
+                    Some(type_ref) => type_ref,
+                    None => panic!("root type '{}' declared with unknown kind {}", name, kind),
+                };
+                let node_name = root_node_name(type_ref);
+                store.create_type(name, node_name, type_ref);
+            }
+            // declared root types are not reported as created
+            store.created_types.clear();
+            store.schema = Some(schema.clone());
+        }
         Doc {
-            client_id,
-            store: RefCell::from(Store::new(client_id)),
+            client_id: options.client_id,
+            guid: options.guid,
+            collection_id: options.collection_id,
+            meta: options.meta,
+            auto_load: options.auto_load,
+            skip_gc: options.skip_gc,
+            max_content_size: options.max_content_size,
+            auto_new_client_id_on_conflict: options.auto_new_client_id_on_conflict,
+            record_timestamps: options.record_timestamps,
+            schema: options.schema,
+            current_client_id: store.current_client_id.clone(),
+            store: RefCell::from(store),
+        }
+    }
+
+    /// Creates a new document with given `options`, pre-seeded with the contents of an `update`
+    /// encoded using lib0 ver.1 data format, ie. one produced by [Doc::encode_state_as_update_v1]
+    /// and loaded from a persistent storage.
+    ///
+    /// Since a document is fresh, its block lists are allocated upfront to fit all incoming
+    /// blocks and an update is not checked for client id conflicts: blocks created by
+    /// `options.client_id` are treated as document's own history. An update, which depends on
+    /// blocks it doesn't contain, is still accepted and its dependent blocks are kept pending,
+    /// just like with [Doc::apply_update_v1].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::{Doc, Options};
+    ///
+    /// let doc = Doc::with_client_id(1);
+    /// let mut txn = doc.transact();
+    /// txn.get_text("text").push(&mut txn, "hello");
+    /// let update = doc.encode_state_as_update_v1(&txn);
+    ///
+    /// let loaded = Doc::from_update_v1(&update, Options::with_client_id(1)).unwrap();
+    /// let mut txn = loaded.transact();
+    /// assert_eq!(txn.get_text("text").to_string(&txn), "hello");
+    /// ```
+    pub fn from_update_v1(update: &[u8], options: Options) -> Result<Doc, Error> {
+        let doc = Doc::with_options(options);
+        {
+            let mut txn = doc.transact();
+            let (update, ds) = Self::decode_update(&txn, DecoderV1::from(update))?;
+            txn.check_content_quota(Self::added_content_size(&txn, &update))?;
+            update.reserve(&mut txn.store.blocks);
+            txn.apply_update(update, ds);
+        }
+        Ok(doc)
+    }
+
+    /// Returns options, which current document was created with. Together with an update
+    /// produced by [Doc::encode_state_as_update_v1] they can be used to recreate an identical
+    /// replica, ie. when a document needs to be serialized as a whole and then restored:
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// txn.get_text("text").push(&mut txn, "hello");
+    /// let (options, update) = (doc.options(), doc.encode_state_as_update_v1(&txn));
+    ///
+    /// let restored = Doc::with_options(options);
+    /// let mut txn = restored.transact();
+    /// restored.apply_update_v1(&mut txn, &update).unwrap();
+    /// assert_eq!(restored.client_id, doc.client_id);
+    /// assert_eq!(txn.get_text("text").to_string(&txn), "hello");
+    /// ```
+    pub fn options(&self) -> Options {
+        Options {
+            client_id: self.current_client_id.get(),
+            guid: self.guid.clone(),
+            collection_id: self.collection_id.clone(),
+            meta: self.meta.clone(),
+            auto_load: self.auto_load,
+            skip_gc: self.skip_gc,
+            max_content_size: self.max_content_size,
+            auto_new_client_id_on_conflict: self.auto_new_client_id_on_conflict,
+            record_timestamps: self.record_timestamps,
+            schema: self.schema.clone(),
         }
     }
 
@@ -69,6 +227,13 @@ impl Doc {
         txn.store.encode_v1()
     }
 
+    /// Encode entire state of a current block store using ver. 1 encoding, compressed with
+    /// [compress_update](crate::compress_update). Use [Doc::apply_update_auto] to apply it.
+    #[cfg(feature = "compression")]
+    pub fn encode_state_as_update_compressed(&self, txn: &Transaction<'_>) -> Vec<u8> {
+        Update::compress(&self.encode_state_as_update_v1(txn))
+    }
+
     /// Encode state vector of a current block store using ver. 1 encoding.
     pub fn encode_state_vector_v1(&self, txn: &Transaction<'_>) -> Vec<u8> {
         txn.store.blocks.get_state_vector().encode_v1()
@@ -84,16 +249,443 @@ impl Doc {
 
     /// Creates a transaction used for all kind of block store operations.
     /// Transaction cleanups & calling event handles happen when the transaction struct is dropped.
+    ///
+    /// This method will panic if current document has been destroyed (see: [Doc::destroy]).
     pub fn transact(&self) -> Transaction {
-        Transaction::new(self.store.borrow_mut())
+        Transaction::new(self.active_store(), None)
+    }
+
+    /// Creates a transaction just like [Doc::transact], but with a given `origin` attached to it.
+    /// Origin is passed to transaction callbacks (see: [Doc::on_after_transaction]), so that
+    /// they can tell where the changes came from, eg. to skip the ones made by a network provider
+    /// itself.
+    pub fn transact_with_origin(&self, origin: Any) -> Transaction {
+        Transaction::new(self.active_store(), Some(origin))
+    }
+
+    fn active_store(&self) -> RefMut<Store> {
+        let store = self.store.borrow_mut();
+        if store.destroyed {
+            panic!(
+                "Cannot start a transaction: document {} has been destroyed",
+                self.guid
+            );
+        }
+        store
+    }
+
+    /// Destroys current document: notifies callbacks registered via [Doc::on_destroy], then
+    /// releases all of its blocks, pending updates and subscribed callbacks. Once destroyed,
+    /// a document can no longer be used - any attempt to start a new transaction will panic.
+    /// Destroying a document more than once has no effect.
+    ///
+    /// Subdocuments are not destroyed automatically, as their content is managed by
+    /// the application. Their guids are passed to destroy callbacks instead.
+    pub fn destroy(&self) {
+        let (handler, event, errors) = {
+            let mut store = self.store.borrow_mut();
+            if store.destroyed {
+                return;
+            }
+            store.destroyed = true;
+            let mut subdocs: Vec<_> = store.subdocs.keys().cloned().collect();
+            subdocs.sort();
+            let event = DestroyEvent {
+                guid: self.guid.clone(),
+                subdocs,
+            };
+            let handler = std::mem::replace(&mut store.destroy_events, EventHandler::new());
+            (handler, event, store.observer_error_events.clone())
+        };
+        handler.publish(&event, &errors);
+
+        let mut store = self.store.borrow_mut();
+        *store = Store::with_gc(
+            self.current_client_id.get(),
+            self.guid.clone(),
+            self.skip_gc,
+        );
+        store.current_client_id = self.current_client_id.clone();
+        store.destroyed = true;
+    }
+
+    /// Checks if current document has been destroyed (see: [Doc::destroy]).
+    pub fn is_destroyed(&self) -> bool {
+        self.store.borrow().destroyed
+    }
+
+    /// Apply a document update assuming it's encoded using lib0 ver.1 data format. Returns an
+    /// error if an update could not be decoded, in which case a document is left unchanged.
+    pub fn apply_update_v1(&self, tr: &mut Transaction, update: &[u8]) -> Result<(), Error> {
+        self.apply_update_with_decoder(tr, DecoderV1::from(update))
+    }
+
+    /// Apply an untrusted document update assuming it's encoded using lib0 ver.1 data format.
+    /// Unlike [Doc::apply_update_v1], an update is also rejected if it declares lengths exceeding
+    /// limits configured by `options`, before any memory for them gets allocated.
+    pub fn try_apply_update_v1(
+        &self,
+        tr: &mut Transaction,
+        update: &[u8],
+        options: &DecoderOptions,
+    ) -> Result<(), Error> {
+        self.apply_update_with_decoder(tr, DecoderV1::with_options(update, *options))
+    }
+
+    /// Apply a document update, which is either compressed (see:
+    /// [Doc::encode_state_as_update_compressed]) or a raw one encoded using lib0 ver.1 data format.
+    #[cfg(feature = "compression")]
+    pub fn apply_update_auto(&self, tr: &mut Transaction, update: &[u8]) -> Result<(), Error> {
+        match Update::decompress(update) {
+            Ok(update) => self.apply_update_v1(tr, &update),
+            // not a compressed payload - try to apply it as a raw update
+            Err(_) => self.apply_update_v1(tr, update),
+        }
     }
 
-    /// Apply a document update assuming it's encoded using lib0 ver.1 data format.
-    pub fn apply_update_v1(&self, tr: &mut Transaction, update: &[u8]) {
+    /// Apply a document update assuming it's encoded using lib0 ver.1 data format. Unlike
+    /// [Doc::apply_update_v1], blocks are decoded and integrated one client at a time, so that
+    /// blocks of each client are dropped as soon as they have been integrated, keeping only the
+    /// ones that could not be integrated yet in memory.
+    ///
+    /// An entire update is decoded and validated (and checked against [Doc::max_content_size] if
+    /// set) before anything is integrated or a client id conflict is acted upon, so that an error
+    /// leaves a document unchanged.
+    pub fn apply_update_v1_lazy(&self, tr: &mut Transaction, update: &[u8]) -> Result<(), Error> {
+        if tr.store.update_events.has_subscribers() {
+            // update observers expect to receive an entire update at once
+            return self.apply_update_with_decoder(tr, DecoderV1::from(update));
+        }
         let mut decoder = DecoderV1::from(update);
-        let update = Update::decode(&mut decoder);
-        let ds = DeleteSet::decode(&mut decoder);
-        tr.apply_update(update, ds)
+        let decoded = Update::decode_lazy(&mut decoder)
+            .and_then(|updates| updates.collect::<Result<Vec<_>, _>>())
+            .and_then(|updates| Ok((updates, DeleteSet::decode(&mut decoder)?)));
+        let (updates, ds) = match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                #[cfg(feature = "log")]
+                log_decode_error(tr, &decoder, &e);
+                return Err(e);
+            }
+        };
+        if self.max_content_size.is_some() {
+            let added = updates
+                .iter()
+                .map(|update| Self::added_content_size(tr, update))
+                .sum();
+            tr.check_content_quota(added)?;
+        }
+        let conflict = tr.client_id_conflict(&updates);
+        tr.resolve_client_id_conflict(conflict)?;
+
+        for update in updates {
+            tr.apply_update(update, DeleteSet::default());
+        }
+        tr.apply_update(Update::default(), ds);
+        Ok(())
+    }
+
+    fn apply_update_with_decoder(
+        &self,
+        tr: &mut Transaction,
+        decoder: DecoderV1,
+    ) -> Result<(), Error> {
+        let (update, ds) = Self::decode_update(tr, decoder)?;
+        if self.max_content_size.is_some() {
+            tr.check_content_quota(Self::added_content_size(tr, &update))?;
+        }
+        let conflict = tr.client_id_conflict([&update]);
+        tr.resolve_client_id_conflict(conflict)?;
+        tr.apply_update(update, ds);
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+    fn decode_update(
+        tr: &Transaction,
+        mut decoder: DecoderV1,
+    ) -> Result<(Update, DeleteSet), Error> {
+        let decoded = Update::decode(&mut decoder)
+            .and_then(|update| Ok((update, DeleteSet::decode(&mut decoder)?)));
+        #[cfg(feature = "log")]
+        if let Err(e) = &decoded {
+            log_decode_error(tr, &decoder, e);
+        }
+        decoded
+    }
+
+    /// Returns a size of content of blocks of a given `update`, which have not been integrated
+    /// yet. All of them are accounted for, even if they are deleted in scope of the same update.
+    fn added_content_size(tr: &Transaction, update: &Update) -> usize {
+        update
+            .blocks()
+            .filter_map(|block| block.as_item())
+            .filter(|item| item.id.clock + item.len() > tr.store.blocks.get_state(&item.id.client))
+            .map(Item::content_size)
+            .sum()
+    }
+
+    /// Returns a number of bytes of content stored in a current document (see:
+    /// [Branch::content_size](crate::types::Branch::content_size)). Deleted content is not
+    /// counted. Unlike the size of a single shared type, it's maintained as items are integrated
+    /// and deleted, so it's not computed on each call.
+    pub fn content_size(&self, txn: &Transaction) -> usize {
+        txn.store.content_size.get()
+    }
+
+    /// Creates a new document with the same content, history and options as a current one, but
+    /// with a new randomized client identifier, so that changes made to a returned document
+    /// don't collide with the ones made to a current document. Both documents can still be
+    /// synchronized with each other afterwards.
+    ///
+    /// A forked document shares the same guid as a current one. Use
+    /// [Doc::fork_with_options] to fork a document under a different guid.
+    pub fn fork(&self, txn: &Transaction) -> Doc {
+        let options = Options {
+            client_id: Options::default().client_id,
+            ..self.options()
+        };
+        self.fork_with_options(txn, options)
+    }
+
+    /// Creates a new document with given `options`, which has the same content and history as
+    /// a current one. It's up to a caller to guarantee that a client identifier from `options` is
+    /// different from the one used by a current document.
+    ///
+    /// Root types of a current document are recreated with the same names and kinds, even if they
+    /// have no content.
+    pub fn fork_with_options(&self, txn: &Transaction, options: Options) -> Doc {
+        let doc = Doc::with_options(options);
+        {
+            let mut fork = doc.transact();
+            for (name, branch) in txn.store.types.iter() {
+                let inner = branch.borrow();
+                fork.store
+                    .create_type(name, inner.name.clone(), inner.type_ref());
+            }
+            doc.apply_update_v1(&mut fork, &self.encode_state_as_update_v1(txn))
+                .unwrap();
+        }
+        doc
+    }
+
+    /// Checks if logical content of all root types of a current document is equal to the content
+    /// of `other` document, regardless of how it was created: client ids, order of operations and
+    /// deleted content are not taken into account. Root types, which exist only in one of the
+    /// documents, are considered equal if they are empty.
+    pub fn eq_content(&self, txn: &Transaction, other: &Doc, other_txn: &Transaction) -> bool {
+        self.diff_report(txn, other, other_txn).is_empty()
+    }
+
+    /// Compares logical content of all root types of a current document with the content of
+    /// `other` document, returning a list of all differences found, ordered by root type names.
+    /// Returned list is empty if both documents have equal content.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let d1 = Doc::new();
+    /// let mut t1 = d1.transact();
+    /// t1.get_text("text").push(&mut t1, "hello");
+    ///
+    /// let d2 = Doc::new();
+    /// let mut t2 = d2.transact();
+    /// t2.get_text("text").push(&mut t2, "help");
+    ///
+    /// let report: Vec<_> = d1
+    ///     .diff_report(&t1, &d2, &t2)
+    ///     .iter()
+    ///     .map(|m| m.to_string())
+    ///     .collect();
+    /// assert_eq!(report, vec![r#"text[3]: "l" != "p""#, r#"text[4]: "o" != <none>"#]);
+    /// ```
+    pub fn diff_report(
+        &self,
+        txn: &Transaction,
+        _other: &Doc,
+        other_txn: &Transaction,
+    ) -> Vec<Mismatch> {
+        crate::compare::diff_content(txn, other_txn)
+    }
+
+    /// Validates internal invariants of a current document's block store: that blocks of every
+    /// client form a contiguous range of clocks, that left and right neighbours link back to each
+    /// other, and that parents and origins of all blocks can be resolved. Returns a list of
+    /// violations found, ordered by client and clock values of blocks. Returned list is empty for
+    /// a healthy document.
+    ///
+    /// It's meant for diagnosing diverged replicas, together with [Transaction::dump_structs].
+    pub fn integrity_check(&self, txn: &Transaction) -> Vec<IntegrityViolation> {
+        crate::integrity::check(txn)
+    }
+
+    /// Returns a snapshot of a current document state, which can be used to restore its content
+    /// later on with [Doc::restore_snapshot].
+    pub fn snapshot(&self, txn: &Transaction) -> Snapshot {
+        Snapshot::new(self.get_state_vector(txn), self.delete_set(txn))
+    }
+
+    /// Creates a new document with the content a current document had at the moment when
+    /// a given `snapshot` was made. A current document must be created with
+    /// [Options::skip_gc] enabled or a snapshot must be protected with [Doc::gc_protect],
+    /// otherwise content deleted since then can not be restored and [Error::GarbageCollected]
+    /// is returned.
+    pub fn restore_snapshot(&self, txn: &Transaction, snapshot: &Snapshot) -> Result<Doc, Error> {
+        let mut clients: Vec<u64> = txn.store.blocks.iter().map(|(&client, _)| client).collect();
+        clients.sort_unstable();
+        for client in clients {
+            for block in txn.store.blocks.get(&client).unwrap().iter() {
+                let collected = match block {
+                    Block::Item(item) => matches!(item.content, ItemContent::Deleted(_)),
+                    _ => true,
+                };
+                if collected && snapshot.is_visible(block.id(), block.len()) {
+                    let id = block.id();
+                    return Err(Error::GarbageCollected {
+                        client: id.client,
+                        clock: id.clock,
+                    });
+                }
+            }
+        }
+        let doc = Doc::with_options(Options {
+            skip_gc: true,
+            ..Options::default()
+        });
+        {
+            let mut restored = doc.transact();
+            for (name, branch) in txn.store.types.iter() {
+                let inner = branch.borrow();
+                restored
+                    .store
+                    .create_type(name, inner.name.clone(), inner.type_ref());
+            }
+            let mut update = Update::decode_v1(&self.encode_state_as_update_v1(txn)).unwrap();
+            update.trim(&snapshot.state_vector);
+            restored.apply_update(update, snapshot.delete_set.clone());
+        }
+        Ok(doc)
+    }
+
+    /// Protects content visible in a given `snapshot` from garbage collection, so that it can
+    /// be restored later on with [Doc::restore_snapshot] even though a current document doesn't
+    /// have [Options::skip_gc] enabled. Deleted blocks not visible in any of the protected
+    /// snapshots are still garbage collected as usual.
+    ///
+    /// Content garbage collected before a snapshot has been protected can not be brought back.
+    /// Protecting the same snapshot more than once requires the same number of
+    /// [Doc::gc_unprotect] calls to release it.
+    pub fn gc_protect(&self, tr: &mut Transaction, snapshot: &Snapshot) {
+        tr.store.gc_protected.push(snapshot.clone());
+    }
+
+    /// Releases a `snapshot` protected with [Doc::gc_protect], allowing content visible only in
+    /// it to be garbage collected by following transactions and [Doc::compact]. Returns `false`
+    /// if a given snapshot was not protected.
+    pub fn gc_unprotect(&self, tr: &mut Transaction, snapshot: &Snapshot) -> bool {
+        let protected = &mut tr.store.gc_protected;
+        match protected.iter().position(|s| s == snapshot) {
+            Some(i) => {
+                protected.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stores a snapshot of a current document state as a named version, within a document
+    /// itself (see: [VERSIONS_ROOT]). This way versions are replicated together with other
+    /// document changes. A document should be created with [Options::skip_gc] enabled in order
+    /// to restore content of its versions.
+    pub fn create_version(&self, txn: &mut Transaction, name: &str) -> Version {
+        let version = Version {
+            name: name.to_string(),
+            timestamp: unix_millis(),
+            snapshot: self.snapshot(txn),
+        };
+        let versions = txn.store.create_type(VERSIONS_ROOT, None, TYPE_REFS_ARRAY);
+        Array::from(versions).push_back(txn, version.to_any());
+        version
+    }
+
+    /// Returns all named versions stored within a current document, in order of their creation.
+    pub fn versions(&self, txn: &Transaction) -> Vec<Version> {
+        let name = Rc::new(VERSIONS_ROOT.to_string());
+        match txn.store.types.get(&name) {
+            Some(branch) => Array::from(branch.clone())
+                .iter(txn)
+                .filter_map(|value| match value {
+                    Value::Any(any) => Version::from_any(&any),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns all commit times stored within a current document, ordered by their clients and
+    /// clocks. See: [Options::record_timestamps].
+    pub fn edit_timestamps(&self, txn: &Transaction) -> Vec<EditTimestamp> {
+        let name = Rc::new(TIMESTAMPS_ROOT.to_string());
+        let mut result: Vec<_> = match txn.store.types.get(&name) {
+            Some(branch) => Map::from(branch.clone())
+                .iter(txn)
+                .filter_map(|(_, value)| match value {
+                    Value::Any(any) => EditTimestamp::from_any(&any),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        result.sort_by_key(|e| (e.client, e.clock.start));
+        result
+    }
+
+    /// Returns a time, in milliseconds since Unix epoch, at which a block with a given `id` has
+    /// been committed or `None` if it was not recorded. See: [Options::record_timestamps].
+    pub fn timestamp_for(&self, txn: &Transaction, id: &ID) -> Option<f64> {
+        self.edit_timestamps(txn)
+            .into_iter()
+            .find(|e| e.client == id.client && e.clock.contains(&id.clock))
+            .map(|e| e.timestamp)
+    }
+
+    /// Returns clock ranges of blocks committed within a given time range (`from` inclusive, `to`
+    /// exclusive, both in milliseconds since Unix epoch), grouped by their clients and ordered by
+    /// clocks. See: [Options::record_timestamps].
+    pub fn edits_between(
+        &self,
+        txn: &Transaction,
+        from: f64,
+        to: f64,
+    ) -> HashMap<u64, Vec<Range<u32>>> {
+        let mut result: HashMap<u64, Vec<Range<u32>>> = HashMap::new();
+        for e in self.edit_timestamps(txn) {
+            if e.timestamp >= from && e.timestamp < to {
+                result.entry(e.client).or_default().push(e.clock);
+            }
+        }
+        result
+    }
+
+    /// Compares content of a document at versions named `a` and `b`, returning a list of all
+    /// differences found, ordered by root type names (see: [Doc::diff_report]). Versions root
+    /// itself is not compared. Returns `None` if any of the versions doesn't exist or its content
+    /// has been garbage collected (see: [Doc::restore_snapshot]).
+    pub fn diff_versions(&self, txn: &Transaction, a: &str, b: &str) -> Option<Vec<Mismatch>> {
+        let versions = self.versions(txn);
+        let find = |name: &str| versions.iter().rev().find(|v| v.name == name);
+        let left = self.restore_snapshot(txn, &find(a)?.snapshot).ok()?;
+        let right = self.restore_snapshot(txn, &find(b)?.snapshot).ok()?;
+        let report = crate::compare::diff_content(&left.transact(), &right.transact());
+        Some(
+            report
+                .into_iter()
+                .filter(|m| m.root != VERSIONS_ROOT)
+                .collect(),
+        )
     }
 
     /// Retrieve document state vector in order to encode the document diff. This state vector
@@ -103,6 +695,79 @@ impl Doc {
         tr.store.blocks.get_state_vector()
     }
 
+    /// Checks if a current document contains any blocks not included in a given state vector,
+    /// ie. one stored together with the last backup. Only the latest clock of every client is
+    /// compared, so it's much cheaper than computing an actual update.
+    ///
+    /// Deleting existing content doesn't create any new blocks, so changes consisting only of
+    /// deletions are not detected this way - compare [Doc::delete_set] for that.
+    pub fn has_changes_since(&self, tr: &Transaction, sv: &StateVector) -> bool {
+        tr.store
+            .blocks
+            .iter()
+            .any(|(client, blocks)| blocks.get_state() > sv.get(client))
+    }
+
+    /// Checks if a peer, which state is described by a given state vector, is missing any changes
+    /// known to a current document. Besides comparing clocks like [Doc::has_changes_since] does,
+    /// it also accounts for content deleted by local transactions since the last call to
+    /// [Doc::mark_synced], as deletions don't advance clocks.
+    ///
+    /// Deletions are tracked on per-document basis, not per-peer: once marked as synced with one
+    /// peer, they are no longer reported for any other.
+    pub fn has_pending_changes_for(&self, tr: &Transaction, sv: &StateVector) -> bool {
+        self.has_changes_since(tr, sv) || !tr.store.unsynced_deletes.is_empty()
+    }
+
+    /// Checks if any local changes have been committed since the last call to
+    /// [Doc::mark_synced]. This flag is maintained on every commit, so it's cheap to check
+    /// ie. for displaying an "unsaved changes" indicator. Changes integrated from remote updates
+    /// don't set it.
+    pub fn is_dirty(&self, tr: &Transaction) -> bool {
+        tr.store.unsynced
+    }
+
+    /// Marks a current document as synced with a peer, which has confirmed receiving all changes
+    /// up to a given state vector, including deletions sent so far. Document remains dirty
+    /// (see: [Doc::is_dirty]) if it contains local blocks not included in a given state vector.
+    pub fn mark_synced(&self, tr: &mut Transaction, sv: &StateVector) {
+        let client = tr.store.client_id;
+        tr.store.unsynced_deletes = DeleteSet::new();
+        tr.store.unsynced = tr.store.blocks.get_state(&client) > sv.get(&client);
+    }
+
+    /// Returns clock ranges of blocks, which an update computed for a given state vector would
+    /// contain (see: [Doc::encode_delta_as_update_v1]), as `(client, from_clock, to_clock)`
+    /// tuples ordered by client ids, with `to_clock` being exclusive. Just like
+    /// [Doc::has_changes_since], it doesn't account for deletions of existing content.
+    pub fn changes_since(&self, tr: &Transaction, sv: &StateVector) -> Vec<(u64, u32, u32)> {
+        let mut changes: Vec<_> = tr
+            .store
+            .diff_state_vector(sv)
+            .into_iter()
+            .map(|(client, from)| (client, from, tr.store.blocks.get_state(&client)))
+            .collect();
+        changes.sort_unstable();
+        changes
+    }
+
+    /// Returns a delete set describing clock ranges of all deleted blocks observed by the current
+    /// block store, including the ones deleted by remote peers.
+    pub fn delete_set(&self, tr: &Transaction) -> DeleteSet {
+        tr.store.delete_set().clone()
+    }
+
+    /// Returns statistics about the size and shape of the current block store.
+    pub fn stats(&self, tr: &Transaction) -> StoreStats {
+        tr.store_stats()
+    }
+
+    /// Performs a full compaction pass over the current block store, reporting its statistics
+    /// before and after. See: [Transaction::compact].
+    pub fn compact(&self, tr: &mut Transaction) -> CompactionReport {
+        tr.compact()
+    }
+
     /// Subscribe callback function for incoming update events. Returns a subscription, which will
     /// unsubscribe function when dropped.
     pub fn on_update<F>(&mut self, f: F) -> Subscription<UpdateEvent>
@@ -112,6 +777,617 @@ impl Doc {
         let mut store = self.store.borrow_mut();
         store.update_events.subscribe(f)
     }
+
+    /// Subscribe callback function called whenever subdocuments are added to, removed from or
+    /// requested to be loaded within a current document. Returns a subscription, which will
+    /// unsubscribe function when dropped.
+    pub fn on_subdocs<F>(&mut self, f: F) -> Subscription<SubdocsEvent>
+    where
+        F: Fn(&SubdocsEvent) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.subdocs_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a transaction, which created new root-level
+    /// types, is being committed. This includes root types introduced by integrated remote
+    /// updates. Returns a subscription, which will unsubscribe function when dropped.
+    pub fn on_root_types<F>(&mut self, f: F) -> Subscription<RootTypesEvent>
+    where
+        F: Fn(&RootTypesEvent) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.root_types_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a transaction, which made any changes to
+    /// a current document, has been committed. Returns a subscription, which will unsubscribe
+    /// function when dropped.
+    pub fn on_commit<F>(&mut self, f: F) -> Subscription<CommitEvent>
+    where
+        F: Fn(&CommitEvent) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.commit_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called once a current document is being destroyed (see:
+    /// [Doc::destroy]). Callback is called at most once and it must not start new transactions
+    /// on a destroyed document. Returns a subscription, which will unsubscribe function when
+    /// dropped.
+    pub fn on_destroy<F>(&mut self, f: F) -> Subscription<DestroyEvent>
+    where
+        F: Fn(&DestroyEvent) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.destroy_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a client id of a current document has been
+    /// replaced with a new one (see: [Options::auto_new_client_id_on_conflict]). Returns
+    /// a subscription, which will unsubscribe function when dropped.
+    pub fn on_client_id_change<F>(&mut self, f: F) -> Subscription<ClientIdChangeEvent>
+    where
+        F: Fn(&ClientIdChangeEvent) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.client_id_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever any other callback subscribed to a current
+    /// document or its shared types has panicked. A panicking callback doesn't prevent the
+    /// remaining ones from being called and its panic is not propagated to the code, which has
+    /// triggered it (ie. a transaction commit). Returns a subscription, which will unsubscribe
+    /// function when dropped.
+    pub fn on_observer_error<F>(&mut self, f: F) -> Subscription<ObserverError>
+    where
+        F: Fn(&ObserverError) -> () + 'static,
+    {
+        let mut store = self.store.borrow_mut();
+        store.observer_error_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a new transaction is started on a current
+    /// document. Returns a subscription, which will unsubscribe function when dropped.
+    pub fn on_before_transaction<F>(&mut self, f: F) -> Subscription<BeforeTransactionEvent>
+    where
+        F: Fn(&Transaction, &BeforeTransactionEvent) -> () + 'static,
+    {
+        let store = self.store.borrow();
+        store.before_transaction_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a transaction is being committed, right after
+    /// observers of the shared types changed by it have been called. Callback receives a summary
+    /// of all changes made by a transaction - including empty ones, that didn't change anything.
+    /// Returns a subscription, which will unsubscribe function when dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use yrs::Doc;
+    ///
+    /// let mut doc = Doc::new();
+    /// let dirty = Rc::new(Cell::new(false));
+    /// let _sub = {
+    ///     let dirty = dirty.clone();
+    ///     doc.on_after_transaction(move |_, e| dirty.set(dirty.get() || e.is_root_changed("text")))
+    /// };
+    ///
+    /// let mut txn = doc.transact();
+    /// txn.get_text("text").push(&mut txn, "hello");
+    /// txn.commit();
+    /// assert!(dirty.get());
+    /// ```
+    pub fn on_after_transaction<F>(&mut self, f: F) -> Subscription<AfterTransactionEvent>
+    where
+        F: Fn(&Transaction, &AfterTransactionEvent) -> () + 'static,
+    {
+        let store = self.store.borrow();
+        store.after_transaction_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a transaction is being committed, once its
+    /// blocks have been garbage collected and squashed. Callback receives the same summary as
+    /// the ones registered via [Doc::on_after_transaction]. Returns a subscription, which will
+    /// unsubscribe function when dropped.
+    pub fn on_after_transaction_cleanup<F>(&mut self, f: F) -> Subscription<AfterTransactionEvent>
+    where
+        F: Fn(&Transaction, &AfterTransactionEvent) -> () + 'static,
+    {
+        let store = self.store.borrow();
+        store.after_transaction_cleanup_events.subscribe(f)
+    }
+
+    /// Subscribe callback function called whenever a committed transaction has changed any of
+    /// the root types with given `roots` names (or types nested in them). Callback receives
+    /// an update encoded using lib0 ver. 1 encoding, which contains only the blocks and deletions
+    /// belonging to these root types. Returns a subscription, which will unsubscribe function when
+    /// dropped.
+    ///
+    /// Each update covers all changes made since the previous one delivered to the same callback
+    /// (or since the subscription, for the first one). Blocks of other root types in that range are
+    /// encoded as skipped ranges, just like Yjs does for missing content. A remote peer keeps
+    /// blocks following a skipped range pending until another update fills it in, so a stream
+    /// only applies on its own to a document, which has received changes of other root types
+    /// by other means. Together with streams of other root types and a catch-all stream
+    /// (see: [Doc::observe_update_except_roots]) it contains every change made to a document:
+    /// applying all of them in any order - or combining them first with [merge_updates] -
+    /// reconstructs the full document.
+    pub fn observe_update_for_roots<F>(
+        &mut self,
+        roots: &[&str],
+        f: F,
+    ) -> Subscription<AfterTransactionEvent>
+    where
+        F: Fn(&[u8]) -> () + 'static,
+    {
+        let roots: Vec<String> = roots.iter().map(|root| root.to_string()).collect();
+        self.observe_update_where(
+            move |root| {
+                root.map(|key| roots.iter().any(|r| r == key))
+                    .unwrap_or(false)
+            },
+            f,
+        )
+    }
+
+    /// Subscribe callback function called whenever a committed transaction has changed anything
+    /// except root types with given `roots` names (or types nested in them). It's a counterpart
+    /// of [Doc::observe_update_for_roots], which delivers updates containing blocks and deletions
+    /// of all other root types, including blocks which don't belong to any root type anymore.
+    /// Returns a subscription, which will unsubscribe function when dropped.
+    pub fn observe_update_except_roots<F>(
+        &mut self,
+        roots: &[&str],
+        f: F,
+    ) -> Subscription<AfterTransactionEvent>
+    where
+        F: Fn(&[u8]) -> () + 'static,
+    {
+        let roots: Vec<String> = roots.iter().map(|root| root.to_string()).collect();
+        self.observe_update_where(
+            move |root| {
+                root.map(|key| roots.iter().all(|r| r != key))
+                    .unwrap_or(true)
+            },
+            f,
+        )
+    }
+
+    /// Subscribes callback function to updates containing blocks and deletions of root types, for
+    /// which an `include` predicate returns true. See: [Store::encode_diff_where].
+    fn observe_update_where<P, F>(
+        &mut self,
+        include: P,
+        f: F,
+    ) -> Subscription<AfterTransactionEvent>
+    where
+        P: Fn(Option<&str>) -> bool + 'static,
+        F: Fn(&[u8]) -> () + 'static,
+    {
+        // `sent` is a state of a subscriber, which only advances when an update is delivered,
+        // while `checked` marks blocks already known not to be included, so that each commit
+        // only checks blocks it has added. Blocks in between are sent as skipped ranges.
+        let state = self.store.borrow().blocks.get_state_vector();
+        let sent = RefCell::new(state.clone());
+        let checked = RefCell::new(state);
+        self.on_after_transaction(move |txn, e| {
+            let mut sent = sent.borrow_mut();
+            let mut checked = checked.borrow_mut();
+            let mut encoder = EncoderV1::new();
+            let covered = txn.store.encode_diff_where(
+                &sent,
+                &checked,
+                &e.delete_set,
+                &include,
+                BLOCK_SKIP_REF_NUMBER,
+                &mut encoder,
+            );
+            *checked = txn.store.blocks.get_state_vector();
+            if let Some(covered) = covered {
+                *sent = covered;
+                f(&encoder.to_vec());
+            }
+        })
+    }
+
+    /// Appends changes made by every transaction committed on a current document - including
+    /// integrated remote updates - to a given `store`. Returns a subscription, which will stop
+    /// appending updates when dropped.
+    ///
+    /// A `store` must not be borrowed at the time when transactions are being committed.
+    pub fn persist_to<S>(&mut self, store: Rc<RefCell<S>>) -> Subscription<CommitEvent>
+    where
+        S: DocStore + 'static,
+    {
+        self.on_commit(move |e| {
+            store.borrow_mut().append_update(&e.update);
+        })
+    }
+
+    /// Loads a document state persisted in a given `store`: its baseline followed by all updates
+    /// appended after it. This should be done before [Doc::persist_to] is called, otherwise loaded
+    /// updates will be appended to a store again.
+    pub fn load_from<S>(&self, txn: &mut Transaction, store: &S) -> Result<(), Error>
+    where
+        S: DocStore + ?Sized,
+    {
+        if let Some(baseline) = store.load_baseline() {
+            self.apply_update_v1(txn, &baseline)?;
+        }
+        for (_, update) in store.load_updates() {
+            self.apply_update_v1(txn, &update)?;
+        }
+        Ok(())
+    }
+
+    /// Returns references to all subdocuments embedded within shared types of a current document,
+    /// including ones added in scope of a given transaction. Returned list is ordered by
+    /// subdocument guids.
+    pub fn subdocs(&self, txn: &Transaction) -> Vec<SubDoc> {
+        let mut subdocs: Vec<_> = txn
+            .store
+            .subdocs
+            .iter()
+            .filter(|(guid, _)| {
+                !txn.subdocs_removed.contains(*guid) && !txn.subdocs_added.contains_key(*guid)
+            })
+            .chain(txn.subdocs_added.iter())
+            .map(|(_, subdoc)| subdoc.clone())
+            .collect();
+        subdocs.sort_by(|a, b| a.guid.cmp(&b.guid));
+        subdocs
+    }
+
+    /// Returns a summary of a current document state, meant to be attached to bug reports.
+    /// Use its [Display](std::fmt::Display) implementation to produce a readable dump.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::with_client_id(1);
+    /// let mut txn = doc.transact();
+    /// txn.get_text("text").push(&mut txn, "hello");
+    ///
+    /// let description = doc.describe(&txn);
+    /// assert_eq!(description.roots[0].kind, "YText");
+    /// assert_eq!(description.roots[0].len, 5);
+    /// println!("{}", description);
+    /// ```
+    pub fn describe(&self, txn: &Transaction) -> DocDescription {
+        let mut roots: Vec<_> = txn
+            .store
+            .types
+            .iter()
+            .map(|(name, branch)| {
+                let inner = branch.borrow();
+                let type_ref = inner.type_ref();
+                RootDescription {
+                    name: name.to_string(),
+                    kind: types::kind_name(type_ref),
+                    len: if type_ref == types::TYPE_REFS_MAP {
+                        inner.map_len()
+                    } else {
+                        inner.len()
+                    },
+                }
+            })
+            .collect();
+        roots.sort_by(|a, b| a.name.cmp(&b.name));
+        DocDescription {
+            client_id: txn.store.client_id,
+            guid: txn.store.guid.clone(),
+            state_vector: txn.store.blocks.get_state_vector(),
+            roots,
+            missing: txn.store.pending.as_ref().map(|p| p.missing.clone()),
+            pending_delete_set: txn.store.pending_ds.is_some(),
+            stats: txn.store_stats(),
+        }
+    }
+}
+
+/// A summary of a document state returned by [Doc::describe].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocDescription {
+    /// Client identifier of a described document replica.
+    pub client_id: u64,
+    /// Globally unique identifier of a described document.
+    pub guid: String,
+    /// State vector of all blocks integrated into a document.
+    pub state_vector: StateVector,
+    /// Root types defined or integrated into a document, ordered by their names.
+    pub roots: Vec<RootDescription>,
+    /// If a document has a pending update waiting for missing blocks to arrive, this state vector
+    /// describes minimal clock values required for it to be integrated.
+    pub missing: Option<StateVector>,
+    /// True if a document has a pending delete set waiting for missing blocks to arrive.
+    pub pending_delete_set: bool,
+    /// Statistics of a document's block store.
+    pub stats: StoreStats,
+}
+
+/// A summary of a single root type, being part of a [DocDescription].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootDescription {
+    /// Name of a root type.
+    pub name: String,
+    /// Kind of a root type, consistent with [Value::kind](crate::types::Value::kind).
+    pub kind: &'static str,
+    /// Number of elements of a root type: entries in case of maps, sequence length otherwise.
+    pub len: u32,
+}
+
+impl std::fmt::Display for DocDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_sv(f: &mut std::fmt::Formatter<'_>, sv: &StateVector) -> std::fmt::Result {
+            let mut entries: Vec<_> = sv.iter().collect();
+            entries.sort();
+            write!(f, "{{")?;
+            for (i, (client, clock)) in entries.into_iter().enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {}", client, clock)?;
+            }
+            write!(f, "}}")
+        }
+
+        writeln!(f, "client id: {}", self.client_id)?;
+        writeln!(f, "guid: {}", self.guid)?;
+        write!(f, "state vector: ")?;
+        write_sv(f, &self.state_vector)?;
+        writeln!(f)?;
+        writeln!(f, "roots:")?;
+        for root in self.roots.iter() {
+            writeln!(f, "  {}: {} (len: {})", root.name, root.kind, root.len)?;
+        }
+        write!(f, "pending: ")?;
+        match &self.missing {
+            Some(missing) => {
+                write!(f, "{} blocks, missing ", self.stats.pending_blocks)?;
+                write_sv(f, missing)?;
+                writeln!(f)?;
+            }
+            None => writeln!(f, "none")?,
+        }
+        writeln!(f, "pending delete set: {}", self.pending_delete_set)?;
+        writeln!(
+            f,
+            "blocks: {} (tombstones: {}), content length: {}, heap size: {} bytes",
+            self.stats.blocks, self.stats.tombstones, self.stats.content_len, self.stats.heap_size
+        )
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_decode_error(txn: &Transaction, decoder: &DecoderV1, error: &Error) {
+    tracing::warn!(
+        guid = %txn.store.guid,
+        client_id = txn.store.client_id,
+        offset = decoder.position(),
+        %error,
+        "failed to decode update"
+    );
+}
+
+/// Configuration options of a [Doc].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// A unique identifier of a document replica. Randomized by default.
+    pub client_id: u64,
+    /// A globally unique identifier of a document, shared by all of its replicas. Randomized by
+    /// default.
+    pub guid: String,
+    /// An identifier of a collection, a document belongs to.
+    pub collection_id: Option<String>,
+    /// Custom metadata attached to a document.
+    pub meta: Option<Any>,
+    /// If true, content of a document is requested to be loaded as soon as it's embedded as
+    /// a subdocument into another document.
+    pub auto_load: bool,
+    /// If true, deleted blocks are never garbage collected, so their content stays available
+    /// after deletion. Disabled by default.
+    pub skip_gc: bool,
+    /// Maximum number of bytes of content a document can hold, as measured by
+    /// [Doc::content_size]. Updates which could make a document exceed it are rejected with
+    /// [Error::QuotaExceeded]. Local changes are limited as well: fallible methods such as
+    /// [Text::try_insert](crate::Text::try_insert), [Array::try_insert](crate::Array::try_insert)
+    /// or [Map::try_insert](crate::Map::try_insert) return [Error::QuotaExceeded] without
+    /// changing a document, while other methods inserting content panic. Unlimited by default.
+    pub max_content_size: Option<usize>,
+    /// Controls what happens when a remote update reveals another replica using the same client
+    /// id, ie. when a persisted document has been reloaded in many processes at once. If true,
+    /// a client id is replaced with a new randomized one (see: [Doc::on_client_id_change]) as
+    /// soon as any blocks created under it by another replica are observed - this includes
+    /// loading a document's own persisted state. Like in Yjs, a new client id is only used for
+    /// future changes: existing blocks are never rewritten, so remote blocks colliding with local
+    /// ones are not integrated. Otherwise updates containing blocks which collide with existing
+    /// local ones are rejected with [Error::DuplicateClientClock]. Disabled by default.
+    pub auto_new_client_id_on_conflict: bool,
+    /// If true, every committed transaction, which inserted any content, stores a range of
+    /// clocks of its blocks together with a commit time in [TIMESTAMPS_ROOT] map, so that it's
+    /// replicated to other peers (see: [Doc::timestamp_for] and [Doc::edits_between]).
+    /// Disabled by default.
+    pub record_timestamps: bool,
+    /// Names and kinds (see: [Value::kind]) of root types declared up front. If set, these root
+    /// types are created together with a document, and type-specific accessors like
+    /// [Transaction::get_text] panic when asked for a root type which is not declared or which
+    /// is declared with a different kind, so that typos fail fast instead of silently creating
+    /// new root types. Undeclared root types can still be accessed explicitly with
+    /// [Transaction::get_or_create_unchecked]. Root types introduced by remote updates are
+    /// accepted regardless of a schema, but they are reported in [RootTypesEvent::non_schema].
+    /// Not set by default.
+    pub schema: Option<Vec<(String, &'static str)>>,
+}
+
+impl Options {
+    /// Creates default options with a specified `client_id`.
+    pub fn with_client_id(client_id: u64) -> Self {
+        Options {
+            client_id,
+            guid: uuid_v4(),
+            collection_id: None,
+            meta: None,
+            auto_load: false,
+            skip_gc: false,
+            max_content_size: None,
+            auto_new_client_id_on_conflict: false,
+            record_timestamps: false,
+            schema: None,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::with_client_id(rand::thread_rng().gen())
+    }
+}
+
+/// A reference to a document embedded as a value within shared types of another document. Only
+/// a guid and options of an embedded document are stored by its parent: loading content of
+/// a subdocument is up to the application, which can identify it by its [SubDoc::guid].
+///
+/// A subdocument can be inserted either by using a [Doc] or a [SubDoc] itself as a value.
+///
+/// # Example
+///
+/// ```
+/// use yrs::{Doc, SubDoc, Value};
+///
+/// let page = Doc::new();
+/// let doc = Doc::new();
+/// let mut txn = doc.transact();
+/// let pages = txn.get_map("pages");
+/// pages.insert(&mut txn, "page-1".to_string(), &page);
+///
+/// match pages.get(&txn, "page-1") {
+///     Some(Value::YDoc(subdoc)) => assert_eq!(subdoc.guid(), page.guid),
+///     other => panic!("expected subdocument, found {:?}", other),
+/// }
+/// assert_eq!(doc.subdocs(&txn), vec![SubDoc::new(page.guid.clone())]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubDoc {
+    guid: String,
+    options: Any,
+}
+
+/// Subdocument option used to request its content to be loaded automatically.
+const OPT_AUTO_LOAD: &str = "autoLoad";
+/// Subdocument option used to pass custom document metadata.
+const OPT_META: &str = "meta";
+
+impl SubDoc {
+    /// Creates a new reference to a subdocument with a given `guid`.
+    pub fn new(guid: String) -> Self {
+        SubDoc {
+            guid,
+            options: Any::Map(HashMap::new()),
+        }
+    }
+
+    /// Creates a new reference to a subdocument with a given `guid`, which content is meant to be
+    /// loaded as soon as it's integrated into its parent document.
+    pub fn auto_loaded(guid: String) -> Self {
+        let mut options = HashMap::new();
+        options.insert(OPT_AUTO_LOAD.to_string(), Any::Bool(true));
+        SubDoc {
+            guid,
+            options: Any::Map(options),
+        }
+    }
+
+    pub(crate) fn with_options(guid: String, options: Any) -> Self {
+        SubDoc { guid, options }
+    }
+
+    /// Returns a guid of a referenced document.
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+
+    /// Returns options a subdocument has been inserted with, as encoded by Yjs.
+    pub fn options(&self) -> &Any {
+        &self.options
+    }
+
+    /// Returns custom metadata of a referenced document, if it had any.
+    pub fn meta(&self) -> Option<&Any> {
+        match &self.options {
+            Any::Map(options) => options.get(OPT_META),
+            _ => None,
+        }
+    }
+
+    /// Returns true if content of a subdocument should be loaded as soon as it's integrated.
+    pub fn should_load(&self) -> bool {
+        match &self.options {
+            Any::Map(options) => options.get(OPT_AUTO_LOAD) == Some(&Any::Bool(true)),
+            _ => false,
+        }
+    }
+
+    /// Requests content of a current subdocument to be loaded. This doesn't load anything by
+    /// itself, but it makes a guid of this subdocument part of [SubdocsEvent::loaded] once
+    /// a transaction is committed, so that the application can load it.
+    pub fn load(&self, txn: &mut Transaction) {
+        if txn.store.subdocs.contains_key(&self.guid) || txn.subdocs_added.contains_key(&self.guid)
+        {
+            txn.subdocs_loaded.insert(self.guid.clone());
+        }
+    }
+}
+
+impl Prelim for SubDoc {
+    fn into_content(self, _txn: &mut Transaction, _ptr: TypePtr) -> (ItemContent, Option<Self>) {
+        (ItemContent::Doc(self.guid, self.options), None)
+    }
+
+    fn integrate(self, _txn: &mut Transaction, _inner_ref: BranchRef) {}
+}
+
+impl From<&Doc> for SubDoc {
+    fn from(doc: &Doc) -> Self {
+        let mut options = HashMap::new();
+        if doc.auto_load {
+            options.insert(OPT_AUTO_LOAD.to_string(), Any::Bool(true));
+        }
+        if let Some(meta) = doc.meta.as_ref() {
+            options.insert(OPT_META.to_string(), meta.clone());
+        }
+        SubDoc::with_options(doc.guid.clone(), Any::Map(options))
+    }
+}
+
+impl Prelim for &Doc {
+    fn into_content(self, txn: &mut Transaction, ptr: TypePtr) -> (ItemContent, Option<Self>) {
+        let (content, _) = SubDoc::from(self).into_content(txn, ptr);
+        (content, None)
+    }
+
+    fn integrate(self, _txn: &mut Transaction, _inner_ref: BranchRef) {}
+}
+
+/// Generates a random guid in a format of UUID v4, the same as used by Yjs.
+fn uuid_v4() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
 }
 
 impl Default for Doc {
@@ -122,11 +1398,27 @@ impl Default for Doc {
 
 #[cfg(test)]
 mod test {
+    use crate::block::Block;
+    use crate::store::Store;
+    use crate::test_utils::exchange_updates;
+    use crate::timestamps::unix_millis;
+    use crate::types::{Change, Event, PathSegment, TYPE_REFS_MAP, TYPE_REFS_TEXT};
     use crate::update::Update;
     use crate::updates::decoder::Decode;
-    use crate::updates::encoder::{Encode, Encoder, EncoderV1};
-    use crate::{Doc, StateVector};
-    use std::cell::Cell;
+    use crate::updates::encoder::{Encode, EncoderV1};
+    use crate::{
+        merge_updates, BranchId, ChangeSource, ClientIdChangeEvent, DeleteSet, DestroyEvent, Doc,
+        Error, IntegrityViolation, Options, PrelimArray, PrelimMap, RevertConflict,
+        RootDescription, RootTypesEvent, StateVector, SubDoc, SubdocsEvent, Text, Transaction,
+        Value, ID,
+    };
+    use lib0::any::Any;
+    use lib0::decoding::{DecodeError, DecoderOptions};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, HashSet};
+    use std::convert::TryFrom;
     use std::rc::Rc;
 
     #[test]
@@ -149,7 +1441,7 @@ mod test {
         ];
         let doc = Doc::new();
         let mut tr = doc.transact();
-        doc.apply_update_v1(&mut tr, update);
+        doc.apply_update_v1(&mut tr, update).unwrap();
 
         let actual = tr.get_text("type").to_string(&tr);
         assert_eq!(actual, "210".to_owned());
@@ -193,12 +1485,14 @@ mod test {
 
         // create an update A->B based on B's state vector
         let mut encoder = EncoderV1::new();
-        t1.store
-            .encode_diff(&StateVector::decode_v1(sv.as_slice()), &mut encoder);
+        t1.store.encode_diff(
+            &StateVector::decode_v1(sv.as_slice()).unwrap(),
+            &mut encoder,
+        );
         let binary = encoder.to_vec();
 
         // decode an update incoming from A and integrate it at B
-        let update = Update::decode_v1(binary.as_slice());
+        let update = Update::decode_v1(binary.as_slice()).unwrap();
         let pending = update.integrate(&mut t2);
 
         assert!(pending.is_none());
@@ -225,14 +1519,2990 @@ mod test {
 
         txt.insert(&mut txn, 0, "abc");
         let u = doc.encode_delta_as_update_v1(&txn, &doc2.get_state_vector(&txn2));
-        doc2.apply_update_v1(&mut txn2, u.as_slice());
+        doc2.apply_update_v1(&mut txn2, u.as_slice()).unwrap();
         assert_eq!(counter.get(), 3); // update has been propagated
 
         drop(sub);
 
         txt.insert(&mut txn, 3, "de");
         let u = doc.encode_delta_as_update_v1(&txn, &doc2.get_state_vector(&txn2));
-        doc2.apply_update_v1(&mut txn2, u.as_slice());
+        doc2.apply_update_v1(&mut txn2, u.as_slice()).unwrap();
         assert_eq!(counter.get(), 3); // since subscription has been dropped, update was not propagated
     }
+
+    #[test]
+    fn subdocs() {
+        let mut d1 = Doc::with_client_id(1);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let e = events.clone();
+        let _sub = d1.on_subdocs(move |event| e.borrow_mut().push(event.clone()));
+
+        let page = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            let pages = txn.get_map("pages");
+            pages.insert(&mut txn, "a".to_string(), &page);
+            pages.insert(&mut txn, "b".to_string(), SubDoc::new("guid-b".to_string()));
+            assert_eq!(d1.subdocs(&txn).len(), 2);
+        }
+        assert_eq!(
+            events.borrow().as_slice(),
+            &[SubdocsEvent {
+                added: {
+                    let mut added = vec![page.guid.clone(), "guid-b".to_string()];
+                    added.sort();
+                    added
+                },
+                removed: vec![],
+                loaded: vec![],
+            }]
+        );
+
+        // remove one of the subdocs, request to load the other one
+        events.borrow_mut().clear();
+        {
+            let mut txn = d1.transact();
+            let pages = txn.get_map("pages");
+            pages.remove(&mut txn, "a");
+            match pages.get(&txn, "b") {
+                Some(Value::YDoc(subdoc)) => subdoc.load(&mut txn),
+                other => panic!("expected subdocument, found {:?}", other),
+            }
+        }
+        assert_eq!(
+            events.borrow().as_slice(),
+            &[SubdocsEvent {
+                added: vec![],
+                removed: vec![page.guid.clone()],
+                loaded: vec!["guid-b".to_string()],
+            }]
+        );
+        let txn = d1.transact();
+        assert_eq!(d1.subdocs(&txn), vec![SubDoc::new("guid-b".to_string())]);
+    }
+
+    #[test]
+    fn subdocs_remote() {
+        let d1 = Doc::with_client_id(1);
+        let update = {
+            let mut txn = d1.transact();
+            let pages = txn.get_array("pages");
+            pages.push_back(&mut txn, SubDoc::auto_loaded("guid-a".to_string()));
+            pages.push_back(&mut txn, SubDoc::new("guid-b".to_string()));
+            d1.encode_state_as_update_v1(&txn)
+        };
+
+        let mut d2 = Doc::with_client_id(2);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let e = events.clone();
+        let _sub = d2.on_subdocs(move |event| e.borrow_mut().push(event.clone()));
+        {
+            let mut txn = d2.transact();
+            let pages = txn.get_array("pages");
+            d2.apply_update_v1(&mut txn, &update).unwrap();
+            let guids: Vec<_> = pages
+                .iter(&txn)
+                .map(|v| SubDoc::try_from(v).unwrap().guid().to_string())
+                .collect();
+            assert_eq!(guids, vec!["guid-a", "guid-b"]);
+        }
+        assert_eq!(
+            events.borrow().as_slice(),
+            &[SubdocsEvent {
+                added: vec!["guid-a".to_string(), "guid-b".to_string()],
+                removed: vec![],
+                loaded: vec!["guid-a".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn subdocs_compatibility() {
+        /* Result of calling following code:
+        ```javascript
+        const doc = new Y.Doc()
+        doc.clientID = 1
+        doc.getMap('map').set('sub', new Y.Doc({ guid: 'abc' }))
+        const update = Y.encodeStateAsUpdate(doc)
+        ```
+         */
+        let update = &[
+            1, 1, 1, 0, 41, 1, 3, 109, 97, 112, 3, 115, 117, 98, 3, 97, 98, 99, 118, 0, 0,
+        ];
+        let doc = Doc::with_client_id(2);
+        let mut txn = doc.transact();
+        let map = txn.get_map("map");
+        doc.apply_update_v1(&mut txn, update).unwrap();
+        assert_eq!(
+            map.get(&txn, "sub"),
+            Some(Value::YDoc(SubDoc::new("abc".to_string())))
+        );
+        assert_eq!(doc.subdocs(&txn), vec![SubDoc::new("abc".to_string())]);
+
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+        m1.insert(&mut t1, "sub".to_string(), SubDoc::new("abc".to_string()));
+        assert_eq!(d1.encode_state_as_update_v1(&t1), update.to_vec());
+    }
+
+    #[test]
+    fn doc_guid() {
+        let d1 = Doc::new();
+        let d2 = Doc::with_client_id(1);
+        assert_ne!(d1.guid, d2.guid);
+        assert_eq!(d1.guid.len(), 36);
+        assert_eq!(d1.collection_id, None);
+        assert_eq!(d1.meta, None);
+        assert!(!d1.auto_load);
+    }
+
+    #[test]
+    fn doc_options_in_subdoc() {
+        let mut meta = HashMap::new();
+        meta.insert("title".to_string(), Any::String("Page 1".to_string()));
+        let page = Doc::with_options(Options {
+            guid: "page-1".to_string(),
+            collection_id: Some("pages".to_string()),
+            meta: Some(Any::Map(meta.clone())),
+            auto_load: true,
+            ..Options::with_client_id(3)
+        });
+        assert_eq!(page.client_id, 3);
+        assert_eq!(page.guid, "page-1");
+        assert_eq!(page.collection_id.as_deref(), Some("pages"));
+
+        let d1 = Doc::with_client_id(1);
+        let update = {
+            let mut txn = d1.transact();
+            let pages = txn.get_array("pages");
+            pages.push_back(&mut txn, &page);
+            d1.encode_state_as_update_v1(&txn)
+        };
+
+        let d2 = Doc::with_client_id(2);
+        let mut txn = d2.transact();
+        let pages = txn.get_array("pages");
+        d2.apply_update_v1(&mut txn, &update).unwrap();
+        let subdoc = SubDoc::try_from(pages.get(&txn, 0).unwrap()).unwrap();
+        assert_eq!(subdoc.guid(), "page-1");
+        assert_eq!(subdoc.meta(), Some(&Any::Map(meta)));
+        assert!(subdoc.should_load());
+        assert_eq!(d2.subdocs(&txn), vec![subdoc]);
+    }
+
+    #[test]
+    fn fork() {
+        let d1 = Doc::with_options(Options {
+            collection_id: Some("docs".to_string()),
+            ..Options::with_client_id(1)
+        });
+        let mut t1 = d1.transact();
+        let txt1 = t1.get_text("text");
+        txt1.insert(&mut t1, 0, "hello world");
+        txt1.remove_range(&mut t1, 5, 6);
+        t1.get_map("empty");
+
+        let d2 = d1.fork(&t1);
+        assert_ne!(d2.client_id, d1.client_id);
+        assert_eq!(d2.guid, d1.guid);
+        assert_eq!(d2.collection_id, d1.collection_id);
+
+        let mut t2 = d2.transact();
+        assert_eq!(d2.get_state_vector(&t2), d1.get_state_vector(&t1));
+        assert_eq!(
+            t2.store
+                .types
+                .get(&"empty".to_string())
+                .unwrap()
+                .borrow()
+                .type_ref(),
+            TYPE_REFS_MAP
+        );
+        assert_eq!(
+            t2.store
+                .types
+                .get(&"text".to_string())
+                .unwrap()
+                .borrow()
+                .type_ref(),
+            TYPE_REFS_TEXT
+        );
+        let txt2 = t2.get_text("text");
+        assert_eq!(txt2.to_string(&t2), "hello");
+
+        // changes made to a fork don't affect the original
+        txt2.push(&mut t2, " fork");
+        assert_eq!(txt1.to_string(&t1), "hello");
+
+        // both documents can still be synced
+        txt1.push(&mut t1, "!");
+        let u1 = d1.encode_delta_as_update_v1(&t1, &d2.get_state_vector(&t2));
+        let u2 = d2.encode_delta_as_update_v1(&t2, &d1.get_state_vector(&t1));
+        d2.apply_update_v1(&mut t2, &u1).unwrap();
+        d1.apply_update_v1(&mut t1, &u2).unwrap();
+        assert_eq!(txt1.to_string(&t1), txt2.to_string(&t2));
+        assert_eq!(txt1.len(), 11);
+    }
+
+    #[test]
+    fn encode_state_filtered() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let content = t1.get_text("content");
+        let private = t1.get_map("private");
+        let meta = t1.get_map("meta");
+        // interleave changes of different root types within the same client clock range
+        content.insert(&mut t1, 0, "hello");
+        private.insert(&mut t1, "secret".to_string(), "password");
+        content.push(&mut t1, " world");
+        meta.insert(
+            &mut t1,
+            "nested".to_string(),
+            PrelimMap::<Any>::from(HashMap::new()),
+        );
+        private.remove(&mut t1, "secret");
+        content.remove_range(&mut t1, 0, 1);
+
+        let update = t1.encode_state_filtered_v1(&StateVector::default(), &["content", "meta"]);
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &update).unwrap();
+        let mut roots: Vec<_> = t2.store.types.keys().map(|k| k.as_str()).collect();
+        roots.sort();
+        assert_eq!(roots, vec!["content", "meta"]);
+        assert_eq!(t2.get_text("content").to_string(&t2), "ello world");
+        assert!(t2.get_map("meta").contains(&t2, "nested"));
+        // omitted blocks are treated as garbage collected
+        assert_eq!(d2.get_state_vector(&t2), d1.get_state_vector(&t1));
+
+        // filtered updates can be computed incrementally
+        content.push(&mut t1, "!");
+        private.insert(&mut t1, "secret".to_string(), "another");
+        let update = t1.encode_state_filtered_v1(&d2.get_state_vector(&t2), &["content", "meta"]);
+        d2.apply_update_v1(&mut t2, &update).unwrap();
+        assert_eq!(t2.get_text("content").to_string(&t2), "ello world!");
+        assert!(!t2.store.types.contains_key(&"private".to_string()));
+    }
+
+    #[test]
+    fn encode_state_filtered_split_origin() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let content = t1.get_text("content");
+        let private = t1.get_text("private");
+        content.insert(&mut t1, 0, "abc");
+        private.insert(&mut t1, 0, "xyz");
+
+        // sync only the beginning of "content" text
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let sv = StateVector::default();
+        d2.apply_update_v1(&mut t2, &t1.encode_state_filtered_v1(&sv, &["content"]))
+            .unwrap();
+        assert_eq!(d2.get_state_vector(&t2).get(&1), 3);
+
+        // a state vector may point into the middle of a block, which then is encoded with offset
+        content.push(&mut t1, "def");
+        private.push(&mut t1, "!");
+        content.push(&mut t1, "g");
+        let sv = d2.get_state_vector(&t2);
+        d2.apply_update_v1(&mut t2, &t1.encode_state_filtered_v1(&sv, &["content"]))
+            .unwrap();
+        assert_eq!(t2.get_text("content").to_string(&t2), "abcdefg");
+
+        // blocks of other root types are covered by a state vector of a remote peer, even though it
+        // never received them: they won't be delivered by following full updates either
+        let sv = d2.get_state_vector(&t2);
+        assert_eq!(sv, d1.get_state_vector(&t1));
+        d2.apply_update_v1(&mut t2, &d1.encode_delta_as_update_v1(&t1, &sv))
+            .unwrap();
+        assert_eq!(t2.get_text("private").to_string(&t2), "");
+        assert_eq!(t1.get_text("private").to_string(&t1), "xyz!");
+    }
+
+    #[test]
+    fn observe_update_for_roots() {
+        fn collect(updates: &Rc<RefCell<Vec<Vec<u8>>>>) -> impl Fn(&[u8]) + 'static {
+            let updates = updates.clone();
+            move |update| updates.borrow_mut().push(update.to_vec())
+        }
+        fn replay(updates: &[Vec<u8>]) -> Doc {
+            let doc = Doc::with_client_id(2);
+            let mut txn = doc.transact();
+            for update in updates {
+                doc.apply_update_v1(&mut txn, update).unwrap();
+            }
+            assert!(txn.store.pending.is_none());
+            assert!(txn.store.pending_ds.is_none());
+            drop(txn);
+            doc
+        }
+        fn roots(doc: &Doc) -> Vec<String> {
+            let txn = doc.transact();
+            let mut roots: Vec<_> = txn.store.types.keys().map(|k| k.to_string()).collect();
+            roots.sort();
+            roots
+        }
+
+        let mut d1 = Doc::with_client_id(1);
+        let chat_updates = Rc::new(RefCell::new(Vec::new()));
+        let document_updates = Rc::new(RefCell::new(Vec::new()));
+        let other_updates = Rc::new(RefCell::new(Vec::new()));
+        let _s1 = d1.observe_update_for_roots(&["chat"], collect(&chat_updates));
+        let _s2 = d1.observe_update_for_roots(&["document"], collect(&document_updates));
+        let _s3 = d1.observe_update_except_roots(&["chat", "document"], collect(&other_updates));
+
+        {
+            // interleave changes of different root types within the same transaction
+            let mut t1 = d1.transact();
+            let chat = t1.get_array("chat");
+            chat.push_back(&mut t1, "hi");
+            t1.get_text("document").insert(&mut t1, 0, "hello");
+            chat.push_back(&mut t1, "how are you?");
+        }
+        {
+            let mut t1 = d1.transact();
+            t1.get_map("meta")
+                .insert(&mut t1, "title".to_string(), "notes");
+        }
+        {
+            let mut t1 = d1.transact();
+            t1.get_array("chat").push_back(&mut t1, "fine");
+        }
+        {
+            // deletion of blocks created by previous transactions
+            let mut t1 = d1.transact();
+            t1.get_array("chat").remove_range(&mut t1, 0, 1);
+        }
+        {
+            let mut t1 = d1.transact();
+            let document = t1.get_text("document");
+            document.push(&mut t1, " world");
+            document.remove_range(&mut t1, 0, 1);
+        }
+        assert_eq!(chat_updates.borrow().len(), 3);
+        assert_eq!(document_updates.borrow().len(), 2);
+        assert_eq!(other_updates.borrow().len(), 1);
+
+        let mut t1 = d1.transact();
+        let chat = t1.get_array("chat").to_json(&t1);
+        let document = t1.get_text("document").to_string(&t1);
+        let meta = t1.get_map("meta").to_json(&t1);
+        drop(t1);
+
+        // blocks of other root types are skipped, so a stream alone leaves the blocks following
+        // them pending
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        for update in chat_updates.borrow().iter() {
+            d2.apply_update_v1(&mut t2, update).unwrap();
+        }
+        assert!(t2.store.pending.is_some());
+        assert_ne!(t2.get_array("chat").to_json(&t2), chat);
+        drop(t2);
+
+        let streams = [chat_updates, document_updates, other_updates];
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        let t1 = d1.transact();
+        let state = d1.get_state_vector(&t1);
+        drop(t1);
+        for order in orders {
+            // streams applied one after another in any order contain all changes
+            let all: Vec<Vec<u8>> = order
+                .iter()
+                .flat_map(|&i| streams[i].borrow().clone())
+                .collect();
+            let merged = {
+                let all: Vec<&[u8]> = all.iter().map(|u| u.as_slice()).collect();
+                merge_updates(&all).unwrap()
+            };
+            for d in [replay(&all), replay(&[merged])] {
+                assert_eq!(roots(&d), vec!["chat", "document", "meta"]);
+                let mut txn = d.transact();
+                assert_eq!(txn.get_array("chat").to_json(&txn), chat);
+                assert_eq!(txn.get_text("document").to_string(&txn), document);
+                assert_eq!(txn.get_map("meta").to_json(&txn), meta);
+                assert_eq!(d.get_state_vector(&txn), state);
+            }
+        }
+    }
+
+    #[test]
+    fn observe_update_for_untouched_roots() {
+        let mut d1 = Doc::with_client_id(1);
+        let updates = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let updates = updates.clone();
+            d1.observe_update_for_roots(&["chat"], move |u| updates.borrow_mut().push(u.to_vec()))
+        };
+        for i in 0..100 {
+            let mut t1 = d1.transact();
+            t1.get_text("document").push(&mut t1, &i.to_string());
+        }
+        assert!(updates.borrow().is_empty());
+        let synced = d1.encode_state_as_update_v1(&d1.transact());
+        for msg in ["hi", "there"] {
+            let mut t1 = d1.transact();
+            t1.get_array("chat").push_back(&mut t1, msg);
+            t1.get_text("document").push(&mut t1, "!");
+        }
+        let updates = updates.borrow();
+        assert_eq!(updates.len(), 2);
+
+        // skipped blocks are covered by a single placeholder
+        let update = Update::decode_v1(&updates[0]).unwrap();
+        let blocks: Vec<_> = update.blocks().collect();
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(blocks[0], Block::Skip(_)));
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &synced).unwrap();
+        for update in updates.iter() {
+            d2.apply_update_v1(&mut t2, update).unwrap();
+        }
+        // the second message waits for a document change made before it
+        assert!(t2.store.pending.is_some());
+        assert_eq!(
+            t2.get_array("chat").to_json(&t2),
+            Any::Array(vec![Any::String("hi".to_owned())].into())
+        );
+
+        let update = d1.encode_delta_as_update_v1(&d1.transact(), &d2.get_state_vector(&t2));
+        d2.apply_update_v1(&mut t2, &update).unwrap();
+        assert!(t2.store.pending.is_none());
+        assert_eq!(
+            t2.get_array("chat").to_json(&t2),
+            Any::Array(
+                vec![
+                    Any::String("hi".to_owned()),
+                    Any::String("there".to_owned())
+                ]
+                .into()
+            )
+        );
+        assert!(t2.get_text("document").to_string(&t2).ends_with("99!!"));
+    }
+
+    #[test]
+    fn try_apply_update_limits() {
+        let options = DecoderOptions::default();
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+
+        // update declaring 2^32-1 clients
+        let payload = [0xff, 0xff, 0xff, 0xff, 0x0f, 0];
+        assert_eq!(
+            doc.try_apply_update_v1(&mut txn, &payload, &options),
+            Err(Error::LimitExceeded(DecodeError::CollectionTooLong {
+                len: u32::MAX as u64,
+                max: options.max_collection_len
+            }))
+        );
+
+        // single string block (client: 1, clock: 0, parent: "t") declaring 1GB of text
+        let payload = [
+            1, 1, 1, 0, 4, 1, 1, b't', 0x80, 0x80, 0x80, 0x80, 0x04, b'a',
+        ];
+        assert_eq!(
+            doc.try_apply_update_v1(&mut txn, &payload, &options),
+            Err(Error::LimitExceeded(DecodeError::BufferTooLong {
+                len: 1 << 30,
+                max: options.max_buf_len
+            }))
+        );
+        assert_eq!(
+            doc.try_apply_update_v1(&mut txn, &payload, &DecoderOptions::unlimited()),
+            Err(Error::UnexpectedEof)
+        );
+
+        // delete set declaring 2^32-1 ranges for a single client
+        let payload = [0, 1, 1, 0xff, 0xff, 0xff, 0xff, 0x0f];
+        let strict = DecoderOptions {
+            max_total_items: 1000,
+            ..DecoderOptions::default()
+        };
+        assert!(doc
+            .try_apply_update_v1(&mut txn, &payload, &strict)
+            .is_err());
+        assert!(txn.store.blocks.is_empty());
+    }
+
+    #[test]
+    fn try_apply_update_truncated() {
+        let source = Doc::with_client_id(1);
+        let mut t1 = source.transact();
+        let txt = t1.get_text("text");
+        txt.insert(&mut t1, 0, "hello world");
+        txt.remove_range(&mut t1, 0, 6);
+        let mut inner = HashMap::new();
+        inner.insert(
+            "key".to_string(),
+            Any::Array(vec![Any::Null, Any::Bool(true)]),
+        );
+        t1.get_map("map")
+            .insert(&mut t1, "nested".to_string(), PrelimMap::from(inner));
+        let update = source.encode_state_as_update_v1(&t1);
+
+        let options = DecoderOptions::default();
+        for i in 0..update.len() {
+            let doc = Doc::with_client_id(2);
+            let mut txn = doc.transact();
+            assert!(doc
+                .try_apply_update_v1(&mut txn, &update[..i], &options)
+                .is_err());
+        }
+
+        let doc = Doc::with_client_id(2);
+        let mut txn = doc.transact();
+        doc.try_apply_update_v1(&mut txn, &update, &options)
+            .unwrap();
+        assert!(source.eq_content(&t1, &doc, &txn));
+
+        let sv = source.encode_state_vector_v1(&t1);
+        assert_eq!(
+            StateVector::try_decode_v1(&sv, &options),
+            Ok(source.get_state_vector(&t1))
+        );
+        assert_eq!(
+            StateVector::try_decode_v1(&sv[..sv.len() - 1], &options),
+            Err(Error::UnexpectedEof)
+        );
+        assert!(StateVector::try_decode_v1(&[0xff, 0xff, 0xff, 0xff, 0x0f], &options).is_err());
+    }
+
+    #[test]
+    fn apply_update_lazy() {
+        // client 3 depends on blocks of client 1, but its blocks are encoded first
+        let d1 = Doc::with_client_id(1);
+        let d3 = Doc::with_client_id(3);
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("text").insert(&mut t1, 0, "abc");
+            t1.get_map("map").insert(&mut t1, "key".to_owned(), 1);
+            let mut t3 = d3.transact();
+            d3.apply_update_v1(&mut t3, &d1.encode_state_as_update_v1(&t1))
+                .unwrap();
+            t3.get_text("text").insert(&mut t3, 1, "xyz");
+            t3.get_text("text").remove_range(&mut t3, 0, 2);
+            t3.get_map("map").insert(&mut t3, "key".to_owned(), 3);
+        }
+        let t3 = d3.transact();
+        let update = d3.encode_state_as_update_v1(&t3);
+
+        let eager = Doc::with_client_id(4);
+        let mut t4 = eager.transact();
+        eager.apply_update_v1(&mut t4, &update).unwrap();
+        let lazy = Doc::with_client_id(5);
+        let mut t5 = lazy.transact();
+        lazy.apply_update_v1_lazy(&mut t5, &update).unwrap();
+
+        assert_eq!(lazy.get_state_vector(&t5), eager.get_state_vector(&t4));
+        assert_eq!(t5.store_stats().pending_blocks, 0);
+        assert_eq!(
+            t5.get_text("text").to_string(&t5),
+            t4.get_text("text").to_string(&t4)
+        );
+        assert_eq!(
+            t5.get_map("map").to_json(&t5),
+            t4.get_map("map").to_json(&t4)
+        );
+        assert_eq!(lazy.delete_set(&t5), eager.delete_set(&t4));
+
+        // an update is validated before anything is integrated or a client id is replaced
+        let mut doc = Doc::with_options(Options {
+            auto_new_client_id_on_conflict: true,
+            ..Options::with_client_id(3)
+        });
+        let events = Rc::new(Cell::new(0));
+        let _sub = {
+            let events = events.clone();
+            doc.on_client_id_change(move |_| events.set(events.get() + 1))
+        };
+        let mut txn = doc.transact();
+        let truncated = &update[..update.len() - 4];
+        assert!(doc.apply_update_v1_lazy(&mut txn, truncated).is_err());
+        assert!(txn.store.blocks.is_empty());
+        assert_eq!(doc.get_state_vector(&txn), StateVector::default());
+        assert_eq!(txn.client_id(), 3);
+        assert_eq!(events.get(), 0);
+    }
+
+    #[test]
+    fn from_update() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        for (doc, chunk) in [(&d1, "abc"), (&d2, "xyz")] {
+            let mut txn = doc.transact();
+            txn.get_text("text").insert(&mut txn, 0, chunk);
+            txn.get_map("map").insert(&mut txn, chunk.to_owned(), 1.0);
+            txn.get_array("array").push_back(&mut txn, chunk);
+        }
+        exchange_updates(&[&d1, &d2]);
+        let update = {
+            let mut txn = d1.transact();
+            txn.get_text("text").remove_range(&mut txn, 1, 3);
+            d1.encode_state_as_update_v1(&txn)
+        };
+
+        let expected = Doc::with_client_id(1);
+        let mut t1 = expected.transact();
+        expected.apply_update_v1(&mut t1, &update).unwrap();
+
+        // own blocks of a loaded document are not treated as a client id conflict
+        let options = Options {
+            auto_new_client_id_on_conflict: true,
+            ..Options::with_client_id(1)
+        };
+        let loaded = Doc::from_update_v1(&update, options).unwrap();
+        let mut t2 = loaded.transact();
+        assert_eq!(t2.client_id(), 1);
+        assert_eq!(loaded.get_state_vector(&t2), expected.get_state_vector(&t1));
+        assert_eq!(loaded.delete_set(&t2), expected.delete_set(&t1));
+        assert_eq!(
+            t2.get_text("text").to_string(&t2),
+            t1.get_text("text").to_string(&t1)
+        );
+        assert_eq!(
+            t2.get_map("map").to_json(&t2),
+            t1.get_map("map").to_json(&t1)
+        );
+        assert_eq!(
+            t2.get_array("array").to_json(&t2),
+            t1.get_array("array").to_json(&t1)
+        );
+        drop(t2);
+
+        // an update missing its dependencies keeps them pending
+        let delta = {
+            let mut txn = d2.transact();
+            txn.get_text("text").insert(&mut txn, 0, "!");
+            d2.encode_delta_as_update_v1(&txn, &d1.get_state_vector(&d1.transact()))
+        };
+        let partial = Doc::from_update_v1(&delta, Options::with_client_id(3)).unwrap();
+        let mut txn = partial.transact();
+        assert!(txn.store_stats().pending_blocks > 0);
+        assert_eq!(txn.get_text("text").to_string(&txn), "");
+
+        assert!(Doc::from_update_v1(&update[..update.len() - 1], Options::default()).is_err());
+    }
+
+    #[test]
+    fn record_timestamps() {
+        let d1 = Doc::with_options(Options {
+            record_timestamps: true,
+            ..Options::with_client_id(1)
+        });
+        let d2 = Doc::with_client_id(2);
+        let before = unix_millis();
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "abc");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let between = unix_millis();
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "de");
+            // changes of peers which don't record timestamps are not timestamped
+            let mut txn = d2.transact();
+            txn.get_text("text").push(&mut txn, "xyz");
+        }
+        exchange_updates(&[&d1, &d2]);
+        let after = unix_millis() + 1.0;
+
+        for doc in [&d1, &d2] {
+            let txn = doc.transact();
+            let edits = doc.edit_timestamps(&txn);
+            assert_eq!(edits.len(), 2);
+            // the second transaction starts after a timestamp entry of the first one
+            assert_eq!(edits[0].clock, 0..3);
+            assert_eq!(edits[1].clock, 4..6);
+
+            let first = doc.timestamp_for(&txn, &ID::new(1, 2)).unwrap();
+            assert!(first >= before && first < between);
+            let second = doc.timestamp_for(&txn, &ID::new(1, 5)).unwrap();
+            assert!(second >= between && second < after);
+            assert_eq!(doc.timestamp_for(&txn, &ID::new(1, 3)), None);
+            assert_eq!(doc.timestamp_for(&txn, &ID::new(2, 0)), None);
+
+            let edits = doc.edits_between(&txn, between, after);
+            assert_eq!(edits, HashMap::from([(1, vec![4..6])]));
+            let edits = doc.edits_between(&txn, before, after);
+            assert_eq!(edits, HashMap::from([(1, vec![0..3, 4..6])]));
+        }
+    }
+
+    #[test]
+    fn transaction_stats() {
+        let mut d1 = Doc::with_client_id(1);
+        let mut d2 = Doc::with_client_id(2);
+        let stats = Rc::new(RefCell::new(Vec::new()));
+        let _s1 = {
+            let stats = stats.clone();
+            d1.on_after_transaction(move |_, e| stats.borrow_mut().push(e.stats))
+        };
+        let _s2 = {
+            let stats = stats.clone();
+            d2.on_after_transaction(move |_, e| stats.borrow_mut().push(e.stats))
+        };
+
+        let update = {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.push(&mut txn, "hello");
+            let array = txn.get_array("array");
+            array.insert_range(&mut txn, 0, [1, 2, 3]);
+            let s = txn.stats();
+            assert_eq!(s.inserted_blocks, 2);
+            assert_eq!(s.added_len, 8);
+            assert_eq!(s.update_len, txn.encode_update_v1().len());
+
+            text.remove_range(&mut txn, 1, 2);
+            txn.encode_update_v1()
+        };
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").remove_range(&mut txn, 0, 1);
+        }
+        {
+            let mut txn = d2.transact();
+            d2.apply_update_v1(&mut txn, &update).unwrap();
+        }
+
+        let stats = stats.borrow();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].inserted_blocks, 2);
+        assert_eq!(stats[0].integrated_blocks, 0);
+        assert_eq!(stats[0].deleted_blocks, 1);
+        assert_eq!((stats[0].added_len, stats[0].removed_len), (8, 2));
+        assert_eq!(stats[0].update_len, update.len());
+
+        assert_eq!(stats[1].inserted_blocks, 0);
+        assert_eq!(stats[1].deleted_blocks, 1);
+        assert_eq!((stats[1].added_len, stats[1].removed_len), (0, 1));
+
+        // blocks are integrated as they were sent, before being squashed by the sender
+        assert_eq!(stats[2].inserted_blocks, 0);
+        assert_eq!(stats[2].integrated_blocks, 4);
+        assert_eq!(stats[2].deleted_blocks, 1);
+        assert_eq!((stats[2].added_len, stats[2].removed_len), (8, 2));
+    }
+
+    #[test]
+    fn integrity_check() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "hello");
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".to_owned(), PrelimArray::from([1, 2]));
+            map.insert(&mut txn, "b".to_owned(), true);
+        }
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text").push(&mut txn, "world");
+            txn.get_map("map").insert(&mut txn, "b".to_owned(), false);
+        }
+        exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").remove_range(&mut txn, 3, 4);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let (t1, t2) = (d1.transact(), d2.transact());
+        assert_eq!(d1.integrity_check(&t1), vec![]);
+        assert_eq!(d2.integrity_check(&t2), vec![]);
+        let dump = t1.dump_structs();
+        assert_eq!(dump, t2.dump_structs());
+        assert!(dump.starts_with(
+            "<1#0> len=3 String parent='text' origin=- right_origin=- \"hel\"\n\
+             <1#3> len=2 Deleted parent='text' origin=<1#2> right_origin=- deleted\n"
+        ));
+        drop((t1, t2));
+
+        // blocks of crafted updates are loaded as they are, without being integrated
+        let (update, sv) = {
+            let txn = d1.transact();
+            (
+                d1.encode_state_as_update_v1(&txn),
+                d1.get_state_vector(&txn),
+            )
+        };
+        let d3 = Doc::with_client_id(3);
+        *d3.store.borrow_mut() = Update::decode_v1(&update).unwrap().into();
+        {
+            let txn = d3.transact();
+            let violations = d3.integrity_check(&txn);
+            // root types have never been initialized and nested array is not linked to its items
+            assert!(violations.contains(&IntegrityViolation::MissingParent(ID::new(1, 0))));
+            assert!(violations.contains(&IntegrityViolation::MissingParent(ID::new(2, 5))));
+            assert!(violations.contains(&IntegrityViolation::BrokenParentLink(ID::new(1, 6))));
+        }
+
+        let update = {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "!");
+            d1.encode_delta_as_update_v1(&txn, &sv)
+        };
+        *d3.store.borrow_mut() = Update::decode_v1(&update).unwrap().into();
+        {
+            let txn = d3.transact();
+            let violations = d3.integrity_check(&txn);
+            assert_eq!(
+                violations,
+                vec![
+                    IntegrityViolation::ClockGap {
+                        client: 1,
+                        expected: 0,
+                        found: 9
+                    },
+                    IntegrityViolation::MissingOrigin(ID::new(2, 4), ID::new(1, 9)),
+                    IntegrityViolation::MissingParent(ID::new(1, 9)),
+                ]
+            );
+        }
+
+        // block replaced with its copy decoded from an update, which has never been linked with
+        // its neighbours
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        let mut decoded: Store = Update::decode_v1(&update).unwrap().into();
+        let decoded = decoded.blocks.get_mut(&1).unwrap();
+        let mut txn = d1.transact();
+        let blocks = txn.store.blocks.get_mut(&1).unwrap();
+        assert_eq!(decoded[1].id(), blocks[1].id());
+        std::mem::swap(&mut blocks[1], &mut decoded[1]);
+        assert_eq!(
+            d1.integrity_check(&txn),
+            vec![
+                IntegrityViolation::BrokenRightLink(ID::new(1, 0)),
+                IntegrityViolation::MissingParent(ID::new(1, 3)),
+                IntegrityViolation::BrokenLeftLink(ID::new(2, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_changes() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let sync = |d1: &Doc, d2: &Doc| {
+            exchange_updates(&[d1, d2]);
+            let sv = d2.get_state_vector(&d2.transact());
+            let mut txn = d1.transact();
+            d1.mark_synced(&mut txn, &sv);
+            assert!(!d1.is_dirty(&txn));
+            assert!(!d1.has_pending_changes_for(&txn, &sv));
+            sv
+        };
+        let edit = |d: &Doc, f: &dyn Fn(&mut Transaction, Text)| {
+            let mut txn = d.transact();
+            let text = txn.get_text("text");
+            f(&mut txn, text);
+        };
+
+        edit(&d1, &|txn, text| text.push(txn, "hello"));
+        {
+            let txn = d1.transact();
+            assert!(d1.is_dirty(&txn));
+            assert!(d1.has_pending_changes_for(&txn, &StateVector::default()));
+        }
+        let sv = sync(&d1, &d2);
+
+        // marking as synced with an outdated state vector keeps local changes dirty
+        edit(&d1, &|txn, text| text.push(txn, "!"));
+        {
+            let mut txn = d1.transact();
+            d1.mark_synced(&mut txn, &sv);
+            assert!(d1.is_dirty(&txn));
+            assert!(d1.has_pending_changes_for(&txn, &sv));
+        }
+        let sv = sync(&d1, &d2);
+
+        // remote changes covered by a state vector don't make a document dirty
+        edit(&d2, &|txn, text| text.push(txn, " world"));
+        exchange_updates(&[&d1, &d2]);
+        {
+            let txn = d1.transact();
+            assert!(!d1.is_dirty(&txn));
+            assert!(d1.has_pending_changes_for(&txn, &sv));
+            let sv = d2.get_state_vector(&d2.transact());
+            assert!(!d1.has_pending_changes_for(&txn, &sv));
+        }
+        let sv = sync(&d1, &d2);
+
+        // deletions don't advance clocks
+        edit(&d1, &|txn, text| text.remove_range(txn, 0, 1));
+        {
+            let txn = d1.transact();
+            assert!(d1.is_dirty(&txn));
+            assert!(!d1.has_changes_since(&txn, &sv));
+            assert!(d1.has_pending_changes_for(&txn, &sv));
+        }
+        sync(&d1, &d2);
+        let mut txn = d2.transact();
+        assert_eq!(txn.get_text("text").to_string(&txn), "ello! world");
+    }
+
+    #[test]
+    fn changes_since() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        for (doc, chunk) in [(&d1, "abc"), (&d2, "de")] {
+            let mut txn = doc.transact();
+            txn.get_text("text").push(&mut txn, chunk);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let mut txn = d1.transact();
+        let backup = d1.get_state_vector(&txn);
+        assert!(!d1.has_changes_since(&txn, &backup));
+        assert_eq!(d1.changes_since(&txn, &backup), vec![]);
+
+        // deletions alone don't create new blocks
+        txn.get_text("text").remove_range(&mut txn, 0, 1);
+        assert!(!d1.has_changes_since(&txn, &backup));
+
+        txn.get_text("text").push(&mut txn, "fg");
+        assert!(d1.has_changes_since(&txn, &backup));
+        assert_eq!(d1.changes_since(&txn, &backup), vec![(1, 3, 5)]);
+        drop(txn);
+
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text").insert(&mut txn, 0, "x");
+        }
+        exchange_updates(&[&d1, &d2]);
+        let txn = d1.transact();
+        assert_eq!(d1.changes_since(&txn, &backup), vec![(1, 3, 5), (2, 2, 3)]);
+        assert_eq!(
+            d1.changes_since(&txn, &StateVector::default()),
+            vec![(1, 0, 5), (2, 0, 3)]
+        );
+        assert!(!d1.has_changes_since(&txn, &d1.get_state_vector(&txn)));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_update_roundtrip() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let txt = txn.get_text("text");
+        for i in 0..1000u32 {
+            txt.insert(&mut txn, i * 12, "lorem ipsum ");
+        }
+        let raw = doc.encode_state_as_update_v1(&txn);
+        let compressed = doc.encode_state_as_update_compressed(&txn);
+        assert!(compressed.len() * 5 < raw.len());
+        assert_eq!(crate::decompress_update(&compressed), Ok(raw.clone()));
+        assert!(crate::decompress_update(&raw).is_err());
+
+        // both compressed and raw payloads are accepted
+        for update in [compressed, raw].iter() {
+            let remote = Doc::with_client_id(2);
+            let mut t2 = remote.transact();
+            remote.apply_update_auto(&mut t2, update).unwrap();
+            let expected = txt.to_string(&txn);
+            assert_eq!(t2.get_text("text").to_string(&t2), expected);
+        }
+
+        // payload with a compression header, but malformed content
+        let remote = Doc::with_client_id(2);
+        let mut t2 = remote.transact();
+        assert!(remote.apply_update_auto(&mut t2, &[0xff, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn full_state_roundtrip_with_concurrent_edits() {
+        // concurrent inserts with origins pointing into the middle of squashed blocks must be
+        // integrated at the same position regardless of the order of integration
+        let docs: Vec<_> = (1..=3).map(Doc::with_client_id).collect();
+        for round in 0..10u32 {
+            for (i, doc) in docs.iter().enumerate() {
+                let mut txn = doc.transact();
+                let txt = txn.get_text("text");
+                let index = (round * 7 + i as u32) % (txt.len() + 1);
+                txt.insert(&mut txn, index, "hello ");
+                if txt.len() > 10 && round % 3 == 0 {
+                    txt.remove_range(&mut txn, index / 2, 3);
+                }
+            }
+            let updates: Vec<_> = docs
+                .iter()
+                .map(|doc| doc.encode_state_as_update_v1(&doc.transact()))
+                .collect();
+            for doc in docs.iter() {
+                let mut txn = doc.transact();
+                for update in updates.iter() {
+                    doc.apply_update_v1(&mut txn, update).unwrap();
+                }
+            }
+            for doc in docs.iter() {
+                let mut txn = doc.transact();
+                let expected = txn.get_text("text").to_string(&txn);
+                let fresh = Doc::new();
+                let mut t2 = fresh.transact();
+                fresh
+                    .apply_update_v1(&mut t2, &doc.encode_state_as_update_v1(&txn))
+                    .unwrap();
+                assert_eq!(t2.get_text("text").to_string(&t2), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn conflict_with_origin_inside_block() {
+        // A3 has origin A1, which is a non-first element of block A0..1. When C0 is integrated
+        // after A, it has to recognize A3 as inserted after an item it already scanned, as Yjs
+        // does by resolving origins to their containing items.
+        let d1 = Doc::with_client_id(1);
+        let d3 = Doc::with_client_id(3);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "aaa");
+        }
+        {
+            let mut txn = d3.transact();
+            txn.get_text("text").insert(&mut txn, 0, "ccc");
+        }
+        exchange_updates(&[&d1, &d3]);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 2, "aaa");
+        }
+        exchange_updates(&[&d1, &d3]);
+
+        let expected = "aaaaaaccc";
+        for doc in [&d1, &d3] {
+            let mut txn = doc.transact();
+            assert_eq!(txn.get_text("text").to_string(&txn), expected);
+        }
+
+        // full state integrates blocks of client 3 first, then squashed blocks of client 1
+        let fresh = Doc::new();
+        let mut txn = fresh.transact();
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        fresh.apply_update_v1(&mut txn, &update).unwrap();
+        assert_eq!(txn.get_text("text").to_string(&txn), expected);
+    }
+
+    #[test]
+    fn apply_update_malformed() {
+        let doc = Doc::with_client_id(2);
+        let mut txn = doc.transact();
+
+        // update truncated in the middle of a block
+        let payload = [1, 1, 1, 0, 4, 1, 1, b't', 3, b'a'];
+        assert_eq!(
+            doc.apply_update_v1(&mut txn, &payload),
+            Err(Error::UnexpectedEof)
+        );
+
+        // string content (client: 1, clock: 0, parent: "t") with invalid UTF-8 sequence
+        let payload = [1, 1, 1, 0, 4, 1, 1, b't', 2, 0xc3, 0x28, 0];
+        assert_eq!(
+            doc.apply_update_v1(&mut txn, &payload),
+            Err(Error::InvalidUtf8)
+        );
+
+        // block with unknown content type tag, even if its payload could be read as a buffer
+        let payload = [1, 1, 1, 0, 11, 1, 1, b't', 3, b'x', b'y', b'z', 0];
+        assert_eq!(
+            doc.apply_update_v1(&mut txn, &payload),
+            Err(Error::UnsupportedContent(11))
+        );
+
+        // content type tag using 5th bit, which must not be mistaken for deleted content (1)
+        let payload = [1, 1, 1, 0, 17, 1, 1, b't', 3, 0];
+        assert_eq!(
+            doc.apply_update_v1(&mut txn, &payload),
+            Err(Error::UnsupportedContent(17))
+        );
+
+        // type content with unknown shared type ref
+        let payload = [1, 1, 1, 0, 7, 1, 1, b't', 42, 0];
+        assert_eq!(
+            doc.apply_update_v1(&mut txn, &payload),
+            Err(Error::InvalidTypeRef(42))
+        );
+
+        assert!(txn.store.blocks.is_empty());
+    }
+
+    #[test]
+    fn encode_diff_into_buffers() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt = t1.get_text("text");
+        txt.insert(&mut t1, 0, "hello world");
+        txt.remove_range(&mut t1, 0, 6);
+        t1.get_map("map").insert(
+            &mut t1,
+            "key".to_string(),
+            Any::Array(vec![Any::Bool(true)]),
+        );
+
+        let d2 = Doc::with_client_id(2);
+        let t2 = d2.transact();
+        let sv = d2.get_state_vector(&t2);
+        let expected = d1.encode_delta_as_update_v1(&t1, &sv);
+        assert_eq!(t1.diff_size_hint(&sv), expected.len());
+
+        let mut encoder = EncoderV1::with_capacity(expected.len());
+        t1.encode_diff(&sv, &mut encoder);
+        assert_eq!(encoder.to_vec(), expected);
+
+        // reused buffer doesn't need to reallocate once it's big enough
+        let mut buf = Vec::with_capacity(t1.diff_size_hint(&sv));
+        let ptr = buf.as_ptr();
+        for _ in 0..3 {
+            buf.clear();
+            t1.encode_diff_v1_into(&sv, &mut buf);
+            assert_eq!(buf, expected);
+            assert_eq!(buf.as_ptr(), ptr);
+        }
+
+        // write directly into a network frame after a header byte
+        let mut frame = vec![0u8; 1 + t1.diff_size_hint(&sv) + 4];
+        frame[0] = 0xff;
+        let mut free = &mut frame[1..];
+        t1.encode_diff_v1_into(&sv, &mut free);
+        assert_eq!(free.len(), 4);
+        assert_eq!(&frame[1..frame.len() - 4], expected.as_slice());
+
+        // encoded diff against partial state
+        let mut sv = StateVector::default();
+        sv.inc_by(1, 3);
+        let expected = d1.encode_delta_as_update_v1(&t1, &sv);
+        let mut buf = vec![0xff];
+        t1.encode_diff_v1_into(&sv, &mut buf);
+        assert_eq!(&buf[1..], expected.as_slice());
+        assert_eq!(t1.diff_size_hint(&sv), expected.len());
+    }
+
+    #[test]
+    fn get_item_by_id() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let array = txn.get_array("array");
+        array.insert_range(&mut txn, 0, vec![10, 20, 30]);
+        let txt = txn.get_text("text");
+        txt.insert(&mut txn, 0, "abc");
+        let map = txn.get_map("map");
+        map.insert(&mut txn, "key".to_string(), "value");
+        array.push_front(&mut txn, 0);
+
+        // capture ids of all blocks from an update
+        let update = Update::decode_v1(&doc.encode_state_as_update_v1(&txn)).unwrap();
+        let ids: Vec<_> = update.blocks().map(|b| b.id().clone()).collect();
+        assert_eq!(
+            ids,
+            vec![ID::new(1, 0), ID::new(1, 3), ID::new(1, 6), ID::new(1, 7)]
+        );
+
+        let info = txn.get_item(&ID::new(1, 1)).unwrap();
+        assert_eq!(info.parent, Value::YArray(array.clone()));
+        assert_eq!(info.parent_sub, None);
+        assert_eq!(info.index, Some(2));
+        assert_eq!(info.kind, "Any");
+        assert_eq!(info.value, Some(Any::Number(20.0)));
+        assert!(!info.deleted);
+        assert_eq!(array.id_at(&txn, 2), Some(ID::new(1, 1)));
+        assert_eq!(array.id_at(&txn, 0), Some(ID::new(1, 7)));
+        assert_eq!(array.id_at(&txn, 4), None);
+
+        let info = txn.get_item(&ID::new(1, 5)).unwrap();
+        assert_eq!(info.parent, Value::YText(txt.clone()));
+        assert_eq!(info.index, Some(2));
+        assert_eq!(info.kind, "String");
+        assert_eq!(info.value, Some(Any::String("c".to_string())));
+        assert_eq!(txt.id_at(&txn, 2), Some(ID::new(1, 5)));
+
+        let info = txn.get_item(&ID::new(1, 6)).unwrap();
+        assert_eq!(info.parent, Value::YMap(map.clone()));
+        assert_eq!(info.parent_sub, Some("key".to_string()));
+        assert_eq!(info.index, None);
+        assert_eq!(info.value, Some(Any::String("value".to_string())));
+
+        assert_eq!(txn.get_item(&ID::new(1, 8)), None);
+        assert_eq!(txn.get_item(&ID::new(2, 0)), None);
+
+        // deleted elements are still resolvable, but have no index or value
+        array.remove(&mut txn, 1);
+        let info = txn.get_item(&ID::new(1, 0)).unwrap();
+        assert!(info.deleted);
+        assert_eq!(info.index, None);
+        assert_eq!(info.value, None);
+        let info = txn.get_item(&ID::new(1, 2)).unwrap();
+        assert_eq!(info.index, Some(2));
+        assert_eq!(array.id_at(&txn, 1), Some(ID::new(1, 1)));
+    }
+
+    #[test]
+    fn store_stats() {
+        let doc = Doc::with_client_id(1);
+        {
+            let mut txn = doc.transact();
+            let txt = txn.get_text("text");
+            for (i, c) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+                txt.insert(&mut txn, i as u32, c);
+            }
+            let stats = txn.store_stats();
+            assert_eq!(stats.blocks, 5);
+            assert_eq!(stats.content_len, 5);
+        }
+
+        // blocks are squashed on commit
+        let txn = doc.transact();
+        let stats = doc.stats(&txn);
+        assert_eq!(stats.blocks, 1);
+        assert_eq!(stats.blocks_per_client.get(&1), Some(&1));
+        assert_eq!(stats.tombstones, 0);
+        assert_eq!(stats.content_len, 5);
+        assert_eq!(stats.pending_blocks, 0);
+        assert!(stats.heap_size > 0);
+
+        // update depending on missing blocks is queued as pending
+        let remote = Doc::with_client_id(2);
+        let mut t2 = remote.transact();
+        let txt = t2.get_text("text");
+        txt.insert(&mut t2, 0, "a");
+        let sv = remote.get_state_vector(&t2);
+        txt.insert(&mut t2, 1, "b");
+        let update = remote.encode_delta_as_update_v1(&t2, &sv);
+        drop(txn);
+        let mut txn = doc.transact();
+        doc.apply_update_v1(&mut txn, &update).unwrap();
+        let stats = txn.store_stats();
+        assert_eq!(stats.pending_blocks, 1);
+        assert_eq!(stats.blocks, 1);
+    }
+
+    #[test]
+    fn store_stats_tombstones() {
+        for &skip_gc in [true, false].iter() {
+            let mut doc = Doc::with_options(Options {
+                skip_gc,
+                ..Options::with_client_id(1)
+            });
+            let fired = Rc::new(Cell::new(0));
+            let f = fired.clone();
+            let _sub = doc.on_update(move |_| f.set(f.get() + 1));
+            {
+                let mut txn = doc.transact();
+                txn.get_text("text").insert(&mut txn, 0, "hello world");
+            }
+            {
+                let mut txn = doc.transact();
+                txn.get_text("text").remove_range(&mut txn, 0, 6);
+            }
+            let txn = doc.transact();
+            let stats = txn.store_stats();
+            assert_eq!(stats.blocks, 2);
+            assert_eq!(stats.tombstones, 1);
+            assert_eq!(stats.content_len, 5);
+
+            // deleted content is kept only when garbage collection is disabled
+            let kind = txn.get_item(&ID::new(1, 0)).unwrap().kind;
+            assert_eq!(kind, if skip_gc { "String" } else { "Deleted" });
+        }
+    }
+
+    #[test]
+    fn squash_blocks_across_transactions() {
+        let doc = Doc::with_client_id(1);
+        let remote = Doc::with_client_id(2);
+        let mut bold = HashMap::new();
+        bold.insert("bold".to_string(), Any::Bool(true));
+        let mut updates = Vec::new();
+        for i in 0..1000u32 {
+            // every keystroke is committed in its own transaction
+            let mut txn = doc.transact();
+            let txt = txn.get_text("text");
+            if i < 500 {
+                txt.insert(&mut txn, i, "a");
+            } else {
+                txt.insert_with_attributes(&mut txn, i, "b", bold.clone());
+            }
+            let update = txn.encode_update_v1();
+            drop(txn);
+            let mut txn = remote.transact();
+            remote.apply_update_v1(&mut txn, &update).unwrap();
+            updates.push(update);
+        }
+
+        let mut txn = doc.transact();
+        let stats = doc.stats(&txn);
+        assert_eq!(stats.content_len, 1000);
+        assert!(
+            stats.blocks <= 5,
+            "text was split into {} blocks",
+            stats.blocks
+        );
+        let t2 = remote.transact();
+        assert_eq!(remote.stats(&t2).blocks, stats.blocks);
+
+        // squashing is local: emitted updates and the full state still decode to the same content
+        let expected = txn.get_text("text").to_string(&txn);
+        let replay = Doc::with_client_id(3);
+        let mut t3 = replay.transact();
+        for update in updates.iter() {
+            replay.apply_update_v1(&mut t3, update).unwrap();
+        }
+        assert_eq!(t3.get_text("text").to_string(&t3), expected);
+        let state = doc.encode_state_as_update_v1(&txn);
+        let restored = Doc::with_client_id(4);
+        let mut t4 = restored.transact();
+        restored.apply_update_v1(&mut t4, &state).unwrap();
+        assert_eq!(t4.get_text("text").to_string(&t4), expected);
+        assert_eq!(t4.store_stats().blocks, stats.blocks);
+    }
+
+    #[test]
+    fn compact_fragmented_doc() {
+        for &skip_gc in [false, true].iter() {
+            let mut doc = Doc::with_options(Options {
+                skip_gc,
+                ..Options::with_client_id(1)
+            });
+            let fired = Rc::new(Cell::new(0));
+            let f = fired.clone();
+            let _sub = doc.on_update(move |_| f.set(f.get() + 1));
+            {
+                let mut txn = doc.transact();
+                txn.get_text("text").insert(&mut txn, 0, "abcdefghij");
+                txn.get_array("array")
+                    .insert_range(&mut txn, 0, vec![1, 2, 3, 4, 5, 6]);
+            }
+            // delete every other element in separate transactions, then the rest of them
+            for i in 0..5 {
+                let mut txn = doc.transact();
+                txn.get_text("text").remove_range(&mut txn, i, 1);
+                if i < 3 {
+                    txn.get_array("array").remove_range(&mut txn, i, 1);
+                }
+            }
+            {
+                let mut txn = doc.transact();
+                txn.get_text("text").remove_range(&mut txn, 1, 2);
+            }
+
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            let array = txn.get_array("array");
+            let sv = doc.get_state_vector(&txn);
+            let expected = (text.to_string(&txn), array.to_json(&txn));
+            assert_eq!(expected.0, "bhj");
+
+            let updates = fired.get();
+            let report = doc.compact(&mut txn);
+            assert!(report.after.blocks < report.before.blocks);
+            assert_eq!(report.after, txn.store_stats());
+            assert_eq!(report.after.content_len, report.before.content_len);
+            assert_eq!((text.to_string(&txn), array.to_json(&txn)), expected);
+            assert_eq!(doc.get_state_vector(&txn), sv);
+
+            // compacted document can still be synchronized
+            let update = doc.encode_state_as_update_v1(&txn);
+            drop(txn);
+            assert_eq!(fired.get(), updates);
+            let remote = Doc::with_client_id(2);
+            let mut t2 = remote.transact();
+            remote.apply_update_v1(&mut t2, &update).unwrap();
+            assert_eq!(t2.get_text("text").to_string(&t2), "bhj");
+            assert_eq!(t2.get_array("array").to_json(&t2), expected.1);
+            t2.get_text("text").insert(&mut t2, 1, "x");
+            let update = remote.encode_delta_as_update_v1(&t2, &sv);
+            let mut txn = doc.transact();
+            doc.apply_update_v1(&mut txn, &update).unwrap();
+            assert_eq!(txn.get_text("text").to_string(&txn), "bxhj");
+        }
+    }
+
+    #[test]
+    fn delete_set_of_unknown_client() {
+        let d1 = Doc::with_client_id(1);
+        let blocks = {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "hello");
+            txn.encode_update_v1()
+        };
+        let deletions = {
+            let mut txn = d1.transact();
+            txn.get_text("text").remove_range(&mut txn, 0, 2);
+            txn.encode_update_v1()
+        };
+
+        // deletions of blocks from a client never seen before are kept until they arrive
+        let d2 = Doc::with_client_id(2);
+        let mut txn = d2.transact();
+        d2.apply_update_v1(&mut txn, &deletions).unwrap();
+        assert!(txn.store.pending_ds.is_some());
+        d2.apply_update_v1(&mut txn, &blocks).unwrap();
+        assert!(txn.store.pending_ds.is_none());
+        assert_eq!(txn.get_text("text").to_string(&txn), "llo");
+    }
+
+    #[test]
+    fn neighbor_pointer_with_stale_pivot() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "abc");
+        }
+        for i in 0..3 {
+            // alternate between types, so that blocks don't get squashed
+            let mut txn = d1.transact();
+            txn.get_array("array").push_back(&mut txn, i);
+            txn.get_text("text").push(&mut txn, "x");
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        // a right neighbor <1#5> is a block stored at index 3, but pointed to by index 0
+        {
+            let mut txn = d2.transact();
+            txn.get_array("array").insert(&mut txn, 1, 10);
+        }
+        exchange_updates(&[&d1, &d2]);
+        for doc in [&d1, &d2] {
+            let mut txn = doc.transact();
+            assert_eq!(
+                txn.get_array("array").to_json(&txn),
+                Any::Array(vec![0.into(), 10.into(), 1.into(), 2.into()].into())
+            );
+            assert_eq!(txn.get_text("text").to_string(&txn), "abcxxx");
+            // every item is linked back by its right neighbor
+            for (_, blocks) in txn.store.blocks.iter() {
+                for item in blocks.iter().filter_map(|block| block.as_item()) {
+                    if let Some(right) = item.right {
+                        let right = txn.store.blocks.get_item(&right).unwrap();
+                        let left = right.left.and_then(|ptr| txn.store.blocks.get_item(&ptr));
+                        assert_eq!(left.map(|left| left.id), Some(item.id));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pending_update_kept_after_unrelated_updates() {
+        let d1 = Doc::with_client_id(1);
+        let updates: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|chunk| {
+                let mut txn = d1.transact();
+                txn.get_text("text").push(&mut txn, chunk);
+                txn.encode_update_v1()
+            })
+            .collect();
+        let other = {
+            let d3 = Doc::with_client_id(3);
+            let mut txn = d3.transact();
+            txn.get_text("other").push(&mut txn, "x");
+            txn.encode_update_v1()
+        };
+
+        let d2 = Doc::with_client_id(2);
+        let mut txn = d2.transact();
+        d2.apply_update_v1(&mut txn, &updates[2]).unwrap();
+        assert!(txn.store.pending.is_some());
+        // an update which integrates fully, but doesn't unblock a pending one
+        d2.apply_update_v1(&mut txn, &other).unwrap();
+        assert!(txn.store.pending.is_some());
+        d2.apply_update_v1(&mut txn, &updates[1]).unwrap();
+        d2.apply_update_v1(&mut txn, &updates[0]).unwrap();
+        assert!(txn.store.pending.is_none());
+        assert_eq!(txn.get_text("text").to_string(&txn), "abc");
+        assert_eq!(txn.get_text("other").to_string(&txn), "x");
+    }
+
+    #[test]
+    fn partially_integrated_block() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "abc");
+        }
+        exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").push(&mut txn, "def");
+        }
+        // both insertions have been squashed into a single block, which d2 knows only in part
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        let decoded = Update::decode_v1(&update).unwrap();
+        assert_eq!(decoded.blocks().count(), 1);
+
+        let mut txn = d2.transact();
+        d2.apply_update_v1(&mut txn, &update).unwrap();
+        assert_eq!(txn.get_text("text").to_string(&txn), "abcdef");
+    }
+
+    #[test]
+    fn cached_lengths_with_out_of_order_updates() {
+        fn assert_lengths(doc: &Doc) {
+            let mut txn = doc.transact();
+            let array = txn.get_array("array");
+            let text = txn.get_text("text");
+            let map = txn.get_map("map");
+            assert_eq!(array.len(), array.iter(&txn).count() as u32);
+            assert_eq!(text.len(), text.to_string(&txn).len() as u32);
+            assert_eq!(map.len(), map.iter(&txn).count() as u32);
+        }
+
+        let mut rng = StdRng::seed_from_u64(0x1e7);
+        for _ in 0..10 {
+            let docs: Vec<_> = (1..=3).map(Doc::with_client_id).collect();
+            // updates produced by every peer, not yet delivered to the other ones
+            let mut queues: Vec<Vec<Vec<u8>>> = vec![Vec::new(); docs.len()];
+            for step in 0..200 {
+                let i = rng.gen_range(0, docs.len());
+                if rng.gen_bool(0.3) && !queues[i].is_empty() {
+                    // deliver a random update, possibly before the updates it depends on
+                    let j = rng.gen_range(0, queues[i].len());
+                    let update = queues[i].remove(j);
+                    let mut txn = docs[i].transact();
+                    docs[i].apply_update_v1(&mut txn, &update).unwrap();
+                } else {
+                    let mut txn = docs[i].transact();
+                    let array = txn.get_array("array");
+                    let text = txn.get_text("text");
+                    let map = txn.get_map("map");
+                    let key = format!("key{}", rng.gen_range(0, 5));
+                    match rng.gen_range(0, 6) {
+                        0 => {
+                            let index = rng.gen_range(0, array.len() + 1);
+                            array.insert_range(&mut txn, index, vec![step, step + 1]);
+                        }
+                        1 if array.len() > 0 => {
+                            let index = rng.gen_range(0, array.len());
+                            array.remove(&mut txn, index);
+                        }
+                        2 => {
+                            let index = rng.gen_range(0, text.len() + 1);
+                            text.insert(&mut txn, index, "abc");
+                        }
+                        3 if text.len() > 0 => {
+                            let index = rng.gen_range(0, text.len());
+                            let len = rng.gen_range(1, text.len() - index + 1).min(2);
+                            text.remove_range(&mut txn, index, len);
+                        }
+                        4 => {
+                            map.insert(&mut txn, key, step);
+                        }
+                        _ => {
+                            map.remove(&mut txn, &key);
+                        }
+                    }
+                    let update = txn.encode_update_v1();
+                    for (j, queue) in queues.iter_mut().enumerate() {
+                        if j != i {
+                            queue.push(update.clone());
+                        }
+                    }
+                }
+                assert_lengths(&docs[i]);
+            }
+
+            for (i, queue) in queues.into_iter().enumerate() {
+                let mut txn = docs[i].transact();
+                for update in queue {
+                    docs[i].apply_update_v1(&mut txn, &update).unwrap();
+                }
+            }
+            for doc in docs.iter() {
+                assert_lengths(doc);
+            }
+            let mut t1 = docs[0].transact();
+            let mut t2 = docs[1].transact();
+            let (m1, m2) = (t1.get_map("map"), t2.get_map("map"));
+            assert_eq!(m1.to_json(&t1), m2.to_json(&t2));
+        }
+    }
+
+    #[test]
+    fn encode_delta_near_identical_state_vector() {
+        let mut docs: Vec<_> = (1..=3).map(Doc::with_client_id).collect();
+        for (i, d) in docs.iter().enumerate() {
+            let mut txn = d.transact();
+            txn.get_text("text").push(&mut txn, &"abc".repeat(i + 1));
+        }
+        exchange_updates(&docs.iter().collect::<Vec<_>>());
+
+        let commits = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let commits = commits.clone();
+            docs[1].on_commit(move |e| commits.borrow_mut().push(e.update.clone()))
+        };
+        let d2 = &docs[1];
+        let remote_sv = d2.get_state_vector(&d2.transact());
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text").push(&mut txn, "!");
+        }
+
+        let txn = d2.transact();
+        let local_sv = d2.get_state_vector(&txn);
+        // nothing to send: no blocks and an empty delete set
+        assert_eq!(d2.encode_delta_as_update_v1(&txn, &local_sv), vec![0, 0]);
+        // a single missing block is encoded just like in an update of a transaction which created it
+        assert_eq!(
+            d2.encode_delta_as_update_v1(&txn, &remote_sv),
+            commits.borrow()[0]
+        );
+        // explicit zero clocks are treated just like the missing ones
+        let mut clocks: HashMap<u64, u32> = local_sv.clone().into();
+        clocks.insert(3, 0);
+        let with_zero: StateVector = clocks.clone().into();
+        clocks.remove(&3);
+        let without: StateVector = clocks.into();
+        assert_eq!(
+            d2.encode_delta_as_update_v1(&txn, &with_zero),
+            d2.encode_delta_as_update_v1(&txn, &without)
+        );
+    }
+
+    #[test]
+    fn encode_delta_after_delete() {
+        let d1 = Doc::with_client_id(1);
+        let map = d1.transact().get_map("map");
+        map.insert(&mut d1.transact(), "key".to_string(), 1);
+        let sv = d1.get_state_vector(&d1.transact());
+        let before = d1.encode_delta_as_update_v1(&d1.transact(), &sv);
+        assert_eq!(before, vec![0, 0]);
+
+        // delete set is recomputed even though the number of blocks didn't change
+        map.remove(&mut d1.transact(), "key");
+        let txn = d1.transact();
+        assert_eq!(d1.stats(&txn).blocks, 1);
+        let after = d1.encode_delta_as_update_v1(&txn, &sv);
+        let mut expected = DeleteSet::new();
+        expected.insert(ID::new(1, 0), 1);
+        assert!(d1.delete_set(&txn).is_deleted(&ID::new(1, 0)));
+        assert_eq!(after, [vec![0], expected.encode_v1()].concat());
+    }
+
+    #[test]
+    fn encode_delta_inside_squashed_block() {
+        let mut d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let commits = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let commits = commits.clone();
+            d1.on_commit(move |e| commits.borrow_mut().push(e.update.clone()))
+        };
+
+        for chunk in ["abc", "def"] {
+            let mut t1 = d1.transact();
+            let text = t1.get_text("text");
+            text.push(&mut t1, chunk);
+        }
+        // empty transactions don't emit commit events
+        drop(d1.transact());
+        assert_eq!(commits.borrow().len(), 2);
+
+        let mut t1 = d1.transact();
+        // both chunks have been squashed into a single block
+        assert_eq!(d1.stats(&t1).blocks, 1);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &commits.borrow()[0]).unwrap();
+        let sv = d2.get_state_vector(&t2);
+        let delta = d1.encode_delta_as_update_v1(&t1, &sv);
+        assert_eq!(delta, commits.borrow()[1]);
+        d2.apply_update_v1(&mut t2, &delta).unwrap();
+        assert_eq!(t2.get_text("text").to_string(&t2), "abcdef");
+        assert_eq!(
+            t1.get_text("text").to_string(&t1),
+            t2.get_text("text").to_string(&t2)
+        );
+    }
+
+    #[test]
+    fn after_transaction_summary() {
+        let mut d1 = Doc::with_client_id(1);
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("other");
+            t1.get_map("map").insert(
+                &mut t1,
+                "nested".to_string(),
+                PrelimMap::<Any>::from(HashMap::new()),
+            );
+        }
+        let nested = BranchId::Nested(ID::new(1, 0));
+
+        let summaries = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let summaries = summaries.clone();
+            d1.on_after_transaction(move |_, e| summaries.borrow_mut().push(e.clone()))
+        };
+
+        {
+            let mut t1 = d1.transact_with_origin(Any::String("local".into()));
+            let map = t1.get_map("map");
+            if let Some(Value::YMap(nested)) = map.get(&t1, "nested") {
+                nested.insert(&mut t1, "key".to_string(), Any::Number(1.0));
+            }
+        }
+        {
+            let e = &summaries.borrow()[0];
+            assert_eq!(e.origin, Some(Any::String("local".into())));
+            assert_eq!(e.changed, HashSet::from([nested.clone()]));
+            assert_eq!(
+                e.changed_parent_types,
+                HashSet::from([nested.clone(), BranchId::Root("map".to_string())])
+            );
+            assert!(e.is_root_changed("map"));
+            assert!(!e.is_root_changed("other"));
+            assert!(e.before_state < e.after_state);
+        }
+
+        // changes integrated from remote updates are summarized as well
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut t2 = d2.transact();
+            let t1 = d1.transact();
+            let update = d1.encode_state_as_update_v1(&t1);
+            drop(t1);
+            d2.apply_update_v1(&mut t2, &update).unwrap();
+            t2.get_text("other").push(&mut t2, "hello");
+            let map = t2.get_map("map");
+            if let Some(Value::YMap(nested)) = map.get(&t2, "nested") {
+                nested.remove(&mut t2, "key");
+            }
+        }
+        summaries.borrow_mut().clear();
+        {
+            let mut t1 = d1.transact();
+            let t2 = d2.transact();
+            let update = d2.encode_delta_as_update_v1(&t2, &d1.get_state_vector(&t1));
+            d1.apply_update_v1(&mut t1, &update).unwrap();
+        }
+        let e = &summaries.borrow()[0];
+        assert_eq!(e.origin, None);
+        assert_eq!(
+            e.changed,
+            HashSet::from([nested.clone(), BranchId::Root("other".to_string())])
+        );
+        assert!(e.is_root_changed("map"));
+        assert!(!e.delete_set.is_empty());
+    }
+
+    #[test]
+    fn before_and_after_transaction_order() {
+        let mut doc = Doc::with_client_id(1);
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let _s1 = {
+            let calls = calls.clone();
+            doc.on_before_transaction(move |_, e| {
+                calls.borrow_mut().push(format!("before {:?}", e.origin))
+            })
+        };
+        let _s2 = {
+            let calls = calls.clone();
+            doc.on_after_transaction(move |_, _| calls.borrow_mut().push("after".to_string()))
+        };
+        let _s3 = {
+            let calls = calls.clone();
+            doc.on_after_transaction_cleanup(move |_, _| {
+                calls.borrow_mut().push("cleanup".to_string())
+            })
+        };
+
+        drop(doc.transact_with_origin(Any::Number(1.0)));
+        assert_eq!(
+            calls.borrow().as_slice(),
+            &["before Some(Number(1.0))", "after", "cleanup"]
+        );
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn commit_span_fields() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        type Fields = Arc<Mutex<HashMap<String, String>>>;
+
+        /// Collects all fields recorded on spans named "commit".
+        struct CommitRecorder {
+            commit_span: Mutex<Option<Id>>,
+            fields: Fields,
+        }
+
+        impl Visit for &CommitRecorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                let mut fields = self.fields.lock().unwrap();
+                fields.insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+
+        impl tracing::Subscriber for CommitRecorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let id = Id::from_u64(1 + self.commit_span.lock().unwrap().is_some() as u64);
+                if span.metadata().name() == "commit" {
+                    *self.commit_span.lock().unwrap() = Some(id.clone());
+                    span.record(&mut &*self);
+                }
+                id
+            }
+
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                if self.commit_span.lock().unwrap().as_ref() == Some(span) {
+                    values.record(&mut &*self);
+                }
+            }
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let fields = Fields::default();
+        let recorder = CommitRecorder {
+            commit_span: Mutex::new(None),
+            fields: fields.clone(),
+        };
+        tracing::subscriber::with_default(recorder, || {
+            let doc = Doc::with_options(Options {
+                guid: "test-doc".to_string(),
+                ..Options::with_client_id(1)
+            });
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            text.push(&mut txn, "abc");
+            text.insert(&mut txn, 0, "def");
+            txn.commit();
+        });
+
+        let fields = fields.lock().unwrap();
+        assert_eq!(fields.get("guid").map(String::as_str), Some("test-doc"));
+        assert_eq!(fields.get("client_id").map(String::as_str), Some("1"));
+        assert_eq!(fields.get("blocks").map(String::as_str), Some("2"));
+        assert_eq!(fields.get("deleted").map(String::as_str), Some("0"));
+        assert!(fields.contains_key("squashed"));
+        assert!(fields.contains_key("duration_us"));
+    }
+
+    fn skip_gc_doc(client_id: u64) -> Doc {
+        Doc::with_options(Options {
+            skip_gc: true,
+            ..Options::with_client_id(client_id)
+        })
+    }
+
+    #[test]
+    fn revert_remote_update() {
+        let d1 = skip_gc_doc(1);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "hello world");
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".to_owned(), 1);
+            map.insert(&mut txn, "b".to_owned(), 2);
+            txn.get_array("array")
+                .insert_range(&mut txn, 0, vec![1, 2, 3]);
+        }
+        let d3 = skip_gc_doc(3);
+        d3.apply_update_v1(
+            &mut d3.transact(),
+            &d1.encode_state_as_update_v1(&d1.transact()),
+        )
+        .unwrap();
+        let before = d1.fork(&d1.transact());
+
+        // changes made by a second peer
+        let d2 = skip_gc_doc(2);
+        let sv1 = {
+            let mut txn = d2.transact();
+            d2.apply_update_v1(&mut txn, &d1.encode_state_as_update_v1(&d1.transact()))
+                .unwrap();
+            d2.get_state_vector(&txn)
+        };
+        let update = {
+            let mut txn = d2.transact();
+            let text = txn.get_text("text");
+            text.remove_range(&mut txn, 0, 6);
+            text.insert(&mut txn, 5, "!");
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".to_owned(), 10);
+            map.remove(&mut txn, "b");
+            map.insert(&mut txn, "c".to_owned(), 3);
+            let array = txn.get_array("array");
+            array.remove(&mut txn, 1);
+            array.push_back(&mut txn, 4);
+            d2.encode_delta_as_update_v1(&txn, &sv1)
+        };
+
+        let sv2 = {
+            let mut txn = d1.transact();
+            d1.apply_update_v1(&mut txn, &update).unwrap();
+            assert_eq!(txn.get_text("text").to_string(&txn), "world!");
+            d1.get_state_vector(&txn)
+        };
+        let revert = {
+            let mut txn = d1.transact();
+            let report = txn.revert(&update).unwrap();
+            assert!(report.conflicts.is_empty(), "{:?}", report.conflicts);
+            assert_eq!(report.deleted, 4); // "!", a=10, c=3 and 4
+            assert_eq!(report.restored, 9); // "hello ", a=1, b=2 and 2
+            d1.encode_delta_as_update_v1(&txn, &sv2)
+        };
+        assert!(d1.eq_content(&d1.transact(), &before, &before.transact()));
+
+        // third replica converges after receiving both an update and its revert
+        {
+            let mut txn = d3.transact();
+            d3.apply_update_v1(&mut txn, &update).unwrap();
+            d3.apply_update_v1(&mut txn, &revert).unwrap();
+        }
+        assert!(d3.eq_content(&d3.transact(), &before, &before.transact()));
+        assert_eq!(
+            d3.encode_state_as_update_v1(&d3.transact()),
+            d1.encode_state_as_update_v1(&d1.transact())
+        );
+    }
+
+    #[test]
+    fn revert_garbage_collected() {
+        let d1 = Doc::with_client_id(1);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello");
+        let update = {
+            let mut txn = d1.transact();
+            text.remove_range(&mut txn, 0, 5);
+            txn.encode_update_v1()
+        };
+
+        let mut txn = d1.transact();
+        let report = txn.revert(&update).unwrap();
+        assert_eq!(report.restored, 0);
+        assert_eq!(
+            report.conflicts,
+            vec![RevertConflict::GarbageCollected(ID::new(1, 0), 5)]
+        );
+    }
+
+    #[test]
+    fn revert_range_inside_block() {
+        let d1 = skip_gc_doc(1);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "abc");
+        }
+        {
+            // separate block starting at clock 3
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "xyz123");
+        }
+
+        let mut inserted = DeleteSet::new();
+        inserted.insert(ID::new(1, 5), 2);
+        let mut txn = d1.transact();
+        let report = txn.revert_ranges(&inserted, &DeleteSet::new());
+        assert!(report.conflicts.is_empty(), "{:?}", report.conflicts);
+        assert_eq!(report.deleted, 2);
+        assert_eq!(txn.get_text("text").to_string(&txn), "xy23abc");
+    }
+
+    #[test]
+    fn apply_remote_delete_inside_block() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "abc");
+            txn.get_array("array")
+                .insert_range(&mut txn, 0, vec![1, 2, 3]);
+        }
+        crate::test_utils::exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d1.transact();
+            txn.get_array("array").remove(&mut txn, 1);
+        }
+        crate::test_utils::exchange_updates(&[&d1, &d2]);
+
+        let mut txn = d2.transact();
+        assert_eq!(txn.get_text("text").to_string(&txn), "abc");
+        let array = txn.get_array("array");
+        assert_eq!(
+            array.to_json(&txn),
+            Any::Array(vec![Any::Number(1.0), Any::Number(3.0)])
+        );
+    }
+
+    #[test]
+    fn transaction_own_update() {
+        let mut doc = Doc::with_client_id(1);
+        let committed = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let committed = committed.clone();
+            doc.on_commit(move |e| committed.borrow_mut().push(e.update.clone()))
+        };
+        {
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, "hello world");
+        }
+
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        text.remove_range(&mut txn, 0, 6);
+        text.push(&mut txn, "!");
+        assert!(txn.has_changes());
+        let before_commit = txn.encode_update_v1();
+        txn.commit();
+        let after_commit = txn.encode_update_v1();
+        drop(txn);
+
+        let committed = committed.borrow();
+        assert_eq!(committed.len(), 2);
+        assert_eq!(after_commit, committed[1]);
+        assert_eq!(before_commit, committed[1]);
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &committed[0]).unwrap();
+        d2.apply_update_v1(&mut t2, &after_commit).unwrap();
+        assert_eq!(t2.get_text("text").to_string(&t2), "world!");
+    }
+
+    #[test]
+    fn read_only_transaction_update() {
+        let doc = Doc::with_client_id(1);
+        let text = doc.transact().get_text("text");
+        text.push(&mut doc.transact(), "abc");
+
+        let txn = doc.transact();
+        assert!(!txn.has_changes());
+        let update = Update::decode_v1(&txn.encode_update_v1()).unwrap();
+        assert_eq!(update, Update::default());
+    }
+
+    #[test]
+    fn destroy() {
+        let mut doc = Doc::with_client_id(1);
+        let page = Doc::with_options(Options {
+            guid: "page".to_string(),
+            ..Options::with_client_id(2)
+        });
+        {
+            let mut txn = doc.transact();
+            txn.get_map("pages")
+                .insert(&mut txn, "page".to_string(), &page);
+            txn.get_text("text").push(&mut txn, "hello");
+        }
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            doc.on_destroy(move |e| events.borrow_mut().push(e.clone()))
+        };
+        let updates = Rc::new(Cell::new(0));
+        let _update_sub = {
+            let updates = updates.clone();
+            doc.on_commit(move |_| updates.set(updates.get() + 1))
+        };
+
+        assert!(!doc.is_destroyed());
+        doc.destroy();
+        doc.destroy();
+        assert!(doc.is_destroyed());
+        assert_eq!(
+            *events.borrow(),
+            vec![DestroyEvent {
+                guid: doc.guid.clone(),
+                subdocs: vec!["page".to_string()],
+            }]
+        );
+        // callbacks have been released together with the rest of a document state
+        assert_eq!(Rc::strong_count(&events), 1);
+        assert_eq!(Rc::strong_count(&updates), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "has been destroyed")]
+    fn transact_destroyed() {
+        let doc = Doc::with_client_id(1);
+        doc.transact().get_text("text");
+        doc.destroy();
+        doc.transact();
+    }
+
+    #[test]
+    fn root_types_created() {
+        let d1 = Doc::with_client_id(1);
+        {
+            let mut txn = d1.transact();
+            txn.get_array("comments").push_back(&mut txn, "hello");
+            txn.get_map("meta")
+                .insert(&mut txn, "version".to_string(), 2);
+        }
+
+        let mut d2 = Doc::with_client_id(2);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            d2.on_root_types(move |e| events.borrow_mut().push(e.created.clone()))
+        };
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text");
+            txn.get_map("notes");
+        }
+        d2.transact().get_text("text");
+        {
+            let mut txn = d2.transact();
+            let update = d1.encode_state_as_update_v1(&d1.transact());
+            d2.apply_update_v1(&mut txn, &update).unwrap();
+        }
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                vec![("notes".to_string(), "YMap"), ("text".to_string(), "YText")],
+                // kinds of remote root types are unknown until they are accessed locally
+                vec![
+                    ("comments".to_string(), "undefined"),
+                    ("meta".to_string(), "undefined")
+                ],
+            ]
+        );
+    }
+
+    fn schema_doc(client_id: u64) -> Doc {
+        Doc::with_options(Options {
+            schema: Some(vec![
+                ("body".to_string(), "YText"),
+                ("meta".to_string(), "YMap"),
+                ("comments".to_string(), "YArray"),
+            ]),
+            ..Options::with_client_id(client_id)
+        })
+    }
+
+    #[test]
+    fn schema_roots() {
+        let d1 = schema_doc(1);
+        let mut txn = d1.transact();
+        let body = txn.get_text("body");
+        body.push(&mut txn, "hello");
+        txn.get_map("meta").insert(&mut txn, "v".to_string(), 1);
+        txn.get_array("comments").push_back(&mut txn, "first");
+        assert_eq!(
+            txn.get_or_create_unchecked("notes", "YMap")
+                .map(|v| v.kind()),
+            Some("YMap")
+        );
+        assert!(txn.get_or_create_unchecked("notes", "YDoc").is_none());
+        let roots: Vec<_> = d1
+            .describe(&txn)
+            .roots
+            .into_iter()
+            .map(|r| (r.name, r.kind))
+            .collect();
+        assert_eq!(
+            roots,
+            vec![
+                ("body".to_string(), "YText"),
+                ("comments".to_string(), "YArray"),
+                ("meta".to_string(), "YMap"),
+                ("notes".to_string(), "YMap"),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "root type 'bodu' is not declared in document schema")]
+    fn schema_root_typo() {
+        let doc = schema_doc(1);
+        doc.transact().get_text("bodu");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "root type 'meta' is declared in document schema as YMap, not YArray"
+    )]
+    fn schema_root_kind_mismatch() {
+        let doc = schema_doc(1);
+        doc.transact().get_array("meta");
+    }
+
+    #[test]
+    fn schema_remote_roots() {
+        let d1 = Doc::with_client_id(1);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("body").push(&mut txn, "hello");
+            txn.get_map("extra").insert(&mut txn, "k".to_string(), 1);
+        }
+
+        let mut d2 = schema_doc(2);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            d2.on_root_types(move |e| events.borrow_mut().push(e.clone()))
+        };
+        let mut txn = d2.transact();
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        d2.apply_update_v1(&mut txn, &update).unwrap();
+        drop(txn);
+
+        // declared root types are not reported, remote ones are accepted but flagged
+        assert_eq!(
+            *events.borrow(),
+            vec![RootTypesEvent {
+                created: vec![("extra".to_string(), "undefined")],
+                non_schema: vec!["extra".to_string()],
+            }]
+        );
+        let mut txn = d2.transact();
+        assert_eq!(txn.get_text("body").to_string(&txn), "hello");
+        let extra = txn.get_or_create_unchecked("extra", "YMap").unwrap();
+        assert_eq!(
+            extra.to_json(&txn),
+            Any::from_json_str(r#"{"k":1.0}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn change_source() {
+        let mut d1 = Doc::with_client_id(1);
+        let mut d2 = Doc::with_client_id(2);
+        let sources = Rc::new(RefCell::new(Vec::new()));
+        let _sub1 = {
+            let sources = sources.clone();
+            d1.on_commit(move |e| sources.borrow_mut().push(e.source))
+        };
+        let local_calls = Rc::new(Cell::new(0));
+        let _sub3 = {
+            let local_calls = local_calls.clone();
+            d2.transact().get_text("text").observe(move |txn, _| {
+                if txn.change_source().is_local() {
+                    local_calls.set(local_calls.get() + 1);
+                }
+            })
+        };
+
+        let _sub2 = {
+            let sources = sources.clone();
+            d2.on_after_transaction(move |_, e| {
+                sources.borrow_mut().push(e.source);
+                sources
+                    .borrow_mut()
+                    .push(if e.integrated_clients.contains(&1) {
+                        ChangeSource::Remote
+                    } else {
+                        ChangeSource::Local
+                    });
+            })
+        };
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("text").push(&mut t1, "hello");
+            assert!(t1.is_local());
+            assert!(t1.integrated_clients().is_empty());
+        }
+        {
+            let mut t2 = d2.transact();
+            let update = d1.encode_state_as_update_v1(&d1.transact());
+            d2.apply_update_v1(&mut t2, &update).unwrap();
+            assert!(!t2.is_local());
+            assert_eq!(t2.integrated_clients(), &HashSet::from([1]));
+        }
+        {
+            let mut t2 = d2.transact();
+            t2.get_text("text").push(&mut t2, " world");
+        }
+        {
+            // remote deletions are not attributed to any integrated client
+            let mut t1 = d1.transact();
+            t1.get_text("text").remove_range(&mut t1, 0, 1);
+        }
+        {
+            let mut t2 = d2.transact();
+            let sv = d2.get_state_vector(&t2);
+            let update = d1.encode_delta_as_update_v1(&d1.transact(), &sv);
+            d2.apply_update_v1(&mut t2, &update).unwrap();
+            assert_eq!(t2.change_source(), ChangeSource::Remote);
+            t2.get_text("text").push(&mut t2, "!");
+            assert_eq!(t2.change_source(), ChangeSource::Mixed);
+        }
+
+        use ChangeSource::*;
+        assert_eq!(
+            *sources.borrow(),
+            vec![
+                Local, // d1: insert
+                Remote, Remote, // d2: integrate insert
+                Local, Local, // d2: local insert
+                Local, // d1: remove
+                Mixed, Local, // d2: integrate remove + local insert
+            ]
+        );
+        assert_eq!(local_calls.get(), 1);
+    }
+
+    #[test]
+    fn transaction_pending_changes() {
+        let doc = Doc::with_client_id(1);
+        {
+            let mut txn = doc.transact();
+            txn.get_array("list")
+                .insert_range(&mut txn, 0, vec![1.0, 2.0, 3.0]);
+            txn.get_map("map").insert(
+                &mut txn,
+                "nested".to_string(),
+                PrelimMap::<Any>::from(HashMap::new()),
+            );
+        }
+
+        let committed = Rc::new(RefCell::new(HashMap::new()));
+        let _sub1 = {
+            let committed = committed.clone();
+            doc.transact().get_array("list").observe_deep(move |_, e| {
+                committed.borrow_mut().insert("list".to_string(), e.clone());
+            })
+        };
+        let _sub2 = {
+            let committed = committed.clone();
+            doc.transact().get_map("map").observe_deep(move |_, e| {
+                committed.borrow_mut().insert("map".to_string(), e.clone());
+            })
+        };
+
+        let mut txn = doc.transact();
+        let list = txn.get_array("list");
+        list.remove_range(&mut txn, 0, 2);
+        let pending = txn.pending_changes();
+        assert_eq!(pending.len(), 1);
+        match &pending["list"][..] {
+            [Event::Array(e)] => assert_eq!(e.delta, vec![Change::Removed(2)]),
+            other => panic!("unexpected events: {:?}", other),
+        }
+
+        list.insert_range(&mut txn, 1, vec![4.0]);
+        if let Some(Value::YMap(nested)) = txn.get_map("map").get(&txn, "nested") {
+            nested.insert(&mut txn, "key".to_string(), Any::Number(1.0));
+        }
+        let pending = txn.pending_changes();
+        match &pending["list"][..] {
+            [Event::Array(e)] => assert_eq!(
+                e.delta,
+                vec![
+                    Change::Removed(2),
+                    Change::Retain(1),
+                    Change::Added(vec![Value::Any(Any::Number(4.0))])
+                ]
+            ),
+            other => panic!("unexpected events: {:?}", other),
+        }
+        match &pending["map"][..] {
+            [Event::Map(e)] => {
+                assert_eq!(e.path, vec![PathSegment::Key("nested".into())]);
+                assert!(e.keys.contains_key("key"));
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+
+        // querying pending changes doesn't trigger observers
+        assert!(committed.borrow().is_empty());
+        drop(txn);
+        assert_eq!(*committed.borrow(), pending);
+    }
+
+    #[test]
+    fn commit_event_metadata() {
+        let mut d1 = Doc::with_client_id(1);
+        let mut d2 = Doc::with_client_id(2);
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("text").push(&mut t1, "hello");
+            t1.get_map("map").insert(
+                &mut t1,
+                "nested".to_string(),
+                PrelimMap::<Any>::from(HashMap::new()),
+            );
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub1 = {
+            let events = events.clone();
+            d1.on_commit(move |e| events.borrow_mut().push(e.clone()))
+        };
+        let _sub2 = {
+            let events = events.clone();
+            d2.on_commit(move |e| events.borrow_mut().push(e.clone()))
+        };
+
+        {
+            let mut t1 = d1.transact_with_origin(Any::String("local".into()));
+            t1.get_text("text").remove_range(&mut t1, 0, 1);
+            if let Some(Value::YMap(nested)) = t1.get_map("map").get(&t1, "nested") {
+                nested.insert(&mut t1, "key".to_string(), Any::Number(1.0));
+            }
+        }
+        {
+            let e = events.borrow()[0].clone();
+            assert_eq!(e.origin, Some(Any::String("local".into())));
+            assert_eq!(e.roots, vec!["map".to_string(), "text".to_string()]);
+            assert!(e.has_deletions);
+            assert_eq!(e.source, ChangeSource::Local);
+            assert_eq!(e.size(), e.update.len());
+
+            let mut t2 = d2.transact();
+            d2.apply_update_v1(&mut t2, &e.update).unwrap();
+        }
+        {
+            let e = &events.borrow()[1];
+            assert_eq!(e.origin, None);
+            assert_eq!(e.roots, vec!["map".to_string(), "text".to_string()]);
+            assert!(e.has_deletions);
+            assert_eq!(e.source, ChangeSource::Remote);
+        }
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("text").push(&mut t1, "!");
+        }
+        let e = &events.borrow()[2];
+        assert_eq!(e.roots, vec!["text".to_string()]);
+        assert!(!e.has_deletions);
+    }
+
+    #[test]
+    fn describe() {
+        let d1 = Doc::with_client_id(1);
+        let first = {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, "hello world");
+            text.remove_range(&mut txn, 5, 6);
+            txn.encode_update_v1()
+        };
+        let second = {
+            let mut txn = d1.transact();
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".to_owned(), 1);
+            map.insert(&mut txn, "b".to_owned(), 2);
+            txn.encode_update_v1()
+        };
+
+        let description = d1.describe(&d1.transact());
+        assert_eq!(description.client_id, 1);
+        assert_eq!(description.guid, d1.guid);
+        assert_eq!(
+            description.state_vector,
+            d1.get_state_vector(&d1.transact())
+        );
+        assert_eq!(
+            description.roots,
+            vec![
+                RootDescription {
+                    name: "map".to_string(),
+                    kind: "YMap",
+                    len: 2
+                },
+                RootDescription {
+                    name: "text".to_string(),
+                    kind: "YText",
+                    len: 5
+                },
+            ]
+        );
+        assert_eq!(description.missing, None);
+        assert!(!description.pending_delete_set);
+
+        // second update depends on blocks from the first one, which never arrived
+        let d2 = Doc::with_client_id(2);
+        d2.apply_update_v1(&mut d2.transact(), &second).unwrap();
+        let description = d2.describe(&d2.transact());
+        assert!(description.state_vector.is_empty());
+        let mut missing = StateVector::default();
+        missing.set_max(1, 10);
+        assert_eq!(description.missing, Some(missing));
+        assert_eq!(description.stats.pending_blocks, 2);
+        let dump = description.to_string();
+        assert!(dump.contains("client id: 2\n"), "{}", dump);
+        assert!(
+            dump.contains("pending: 2 blocks, missing {1: 10}\n"),
+            "{}",
+            dump
+        );
+
+        d2.apply_update_v1(&mut d2.transact(), &first).unwrap();
+        let description = d2.describe(&d2.transact());
+        assert_eq!(description.missing, None);
+        assert_eq!(
+            description.state_vector,
+            d1.get_state_vector(&d1.transact())
+        );
+        let dump = description.to_string();
+        assert!(dump.contains("state vector: {1: 13}\n"), "{}", dump);
+        assert!(dump.contains("pending: none\n"), "{}", dump);
+    }
+
+    #[test]
+    fn content_size() {
+        let d1 = Doc::with_client_id(1);
+        let mut txn = d1.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "hello ŵorld");
+        assert_eq!(text.content_size(&txn), 12);
+        text.remove_range(&mut txn, 0, 6);
+        assert_eq!(text.content_size(&txn), 6);
+
+        let map = txn.get_map("map");
+        map.insert(&mut txn, "key".to_owned(), "value");
+        // key + lib0 encoded string: type tag, length and 5 bytes
+        assert_eq!(map.content_size(&txn), 3 + 7);
+        map.insert(&mut txn, "list".to_owned(), PrelimArray::from(vec![true]));
+        assert_eq!(map.content_size(&txn), 3 + 7 + 4 + 1);
+        assert_eq!(d1.content_size(&txn), 6 + 15);
+        map.remove(&mut txn, "key");
+        assert_eq!(d1.content_size(&txn), 6 + 5);
+    }
+
+    #[test]
+    fn content_size_quota() {
+        let d1 = Doc::with_client_id(1);
+        let text = d1.transact().get_text("text");
+        let first = {
+            let mut txn = d1.transact();
+            text.insert(&mut txn, 0, "hello");
+            txn.encode_update_v1()
+        };
+        let second = {
+            let mut txn = d1.transact();
+            text.insert(&mut txn, 5, " world");
+            txn.encode_update_v1()
+        };
+
+        let d2 = Doc::with_options(Options {
+            max_content_size: Some(10),
+            ..Options::with_client_id(2)
+        });
+        assert_eq!(d2.options().max_content_size, Some(10));
+        d2.apply_update_v1(&mut d2.transact(), &first).unwrap();
+        assert_eq!(
+            d2.apply_update_v1(&mut d2.transact(), &second),
+            Err(Error::QuotaExceeded {
+                limit: 10,
+                size: 11
+            })
+        );
+        assert_eq!(
+            d2.apply_update_v1_lazy(&mut d2.transact(), &second),
+            Err(Error::QuotaExceeded {
+                limit: 10,
+                size: 11
+            })
+        );
+        {
+            let mut txn = d2.transact();
+            assert_eq!(txn.get_text("text").to_string(&txn), "hello");
+            assert_eq!(d2.content_size(&txn), 5);
+            assert!(txn.store.pending.is_none());
+        }
+
+        // re-applying already integrated content doesn't count towards the limit
+        d2.apply_update_v1(&mut d2.transact(), &first).unwrap();
+    }
+
+    #[test]
+    fn content_size_quota_lazy() {
+        // an update with blocks of two clients, which fit into the quota only separately
+        let d1 = Doc::with_client_id(1);
+        let d3 = Doc::with_client_id(3);
+        let mut t1 = d1.transact();
+        let mut t3 = d3.transact();
+        t1.get_text("text").push(&mut t1, "hello");
+        t3.get_text("text").push(&mut t3, "world!");
+        d1.apply_update_v1(&mut t1, &d3.encode_state_as_update_v1(&t3))
+            .unwrap();
+        let update = d1.encode_state_as_update_v1(&t1);
+
+        let d2 = Doc::with_options(Options {
+            max_content_size: Some(10),
+            ..Options::with_client_id(2)
+        });
+        let mut t2 = d2.transact();
+        assert_eq!(
+            d2.apply_update_v1_lazy(&mut t2, &update),
+            Err(Error::QuotaExceeded {
+                limit: 10,
+                size: 11
+            })
+        );
+        assert_eq!(t2.get_text("text").to_string(&t2), "");
+        assert!(t2.store.blocks.get_state_vector().is_empty());
+        assert_eq!(d2.content_size(&t2), 0);
+    }
+
+    #[test]
+    fn content_size_quota_local() {
+        let doc = Doc::with_options(Options {
+            max_content_size: Some(10),
+            ..Options::with_client_id(1)
+        });
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        let array = txn.get_array("array");
+        let map = txn.get_map("map");
+        text.try_insert(&mut txn, 0, "hello").unwrap();
+        let quota = |size| Err(Error::QuotaExceeded { limit: 10, size });
+        assert_eq!(text.try_insert(&mut txn, 5, " world"), quota(11));
+        // strings stored as Any values are accounted for with their tag and length prefix
+        assert_eq!(array.try_insert(&mut txn, 0, "world!"), quota(13));
+        assert_eq!(
+            map.try_insert(&mut txn, "key".into(), "abc").map(|_| ()),
+            quota(13)
+        );
+        // nothing has been integrated
+        assert_eq!(text.to_string(&txn), "hello");
+        assert_eq!(array.len(), 0);
+        assert_eq!(map.len(), 0);
+        assert_eq!(txn.store.blocks.get_state(&1), 5);
+        assert_eq!(doc.content_size(&txn), 5);
+
+        // deleted content no longer counts towards the quota
+        text.remove_range(&mut txn, 0, 4);
+        map.try_insert(&mut txn, "key".into(), "abc").unwrap();
+        array.try_insert(&mut txn, 0, true).unwrap();
+        assert_eq!(doc.content_size(&txn), 1 + 3 + 5 + 1);
+        assert_eq!(array.try_insert(&mut txn, 1, "ab"), quota(14));
+        assert_eq!(
+            array.to_json(&txn),
+            Any::Array(vec![Any::Bool(true)].into())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "content size quota exceeded")]
+    fn content_size_quota_local_panics() {
+        let doc = Doc::with_options(Options {
+            max_content_size: Some(4),
+            ..Options::with_client_id(1)
+        });
+        let mut txn = doc.transact();
+        txn.get_text("text").insert(&mut txn, 0, "hello");
+    }
+
+    #[test]
+    fn content_size_cache() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let nested = {
+            let mut txn = d1.transact();
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".into(), PrelimArray::from(vec!["xyz"]));
+            match map.get(&txn, "a") {
+                Some(Value::YArray(array)) => array,
+                other => panic!("expected nested array, got {:?}", other),
+            }
+        };
+        exchange_updates(&[&d1, &d2]);
+        let size = |doc: &Doc| {
+            let mut txn = doc.transact();
+            let map = txn.get_map("map");
+            let size = map.content_size(&txn);
+            let branch = &txn.store.types[&Rc::new("map".to_string())];
+            let cached = branch.borrow().content_size_cache.get();
+            assert_eq!(cached, Some(size));
+            assert_eq!(doc.content_size(&txn), size);
+            size
+        };
+        assert_eq!(size(&d1), 1 + 5);
+
+        // local change of a nested type
+        nested.push_back(&mut d1.transact(), "pq");
+        assert_eq!(size(&d1), 1 + 5 + 4);
+        nested.remove(&mut d1.transact(), 0);
+        assert_eq!(size(&d1), 1 + 4);
+
+        // remote change of a nested type
+        assert_eq!(size(&d2), 1 + 5);
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(size(&d2), 1 + 4);
+        {
+            let mut txn = d2.transact();
+            let map = txn.get_map("map");
+            map.remove(&mut txn, "a");
+        }
+        assert_eq!(size(&d2), 0);
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(size(&d1), 0);
+    }
+
+    #[test]
+    fn content_size_tracks_changes() {
+        fn traversed_size(doc: &Doc, txn: &Transaction) -> usize {
+            let size = txn
+                .store
+                .types
+                .values()
+                .map(|branch| branch.borrow().content_size(txn))
+                .sum();
+            assert_eq!(doc.content_size(txn), size);
+            size
+        }
+
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            for doc in [&d1, &d2] {
+                let mut txn = doc.transact();
+                let text = txn.get_text("text");
+                let map = txn.get_map("map");
+                let len = text.len();
+                let key = format!("k{}", rng.gen_range(0, 3));
+                match rng.gen_range(0, 5) {
+                    0 => text.insert(&mut txn, rng.gen_range(0, len + 1), "ab"),
+                    1 if len > 0 => {
+                        let index = rng.gen_range(0, len);
+                        text.remove_range(&mut txn, index, 1);
+                    }
+                    2 => {
+                        map.insert(&mut txn, key, PrelimArray::from(vec![1, 2]));
+                    }
+                    3 => {
+                        if let Some(Value::YArray(nested)) = map.get(&txn, &key) {
+                            nested.push_back(&mut txn, "c");
+                        }
+                    }
+                    _ => {
+                        map.remove(&mut txn, &key);
+                    }
+                }
+                traversed_size(doc, &txn);
+            }
+            exchange_updates(&[&d1, &d2]);
+        }
+        let t1 = d1.transact();
+        let t2 = d2.transact();
+        assert_eq!(traversed_size(&d1, &t1), traversed_size(&d2, &t2));
+    }
+
+    #[test]
+    fn client_blocks() {
+        let doc = |client_id| {
+            Doc::with_options(Options {
+                skip_gc: true,
+                ..Options::with_client_id(client_id)
+            })
+        };
+        let d1 = doc(1);
+        let d2 = doc(2);
+        let t1 = d1.transact().get_text("text");
+        let t2 = d2.transact().get_text("text");
+        t1.insert(&mut d1.transact(), 0, "hello");
+        exchange_updates(&[&d1, &d2]);
+        t2.insert(&mut d2.transact(), 5, " world");
+        {
+            let mut txn = d1.transact();
+            let meta = txn.get_map("meta");
+            meta.insert(&mut txn, "tags".to_owned(), PrelimArray::from(vec!["a"]));
+        }
+        exchange_updates(&[&d1, &d2]);
+        t2.remove_range(&mut d2.transact(), 0, 3);
+        exchange_updates(&[&d1, &d2]);
+
+        let txn = d1.transact();
+        let summary = |client| -> Vec<_> {
+            txn.client_blocks(client)
+                .into_iter()
+                .map(|b| {
+                    (
+                        b.clock,
+                        b.root.unwrap(),
+                        b.path,
+                        b.parent_sub,
+                        b.kind,
+                        b.preview.unwrap(),
+                        b.deleted,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            summary(1),
+            vec![
+                (
+                    0..3,
+                    "text".into(),
+                    vec![],
+                    None,
+                    "String",
+                    "hel".into(),
+                    true
+                ),
+                (
+                    3..5,
+                    "text".into(),
+                    vec![],
+                    None,
+                    "String",
+                    "lo".into(),
+                    false
+                ),
+                (
+                    5..6,
+                    "meta".into(),
+                    vec![],
+                    Some("tags".into()),
+                    "Type",
+                    "<YArray>".into(),
+                    false
+                ),
+                (
+                    6..7,
+                    "meta".into(),
+                    vec![PathSegment::Key("tags".into())],
+                    None,
+                    "Any",
+                    "\"a\"".into(),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            summary(2),
+            vec![(
+                0..6,
+                "text".into(),
+                vec![],
+                None,
+                "String",
+                " world".into(),
+                false
+            )]
+        );
+        assert!(txn.client_blocks(3).is_empty());
+    }
+
+    #[test]
+    fn client_id_conflict() {
+        let persisted = {
+            let doc = Doc::with_client_id(1);
+            let mut txn = doc.transact();
+            txn.get_text("text").push(&mut txn, "hello");
+            doc.encode_state_as_update_v1(&txn)
+        };
+        let content = |d: &Doc| {
+            let mut txn = d.transact();
+            txn.get_text("text").to_string(&txn)
+        };
+        let reload = |options: Options, chunk: &str| {
+            let mut doc = Doc::with_options(options);
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let sub = {
+                let events = events.clone();
+                doc.on_client_id_change(move |e| events.borrow_mut().push(e.clone()))
+            };
+            doc.apply_update_v1(&mut doc.transact(), &persisted)
+                .unwrap();
+            {
+                let mut txn = doc.transact();
+                txn.get_text("text").push(&mut txn, chunk);
+            }
+            (doc, events, sub)
+        };
+
+        // conflicting blocks are rejected by default
+        let (d1, events, _sub) = reload(Options::with_client_id(1), " world");
+        let (d2, _, _sub) = reload(Options::with_client_id(1), "!!!");
+        assert!(events.borrow().is_empty());
+        let u1 = d1.encode_state_as_update_v1(&d1.transact());
+        let u2 = d2.encode_state_as_update_v1(&d2.transact());
+        assert_eq!(
+            d1.apply_update_v1(&mut d1.transact(), &u2),
+            Err(Error::DuplicateClientClock {
+                client: 1,
+                clock: 5
+            })
+        );
+        // replica's own blocks are not considered a conflict
+        d1.apply_update_v1(&mut d1.transact(), &u1).unwrap();
+        assert_eq!(content(&d1), "hello world");
+
+        // replicas reloaded with the same client id replace it and converge
+        let options = Options {
+            auto_new_client_id_on_conflict: true,
+            ..Options::with_client_id(1)
+        };
+        let (d1, e1, _sub) = reload(options.clone(), " world");
+        let (d2, e2, _sub) = reload(options, "!!!");
+        for (d, events) in [(&d1, &e1), (&d2, &e2)] {
+            let events = events.borrow();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].previous, 1);
+            assert_eq!(events[0].duplicate_clock, None);
+            assert_eq!(d.transact().client_id(), events[0].current);
+        }
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(content(&d1), content(&d2));
+        assert!(content(&d1).contains(" world"));
+        assert!(content(&d1).contains("!!!"));
+
+        // colliding remote blocks are not integrated and local blocks keep their ids
+        let mut d3 = Doc::with_options(Options {
+            auto_new_client_id_on_conflict: true,
+            ..Options::with_client_id(3)
+        });
+        let d4 = Doc::with_client_id(3);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            d3.on_client_id_change(move |e| events.borrow_mut().push(e.clone()))
+        };
+        for (d, chunk) in [(&d3, "a"), (&d4, "b")] {
+            let mut txn = d.transact();
+            txn.get_text("text").push(&mut txn, chunk);
+        }
+        let u4 = d4.encode_state_as_update_v1(&d4.transact());
+        d3.apply_update_v1(&mut d3.transact(), &u4).unwrap();
+        let current = d3.transact().client_id();
+        assert_eq!(
+            *events.borrow(),
+            vec![ClientIdChangeEvent {
+                previous: 3,
+                current,
+                duplicate_clock: Some(0),
+            }]
+        );
+        assert_ne!(current, 3);
+        assert_eq!(d3.options().client_id, current);
+        assert_eq!(content(&d3), "a");
+
+        // new client id is used only for future changes
+        {
+            let mut txn = d3.transact();
+            txn.get_text("text").push(&mut txn, "c");
+        }
+        let sv = d3.get_state_vector(&d3.transact());
+        assert_eq!(sv.get(&3), 1);
+        assert_eq!(sv.get(&current), 1);
+        assert_eq!(content(&d3), "ac");
+    }
 }
@@ -0,0 +1,105 @@
+use crate::update::ClientRemapError;
+use lib0::decoding::DecodeError;
+
+/// Error returned by fallible operations on binary payloads (eg. document updates, state vectors
+/// or sync protocol messages), mostly when they could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Payload ended before all of the declared data could be read.
+    UnexpectedEof,
+    /// Variable length integer doesn't fit into its expected type.
+    VarIntOverflow,
+    /// String is not a valid UTF-8 sequence.
+    InvalidUtf8,
+    /// Shared type reference number is not recognized.
+    InvalidTypeRef(u8),
+    /// Block content type tag is not recognized, i.e. it was introduced by a newer version of
+    /// Yjs. Block contents are not length-prefixed, so a block of unknown type cannot be skipped
+    /// or preserved and the whole update is rejected instead.
+    UnsupportedContent(u8),
+    /// Payload declares lengths exceeding limits configured by
+    /// [DecoderOptions](lib0::decoding::DecoderOptions).
+    LimitExceeded(DecodeError),
+    /// Payload contains other data not recognized by a decoder.
+    InvalidData(&'static str),
+    /// Blocks of a remapped client would collide with existing blocks of a target client.
+    ClientRemap(ClientRemapError),
+    /// Applying an update or a local change could make a document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size).
+    QuotaExceeded {
+        /// Maximum content size of a document.
+        limit: usize,
+        /// Content size a document could reach after applying a change.
+        size: usize,
+    },
+    /// An update contains a block created by another replica using the same client id as
+    /// a current document, which differs from a local block with the same id. See:
+    /// [Options::auto_new_client_id_on_conflict](crate::Options::auto_new_client_id_on_conflict).
+    DuplicateClientClock {
+        /// Client id used by both replicas.
+        client: u64,
+        /// Clock of the first conflicting block.
+        clock: u32,
+    },
+    /// Content visible in a restored snapshot has been garbage collected. Snapshots can be
+    /// protected from garbage collection with [Doc::gc_protect](crate::Doc::gc_protect).
+    GarbageCollected {
+        /// Client id of the first garbage collected block.
+        client: u64,
+        /// Clock of the first garbage collected block.
+        clock: u32,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "unexpected end of payload"),
+            Error::VarIntOverflow => write!(f, "variable length integer overflow"),
+            Error::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            Error::InvalidTypeRef(type_ref) => write!(f, "unknown shared type ref: {}", type_ref),
+            Error::UnsupportedContent(tag) => {
+                write!(f, "unsupported block content type: {}", tag)
+            }
+            Error::LimitExceeded(e) => write!(f, "decoding limit exceeded: {}", e),
+            Error::InvalidData(msg) => write!(f, "invalid data: {}", msg),
+            Error::ClientRemap(e) => write!(f, "{}", e),
+            Error::QuotaExceeded { limit, size } => write!(
+                f,
+                "content size quota exceeded: {} bytes over the limit of {}",
+                size - limit,
+                limit
+            ),
+            Error::DuplicateClientClock { client, clock } => write!(
+                f,
+                "block {}:{} has been created by another replica using the same client id",
+                client, clock
+            ),
+            Error::GarbageCollected { client, clock } => write!(
+                f,
+                "block {}:{} visible in a snapshot has been garbage collected",
+                client, clock
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::UnexpectedEnd => Error::UnexpectedEof,
+            DecodeError::VarIntOverflow => Error::VarIntOverflow,
+            DecodeError::InvalidUtf8 => Error::InvalidUtf8,
+            DecodeError::InvalidData(msg) => Error::InvalidData(msg),
+            limit => Error::LimitExceeded(limit),
+        }
+    }
+}
+
+impl From<ClientRemapError> for Error {
+    fn from(e: ClientRemapError) -> Self {
+        Error::ClientRemap(e)
+    }
+}
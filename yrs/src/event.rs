@@ -1,15 +1,22 @@
+use crate::block::ID;
+use crate::block_store::StateVector;
 use crate::id_set::DeleteSet;
+use crate::transaction::TransactionStats;
 use crate::update::Update;
+use crate::Transaction;
+use lib0::any::Any;
 use rand::RngCore;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
 use std::rc::{Rc, Weak};
 
 pub(crate) struct EventHandler<T>(Rc<RefCell<Subscriptions<T>>>);
 
-type Subscriptions<T> = HashMap<u32, Box<dyn Fn(&T) -> ()>>;
+type Subscriptions<T> = HashMap<u32, Rc<dyn Fn(&T) -> ()>>;
 
-impl<T> EventHandler<T> {
+impl<T: 'static> EventHandler<T> {
     pub fn new() -> Self {
         EventHandler(Rc::new(RefCell::new(Subscriptions::new())))
     }
@@ -20,15 +27,24 @@ impl<T> EventHandler<T> {
     {
         let mut rng = rand::thread_rng();
         let id = rng.next_u32();
-        self.0.borrow_mut().insert(id, Box::new(f));
-        let subscriptions = Rc::downgrade(&self.0);
-        Subscription { id, subscriptions }
+        self.0.borrow_mut().insert(id, Rc::new(f));
+        let weak: Weak<RefCell<_>> = Rc::downgrade(&self.0);
+        Subscription::new(id, weak)
     }
 
-    pub fn publish(&self, arg: &T) {
-        let subscriptions = self.0.borrow_mut();
-        for f in subscriptions.values() {
-            f(arg);
+    /// Calls all subscribed callbacks with a given `arg`. Callbacks are free to subscribe or
+    /// unsubscribe (including themselves) while being called: new subscriptions are not called
+    /// until the next publish, while unsubscribed callbacks which were not called yet are skipped.
+    ///
+    /// A panic raised by one callback doesn't prevent the remaining ones from being called. It's
+    /// passed to `errors` handler instead of being propagated to the caller.
+    pub fn publish(&self, arg: &T, errors: &EventHandler<ObserverError>) {
+        for (id, f) in snapshot(&self.0) {
+            if self.0.borrow().contains_key(&id) {
+                if let Err(e) = call_isolated(|| f(arg)) {
+                    errors.report(e);
+                }
+            }
         }
     }
 
@@ -41,18 +57,191 @@ impl<T> EventHandler<T> {
     }
 }
 
+impl EventHandler<ObserverError> {
+    /// Passes a given `error` to all subscribed callbacks. Panics raised by these callbacks are
+    /// dropped, as there's nowhere left to report them.
+    pub fn report(&self, error: ObserverError) {
+        for (id, f) in snapshot(&self.0) {
+            if self.0.borrow().contains_key(&id) {
+                let _ = call_isolated(|| f(&error));
+            }
+        }
+    }
+}
+
+impl<T> Clone for EventHandler<T> {
+    fn clone(&self) -> Self {
+        EventHandler(self.0.clone())
+    }
+}
+
+/// Calls a given callback, catching a panic it may raise.
+fn call_isolated<F: FnOnce()>(f: F) -> Result<(), ObserverError> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| ObserverError { payload })
+}
+
+/// A panic raised by one of the callbacks subscribed to a document or its shared types. Instead
+/// of unwinding through the code, which has called the callback (ie. committing a transaction),
+/// it's caught and passed to callbacks registered with
+/// [Doc::on_observer_error](crate::Doc::on_observer_error), while the remaining callbacks are
+/// still called.
+///
+/// A panic message has already been written to stderr by a panic hook at this point, so errors
+/// are not reported anywhere else when no error callback has been registered.
+pub struct ObserverError {
+    payload: Box<dyn std::any::Any + Send>,
+}
+
+impl ObserverError {
+    /// Returns a message of a caught panic or `None` if it was raised with a payload other than
+    /// a string, eg. via [std::panic::panic_any].
+    pub fn message(&self) -> Option<&str> {
+        if let Some(msg) = self.payload.downcast_ref::<&'static str>() {
+            Some(msg)
+        } else {
+            self.payload.downcast_ref::<String>().map(String::as_str)
+        }
+    }
+
+    /// Returns a payload of a caught panic, ie. to resume unwinding with
+    /// [std::panic::resume_unwind].
+    pub fn into_payload(self) -> Box<dyn std::any::Any + Send> {
+        self.payload
+    }
+}
+
+impl std::fmt::Debug for ObserverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObserverError")
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl std::fmt::Display for ObserverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "observer callback panicked: {}",
+            self.message().unwrap_or("<non-string payload>")
+        )
+    }
+}
+
+/// An observer of changes made to a shared type, used to notify user-defined callbacks. Unlike
+/// [EventHandler], callbacks are called together with a transaction, in scope of which changes
+/// have been made.
+pub(crate) struct Observer<T>(Rc<RefCell<Callbacks<T>>>);
+
+type Callbacks<T> = HashMap<u32, Rc<dyn Fn(&Transaction, &T) -> ()>>;
+
+impl<T: 'static> Observer<T> {
+    pub fn new() -> Self {
+        Observer(Rc::new(RefCell::new(Callbacks::new())))
+    }
+
+    pub fn subscribe<F>(&self, f: F) -> Subscription<T>
+    where
+        F: Fn(&Transaction, &T) -> () + 'static,
+    {
+        let mut rng = rand::thread_rng();
+        let id = rng.next_u32();
+        self.0.borrow_mut().insert(id, Rc::new(f));
+        let weak: Weak<RefCell<_>> = Rc::downgrade(&self.0);
+        Subscription::new(id, weak)
+    }
+
+    /// Calls all subscribed callbacks with a given transaction and `arg`. Just like
+    /// [EventHandler::publish], it's safe to subscribe or unsubscribe while being called and
+    /// panicking callbacks are reported to the document's observer error handler.
+    pub fn publish(&self, txn: &Transaction, arg: &T) {
+        for (id, f) in snapshot(&self.0) {
+            if self.0.borrow().contains_key(&id) {
+                if let Err(e) = call_isolated(|| f(txn, arg)) {
+                    txn.store.observer_error_events.report(e);
+                }
+            }
+        }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.0.borrow().is_empty()
+    }
+}
+
+impl<T> Clone for Observer<T> {
+    fn clone(&self) -> Self {
+        Observer(self.0.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for Observer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observer({} subscribers)", self.0.borrow().len())
+    }
+}
+
+impl<T> PartialEq for Observer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Observer<T> {}
+
+/// Returns a copy of currently subscribed callbacks, so that they can be called without keeping
+/// the subscriptions borrowed.
+fn snapshot<F: ?Sized>(callbacks: &RefCell<HashMap<u32, Rc<F>>>) -> Vec<(u32, Rc<F>)> {
+    callbacks
+        .borrow()
+        .iter()
+        .map(|(&id, f)| (id, f.clone()))
+        .collect()
+}
+
+trait Unsubscribe {
+    fn unsubscribe(&self, id: u32);
+}
+
+impl<F: ?Sized> Unsubscribe for RefCell<HashMap<u32, Rc<F>>> {
+    fn unsubscribe(&self, id: u32) {
+        // callback is dropped after the borrow is released, as it may own other subscriptions
+        let removed = self.borrow_mut().remove(&id);
+        drop(removed);
+    }
+}
+
 /// A subscription handle to a custom user-defined callback for an event handler. When dropped,
 /// it will unsubscribe corresponding callback.
 pub struct Subscription<T> {
     id: u32,
-    subscriptions: Weak<RefCell<Subscriptions<T>>>,
+    subscriptions: Weak<dyn Unsubscribe>,
+    _marker: PhantomData<T>,
 }
 
-impl<T> Drop for Subscription<T> {
-    fn drop(&mut self) {
+impl<T> Subscription<T> {
+    fn new(id: u32, subscriptions: Weak<dyn Unsubscribe>) -> Self {
+        Subscription {
+            id,
+            subscriptions,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Unsubscribes a corresponding callback, so that it won't be called anymore. It's safe to
+    /// call it multiple times, from within the callback itself or after an observed document or
+    /// type has been dropped. Dropping a subscription unsubscribes it as well.
+    pub fn unsubscribe(&mut self) {
         if let Some(cell) = self.subscriptions.upgrade() {
-            cell.borrow_mut().remove(&self.id);
+            cell.unsubscribe(self.id);
         }
+        self.subscriptions = Weak::<RefCell<Subscriptions<()>>>::new();
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.unsubscribe();
     }
 }
 
@@ -73,15 +262,176 @@ impl UpdateEvent {
     }
 }
 
+/// An event emitted by a document once a transaction, which made any changes to it, has been
+/// committed. It's emitted for local changes as well as for integrated remote updates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitEvent {
+    /// Changes made in scope of a committed transaction: blocks inserted and deleted by it,
+    /// encoded as an update using lib0 ver. 1 encoding.
+    pub update: Vec<u8>,
+    /// Whether committed changes come from local operations, remote updates or both. Network
+    /// providers can use it to avoid sending remote changes back to their peers.
+    pub source: ChangeSource,
+    /// An origin of a committed transaction. See: [Transaction::origin].
+    pub origin: Option<Any>,
+    /// Names of root-level types changed in scope of a committed transaction, either directly or
+    /// through any of the types nested in them, in alphabetical order.
+    pub roots: Vec<String>,
+    /// Whether a committed transaction has deleted any elements.
+    pub has_deletions: bool,
+}
+
+impl CommitEvent {
+    /// Returns a length of an encoded [CommitEvent::update] in bytes.
+    pub fn size(&self) -> usize {
+        self.update.len()
+    }
+}
+
+/// Describes where the changes made in scope of a transaction come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeSource {
+    /// All changes have been made by local operations, ie. inserting text or removing map entries.
+    /// Transactions with no changes at all are also considered to be local.
+    Local,
+    /// All changes come from remote updates, integrated with [Transaction::apply_update].
+    Remote,
+    /// Transaction contains both local changes and integrated remote updates.
+    Mixed,
+}
+
+impl ChangeSource {
+    /// Checks if changes come from local operations only.
+    pub fn is_local(&self) -> bool {
+        *self == ChangeSource::Local
+    }
+
+    /// Checks if changes come from remote updates only.
+    pub fn is_remote(&self) -> bool {
+        *self == ChangeSource::Remote
+    }
+}
+
+/// An event emitted by a document, whenever subdocuments have been added, removed or requested to
+/// be loaded in scope of a transaction. Subdocuments are identified by their guids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubdocsEvent {
+    /// Guids of subdocuments inserted into a document.
+    pub added: Vec<String>,
+    /// Guids of subdocuments removed from a document.
+    pub removed: Vec<String>,
+    /// Guids of subdocuments, which content should be loaded by the application.
+    pub loaded: Vec<String>,
+}
+
+/// An event emitted by a document, when a transaction which created new root-level types is
+/// being committed. It includes root types defined locally as well as the ones introduced by
+/// integrated remote updates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RootTypesEvent {
+    /// Names and kinds (see: [Value::kind](crate::Value::kind)) of created root types, ordered by
+    /// name. Kinds of root types introduced by remote updates are not known until they are
+    /// accessed locally, therefore they are reported as `"undefined"`.
+    pub created: Vec<(String, &'static str)>,
+    /// Names of created root types, which are not declared in a document schema (see:
+    /// [Options::schema](crate::Options::schema)), ordered by name. Always empty for documents
+    /// without a schema.
+    pub non_schema: Vec<String>,
+}
+
+/// An event emitted by a document once it's being destroyed (see: [Doc::destroy](crate::Doc::destroy)).
+/// Providers and other integrations should use it to release resources they hold for a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DestroyEvent {
+    /// Guid of a destroyed document.
+    pub guid: String,
+    /// Guids of subdocuments embedded within a destroyed document. Since their content is
+    /// managed by the application, it's up to the application to destroy them as well.
+    pub subdocs: Vec<String>,
+}
+
+/// An event emitted by a document, whenever its client id has been replaced with a new one, because
+/// another replica using the same client id has been detected. See:
+/// [Options::auto_new_client_id_on_conflict](crate::Options::auto_new_client_id_on_conflict).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIdChangeEvent {
+    /// Client id used by a document before the change.
+    pub previous: u64,
+    /// Client id used by a document from now on.
+    pub current: u64,
+    /// Clock of the first remote block, which content differs from a local block with the same id.
+    /// Such blocks cannot be integrated. `None` if remote blocks didn't collide with local ones.
+    pub duplicate_clock: Option<u32>,
+}
+
+/// An event emitted by a document, whenever a new transaction has been started, before any
+/// changes are made in its scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeforeTransactionEvent {
+    /// Origin of a started transaction, as passed to [Doc::transact_with_origin](crate::Doc::transact_with_origin).
+    pub origin: Option<Any>,
+    /// State vector of a document at the moment when a transaction has been started.
+    pub before_state: StateVector,
+}
+
+/// An event emitted by a document, when a transaction is being committed. It summarizes all of
+/// the changes made in scope of that transaction, both local ones and integrated remote updates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AfterTransactionEvent {
+    /// Origin of a committed transaction, as passed to [Doc::transact_with_origin](crate::Doc::transact_with_origin).
+    pub origin: Option<Any>,
+    /// State vector of a document at the moment when a transaction has been started.
+    pub before_state: StateVector,
+    /// State vector of a document after all changes made by a transaction.
+    pub after_state: StateVector,
+    /// Blocks deleted in scope of a transaction.
+    pub delete_set: DeleteSet,
+    /// Shared types which have been directly modified in scope of a transaction (property added
+    /// or child inserted/deleted). Types created or deleted by a transaction are not included.
+    pub changed: HashSet<BranchId>,
+    /// Shared types which have been directly modified in scope of a transaction together with all
+    /// of their parents up to the root-level types.
+    pub changed_parent_types: HashSet<BranchId>,
+    /// Whether changes come from local operations, remote updates or both.
+    pub source: ChangeSource,
+    /// Ids of clients, which blocks have been integrated from remote updates.
+    pub integrated_clients: HashSet<u64>,
+    /// Counters of operations performed by a transaction.
+    pub stats: TransactionStats,
+}
+
+impl AfterTransactionEvent {
+    /// Checks if a root-level type with a given `name` or any of its nested types have been
+    /// modified in scope of a transaction.
+    pub fn is_root_changed(&self, name: &str) -> bool {
+        self.changed_parent_types
+            .iter()
+            .any(|id| matches!(id, BranchId::Root(root) if root == name))
+    }
+}
+
+/// Identifier of a shared type within a document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BranchId {
+    /// A root-level type, identified by its name.
+    Root(String),
+    /// A type nested within another shared type, identified by an ID of a block containing it.
+    Nested(ID),
+}
+
 #[cfg(test)]
 mod test {
-    use crate::event::EventHandler;
+    use crate::event::{EventHandler, Subscription};
+    use crate::Doc;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
     use std::sync::atomic::{AtomicU32, Ordering};
     use std::sync::Arc;
 
     #[test]
     fn subscription() {
         let mut eh: EventHandler<u32> = EventHandler::new();
+        let errors = EventHandler::new();
         let s1_state = Arc::new(AtomicU32::new(0));
         let s2_state = Arc::new(AtomicU32::new(0));
 
@@ -93,11 +443,11 @@ mod test {
             let _s2 = eh.subscribe(move |value| b.store(*value * 2, Ordering::Release));
             assert_eq!(eh.subscription_count(), 2);
 
-            eh.publish(&1);
+            eh.publish(&1, &errors);
             assert_eq!(s1_state.load(Ordering::Acquire), 1);
             assert_eq!(s2_state.load(Ordering::Acquire), 2);
 
-            eh.publish(&2);
+            eh.publish(&2, &errors);
             assert_eq!(s1_state.load(Ordering::Acquire), 2);
             assert_eq!(s2_state.load(Ordering::Acquire), 4);
         }
@@ -106,8 +456,126 @@ mod test {
         assert_eq!(eh.subscription_count(), 0);
 
         // subscriptions were dropped, we don't expect updates to be propagated
-        eh.publish(&3);
+        eh.publish(&3, &errors);
         assert_eq!(s1_state.load(Ordering::Acquire), 2);
         assert_eq!(s2_state.load(Ordering::Acquire), 4);
     }
+
+    #[test]
+    fn unsubscribe_inside_callback() {
+        let mut eh: EventHandler<u32> = EventHandler::new();
+        let errors = EventHandler::new();
+        let calls = Rc::new(Cell::new(0));
+        let sub: Rc<RefCell<Option<Subscription<u32>>>> = Rc::new(RefCell::new(None));
+        let s = {
+            let calls = calls.clone();
+            let sub = sub.clone();
+            eh.subscribe(move |_| {
+                calls.set(calls.get() + 1);
+                // unsubscribe itself - it's safe to do it more than once
+                if let Some(s) = sub.borrow_mut().as_mut() {
+                    s.unsubscribe();
+                    s.unsubscribe();
+                }
+            })
+        };
+        sub.borrow_mut().replace(s);
+
+        eh.publish(&1, &errors);
+        eh.publish(&2, &errors);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(eh.subscription_count(), 0);
+
+        // subscriptions can be created again after unsubscribing
+        let _s = {
+            let calls = calls.clone();
+            eh.subscribe(move |_| calls.set(calls.get() + 1))
+        };
+        eh.publish(&3, &errors);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(eh.subscription_count(), 1);
+    }
+
+    #[test]
+    fn unsubscribe_other_inside_callback() {
+        let mut eh: EventHandler<u32> = EventHandler::new();
+        let errors = EventHandler::new();
+        let calls = Rc::new(Cell::new(0));
+        let subs: Rc<RefCell<Vec<Subscription<u32>>>> = Rc::new(RefCell::new(Vec::new()));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            let inner = subs.clone();
+            let s = eh.subscribe(move |_| {
+                calls.set(calls.get() + 1);
+                // the first called callback unsubscribes both of them
+                inner.borrow_mut().clear();
+            });
+            subs.borrow_mut().push(s);
+        }
+
+        eh.publish(&1, &errors);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(eh.subscription_count(), 0);
+    }
+
+    #[test]
+    fn drop_doc_before_subscription() {
+        let mut doc = Doc::new();
+        let mut sub = doc.on_commit(|_| {});
+        let mut deep_sub = {
+            let mut txn = doc.transact();
+            txn.get_xml_element("xml").observe_deep(|_, _| {})
+        };
+        drop(doc);
+        sub.unsubscribe();
+        deep_sub.unsubscribe();
+    }
+
+    #[test]
+    fn panicking_observer_is_isolated() {
+        let mut doc = Doc::with_client_id(1);
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let _err_sub = {
+            let errors = errors.clone();
+            doc.on_observer_error(move |e| errors.borrow_mut().push(e.message().map(str::to_owned)))
+        };
+        let calls = Rc::new(Cell::new(0));
+        let text = doc.transact().get_text("text");
+        let _subs: Vec<_> = (0..3)
+            .map(|i| {
+                let calls = calls.clone();
+                text.observe(move |_, _| {
+                    if i == 1 {
+                        panic!("observer {} failed", i);
+                    }
+                    calls.set(calls.get() + 1);
+                })
+            })
+            .collect();
+        let _commit_subs: Vec<_> = (0..3)
+            .map(|i| {
+                let calls = calls.clone();
+                doc.on_commit(move |_| {
+                    if i == 1 {
+                        std::panic::panic_any(i);
+                    }
+                    calls.set(calls.get() + 1);
+                })
+            })
+            .collect();
+
+        {
+            let mut txn = doc.transact();
+            text.push(&mut txn, "abc");
+        }
+
+        // remaining callbacks were still called, while committing code didn't see a panic
+        assert_eq!(calls.get(), 4);
+        assert_eq!(
+            *errors.borrow(),
+            vec![Some("observer 1 failed".to_owned()), None]
+        );
+        let txn = doc.transact();
+        assert_eq!(text.to_string(&txn), "abc");
+    }
 }
@@ -20,10 +20,13 @@ impl Encode for Range<u32> {
 }
 
 impl Decode for Range<u32> {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
-        let clock = decoder.read_ds_clock();
-        let len = decoder.read_ds_len();
-        clock..(clock + len)
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let clock = decoder.read_ds_clock()?;
+        let len = decoder.read_ds_len()?;
+        let end = clock
+            .checked_add(len)
+            .ok_or(Error::InvalidData("delete set range overflow"))?;
+        Ok(clock..end)
     }
 }
 
@@ -83,6 +86,18 @@ impl IdRange {
         }
     }
 
+    /// Returns a range containing a given `clock`. Ranges of a fragmented [IdRange] are binary
+    /// searched, so they must be squashed first.
+    pub(crate) fn find(&self, clock: u32) -> Option<&Range<u32>> {
+        match self {
+            IdRange::Continuous(range) => Some(range).filter(|r| r.contains(&clock)),
+            IdRange::Fragmented(ranges) => {
+                let i = ranges.partition_point(|r| r.end <= clock);
+                ranges.get(i).filter(|r| r.contains(&clock))
+            }
+        }
+    }
+
     /// Iterate over ranges described by current [IdRange].
     pub fn iter(&self) -> IdRangeIter<'_> {
         let (range, inner) = match self {
@@ -95,8 +110,10 @@ impl IdRange {
     fn push(&mut self, range: Range<u32>) {
         match self {
             IdRange::Continuous(r) => {
-                if r.end >= range.start {
-                    r.end = range.end; // two ranges overlap, we can eagerly merge them
+                if r.end >= range.start && r.start <= range.end {
+                    // two ranges overlap, we can eagerly merge them
+                    r.start = r.start.min(range.start);
+                    r.end = r.end.max(range.end);
                 } else {
                     *self = IdRange::Fragmented(vec![r.clone(), range])
                 }
@@ -123,7 +140,7 @@ impl IdRange {
                         let next = head.offset(i).as_ref().unwrap();
                         if next.start <= current.end {
                             // merge next to current eg. curr=[0,5) & next=[3,6) => curr=[0,6)
-                            current.end = next.end;
+                            current.end = current.end.max(next.end);
                         } else {
                             // current and next are disjoined eg. [0,5) & [6,9)
 
@@ -133,7 +150,7 @@ impl IdRange {
 
                             // make next a new current
                             current.start = next.start;
-                            current.end = next.end;
+                            current.end = current.end.max(next.end);
                             new_len += 1;
                         }
 
@@ -142,7 +159,7 @@ impl IdRange {
                 }
 
                 if new_len == 1 {
-                    *self = IdRange::Continuous(ranges.pop().unwrap())
+                    *self = IdRange::Continuous(ranges.swap_remove(0))
                 } else if ranges.len() != new_len as usize {
                     ranges.truncate(new_len as usize);
                 }
@@ -169,20 +186,20 @@ impl Encode for IdRange {
 }
 
 impl Decode for IdRange {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
-        match decoder.read_len() {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        match decoder.read_collection_len()? {
             1 => {
-                let range = Range::decode(decoder);
-                IdRange::Continuous(range)
+                let range = Range::decode(decoder)?;
+                Ok(IdRange::Continuous(range))
             }
             len => {
                 let mut ranges = Vec::with_capacity(len as usize);
                 let mut i = 0;
                 while i < len {
-                    ranges.push(Range::decode(decoder));
+                    ranges.push(Range::decode(decoder)?);
                     i += 1;
                 }
-                IdRange::Fragmented(ranges)
+                Ok(IdRange::Fragmented(ranges))
             }
         }
     }
@@ -226,7 +243,7 @@ impl<'a> DoubleEndedIterator for IdRangeIter<'a> {
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct IdSet(HashMap<u64, IdRange, BuildHasherDefault<ClientHasher>>);
 
-pub(crate) type Iter<'a> = std::collections::hash_map::Iter<'a, u64, IdRange>;
+pub type Iter<'a> = std::collections::hash_map::Iter<'a, u64, IdRange>;
 
 //TODO: I'd say we should split IdSet and DeleteSet into two structures. While DeleteSet can be
 // implemented in terms of IdSet, it has more specific methods (related to deletion process), while
@@ -249,6 +266,11 @@ impl IdSet {
         }
     }
 
+    /// Returns clock ranges of a given `client`.
+    pub(crate) fn get(&self, client: &u64) -> Option<&IdRange> {
+        self.0.get(client)
+    }
+
     /// Checks if current ID set contains any data.
     pub fn is_empty(&self) -> bool {
         self.0.is_empty() || self.0.values().all(|r| r.is_empty())
@@ -288,7 +310,7 @@ impl IdSet {
                     match (r, range) {
                         (IdRange::Continuous(r1), IdRange::Continuous(r2)) => {
                             if r1.end >= r2.start && r1.start <= r2.start {
-                                r1.end = r2.end;
+                                r1.end = r1.end.max(r2.end);
                             } else {
                                 let new = IdRange::Fragmented(vec![r1.clone(), r2.clone()]);
                                 e.replace_entry(new);
@@ -330,18 +352,18 @@ impl Encode for IdSet {
 }
 
 impl Decode for IdSet {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
         let mut set = Self::new();
-        let client_len = decoder.read_len();
+        let client_len = decoder.read_collection_len()?;
         let mut i = 0;
         while i < client_len {
             decoder.reset_ds_cur_val();
-            let client = decoder.read_client();
-            let range = IdRange::decode(decoder);
+            let client = decoder.read_client()?;
+            let range = IdRange::decode(decoder)?;
             set.0.insert(client, range);
             i += 1;
         }
-        set
+        Ok(set)
     }
 }
 
@@ -362,7 +384,8 @@ impl<'a> From<&'a BlockStore> for DeleteSet {
     fn from(store: &'a BlockStore) -> Self {
         let mut set = DeleteSet(IdSet::new());
         for (&client, blocks) in store.iter() {
-            let mut deletes = IdRange::with_capacity(blocks.len());
+            // most of the clients usually have no deleted blocks, so don't allocate upfront
+            let mut deletes = IdRange::with_capacity(0);
             for block in blocks.iter() {
                 if block.is_deleted() {
                     let start = block.id().clock;
@@ -438,6 +461,16 @@ impl DeleteSet {
         self.0.contains(id)
     }
 
+    /// Checks if given `id` is included in any of the clock ranges of current delete set.
+    pub fn contains(&self, id: &ID) -> bool {
+        self.0.contains(id)
+    }
+
+    /// Returns deleted clock ranges of a given `client`.
+    pub(crate) fn get(&self, client: &u64) -> Option<&IdRange> {
+        self.0.get(client)
+    }
+
     /// Returns an iterator over all client-range pairs registered in this delete set.
     pub fn iter(&self) -> Iter<'_> {
         self.0.iter()
@@ -462,8 +495,8 @@ impl DeleteSet {
 }
 
 impl Decode for DeleteSet {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
-        DeleteSet(IdSet::decode(decoder))
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        Ok(DeleteSet(IdSet::decode(decoder)?))
     }
 }
 
@@ -477,8 +510,8 @@ impl Encode for DeleteSet {
 mod test {
     use crate::id_set::{IdRange, IdSet};
     use crate::updates::decoder::{Decode, DecoderV1};
-    use crate::updates::encoder::{Encode, Encoder, EncoderV1};
-    use crate::ID;
+    use crate::updates::encoder::{Encode, EncoderV1};
+    use crate::{decode_delete_set_v1, encode_delete_set_v1, Doc, ID};
     use std::fmt::Debug;
 
     #[test]
@@ -488,6 +521,17 @@ mod test {
         assert_eq!(r, IdRange::Fragmented(vec![(0..5), (6..7)]));
     }
 
+    #[test]
+    fn id_range_compact_into_continuous() {
+        let mut r = IdRange::Fragmented(vec![(2..3), (0..1), (1..2)]);
+        r.squash();
+        assert_eq!(r, IdRange::Continuous(0..3));
+
+        let mut r = IdRange::Fragmented(vec![(0..5), (1..2)]);
+        r.squash();
+        assert_eq!(r, IdRange::Continuous(0..5));
+    }
+
     #[test]
     fn id_range_invert() {
         assert!(IdRange::Continuous(0..3).invert().is_empty());
@@ -554,6 +598,22 @@ mod test {
         roundtrip(&set);
     }
 
+    #[test]
+    fn id_set_merge_contained_range() {
+        let mut set = IdSet::new();
+        set.insert(ID::new(1, 0), 10);
+        let mut other = IdSet::new();
+        other.insert(ID::new(1, 2), 3);
+        set.merge(other);
+        assert_eq!(set.get(&1), Some(&IdRange::Continuous(0..10)));
+        assert!(set.contains(&ID::new(1, 9)));
+
+        let mut other = IdSet::new();
+        other.insert(ID::new(1, 8), 4);
+        set.merge(other);
+        assert_eq!(set.get(&1), Some(&IdRange::Continuous(0..12)));
+    }
+
     fn roundtrip<T>(value: &T)
     where
         T: Encode + Decode + PartialEq + Debug,
@@ -562,8 +622,52 @@ mod test {
         value.encode(&mut encoder);
         let buf = encoder.to_vec();
         let mut decoder = DecoderV1::from(buf.as_slice());
-        let decoded = T::decode(&mut decoder);
+        let decoded = T::decode(&mut decoder).unwrap();
 
         assert_eq!(value, &decoded);
     }
+
+    #[test]
+    fn delete_set_of_removed_array_range() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let array = txn.get_array("array");
+        array.insert_range(&mut txn, 0, vec![1, 2, 3, 4, 5]);
+        assert!(txn.delete_set().is_empty());
+        assert!(!txn.is_deleted(&ID::new(1, 2)));
+
+        array.remove_range(&mut txn, 1, 3);
+        let ranges: Vec<_> = txn
+            .delete_set()
+            .iter()
+            .map(|(&client, range)| (client, range.iter().cloned().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(ranges, vec![(1, vec![1..4])]);
+
+        let deleted: Vec<_> = (0..6)
+            .map(|clock| txn.is_deleted(&ID::new(1, clock)))
+            .collect();
+        assert_eq!(deleted, vec![false, true, true, true, false, false]);
+        assert!(txn.delete_set().contains(&ID::new(1, 3)));
+        assert!(!txn.delete_set().contains(&ID::new(1, 4)));
+        assert!(!txn.is_deleted(&ID::new(2, 0)));
+
+        let mut ds = doc.delete_set(&txn);
+        ds.squash();
+        assert_eq!(ds.iter().next(), Some((&1, &IdRange::Continuous(1..4))));
+
+        let encoded = encode_delete_set_v1(&ds);
+        assert_eq!(decode_delete_set_v1(&encoded).unwrap(), ds);
+        assert!(decode_delete_set_v1(&encoded[..encoded.len() - 1]).is_err());
+
+        // deletions are visible to remote peers after applying an update
+        let update = doc.encode_state_as_update_v1(&txn);
+        let remote = Doc::with_client_id(2);
+        let mut txn = remote.transact();
+        remote.apply_update_v1(&mut txn, &update).unwrap();
+        assert!(txn.is_deleted(&ID::new(1, 2)));
+        assert!(!txn.is_deleted(&ID::new(1, 4)));
+        assert!(txn.delete_set().is_deleted(&ID::new(1, 1)));
+        assert!(remote.delete_set(&txn).is_deleted(&ID::new(1, 1)));
+    }
 }
@@ -0,0 +1,134 @@
+use crate::block::{Block, BlockPtr, Item, ID};
+use crate::Transaction;
+use std::fmt::Formatter;
+
+/// A single violation of block store invariants found by
+/// [Doc::integrity_check](crate::Doc::integrity_check). Documents built only by integrating
+/// updates are expected to never report any of them - if they do, it usually means that
+/// the document has been corrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityViolation {
+    /// Blocks of a given client don't form a contiguous range of clock values starting from 0.
+    /// Contains a clock value, at which the next block was expected and a clock value of a block
+    /// found instead.
+    ClockGap {
+        client: u64,
+        expected: u32,
+        found: u32,
+    },
+    /// A parent type of a block with a given ID could not be resolved.
+    MissingParent(ID),
+    /// An origin (first ID) of a block (second ID) refers to an element not present in
+    /// a document.
+    MissingOrigin(ID, ID),
+    /// A left neighbour of a block with a given ID doesn't exist or doesn't point back to it.
+    BrokenLeftLink(ID),
+    /// A right neighbour of a block with a given ID doesn't exist or doesn't point back to it.
+    BrokenRightLink(ID),
+    /// A block with a given ID is the first element of a sequence or the last value of a map
+    /// entry, but its parent type doesn't point to it.
+    BrokenParentLink(ID),
+}
+
+impl std::fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityViolation::ClockGap {
+                client,
+                expected,
+                found,
+            } => write!(
+                f,
+                "blocks of client {} expected at clock {}, found at {}",
+                client, expected, found
+            ),
+            IntegrityViolation::MissingParent(id) => write!(f, "{}: parent not found", id),
+            IntegrityViolation::MissingOrigin(origin, id) => {
+                write!(f, "{}: origin {} not found", id, origin)
+            }
+            IntegrityViolation::BrokenLeftLink(id) => write!(f, "{}: broken left link", id),
+            IntegrityViolation::BrokenRightLink(id) => write!(f, "{}: broken right link", id),
+            IntegrityViolation::BrokenParentLink(id) => write!(f, "{}: broken parent link", id),
+        }
+    }
+}
+
+/// Validates invariants of all blocks stored within a document, ordered by their client and
+/// clock values.
+pub(crate) fn check(txn: &Transaction) -> Vec<IntegrityViolation> {
+    let mut clients: Vec<u64> = txn.store.blocks.iter().map(|(client, _)| *client).collect();
+    clients.sort_unstable();
+    let mut violations = Vec::new();
+    for client in clients {
+        let blocks = txn.store.blocks.get(&client).unwrap();
+        let mut expected = 0;
+        for block in blocks.iter() {
+            let id = *block.id();
+            if id.clock != expected || matches!(block, Block::Skip(_)) {
+                violations.push(IntegrityViolation::ClockGap {
+                    client,
+                    expected,
+                    found: id.clock,
+                });
+            }
+            expected = id.clock + block.len();
+            if let Block::Item(item) = block {
+                check_item(txn, item, &mut violations);
+            }
+        }
+    }
+    violations
+}
+
+fn check_item(txn: &Transaction, item: &Item, violations: &mut Vec<IntegrityViolation>) {
+    let id = item.id;
+    for origin in item.origin.iter().chain(item.right_origin.iter()) {
+        if txn.store.blocks.get_state(&origin.client) <= origin.clock {
+            violations.push(IntegrityViolation::MissingOrigin(*origin, id));
+        }
+    }
+    if let Some(left) = item.left.as_ref() {
+        if !points_to(txn, left, |n| n.right.as_ref(), item) {
+            violations.push(IntegrityViolation::BrokenLeftLink(id));
+        }
+    }
+    if let Some(right) = item.right.as_ref() {
+        if !points_to(txn, right, |n| n.left.as_ref(), item) {
+            violations.push(IntegrityViolation::BrokenRightLink(id));
+        }
+    }
+    match txn.store.get_type(&item.parent) {
+        None => violations.push(IntegrityViolation::MissingParent(id)),
+        Some(branch) => {
+            let branch = branch.borrow();
+            let linked = match item.parent_sub.as_ref() {
+                None if item.left.is_none() => branch.start.as_ref(),
+                Some(key) if item.right.is_none() => branch.map.get(key),
+                _ => return,
+            };
+            if !matches!(linked, Some(ptr) if links_to(ptr, item)) {
+                violations.push(IntegrityViolation::BrokenParentLink(id));
+            }
+        }
+    }
+}
+
+/// Checks if a `neighbour` block exists and links back to a given `item`.
+fn points_to<F>(txn: &Transaction, neighbour: &BlockPtr, back: F, item: &Item) -> bool
+where
+    F: Fn(&Item) -> Option<&BlockPtr>,
+{
+    match txn.store.blocks.get_item(neighbour).and_then(back) {
+        Some(ptr) => links_to(ptr, item),
+        None => false,
+    }
+}
+
+/// Checks if a given pointer refers to a given `item`. Pointers may refer to any element of
+/// a block, not necessarily to its first one.
+fn links_to(ptr: &BlockPtr, item: &Item) -> bool {
+    let id = &ptr.id;
+    id.client == item.id.client
+        && id.clock >= item.id.clock
+        && id.clock < item.id.clock + item.len()
+}
@@ -24,12 +24,22 @@
 //! build them easily on your own.
 
 mod alt;
+pub mod awareness;
 mod block;
 mod block_store;
+mod compare;
 mod doc;
+mod error;
 mod event;
 mod id_set;
+mod integrity;
+mod permanent_user_data;
+pub mod persistence;
+mod relative_position;
+mod snapshot;
 mod store;
+pub mod sync;
+mod timestamps;
 mod transaction;
 mod types;
 mod update;
@@ -41,16 +51,80 @@ mod compatibility_tests;
 #[cfg(test)]
 mod test_utils;
 
-pub use crate::alt::{diff_updates, encode_state_vector_from_update, merge_updates};
+pub use crate::alt::{
+    blocks_only_update, decode_delete_set_v1, delete_set_from_update, deletions_only_update,
+    diff_updates, encode_delete_set_v1, encode_state_vector_from_update, filter_update_clients,
+    merge_updates, remap_update_client, split_update_v1,
+};
+#[cfg(feature = "compression")]
+pub use crate::alt::{compress_update, decompress_update};
+pub use crate::block::BlockInfo;
+pub use crate::block::ItemInfo;
+pub use crate::block::Prelim;
 pub use crate::block::ID;
 pub use crate::block_store::StateVector;
+pub use crate::compare::Mismatch;
 pub use crate::doc::Doc;
+pub use crate::doc::DocDescription;
+pub use crate::doc::Options;
+pub use crate::doc::RootDescription;
+pub use crate::doc::SubDoc;
+pub use crate::error::Error;
+pub use crate::event::AfterTransactionEvent;
+pub use crate::event::BeforeTransactionEvent;
+pub use crate::event::BranchId;
+pub use crate::event::ChangeSource;
+pub use crate::event::ClientIdChangeEvent;
+pub use crate::event::CommitEvent;
+pub use crate::event::DestroyEvent;
+pub use crate::event::ObserverError;
+pub use crate::event::RootTypesEvent;
+pub use crate::event::SubdocsEvent;
+pub use crate::event::Subscription;
+pub use crate::id_set::DeleteSet;
+pub use crate::id_set::IdRange;
+pub use crate::integrity::IntegrityViolation;
+pub use crate::permanent_user_data::PermanentUserData;
+pub use crate::relative_position::AbsolutePosition;
+pub use crate::relative_position::PositionError;
+pub use crate::relative_position::RelativePosition;
+pub use crate::snapshot::Snapshot;
+pub use crate::snapshot::Version;
+pub use crate::snapshot::VERSIONS_ROOT;
+pub use crate::store::CompactionReport;
+pub use crate::store::StoreStats;
+pub use crate::timestamps::EditTimestamp;
+pub use crate::timestamps::TIMESTAMPS_ROOT;
+pub use crate::transaction::RevertConflict;
+pub use crate::transaction::RevertReport;
 pub use crate::transaction::Transaction;
+pub use crate::transaction::TransactionStats;
 pub use crate::types::array::Array;
+pub use crate::types::array::ArrayCursor;
+pub use crate::types::array::ArrayEvent;
 pub use crate::types::array::PrelimArray;
 pub use crate::types::map::Map;
+pub use crate::types::map::MapEvent;
 pub use crate::types::map::PrelimMap;
+pub use crate::types::text::Attrs;
+pub use crate::types::text::Diff;
 pub use crate::types::text::Text;
+pub use crate::types::text::TextCursor;
+pub use crate::types::text::TextEvent;
 pub use crate::types::xml::Xml;
 pub use crate::types::xml::XmlElement;
+pub use crate::types::xml::XmlEvent;
+pub use crate::types::xml::XmlFragment;
+pub use crate::types::xml::XmlParseError;
 pub use crate::types::xml::XmlText;
+pub use crate::types::xml::XmlTextEvent;
+pub use crate::types::Change;
+pub use crate::types::Delta;
+pub use crate::types::EntryChange;
+pub use crate::types::Event;
+pub use crate::types::Path;
+pub use crate::types::PathSegment;
+pub use crate::types::PrelimValue;
+pub use crate::types::Value;
+pub use crate::update::ClientRemapError;
+pub use crate::update::Update;
@@ -0,0 +1,207 @@
+use crate::types::{Value, TYPE_REFS_MAP};
+use crate::updates::decoder::Decode;
+use crate::updates::encoder::Encode;
+use crate::{Array, DeleteSet, Map, PrelimArray, PrelimMap, Transaction, ID};
+use lib0::any::Any;
+use std::collections::HashMap;
+
+/// Name of a root-level map used by Yjs to store user mappings.
+pub const USERS_ROOT: &str = "users";
+
+const IDS: &str = "ids";
+const DELETE_SETS: &str = "ds";
+
+/// A persistent mapping between ephemeral client ids and user identities, compatible with Yjs
+/// `PermanentUserData`. It's stored as a root-level map, where every user id owns an array of
+/// client ids (`"ids"`) and an array of encoded delete sets (`"ds"`) describing deletions made by
+/// that user. Since mappings are part of a document itself, they survive across sessions and are
+/// replicated together with other document changes.
+#[derive(Debug, Clone)]
+pub struct PermanentUserData {
+    users: Map,
+}
+
+impl PermanentUserData {
+    /// Creates a new permanent user data backed by a default `"users"` root map.
+    pub fn new(txn: &mut Transaction) -> Self {
+        Self::with_root(txn, USERS_ROOT)
+    }
+
+    /// Creates a new permanent user data backed by a root map with a given `name`.
+    pub fn with_root(txn: &mut Transaction, name: &str) -> Self {
+        PermanentUserData {
+            users: Map::from(txn.store.create_type(name, None, TYPE_REFS_MAP)),
+        }
+    }
+
+    /// Records that all changes made by a given `client_id` have been authored by `user_id`.
+    /// The same user may be mapped to many client ids, ie. one per every editing session.
+    pub fn set_user_mapping(&self, txn: &mut Transaction, client_id: u64, user_id: &str) {
+        let user = self.user_map(txn, user_id);
+        let ids = Self::array(txn, &user, IDS);
+        let client = Any::Number(client_id as f64);
+        let exists = ids.iter(txn).any(|v| v == Value::Any(client.clone()));
+        if !exists {
+            ids.push_back(txn, client);
+        }
+    }
+
+    /// Stores deletions made in scope of a given transaction on behalf of a user mapped to the
+    /// client id of a current document. This should be called right before transaction is
+    /// committed. Returns `false` if nothing was recorded, either because transaction didn't
+    /// delete anything or because local client has not been mapped to any user.
+    pub fn record_deletes(&self, txn: &mut Transaction) -> bool {
+        if txn.delete_set.is_empty() {
+            return false;
+        }
+        let client_id = txn.store.client_id;
+        if let Some(user_id) = self.user_by_client_id(txn, client_id) {
+            let mut ds = txn.delete_set.clone();
+            ds.squash();
+            let user = self.user_map(txn, &user_id);
+            let dss = Self::array(txn, &user, DELETE_SETS);
+            dss.push_back(txn, Any::Buffer(ds.encode_v1().into_boxed_slice()));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns an id of a user, which has been mapped to a given `client_id`.
+    pub fn user_by_client_id(&self, txn: &Transaction, client_id: u64) -> Option<String> {
+        let client = Value::Any(Any::Number(client_id as f64));
+        self.find_user(txn, IDS, |ids| ids.iter(txn).any(|v| v == client))
+    }
+
+    /// Returns an id of a user, who deleted a block with a given `id`. Only deletions stored with
+    /// [PermanentUserData::record_deletes] are taken into account.
+    pub fn user_by_deleted_id(&self, txn: &Transaction, id: &ID) -> Option<String> {
+        self.find_user(txn, DELETE_SETS, |dss| {
+            dss.iter(txn).any(|v| match v {
+                Value::Any(Any::Buffer(buf)) => match DeleteSet::decode_v1(&buf) {
+                    Ok(ds) => ds.is_deleted(id),
+                    Err(_) => false,
+                },
+                _ => false,
+            })
+        })
+    }
+
+    /// Returns an id of the first user, which array stored under a given `key` satisfies
+    /// a `predicate`.
+    fn find_user<F>(&self, txn: &Transaction, key: &str, predicate: F) -> Option<String>
+    where
+        F: Fn(&Array) -> bool,
+    {
+        self.users.keys(txn).find_map(|user_id| {
+            if let Some(Value::YMap(user)) = self.users.get(txn, user_id) {
+                if let Some(Value::YArray(array)) = user.get(txn, key) {
+                    if predicate(&array) {
+                        return Some(user_id.clone());
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    fn user_map(&self, txn: &mut Transaction, user_id: &str) -> Map {
+        match self.users.get(txn, user_id) {
+            Some(Value::YMap(user)) => user,
+            _ => {
+                let prelim: PrelimMap<Any> = PrelimMap::from(HashMap::new());
+                self.users.insert(txn, user_id.to_string(), prelim);
+                match self.users.get(txn, user_id) {
+                    Some(Value::YMap(user)) => user,
+                    _ => panic!("Defect: user entry is not a map"),
+                }
+            }
+        }
+    }
+
+    fn array(txn: &mut Transaction, user: &Map, key: &str) -> Array {
+        match user.get(txn, key) {
+            Some(Value::YArray(array)) => array,
+            _ => {
+                let prelim: PrelimArray<Vec<Any>, Any> = PrelimArray::from(Vec::new());
+                user.insert(txn, key.to_string(), prelim);
+                match user.get(txn, key) {
+                    Some(Value::YArray(array)) => array,
+                    _ => panic!("Defect: user entry `{}` is not an array", key),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::exchange_updates;
+    use crate::{Doc, PermanentUserData};
+
+    #[test]
+    fn same_user_across_sessions() {
+        let d1 = Doc::with_client_id(1);
+        {
+            let mut txn = d1.transact();
+            let pud = PermanentUserData::new(&mut txn);
+            pud.set_user_mapping(&mut txn, 1, "alice");
+            txn.get_text("text").insert(&mut txn, 0, "hello");
+        }
+
+        // the same user opens the document again in a new session
+        let d2 = Doc::with_client_id(2);
+        exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d2.transact();
+            let pud = PermanentUserData::new(&mut txn);
+            pud.set_user_mapping(&mut txn, 2, "alice");
+            pud.set_user_mapping(&mut txn, 2, "alice");
+            txn.get_text("text").insert(&mut txn, 5, " world");
+        }
+
+        let d3 = Doc::with_client_id(3);
+        exchange_updates(&[&d1, &d2, &d3]);
+        let mut txn = d3.transact();
+        let pud = PermanentUserData::new(&mut txn);
+        let text = txn.get_text("text");
+        assert_eq!(text.to_string(&txn), "hello world");
+        for i in 0..11 {
+            let id = text.id_at(&txn, i).unwrap();
+            assert_eq!(pud.user_by_client_id(&txn, id.client), Some("alice".into()));
+        }
+        assert_eq!(pud.user_by_client_id(&txn, 3), None);
+    }
+
+    #[test]
+    fn attribute_deletions() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            let pud = PermanentUserData::new(&mut txn);
+            pud.set_user_mapping(&mut txn, 1, "alice");
+            pud.set_user_mapping(&mut txn, 2, "bob");
+            txn.get_text("text").insert(&mut txn, 0, "abc");
+        }
+        exchange_updates(&[&d1, &d2]);
+        let (kept, deleted) = {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            (text.id_at(&txn, 0).unwrap(), text.id_at(&txn, 1).unwrap())
+        };
+        {
+            let mut txn = d2.transact();
+            let pud = PermanentUserData::new(&mut txn);
+            txn.get_text("text").remove_range(&mut txn, 1, 1);
+            assert!(pud.record_deletes(&mut txn));
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let mut txn = d1.transact();
+        let pud = PermanentUserData::new(&mut txn);
+        assert_eq!(pud.user_by_deleted_id(&txn, &deleted), Some("bob".into()));
+        assert_eq!(pud.user_by_deleted_id(&txn, &kept), None);
+        assert!(!pud.record_deletes(&mut txn));
+    }
+}
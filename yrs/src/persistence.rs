@@ -0,0 +1,242 @@
+//! Persistence of documents in form of an incremental log of updates.
+//!
+//! Instead of encoding an entire document state on every change, a [DocStore] keeps a baseline
+//! update together with a log of updates appended after it, each identified by a sequence number.
+//! Once the log grows large enough, it can be [compacted](DocStore::compact) by merging all of its
+//! updates into a new baseline.
+//!
+//! A document can be kept in sync with a store using [Doc::persist_to](crate::Doc::persist_to),
+//! which appends changes of every committed transaction to the log, and restored from it using
+//! [Doc::load_from](crate::Doc::load_from):
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//! use yrs::Doc;
+//! use yrs::persistence::{DocStore, InMemoryDocStore};
+//!
+//! let store = Rc::new(RefCell::new(InMemoryDocStore::new()));
+//! let mut doc = Doc::new();
+//! let _sub = doc.persist_to(store.clone());
+//! {
+//!     let mut txn = doc.transact();
+//!     let text = txn.get_text("text");
+//!     text.push(&mut txn, "hello");
+//! }
+//! store.borrow_mut().compact(1).unwrap();
+//!
+//! let restored = Doc::new();
+//! let mut txn = restored.transact();
+//! restored.load_from(&mut txn, &*store.borrow()).unwrap();
+//! let text = txn.get_text("text");
+//! assert_eq!(text.to_string(&txn), "hello");
+//! ```
+
+use crate::{merge_updates, Error};
+
+/// A storage of document updates, which consists of a baseline update and a log of updates
+/// appended after it. All updates are encoded using lib0 ver. 1 encoding.
+pub trait DocStore {
+    /// Returns a baseline update, which contains merged state of all updates compacted so far,
+    /// or `None` if a store has not been compacted yet.
+    fn load_baseline(&self) -> Option<Vec<u8>>;
+
+    /// Returns all updates appended after a current baseline, ordered by their sequence numbers.
+    fn load_updates(&self) -> Vec<(u64, Vec<u8>)>;
+
+    /// Appends an update to the log, returning a sequence number assigned to it. Sequence numbers
+    /// must grow monotonically.
+    fn append_update(&mut self, update: &[u8]) -> u64;
+
+    /// Replaces a current baseline with a given one and removes all updates with sequence numbers
+    /// up to (and including) `seq_nr` from the log.
+    fn replace_baseline(&mut self, baseline: Vec<u8>, seq_nr: u64);
+
+    /// Merges a current baseline together with all logged updates into a new baseline, if a log
+    /// contains at least `threshold` updates. Returns `true` if compaction took place.
+    fn compact(&mut self, threshold: usize) -> Result<bool, Error> {
+        let updates = self.load_updates();
+        let seq_nr = match updates.last() {
+            Some((seq_nr, _)) if updates.len() >= threshold => *seq_nr,
+            _ => return Ok(false),
+        };
+        let baseline = self.load_baseline();
+        let mut parts: Vec<&[u8]> = Vec::with_capacity(updates.len() + 1);
+        parts.extend(baseline.as_deref());
+        parts.extend(updates.iter().map(|(_, update)| update.as_slice()));
+        let merged = merge_updates(&parts)?;
+        self.replace_baseline(merged, seq_nr);
+        Ok(true)
+    }
+}
+
+/// A reference [DocStore] implementation, which keeps all updates in memory.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDocStore {
+    baseline: Option<Vec<u8>>,
+    updates: Vec<(u64, Vec<u8>)>,
+    next_seq_nr: u64,
+}
+
+impl InMemoryDocStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a number of updates appended after a current baseline.
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Returns `true` if no updates have been appended after a current baseline.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+}
+
+impl DocStore for InMemoryDocStore {
+    fn load_baseline(&self) -> Option<Vec<u8>> {
+        self.baseline.clone()
+    }
+
+    fn load_updates(&self) -> Vec<(u64, Vec<u8>)> {
+        self.updates.clone()
+    }
+
+    fn append_update(&mut self, update: &[u8]) -> u64 {
+        let seq_nr = self.next_seq_nr;
+        self.next_seq_nr += 1;
+        self.updates.push((seq_nr, update.to_vec()));
+        seq_nr
+    }
+
+    fn replace_baseline(&mut self, baseline: Vec<u8>, seq_nr: u64) {
+        self.baseline = Some(baseline);
+        self.updates.retain(|(n, _)| *n > seq_nr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::persistence::{DocStore, InMemoryDocStore};
+    use crate::{Doc, PrelimMap};
+    use lib0::any::Any;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    fn load(store: &InMemoryDocStore) -> Doc {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact();
+            doc.load_from(&mut txn, store).unwrap();
+        }
+        doc
+    }
+
+    fn assert_same_content(expected: &Doc, actual: &Doc) {
+        let mut t1 = expected.transact();
+        let mut t2 = actual.transact();
+        assert_eq!(
+            t1.get_text("text").to_string(&t1),
+            t2.get_text("text").to_string(&t2)
+        );
+        assert_eq!(
+            t1.get_map("map").to_json(&t1),
+            t2.get_map("map").to_json(&t2)
+        );
+        assert_eq!(expected.get_state_vector(&t1), actual.get_state_vector(&t2));
+        assert_eq!(t2.store.pending, None);
+    }
+
+    #[test]
+    fn crash_recovery() {
+        let store = Rc::new(RefCell::new(InMemoryDocStore::new()));
+        let mut d1 = Doc::with_client_id(1);
+        let _sub = d1.persist_to(store.clone());
+
+        for i in 0..100u32 {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            let map = txn.get_map("map");
+            text.insert(&mut txn, i % (text.len() + 1), &i.to_string());
+            if i % 7 == 0 {
+                text.remove_range(&mut txn, 0, 1);
+            }
+            if i % 10 == 0 {
+                let nested = PrelimMap::<Any>::from(HashMap::from([(
+                    "i".to_owned(),
+                    Any::Number(i as f64),
+                )]));
+                map.insert(&mut txn, format!("k{}", i % 30), nested);
+            }
+        }
+        // a transaction without changes is not persisted
+        drop(d1.transact());
+        assert_eq!(store.borrow().len(), 100);
+
+        // reload a document from the log
+        let d2 = load(&store.borrow());
+        assert_same_content(&d1, &d2);
+
+        // log is too short to compact
+        assert!(!store.borrow_mut().compact(101).unwrap());
+        assert_eq!(store.borrow().len(), 100);
+
+        assert!(store.borrow_mut().compact(100).unwrap());
+        assert!(store.borrow().is_empty());
+        assert!(store.borrow().load_baseline().is_some());
+        let d3 = load(&store.borrow());
+        assert_same_content(&d1, &d3);
+
+        // changes made after compaction are appended to a log after a new baseline
+        {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.push(&mut txn, "tail");
+            text.remove_range(&mut txn, 0, 2);
+        }
+        assert_eq!(store.borrow().len(), 1);
+        let d4 = load(&store.borrow());
+        assert_same_content(&d1, &d4);
+
+        assert!(store.borrow_mut().compact(1).unwrap());
+        let d5 = load(&store.borrow());
+        assert_same_content(&d1, &d5);
+    }
+
+    #[test]
+    fn persist_remote_updates() {
+        let store = Rc::new(RefCell::new(InMemoryDocStore::new()));
+        let mut d1 = Doc::with_client_id(1);
+        let _sub = d1.persist_to(store.clone());
+
+        let d2 = Doc::with_client_id(2);
+        for i in 0..10 {
+            let mut t2 = d2.transact();
+            let text = t2.get_text("text");
+            text.push(&mut t2, &i.to_string());
+            let update = t2.encode_update_v1();
+            drop(t2);
+
+            let mut t1 = d1.transact();
+            d1.apply_update_v1(&mut t1, &update).unwrap();
+        }
+        {
+            let mut t1 = d1.transact();
+            let text = t1.get_text("text");
+            text.remove_range(&mut t1, 2, 5);
+        }
+        assert_eq!(store.borrow().len(), 11);
+
+        let d3 = load(&store.borrow());
+        assert_same_content(&d1, &d3);
+        let mut t3 = d3.transact();
+        assert_eq!(t3.get_text("text").to_string(&t3), "01789");
+        drop(t3);
+
+        assert!(store.borrow_mut().compact(5).unwrap());
+        let d4 = load(&store.borrow());
+        assert_same_content(&d1, &d4);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::block::ID;
+use crate::types::{BranchRef, TypePtr, Value};
+use crate::updates::decoder::{Decode, Decoder};
+use crate::updates::encoder::{Encode, Encoder};
+use crate::{Error, Transaction};
+use lib0::any::Any;
+use lib0::decoding::{CheckedCursor, DecoderOptions};
+use std::collections::HashMap;
+
+/// A position within a sequence of a shared type (ie. [Text](crate::Text) or
+/// [Array](crate::Array)), which - unlike an index - stays valid while concurrent changes are
+/// being made. It's attached to an element next to it, so it can be resolved back into an index
+/// (see: [RelativePosition::resolve]) at any later point in time, on any peer that observed that
+/// element.
+///
+/// Both binary and JSON representations are compatible with Yjs `RelativePosition`, so they can
+/// be exchanged with Yjs peers, ie. as part of awareness state used to display remote cursors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelativePosition {
+    /// ID of a block containing a nested shared type, current position refers to. `None` for
+    /// root-level types.
+    pub type_id: Option<ID>,
+    /// Name of a root-level type, current position refers to. `None` for nested types.
+    pub tname: Option<String>,
+    /// ID of an element current position is attached to. `None` if position points to the end of
+    /// a sequence (or to its beginning, if `assoc` is negative).
+    pub item: Option<ID>,
+    /// Tells which element current position is attached to: when non-negative, it's an element
+    /// on the right side of a position, otherwise it's an element on the left side of it.
+    pub assoc: i32,
+}
+
+impl RelativePosition {
+    /// Creates a position pointing to a given `index` within a sequence of a given `branch`.
+    pub(crate) fn from_type_index(
+        txn: &Transaction,
+        branch: &BranchRef,
+        mut index: u32,
+        assoc: i32,
+    ) -> Self {
+        let inner = branch.borrow();
+        let (type_id, tname) = match &inner.ptr {
+            TypePtr::Named(name) => (None, Some(name.as_ref().clone())),
+            TypePtr::Id(ptr) => (Some(ptr.id), None),
+            TypePtr::Unknown => (None, None),
+        };
+        let position = |item| RelativePosition {
+            type_id,
+            tname: tname.clone(),
+            item,
+            assoc,
+        };
+        if assoc < 0 {
+            if index == 0 {
+                return position(None);
+            }
+            index -= 1;
+        }
+        let mut current = inner.start;
+        while let Some(ptr) = current {
+            let item = match txn.store.blocks.get_item(&ptr) {
+                Some(item) => item,
+                None => break,
+            };
+            if !item.is_deleted() && item.is_countable() {
+                if item.len() > index {
+                    return position(Some(ID::new(item.id.client, item.id.clock + index)));
+                }
+                index -= item.len();
+            }
+            if item.right.is_none() && assoc < 0 {
+                return position(Some(item.last_id()));
+            }
+            current = item.right;
+        }
+        position(None)
+    }
+
+    /// Resolves current position into an index within a sequence of a shared type, it refers to.
+    /// Returns `None` if that type or an element current position is attached to has not been
+    /// observed by a document yet.
+    ///
+    /// If an element current position is attached to has been deleted, position is resolved to
+    /// the index where that element used to be.
+    pub fn resolve(&self, txn: &Transaction) -> Option<AbsolutePosition> {
+        let store = &txn.store;
+        if let Some(id) = &self.item {
+            if store.blocks.get_state(&id.client) <= id.clock {
+                return None;
+            }
+            let item = store
+                .blocks
+                .get(&id.client)?
+                .find_block(id.clock)?
+                .as_item()?;
+            let branch = store.get_type(&item.parent)?.clone();
+            let mut index = 0;
+            let parent_deleted = match branch.borrow().item {
+                Some(ptr) => store
+                    .blocks
+                    .get_item(&ptr)
+                    .map(|i| i.is_deleted())
+                    .unwrap_or(true),
+                None => false,
+            };
+            if !parent_deleted {
+                if !item.is_deleted() && item.is_countable() {
+                    index = id.clock - item.id.clock + if self.assoc >= 0 { 0 } else { 1 };
+                }
+                let mut left = item.left;
+                while let Some(ptr) = left {
+                    let item = store.blocks.get_item(&ptr)?;
+                    if !item.is_deleted() && item.is_countable() {
+                        index += item.len();
+                    }
+                    left = item.left;
+                }
+            }
+            Some(AbsolutePosition {
+                branch,
+                index,
+                assoc: self.assoc,
+            })
+        } else {
+            let branch = if let Some(name) = &self.tname {
+                store.types.get(name)?.clone()
+            } else if let Some(id) = &self.type_id {
+                if store.blocks.get_state(&id.client) <= id.clock {
+                    return None;
+                }
+                let block = store.blocks.get(&id.client)?.find_block(id.clock)?;
+                match &block.as_item()?.content {
+                    crate::block::ItemContent::Type(branch) => branch.clone(),
+                    _ => return None,
+                }
+            } else {
+                return None;
+            };
+            let index = if self.assoc >= 0 {
+                branch.borrow().len
+            } else {
+                0
+            };
+            Some(AbsolutePosition {
+                branch,
+                index,
+                assoc: self.assoc,
+            })
+        }
+    }
+
+    /// Resolves current position into an index within a sequence of a given `branch`, at which
+    /// new elements can be inserted. Unlike [RelativePosition::resolve], it fails if an element
+    /// current position is attached to has been deleted.
+    pub(crate) fn resolve_in(
+        &self,
+        txn: &Transaction,
+        branch: &BranchRef,
+    ) -> Result<u32, PositionError> {
+        let pos = self.resolve(txn).ok_or(PositionError::Unresolved)?;
+        if !pos.branch.ptr_eq(branch) {
+            return Err(PositionError::OtherType);
+        }
+        if let Some(id) = &self.item {
+            let deleted = txn
+                .store
+                .blocks
+                .get(&id.client)
+                .and_then(|blocks| blocks.find_block(id.clock))
+                .map(|block| block.is_deleted())
+                .unwrap_or(true);
+            if deleted {
+                return Err(PositionError::AnchorDeleted);
+            }
+        }
+        Ok(pos.index)
+    }
+
+    /// Converts current position into a JSON-like object of the same shape as the one produced by
+    /// Yjs `relativePositionToJSON`, ie. `{"tname":"text","item":{"client":1,"clock":3},"assoc":0}`.
+    pub fn to_json(&self) -> Any {
+        fn id_to_json(id: &ID) -> Any {
+            let mut map = HashMap::new();
+            map.insert("client".to_string(), Any::Number(id.client as f64));
+            map.insert("clock".to_string(), Any::Number(id.clock as f64));
+            Any::Map(map)
+        }
+
+        let mut map = HashMap::new();
+        if let Some(id) = &self.type_id {
+            map.insert("type".to_string(), id_to_json(id));
+        }
+        if let Some(tname) = &self.tname {
+            map.insert("tname".to_string(), Any::String(tname.clone()));
+        }
+        if let Some(id) = &self.item {
+            map.insert("item".to_string(), id_to_json(id));
+        }
+        map.insert("assoc".to_string(), Any::Number(self.assoc as f64));
+        Any::Map(map)
+    }
+
+    /// Reads a position from a JSON-like object produced either by [RelativePosition::to_json]
+    /// or Yjs `relativePositionToJSON`. Missing or `null` fields are treated as absent. Returns
+    /// `None` if a `json` object is malformed.
+    pub fn from_json(json: &Any) -> Option<Self> {
+        fn id_from_json(json: Option<&Any>) -> Option<Option<ID>> {
+            match json {
+                None | Some(Any::Null) | Some(Any::Undefined) => Some(None),
+                Some(id) => {
+                    let client = id.get("client")?.as_i64()?;
+                    let clock = id.get("clock")?.as_i64()?;
+                    if client < 0 || clock < 0 || clock > u32::MAX as i64 {
+                        return None;
+                    }
+                    Some(Some(ID::new(client as u64, clock as u32)))
+                }
+            }
+        }
+
+        let map = json.as_map()?;
+        let tname = match map.get("tname") {
+            None | Some(Any::Null) | Some(Any::Undefined) => None,
+            Some(tname) => Some(tname.as_str()?.to_string()),
+        };
+        let assoc = match map.get("assoc") {
+            None | Some(Any::Null) | Some(Any::Undefined) => 0,
+            Some(assoc) => {
+                let assoc = assoc.as_i64()?;
+                if assoc < i32::MIN as i64 || assoc > i32::MAX as i64 {
+                    return None;
+                }
+                assoc as i32
+            }
+        };
+        Some(RelativePosition {
+            type_id: id_from_json(map.get("type"))?,
+            tname,
+            item: id_from_json(map.get("item"))?,
+            assoc,
+        })
+    }
+}
+
+impl Encode for RelativePosition {
+    /// Encodes current position using the same format as Yjs `encodeRelativePosition`.
+    /// Positions which neither refer to any type nor element are encoded as pointing to the end
+    /// of a root-level type with an empty name.
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        if let Some(id) = &self.item {
+            encoder.write_uvar(0u32);
+            encoder.write_uvar(id.client);
+            encoder.write_uvar(id.clock);
+        } else if let Some(id) = &self.type_id {
+            encoder.write_uvar(2u32);
+            encoder.write_uvar(id.client);
+            encoder.write_uvar(id.clock);
+        } else {
+            encoder.write_uvar(1u32);
+            encoder.write_string(self.tname.as_deref().unwrap_or(""));
+        }
+        encoder.write_ivar(self.assoc as i64);
+    }
+}
+
+impl Decode for RelativePosition {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let mut pos = RelativePosition {
+            type_id: None,
+            tname: None,
+            item: None,
+            assoc: 0,
+        };
+        match decoder.read_uvar::<u32>()? {
+            0 => pos.item = Some(ID::new(decoder.read_uvar()?, decoder.read_uvar()?)),
+            1 => pos.tname = Some(decoder.read_string()?.to_string()),
+            2 => pos.type_id = Some(ID::new(decoder.read_uvar()?, decoder.read_uvar()?)),
+            _ => return Err(Error::InvalidData("unknown relative position kind")),
+        }
+        // positions encoded by older versions of Yjs don't have association
+        let rest = decoder.read_to_end();
+        if !rest.is_empty() {
+            let assoc = CheckedCursor::new(rest, DecoderOptions::default()).read_ivar()?;
+            if assoc < i32::MIN as i64 || assoc > i32::MAX as i64 {
+                return Err(Error::VarIntOverflow);
+            }
+            pos.assoc = assoc as i32;
+        }
+        Ok(pos)
+    }
+}
+
+/// Error returned when a [RelativePosition] cannot be used as a place to insert new content at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// Position refers to a shared type or an element, which has not been observed yet.
+    Unresolved,
+    /// Element a position is attached to has been deleted.
+    AnchorDeleted,
+    /// Position refers to a different shared type.
+    OtherType,
+}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PositionError::Unresolved => write!(f, "relative position could not be resolved"),
+            PositionError::AnchorDeleted => write!(f, "relative position anchor has been deleted"),
+            PositionError::OtherType => write!(f, "relative position refers to another type"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// A [RelativePosition] resolved into an index within a sequence of a shared type.
+#[derive(Debug, Clone)]
+pub struct AbsolutePosition {
+    pub(crate) branch: BranchRef,
+    /// Index within a sequence of a shared type.
+    pub index: u32,
+    /// Association of a [RelativePosition] this position has been resolved from.
+    pub assoc: i32,
+}
+
+impl AbsolutePosition {
+    /// Returns a shared type, current position refers to.
+    pub fn target(&self, txn: &Transaction) -> Value {
+        self.branch.clone().into_value(txn)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::exchange_updates;
+    use crate::updates::decoder::Decode;
+    use crate::updates::encoder::Encode;
+    use crate::{Doc, PrelimArray, RelativePosition, Value, ID};
+    use lib0::any::Any;
+
+    #[test]
+    fn resolve_after_concurrent_changes() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello world");
+        exchange_updates(&[&d1, &d2]);
+
+        let (right, left, end, start) = {
+            let txn = d1.transact();
+            (
+                text.relative_position(&txn, 6, 0),
+                text.relative_position(&txn, 6, -1),
+                text.relative_position(&txn, 11, 0),
+                text.relative_position(&txn, 0, -1),
+            )
+        };
+        assert_eq!(right.item, Some(ID::new(1, 6)));
+        assert_eq!(left.item, Some(ID::new(1, 5)));
+        assert_eq!(end.item, None);
+        assert_eq!(start.item, None);
+
+        {
+            let mut txn = d2.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, ">> ");
+            text.insert(&mut txn, 9, "big ");
+            text.remove_range(&mut txn, 4, 3);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        for doc in [&d1, &d2] {
+            let mut txn = doc.transact();
+            assert_eq!(txn.get_text("text").to_string(&txn), ">> ho big world");
+            let resolve = |pos: &RelativePosition| pos.resolve(&txn).unwrap().index;
+            assert_eq!(resolve(&right), 10);
+            assert_eq!(resolve(&left), 6);
+            assert_eq!(resolve(&end), 15);
+            assert_eq!(resolve(&start), 0);
+        }
+    }
+
+    #[test]
+    fn resolve_nested() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let root = txn.get_array("array");
+        root.push_back(&mut txn, PrelimArray::<_, Any>::from(vec![]));
+        let nested = match root.get(&txn, 0) {
+            Some(Value::YArray(array)) => array,
+            other => panic!("expected array, found {:?}", other),
+        };
+        nested.push_back(&mut txn, 1);
+        nested.push_back(&mut txn, 2);
+
+        let pos = nested.relative_position(&txn, 2, 0);
+        assert_eq!(pos.type_id, Some(ID::new(1, 0)));
+        assert_eq!(pos.item, None);
+        nested.insert(&mut txn, 0, 0);
+        let resolved = pos.resolve(&txn).unwrap();
+        assert_eq!(resolved.index, 3);
+        assert_eq!(resolved.target(&txn), Value::YArray(nested.clone()));
+
+        // positions in deleted types resolve to the beginning of a sequence
+        let pos = nested.relative_position(&txn, 1, 0);
+        root.remove(&mut txn, 0);
+        assert_eq!(pos.resolve(&txn).unwrap().index, 0);
+    }
+
+    #[test]
+    fn unknown_position() {
+        let doc = Doc::with_client_id(1);
+        let txn = doc.transact();
+        let pos = RelativePosition {
+            type_id: None,
+            tname: Some("text".to_string()),
+            item: Some(ID::new(2, 0)),
+            assoc: 0,
+        };
+        assert!(pos.resolve(&txn).is_none());
+    }
+
+    #[test]
+    fn binary_encoding() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "abc");
+        for (index, assoc) in [(1, 0), (1, -1), (3, 0), (0, -1)] {
+            let pos = text.relative_position(&txn, index, assoc);
+            let decoded = RelativePosition::decode_v1(&pos.encode_v1()).unwrap();
+            assert_eq!(decoded.item, pos.item);
+            assert_eq!(decoded.assoc, pos.assoc);
+            assert_eq!(decoded.resolve(&txn).unwrap().index, index);
+        }
+
+        // encoded by Yjs: createRelativePositionFromTypeIndex(ytext, 1) for an item (1,0) "abc"
+        let yjs = [0, 1, 1, 0];
+        let pos = RelativePosition::decode_v1(&yjs).unwrap();
+        assert_eq!(pos.item, Some(ID::new(1, 1)));
+        assert_eq!(pos.resolve(&txn).unwrap().index, 1);
+
+        // older Yjs versions didn't encode association
+        let pos = RelativePosition::decode_v1(&[1, 4, b't', b'e', b'x', b't']).unwrap();
+        assert_eq!(pos.tname.as_deref(), Some("text"));
+        assert_eq!(pos.assoc, 0);
+        assert_eq!(pos.resolve(&txn).unwrap().index, 3);
+
+        assert!(RelativePosition::decode_v1(&[3]).is_err());
+    }
+
+    #[test]
+    fn json_encoding() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "abc");
+        let pos = text.relative_position(&txn, 1, -1);
+        assert_eq!(
+            pos.to_json().to_json_string(),
+            r#"{"assoc":-1.0,"item":{"client":1.0,"clock":0.0},"tname":"text"}"#
+        );
+        assert_eq!(RelativePosition::from_json(&pos.to_json()), Some(pos));
+
+        // JSON.stringify(Y.relativePositionToJSON(pos)) - missing association defaults to 0
+        let json =
+            Any::from_json_str(r#"{"type":null,"tname":"text","item":{"client":1,"clock":2}}"#)
+                .unwrap();
+        let pos = RelativePosition::from_json(&json).unwrap();
+        assert_eq!(pos.item, Some(ID::new(1, 2)));
+        assert_eq!(pos.assoc, 0);
+
+        for malformed in [
+            r#"[]"#,
+            r#"{"tname":1}"#,
+            r#"{"item":{"client":1}}"#,
+            r#"{"item":{"client":-1,"clock":0}}"#,
+            r#"{"assoc":1.5}"#,
+        ] {
+            let json = Any::from_json_str(malformed).unwrap();
+            assert_eq!(RelativePosition::from_json(&json), None, "{}", malformed);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::block::ID;
+use crate::block_store::StateVector;
+use crate::id_set::DeleteSet;
+use crate::updates::decoder::{Decode, Decoder};
+use crate::updates::encoder::{Encode, Encoder};
+use crate::Error;
+use lib0::any::Any;
+use std::collections::HashMap;
+
+/// Name of a root-level array used to store named versions of a document.
+pub const VERSIONS_ROOT: &str = "versions";
+
+/// A snapshot of a document state at some point in time. It consists of a state vector, which
+/// describes blocks observed at that time, and a delete set, which describes blocks deleted at
+/// that time. Snapshots are lightweight, but restoring a document content from them requires
+/// a document to keep its deleted blocks (see: [Options::skip_gc](crate::Options::skip_gc)).
+///
+/// Snapshot binary format is compatible with Yjs `encodeSnapshot`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Blocks observed at the moment of snapshot creation.
+    pub state_vector: StateVector,
+    /// Blocks deleted at the moment of snapshot creation. Must be squashed (see:
+    /// [DeleteSet::squash]), which [Snapshot::new] does.
+    pub delete_set: DeleteSet,
+}
+
+impl Snapshot {
+    pub fn new(state_vector: StateVector, mut delete_set: DeleteSet) -> Self {
+        delete_set.squash();
+        Snapshot {
+            state_vector,
+            delete_set,
+        }
+    }
+
+    /// Checks if any of `len` elements starting at a given `id` was visible at the moment of
+    /// snapshot creation, ie. it has been already inserted and not yet deleted.
+    pub(crate) fn is_visible(&self, id: &ID, len: u32) -> bool {
+        let end = (id.clock + len).min(self.state_vector.get(&id.client));
+        let deleted = self.delete_set.get(&id.client);
+        let mut clock = id.clock;
+        while clock < end {
+            // deleted ranges are squashed, so at most one of them is skipped
+            match deleted.and_then(|ranges| ranges.find(clock)) {
+                Some(range) => clock = range.end,
+                None => return true,
+            }
+        }
+        false
+    }
+}
+
+impl Encode for Snapshot {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        self.delete_set.encode(encoder);
+        self.state_vector.encode(encoder)
+    }
+}
+
+impl Decode for Snapshot {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let delete_set = DeleteSet::decode(decoder)?;
+        let state_vector = StateVector::decode(decoder)?;
+        Ok(Snapshot::new(state_vector, delete_set))
+    }
+}
+
+/// A named version of a document, created with [Doc::create_version](crate::Doc::create_version)
+/// and stored within a document itself, under [VERSIONS_ROOT] array.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    /// Name of a version.
+    pub name: String,
+    /// Time of version creation, in milliseconds since Unix epoch.
+    pub timestamp: f64,
+    /// Snapshot of a document state at the moment of version creation.
+    pub snapshot: Snapshot,
+}
+
+impl Version {
+    pub(crate) fn to_any(&self) -> Any {
+        let mut map = HashMap::new();
+        map.insert("name".to_string(), Any::String(self.name.clone()));
+        map.insert("timestamp".to_string(), Any::Number(self.timestamp));
+        map.insert(
+            "snapshot".to_string(),
+            Any::Buffer(self.snapshot.encode_v1().into_boxed_slice()),
+        );
+        Any::Map(map)
+    }
+
+    /// Reads a version from its stored representation. Returns `None` if it's malformed.
+    pub(crate) fn from_any(value: &Any) -> Option<Self> {
+        if let Any::Map(map) = value {
+            match (map.get("name"), map.get("timestamp"), map.get("snapshot")) {
+                (
+                    Some(Any::String(name)),
+                    Some(Any::Number(timestamp)),
+                    Some(Any::Buffer(snapshot)),
+                ) => Some(Version {
+                    name: name.clone(),
+                    timestamp: *timestamp,
+                    snapshot: Snapshot::decode_v1(snapshot).ok()?,
+                }),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block::ID;
+    use crate::updates::decoder::Decode;
+    use crate::updates::encoder::Encode;
+    use crate::{DeleteSet, Doc, Error, Options, Snapshot, StateVector};
+
+    fn doc(client_id: u64) -> Doc {
+        Doc::with_options(Options {
+            skip_gc: true,
+            ..Options::with_client_id(client_id)
+        })
+    }
+
+    #[test]
+    fn snapshot_encoding() {
+        let d1 = doc(1);
+        let mut txn = d1.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "hello world");
+        text.remove_range(&mut txn, 0, 6);
+        let snapshot = d1.snapshot(&txn);
+        let decoded = Snapshot::decode_v1(&snapshot.encode_v1()).unwrap();
+        assert_eq!(decoded, snapshot);
+    }
+
+    #[test]
+    fn snapshot_visibility() {
+        let mut ds = DeleteSet::new();
+        ds.insert(ID::new(1, 8), 2);
+        ds.insert(ID::new(1, 0), 2);
+        ds.insert(ID::new(1, 2), 1);
+        ds.insert(ID::new(1, 5), 1);
+        let mut sv = StateVector::default();
+        sv.set_max(1, 12);
+        let snapshot = Snapshot::new(sv, ds);
+
+        assert!(!snapshot.is_visible(&ID::new(1, 0), 3));
+        assert!(snapshot.is_visible(&ID::new(1, 0), 4));
+        assert!(snapshot.is_visible(&ID::new(1, 4), 1));
+        assert!(!snapshot.is_visible(&ID::new(1, 5), 1));
+        assert!(snapshot.is_visible(&ID::new(1, 5), 2));
+        assert!(!snapshot.is_visible(&ID::new(1, 8), 2));
+        assert!(snapshot.is_visible(&ID::new(1, 9), 3));
+        // not yet inserted at the moment of snapshot creation
+        assert!(!snapshot.is_visible(&ID::new(1, 12), 1));
+        assert!(!snapshot.is_visible(&ID::new(2, 0), 1));
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let d1 = doc(1);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello");
+        let snapshot = d1.snapshot(&d1.transact());
+        {
+            let mut txn = d1.transact();
+            text.remove_range(&mut txn, 0, 2);
+            text.insert(&mut txn, 3, " world");
+        }
+
+        let txn = d1.transact();
+        let restored = d1.restore_snapshot(&txn, &snapshot).unwrap();
+        let mut rtxn = restored.transact();
+        assert_eq!(rtxn.get_text("text").to_string(&rtxn), "hello");
+        assert_eq!(text.to_string(&txn), "llo world");
+    }
+
+    #[test]
+    fn gc_protected_snapshot() {
+        let d1 = Doc::with_client_id(1);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello world");
+        let snapshot = d1.snapshot(&d1.transact());
+        d1.gc_protect(&mut d1.transact(), &snapshot);
+        text.remove_range(&mut d1.transact(), 0, 6);
+        // content not visible in a protected snapshot is collected as usual
+        text.insert(&mut d1.transact(), 5, "!");
+        text.remove_range(&mut d1.transact(), 5, 1);
+        d1.compact(&mut d1.transact());
+        {
+            let txn = d1.transact();
+            let kinds: Vec<_> = txn
+                .client_blocks(1)
+                .into_iter()
+                .map(|b| (b.clock, b.kind))
+                .collect();
+            assert_eq!(
+                kinds,
+                vec![(0..6, "String"), (6..11, "String"), (11..12, "Deleted")]
+            );
+            let restored = d1.restore_snapshot(&txn, &snapshot).unwrap();
+            let mut rtxn = restored.transact();
+            assert_eq!(rtxn.get_text("text").to_string(&rtxn), "hello world");
+        }
+
+        assert!(d1.gc_unprotect(&mut d1.transact(), &snapshot));
+        assert!(!d1.gc_unprotect(&mut d1.transact(), &snapshot));
+        d1.compact(&mut d1.transact());
+        let txn = d1.transact();
+        assert_eq!(text.to_string(&txn), "world");
+        match d1.restore_snapshot(&txn, &snapshot) {
+            Err(e) => assert_eq!(
+                e,
+                Error::GarbageCollected {
+                    client: 1,
+                    clock: 0
+                }
+            ),
+            Ok(_) => panic!("garbage collected snapshot has been restored"),
+        }
+    }
+
+    #[test]
+    fn diff_versions() {
+        let d1 = doc(1);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello world");
+        d1.create_version(&mut d1.transact(), "v1");
+        {
+            let mut txn = d1.transact();
+            text.remove_range(&mut txn, 6, 5);
+            text.insert(&mut txn, 6, "there");
+        }
+        d1.create_version(&mut d1.transact(), "v2");
+
+        let txn = d1.transact();
+        let names: Vec<_> = d1.versions(&txn).into_iter().map(|v| v.name).collect();
+        assert_eq!(names, vec!["v1".to_string(), "v2".to_string()]);
+
+        let report: Vec<_> = d1
+            .diff_versions(&txn, "v1", "v2")
+            .unwrap()
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                r#"text[6]: "w" != "t""#,
+                r#"text[7]: "o" != "h""#,
+                r#"text[8]: "r" != "e""#,
+                r#"text[9]: "l" != "r""#,
+                r#"text[10]: "d" != "e""#,
+            ]
+        );
+        assert!(d1.diff_versions(&txn, "v1", "v3").is_none());
+    }
+}
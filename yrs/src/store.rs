@@ -1,15 +1,52 @@
-use crate::block::ItemContent;
+use crate::block::{Block, Item, ItemContent, BLOCK_GC_REF_NUMBER, ID};
 use crate::block_store::{BlockStore, SquashResult, StateVector};
-use crate::event::{EventHandler, UpdateEvent};
+use crate::doc::SubDoc;
+use crate::event::{
+    AfterTransactionEvent, BeforeTransactionEvent, ClientIdChangeEvent, CommitEvent, DestroyEvent,
+    EventHandler, Observer, ObserverError, RootTypesEvent, SubdocsEvent, UpdateEvent,
+};
 use crate::id_set::DeleteSet;
+use crate::snapshot::Snapshot;
 use crate::types;
-use crate::types::{BranchRef, TypePtr, TypeRefs, TYPE_REFS_UNDEFINED};
+use crate::types::{Branch, BranchRef, TypePtr, TypeRefs, TYPE_REFS_UNDEFINED};
 use crate::update::PendingUpdate;
 use crate::updates::encoder::{Encode, Encoder};
+use std::cell::{Cell, Ref, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// Statistics about the size and shape of a document's block store, which can be used to detect
+/// documents fragmented into many small blocks or consisting mostly of tombstones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Total number of blocks in a block store.
+    pub blocks: usize,
+    /// Number of blocks in a block store, grouped by the client who created them.
+    pub blocks_per_client: HashMap<u64, usize>,
+    /// Number of deleted blocks, including garbage collected ones.
+    pub tombstones: usize,
+    /// Total length of a content stored by non-deleted blocks.
+    pub content_len: usize,
+    /// Number of blocks waiting in a pending update queue for missing updates to arrive.
+    pub pending_blocks: usize,
+    /// Number of non-deleted formatting attribute markers of rich text content. See:
+    /// [Text::normalize_formatting](crate::Text::normalize_formatting).
+    pub format_markers: usize,
+    /// Estimated number of bytes allocated on the heap by a block store.
+    pub heap_size: usize,
+}
+
+/// Block store statistics captured before and after a compaction pass
+/// (see: [Transaction::compact](crate::Transaction::compact)).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Statistics of a block store before compaction.
+    pub before: StoreStats,
+    /// Statistics of a block store after compaction.
+    pub after: StoreStats,
+}
+
 /// Store is a core element of a document. It contains all of the information, like block store
 /// map of root types, pending updates waiting to be applied once a missing update information
 /// arrives and all subscribed callbacks.
@@ -17,11 +54,21 @@ pub(crate) struct Store {
     /// An unique identifier of a current document replica.
     pub client_id: u64,
 
+    /// A copy of `client_id` shared with a [Doc](crate::Doc) owning current store, so that it
+    /// knows a client id currently in use without borrowing a store.
+    pub(crate) current_client_id: Rc<Cell<u64>>,
+
+    /// A globally unique identifier of a document, this store belongs to.
+    pub guid: String,
+
     /// Root types (a.k.a. top-level types). These types are defined by users at the document level,
     /// they have their own unique names and represent core shared types that expose operations
     /// which can be called concurrently by remote peers in a conflict-free manner.
     pub types: HashMap<Rc<String>, BranchRef>,
 
+    /// Names of root types created since the last transaction commit.
+    pub(crate) created_types: Vec<Rc<String>>,
+
     /// A block store of a current document. It represent all blocks (inserted or tombstoned
     /// operations) integrated - and therefore visible - into a current document.
     pub(crate) blocks: BlockStore,
@@ -39,21 +86,156 @@ pub(crate) struct Store {
     /// A subscription handler. It contains all callbacks with registered by user functions that
     /// are supposed to be called, once a new update arrives.
     pub(crate) update_events: EventHandler<UpdateEvent>,
+
+    /// If true, deleted blocks are not garbage collected when a transaction is committed.
+    pub skip_gc: bool,
+
+    /// Subdocuments embedded within shared types of a current document, keyed by their guids.
+    pub(crate) subdocs: HashMap<String, SubDoc>,
+
+    /// A subscription handler for callbacks notified about added, removed or loaded subdocuments.
+    pub(crate) subdocs_events: EventHandler<SubdocsEvent>,
+
+    /// A subscription handler for callbacks notified about changes made by committed transactions.
+    pub(crate) commit_events: EventHandler<CommitEvent>,
+
+    /// Callbacks notified whenever a new transaction has been started.
+    pub(crate) before_transaction_events: Observer<BeforeTransactionEvent>,
+
+    /// Callbacks notified about changes made by a transaction, once it's being committed, right
+    /// after type observers have been called.
+    pub(crate) after_transaction_events: Observer<AfterTransactionEvent>,
+
+    /// Callbacks notified about changes made by a transaction, once it's being committed, after
+    /// its blocks have been garbage collected and squashed.
+    pub(crate) after_transaction_cleanup_events: Observer<AfterTransactionEvent>,
+
+    /// A subscription handler for callbacks notified about created root types.
+    pub(crate) root_types_events: EventHandler<RootTypesEvent>,
+
+    /// Callbacks notified once a document is being destroyed.
+    pub(crate) destroy_events: EventHandler<DestroyEvent>,
+
+    /// If true, `client_id` is replaced with a new one, once another replica using the same
+    /// client id is detected. Otherwise updates with conflicting blocks are rejected.
+    pub(crate) auto_new_client_id: bool,
+
+    /// If true, a commit time of blocks created by `client_id` is stored within a document itself.
+    pub(crate) record_timestamps: bool,
+
+    /// Names and kinds of root types declared up front. If set, only these root types can be
+    /// accessed with type-specific accessors of a transaction.
+    pub(crate) schema: Option<Vec<(String, &'static str)>>,
+
+    /// Callbacks notified whenever `client_id` has been replaced with a new one.
+    pub(crate) client_id_events: EventHandler<ClientIdChangeEvent>,
+
+    /// Callbacks notified about panics raised by other callbacks subscribed to a current document
+    /// or its shared types.
+    pub(crate) observer_error_events: EventHandler<ObserverError>,
+
+    /// If true, a document owning this store has been destroyed and cannot be used anymore.
+    pub destroyed: bool,
+
+    /// Number of times blocks of a current store have been marked as deleted.
+    pub(crate) deletions: Cell<u64>,
+
+    /// Number of bytes of content stored by all integrated items, which are not deleted. See:
+    /// [Item::content_size].
+    pub(crate) content_size: Cell<usize>,
+
+    /// Maximum number of bytes of content, which local changes cannot exceed. See:
+    /// [Options::max_content_size](crate::Options::max_content_size).
+    pub(crate) max_content_size: Option<usize>,
+
+    /// If true, local changes have been committed since the last call to
+    /// [Doc::mark_synced](crate::Doc::mark_synced).
+    pub(crate) unsynced: bool,
+
+    /// Blocks deleted by local transactions since the last call to
+    /// [Doc::mark_synced](crate::Doc::mark_synced).
+    pub(crate) unsynced_deletes: DeleteSet,
+
+    /// Snapshots, which content must survive garbage collection. See:
+    /// [Doc::gc_protect](crate::Doc::gc_protect).
+    pub(crate) gc_protected: Vec<Snapshot>,
+
+    /// A delete set computed from `blocks` together with a number of `deletions` and a total
+    /// number of blocks at the time when it was computed. See: [Store::delete_set].
+    delete_set_cache: RefCell<Option<(u64, usize, DeleteSet)>>,
 }
 
 impl Store {
-    /// Create a new empty store in context of a given `client_id`.
-    pub fn new(client_id: u64) -> Self {
+    /// Create a new empty store in context of a given `client_id` and document `guid`, which
+    /// garbage collects deleted blocks unless `skip_gc` is set.
+    pub fn with_gc(client_id: u64, guid: String, skip_gc: bool) -> Self {
         Store {
             client_id,
+            current_client_id: Rc::new(Cell::new(client_id)),
+            guid,
             types: Default::default(),
+            created_types: Vec::new(),
             blocks: BlockStore::new(),
             pending: None,
             pending_ds: None,
             update_events: EventHandler::new(),
+            skip_gc,
+            subdocs: HashMap::new(),
+            subdocs_events: EventHandler::new(),
+            commit_events: EventHandler::new(),
+            before_transaction_events: Observer::new(),
+            after_transaction_events: Observer::new(),
+            after_transaction_cleanup_events: Observer::new(),
+            root_types_events: EventHandler::new(),
+            destroy_events: EventHandler::new(),
+            auto_new_client_id: false,
+            record_timestamps: false,
+            schema: None,
+            client_id_events: EventHandler::new(),
+            observer_error_events: EventHandler::new(),
+            destroyed: false,
+            deletions: Cell::new(0),
+            content_size: Cell::new(0),
+            max_content_size: None,
+            unsynced: false,
+            unsynced_deletes: DeleteSet::new(),
+            gc_protected: Vec::new(),
+            delete_set_cache: RefCell::new(None),
         }
     }
 
+    /// Computes statistics about the size and shape of a current block store.
+    pub fn stats(&self) -> StoreStats {
+        let mut stats = StoreStats::default();
+        for (&client, blocks) in self.blocks.iter() {
+            stats.blocks += blocks.len();
+            stats.blocks_per_client.insert(client, blocks.len());
+            stats.heap_size += blocks.capacity() * std::mem::size_of::<Block>();
+            for block in blocks.iter() {
+                if block.is_deleted() {
+                    stats.tombstones += 1;
+                }
+                if let Block::Item(item) = block {
+                    if !item.is_deleted() {
+                        if item.is_countable() {
+                            stats.content_len += item.len() as usize;
+                        } else if let ItemContent::Format(_, _) = item.content {
+                            stats.format_markers += 1;
+                        }
+                    }
+                    stats.heap_size += item.content.heap_size();
+                    if let Some(key) = &item.parent_sub {
+                        stats.heap_size += key.capacity();
+                    }
+                }
+            }
+        }
+        if let Some(pending) = &self.pending {
+            stats.pending_blocks = pending.update.blocks().count();
+        }
+        stats
+    }
+
     /// Get the latest clock sequence number observed and integrated into a current store client.
     /// This is exclusive value meaning it describes a clock value of the beginning of the next
     /// block that's about to be inserted. You cannot use that clock value to find any existing
@@ -64,6 +246,22 @@ impl Store {
 
     /// Returns a branch reference to a complex type identified by its pointer. Returns `None` if
     /// no such type could be found or was ever defined.
+    /// Drops a cached content size of a given `branch` and all of its parents (see:
+    /// [Branch::content_size]). Parents of a branch without a cached size don't have it either.
+    pub(crate) fn invalidate_content_size(&self, branch: &Branch) {
+        branch.content_size_cache.set(None);
+        let mut current = branch.item;
+        while let Some(item) = current.and_then(|ptr| self.blocks.get_item(&ptr)) {
+            match self.get_type(&item.parent).map(|p| p.try_borrow()) {
+                Some(Ok(parent)) if parent.content_size_cache.get().is_some() => {
+                    parent.content_size_cache.set(None);
+                    current = parent.item;
+                }
+                _ => break,
+            }
+        }
+    }
+
     pub fn get_type(&self, ptr: &TypePtr) -> Option<&BranchRef> {
         match ptr {
             TypePtr::Id(id) => {
@@ -84,21 +282,11 @@ impl Store {
     /// (defined by the user at document level) of such type didn't exist before, it will be created
     /// and returned. For other (recursively nested) types, they will be returned only if they
     /// already existed. Otherwise a `None` will be returned.
-    pub fn init_type_from_ptr(
-        &mut self,
-        ptr: &types::TypePtr,
-        content: &ItemContent,
-    ) -> Option<BranchRef> {
+    pub fn init_type_from_ptr(&mut self, ptr: &types::TypePtr) -> Option<BranchRef> {
         match ptr {
             types::TypePtr::Named(name) => {
-                if let ItemContent::Type(inner) = content {
-                    let e = self.types.entry(name.clone());
-                    let inner = e.or_insert(inner.clone());
-                    Some(inner.clone())
-                } else {
-                    let inner = self.init_type_ref(name.clone(), None, TYPE_REFS_UNDEFINED);
-                    Some(inner)
-                }
+                let inner = self.init_type_ref(name.clone(), None, TYPE_REFS_UNDEFINED);
+                Some(inner)
             }
             _ => {
                 if let Some(inner) = self.get_type(ptr) {
@@ -129,12 +317,17 @@ impl Store {
         node_name: Option<String>,
         type_ref: TypeRefs,
     ) -> BranchRef {
+        let created_types = &mut self.created_types;
         let e = self.types.entry(name.clone());
         let value = e.or_insert_with(|| {
+            created_types.push(name.clone());
             let type_ptr = types::TypePtr::Named(name.clone());
             let inner = types::Branch::new(type_ptr, type_ref, node_name);
             BranchRef::new(inner)
         });
+        if type_ref != TYPE_REFS_UNDEFINED {
+            value.borrow_mut().repair_type_ref(type_ref);
+        }
         value.clone()
     }
 
@@ -152,18 +345,33 @@ impl Store {
         // 2. make Diff implement Encode trait and encode it
         // this way we can add some extra utility method on top of Diff (like introspection) without need of decoding it.
         self.write_blocks(remote_sv, encoder);
-        let delete_set = DeleteSet::from(&self.blocks);
-        delete_set.encode(encoder);
+        self.delete_set().encode(encoder);
     }
 
-    fn write_blocks<E: Encoder>(&self, remote_sv: &StateVector, encoder: &mut E) {
-        let local_sv = self.blocks.get_state_vector();
-        let mut diff = Self::diff_state_vectors(&local_sv, remote_sv);
-
-        // Write items with higher client ids first
-        // This heavily improves the conflict algorithm.
-        diff.sort_by(|a, b| b.0.cmp(&a.0));
+    /// Returns a delete set describing clock ranges of all deleted blocks.
+    ///
+    /// Computing it requires visiting every block, so it's cached until any block gets deleted or
+    /// a number of blocks changes (ie. when blocks are integrated, split or squashed). This way
+    /// diffs broadcasted to many peers after a single change compute it only once.
+    pub(crate) fn delete_set(&self) -> Ref<'_, DeleteSet> {
+        let blocks: usize = self.blocks.iter().map(|(_, blocks)| blocks.len()).sum();
+        let mut cache = self.delete_set_cache.borrow_mut();
+        let outdated = match &*cache {
+            Some((deletions, len, _)) => *deletions != self.deletions.get() || *len != blocks,
+            None => true,
+        };
+        if outdated {
+            let delete_set = DeleteSet::from(&self.blocks);
+            *cache = Some((self.deletions.get(), blocks, delete_set));
+        }
+        drop(cache);
+        Ref::map(self.delete_set_cache.borrow(), |cache| {
+            &cache.as_ref().unwrap().2
+        })
+    }
 
+    pub(crate) fn write_blocks<E: Encoder>(&self, remote_sv: &StateVector, encoder: &mut E) {
+        let diff = self.diff_state_vector(remote_sv);
         encoder.write_uvar(diff.len());
         for (client, clock) in diff {
             let blocks = self.blocks.get(&client).unwrap();
@@ -175,27 +383,204 @@ impl Store {
             encoder.write_uvar(clock);
             let first_block = &blocks[start];
             // write first struct with an offset
-            first_block.encode(self, encoder);
+            let offset = clock - first_block.id().clock;
+            if offset == 0 {
+                first_block.encode(self, encoder);
+            } else {
+                first_block.encode_with_offset(encoder, offset);
+            }
             for i in (start + 1)..blocks.integrated_len() {
                 blocks[i].encode(self, encoder);
             }
         }
     }
 
-    fn diff_state_vectors(local_sv: &StateVector, remote_sv: &StateVector) -> Vec<(u64, u32)> {
-        let mut diff = Vec::new();
-        for (client, &remote_clock) in remote_sv.iter() {
-            let local_clock = local_sv.get(client);
-            if local_clock > remote_clock {
-                diff.push((*client, remote_clock));
+    /// Returns clients which blocks have not been fully observed by a remote peer, together with
+    /// the first clock a remote peer has not observed yet. Clients are ordered by their ids in
+    /// descending order: writing items with higher client ids first heavily improves the conflict
+    /// algorithm.
+    ///
+    /// Only the last block of each client is inspected, so clients which are up to date cost
+    /// a single state vector lookup.
+    pub(crate) fn diff_state_vector(&self, remote_sv: &StateVector) -> Vec<(u64, u32)> {
+        let mut diff: Vec<_> = self
+            .blocks
+            .iter()
+            .filter_map(|(&client, blocks)| {
+                let remote_clock = remote_sv.get(&client);
+                if blocks.get_state() > remote_clock {
+                    Some((client, remote_clock))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        diff.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        diff
+    }
+
+    /// Compute a diff to sync with another client, limited only to the content of root types with
+    /// given `roots` names together with all of their nested types.
+    ///
+    /// Blocks belonging to other root types are omitted: in order to keep clock ranges of each
+    /// client continuous, they are encoded as GC blocks of corresponding length, so a remote peer
+    /// will treat them as already deleted. Likewise the delete set contains only the ranges of
+    /// blocks included in the update.
+    pub fn encode_filtered_diff<E: Encoder>(
+        &self,
+        remote_sv: &StateVector,
+        roots: &[&str],
+        encoder: &mut E,
+    ) {
+        let include = |root: Option<&str>| root.map(|key| roots.contains(&key)).unwrap_or(false);
+        self.encode_diff_where(
+            remote_sv,
+            remote_sv,
+            &DeleteSet::new(),
+            include,
+            BLOCK_GC_REF_NUMBER,
+            encoder,
+        );
+    }
+
+    /// Compute a diff to sync with another client, limited only to the content of root types for
+    /// which an `include` predicate returns true. A predicate is called with a name of a root type
+    /// or with `None` for blocks which don't belong to any existing root type. See:
+    /// [Store::encode_filtered_diff] for details.
+    ///
+    /// Omitted blocks are encoded as ranges of a given `omitted_ref` kind: either GC blocks, which
+    /// a remote peer treats as deleted content, or skipped ranges, which it treats as missing
+    /// until another update fills them in. Blocks between `remote_sv` and `checked` clocks are
+    /// known not to be included, so they're encoded as omitted without checking them again. Besides deleted blocks included in a diff,
+    /// the delete set contains included parts of `deletes` ranges, which may refer to blocks
+    /// already known to a remote peer.
+    ///
+    /// Returns `None` if neither blocks nor deletions have been included. Otherwise returns
+    /// a `remote_sv` advanced by clock ranges of encoded blocks (including omitted ones).
+    pub(crate) fn encode_diff_where<E, F>(
+        &self,
+        remote_sv: &StateVector,
+        checked: &StateVector,
+        deletes: &DeleteSet,
+        include: F,
+        omitted_ref: u8,
+        encoder: &mut E,
+    ) -> Option<StateVector>
+    where
+        E: Encoder,
+        F: Fn(Option<&str>) -> bool,
+    {
+        let included = |item: &Item| include(self.root_key(item).map(|key| key.as_str()));
+        let diff = self.diff_state_vector(remote_sv);
+        let mut delete_set = DeleteSet::new();
+        for (client, range) in deletes.iter() {
+            let blocks = match self.blocks.get(client) {
+                Some(blocks) => blocks,
+                None => continue,
+            };
+            for r in range.iter() {
+                let mut i = match blocks.find_pivot(r.start) {
+                    Some(i) => i,
+                    None => continue,
+                };
+                while i < blocks.integrated_len() && blocks[i].id().clock < r.end {
+                    if let Block::Item(item) = &blocks[i] {
+                        if included(item) {
+                            let start = item.id.clock.max(r.start);
+                            let end = (item.id.clock + item.len()).min(r.end);
+                            delete_set.insert(ID::new(*client, start), end - start);
+                        }
+                    }
+                    i += 1;
+                }
             }
         }
-        for (client, _) in local_sv.iter() {
-            if remote_sv.get(client) == 0 {
-                diff.push((*client, 0));
+        let mut covered = remote_sv.clone();
+        let mut clients = Vec::with_capacity(diff.len());
+        for (client, clock) in diff {
+            let blocks = self.blocks.get(&client).unwrap();
+            let clock = clock.max(blocks.first().id().clock);
+            let check_from = checked.get(&client).max(clock);
+            let mut filtered: Vec<Filtered> = Vec::new();
+            if check_from > clock {
+                filtered.push(Filtered::Omitted(check_from - clock));
+            }
+            let start = match blocks.find_pivot(check_from) {
+                Some(start) => start,
+                None => continue,
+            };
+            for i in start..blocks.integrated_len() {
+                let block = &blocks[i];
+                let offset = if i == start {
+                    check_from - block.id().clock
+                } else {
+                    0
+                };
+                let included = match block {
+                    Block::Item(item) => included(item),
+                    _ => false,
+                };
+                if included {
+                    if block.is_deleted() {
+                        let id = block.id();
+                        delete_set
+                            .insert(ID::new(id.client, id.clock + offset), block.len() - offset);
+                    }
+                    filtered.push(Filtered::Block(block, offset));
+                } else if let Some(Filtered::Omitted(len)) = filtered.last_mut() {
+                    *len += block.len() - offset;
+                } else {
+                    filtered.push(Filtered::Omitted(block.len() - offset));
+                }
+            }
+            // omitted blocks at the end don't have to be send at all
+            if let Some(Filtered::Omitted(_)) = filtered.last() {
+                filtered.pop();
+            }
+            if let Some(Filtered::Block(block, _)) = filtered.last() {
+                covered.set_max(client, block.clock_end());
+                clients.push((client, clock, filtered));
+            }
+        }
+
+        let changed = !clients.is_empty() || !delete_set.is_empty();
+        encoder.write_uvar(clients.len());
+        for (client, clock, filtered) in clients {
+            encoder.write_uvar(filtered.len());
+            encoder.write_client(client);
+            encoder.write_uvar(clock);
+            for f in filtered {
+                match f {
+                    Filtered::Block(block, 0) => block.encode(self, encoder),
+                    Filtered::Block(block, offset) => block.encode_with_offset(encoder, offset),
+                    Filtered::Omitted(len) => {
+                        encoder.write_info(omitted_ref);
+                        encoder.write_len(len);
+                    }
+                }
+            }
+        }
+        delete_set.squash();
+        delete_set.encode(encoder);
+        if changed {
+            Some(covered)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a name of a root type, which given `item` belongs to - either directly or as part
+    /// of some nested type.
+    fn root_key(&self, item: &Item) -> Option<&Rc<String>> {
+        let mut item = item;
+        loop {
+            let parent = self.get_type(&item.parent)?;
+            let parent_item = parent.borrow().item;
+            match parent_item {
+                Some(ptr) => item = self.blocks.get_item(&ptr)?,
+                None => return self.get_root_type_key(parent),
             }
         }
-        diff
     }
 
     pub(crate) fn gc_cleanup(&mut self, compaction: SquashResult) {
@@ -233,6 +618,14 @@ impl Store {
     }
 }
 
+/// Element of a client's block range encoded by [Store::encode_filtered_diff].
+enum Filtered<'a> {
+    /// Block which should be encoded, starting from a given offset.
+    Block(&'a Block, u32),
+    /// Continuous range of blocks of a given length, which should be skipped.
+    Omitted(u32),
+}
+
 impl Encode for Store {
     /// Encodes the document state to a binary format.
     ///
@@ -0,0 +1,518 @@
+//! Implementation of the [y-protocols](https://github.com/yjs/y-protocols) sync protocol messages,
+//! used by Yjs network providers (eg. y-websocket) to exchange document updates.
+//!
+//! A standard handshake between two peers looks like this:
+//!
+//! 1. Each peer sends a [SyncMessage::SyncStep1] containing its own state vector.
+//! 2. A peer receiving [SyncMessage::SyncStep1] replies with [SyncMessage::SyncStep2] containing
+//!    all updates not yet observed by the remote side.
+//! 3. Once synced, all subsequent document changes are exchanged as [SyncMessage::Update].
+//!
+//! [handle_message] implements the receiving side of that protocol, so that a server only has to
+//! pass decoded messages in and send the returned replies back.
+//!
+//! Update payloads are stored as opaque binaries, so message framing is the same regardless of
+//! the update encoding version used. [handle_message] applies them using lib0 v1 encoding - lib0 v2
+//! updates are not supported, so peers have to be configured to use v1.
+//!
+//! [Connection] combines document synchronization with [Awareness] exchange into a single state
+//! machine operating on encoded frames, which can be used with any transport.
+
+use crate::awareness::Awareness;
+use crate::updates::decoder::{Decode, Decoder, DecoderV1};
+use crate::updates::encoder::{Encode, Encoder};
+use crate::{Doc, Error, StateVector, Transaction};
+use std::cell::RefCell;
+
+/// Tag id for [Message::Sync].
+pub const MSG_SYNC: u32 = 0;
+/// Tag id for [Message::Awareness].
+pub const MSG_AWARENESS: u32 = 1;
+/// Tag id for [Message::Auth].
+pub const MSG_AUTH: u32 = 2;
+/// Tag id for [Message::AwarenessQuery].
+pub const MSG_QUERY_AWARENESS: u32 = 3;
+
+/// Tag id for [SyncMessage::SyncStep1].
+pub const MSG_SYNC_STEP_1: u32 = 0;
+/// Tag id for [SyncMessage::SyncStep2].
+pub const MSG_SYNC_STEP_2: u32 = 1;
+/// Tag id for [SyncMessage::Update].
+pub const MSG_SYNC_UPDATE: u32 = 2;
+
+const PERMISSION_DENIED: u32 = 0;
+const PERMISSION_GRANTED: u32 = 1;
+
+/// A top-level message of the y-protocols, as sent over the wire between peers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Message {
+    /// Document synchronization message.
+    Sync(SyncMessage),
+    /// Authorization message: `Some(reason)` if permission was denied, `None` if it was granted.
+    Auth(Option<String>),
+    /// Request for the current awareness state of a remote peer.
+    AwarenessQuery,
+    /// Encoded awareness update.
+    Awareness(Vec<u8>),
+    /// Message of a type not recognized by this implementation, together with its raw payload.
+    Custom(u32, Vec<u8>),
+}
+
+impl Message {
+    /// Encodes current message using lib0 ver. 1 encoding.
+    pub fn encode_v1(&self) -> Vec<u8> {
+        Encode::encode_v1(self)
+    }
+
+    /// Decodes a single message, assuming it's encoded using lib0 ver. 1 encoding.
+    /// Returns an error if provided `data` is not a valid y-protocols message.
+    pub fn decode_v1(data: &[u8]) -> Result<Self, Error> {
+        Decode::decode_v1(data)
+    }
+}
+
+impl Encode for Message {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        match self {
+            Message::Sync(msg) => {
+                encoder.write_uvar(MSG_SYNC);
+                msg.encode(encoder);
+            }
+            Message::Auth(reason) => {
+                encoder.write_uvar(MSG_AUTH);
+                if let Some(reason) = reason {
+                    encoder.write_uvar(PERMISSION_DENIED);
+                    encoder.write_string(reason);
+                } else {
+                    encoder.write_uvar(PERMISSION_GRANTED);
+                }
+            }
+            Message::AwarenessQuery => encoder.write_uvar(MSG_QUERY_AWARENESS),
+            Message::Awareness(update) => {
+                encoder.write_uvar(MSG_AWARENESS);
+                encoder.write_buf(update);
+            }
+            Message::Custom(tag, payload) => {
+                encoder.write_uvar(*tag);
+                encoder.write(payload);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let tag: u32 = decoder.read_uvar()?;
+        let msg = match tag {
+            MSG_SYNC => Message::Sync(SyncMessage::decode(decoder)?),
+            MSG_AWARENESS => Message::Awareness(decoder.read_buf()?.to_vec()),
+            MSG_AUTH => {
+                let reason = if decoder.read_uvar::<u32>()? == PERMISSION_DENIED {
+                    Some(decoder.read_string()?.to_string())
+                } else {
+                    None
+                };
+                Message::Auth(reason)
+            }
+            MSG_QUERY_AWARENESS => Message::AwarenessQuery,
+            tag => Message::Custom(tag, decoder.read_to_end().to_vec()),
+        };
+        Ok(msg)
+    }
+}
+
+/// A document synchronization message, carried by [Message::Sync].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SyncMessage {
+    /// Sends a state vector of a sender, asking a receiver for all updates it's missing.
+    SyncStep1(StateVector),
+    /// A reply to [SyncMessage::SyncStep1], containing all updates missing on the requester side.
+    SyncStep2(Vec<u8>),
+    /// An incremental update, broadcasted once peers have been synced.
+    Update(Vec<u8>),
+}
+
+impl Encode for SyncMessage {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        match self {
+            SyncMessage::SyncStep1(sv) => {
+                encoder.write_uvar(MSG_SYNC_STEP_1);
+                encoder.write_buf(sv.encode_v1());
+            }
+            SyncMessage::SyncStep2(update) => {
+                encoder.write_uvar(MSG_SYNC_STEP_2);
+                encoder.write_buf(update);
+            }
+            SyncMessage::Update(update) => {
+                encoder.write_uvar(MSG_SYNC_UPDATE);
+                encoder.write_buf(update);
+            }
+        }
+    }
+}
+
+impl Decode for SyncMessage {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let tag: u32 = decoder.read_uvar()?;
+        let msg = match tag {
+            MSG_SYNC_STEP_1 => {
+                let mut sv = DecoderV1::from(decoder.read_buf()?);
+                SyncMessage::SyncStep1(StateVector::decode(&mut sv)?)
+            }
+            MSG_SYNC_STEP_2 => SyncMessage::SyncStep2(decoder.read_buf()?.to_vec()),
+            MSG_SYNC_UPDATE => SyncMessage::Update(decoder.read_buf()?.to_vec()),
+            _ => return Err(Error::InvalidData("unrecognized sync message type")),
+        };
+        Ok(msg)
+    }
+}
+
+/// Creates a [SyncMessage::SyncStep1] message, used to start synchronization with a remote peer.
+pub fn sync_step1(doc: &Doc, txn: &Transaction) -> Message {
+    Message::Sync(SyncMessage::SyncStep1(doc.get_state_vector(txn)))
+}
+
+/// Creates a [SyncMessage::Update] message out of an update encoded using lib0 ver. 1 encoding.
+pub fn sync_update(update: Vec<u8>) -> Message {
+    Message::Sync(SyncMessage::Update(update))
+}
+
+/// Handles a `msg` received from a remote peer by applying it to a given `doc`, returning a list
+/// of messages, which should be sent back to that peer in reply:
+///
+/// - [SyncMessage::SyncStep1] is answered with [SyncMessage::SyncStep2] containing all updates
+///   missing on the remote side.
+/// - [SyncMessage::SyncStep2] and [SyncMessage::Update] are applied to a `doc`.
+/// - Other messages don't need any reply from the document side.
+///
+/// # Example
+///
+/// ```
+/// use yrs::sync::{handle_message, sync_step1, Message};
+/// use yrs::Doc;
+///
+/// let d1 = Doc::with_client_id(1);
+/// let mut t1 = d1.transact();
+/// t1.get_text("text").push(&mut t1, "hello");
+///
+/// let d2 = Doc::with_client_id(2);
+/// let mut t2 = d2.transact();
+/// let text = t2.get_text("text");
+///
+/// // d2 requests missing updates from d1
+/// let request = sync_step1(&d2, &t2).encode_v1();
+/// let request = Message::decode_v1(&request).unwrap();
+/// for reply in handle_message(&d1, &mut t1, request).unwrap() {
+///     let reply = Message::decode_v1(&reply.encode_v1()).unwrap();
+///     handle_message(&d2, &mut t2, reply).unwrap();
+/// }
+/// assert_eq!(text.to_string(&t2), "hello");
+/// ```
+pub fn handle_message(
+    doc: &Doc,
+    txn: &mut Transaction,
+    msg: Message,
+) -> Result<Vec<Message>, Error> {
+    let replies = match msg {
+        Message::Sync(SyncMessage::SyncStep1(sv)) => {
+            let update = doc.encode_delta_as_update_v1(txn, &sv);
+            vec![Message::Sync(SyncMessage::SyncStep2(update))]
+        }
+        Message::Sync(SyncMessage::SyncStep2(update))
+        | Message::Sync(SyncMessage::Update(update)) => {
+            doc.apply_update_v1(txn, &update)?;
+            Vec::new()
+        }
+        Message::Auth(_)
+        | Message::AwarenessQuery
+        | Message::Awareness(_)
+        | Message::Custom(_, _) => Vec::new(),
+    };
+    Ok(replies)
+}
+
+/// A connection with a single remote peer, which keeps a document and awareness states of both
+/// sides in sync. It doesn't perform any I/O on its own: it consumes frames received from
+/// a remote peer and produces frames, which should be sent to it.
+///
+/// An [Awareness] is passed in a [RefCell], so that it can be shared by many connections, ie.
+/// when a server relays states of all of its clients.
+pub struct Connection<'a> {
+    doc: &'a Doc,
+    awareness: &'a RefCell<Awareness>,
+    synced: bool,
+}
+
+impl<'a> Connection<'a> {
+    /// Creates a new connection for a given document and awareness of a local peer.
+    pub fn new(doc: &'a Doc, awareness: &'a RefCell<Awareness>) -> Self {
+        Connection {
+            doc,
+            awareness,
+            synced: false,
+        }
+    }
+
+    /// Checks if a [SyncMessage::SyncStep2] has been received from a remote peer, meaning that
+    /// a local document has observed all of its updates from before the connection was made.
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// Returns frames which should be sent to a remote peer once a connection is established:
+    /// a [SyncMessage::SyncStep1] and, if any states are known, a full awareness update.
+    pub fn handshake(&self) -> Vec<Vec<u8>> {
+        let mut frames = vec![sync_step1(self.doc, &self.doc.transact()).encode_v1()];
+        let awareness = self.awareness.borrow();
+        if !awareness.states().is_empty() {
+            frames.push(Message::Awareness(awareness.encode_full_update()).encode_v1());
+        }
+        frames
+    }
+
+    /// Handles a `frame` received from a remote peer, returning frames which should be sent back
+    /// to it in reply. See: [handle_message].
+    ///
+    /// Awareness updates are applied to a local awareness, while awareness queries are answered
+    /// with states of all known clients.
+    pub fn receive(&mut self, frame: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let msg = Message::decode_v1(frame)?;
+        let replies = match msg {
+            Message::Awareness(update) => {
+                self.awareness.borrow_mut().apply_update(&update)?;
+                Vec::new()
+            }
+            Message::AwarenessQuery => {
+                let update = self.awareness.borrow().encode_full_update();
+                vec![Message::Awareness(update)]
+            }
+            msg => {
+                if let Message::Sync(SyncMessage::SyncStep2(_)) = &msg {
+                    self.synced = true;
+                }
+                handle_message(self.doc, &mut self.doc.transact(), msg)?
+            }
+        };
+        Ok(replies.into_iter().map(|msg| msg.encode_v1()).collect())
+    }
+
+    /// Returns a frame broadcasting a document `update` made locally, encoded using lib0 v1
+    /// encoding (ie. one passed to [Doc::on_update] callbacks).
+    pub fn local_update(&self, update: &[u8]) -> Vec<u8> {
+        sync_update(update.to_vec()).encode_v1()
+    }
+
+    /// Returns a frame broadcasting current awareness states of given `clients`, ie. after
+    /// a local state has been changed.
+    pub fn awareness_changed(&self, clients: &[u64]) -> Vec<u8> {
+        let update = self.awareness.borrow().encode_update(clients);
+        Message::Awareness(update).encode_v1()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::awareness::Awareness;
+    use crate::sync::{handle_message, sync_step1, sync_update, Connection, Message, SyncMessage};
+    use crate::{Doc, Error, StateVector, Transaction};
+    use lib0::any::Any;
+    use std::cell::RefCell;
+
+    /// Passes a message through its binary representation to a given document, returning encoded
+    /// replies.
+    fn exchange(doc: &Doc, txn: &mut Transaction, data: &[u8]) -> Vec<Vec<u8>> {
+        let msg = Message::decode_v1(data).unwrap();
+        handle_message(doc, txn, msg)
+            .unwrap()
+            .into_iter()
+            .map(|reply| reply.encode_v1())
+            .collect()
+    }
+
+    #[test]
+    fn message_encoding() {
+        let d = Doc::with_client_id(1);
+        let mut txn = d.transact();
+        txn.get_text("text").push(&mut txn, "abc");
+        let sv = d.get_state_vector(&txn);
+        let update = d.encode_state_as_update_v1(&txn);
+
+        let messages = vec![
+            Message::Sync(SyncMessage::SyncStep1(sv)),
+            Message::Sync(SyncMessage::SyncStep1(StateVector::default())),
+            Message::Sync(SyncMessage::SyncStep2(update.clone())),
+            Message::Sync(SyncMessage::Update(update)),
+            Message::Auth(Some("reason".to_string())),
+            Message::Auth(None),
+            Message::AwarenessQuery,
+            Message::Awareness(vec![1, 2, 3]),
+            Message::Custom(100, vec![4, 5, 6]),
+        ];
+        for msg in messages {
+            let encoded = msg.encode_v1();
+            assert_eq!(Message::decode_v1(&encoded), Ok(msg));
+        }
+    }
+
+    #[test]
+    fn message_decoding_errors() {
+        assert_eq!(Message::decode_v1(&[]), Err(Error::UnexpectedEof));
+        assert_eq!(
+            Message::decode_v1(&[0, 7, 0]),
+            Err(Error::InvalidData("unrecognized sync message type"))
+        );
+        // sync step 2 declaring more bytes than the payload contains
+        assert_eq!(
+            Message::decode_v1(&[0, 1, 10, 0]),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn message_compatibility() {
+        // syncProtocol.writeSyncStep1 for a document with client 1 at clock 3
+        let step1 = sync_step1_for(1, "abc");
+        assert_eq!(step1, vec![0, 0, 3, 1, 1, 3]);
+
+        // authProtocol.writePermissionDenied(encoder, 'no')
+        let denied = Message::Auth(Some("no".to_string())).encode_v1();
+        assert_eq!(denied, vec![2, 0, 2, b'n', b'o']);
+
+        // messageQueryAwareness
+        assert_eq!(Message::AwarenessQuery.encode_v1(), vec![3]);
+    }
+
+    fn sync_step1_for(client_id: u64, content: &str) -> Vec<u8> {
+        let d = Doc::with_client_id(client_id);
+        let mut txn = d.transact();
+        txn.get_text("text").push(&mut txn, content);
+        sync_step1(&d, &txn).encode_v1()
+    }
+
+    #[test]
+    fn handshake() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt1 = t1.get_text("text");
+        txt1.push(&mut t1, "hello");
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let txt2 = t2.get_text("text");
+
+        // d2 has nothing, so its reply carries no new blocks
+        let step1 = sync_step1(&d1, &t1).encode_v1();
+        for reply in exchange(&d2, &mut t2, &step1) {
+            assert!(exchange(&d1, &mut t1, &reply).is_empty());
+        }
+        let step1 = sync_step1(&d2, &t2).encode_v1();
+        for reply in exchange(&d1, &mut t1, &step1) {
+            assert!(exchange(&d2, &mut t2, &reply).is_empty());
+        }
+        assert_eq!(txt2.to_string(&t2), "hello");
+
+        // incremental updates after initial sync
+        txt2.push(&mut t2, " world");
+        let update = sync_update(d2.encode_delta_as_update_v1(&t2, &d1.get_state_vector(&t1)));
+        assert!(exchange(&d1, &mut t1, &update.encode_v1()).is_empty());
+        assert_eq!(txt1.to_string(&t1), "hello world");
+    }
+
+    #[test]
+    fn handshake_with_disjoint_changes() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt1 = t1.get_text("text");
+        txt1.push(&mut t1, "abc");
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let txt2 = t2.get_text("text");
+        txt2.push(&mut t2, "def");
+
+        // both sides start the handshake at the same time
+        let step1_from_1 = sync_step1(&d1, &t1).encode_v1();
+        let step1_from_2 = sync_step1(&d2, &t2).encode_v1();
+        let replies_from_2 = exchange(&d2, &mut t2, &step1_from_1);
+        let replies_from_1 = exchange(&d1, &mut t1, &step1_from_2);
+        assert_eq!(replies_from_1.len(), 1);
+        assert_eq!(replies_from_2.len(), 1);
+
+        for reply in replies_from_2 {
+            assert!(exchange(&d1, &mut t1, &reply).is_empty());
+        }
+        for reply in replies_from_1 {
+            assert!(exchange(&d2, &mut t2, &reply).is_empty());
+        }
+
+        assert_eq!(txt1.to_string(&t1), txt2.to_string(&t2));
+        assert_eq!(txt1.len(), 6);
+        assert_eq!(d1.get_state_vector(&t1), d2.get_state_vector(&t2));
+    }
+
+    /// Delivers frames between two connections until neither of them has anything to reply.
+    fn pump(c1: &mut Connection, c2: &mut Connection, to_1: Vec<Vec<u8>>, to_2: Vec<Vec<u8>>) {
+        let (mut to_1, mut to_2) = (to_1, to_2);
+        while !to_1.is_empty() || !to_2.is_empty() {
+            let mut next_2 = Vec::new();
+            for frame in to_1.drain(..) {
+                next_2.extend(c1.receive(&frame).unwrap());
+            }
+            let mut next_1 = Vec::new();
+            for frame in to_2.drain(..) {
+                next_1.extend(c2.receive(&frame).unwrap());
+            }
+            to_1 = next_1;
+            to_2 = next_2;
+        }
+    }
+
+    #[test]
+    fn connection() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let a1 = RefCell::new(Awareness::new(1));
+        let a2 = RefCell::new(Awareness::new(2));
+        for (d, chunk) in [(&d1, "hello"), (&d2, "world")] {
+            let mut txn = d.transact();
+            txn.get_text("text").push(&mut txn, chunk);
+        }
+        a1.borrow_mut()
+            .set_local_state(Any::from_json_str(r#"{"name":"alice"}"#).unwrap());
+
+        let mut c1 = Connection::new(&d1, &a1);
+        let mut c2 = Connection::new(&d2, &a2);
+        assert_eq!(c1.handshake().len(), 2);
+        assert_eq!(c2.handshake().len(), 1);
+        let (h1, h2) = (c1.handshake(), c2.handshake());
+        pump(&mut c1, &mut c2, h2, h1);
+        assert!(c1.is_synced() && c2.is_synced());
+        let content = |d: &Doc| {
+            let mut txn = d.transact();
+            txn.get_text("text").to_string(&txn)
+        };
+        assert_eq!(content(&d1), content(&d2));
+        assert_eq!(a2.borrow().states(), a1.borrow().states());
+
+        // local changes broadcasted after the handshake
+        let update = {
+            let mut txn = d2.transact();
+            txn.get_text("text").push(&mut txn, "!");
+            txn.encode_update_v1()
+        };
+        a2.borrow_mut()
+            .set_local_state(Any::from_json_str(r#"{"name":"bob"}"#).unwrap());
+        let frames = vec![c2.local_update(&update), c2.awareness_changed(&[2])];
+        pump(&mut c1, &mut c2, frames, Vec::new());
+        assert_eq!(content(&d1), content(&d2));
+        assert!(content(&d1).ends_with('!'));
+        assert_eq!(a1.borrow().states().len(), 2);
+
+        // awareness query is answered with all known states
+        let query = Message::AwarenessQuery.encode_v1();
+        let replies = c1.receive(&query).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert!(c2.receive(&replies[0]).unwrap().is_empty());
+        assert_eq!(a2.borrow().states(), a1.borrow().states());
+    }
+}
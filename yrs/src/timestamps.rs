@@ -0,0 +1,62 @@
+use lib0::any::Any;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Name of a root-level map used to store times of committed changes, when a document has been
+/// created with [Options::record_timestamps](crate::Options::record_timestamps) enabled.
+pub const TIMESTAMPS_ROOT: &str = "timestamps";
+
+/// A time at which a range of blocks created by a single client has been committed. Entries are
+/// stored within a document itself, under [TIMESTAMPS_ROOT] map, so they are replicated together
+/// with other document changes, while peers which don't record them see just another root map.
+///
+/// Only insertions are timestamped, as deletions don't create blocks with their own clocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditTimestamp {
+    /// Client which created timestamped blocks.
+    pub client: u64,
+    /// Range of clocks of timestamped blocks.
+    pub clock: Range<u32>,
+    /// Time of a transaction commit, in milliseconds since Unix epoch.
+    pub timestamp: f64,
+}
+
+impl EditTimestamp {
+    /// Returns a key under which current entry is stored in [TIMESTAMPS_ROOT] map. Ranges of
+    /// different transactions never overlap, so neither do their keys.
+    pub(crate) fn key(&self) -> String {
+        format!("{}:{}", self.client, self.clock.start)
+    }
+
+    pub(crate) fn to_any(&self) -> Any {
+        let mut map = HashMap::new();
+        // client ids may not fit into a safe integer range of a JS number
+        map.insert("client".to_string(), Any::BigInt(self.client as i64));
+        map.insert("start".to_string(), Any::Number(self.clock.start as f64));
+        map.insert("end".to_string(), Any::Number(self.clock.end as f64));
+        map.insert("timestamp".to_string(), Any::Number(self.timestamp));
+        Any::Map(map)
+    }
+
+    /// Reads an entry from its stored representation. Returns `None` if it's malformed.
+    pub(crate) fn from_any(value: &Any) -> Option<Self> {
+        let map = value.as_map()?;
+        let client = map.get("client")?.as_i64()? as u64;
+        let start = map.get("start")?.as_i64()? as u32;
+        let end = map.get("end")?.as_i64()? as u32;
+        let timestamp = map.get("timestamp")?.as_f64()?;
+        Some(EditTimestamp {
+            client,
+            clock: start..end,
+            timestamp,
+        })
+    }
+}
+
+/// Returns a current time in milliseconds since Unix epoch.
+pub(crate) fn unix_millis() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or_default()
+}
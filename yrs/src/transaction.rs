@@ -1,17 +1,28 @@
 use crate::*;
 
-use crate::block::{Block, BlockPtr, Item, ItemContent, Prelim, ID};
+use crate::block::{
+    Block, BlockInfo, BlockPtr, Item, ItemContent, ItemInfo, ItemPosition, Prelim, ID,
+};
 use crate::block_store::StateVector;
-use crate::event::UpdateEvent;
+use crate::event::{
+    AfterTransactionEvent, BeforeTransactionEvent, BranchId, ChangeSource, ClientIdChangeEvent,
+    CommitEvent, Observer, RootTypesEvent, SubdocsEvent, UpdateEvent,
+};
 use crate::id_set::{DeleteSet, IdSet};
-use crate::store::Store;
+use crate::store::{CompactionReport, Store, StoreStats};
+use crate::timestamps::unix_millis;
 use crate::types::array::Array;
-use crate::types::xml::{XmlElement, XmlText};
+use crate::types::xml::{XmlElement, XmlFragment, XmlText};
 use crate::types::{
-    Branch, Map, Text, TypePtr, TYPE_REFS_ARRAY, TYPE_REFS_MAP, TYPE_REFS_TEXT,
-    TYPE_REFS_XML_ELEMENT, TYPE_REFS_XML_TEXT,
+    kind_name, kind_type_ref, root_node_name, Branch, BranchRef, Event, Map, Observers, Path,
+    PathSegment, Text, TypePtr, TypeRefs, TYPE_REFS_ARRAY, TYPE_REFS_MAP, TYPE_REFS_TEXT,
+    TYPE_REFS_XML_ELEMENT, TYPE_REFS_XML_FRAGMENT, TYPE_REFS_XML_TEXT,
 };
 use crate::update::Update;
+use crate::updates::decoder::{Decode, DecoderV1};
+use lib0::any::Any;
+use lib0::encoding::Write;
+use rand::Rng;
 use std::cell::RefMut;
 use std::collections::{HashMap, HashSet};
 use std::ops::Range;
@@ -30,22 +41,197 @@ pub struct Transaction<'a> {
     pub merge_blocks: Vec<ID>,
     /// Describes the set of deleted items by ids.
     pub delete_set: DeleteSet,
+    /// Optional information about the source of changes made in scope of a current transaction.
+    pub origin: Option<Any>,
     /// All types that were directly modified (property added or child inserted/deleted).
     /// New types are not included in this Set.
     changed: HashMap<TypePtr, HashSet<Option<String>>>,
+    /// Subdocuments inserted in scope of a current transaction, keyed by their guids.
+    pub(crate) subdocs_added: HashMap<String, SubDoc>,
+    /// Guids of subdocuments removed in scope of a current transaction.
+    pub(crate) subdocs_removed: HashSet<String>,
+    /// Guids of subdocuments requested to be loaded in scope of a current transaction.
+    pub(crate) subdocs_loaded: HashSet<String>,
+    /// Ids of clients, which blocks have been integrated from remote updates.
+    integrated_clients: HashSet<u64>,
+    /// Whether any remote update has changed a document in scope of a current transaction.
+    remote_changes: bool,
+    /// Whether any local operation has changed a document in scope of a current transaction.
+    local_changes: bool,
+    /// Whether a remote update is being applied at the moment.
+    integrating: bool,
+    /// Whether current transaction has been already committed.
+    committed: bool,
+    /// Counters of operations performed in scope of a current transaction.
+    stats: TransactionStats,
+    /// Moment in time when a current transaction has been created.
+    #[cfg(feature = "log")]
+    started: std::time::Instant,
 }
 
 impl<'a> Transaction<'a> {
-    pub(crate) fn new(store: RefMut<'a, Store>) -> Transaction {
+    pub(crate) fn new(store: RefMut<'a, Store>, origin: Option<Any>) -> Transaction {
         let begin_timestamp = store.blocks.get_state_vector();
-        Transaction {
+        #[cfg(feature = "log")]
+        tracing::trace!(guid = %store.guid, client_id = store.client_id, "transaction begin");
+        let txn = Transaction {
             store,
             before_state: begin_timestamp,
             merge_blocks: Vec::new(),
             delete_set: DeleteSet::new(),
+            origin,
             changed: HashMap::new(),
             after_state: StateVector::default(),
+            subdocs_added: HashMap::new(),
+            subdocs_removed: HashSet::new(),
+            subdocs_loaded: HashSet::new(),
+            integrated_clients: HashSet::new(),
+            remote_changes: false,
+            local_changes: false,
+            integrating: false,
+            committed: false,
+            stats: TransactionStats::default(),
+            #[cfg(feature = "log")]
+            started: std::time::Instant::now(),
+        };
+        if txn.store.before_transaction_events.has_subscribers() {
+            let event = BeforeTransactionEvent {
+                origin: txn.origin.clone(),
+                before_state: txn.before_state.clone(),
+            };
+            txn.store.before_transaction_events.publish(&txn, &event);
+        }
+        txn
+    }
+
+    /// Returns ids of clients, which blocks have been integrated from remote updates applied in
+    /// scope of a current transaction (see: [Transaction::apply_update]). Local client id may be
+    /// present as well, ie. when a document is loaded from previously persisted updates.
+    pub fn integrated_clients(&self) -> &HashSet<u64> {
+        &self.integrated_clients
+    }
+
+    /// Tells if changes made so far in scope of a current transaction come from local operations,
+    /// remote updates or both. It can be used by observers to skip the changes they are not
+    /// interested in, ie. to avoid broadcasting remote changes back to the network.
+    pub fn change_source(&self) -> ChangeSource {
+        match (self.local_changes, self.remote_changes) {
+            (_, false) => ChangeSource::Local,
+            (false, true) => ChangeSource::Remote,
+            (true, true) => ChangeSource::Mixed,
+        }
+    }
+
+    /// Checks if current transaction contains no changes coming from remote updates.
+    pub fn is_local(&self) -> bool {
+        !self.remote_changes
+    }
+
+    /// Returns a client id used to create blocks in scope of a current transaction. It may differ
+    /// from [Doc::client_id] if a document has replaced its client id since it was created (see:
+    /// [Options::auto_new_client_id_on_conflict]).
+    pub fn client_id(&self) -> u64 {
+        self.store.client_id
+    }
+
+    /// Checks if remote `updates` contain blocks created by another replica using the same client
+    /// id as a current document. It has no side effects, so that updates can be rejected for
+    /// other reasons before a conflict is acted upon (see:
+    /// [Transaction::resolve_client_id_conflict]).
+    ///
+    /// Blocks beyond a local state are not considered to be conflicting unless a client id is
+    /// replaced automatically, as they are also produced by a replica reloading its own state.
+    pub(crate) fn client_id_conflict<'u, I>(&self, updates: I) -> ClientIdConflict
+    where
+        I: IntoIterator<Item = &'u Update>,
+    {
+        let client = self.store.client_id;
+        let local_state = self.store.blocks.get_state(&client);
+        let mut conflict = ClientIdConflict::default();
+        for update in updates {
+            for item in update.blocks().filter_map(Block::as_item) {
+                if item.id.client != client {
+                    continue;
+                }
+                if item.id.clock + item.len() > local_state {
+                    conflict.reused = true;
+                }
+                if item.id.clock < local_state && conflict.duplicate_clock.is_none() {
+                    conflict.duplicate_clock = self.find_duplicate_clock(item);
+                }
+            }
+        }
+        conflict
+    }
+
+    /// Acts upon a `conflict` found by [Transaction::client_id_conflict]. Depending on
+    /// [Options::auto_new_client_id_on_conflict] either a client id of a current document is
+    /// replaced with a new one or - if remote blocks collide with local blocks with the same ids
+    /// but a different content - an [Error::DuplicateClientClock] is returned.
+    ///
+    /// Like in Yjs, a new client id is only used for future changes: existing blocks keep their
+    /// ids, so colliding remote blocks are not integrated.
+    pub(crate) fn resolve_client_id_conflict(
+        &mut self,
+        conflict: ClientIdConflict,
+    ) -> Result<(), Error> {
+        let client = self.store.client_id;
+        if self.store.auto_new_client_id {
+            if conflict.reused || conflict.duplicate_clock.is_some() {
+                let event = ClientIdChangeEvent {
+                    previous: client,
+                    current: rand::thread_rng().gen(),
+                    duplicate_clock: conflict.duplicate_clock,
+                };
+                #[cfg(feature = "log")]
+                tracing::warn!(
+                    guid = %self.store.guid,
+                    previous = event.previous,
+                    current = event.current,
+                    duplicate_clock = ?event.duplicate_clock,
+                    "client id reused by another replica, replacing it"
+                );
+                self.store.client_id = event.current;
+                self.store.current_client_id.set(event.current);
+                self.store
+                    .client_id_events
+                    .publish(&event, &self.store.observer_error_events);
+            }
+        } else if let Some(clock) = conflict.duplicate_clock {
+            return Err(Error::DuplicateClientClock { client, clock });
         }
+        Ok(())
+    }
+
+    /// Compares a remote `item` with integrated local blocks it overlaps with, returning a clock
+    /// of the first element which differs between them.
+    fn find_duplicate_clock(&self, item: &Item) -> Option<u32> {
+        let blocks = self.store.blocks.get(&item.id.client)?;
+        let end = item.id.clock + item.len();
+        let mut index = blocks.find_pivot(item.id.clock)?;
+        while index < blocks.integrated_len() {
+            let local = match &blocks[index] {
+                Block::Item(local) => local,
+                _ => return None,
+            };
+            if local.id.clock >= end {
+                break;
+            }
+            let start = local.id.clock.max(item.id.clock);
+            let len = (local.id.clock + local.len()).min(end) - start;
+            let (offset, local_offset) = (start - item.id.clock, start - local.id.clock);
+            if offset == 0 && local_offset == 0 && local.origin != item.origin {
+                return Some(start);
+            }
+            let mismatch = item
+                .content
+                .find_mismatch(offset, &local.content, local_offset, len);
+            if let Some(i) = mismatch {
+                return Some(start + i);
+            }
+            index += 1;
+        }
+        None
     }
 
     /// Returns a [Text] data structure stored under a given `name`. Text structures are used for
@@ -59,7 +245,7 @@ impl<'a> Transaction<'a> {
     /// reinterpreted as a text (in such case a sequence component of complex data type will be
     /// interpreted as a list of text chunks).
     pub fn get_text(&mut self, name: &str) -> Text {
-        let c = self.store.create_type(name, None, TYPE_REFS_TEXT);
+        let c = self.get_root(name, TYPE_REFS_TEXT);
         Text::from(c)
     }
 
@@ -75,7 +261,7 @@ impl<'a> Transaction<'a> {
     /// reinterpreted as a map (in such case a map component of complex data type will be
     /// interpreted as native map).
     pub fn get_map(&mut self, name: &str) -> Map {
-        let c = self.store.create_type(name, None, TYPE_REFS_MAP);
+        let c = self.get_root(name, TYPE_REFS_MAP);
         Map::from(c)
     }
 
@@ -90,7 +276,7 @@ impl<'a> Transaction<'a> {
     /// reinterpreted as an array (in such case a sequence component of complex data type will be
     /// interpreted as a list of inserted values).
     pub fn get_array(&mut self, name: &str) -> Array {
-        let c = self.store.create_type(name, None, TYPE_REFS_ARRAY);
+        let c = self.get_root(name, TYPE_REFS_ARRAY);
         Array::from(c)
     }
 
@@ -107,12 +293,25 @@ impl<'a> Transaction<'a> {
     /// interpreted as map of its attributes, while a sequence component - as a list of its child
     /// XML nodes).
     pub fn get_xml_element(&mut self, name: &str) -> XmlElement {
-        let c = self
-            .store
-            .create_type(name, Some("UNDEFINED".to_string()), TYPE_REFS_XML_ELEMENT);
+        let c = self.get_root(name, TYPE_REFS_XML_ELEMENT);
         XmlElement::from(c)
     }
 
+    /// Returns a [XmlFragment] data structure stored under a given `name`. XML fragments are
+    /// containers of XML nodes (other [XmlElement]s and [XmlText]s). Unlike [XmlElement] they don't
+    /// have a tag name or attributes. This is a type of root used by ie. y-prosemirror bindings.
+    ///
+    /// If not structure under defined `name` existed before, it will be created and returned
+    /// instead.
+    ///
+    /// If a structure under defined `name` already existed, but its type was different it will be
+    /// reinterpreted as a XML fragment (in such case a sequence component of complex data type
+    /// will be interpreted as a list of its child XML nodes).
+    pub fn get_xml_fragment(&mut self, name: &str) -> XmlFragment {
+        let c = self.get_root(name, TYPE_REFS_XML_FRAGMENT);
+        XmlFragment::from(c)
+    }
+
     /// Returns a [XmlText] data structure stored under a given `name`. Text structures are used for
     /// collaborative text editing: they expose operations to append and remove chunks of text,
     /// which are free to execute concurrently by multiple peers over remote boundaries.
@@ -124,11 +323,294 @@ impl<'a> Transaction<'a> {
     /// reinterpreted as a text (in such case a sequence component of complex data type will be
     /// interpreted as a list of text chunks).
     pub fn get_xml_text(&mut self, name: &str) -> XmlText {
-        let c = self.store.create_type(name, None, TYPE_REFS_XML_TEXT);
+        let c = self.get_root(name, TYPE_REFS_XML_TEXT);
         XmlText::from(c)
     }
 
-    /// Encodes the document state to a binary format.
+    /// Returns a root type stored under a given `name`, creating it if it didn't exist before.
+    /// `kind` is one of the names returned by [Value::kind] for shared types, ie. `"YText"`. Unlike
+    /// type-specific accessors, it's not validated against a document schema (see:
+    /// [Options::schema]). Returns `None` if `kind` doesn't refer to a shared type.
+    ///
+    /// If a root type under a given `name` already existed, it's reinterpreted as a given kind,
+    /// just like with type-specific accessors.
+    pub fn get_or_create_unchecked(&mut self, name: &str, kind: &str) -> Option<Value> {
+        let type_ref = kind_type_ref(kind)?;
+        let branch = self
+            .store
+            .create_type(name, root_node_name(type_ref), type_ref);
+        Some(branch.into_value(self))
+    }
+
+    /// Returns a root type stored under a given `name`, creating it if it didn't exist before.
+    /// Panics if a document has a schema, which doesn't declare such root type.
+    fn get_root(&mut self, name: &str, type_ref: TypeRefs) -> BranchRef {
+        if let Some(schema) = self.store.schema.as_ref() {
+            let kind = kind_name(type_ref);
+            match schema.iter().find(|(declared, _)| declared == name) {
+                Some((_, declared)) if *declared == kind => {}
+                Some((_, declared)) => panic!(
+                    "root type '{}' is declared in document schema as {}, not {}",
+                    name, declared, kind
+                ),
+                None => panic!("root type '{}' is not declared in document schema", name),
+            }
+        }
+        self.store
+            .create_type(name, root_node_name(type_ref), type_ref)
+    }
+
+    /// Returns a delete set containing all blocks deleted within the scope of current transaction
+    /// so far. Use [Doc::delete_set] to get all deletions observed by a document.
+    pub fn delete_set(&self) -> &DeleteSet {
+        &self.delete_set
+    }
+
+    /// Checks if a block containing given `id` has been deleted. Returns `false` if no such block
+    /// has been observed by the document yet.
+    pub fn is_deleted(&self, id: &ID) -> bool {
+        match self.store.blocks.get(&id.client) {
+            Some(blocks) => blocks
+                .find_block(id.clock)
+                .map(|block| block.is_deleted())
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Checks if a given shared type `branch` belongs to the document, which this transaction was
+    /// created for. Shared types must never be modified using transactions of other documents.
+    pub fn contains_type(&self, branch: &BranchRef) -> bool {
+        let ptr = branch.borrow().ptr.clone();
+        match ptr {
+            TypePtr::Named(name) => match self.store.types.get(&name) {
+                Some(root) => root.ptr_eq(branch),
+                None => false,
+            },
+            TypePtr::Id(ptr) => match self.store.blocks.get_item(&ptr) {
+                Some(Item {
+                    content: ItemContent::Type(inner),
+                    ..
+                }) => inner.ptr_eq(branch),
+                _ => false,
+            },
+            TypePtr::Unknown => false,
+        }
+    }
+
+    /// Returns statistics about the size and shape of a document's block store. They reflect
+    /// changes made by a current transaction, but blocks are squashed together only when
+    /// a transaction is committed.
+    pub fn store_stats(&self) -> StoreStats {
+        self.store.stats()
+    }
+
+    /// Performs a full compaction pass over a document's block store, which - unlike squashing
+    /// done when a transaction is committed - is not limited to the blocks changed by a current
+    /// transaction. It garbage collects content of all deleted blocks (unless
+    /// [Options::skip_gc] is set or they're visible in one of the snapshots protected with
+    /// [Doc::gc_protect]), merges all adjacent blocks that can be squashed together and shrinks
+    /// internal buffers.
+    ///
+    /// Compaction doesn't change observable document content nor its clock values, so it doesn't
+    /// affect synchronization with other peers and doesn't trigger any observers.
+    pub fn compact(&mut self) -> CompactionReport {
+        let before = self.store.stats();
+        let clients: Vec<u64> = self
+            .store
+            .blocks
+            .iter()
+            .map(|(&client, _)| client)
+            .collect();
+        let protected = std::mem::take(&mut self.store.gc_protected);
+        for client in clients {
+            if !self.store.skip_gc {
+                let blocks = self.store.blocks.get_mut(&client).unwrap();
+                for i in 0..blocks.len() {
+                    if let Block::Item(item) = &mut blocks[i] {
+                        let len = item.len();
+                        if item.is_deleted()
+                            && !protected.iter().any(|s| s.is_visible(&item.id, len))
+                        {
+                            match &item.content {
+                                // nested types are not garbage collected, see: Transaction::try_gc
+                                ItemContent::Type(_) | ItemContent::Deleted(_) => {}
+                                _ => item.content = ItemContent::Deleted(item.len()),
+                            }
+                        }
+                    }
+                }
+            }
+
+            let mut blocks = self.store.blocks.get_mut(&client).unwrap();
+            let mut i = blocks.len();
+            while i > 1 {
+                i -= 1;
+                if let Some(compaction) = blocks.squash_left(i) {
+                    self.store.gc_cleanup(compaction);
+                    blocks = self.store.blocks.get_mut(&client).unwrap();
+                }
+            }
+            blocks.shrink_to_fit();
+        }
+        self.store.gc_protected = protected;
+        let after = self.store.stats();
+        #[cfg(feature = "log")]
+        tracing::debug!(
+            guid = %self.store.guid,
+            client_id = self.store.client_id,
+            blocks_before = before.blocks,
+            blocks_after = after.blocks,
+            tombstones = after.tombstones,
+            "compaction finished"
+        );
+        CompactionReport { before, after }
+    }
+
+    /// Resolves an element identified by a given `id` into an information about its parent shared
+    /// type, its current index within that type, content kind and value. Returns `None` if no
+    /// such element has been observed by the document or if it has been garbage collected.
+    pub fn get_item(&self, id: &ID) -> Option<ItemInfo> {
+        let block = self.store.blocks.get(&id.client)?.find_block(id.clock)?;
+        let item = block.as_item()?;
+        let branch = self.store.get_type(&item.parent)?;
+        let deleted = item.is_deleted();
+        let offset = id.clock - item.id.clock;
+        let index = match item.parent_sub {
+            None if !deleted => branch.as_ref().index_of(self, id),
+            _ => None,
+        };
+        let value = if deleted {
+            None
+        } else {
+            item.content.get_any_at(offset as usize)
+        };
+        Some(ItemInfo {
+            id: id.clone(),
+            parent: branch.clone().into_value(self),
+            parent_sub: item.parent_sub.clone(),
+            index,
+            deleted,
+            kind: item.content.kind(),
+            value,
+        })
+    }
+
+    /// Returns information about all blocks inserted by a given `client`, in order of their
+    /// clock values. Tombstones are included, but their content is only available if
+    /// the document doesn't garbage collect deleted blocks (see: [Options::skip_gc]).
+    pub fn client_blocks(&self, client: u64) -> Vec<BlockInfo> {
+        const PREVIEW_CHARS: usize = 32;
+        let blocks = match self.store.blocks.get(&client) {
+            Some(blocks) => blocks,
+            None => return Vec::new(),
+        };
+        let mut cache = ParentLinks::new();
+        let mut result = Vec::with_capacity(blocks.len());
+        for block in blocks.iter() {
+            let clock = block.id().clock..block.id().clock + block.len();
+            let info = match block {
+                Block::Item(item) => {
+                    let parent = self
+                        .store
+                        .get_type(&item.parent)
+                        .map(|branch| branch.borrow().ptr.clone());
+                    let mut path = Path::new();
+                    let root = parent.and_then(|ptr| {
+                        let root = self.path_to_root(ptr, &mut path, &mut cache);
+                        path.reverse();
+                        root
+                    });
+                    BlockInfo {
+                        clock,
+                        root,
+                        path,
+                        parent_sub: item.parent_sub.clone(),
+                        kind: item.content.kind(),
+                        preview: item.content.preview(PREVIEW_CHARS),
+                        deleted: item.is_deleted(),
+                    }
+                }
+                _ => BlockInfo {
+                    clock,
+                    root: None,
+                    path: Path::new(),
+                    parent_sub: None,
+                    kind: "GC",
+                    preview: None,
+                    deleted: true,
+                },
+            };
+            result.push(info);
+        }
+        result
+    }
+
+    /// Returns a textual dump of all blocks stored within a document, one per line, ordered by
+    /// their client and clock values. Each line contains block ID and length, content kind,
+    /// parent, origins, deletion flag and a short preview of the content.
+    ///
+    /// Output doesn't depend on hash map ordering or pointer values, so that dumps of two replicas
+    /// can be compared textually in order to find where their internal structures diverged.
+    /// See also: [Doc::integrity_check](crate::Doc::integrity_check).
+    pub fn dump_structs(&self) -> String {
+        use std::fmt::Write;
+
+        const PREVIEW_CHARS: usize = 32;
+        fn fmt_id(id: Option<&ID>) -> String {
+            id.map(ID::to_string).unwrap_or_else(|| "-".to_string())
+        }
+
+        let mut clients: Vec<u64> = self
+            .store
+            .blocks
+            .iter()
+            .map(|(client, _)| *client)
+            .collect();
+        clients.sort_unstable();
+        let mut out = String::new();
+        for client in clients {
+            for block in self.store.blocks.get(&client).unwrap().iter() {
+                write!(out, "{} len={}", block.id(), block.len()).unwrap();
+                match block {
+                    Block::Item(item) => {
+                        let parent = match &item.parent {
+                            TypePtr::Id(ptr) => ptr.id.to_string(),
+                            other => other.to_string(),
+                        };
+                        write!(
+                            out,
+                            " {} parent={} origin={} right_origin={}",
+                            item.content.kind(),
+                            parent,
+                            fmt_id(item.origin.as_ref()),
+                            fmt_id(item.right_origin.as_ref())
+                        )
+                        .unwrap();
+                        if let Some(key) = item.parent_sub.as_ref() {
+                            write!(out, " key={:?}", key).unwrap();
+                        }
+                        if item.is_deleted() {
+                            out.push_str(" deleted");
+                        }
+                        if let Some(preview) = item.content.preview(PREVIEW_CHARS) {
+                            write!(out, " {:?}", preview).unwrap();
+                        }
+                    }
+                    Block::GC(_) => out.push_str(" GC"),
+                    Block::Skip(_) => out.push_str(" Skip"),
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Encodes changes made in scope of a current transaction - blocks inserted and deleted by
+    /// it - as an update using lib0 ver. 1 encoding. It can be called both before and after
+    /// transaction has been committed. Once committed, returned update is equal to the one passed
+    /// to [Doc::on_commit] callbacks. If transaction made no changes, an update with no blocks
+    /// and an empty delete set is returned.
     ///
     /// Document updates are idempotent and commutative. Caveats:
     /// * It doesn't matter in which order document updates are applied.
@@ -137,10 +619,73 @@ impl<'a> Transaction<'a> {
     /// * Even if an update contains known information, the unknown information
     ///   is extracted and integrated into the document structure.
     pub fn encode_update_v1(&self) -> Vec<u8> {
-        let mut update_encoder = updates::encoder::EncoderV1::new();
-        self.store
-            .encode_diff(&self.before_state, &mut update_encoder);
-        update_encoder.to_vec()
+        let mut encoder = EncoderV1::new();
+        self.store.write_blocks(&self.before_state, &mut encoder);
+        if self.committed {
+            self.delete_set.encode(&mut encoder);
+        } else {
+            let mut ds = self.delete_set.clone();
+            ds.squash();
+            ds.encode(&mut encoder);
+        }
+        encoder.to_vec()
+    }
+
+    /// Checks if any blocks have been inserted or deleted in scope of a current transaction.
+    pub fn has_changes(&self) -> bool {
+        self.store.blocks.get_state_vector() != self.before_state || !self.delete_set.is_empty()
+    }
+
+    /// Encodes a difference between current document state and a remote one (described by its
+    /// state vector `sv`) using a given `encoder`.
+    pub fn encode_diff<E: Encoder>(&self, sv: &StateVector, encoder: &mut E) {
+        self.store.encode_diff(sv, encoder)
+    }
+
+    /// Encodes a difference between current document state and a remote one (described by its
+    /// state vector `sv`) using lib0 ver. 1 encoding, writing it directly into a given `writer`.
+    ///
+    /// This way an update can be appended to a reused `Vec<u8>` or written straight into a
+    /// preallocated `&mut [u8]` frame, which is then advanced past the written bytes. Use
+    /// [Transaction::diff_size_hint] to find out how much space is necessary.
+    pub fn encode_diff_v1_into<W: Write + ?Sized>(&self, sv: &StateVector, writer: &mut W) {
+        let mut encoder = EncoderV1::from_writer(writer);
+        self.store.encode_diff(sv, &mut encoder);
+    }
+
+    /// Returns a number of bytes, which is enough to fit a difference between current document
+    /// state and a remote one (described by its state vector `sv`) encoded using lib0 ver. 1
+    /// encoding. It can be used to presize buffers passed to [Transaction::encode_diff_v1_into].
+    pub fn diff_size_hint(&self, sv: &StateVector) -> usize {
+        let mut encoder = EncoderV1::from_writer(ByteCounter::default());
+        self.store.encode_diff(sv, &mut encoder);
+        encoder.into_inner().0
+    }
+
+    /// Encodes a difference between current document state and a remote one (described by its
+    /// state vector `sv`), limited only to the content of root types with given `roots` names.
+    ///
+    /// Applying such update to a fresh document creates only the chosen root types. Blocks of all
+    /// other root types are omitted and encoded as garbage collected ranges instead, which means
+    /// that a peer, which applied a filtered update, will consider them deleted - they won't be
+    /// delivered by any subsequent update based on that peer's state vector. Since an item's
+    /// origins always point to neighbours within the same parent type, content of chosen root
+    /// types can always be integrated on its own.
+    pub fn encode_state_filtered<E: Encoder>(
+        &self,
+        sv: &StateVector,
+        roots: &[&str],
+        encoder: &mut E,
+    ) {
+        self.store.encode_filtered_diff(sv, roots, encoder)
+    }
+
+    /// Same as [Transaction::encode_state_filtered], but returns an update encoded using lib0
+    /// ver. 1 encoding.
+    pub fn encode_state_filtered_v1(&self, sv: &StateVector, roots: &[&str]) -> Vec<u8> {
+        let mut encoder = updates::encoder::EncoderV1::new();
+        self.encode_state_filtered(sv, roots, &mut encoder);
+        encoder.to_vec()
     }
 
     pub(crate) fn iterate_structs<F>(&mut self, client: &u64, range: &Range<u32>, f: &F)
@@ -180,7 +725,7 @@ impl<'a> Transaction<'a> {
         if let Some(item) = block.as_item_mut() {
             if item.id.clock < clock {
                 // if we run over the clock, we need to the split item
-                let id = ID::new(*client, clock - item.id.clock);
+                let id = ID::new(*client, clock);
                 self.store
                     .blocks
                     .split_block(&BlockPtr::new(id, index as u32));
@@ -197,7 +742,7 @@ impl<'a> Transaction<'a> {
     {
         // equivalent of JS: Y.iterateDeletedStructs
         for (client, ranges) in set.iter() {
-            if self.store.blocks.contains_client(client) {
+            if self.store.blocks.get(client).is_some() {
                 for range in ranges.iter() {
                     self.iterate_structs(client, range, f);
                 }
@@ -210,7 +755,16 @@ impl<'a> Transaction<'a> {
     pub(crate) fn apply_delete(&mut self, ds: &DeleteSet) -> Option<DeleteSet> {
         let mut unapplied = DeleteSet::new();
         for (client, ranges) in ds.iter() {
-            let mut blocks = self.store.blocks.get_mut(client).unwrap();
+            let mut blocks = match self.store.blocks.get_mut(client) {
+                Some(blocks) => blocks,
+                None => {
+                    // no blocks of that client have been integrated yet
+                    for range in ranges.iter() {
+                        unapplied.insert(ID::new(*client, range.start), range.end - range.start);
+                    }
+                    continue;
+                }
+            };
             let state = blocks.get_state();
 
             for range in ranges.iter() {
@@ -227,10 +781,8 @@ impl<'a> Transaction<'a> {
                         if let Some(item) = blocks[index].as_item_mut() {
                             // split the first item if necessary
                             if !item.is_deleted() && item.id.clock < clock {
-                                let split_ptr = BlockPtr::new(
-                                    ID::new(*client, clock - item.id.clock),
-                                    index as u32,
-                                );
+                                let split_ptr =
+                                    BlockPtr::new(ID::new(*client, clock), index as u32);
                                 let (_, right) = self.store.blocks.split_block(&split_ptr);
                                 if let Some(right) = right {
                                     index += 1;
@@ -290,34 +842,66 @@ impl<'a> Transaction<'a> {
 
         if let Some(item) = self.store.blocks.get_item(&ptr) {
             if !item.is_deleted() {
-                if item.parent_sub.is_none() && item.is_countable() {
+                if self.integrating {
+                    self.remote_changes = true;
+                } else {
+                    self.local_changes = true;
+                }
+                self.stats.deleted_blocks += 1;
+                if item.is_countable() {
+                    self.stats.removed_len += item.len();
+                }
+                if item.parent_sub.is_none() {
                     if let Some(parent) = self.store.get_type(&item.parent) {
                         let mut inner = parent.borrow_mut();
-                        inner.len -= item.len();
+                        if item.is_countable() {
+                            inner.len -= item.len();
+                        }
+                        inner.search_markers.clear();
+                    }
+                }
+
+                if let Some(parent_sub) = item.parent_sub.as_ref() {
+                    // parent can be already mutably borrowed when deleting a previous map entry in
+                    // scope of Item::integrate, which has replaced that entry on its own
+                    if let Some(Ok(parent)) =
+                        self.store.get_type(&item.parent).map(|p| p.try_borrow())
+                    {
+                        if parent.map.get(parent_sub).map(|p| p.id) == Some(item.id) {
+                            parent.map_len.set(parent.map_len.get() - 1);
+                        }
                     }
                 }
 
                 item.mark_as_deleted();
+                self.store.deletions.set(self.store.deletions.get() + 1);
+                let size = &self.store.content_size;
+                debug_assert!(size.get() >= item.content_size());
+                size.set(size.get().saturating_sub(item.content_size()));
                 self.delete_set.insert(item.id.clone(), item.len());
-                // addChangedTypeToTransaction(transaction, item.type, item.parentSub)
-                if item.id.clock < self.before_state.get(&item.id.client) {
-                    let set = self.changed.entry(item.parent.clone()).or_default();
-                    set.insert(item.parent_sub.clone());
+                // parent can be already borrowed when deleting a previous map entry in scope of
+                // Item::integrate, which marks the same parent as changed on its own
+                if let Some(Ok(parent)) = self.store.get_type(&item.parent).map(|p| p.try_borrow())
+                {
+                    self.store.invalidate_content_size(&parent);
+                    if is_type_changed(&self.store, &self.before_state, &parent) {
+                        let set = self.changed.entry(parent.ptr.clone()).or_default();
+                        set.insert(item.parent_sub.clone());
+                    }
                 }
 
                 match &item.content {
-                    ItemContent::Doc(_, _) => {
-                        //if (transaction.subdocsAdded.has(this.doc)) {
-                        //    transaction.subdocsAdded.delete(this.doc)
-                        //} else {
-                        //    transaction.subdocsRemoved.add(this.doc)
-                        //}
-                        todo!()
+                    ItemContent::Doc(guid, _) => {
+                        if self.subdocs_added.remove(guid).is_none() {
+                            self.subdocs_removed.insert(guid.clone());
+                        }
+                        self.subdocs_loaded.remove(guid);
                     }
                     ItemContent::Type(t) => {
                         let inner = t.borrow_mut();
                         let mut ptr = inner.start;
-                        self.changed.remove(&item.parent);
+                        // deleted type will not emit any events
+                        self.changed.remove(&inner.ptr);
 
                         while let Some(item) = ptr.and_then(|ptr| self.store.blocks.get_item(&ptr))
                         {
@@ -349,13 +933,193 @@ impl<'a> Transaction<'a> {
         result
     }
 
+    /// Reverts changes introduced by a given lib0 v1 encoded `update`, which has been already
+    /// applied to a current document: content inserted by it is deleted again and content deleted
+    /// by it is restored. See [Transaction::revert_ranges] for details.
+    pub fn revert(&mut self, update: &[u8]) -> Result<RevertReport, Error> {
+        let mut decoder = DecoderV1::from(update);
+        let update = Update::decode(&mut decoder)?;
+        let deleted = DeleteSet::decode(&mut decoder)?;
+        let mut inserted = DeleteSet::new();
+        for block in update.blocks() {
+            if let Block::Item(item) = block {
+                inserted.insert(item.id, item.len());
+            }
+        }
+        Ok(self.revert_ranges(&inserted, &deleted))
+    }
+
+    /// Reverts changes made to a current document: blocks within `inserted` ID ranges are deleted,
+    /// while blocks within `deleted` ID ranges get their content restored. Since deleted blocks
+    /// can not be brought back, restored content is inserted as new blocks right next to them,
+    /// so that reverting changes can be synchronized with other peers just like any other change.
+    ///
+    /// Restoring deleted content requires a document to not garbage collect it (see:
+    /// [Options::skip_gc]). Blocks, which could not be reverted, are listed as conflicts of
+    /// a returned report. Deleted blocks don't keep track of their restored copies, so reverting
+    /// the same changes twice restores their deleted content twice.
+    pub fn revert_ranges(&mut self, inserted: &DeleteSet, deleted: &DeleteSet) -> RevertReport {
+        let mut report = RevertReport::default();
+
+        let mut ds = DeleteSet::new();
+        for (client, ranges) in inserted.iter() {
+            for range in ranges.iter() {
+                for ptr in self.split_range(*client, range, &mut report.conflicts) {
+                    match self.store.blocks.get_item(&ptr) {
+                        Some(item) if !item.is_deleted() => {
+                            if item.is_countable() {
+                                report.deleted += item.len();
+                            }
+                            ds.insert(item.id, item.len());
+                        }
+                        Some(item) => report
+                            .conflicts
+                            .push(RevertConflict::AlreadyDeleted(item.id, item.len())),
+                        None => {} // GC blocks have been deleted long ago
+                    }
+                }
+            }
+        }
+        self.apply_delete(&ds);
+
+        // restored map entries override the current ones, so they must be deleted beforehand
+        for (client, ranges) in deleted.iter() {
+            for range in ranges.iter() {
+                for ptr in self.split_range(*client, range, &mut report.conflicts) {
+                    if !inserted.is_deleted(&ptr.id) {
+                        self.restore(&ptr, &mut report);
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Splits blocks of a given `client` at the boundaries of a clock `range`, returning pointers
+    /// to all blocks within that range. Parts of the range, which are not present in a block
+    /// store, are reported as conflicts.
+    fn split_range(
+        &mut self,
+        client: u64,
+        range: &Range<u32>,
+        conflicts: &mut Vec<RevertConflict>,
+    ) -> Vec<BlockPtr> {
+        let state = self.store.blocks.get_state(&client);
+        if state < range.end {
+            let start = range.start.max(state);
+            conflicts.push(RevertConflict::Missing(
+                ID::new(client, start),
+                range.end - start,
+            ));
+        }
+        let end = range.end.min(state);
+        let mut result = Vec::new();
+        if range.start >= end {
+            return result;
+        }
+        if end < state {
+            self.find_index_clean_start(&client, end);
+        }
+        if let Some(mut index) = self.find_index_clean_start(&client, range.start) {
+            let blocks = self.store.blocks.get(&client).unwrap();
+            while index < blocks.len() && blocks[index].id().clock < end {
+                result.push(BlockPtr::new(*blocks[index].id(), index as u32));
+                index += 1;
+            }
+        }
+        result
+    }
+
+    /// Inserts a copy of a deleted block content right after that block.
+    fn restore(&mut self, ptr: &BlockPtr, report: &mut RevertReport) {
+        let (pos, content, parent_sub, len) = match self.store.blocks.get_item(ptr) {
+            None => {
+                let len = self
+                    .store
+                    .blocks
+                    .get_block(ptr)
+                    .map(Block::len)
+                    .unwrap_or(0);
+                let conflict = RevertConflict::GarbageCollected(ptr.id, len);
+                report.conflicts.push(conflict);
+                return;
+            }
+            Some(item) if !item.is_deleted() => {
+                let conflict = RevertConflict::NotDeleted(item.id, item.len());
+                report.conflicts.push(conflict);
+                return;
+            }
+            Some(item) => {
+                let content = match restored_content(&item.content) {
+                    Some(content) => content,
+                    None => {
+                        let conflict = if let ItemContent::Deleted(_) = item.content {
+                            RevertConflict::GarbageCollected(item.id, item.len())
+                        } else {
+                            RevertConflict::UnsupportedContent(item.id, item.len())
+                        };
+                        report.conflicts.push(conflict);
+                        return;
+                    }
+                };
+                let parent = match self.store.get_type(&item.parent) {
+                    Some(parent) => parent.borrow(),
+                    None => {
+                        let conflict = RevertConflict::ParentDeleted(item.id, item.len());
+                        report.conflicts.push(conflict);
+                        return;
+                    }
+                };
+                let parent_deleted = parent
+                    .item
+                    .as_ref()
+                    .and_then(|p| self.store.blocks.get_item(p))
+                    .map(Item::is_deleted)
+                    .unwrap_or(false);
+                if parent_deleted {
+                    let conflict = RevertConflict::ParentDeleted(item.id, item.len());
+                    report.conflicts.push(conflict);
+                    return;
+                }
+                let (left, right) = match item.parent_sub.as_ref() {
+                    // map entries are always appended after the current entry under the same key
+                    Some(key) => (parent.map.get(key).cloned(), None),
+                    None => (Some(*ptr), item.right),
+                };
+                let pos = ItemPosition {
+                    parent: item.parent.clone(),
+                    left,
+                    right,
+                    index: 0,
+                };
+                let len = if item.is_countable() { item.len() } else { 0 };
+                (pos, content, item.parent_sub.clone(), len)
+            }
+        };
+        self.create_item(&pos, RestoredContent(content), parent_sub);
+        report.restored += len;
+    }
+
     pub fn apply_update(&mut self, mut update: Update, mut ds: DeleteSet) {
+        #[cfg(feature = "log")]
+        let _span = tracing::debug_span!(
+            "apply_update",
+            guid = %self.store.guid,
+            client_id = self.store.client_id,
+            clients = update.clients_len(),
+            clocks = ?update.clock_ranges(),
+        )
+        .entered();
         if self.store.update_events.has_subscribers() {
             let event = UpdateEvent::new(update, ds);
-            self.store.update_events.publish(&event);
+            self.store
+                .update_events
+                .publish(&event, &self.store.observer_error_events);
             update = event.update;
             ds = event.delete_set;
         }
+        let integrating = std::mem::replace(&mut self.integrating, true);
+        let before_state = self.store.blocks.get_state_vector();
         let remaining = update.integrate(self);
 
         let mut retry = false;
@@ -369,14 +1133,20 @@ impl<'a> Transaction<'a> {
             }
 
             if let Some(remaining) = remaining {
+                #[cfg(feature = "log")]
+                tracing::debug!(missing = ?remaining.missing, "update blocks added to pending queue");
                 // merge restStructs into store.pending
                 for (&client, &clock) in remaining.missing.iter() {
                     pending.missing.set_min(client, clock);
                 }
                 pending.update.merge(remaining.update);
-                self.store.pending = Some(pending);
             }
+            self.store.pending = Some(pending);
         } else {
+            #[cfg(feature = "log")]
+            if let Some(remaining) = &remaining {
+                tracing::debug!(missing = ?remaining.missing, "update blocks added to pending queue");
+            }
             self.store.pending = remaining;
         }
 
@@ -398,18 +1168,58 @@ impl<'a> Transaction<'a> {
 
         if retry {
             if let Some(pending) = self.store.pending.take() {
+                #[cfg(feature = "log")]
+                tracing::debug!(missing = ?pending.missing, "retrying pending update");
                 let ds = self.store.pending_ds.take().unwrap_or_default();
                 self.apply_update(pending.update, ds);
             }
         }
+
+        for (client, &clock) in self.store.blocks.get_state_vector().iter() {
+            if clock > before_state.get(client) {
+                self.integrated_clients.insert(*client);
+                self.remote_changes = true;
+            }
+        }
+        self.integrating = integrating;
+    }
+
+    /// Checks if adding `added` bytes of content could make a current document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size).
+    pub(crate) fn check_content_quota(&self, added: usize) -> Result<(), Error> {
+        if let Some(limit) = self.store.max_content_size {
+            let size = self.store.content_size.get() + added;
+            if added > 0 && size > limit {
+                return Err(Error::QuotaExceeded { limit, size });
+            }
+        }
+        Ok(())
     }
 
+    /// Creates a new item with a given `value` at position `pos`. Panics if it would make
+    /// a document exceed its [Options::max_content_size](crate::Options::max_content_size).
     pub(crate) fn create_item<T: Prelim>(
         &mut self,
         pos: &block::ItemPosition,
         value: T,
         parent_sub: Option<String>,
     ) -> &Item {
+        match self.try_create_item(pos, value, parent_sub) {
+            Ok(item) => item,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a new item with a given `value` at position `pos`. If it would make a document
+    /// exceed its [Options::max_content_size](crate::Options::max_content_size), an
+    /// [Error::QuotaExceeded] is returned and nothing is integrated. Content of nested preliminary
+    /// types is inserted after their item is created, so it's checked item by item.
+    pub(crate) fn try_create_item<T: Prelim>(
+        &mut self,
+        pos: &block::ItemPosition,
+        value: T,
+        parent_sub: Option<String>,
+    ) -> Result<&Item, Error> {
         let left = pos.left;
         let right = pos.right;
         let origin = if let Some(ptr) = pos.left.as_ref() {
@@ -434,6 +1244,10 @@ impl<'a> Transaction<'a> {
 
         let ptr = BlockPtr::new(id, pivot);
         let (content, remainder) = value.into_content(self, TypePtr::Id(ptr));
+        self.check_content_quota(
+            content.content_size() + parent_sub.as_ref().map_or(0, String::len),
+        )?;
+        self.local_changes = true;
         let inner_ref = if let ItemContent::Type(inner_ref) = &content {
             Some(inner_ref.clone())
         } else {
@@ -450,6 +1264,10 @@ impl<'a> Transaction<'a> {
             content,
         );
         item.integrate(self, pivot, 0);
+        self.stats.inserted_blocks += 1;
+        if item.is_countable() {
+            self.stats.added_len += item.len();
+        }
         let local_block_list = self.store.blocks.get_client_blocks_mut(client_id);
         local_block_list.push(block::Block::Item(item));
         let idx = local_block_list.len() - 1;
@@ -458,9 +1276,9 @@ impl<'a> Transaction<'a> {
             remainder.integrate(self, inner_ref.unwrap())
         }
 
-        self.store.blocks.get_client_blocks_mut(client_id)[idx]
+        Ok(self.store.blocks.get_client_blocks_mut(client_id)[idx]
             .as_item()
-            .unwrap()
+            .unwrap())
     }
 
     /// Commits current transaction. This step involves cleaning up and optimizing changes performed
@@ -468,16 +1286,65 @@ impl<'a> Transaction<'a> {
     /// or squashing blocks that have been appended one after another to preserve memory.
     ///
     /// This step is performed automatically when a transaction is about to be dropped (its life
-    /// scope comes to an end).
+    /// scope comes to an end). Committing a transaction more than once has no effect, therefore
+    /// committed transaction should no longer be used to modify a document.
     pub fn commit(&mut self) {
+        if self.committed {
+            return;
+        }
+        self.committed = true;
+        #[cfg(feature = "log")]
+        let span = tracing::debug_span!(
+            "commit",
+            guid = %self.store.guid,
+            client_id = self.store.client_id,
+            blocks = tracing::field::Empty,
+            deleted = tracing::field::Empty,
+            squashed = tracing::field::Empty,
+            duration_us = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "log")]
+        let mut squashed = 0usize;
+
+        self.record_timestamp();
+
         // 1. sort and merge delete set
         self.delete_set.squash();
         self.after_state = self.store.blocks.get_state_vector();
+        if self.local_changes {
+            self.store.unsynced = true;
+            if !self.delete_set.is_empty() {
+                self.store.unsynced_deletes.merge(self.delete_set.clone());
+            }
+        }
+        #[cfg(feature = "log")]
+        {
+            span.record("blocks", self.added_blocks_count());
+            span.record("deleted", self.delete_set.iter().count());
+        }
 
         // 2. emit 'beforeObserverCalls'
         // 3. for each change observed by the transaction call 'afterTransaction'
+        self.call_observers();
+        let summary = if self.store.after_transaction_events.has_subscribers()
+            || self
+                .store
+                .after_transaction_cleanup_events
+                .has_subscribers()
+        {
+            Some(self.summary())
+        } else {
+            None
+        };
+        if let Some(summary) = summary.as_ref() {
+            self.store.after_transaction_events.publish(self, summary);
+        }
+
         // 4. try GC delete set
-        self.try_gc(); //TODO: eventually this is a configurable variant: if (doc.gc)
+        if !self.store.skip_gc {
+            self.try_gc();
+        }
 
         // 5. try merge delete set
         self.delete_set.try_compact(&self.store.blocks);
@@ -491,6 +1358,10 @@ impl<'a> Transaction<'a> {
                 let mut i = blocks.len() - 1;
                 while i >= first_change {
                     if let Some(compaction) = blocks.squash_left(i) {
+                        #[cfg(feature = "log")]
+                        {
+                            squashed += 1;
+                        }
                         self.store.gc_cleanup(compaction);
                         blocks = self.store.blocks.get_mut(client).unwrap();
                     }
@@ -504,24 +1375,273 @@ impl<'a> Transaction<'a> {
             let clock = id.clock;
             let blocks = self.store.blocks.get_mut(&client).unwrap();
             let replaced_pos = blocks.find_pivot(clock).unwrap();
-            if replaced_pos + 1 < blocks.len() {
-                if let Some(compaction) = blocks.squash_left(replaced_pos + 1) {
-                    self.store.gc_cleanup(compaction);
-                }
+            let compaction = if replaced_pos + 1 < blocks.len() {
+                blocks.squash_left(replaced_pos + 1)
             } else if replaced_pos > 0 {
-                if let Some(compaction) = blocks.squash_left(replaced_pos) {
-                    self.store.gc_cleanup(compaction);
+                blocks.squash_left(replaced_pos)
+            } else {
+                None
+            };
+            if let Some(compaction) = compaction {
+                #[cfg(feature = "log")]
+                {
+                    squashed += 1;
                 }
+                self.store.gc_cleanup(compaction);
             }
         }
         // 8. emit 'afterTransactionCleanup'
+        if let Some(summary) = summary.as_ref() {
+            self.store
+                .after_transaction_cleanup_events
+                .publish(self, summary);
+        }
         // 9. emit 'update'
+        self.call_commit_observers();
         // 10. emit 'updateV2'
         // 11. add and remove subdocs
         // 12. emit 'subdocs'
+        self.call_subdocs_observers();
+        self.call_root_types_observers();
+
+        #[cfg(feature = "log")]
+        {
+            span.record("squashed", squashed);
+            span.record("duration_us", self.started.elapsed().as_micros() as u64);
+        }
+    }
+
+    /// Stores a commit time of blocks created by a local client in scope of a current transaction
+    /// in [TIMESTAMPS_ROOT] map, if a document has been configured to do so (see:
+    /// [Options::record_timestamps]). Blocks of an entry itself are not timestamped.
+    fn record_timestamp(&mut self) {
+        if !self.store.record_timestamps {
+            return;
+        }
+        let client = self.store.client_id;
+        let start = self.before_state.get(&client);
+        let end = self.store.blocks.get_state(&client);
+        if start < end {
+            let edit = EditTimestamp {
+                client,
+                clock: start..end,
+                timestamp: unix_millis(),
+            };
+            let timestamps =
+                Map::from(self.store.create_type(TIMESTAMPS_ROOT, None, TYPE_REFS_MAP));
+            timestamps.insert(self, edit.key(), edit.to_any());
+        }
+    }
+
+    /// Returns a summary of changes made in scope of a current transaction.
+    fn summary(&self) -> AfterTransactionEvent {
+        let mut changed = HashSet::new();
+        let mut changed_parent_types = HashSet::new();
+        for ptr in self.changed.keys() {
+            let branch = match self.store.get_type(ptr) {
+                Some(branch) => branch.clone(),
+                None => continue,
+            };
+            if let Some(id) = self.branch_id(&branch) {
+                changed.insert(id);
+            }
+            let mut current = Some(branch);
+            while let Some(b) = current {
+                let visited = match self.branch_id(&b) {
+                    Some(id) => !changed_parent_types.insert(id),
+                    None => true,
+                };
+                if visited {
+                    // this type and all of its parents have been already visited
+                    break;
+                }
+                current = self.parent_of(&b);
+            }
+        }
+        AfterTransactionEvent {
+            origin: self.origin.clone(),
+            before_state: self.before_state.clone(),
+            after_state: self.after_state.clone(),
+            delete_set: self.delete_set.clone(),
+            changed,
+            changed_parent_types,
+            source: self.change_source(),
+            integrated_clients: self.integrated_clients.clone(),
+            stats: self.stats(),
+        }
+    }
+
+    /// Returns counters of operations performed in scope of a current transaction so far.
+    ///
+    /// All counters except [TransactionStats::update_len] are collected during mutation. An update
+    /// length requires encoding changes made by current transaction, which happens every time this
+    /// method is called.
+    pub fn stats(&self) -> TransactionStats {
+        TransactionStats {
+            update_len: self.encode_update_v1().len(),
+            ..self.stats
+        }
+    }
+
+    pub(crate) fn stats_mut(&mut self) -> &mut TransactionStats {
+        &mut self.stats
+    }
+
+    /// Returns changes made in scope of a current transaction so far, grouped by names of
+    /// root-level types containing changed types. Events are the same as the ones, which deep
+    /// observers of these root types would receive if a transaction was committed right now: their
+    /// paths are relative to a root type and they're ordered by path length.
+    ///
+    /// Changes are computed on demand against the state of a document at the beginning of
+    /// a transaction, so this method can be called any number of times before commit, ie. to
+    /// validate changes before deciding whether they should be kept. Calling it doesn't trigger
+    /// any observers.
+    pub fn pending_changes(&self) -> HashMap<String, Vec<Event>> {
+        let mut result: HashMap<String, Vec<Event>> = HashMap::new();
+        let mut cache = ParentLinks::new();
+        for (ptr, keys) in self.changed.iter() {
+            let branch = match self.store.get_type(ptr) {
+                Some(branch) => branch.clone(),
+                None => continue,
+            };
+            let deleted = branch
+                .borrow()
+                .item
+                .and_then(|ptr| self.store.blocks.get_item(&ptr))
+                .map(|item| item.is_deleted())
+                .unwrap_or(false);
+            if deleted {
+                continue;
+            }
+            if let Some(mut event) = Event::new(self, &branch, keys) {
+                let mut path = Path::new();
+                let ptr = branch.borrow().ptr.clone();
+                if let Some(root) = self.path_to_root(ptr, &mut path, &mut cache) {
+                    path.reverse();
+                    event.set_path(path);
+                    result.entry(root).or_default().push(event);
+                }
+            }
+        }
+        for events in result.values_mut() {
+            events.sort_by_key(|e| e.path().len());
+        }
+        result
+    }
+
+    /// Returns an identifier of a given `branch`.
+    fn branch_id(&self, branch: &BranchRef) -> Option<BranchId> {
+        let item = branch.borrow().item;
+        match item {
+            Some(ptr) => Some(BranchId::Nested(ptr.id)),
+            None => {
+                let name = self.store.get_root_type_key(branch)?;
+                Some(BranchId::Root(name.as_ref().clone()))
+            }
+        }
+    }
+
+    /// Returns a number of blocks integrated into a block store in scope of a current
+    /// transaction, before they have been squashed.
+    #[cfg(feature = "log")]
+    fn added_blocks_count(&self) -> usize {
+        let mut count = 0;
+        for (client, &clock) in self.after_state.iter() {
+            let before_clock = self.before_state.get(client);
+            if before_clock != clock {
+                if let Some(blocks) = self.store.blocks.get(client) {
+                    let first_change = blocks.find_pivot(before_clock).unwrap_or(0);
+                    count += blocks.len() - first_change;
+                }
+            }
+        }
+        count
+    }
+
+    fn call_commit_observers(&self) {
+        if !self.store.commit_events.has_subscribers()
+            || (self.before_state == self.after_state && self.delete_set.is_empty())
+        {
+            return;
+        }
+
+        let event = CommitEvent {
+            update: self.encode_update_v1(),
+            source: self.change_source(),
+            origin: self.origin.clone(),
+            roots: self.changed_roots(),
+            has_deletions: !self.delete_set.is_empty(),
+        };
+        self.store
+            .commit_events
+            .publish(&event, &self.store.observer_error_events);
+    }
+
+    fn call_subdocs_observers(&mut self) {
+        let added = std::mem::take(&mut self.subdocs_added);
+        let removed = std::mem::take(&mut self.subdocs_removed);
+        let loaded = std::mem::take(&mut self.subdocs_loaded);
+        if added.is_empty() && removed.is_empty() && loaded.is_empty() {
+            return;
+        }
+
+        let mut event = SubdocsEvent::default();
+        for guid in removed {
+            self.store.subdocs.remove(&guid);
+            event.removed.push(guid);
+        }
+        for (guid, subdoc) in added {
+            self.store.subdocs.insert(guid.clone(), subdoc);
+            event.added.push(guid);
+        }
+        event.loaded.extend(loaded);
+        event.added.sort();
+        event.removed.sort();
+        event.loaded.sort();
+
+        if self.store.subdocs_events.has_subscribers() {
+            self.store
+                .subdocs_events
+                .publish(&event, &self.store.observer_error_events);
+        }
+    }
+
+    fn call_root_types_observers(&mut self) {
+        let names = std::mem::take(&mut self.store.created_types);
+        if names.is_empty() || !self.store.root_types_events.has_subscribers() {
+            return;
+        }
+
+        let mut created: Vec<_> = names
+            .into_iter()
+            .filter_map(|name| {
+                let branch = self.store.types.get(&name)?;
+                let kind = kind_name(branch.borrow().type_ref());
+                Some((name.to_string(), kind))
+            })
+            .collect();
+        created.sort();
+        let non_schema = match self.store.schema.as_ref() {
+            Some(schema) => created
+                .iter()
+                .filter(|(name, _)| !schema.iter().any(|(declared, _)| declared == name))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        self.store.root_types_events.publish(
+            &RootTypesEvent {
+                created,
+                non_schema,
+            },
+            &self.store.observer_error_events,
+        );
     }
 
     fn try_gc(&mut self) {
+        #[cfg(feature = "log")]
+        let mut collected = 0usize;
+        let protected = std::mem::take(&mut self.store.gc_protected);
         for (client, range) in self.delete_set.iter() {
             if let Some(blocks) = self.store.blocks.get_mut(client) {
                 for delete_item in range.iter().rev() {
@@ -536,7 +1656,11 @@ impl<'a> Transaction<'a> {
                             } else {
                                 if let Block::Item(item) = block {
                                     if item.is_deleted() {
-                                        if let ItemContent::Type(t) = &item.content {
+                                        if let ItemContent::Type(_) = &item.content {
+                                            // Nested types are not garbage collected yet: their
+                                            // children still refer to them via parent pointers,
+                                            // so we keep the type content intact.
+
                                             /*
                                             let item = this.type._start
                                             while (item !== null) {
@@ -552,10 +1676,16 @@ impl<'a> Transaction<'a> {
                                             })
                                             this.type._map = new Map()
                                             */
-                                            todo!()
+                                        } else if !protected
+                                            .iter()
+                                            .any(|s| s.is_visible(&item.id, len))
+                                        {
+                                            #[cfg(feature = "log")]
+                                            if !matches!(item.content, ItemContent::Deleted(_)) {
+                                                collected += 1;
+                                            }
+                                            item.content = ItemContent::Deleted(len);
                                         }
-
-                                        item.content = ItemContent::Deleted(len);
                                     }
                                 }
                                 i += 1;
@@ -565,16 +1695,229 @@ impl<'a> Transaction<'a> {
                 }
             }
         }
+        self.store.gc_protected = protected;
+        #[cfg(feature = "log")]
+        tracing::trace!(collected, "garbage collection finished");
+    }
+
+    /// Marks a given `parent` type as changed in scope of a current transaction, unless it was
+    /// created or deleted in it. `parent_sub` is a key of changed map entry or `None` if indexed
+    /// sequence component of a type has changed.
+    pub(crate) fn add_changed_type(&mut self, parent: &Branch, parent_sub: Option<&String>) {
+        if is_type_changed(&self.store, &self.before_state, parent) {
+            let set = self.changed.entry(parent.ptr.clone()).or_default();
+            set.insert(parent_sub.cloned());
+        }
+    }
+
+    /// Returns names of root-level types containing types changed in scope of a current
+    /// transaction, in alphabetical order.
+    fn changed_roots(&self) -> Vec<String> {
+        let mut roots = Vec::new();
+        for ptr in self.changed.keys() {
+            let mut current = self.store.get_type(ptr).cloned();
+            while let Some(branch) = current {
+                match self.parent_of(&branch) {
+                    Some(parent) => current = Some(parent),
+                    None => {
+                        if let Some(name) = self.store.get_root_type_key(&branch) {
+                            roots.push(name.as_ref().clone());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        roots.sort_unstable();
+        roots.dedup();
+        roots
+    }
+
+    /// Returns a parent type of a given `branch` or `None` if it's a root-level type.
+    fn parent_of(&self, branch: &BranchRef) -> Option<BranchRef> {
+        let item = branch.borrow().item?;
+        let item = self.store.blocks.get_item(&item)?;
+        self.store.get_type(&item.parent).cloned()
     }
 
-    pub(crate) fn add_changed_type(&mut self, parent: &mut Branch, parent_sub: Option<&String>) {
-        // TODO:
-        /*
-              const item = type._item
-              if (item === null || (item.id.clock < (transaction.beforeState.get(item.id.client) || 0) && !item.deleted)) {
-                map.setIfUndefined(transaction.changed, type, set.create).add(parentSub)
-              }
-        */
+    /// Returns a path leading from an `ancestor` type to a given `ptr` type. Links between types
+    /// and their parents resolved on the way are stored in a `cache`, so that paths of multiple
+    /// events sharing the same ancestors don't need to resolve them over again.
+    fn path_from(&self, ancestor: &TypePtr, ptr: &TypePtr, cache: &mut ParentLinks) -> Path {
+        let mut path = Path::new();
+        let mut current = ptr.clone();
+        while &current != ancestor {
+            let link = match cache.get(&current) {
+                Some(link) => link.clone(),
+                None => {
+                    let link = self.parent_link(&current);
+                    cache.insert(current.clone(), link.clone());
+                    link
+                }
+            };
+            match link {
+                Some((parent, segment)) => {
+                    path.push(segment);
+                    current = parent;
+                }
+                None => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// Walks up from a type pointed by a given `ptr` to its root-level type, pushing path segments
+    /// on the way into `path` (in reverse order). Returns a name of a root type or `None` if
+    /// a chain of parents is broken, ie. by garbage collection.
+    fn path_to_root(
+        &self,
+        mut current: TypePtr,
+        path: &mut Path,
+        cache: &mut ParentLinks,
+    ) -> Option<String> {
+        loop {
+            let link = match cache.get(&current) {
+                Some(link) => link.clone(),
+                None => {
+                    let link = self.parent_link(&current);
+                    cache.insert(current.clone(), link.clone());
+                    link
+                }
+            };
+            match link {
+                Some((parent, segment)) => {
+                    path.push(segment);
+                    current = parent;
+                }
+                None => {
+                    return match current {
+                        TypePtr::Named(name) => Some(name.as_ref().clone()),
+                        _ => None,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a parent of a type pointed by a given `ptr` together with a path segment under
+    /// which that type can be found within its parent at the moment. Returns `None` for root-level
+    /// types.
+    fn parent_link(&self, ptr: &TypePtr) -> Option<(TypePtr, PathSegment)> {
+        let branch = self.store.get_type(ptr)?;
+        let item = branch.borrow().item?;
+        let item = self.store.blocks.get_item(&item)?;
+        let parent = self.store.get_type(&item.parent)?;
+        let segment = if let Some(key) = item.parent_sub.as_ref() {
+            PathSegment::Key(key.clone())
+        } else {
+            let mut index = 0;
+            let mut c = parent.borrow().start;
+            while let Some(i) = c.and_then(|ptr| self.store.blocks.get_item(&ptr)) {
+                if i.id == item.id {
+                    break;
+                }
+                if !i.is_deleted() && i.is_countable() {
+                    index += i.len();
+                }
+                c = i.right;
+            }
+            PathSegment::Index(index)
+        };
+        let parent_ptr = parent.borrow().ptr.clone();
+        Some((parent_ptr, segment))
+    }
+
+    /// Calls callbacks subscribed to all types changed in scope of a current transaction, as well
+    /// as deep observers of their parents.
+    fn call_observers(&mut self) {
+        let changed = std::mem::take(&mut self.changed);
+        let mut deep_events: HashMap<TypePtr, (Observer<Vec<Event>>, Vec<Event>)> = HashMap::new();
+        for (ptr, keys) in changed.iter() {
+            let branch = match self.store.get_type(ptr) {
+                Some(branch) => branch.clone(),
+                None => continue,
+            };
+            let deleted = branch
+                .borrow()
+                .item
+                .and_then(|ptr| self.store.blocks.get_item(&ptr))
+                .map(|item| item.is_deleted())
+                .unwrap_or(false);
+            if deleted {
+                continue;
+            }
+
+            // collect deep observers of a changed type and all of its parents
+            let mut deep_observers = Vec::new();
+            let mut current = Some(branch.clone());
+            while let Some(b) = current {
+                {
+                    let inner = b.borrow();
+                    if let Some(observer) = inner.deep_observers.as_ref() {
+                        if observer.has_subscribers() {
+                            deep_observers.push((inner.ptr.clone(), observer.clone()));
+                        }
+                    }
+                }
+                current = self.parent_of(&b);
+            }
+            let observers = branch.borrow().observers.clone();
+            let has_observers = match &observers {
+                Some(Observers::Array(o)) => o.has_subscribers(),
+                Some(Observers::Map(o)) => o.has_subscribers(),
+                Some(Observers::Text(o)) => o.has_subscribers(),
+                Some(Observers::Xml(o)) => o.has_subscribers(),
+                Some(Observers::XmlText(o)) => o.has_subscribers(),
+                None => false,
+            };
+            if !has_observers && deep_observers.is_empty() {
+                continue;
+            }
+
+            if let Some(event) = Event::new(self, &branch, keys) {
+                match (&observers, &event) {
+                    (Some(Observers::Array(o)), Event::Array(e)) => o.publish(self, e),
+                    (Some(Observers::Map(o)), Event::Map(e)) => o.publish(self, e),
+                    (Some(Observers::Text(o)), Event::Text(e)) => o.publish(self, e),
+                    (Some(Observers::Xml(o)), Event::Xml(e)) => o.publish(self, e),
+                    (Some(Observers::XmlText(o)), Event::XmlText(e)) => o.publish(self, e),
+                    _ => {}
+                }
+                for (ptr, observer) in deep_observers {
+                    let entry = deep_events
+                        .entry(ptr)
+                        .or_insert_with(|| (observer, Vec::new()));
+                    entry.1.push(event.clone());
+                }
+            }
+        }
+
+        let mut parent_links = ParentLinks::new();
+        for (ptr, (observer, mut events)) in deep_events {
+            for event in events.iter_mut() {
+                let path = self.path_from(&ptr, &event.target_ptr(), &mut parent_links);
+                event.set_path(path);
+            }
+            events.sort_by_key(|e| e.path().len());
+            observer.publish(self, &events);
+        }
+        self.changed = changed;
+    }
+}
+
+/// Parents of shared types together with path segments leading from them to their children.
+type ParentLinks = HashMap<TypePtr, Option<(TypePtr, PathSegment)>>;
+
+/// Checks if changes made to a given `branch` should be observable, which is true unless
+/// a branch has been created or deleted in scope of a transaction with a given `before_state`.
+fn is_type_changed(store: &Store, before_state: &StateVector, branch: &Branch) -> bool {
+    match branch.item.as_ref() {
+        None => true,
+        Some(ptr) => match store.blocks.get_item(ptr) {
+            Some(item) => item.id.clock < before_state.get(&item.id.client) && !item.is_deleted(),
+            None => false,
+        },
     }
 }
 
@@ -583,3 +1926,87 @@ impl<'a> Drop for Transaction<'a> {
         self.commit()
     }
 }
+
+/// Counters of operations performed in scope of a single transaction, returned by
+/// [Transaction::stats] and attached to [AfterTransactionEvent].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionStats {
+    /// Number of blocks created by local insert operations.
+    pub inserted_blocks: u32,
+    /// Number of blocks integrated from remote updates.
+    pub integrated_blocks: u32,
+    /// Number of blocks deleted by local operations or remote updates, including the contents of
+    /// deleted nested types.
+    pub deleted_blocks: u32,
+    /// Number of countable elements (ie. characters or array elements) inserted locally or
+    /// integrated from remote updates.
+    pub added_len: u32,
+    /// Number of countable elements deleted.
+    pub removed_len: u32,
+    /// Length in bytes of an update encoded with [Transaction::encode_update_v1].
+    pub update_len: usize,
+}
+
+/// Blocks of remote updates created by another replica using the same client id as a current
+/// document, found by [Transaction::client_id_conflict].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ClientIdConflict {
+    /// True if remote blocks extend beyond a local state of a current client id.
+    reused: bool,
+    /// Clock of the first remote block, which content differs from a local block with the same id.
+    duplicate_clock: Option<u32>,
+}
+
+/// Result of [Transaction::revert] and [Transaction::revert_ranges].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RevertReport {
+    /// Number of countable elements (ie. characters or array elements) deleted again.
+    pub deleted: u32,
+    /// Number of countable elements, which content has been restored.
+    pub restored: u32,
+    /// Blocks which could not be reverted.
+    pub conflicts: Vec<RevertConflict>,
+}
+
+/// Describes a range of blocks, which could not be reverted by [Transaction::revert_ranges],
+/// together with a reason. Each variant contains an ID of the first block and a length of a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevertConflict {
+    /// Inserted content has been already deleted.
+    AlreadyDeleted(ID, u32),
+    /// Deleted content is not deleted in a current document.
+    NotDeleted(ID, u32),
+    /// Blocks are not present in a current document.
+    Missing(ID, u32),
+    /// Deleted content has been garbage collected and can not be restored.
+    GarbageCollected(ID, u32),
+    /// Deleted content can not be restored, because its parent type has been deleted.
+    ParentDeleted(ID, u32),
+    /// Deleted content can not be restored, because it's a nested shared type.
+    UnsupportedContent(ID, u32),
+}
+
+/// Returns a copy of a deleted content, which can be inserted again. Nested shared types and
+/// garbage collected content can not be restored.
+fn restored_content(content: &ItemContent) -> Option<ItemContent> {
+    match content {
+        ItemContent::Any(v) => Some(ItemContent::Any(v.clone())),
+        ItemContent::Binary(v) => Some(ItemContent::Binary(v.clone())),
+        ItemContent::Doc(guid, opts) => Some(ItemContent::Doc(guid.clone(), opts.clone())),
+        ItemContent::JSON(v) => Some(ItemContent::JSON(v.clone())),
+        ItemContent::Embed(v) => Some(ItemContent::Embed(v.clone())),
+        ItemContent::Format(k, v) => Some(ItemContent::Format(k.clone(), v.clone())),
+        ItemContent::String(v) => Some(ItemContent::String(v.clone())),
+        ItemContent::Deleted(_) | ItemContent::Type(_) => None,
+    }
+}
+
+struct RestoredContent(ItemContent);
+
+impl Prelim for RestoredContent {
+    fn into_content(self, _txn: &mut Transaction, _ptr: TypePtr) -> (ItemContent, Option<Self>) {
+        (self.0, None)
+    }
+
+    fn integrate(self, _txn: &mut Transaction, _inner_ref: BranchRef) {}
+}
@@ -1,15 +1,21 @@
-use crate::block::{BlockPtr, ItemContent, ItemPosition, Prelim};
-use crate::types::{Branch, BranchRef, TypePtr, Value, TYPE_REFS_ARRAY};
-use crate::Transaction;
-use lib0::any::Any;
+use crate::block::{BlockPtr, ItemContent, Prelim};
+use crate::event::{Observer, Subscription};
+use crate::types::{
+    event_changes, missing_value, Branch, BranchRef, Change, Event, Observers, Path, SearchMarker,
+    TypePtr, Value, TYPE_REFS_ARRAY,
+};
+use crate::{Error, RelativePosition, Transaction, ID};
+use lib0::any::{Any, TypeMismatch};
 use std::collections::VecDeque;
-use std::error::Error;
+use std::convert::TryFrom;
 use std::fmt::Formatter;
 
 /// A collection used to store data in an indexed sequence structure.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Array(BranchRef);
 
+impl_branch_identity!(Array);
+
 impl Array {
     /// Returns a number of elements stored in current array.
     pub fn len(&self) -> u32 {
@@ -17,33 +23,46 @@ impl Array {
         inner.len()
     }
 
+    /// Returns a number of bytes of content stored in current array, including its nested
+    /// shared types. See: [Branch::content_size].
+    pub fn content_size(&self, txn: &Transaction) -> usize {
+        self.0.borrow().content_size(txn)
+    }
+
+    /// Returns a unique identifier of an element stored at a given `index` of current array or
+    /// `None` if `index` was outside of array's bounds. It can be resolved back using
+    /// [Transaction::get_item].
+    pub fn id_at(&self, txn: &Transaction, index: u32) -> Option<ID> {
+        self.0.borrow().id_at(txn, index)
+    }
+
+    /// Returns a [RelativePosition] pointing to a given `index` of current array, which stays
+    /// attached to the same place in array while concurrent changes are made.
+    /// See: [Text::relative_position](crate::Text::relative_position).
+    pub fn relative_position(&self, txn: &Transaction, index: u32, assoc: i32) -> RelativePosition {
+        RelativePosition::from_type_index(txn, &self.0, index, assoc)
+    }
+
     /// Inserts a `value` at the given `index`. Inserting at index `0` is equivalent to prepending
     /// current array with given `value`, while inserting at array length is equivalent to appending
     /// that value at the end of it.
     ///
     /// Using `index` value that's higher than current array length results in panic.
     pub fn insert<V: Prelim>(&self, txn: &mut Transaction, index: u32, value: V) {
-        let (start, parent) = {
-            let parent = self.0.borrow();
-            if index <= parent.len() {
-                (parent.start, parent.ptr.clone())
-            } else {
-                panic!("Cannot insert item at index over the length of an array")
-            }
-        };
-        let (left, right) = if index == 0 {
-            (None, None)
-        } else {
-            Branch::index_to_ptr(txn, start, index)
-        };
-        let pos = ItemPosition {
-            parent,
-            left,
-            right,
-            index: 0,
-        };
+        self.0.insert_at(txn, index, value);
+    }
 
-        txn.create_item(&pos, value, None);
+    /// Works like [Array::insert], but if inserting a `value` could make a document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size), an [Error::QuotaExceeded]
+    /// is returned and array is left unchanged.
+    pub fn try_insert<V: Prelim>(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        value: V,
+    ) -> Result<(), Error> {
+        self.0.try_insert_at_from(txn, index, value, None)?;
+        Ok(())
     }
 
     /// Inserts multiple `values` at the given `index`. Inserting at index `0` is equivalent to
@@ -75,10 +94,25 @@ impl Array {
         self.remove_range(txn, index, 1)
     }
 
+    /// Removes a single element at provided `index` and returns it, or returns `None` if `index`
+    /// was outside of the bounds of a current array.
+    ///
+    /// Since removed shared types can no longer be modified, they are returned as their JSON
+    /// projection (see: [Value::to_json]) rather than as shared type handles.
+    pub fn remove_at(&self, txn: &mut Transaction, index: u32) -> Option<Value> {
+        let value = match self.get(txn, index)? {
+            value @ Value::Any(_) | value @ Value::YDoc(_) => value,
+            shared => Value::Any(shared.to_json(txn)),
+        };
+        self.remove(txn, index);
+        Some(value)
+    }
+
     /// Removes a range of elements from current array, starting at given `index` up until
     /// a particular number described by `len` has been deleted. This method panics in case when
     /// not all expected elements were removed (due to insufficient number of elements in an array)
-    /// or `index` is outside of the bounds of an array.
+    /// or `index` is outside of the bounds of an array. It also panics without modifying an array
+    /// if a removed range doesn't fit into `u32` bounds.
     pub fn remove_range(&self, txn: &mut Transaction, index: u32, len: u32) {
         let removed = self.0.remove_at(txn, index, len);
         if removed != len {
@@ -86,12 +120,142 @@ impl Array {
         }
     }
 
+    /// Removes all elements of a current array. Clearing an empty array has no effect.
+    pub fn clear(&self, txn: &mut Transaction) {
+        self.0.clear(txn)
+    }
+
+    /// Replaces all elements of a current array with given `values`. Both removal and insertion
+    /// happen in scope of the same transaction, so that observers receive them as a single delta.
+    ///
+    /// When the same array is replaced concurrently by different peers, all of their values will
+    /// be present after synchronization, just like with any other concurrent insertions.
+    pub fn replace_all<T, V>(&self, txn: &mut Transaction, values: T)
+    where
+        T: IntoIterator<Item = V>,
+        V: Prelim,
+    {
+        self.clear(txn);
+        for value in values {
+            self.push_back(txn, value);
+        }
+    }
+
+    /// Applies a given `delta` (ie. one received from an array event of another replica or parsed
+    /// with [Change::from_json]) at the beginning of a current array. Inserted shared types are
+    /// inserted as their JSON representation.
+    ///
+    /// This method panics if `delta` retains or removes more elements than an array contains.
+    pub fn apply_delta(&self, txn: &mut Transaction, delta: &[Change]) {
+        let mut index = 0;
+        for change in delta {
+            match change {
+                Change::Added(values) => {
+                    let values: Vec<_> = values.iter().map(|v| v.clone().to_json(txn)).collect();
+                    let len = values.len() as u32;
+                    self.insert_range(txn, index, values);
+                    index += len;
+                }
+                Change::Removed(len) => self.remove_range(txn, index, *len),
+                Change::Retain(len) => index += len,
+            }
+        }
+    }
+
     /// Retrieves a value stored at a given `index`. Returns `None` when provided index was out
     /// of the range of a current array.
     pub fn get(&self, txn: &Transaction, index: u32) -> Option<Value> {
         let inner = self.0.borrow();
         let (content, idx) = inner.get_at(txn, index)?;
-        Some(content.get_content(txn).remove(idx))
+        match content {
+            // avoid materializing all values of a block in order to read just one of them
+            ItemContent::Any(values) => values.get(idx).cloned().map(Value::Any),
+            _ => Some(content.get_content(txn).remove(idx)),
+        }
+    }
+
+    /// Returns an index of the first element of a current array for which a given predicate
+    /// returns `false`, assuming that the array is partitioned according to it: elements for
+    /// which it returns `true` are placed before the ones for which it returns `false` (ie. array
+    /// is sorted and predicate compares elements with a searched value). Works like
+    /// [slice::partition_point], reading O(log n) elements.
+    pub fn partition_point<P>(&self, txn: &Transaction, mut pred: P) -> u32
+    where
+        P: FnMut(&Value) -> bool,
+    {
+        let mut lo = 0;
+        let mut hi = self.len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.get(txn, mid) {
+                Some(value) if pred(&value) => lo = mid + 1,
+                _ => hi = mid,
+            }
+        }
+        lo
+    }
+
+    /// Inserts a given `value` into a current array sorted by keys computed with a `key`
+    /// function, after all elements with keys lower or equal to the key of inserted value.
+    /// Returns an index at which the value has been inserted.
+    ///
+    /// Keeping an array sorted is a local guarantee only: values inserted concurrently by
+    /// different peers at the same position end up ordered by their client ids rather than
+    /// their keys once merged. Use [Array::is_sorted_by_key] to detect such cases.
+    pub fn insert_sorted_by_key<V, K, F>(&self, txn: &mut Transaction, value: V, mut key: F) -> u32
+    where
+        V: Into<Any>,
+        K: PartialOrd,
+        F: FnMut(&Value) -> K,
+    {
+        let value: Any = value.into();
+        let k = key(&Value::Any(value.clone()));
+        let index = self.partition_point(txn, |v| key(v) <= k);
+        self.insert(txn, index, value);
+        index
+    }
+
+    /// Checks if elements of a current array are sorted by keys computed with a `key` function.
+    pub fn is_sorted_by_key<K, F>(&self, txn: &Transaction, mut key: F) -> bool
+    where
+        K: PartialOrd,
+        F: FnMut(&Value) -> K,
+    {
+        let mut prev: Option<K> = None;
+        for value in self.iter(txn) {
+            let k = key(&value);
+            if let Some(prev) = prev.as_ref() {
+                if prev > &k {
+                    return false;
+                }
+            }
+            prev = Some(k);
+        }
+        true
+    }
+
+    /// Retrieves a value stored at a given `index`, converted into a requested type `T`. Returns
+    /// an error when provided index was out of the range of a current array or when the value
+    /// could not be converted into `T`.
+    pub fn get_as<T>(&self, txn: &Transaction, index: u32) -> Result<T, TypeMismatch>
+    where
+        T: TryFrom<Value, Error = TypeMismatch>,
+    {
+        match self.get(txn, index) {
+            Some(value) => T::try_from(value),
+            None => Err(missing_value::<T>()),
+        }
+    }
+
+    /// Returns a cursor placed at the beginning of a current array. See: [ArrayCursor].
+    pub fn cursor(&self, txn: &Transaction) -> ArrayCursor {
+        let mut marker = None;
+        self.0.borrow().find_marker_from(txn, 0, &mut marker);
+        ArrayCursor {
+            array: self.clone(),
+            index: 0,
+            marker,
+        }
     }
 
     /// Returns an iterator, that can be used to lazely traverse over all values stored in a current
@@ -105,6 +269,218 @@ impl Array {
         let res = self.iter(txn).map(|v| v.to_json(txn)).collect();
         Any::Array(res)
     }
+
+    /// Subscribes a given callback to be called whenever elements of a current array are
+    /// inserted or removed. Callback is called once per transaction, with an event describing
+    /// the changes as a delta. Returns a subscription, which will unsubscribe callback when
+    /// dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use yrs::{Change, Doc};
+    ///
+    /// let doc = Doc::new();
+    /// let array = doc.transact().get_array("array");
+    /// array.insert_range(&mut doc.transact(), 0, vec![1, 2, 3]);
+    ///
+    /// let delta = Rc::new(RefCell::new(Vec::new()));
+    /// let d = delta.clone();
+    /// let _sub = array.observe(move |_, e| *d.borrow_mut() = e.delta.clone());
+    ///
+    /// array.remove(&mut doc.transact(), 1);
+    /// assert_eq!(*delta.borrow(), vec![Change::Retain(1), Change::Removed(1)]);
+    /// ```
+    pub fn observe<F>(&self, f: F) -> Subscription<ArrayEvent>
+    where
+        F: Fn(&Transaction, &ArrayEvent) -> () + 'static,
+    {
+        let mut inner = self.0.borrow_mut();
+        let observers = inner
+            .observers
+            .get_or_insert_with(|| Observers::Array(Observer::new()));
+        match observers {
+            Observers::Array(observer) => observer.subscribe(f),
+            _ => panic!("Defect: array has observers of unexpected type"),
+        }
+    }
+
+    /// Subscribes a given callback to be called whenever a current array or any of its nested
+    /// shared types is changed. Callback is called once per transaction, with a list of events
+    /// produced by all changed types, ordered by their depth. Path of each event leads from
+    /// a current array to the changed type.
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.borrow_mut().observe_deep(f)
+    }
+}
+
+/// A cursor over an [Array], which keeps track of a position within it, so that a sequence of
+/// reads and edits made one after another doesn't need to look that position up from
+/// the beginning of an array every time. A cursor remembers a block it has visited last and as
+/// long as no elements have been inserted or removed since then by other means than the cursor
+/// itself, following reads and edits continue from there. Otherwise it falls back to search
+/// markers shared with indexed array operations.
+///
+/// A cursor doesn't borrow a transaction, which is passed to each operation instead, so it can be
+/// used across many transactions. Its position is an index, which is not adjusted for changes
+/// made by other means than a cursor itself, including remote updates.
+#[derive(Debug, Clone)]
+pub struct ArrayCursor {
+    array: Array,
+    index: u32,
+    /// Marker found by the last operation, together with a generation of branch search markers
+    /// at the moment it was found.
+    marker: Option<(u64, SearchMarker)>,
+}
+
+impl ArrayCursor {
+    /// Returns a current position of a cursor.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Moves a cursor to a given `index`. This method panics if `index` is greater than the
+    /// length of an array.
+    pub fn seek(&mut self, index: u32) {
+        let len = self.array.len();
+        if index > len {
+            panic!(
+                "Cannot seek to index {} of an array of length {}",
+                index, len
+            );
+        }
+        self.index = index;
+    }
+
+    /// Moves a cursor forward by up to `n` elements, stopping at the end of an array. Returns
+    /// a number of elements it has been moved by.
+    pub fn forward(&mut self, n: u32) -> u32 {
+        let n = n.min(self.remaining());
+        self.index += n;
+        n
+    }
+
+    /// Reads up to `n` values following a cursor position and moves a cursor past them.
+    pub fn read(&mut self, txn: &Transaction, n: u32) -> Vec<Value> {
+        let mut values = Vec::new();
+        let inner = self.array.0.borrow();
+        let marker = match inner.find_marker_from(txn, self.index, &mut self.marker) {
+            Some(marker) => marker,
+            None => return values,
+        };
+        let mut offset = (self.index - marker.index) as usize;
+        let mut ptr = Some(marker.ptr);
+        while values.len() < n as usize {
+            let item = match ptr.and_then(|p| txn.store.blocks.get_item(&p)) {
+                Some(item) => item,
+                None => break,
+            };
+            if !item.is_deleted() && item.is_countable() {
+                let remaining = n as usize - values.len();
+                if let ItemContent::Any(any) = &item.content {
+                    let end = any.len().min(offset + remaining);
+                    values.extend(any[offset..end].iter().map(|v| Value::Any(v.clone())));
+                } else {
+                    let content = item.content.get_content(txn);
+                    values.extend(content.into_iter().skip(offset).take(remaining));
+                }
+                offset = 0;
+            }
+            ptr = item.right;
+        }
+        self.index += values.len() as u32;
+        values
+    }
+
+    /// Inserts given `values` at a cursor position and moves a cursor past them.
+    pub fn insert<T, V>(&mut self, txn: &mut Transaction, values: T)
+    where
+        T: IntoIterator<Item = V>,
+        V: Into<Any>,
+    {
+        let values: Vec<Any> = values.into_iter().map(Into::into).collect();
+        if !values.is_empty() {
+            let len = values.len() as u32;
+            self.array.0.insert_at_from(
+                txn,
+                self.index,
+                PrelimRange(values),
+                Some(&mut self.marker),
+            );
+            self.index += len;
+        }
+    }
+
+    /// Removes up to `n` elements following a cursor position. Cursor position doesn't change.
+    /// Returns a number of removed elements.
+    pub fn delete(&mut self, txn: &mut Transaction, n: u32) -> u32 {
+        let n = n.min(self.remaining());
+        if n > 0 {
+            self.array
+                .0
+                .remove_at_from(txn, self.index, n, Some(&mut self.marker));
+        }
+        n
+    }
+
+    /// Returns a number of elements following a cursor position.
+    fn remaining(&self) -> u32 {
+        self.array.len().saturating_sub(self.index)
+    }
+}
+
+/// An event produced by [Array] when its elements have been inserted or removed in scope of
+/// a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayEvent {
+    /// Array that has been changed.
+    pub target: Array,
+    /// Path from an observed type to the changed array. It's empty unless event has been passed
+    /// to a deep observer of one of the changed array's parents.
+    pub path: Path,
+    /// Changes made to a changed array. Applied in order on the array state from before the
+    /// transaction, they produce its current state.
+    pub delta: Vec<Change>,
+    /// Length of a changed array from before the transaction.
+    pub before_len: u32,
+    /// Length of a changed array at the moment of transaction commit.
+    pub after_len: u32,
+}
+
+impl ArrayEvent {
+    pub(crate) fn new(txn: &Transaction, branch: BranchRef) -> Self {
+        let (delta, after_len) = {
+            let inner = branch.borrow();
+            (event_changes(txn, &inner), inner.len())
+        };
+        let before_len = delta.iter().fold(after_len, |len, change| match change {
+            Change::Added(values) => len - values.len() as u32,
+            Change::Removed(removed) => len + removed,
+            Change::Retain(_) => len,
+        });
+        ArrayEvent {
+            target: Array::from(branch),
+            path: Path::new(),
+            delta,
+            before_len,
+            after_len,
+        }
+    }
+
+    /// Returns an [ArrayEvent::delta] converted into a JSON-like array of the same shape as the one
+    /// emitted by Yjs array events. See: [Change::to_json].
+    pub fn delta_json(&self, txn: &Transaction) -> Any {
+        Any::Array(self.delta.iter().map(|c| c.to_json(txn)).collect())
+    }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        self.target.0.borrow().ptr.clone()
+    }
 }
 
 pub struct Iter<'b, 'txn> {
@@ -207,12 +583,182 @@ where
 
 #[cfg(test)]
 mod test {
-    use crate::test_utils::exchange_updates;
+    use crate::test_utils::{check_search_markers, exchange_updates};
     use crate::types::map::PrelimMap;
-    use crate::types::Value;
-    use crate::Doc;
-    use lib0::any::Any;
+    use crate::types::{Change, Value};
+    use crate::{Array, ArrayEvent, Doc, PrelimArray, PrelimValue, Subscription, Transaction};
+    use lib0::any::{Any, TypeMismatch};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::RefCell;
     use std::collections::HashMap;
+    use std::rc::Rc;
+
+    fn number(value: &Value) -> f64 {
+        match value {
+            Value::Any(Any::Number(n)) => *n,
+            other => panic!("expected number, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn insert_sorted() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let a = txn.get_array("array");
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut expected = Vec::new();
+        for _ in 0..200 {
+            let value = rng.gen_range(0, 50) as f64;
+            let index = a.insert_sorted_by_key(&mut txn, value, number);
+            // equal values are inserted after the existing ones
+            let pos = expected.partition_point(|v| *v <= value);
+            assert_eq!(index, pos as u32);
+            expected.insert(pos, value);
+        }
+        let values: Vec<_> = a.iter(&txn).map(|v| number(&v)).collect();
+        assert_eq!(values, expected);
+        assert!(a.is_sorted_by_key(&txn, number));
+        let pos = expected.partition_point(|v| *v < 25.0);
+        assert_eq!(a.partition_point(&txn, |v| number(v) < 25.0), pos as u32);
+        assert_eq!(a.partition_point(&txn, |_| true), a.len());
+    }
+
+    #[test]
+    fn insert_sorted_concurrently() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut txn = d1.transact();
+            txn.get_array("array")
+                .insert_range(&mut txn, 0, vec![1.0, 5.0]);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        // both values are inserted between the same neighbours
+        let mut t1 = d1.transact();
+        let a1 = t1.get_array("array");
+        assert_eq!(a1.insert_sorted_by_key(&mut t1, 4.0, number), 1);
+        drop(t1);
+        let mut t2 = d2.transact();
+        let a2 = t2.get_array("array");
+        assert_eq!(a2.insert_sorted_by_key(&mut t2, 3.0, number), 1);
+        drop(t2);
+        exchange_updates(&[&d1, &d2]);
+
+        for (doc, a) in [(&d1, &a1), (&d2, &a2)] {
+            let txn = doc.transact();
+            let values: Vec<_> = a.iter(&txn).map(|v| number(&v)).collect();
+            assert_eq!(values, vec![1.0, 4.0, 3.0, 5.0]);
+            assert!(!a.is_sorted_by_key(&txn, number));
+        }
+    }
+
+    #[test]
+    fn cursor_edit_script() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(1);
+        let a1 = d1.transact().get_array("array");
+        let a2 = d2.transact().get_array("array");
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut cursor = a1.cursor(&d1.transact());
+        let mut index = 0;
+        for round in 0..20 {
+            let mut t1 = d1.transact();
+            let mut t2 = d2.transact();
+            for i in 0..100 {
+                let remaining = a2.len() - index;
+                match rng.gen_range(0, 10) {
+                    0..=3 => {
+                        let values: Vec<_> = (0..rng.gen_range(1, 5))
+                            .map(|j| (round * 1000 + i * 10 + j) as f64)
+                            .collect();
+                        let len = values.len() as u32;
+                        cursor.insert(&mut t1, values.clone());
+                        a2.insert_range(&mut t2, index, values);
+                        // cursor keeps its position cached across its own edits
+                        assert_eq!(cursor.marker.is_some(), index > 0);
+                        index += len;
+                    }
+                    4..=5 => {
+                        let n = rng.gen_range(0, 4);
+                        let removed = n.min(remaining);
+                        assert_eq!(cursor.delete(&mut t1, n), removed);
+                        if removed > 0 {
+                            a2.remove_range(&mut t2, index, removed);
+                        }
+                    }
+                    6..=7 => {
+                        let n = rng.gen_range(0, 6).min(remaining);
+                        let expected: Vec<_> = (index..index + n)
+                            .map(|i| a2.get(&t2, i).unwrap())
+                            .collect();
+                        assert_eq!(cursor.read(&t1, n), expected);
+                        index += n;
+                    }
+                    8 => {
+                        let n = rng.gen_range(0, 6);
+                        assert_eq!(cursor.forward(n), n.min(remaining));
+                        index += n.min(remaining);
+                    }
+                    _ => {
+                        index = rng.gen_range(0, a2.len() + 1);
+                        cursor.seek(index);
+                    }
+                }
+                assert_eq!(cursor.index(), index);
+            }
+        }
+        assert_eq!(a1.to_json(&d1.transact()), a2.to_json(&d2.transact()));
+    }
+
+    #[test]
+    fn get_as() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let a = txn.get_array("array");
+
+        a.push_back(&mut txn, "a");
+        a.push_back(&mut txn, 1.5);
+        a.push_back(&mut txn, vec![1u8, 2, 3]);
+
+        assert_eq!(a.get_as::<String>(&txn, 0), Ok("a".to_owned()));
+        assert_eq!(a.get_as::<f64>(&txn, 1), Ok(1.5));
+        assert_eq!(
+            a.get_as::<i64>(&txn, 1),
+            Err(TypeMismatch::new("i64", "number with fractional part"))
+        );
+        assert_eq!(a.get_as::<Vec<u8>>(&txn, 2), Ok(vec![1, 2, 3]));
+        assert_eq!(
+            a.get_as::<String>(&txn, 3),
+            Err(TypeMismatch::new("string", "undefined"))
+        );
+    }
+
+    #[test]
+    fn push_back_native_types() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let a = txn.get_array("array");
+
+        a.push_back(&mut txn, 1u16);
+        a.push_back(&mut txn, -1i64);
+        a.push_back(&mut txn, "a".to_owned());
+        a.push_back(&mut txn, Some(vec![1.5f64]));
+        a.push_back(&mut txn, vec![1u8, 2]);
+
+        let actual: Vec<_> = a.iter(&txn).collect();
+        assert_eq!(
+            actual,
+            vec![
+                Value::Any(Any::Number(1.0)),
+                Value::Any(Any::BigInt(-1)),
+                Value::Any(Any::String("a".to_owned())),
+                Value::Any(Any::Array(vec![Any::Number(1.5)])),
+                Value::Any(Any::Buffer(vec![1, 2].into_boxed_slice())),
+            ]
+        );
+    }
 
     #[test]
     fn push_back() {
@@ -256,6 +802,42 @@ mod test {
         assert_eq!(actual, vec!["a".into(), "b".into(), "c".into()]);
     }
 
+    #[test]
+    fn get_skips_deleted_elements() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let a = txn.get_array("array");
+
+        a.insert_range(&mut txn, 0, ["a", "b", "c", "d"]);
+        a.remove_range(&mut txn, 1, 2);
+        a.0.borrow().search_markers.clear();
+
+        assert_eq!(a.get(&txn, 0), Some("a".into()));
+        assert_eq!(a.get(&txn, 1), Some("d".into()));
+        assert_eq!(a.get(&txn, 2), None);
+    }
+
+    #[test]
+    fn search_marker_of_removed_element_is_moved_left() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let a = txn.get_array("array");
+
+        for i in 0..200 {
+            a.insert(&mut txn, i, i);
+        }
+        a.0.borrow().search_markers.clear();
+        assert_eq!(a.get(&txn, 5), Some(5.into()));
+        assert_eq!(a.0.borrow().search_markers.indexes(), vec![5]);
+
+        // lookups close to the beginning are not cached, so only a moved marker is left
+        a.remove_range(&mut txn, 2, 5);
+        assert_eq!(a.0.borrow().search_markers.indexes(), vec![1]);
+        assert_eq!(a.get(&txn, 1), Some(1.into()));
+        assert_eq!(a.get(&txn, 2), Some(7.into()));
+        assert_eq!(a.get(&txn, 100), Some(105.into()));
+    }
+
     #[test]
     fn basic() {
         let d1 = Doc::with_client_id(1);
@@ -268,7 +850,7 @@ mod test {
         let update = d1.encode_state_as_update_v1(&t1);
 
         let mut t2 = d2.transact();
-        d2.apply_update_v1(&mut t2, update.as_slice());
+        d2.apply_update_v1(&mut t2, update.as_slice()).unwrap();
         let a2 = t2.get_array("array");
         let actual: Vec<_> = a2.iter(&t2).collect();
 
@@ -556,4 +1138,454 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn search_markers_match_unoptimized_lookups() {
+        check_search_markers("array", |rng, step, txns| {
+            let arrays: Vec<_> = txns.iter_mut().map(|t| t.get_array("array")).collect();
+            let len = arrays[0].len();
+            match rng.gen_range(0, 9) {
+                0..=4 => {
+                    let index = rng.gen_range(0, len + 1);
+                    let values: Vec<_> = (0..rng.gen_range(1, 4))
+                        .map(|i| step as i32 * 10 + i)
+                        .collect();
+                    for (a, t) in arrays.iter().zip(txns.iter_mut()) {
+                        a.insert_range(t, index, values.clone());
+                    }
+                }
+                5..=6 if len > 0 => {
+                    let index = rng.gen_range(0, len);
+                    let count = rng.gen_range(1, (len - index).min(3) + 1);
+                    for (a, t) in arrays.iter().zip(txns.iter_mut()) {
+                        a.remove_range(t, index, count);
+                    }
+                }
+                _ if len > 0 => {
+                    let index = rng.gen_range(0, len);
+                    let expected = (
+                        arrays[0].get(txns[0], index),
+                        arrays[0].id_at(txns[0], index),
+                    );
+                    for (a, t) in arrays.iter().zip(txns.iter()) {
+                        assert_eq!((a.get(t, index), a.id_at(t, index)), expected);
+                    }
+                }
+                _ => {}
+            }
+            let expected = arrays[0].to_json(txns[0]);
+            for (a, t) in arrays.iter().zip(txns.iter()) {
+                assert_eq!(a.to_json(t), expected);
+            }
+        });
+    }
+
+    /// Applies a `delta` on a plain list of values.
+    fn apply_delta(txn: &Transaction, list: &mut Vec<Any>, delta: &[Change]) {
+        let mut i = 0;
+        for change in delta {
+            match change {
+                Change::Retain(len) => i += *len as usize,
+                Change::Removed(len) => {
+                    list.drain(i..(i + *len as usize));
+                }
+                Change::Added(values) => {
+                    for value in values {
+                        list.insert(i, value.clone().to_json(txn));
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Subscribes to changes of a given `array`, applying delta of each event on a plain list of
+    /// values.
+    fn mirror(array: &Array, list: Vec<Any>) -> (Rc<RefCell<Vec<Any>>>, Subscription<ArrayEvent>) {
+        let list = Rc::new(RefCell::new(list));
+        let l = list.clone();
+        let sub = array.observe(move |txn, e| apply_delta(txn, &mut l.borrow_mut(), &e.delta));
+        (list, sub)
+    }
+
+    fn to_vec(txn: &Transaction, array: &Array) -> Vec<Any> {
+        match array.to_json(txn) {
+            Any::Array(values) => values,
+            other => panic!("expected an array, got {}", other),
+        }
+    }
+
+    #[test]
+    fn observe_delta_disjoint_edits() {
+        let doc = Doc::with_client_id(1);
+        let array = doc.transact().get_array("array");
+        array.insert_range(&mut doc.transact(), 0, vec![1, 2, 3, 4, 5, 6]);
+        let (list, _sub) = mirror(&array, to_vec(&doc.transact(), &array));
+
+        {
+            let mut txn = doc.transact();
+            array.remove(&mut txn, 0);
+            array.insert(&mut txn, 2, "a");
+            array.remove_range(&mut txn, 4, 2);
+            array.push_back(&mut txn, "b");
+        }
+
+        let txn = doc.transact();
+        assert_eq!(*list.borrow(), to_vec(&txn, &array));
+    }
+
+    #[test]
+    fn observe_delta_insert_and_delete_at_same_index() {
+        let doc = Doc::with_client_id(1);
+        let array = doc.transact().get_array("array");
+        array.insert_range(&mut doc.transact(), 0, vec![1, 2, 3]);
+        let delta = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let delta = delta.clone();
+            array.observe(move |_, e| *delta.borrow_mut() = e.delta.clone())
+        };
+
+        {
+            let mut txn = doc.transact();
+            array.remove(&mut txn, 1);
+            array.insert(&mut txn, 1, "a");
+            // inserted and removed within the same transaction
+            array.insert(&mut txn, 0, "b");
+            array.remove(&mut txn, 0);
+        }
+
+        assert_eq!(
+            *delta.borrow(),
+            vec![
+                Change::Retain(1),
+                Change::Added(vec![Value::Any(Any::String("a".into()))]),
+                Change::Removed(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_delta_remote_changes() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut t1 = d1.transact();
+            t1.get_array("array")
+                .insert_range(&mut t1, 0, vec![1, 2, 3, 4]);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let a2 = d2.transact().get_array("array");
+        let (list, _sub) = mirror(&a2, to_vec(&d2.transact(), &a2));
+        {
+            let mut t1 = d1.transact();
+            let a1 = t1.get_array("array");
+            a1.remove_range(&mut t1, 1, 2);
+            a1.insert(&mut t1, 0, true);
+            a1.push_back(&mut t1, PrelimArray::from(vec![5]));
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        let t2 = d2.transact();
+        assert_eq!(*list.borrow(), to_vec(&t2, &a2));
+        assert_eq!(
+            *list.borrow(),
+            vec![
+                Any::Bool(true),
+                Any::Number(1.0),
+                Any::Number(4.0),
+                Any::Array(vec![Any::Number(5.0)])
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_delta_new_array() {
+        let doc = Doc::with_client_id(1);
+        let array = doc.transact().get_array("array");
+        let (list, _sub) = mirror(&array, Vec::new());
+
+        {
+            let mut txn = doc.transact();
+            array.insert_range(&mut txn, 0, vec![1, 2]);
+            array.insert(&mut txn, 1, PrelimArray::from(vec!["a"]));
+            let nested = match array.get(&txn, 1) {
+                Some(Value::YArray(nested)) => nested,
+                other => panic!("expected an array, got {:?}", other),
+            };
+            // changes of a nested array created in the same transaction are part of an insert
+            nested.push_back(&mut txn, "b");
+        }
+
+        let txn = doc.transact();
+        assert_eq!(*list.borrow(), to_vec(&txn, &array));
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn insert_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let array = d1.transact().get_array("array");
+        let mut txn = d2.transact();
+        txn.get_array("array");
+        array.push_back(&mut txn, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn remove_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let array = d1.transact().get_array("array");
+        array.push_back(&mut d1.transact(), 1);
+        array.remove(&mut d2.transact(), 0);
+    }
+
+    #[test]
+    fn insert_mixed_prelim_value() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let array = d1.transact().get_array("array");
+        {
+            let mut txn = d1.transact();
+            let mut entries = HashMap::new();
+            let items = PrelimValue::list(vec![PrelimValue::Text("hi".to_string())]);
+            entries.insert("items".to_string(), items);
+            entries.insert("count".to_string(), PrelimValue::from(1));
+            array.push_back(&mut txn, PrelimValue::object(entries));
+
+            let mut plain = HashMap::new();
+            plain.insert(
+                "a".to_string(),
+                PrelimValue::list(vec![PrelimValue::from(true)]),
+            );
+            array.push_back(&mut txn, PrelimValue::object(plain));
+        }
+
+        {
+            let mut txn = d1.transact();
+            let map = match array.get(&txn, 0) {
+                Some(Value::YMap(map)) => map,
+                other => panic!("expected map, found {:?}", other),
+            };
+            let items = match map.get(&txn, "items") {
+                Some(Value::YArray(items)) => items,
+                other => panic!("expected array, found {:?}", other),
+            };
+            match items.get(&txn, 0) {
+                Some(Value::YText(text)) => text.push(&mut txn, " there"),
+                other => panic!("expected text, found {:?}", other),
+            }
+            assert!(matches!(array.get(&txn, 1), Some(Value::Any(Any::Map(_)))));
+        }
+
+        exchange_updates(&[&d1, &d2]);
+        let t1 = d1.transact();
+        let mut t2 = d2.transact();
+        let expected = {
+            let mut object = HashMap::new();
+            object.insert(
+                "items".to_string(),
+                Any::Array(vec![Any::String("hi there".to_string())]),
+            );
+            object.insert("count".to_string(), Any::Number(1.0));
+            let mut plain = HashMap::new();
+            plain.insert("a".to_string(), Any::Array(vec![Any::Bool(true)]));
+            Any::Array(vec![Any::Map(object), Any::Map(plain)])
+        };
+        assert_eq!(array.to_json(&t1), expected);
+        assert_eq!(t2.get_array("array").to_json(&t2), expected);
+    }
+
+    #[test]
+    fn insert_nested_prelim_tree() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = |s: &str| PrelimValue::Text(s.to_string());
+        let object = |entries: Vec<(&str, PrelimValue)>| {
+            let entries = entries.into_iter().map(|(k, v)| (k.to_string(), v));
+            PrelimValue::object(entries.collect())
+        };
+        {
+            let mut txn = d1.transact();
+            let tree = PrelimValue::list(vec![
+                text("a"),
+                object(vec![
+                    ("k", PrelimValue::from(1)),
+                    (
+                        "nested",
+                        PrelimValue::list(vec![text("b"), object(vec![("deep", text("c"))])]),
+                    ),
+                ]),
+                PrelimValue::from(2),
+            ]);
+            txn.get_array("array").push_back(&mut txn, tree);
+        }
+
+        exchange_updates(&[&d1, &d2]);
+        let expected =
+            Any::from_json_str(r#"[["a",{"k":1.0,"nested":["b",{"deep":"c"}]},2.0]]"#).unwrap();
+        for d in [&d1, &d2] {
+            let mut txn = d.transact();
+            assert_eq!(txn.get_array("array").to_json(&txn), expected);
+        }
+    }
+
+    #[test]
+    fn remove_at() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let array = txn.get_array("array");
+        array.insert_range(&mut txn, 0, vec![1, 2, 3]);
+        let mut map = HashMap::new();
+        map.insert("key".to_string(), 4);
+        array.push_back(&mut txn, PrelimMap::from(map));
+
+        let expected = {
+            let mut map = HashMap::new();
+            map.insert("key".to_string(), Any::Number(4.0));
+            Value::Any(Any::Map(map))
+        };
+        assert_eq!(array.remove_at(&mut txn, 3), Some(expected));
+        assert_eq!(array.remove_at(&mut txn, 0), Some(Value::from(1)));
+        assert_eq!(array.remove_at(&mut txn, 1), Some(Value::from(3)));
+        assert_eq!(array.remove_at(&mut txn, 1), None);
+        assert_eq!(array.to_json(&txn), Any::Array(vec![Any::Number(2.0)]));
+        assert_eq!(array.remove_at(&mut txn, 0), Some(Value::from(2)));
+        assert_eq!(array.remove_at(&mut txn, 0), None);
+    }
+
+    #[test]
+    fn clear_and_replace_all() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let array = d1.transact().get_array("array");
+        {
+            let mut txn = d1.transact();
+            array.clear(&mut txn);
+            assert!(!txn.has_changes());
+            array.insert_range(&mut txn, 0, vec![1, 2, 3]);
+        }
+
+        let deltas = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let deltas = deltas.clone();
+            array.observe(move |_, e| deltas.borrow_mut().push(e.delta.clone()))
+        };
+        array.replace_all(&mut d1.transact(), vec![4, 5]);
+        assert_eq!(
+            *deltas.borrow(),
+            vec![vec![
+                Change::Added(vec![Value::from(4), Value::from(5)]),
+                Change::Removed(3)
+            ]]
+        );
+
+        exchange_updates(&[&d1, &d2]);
+        let a2 = d2.transact().get_array("array");
+        a2.replace_all(&mut d2.transact(), vec![6]);
+        array.replace_all(&mut d1.transact(), vec![7]);
+        exchange_updates(&[&d1, &d2]);
+        // concurrent replacements are both kept, just like any other concurrent insertions
+        let expected = Any::Array(vec![Any::Number(7.0), Any::Number(6.0)]);
+        assert_eq!(array.to_json(&d1.transact()), expected);
+        assert_eq!(a2.to_json(&d2.transact()), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the maximum length")]
+    fn remove_range_overflow() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let array = txn.get_array("array");
+        array.insert_range(&mut txn, 0, vec![1, 2, 3]);
+        array.remove_range(&mut txn, 1, u32::MAX);
+    }
+
+    #[test]
+    fn event_lengths() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let lengths = Rc::new(RefCell::new(Vec::new()));
+        let _subs: Vec<_> = [&d1, &d2]
+            .iter()
+            .map(|doc| {
+                let l = lengths.clone();
+                doc.transact()
+                    .get_array("array")
+                    .observe(move |_, e| l.borrow_mut().push((e.before_len, e.after_len)))
+            })
+            .collect();
+
+        // root populated in the same transaction it was created in
+        {
+            let mut txn = d1.transact();
+            let array = txn.get_array("array");
+            array.insert_range(&mut txn, 0, vec![1, 2, 3, 4]);
+        }
+        assert_eq!(lengths.take(), vec![(0, 4)]);
+
+        // multiple edits within a single transaction
+        {
+            let mut txn = d1.transact();
+            let array = txn.get_array("array");
+            array.remove_range(&mut txn, 0, 2);
+            array.push_back(&mut txn, 5);
+            array.insert(&mut txn, 1, 6);
+            array.push_back(&mut txn, 7);
+            array.remove(&mut txn, 4);
+        }
+        assert_eq!(lengths.take(), vec![(4, 4)]);
+
+        // events triggered by remote updates
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(lengths.take(), vec![(0, 4)]);
+    }
+
+    #[test]
+    fn delta_json_roundtrip() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let array = {
+            let mut txn = d1.transact();
+            let array = txn.get_array("array");
+            array.insert_range(&mut txn, 0, [1.0, 2.0, 3.0]);
+            array
+        };
+        exchange_updates(&[&d1, &d2]);
+
+        let delta = Rc::new(RefCell::new(None));
+        let _sub = {
+            let delta = delta.clone();
+            array.observe(move |txn, e| *delta.borrow_mut() = Some(e.delta_json(txn)))
+        };
+        {
+            let mut txn = d1.transact();
+            array.insert_range(&mut txn, 1, ["a", "b"]);
+            array.remove_range(&mut txn, 4, 1);
+        }
+
+        // delta emitted by Yjs array observer for the same changes
+        let expected =
+            Any::from_json_str(r#"[{"retain":1},{"insert":["a","b"]},{"retain":1},{"delete":1}]"#)
+                .unwrap();
+        let actual = delta.borrow_mut().take().unwrap();
+        assert!(actual.loosely_eq(&expected), "{}", actual);
+
+        // delta parsed from JSON can be applied to another replica
+        let parsed: Vec<Change> = expected
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|op| Change::from_json(op).unwrap())
+            .collect();
+        let mut t2 = d2.transact();
+        t2.get_array("array").apply_delta(&mut t2, &parsed);
+        let t1 = d1.transact();
+        assert_eq!(t2.get_array("array").to_json(&t2), array.to_json(&t1));
+
+        let invalid = Any::from_json_str(r#"{"insert":"a"}"#).unwrap();
+        assert!(Change::from_json(&invalid).is_err());
+    }
 }
@@ -1,13 +1,20 @@
-use crate::block::{ItemContent, ItemPosition, Prelim};
-use crate::types::{Branch, BranchRef, Entries, TypePtr, Value, TYPE_REFS_MAP};
+use crate::block::{BlockPtr, Item, ItemContent, ItemPosition, Prelim};
+use crate::event::{Observer, Subscription};
+use crate::types::{
+    adds, event_keys, missing_value, Branch, BranchRef, Entries, EntryChange, Event, Observers,
+    Path, PrelimValue, TypePtr, Value, TYPE_REFS_MAP,
+};
 use crate::*;
-use lib0::any::Any;
-use std::collections::HashMap;
+use lib0::any::{Any, TypeMismatch};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
 /// Collection used to store key-value entries in an unordered manner.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Map(BranchRef);
 
+impl_branch_identity!(Map);
+
 impl Map {
     /// Converts all entries of a current map into JSON-like object representation.
     pub fn to_json(&self, txn: &Transaction<'_>) -> Any {
@@ -28,19 +35,16 @@ impl Map {
         Any::Map(res)
     }
 
-    /// Returns a number of entries stored within current map.
-    pub fn len(&self, txn: &Transaction<'_>) -> u32 {
-        let mut len = 0;
-        let inner = self.0.borrow();
-        for ptr in inner.map.values() {
-            //TODO: maybe it would be better to just cache len in the map itself?
-            if let Some(item) = txn.store.blocks.get_item(ptr) {
-                if !item.is_deleted() {
-                    len += 1;
-                }
-            }
-        }
-        len
+    /// Returns a number of entries stored within current map. This value is cached on the map
+    /// itself, so it doesn't require a transaction to be read.
+    pub fn len(&self) -> u32 {
+        self.0.borrow().map_len()
+    }
+
+    /// Returns a number of bytes of content stored within current map, including entry keys and
+    /// nested shared types. See: [Branch::content_size].
+    pub fn content_size(&self, txn: &Transaction) -> usize {
+        self.0.borrow().content_size(txn)
     }
 
     fn entries<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> Entries<'b, 'txn> {
@@ -60,7 +64,9 @@ impl Map {
     }
 
     /// Returns an iterator that enables to traverse over all entries - tuple of key-value pairs -
-    /// stored within current map.
+    /// stored within current map. Values are converted the same way as in [Map::get].
+    ///
+    /// Iteration order is unspecified, but it's stable as long as the map is not modified.
     pub fn iter<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> Iter<'b, 'txn> {
         Iter(self.entries(txn))
     }
@@ -68,6 +74,22 @@ impl Map {
     /// Inserts a new `value` under given `key` into current map. Returns a value stored previously
     /// under the same key (if any existed).
     pub fn insert<V: Prelim>(&self, txn: &mut Transaction, key: String, value: V) -> Option<Value> {
+        match self.try_insert(txn, key, value) {
+            Ok(previous) => previous,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Works like [Map::insert], but if inserting a `value` could make a document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size), an [Error::QuotaExceeded]
+    /// is returned and map is left unchanged.
+    pub fn try_insert<V: Prelim>(
+        &self,
+        txn: &mut Transaction,
+        key: String,
+        value: V,
+    ) -> Result<Option<Value>, Error> {
+        self.0.debug_assert_owned_by(txn);
         let previous = self.get(txn, &key);
         let pos = {
             let inner = self.0.borrow();
@@ -80,13 +102,14 @@ impl Map {
             }
         };
 
-        txn.create_item(&pos, value, Some(key));
-        previous
+        txn.try_create_item(&pos, value, Some(key))?;
+        Ok(previous)
     }
 
     /// Removes a stored within current map under a given `key`. Returns that value or `None` if
     /// no entry with a given `key` was present in current map.
     pub fn remove(&self, txn: &mut Transaction, key: &str) -> Option<Value> {
+        self.0.debug_assert_owned_by(txn);
         let t = self.0.borrow();
         t.remove(txn, key)
     }
@@ -98,6 +121,116 @@ impl Map {
         t.get(txn, key)
     }
 
+    /// Returns all values written concurrently under a given `key`, each tagged with an id of
+    /// a client who wrote it. A winning value - the same one returned by [Map::get] - always
+    /// comes first, followed by values it has overwritten without having observed them.
+    ///
+    /// Overwritten values can only be reported as long as they were not garbage collected
+    /// (see: [Options::skip_gc]). Returns an empty vector if no entry with such `key` existed.
+    pub fn get_all(&self, txn: &Transaction, key: &str) -> Vec<(u64, Value)> {
+        let t = self.0.borrow();
+        let winner = match t.map.get(key).and_then(|p| txn.store.blocks.get_item(p)) {
+            Some(item) if !item.is_deleted() => item,
+            _ => return Vec::new(),
+        };
+        std::iter::once(winner)
+            .chain(concurrent_entries(txn, winner))
+            .filter_map(|item| Some((item.id.client, item.content.get_content_last(txn)?)))
+            .collect()
+    }
+
+    /// Returns a value stored under a given `key` within current map, converted into a requested
+    /// type `T`. Returns an error if no entry with such `key` existed or if its value could not
+    /// be converted into `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let map = txn.get_map("map");
+    /// map.insert(&mut txn, "name".to_owned(), "John");
+    ///
+    /// let name: String = map.get_as(&txn, "name").unwrap();
+    /// assert_eq!(name, "John");
+    /// assert!(map.get_as::<bool>(&txn, "name").is_err());
+    /// ```
+    pub fn get_as<T>(&self, txn: &Transaction, key: &str) -> Result<T, TypeMismatch>
+    where
+        T: TryFrom<Value, Error = TypeMismatch>,
+    {
+        match self.get(txn, key) {
+            Some(value) => T::try_from(value),
+            None => Err(missing_value::<T>()),
+        }
+    }
+
+    /// Returns a [Map] stored under a given `key`, creating an empty one if no entry with such
+    /// `key` existed. Returns an error if an existing entry is not a map.
+    ///
+    /// When two peers concurrently create a map under the same key, only one of them is kept
+    /// after synchronization (just like any other concurrent map insert), together with its
+    /// content.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let root = txn.get_map("root");
+    ///
+    /// let settings = root.get_or_init_map(&mut txn, "settings").unwrap();
+    /// settings.insert(&mut txn, "theme".to_owned(), "dark");
+    ///
+    /// let same = root.get_or_init_map(&mut txn, "settings").unwrap();
+    /// assert_eq!(same, settings);
+    /// assert!(root.get_or_init_text(&mut txn, "settings").is_err());
+    /// ```
+    pub fn get_or_init_map(&self, txn: &mut Transaction, key: &str) -> Result<Map, TypeMismatch> {
+        self.get_or_init(txn, key, PrelimValue::Map(HashMap::new()))
+    }
+
+    /// Returns an [Array] stored under a given `key`, creating an empty one if no entry with such
+    /// `key` existed. Returns an error if an existing entry is not an array.
+    /// See: [Map::get_or_init_map].
+    pub fn get_or_init_array(
+        &self,
+        txn: &mut Transaction,
+        key: &str,
+    ) -> Result<Array, TypeMismatch> {
+        self.get_or_init(txn, key, PrelimValue::Array(Vec::new()))
+    }
+
+    /// Returns a [Text] stored under a given `key`, creating an empty one if no entry with such
+    /// `key` existed. Returns an error if an existing entry is not a text.
+    /// See: [Map::get_or_init_map].
+    pub fn get_or_init_text(&self, txn: &mut Transaction, key: &str) -> Result<Text, TypeMismatch> {
+        self.get_or_init(txn, key, PrelimValue::Text(String::new()))
+    }
+
+    fn get_or_init<T>(
+        &self,
+        txn: &mut Transaction,
+        key: &str,
+        prelim: PrelimValue,
+    ) -> Result<T, TypeMismatch>
+    where
+        T: TryFrom<Value, Error = TypeMismatch>,
+    {
+        if let Some(value) = self.get(txn, key) {
+            return T::try_from(value);
+        }
+        self.insert(txn, key.to_string(), prelim);
+        match self.get(txn, key) {
+            Some(value) => T::try_from(value),
+            None => panic!("Defect: map entry `{}` has not been inserted", key),
+        }
+    }
+
     /// Checks if an entry with given `key` can be found within current map.
     pub fn contains(&self, txn: &Transaction, key: &str) -> bool {
         let t = self.0.borrow();
@@ -109,27 +242,259 @@ impl Map {
         false
     }
 
-    /// Clears the contents of current map, effectively removing all of its entries.
+    /// Clears the contents of current map, effectively removing all of its entries. All live
+    /// entries are collected first and then deleted within a single sweep, so that all of the
+    /// removals end up in the delete set of a given transaction.
     pub fn clear(&self, txn: &mut Transaction<'_>) {
-        let t = self.0.borrow();
-        for (_, ptr) in t.map.iter() {
-            if let Some(item) = txn.store.blocks.get_item(ptr) {
-                if !item.is_deleted() {
-                    item.mark_as_deleted();
-                }
+        let ptrs: Vec<BlockPtr> = {
+            let t = self.0.borrow();
+            t.map
+                .values()
+                .filter(|ptr| {
+                    txn.store
+                        .blocks
+                        .get_item(ptr)
+                        .map(|item| !item.is_deleted())
+                        .unwrap_or(false)
+                })
+                .cloned()
+                .collect()
+        };
+        for ptr in ptrs.iter() {
+            txn.delete(ptr);
+        }
+    }
+
+    /// Removes entries stored under given `keys` within a single sweep. Missing keys and keys
+    /// repeated more than once are ignored. Returns a number of removed entries.
+    ///
+    /// Like with other changes, all removals made in scope of the same transaction are reported
+    /// to observers within a single [MapEvent].
+    pub fn delete_many<I, K>(&self, txn: &mut Transaction, keys: I) -> u32
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        self.0.debug_assert_owned_by(txn);
+        let ptrs: Vec<BlockPtr> = {
+            let t = self.0.borrow();
+            keys.into_iter()
+                .filter_map(|key| t.map.get(key.as_ref()).cloned())
+                .collect()
+        };
+        Self::delete_entries(txn, ptrs)
+    }
+
+    /// Removes all entries which keys start with a given `prefix`. Returns a number of removed
+    /// entries. See: [Map::keys_with_prefix].
+    pub fn delete_prefix(&self, txn: &mut Transaction, prefix: &str) -> u32 {
+        self.0.debug_assert_owned_by(txn);
+        let ptrs: Vec<BlockPtr> = {
+            let t = self.0.borrow();
+            t.map
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(_, ptr)| ptr.clone())
+                .collect()
+        };
+        Self::delete_entries(txn, ptrs)
+    }
+
+    /// Returns an iterator over the keys of entries stored within current map, which start with
+    /// a given `prefix`. Keys are matched as plain byte sequences, without any Unicode
+    /// normalization or case folding. These keys are not ordered.
+    ///
+    /// Keys are borrowed from a map, therefore to remove them within the same transaction they
+    /// need to be collected first - or simply use [Map::delete_prefix].
+    pub fn keys_with_prefix<'b, 'txn, 'p>(
+        &self,
+        txn: &'b Transaction<'txn>,
+        prefix: &'p str,
+    ) -> KeysWithPrefix<'b, 'txn, 'p> {
+        KeysWithPrefix {
+            keys: self.keys(txn),
+            prefix,
+        }
+    }
+
+    /// Deletes blocks of given map entries, skipping ones which are already deleted. Returns
+    /// a number of entries deleted by this call.
+    fn delete_entries(txn: &mut Transaction, ptrs: Vec<BlockPtr>) -> u32 {
+        let mut deleted = 0;
+        for ptr in ptrs.iter() {
+            if txn.delete(ptr) {
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// Subscribes a given callback to be called whenever entries of a current map are inserted,
+    /// updated or removed. Callback is called once per transaction, with an event describing all
+    /// changed keys. Returns a subscription, which will unsubscribe callback when dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use yrs::{Doc, EntryChange};
+    ///
+    /// let doc = Doc::new();
+    /// let map = doc.transact().get_map("map");
+    /// let changes = Rc::new(RefCell::new(Vec::new()));
+    /// let c = changes.clone();
+    /// let _sub = map.observe(move |_, e| {
+    ///     c.borrow_mut().extend(e.keys.iter().map(|(k, v)| (k.clone(), v.clone())));
+    /// });
+    ///
+    /// map.insert(&mut doc.transact(), "key".to_string(), "value");
+    /// assert!(matches!(changes.borrow()[0], (_, EntryChange::Inserted(_))));
+    /// ```
+    pub fn observe<F>(&self, f: F) -> Subscription<MapEvent>
+    where
+        F: Fn(&Transaction, &MapEvent) -> () + 'static,
+    {
+        let mut inner = self.0.borrow_mut();
+        let observers = inner
+            .observers
+            .get_or_insert_with(|| Observers::Map(Observer::new()));
+        match observers {
+            Observers::Map(observer) => observer.subscribe(f),
+            _ => panic!("Defect: map has observers of unexpected type"),
+        }
+    }
+
+    /// Subscribes a given callback to be called only when an entry under a given `key` has been
+    /// changed in scope of a transaction. Changes made to other entries of this map are ignored.
+    /// Returns a subscription, which will unsubscribe callback when dropped.
+    pub fn observe_key<K, F>(&self, key: K, f: F) -> Subscription<MapEvent>
+    where
+        K: Into<String>,
+        F: Fn(&Transaction, &EntryChange) -> () + 'static,
+    {
+        let key = key.into();
+        self.observe(move |txn, e| {
+            if let Some(change) = e.keys.get(&key) {
+                f(txn, change)
             }
+        })
+    }
+
+    /// Subscribes a given callback to be called whenever a current map or any of its nested
+    /// shared types is changed. Callback is called once per transaction, with a list of events
+    /// produced by all changed types, ordered by their depth. Path of each event leads from
+    /// a current map to the changed type.
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.borrow_mut().observe_deep(f)
+    }
+}
+
+/// An event produced by [Map] when its entries have been changed in scope of a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapEvent {
+    /// Map that has been changed.
+    pub target: Map,
+    /// Path from an observed type to the changed map. It's empty unless event has been passed to
+    /// a deep observer of one of the changed map's parents.
+    pub path: Path,
+    /// Changes made to entries of a changed map, keyed by entry key. Previous values of removed
+    /// or overwritten shared types are provided as their JSON projection.
+    pub keys: HashMap<String, EntryChange>,
+    /// Keys of entries, which changes made in scope of a transaction resolved a concurrent
+    /// write of the same entry. Competing values can be inspected using [Map::get_all].
+    pub conflicts: HashSet<String>,
+    /// Number of entries of a changed map from before the transaction.
+    pub before_len: u32,
+    /// Number of entries of a changed map at the moment of transaction commit.
+    pub after_len: u32,
+}
+
+impl MapEvent {
+    pub(crate) fn new(
+        txn: &Transaction,
+        branch: BranchRef,
+        keys: &HashSet<Option<String>>,
+    ) -> Self {
+        let inner = branch.borrow();
+        let conflicts = keys
+            .iter()
+            .flatten()
+            .filter(|key| {
+                let winner = match inner
+                    .map
+                    .get(*key)
+                    .and_then(|p| txn.store.blocks.get_item(p))
+                {
+                    Some(item) => item,
+                    None => return false,
+                };
+                let concurrent = concurrent_entries(txn, winner);
+                !concurrent.is_empty()
+                    && std::iter::once(winner)
+                        .chain(concurrent)
+                        .any(|item| adds(txn, item))
+            })
+            .cloned()
+            .collect();
+        let keys = event_keys(txn, &inner, keys);
+        let after_len = inner.map_len();
+        let before_len = keys.values().fold(after_len, |len, change| match change {
+            EntryChange::Inserted(_) => len - 1,
+            EntryChange::Removed(_) => len + 1,
+            EntryChange::Updated(_, _) => len,
+        });
+        drop(inner);
+        MapEvent {
+            target: Map::from(branch),
+            path: Path::new(),
+            keys,
+            conflicts,
+            before_len,
+            after_len,
         }
     }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        self.target.0.borrow().ptr.clone()
+    }
+}
+
+/// Returns entries of the same key, which have been written concurrently to a given `winner`
+/// and lost to it, ordered from the most recently positioned one. These are all preceding
+/// entries, which the `winner` was not written on top of - either directly or through its
+/// origins.
+fn concurrent_entries<'a>(txn: &'a Transaction, winner: &Item) -> Vec<&'a Item> {
+    let mut preceding = Vec::new();
+    let mut current = winner.left.and_then(|p| txn.store.blocks.get_item(&p));
+    while let Some(item) = current {
+        preceding.push(item);
+        current = item.left.and_then(|p| txn.store.blocks.get_item(&p));
+    }
+    let by_id: HashMap<ID, &Item> = preceding.iter().map(|i| (i.last_id(), *i)).collect();
+    let mut observed = HashSet::new();
+    let mut origin = winner.origin;
+    while let Some(item) = origin.and_then(|id| by_id.get(&id)) {
+        observed.insert(item.last_id());
+        origin = item.origin;
+    }
+    preceding.retain(|item| !observed.contains(&item.last_id()));
+    preceding
 }
 
+/// An unordered iterator over the entries - key-value pairs - of a [Map].
 pub struct Iter<'a, 'txn>(Entries<'a, 'txn>);
 
 impl<'a, 'txn> Iterator for Iter<'a, 'txn> {
-    type Item = (&'a String, Vec<Value>);
+    type Item = (&'a String, Value);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (key, item) = self.0.next()?;
-        Some((key, item.content.get_content(self.0.txn)))
+        let txn = self.0.txn;
+        self.0
+            .find_map(|(key, item)| Some((key, item.content.get_content_last(txn)?)))
     }
 }
 
@@ -145,15 +510,32 @@ impl<'a, 'txn> Iterator for Keys<'a, 'txn> {
     }
 }
 
+/// An unordered iterator over the keys of a [Map] starting with a given prefix.
+/// See: [Map::keys_with_prefix].
+pub struct KeysWithPrefix<'a, 'txn, 'p> {
+    keys: Keys<'a, 'txn>,
+    prefix: &'p str,
+}
+
+impl<'a, 'txn, 'p> Iterator for KeysWithPrefix<'a, 'txn, 'p> {
+    type Item = &'a String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let prefix = self.prefix;
+        self.keys.find(|key| key.starts_with(prefix))
+    }
+}
+
 /// Iterator over the values of a [Map].
 pub struct Values<'a, 'txn>(Entries<'a, 'txn>);
 
 impl<'a, 'txn> Iterator for Values<'a, 'txn> {
-    type Item = Vec<Value>;
+    type Item = Value;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (_, item) = self.0.next()?;
-        Some(item.content.get_content(self.0.txn))
+        let txn = self.0.txn;
+        self.0
+            .find_map(|(_, item)| item.content.get_content_last(txn))
     }
 }
 
@@ -190,10 +572,14 @@ impl<T: Prelim> Prelim for PrelimMap<T> {
 #[cfg(test)]
 mod test {
     use crate::test_utils::exchange_updates;
-    use crate::types::{Map, Value};
-    use crate::{Doc, Transaction};
-    use lib0::any::Any;
+    use crate::types::{EntryChange, Event, Map, PathSegment, Text, Value};
+    use crate::{Doc, Options, PrelimArray, PrelimMap, Transaction, ID};
+    use lib0::any::{Any, TypeMismatch};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::RefCell;
     use std::collections::HashMap;
+    use std::rc::Rc;
 
     #[test]
     fn map_basic() {
@@ -226,7 +612,7 @@ mod test {
 
         //TODO: YArray within YMap
         fn compare_all(t: &Transaction<'_>, m: &Map) {
-            assert_eq!(m.len(&t), 5);
+            assert_eq!(m.len(), 5);
             assert_eq!(m.get(&t, &"number".to_owned()), Some(Value::from(1f64)));
             assert_eq!(m.get(&t, &"boolean0".to_owned()), Some(Value::from(false)));
             assert_eq!(m.get(&t, &"boolean1".to_owned()), Some(Value::from(true)));
@@ -249,7 +635,7 @@ mod test {
         compare_all(&t1, &m1);
 
         let update = d1.encode_state_as_update_v1(&t1);
-        d2.apply_update_v1(&mut t2, update.as_slice());
+        d2.apply_update_v1(&mut t2, update.as_slice()).unwrap();
 
         compare_all(&t2, &m2);
     }
@@ -268,7 +654,7 @@ mod test {
         let d2 = Doc::with_client_id(2);
         let mut t2 = d2.transact();
 
-        d2.apply_update_v1(&mut t2, update.as_slice());
+        d2.apply_update_v1(&mut t2, update.as_slice()).unwrap();
 
         let m2 = t2.get_map("map");
         assert_eq!(
@@ -294,13 +680,90 @@ mod test {
         let u1 = d1.encode_state_as_update_v1(&t1);
         let u2 = d2.encode_state_as_update_v1(&t2);
 
-        d1.apply_update_v1(&mut t1, u2.as_slice());
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         assert_eq!(m1.get(&t1, &"stuff".to_owned()), Some(Value::from("c1")));
         assert_eq!(m2.get(&t2, &"stuff".to_owned()), Some(Value::from("c1")));
     }
 
+    #[test]
+    fn map_conflict_deletes_nested_content() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let m2 = t2.get_map("map");
+
+        m1.insert(&mut t1, "stuff".to_owned(), PrelimArray::from(vec![1, 2]));
+        m2.insert(&mut t2, "stuff".to_owned(), PrelimArray::from(vec![3, 4]));
+
+        let u1 = d1.encode_state_as_update_v1(&t1);
+        let u2 = d2.encode_state_as_update_v1(&t2);
+
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
+
+        // the array of the losing entry is deleted together with all of its elements
+        for txn in [&t1, &t2] {
+            let blocks = txn.store.blocks.get(&1).unwrap();
+            for block in blocks.iter() {
+                assert!(block.is_deleted(), "{} should be deleted", block.id());
+            }
+        }
+        assert_eq!(
+            m1.get(&t1, &"stuff".to_owned()).unwrap().to_json(&t1),
+            m2.get(&t2, &"stuff".to_owned()).unwrap().to_json(&t2)
+        );
+    }
+
+    #[test]
+    fn insert_into_concurrently_deleted_type() {
+        // without GC the deleted array is kept around as a parent for incoming elements
+        let doc = |client_id| {
+            Doc::with_options(Options {
+                skip_gc: true,
+                ..Options::with_client_id(client_id)
+            })
+        };
+        let d1 = doc(1);
+        let d2 = doc(2);
+        {
+            let mut t1 = d1.transact();
+            let m1 = t1.get_map("map");
+            m1.insert(&mut t1, "stuff".to_owned(), PrelimArray::from(vec![1]));
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        {
+            let mut t1 = d1.transact();
+            t1.get_map("map").remove(&mut t1, "stuff");
+        }
+        {
+            let mut t2 = d2.transact();
+            let m2 = t2.get_map("map");
+            if let Some(Value::YArray(a2)) = m2.get(&t2, "stuff") {
+                a2.push_back(&mut t2, 2);
+            } else {
+                panic!("expected nested array");
+            }
+        }
+        // client 2 sends its insert first, so it lands in an array that client 1 already deleted
+        exchange_updates(&[&d2, &d1]);
+
+        for d in [&d1, &d2] {
+            let mut txn = d.transact();
+            let blocks = txn.store.blocks.get(&2).unwrap();
+            for block in blocks.iter() {
+                assert!(block.is_deleted(), "{} should be deleted", block.id());
+            }
+            let map = txn.get_map("map");
+            assert!(map.get(&txn, "stuff").is_none());
+        }
+    }
+
     #[test]
     fn map_len_remove() {
         let d1 = Doc::with_client_id(1);
@@ -312,19 +775,19 @@ mod test {
 
         m1.insert(&mut t1, key1.clone(), "c0");
         m1.insert(&mut t1, key2.clone(), "c1");
-        assert_eq!(m1.len(&t1), 2);
+        assert_eq!(m1.len(), 2);
 
         // remove 'stuff'
         assert_eq!(m1.remove(&mut t1, &key1), Some(Value::from("c0")));
-        assert_eq!(m1.len(&t1), 1);
+        assert_eq!(m1.len(), 1);
 
         // remove 'stuff' again - nothing should happen
         assert_eq!(m1.remove(&mut t1, &key1), None);
-        assert_eq!(m1.len(&t1), 1);
+        assert_eq!(m1.len(), 1);
 
         // remove 'other-stuff'
         assert_eq!(m1.remove(&mut t1, &key2), Some(Value::from("c1")));
-        assert_eq!(m1.len(&t1), 0);
+        assert_eq!(m1.len(), 0);
     }
 
     #[test]
@@ -337,7 +800,7 @@ mod test {
         m1.insert(&mut t1, "key2".to_owned(), "c1");
         m1.clear(&mut t1);
 
-        assert_eq!(m1.len(&t1), 0);
+        assert_eq!(m1.len(), 0);
         assert_eq!(m1.get(&t1, &"key1".to_owned()), None);
         assert_eq!(m1.get(&t1, &"key2".to_owned()), None);
 
@@ -345,14 +808,167 @@ mod test {
         let mut t2 = d2.transact();
 
         let u1 = d1.encode_state_as_update_v1(&t1);
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let m2 = t2.get_map("map");
-        assert_eq!(m2.len(&t2), 0);
+        assert_eq!(m2.len(), 0);
         assert_eq!(m2.get(&t2, &"key1".to_owned()), None);
         assert_eq!(m2.get(&t2, &"key2".to_owned()), None);
     }
 
+    #[test]
+    fn map_clear_updates_delete_set() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+
+        m1.insert(&mut t1, "key1".to_owned(), "c0");
+        m1.insert(&mut t1, "key2".to_owned(), "c1");
+        m1.insert(&mut t1, "key3".to_owned(), "c2");
+        m1.remove(&mut t1, "key3");
+        m1.clear(&mut t1);
+
+        for clock in 0..3 {
+            assert!(t1.delete_set.is_deleted(&ID::new(1, clock)));
+        }
+        assert_eq!(m1.keys(&t1).count(), 0);
+        assert_eq!(m1.values(&t1).count(), 0);
+    }
+
+    #[test]
+    fn map_iter_matches_to_json() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+        m1.insert(&mut t1, "a".to_owned(), 1);
+        m1.insert(&mut t1, "b".to_owned(), "hello");
+        m1.insert(
+            &mut t1,
+            "c".to_owned(),
+            PrelimMap::<Any>::from(HashMap::new()),
+        );
+        m1.insert(&mut t1, "b".to_owned(), "world");
+
+        let entries: HashMap<_, _> = m1
+            .iter(&t1)
+            .map(|(k, v)| (k.clone(), v.to_json(&t1)))
+            .collect();
+        assert_eq!(Any::Map(entries), m1.to_json(&t1));
+        assert!(matches!(
+            m1.iter(&t1).find(|(k, _)| *k == "c"),
+            Some((_, Value::YMap(_)))
+        ));
+    }
+
+    #[test]
+    fn map_iter_within_same_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+
+        m1.insert(&mut t1, "a".to_owned(), 1);
+        m1.insert(&mut t1, "b".to_owned(), 2);
+        m1.insert(&mut t1, "c".to_owned(), 3);
+        m1.remove(&mut t1, "b");
+        m1.insert(&mut t1, "a".to_owned(), 4);
+
+        let mut keys: Vec<_> = m1.keys(&t1).cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a".to_owned(), "c".to_owned()]);
+
+        let mut values: Vec<_> = m1.values(&t1).collect();
+        values.sort_by_key(|v| v.clone().to_string(&t1));
+        assert_eq!(values, vec![Value::from(3f64), Value::from(4f64)]);
+
+        let mut entries: Vec<_> = m1.iter(&t1).map(|(k, v)| (k.clone(), v)).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            entries,
+            vec![
+                ("a".to_owned(), Value::from(4f64)),
+                ("c".to_owned(), Value::from(3f64))
+            ]
+        );
+    }
+
+    #[test]
+    fn map_get_as() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+
+        m1.insert(&mut t1, "string".to_owned(), "hello");
+        m1.insert(&mut t1, "number".to_owned(), 2);
+        m1.insert(&mut t1, "bool".to_owned(), true);
+        m1.insert(
+            &mut t1,
+            "map".to_owned(),
+            PrelimMap::<bool>::from(HashMap::new()),
+        );
+
+        assert_eq!(m1.get_as::<String>(&t1, "string"), Ok("hello".to_owned()));
+        assert_eq!(m1.get_as::<f64>(&t1, "number"), Ok(2.0));
+        assert_eq!(m1.get_as::<i64>(&t1, "number"), Ok(2));
+        assert_eq!(m1.get_as::<bool>(&t1, "bool"), Ok(true));
+
+        let nested: Map = m1.get_as(&t1, "map").unwrap();
+        nested.insert(&mut t1, "key".to_owned(), "value");
+        assert_eq!(nested.get_as::<String>(&t1, "key"), Ok("value".to_owned()));
+
+        assert_eq!(
+            m1.get_as::<bool>(&t1, "string"),
+            Err(TypeMismatch::new("bool", "string"))
+        );
+        // errors describe requested types the same way, regardless of what was found
+        assert_eq!(
+            m1.get_as::<String>(&t1, "map"),
+            Err(TypeMismatch::new("string", "YMap"))
+        );
+        assert_eq!(
+            m1.get_as::<HashMap<String, Any>>(&t1, "map"),
+            Err(TypeMismatch::new("map", "YMap"))
+        );
+        assert_eq!(
+            m1.get_as::<Text>(&t1, "map"),
+            Err(TypeMismatch::new("YText", "YMap"))
+        );
+        assert_eq!(
+            m1.get_as::<String>(&t1, "missing"),
+            Err(TypeMismatch::new("string", "undefined"))
+        );
+        assert_eq!(
+            m1.get_as::<Text>(&t1, "missing"),
+            Err(TypeMismatch::new("YText", "undefined"))
+        );
+        assert_eq!(
+            m1.get_as::<Any>(&t1, "missing"),
+            Err(TypeMismatch::new("any", "undefined"))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn map_insert_serde_json() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let m1 = t1.get_map("map");
+
+        m1.insert(
+            &mut t1,
+            "cfg".to_owned(),
+            serde_json::json!({ "a": 1, "b": [true, null, 1.5], "c": "str" }),
+        );
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), Any::BigInt(1));
+        expected.insert(
+            "b".to_owned(),
+            Any::Array(vec![Any::Bool(true), Any::Null, Any::Number(1.5)]),
+        );
+        expected.insert("c".to_owned(), Any::String("str".to_owned()));
+        assert_eq!(m1.get(&t1, "cfg"), Some(Value::Any(Any::Map(expected))));
+    }
+
     #[test]
     fn map_clear_sync() {
         let d1 = Doc::with_client_id(1);
@@ -412,7 +1028,7 @@ mod test {
                 doc.client_id
             );
             assert_eq!(
-                map.len(&txn),
+                map.len(),
                 0,
                 "all entries for peer {} should be removed",
                 doc.client_id
@@ -513,4 +1129,427 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn observe_entry_changes() {
+        let d1 = Doc::with_client_id(1);
+        let map = {
+            let mut txn = d1.transact();
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "updated".to_owned(), "a");
+            map.insert(&mut txn, "removed".to_owned(), "b");
+            let mut nested = HashMap::new();
+            nested.insert("key".to_owned(), Any::Bool(true));
+            map.insert(&mut txn, "nested".to_owned(), PrelimMap::from(nested));
+            map
+        };
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            map.observe(move |_, e| events.borrow_mut().push(e.keys.clone()))
+        };
+
+        {
+            let mut txn = d1.transact();
+            map.insert(&mut txn, "inserted".to_owned(), 1);
+            map.insert(&mut txn, "updated".to_owned(), "c");
+            map.insert(&mut txn, "updated".to_owned(), "d");
+            map.remove(&mut txn, "removed");
+            map.remove(&mut txn, "nested");
+            // entries both added and removed within the same transaction are not reported
+            map.insert(&mut txn, "temporary".to_owned(), 2);
+            map.remove(&mut txn, "temporary");
+        }
+
+        let mut expected = HashMap::new();
+        expected.insert(
+            "inserted".to_owned(),
+            EntryChange::Inserted(Value::Any(Any::Number(1.0))),
+        );
+        expected.insert(
+            "updated".to_owned(),
+            EntryChange::Updated(
+                Value::Any(Any::String("a".into())),
+                Value::Any(Any::String("d".into())),
+            ),
+        );
+        expected.insert(
+            "removed".to_owned(),
+            EntryChange::Removed(Value::Any(Any::String("b".into()))),
+        );
+        let mut nested = HashMap::new();
+        nested.insert("key".to_owned(), Any::Bool(true));
+        expected.insert(
+            "nested".to_owned(),
+            EntryChange::Removed(Value::Any(Any::Map(nested))),
+        );
+        assert_eq!(*events.borrow(), vec![expected]);
+    }
+
+    #[test]
+    fn observe_remote_changes() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let m2 = d2.transact().get_map("map");
+        let keys = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let keys = keys.clone();
+            m2.observe(move |_, e| keys.borrow_mut().extend(e.keys.keys().cloned()))
+        };
+
+        {
+            let mut t1 = d1.transact();
+            t1.get_map("map").insert(&mut t1, "key".to_owned(), "value");
+        }
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(*keys.borrow(), vec!["key".to_owned()]);
+    }
+
+    #[test]
+    fn observe_deep_replaced_parent() {
+        let doc = Doc::with_client_id(1);
+        let (root, inner) = {
+            let mut txn = doc.transact();
+            let root = txn.get_map("map");
+            root.insert(
+                &mut txn,
+                "a".to_owned(),
+                PrelimMap::<Any>::from(HashMap::new()),
+            );
+            let inner = match root.get(&txn, "a") {
+                Some(Value::YMap(map)) => map,
+                other => panic!("expected a map, got {:?}", other),
+            };
+            (root, inner)
+        };
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let events = events.clone();
+            root.observe_deep(move |_, e| events.borrow_mut().extend(e.iter().cloned()))
+        };
+
+        {
+            let mut txn = doc.transact();
+            inner.insert(&mut txn, "key".to_owned(), "value");
+        }
+        {
+            let events = events.borrow();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].path(), &vec![PathSegment::Key("a".to_owned())]);
+            assert_eq!(events[0].target(), Value::YMap(inner.clone()));
+        }
+        events.borrow_mut().clear();
+
+        {
+            // changes made to a type, which is replaced afterwards are not reported
+            let mut txn = doc.transact();
+            inner.insert(&mut txn, "key".to_owned(), "value2");
+            root.insert(&mut txn, "a".to_owned(), "replaced");
+        }
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            Event::Map(e) => {
+                assert!(e.path.is_empty());
+                assert!(matches!(e.keys["a"], EntryChange::Updated(_, _)));
+            }
+            other => panic!("expected a map event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn observe_single_key() {
+        let doc = Doc::with_client_id(1);
+        let map = doc.transact().get_map("map");
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let changes = changes.clone();
+            map.observe_key("title", move |_, c| changes.borrow_mut().push(c.clone()))
+        };
+
+        map.insert(&mut doc.transact(), "body".to_owned(), "lorem");
+        assert!(changes.borrow().is_empty());
+
+        {
+            let mut txn = doc.transact();
+            map.insert(&mut txn, "title".to_owned(), "a");
+            map.insert(&mut txn, "body".to_owned(), "ipsum");
+        }
+        map.insert(&mut doc.transact(), "title".to_owned(), "b");
+        map.remove(&mut doc.transact(), "title");
+
+        let a = Value::Any(Any::String("a".into()));
+        let b = Value::Any(Any::String("b".into()));
+        assert_eq!(
+            *changes.borrow(),
+            vec![
+                EntryChange::Inserted(a.clone()),
+                EntryChange::Updated(a, b.clone()),
+                EntryChange::Removed(b),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn insert_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let map = d1.transact().get_map("map");
+        let mut txn = d2.transact();
+        txn.get_map("map");
+        map.insert(&mut txn, "key".to_string(), "value");
+    }
+
+    #[test]
+    fn contains_type() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let map = d1.transact().get_map("map");
+        map.insert(
+            &mut d1.transact(),
+            "nested".to_string(),
+            PrelimMap::<Any>::from(HashMap::new()),
+        );
+        let nested = match map.get(&d1.transact(), "nested") {
+            Some(Value::YMap(nested)) => nested,
+            other => panic!("expected nested map, got {:?}", other),
+        };
+
+        let t1 = d1.transact();
+        assert!(t1.contains_type(&map.0));
+        assert!(t1.contains_type(&nested.0));
+
+        let mut t2 = d2.transact();
+        t2.get_map("map");
+        assert!(!t2.contains_type(&map.0));
+        assert!(!t2.contains_type(&nested.0));
+    }
+
+    #[test]
+    fn get_or_init_nested() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let root = t1.get_map("root");
+
+        let text = root.get_or_init_text(&mut t1, "text").unwrap();
+        text.push(&mut t1, "hello");
+        let array = root.get_or_init_array(&mut t1, "array").unwrap();
+        array.push_back(&mut t1, 1);
+        root.insert(&mut t1, "number".to_owned(), 1);
+
+        assert_eq!(root.get_or_init_text(&mut t1, "text").unwrap(), text);
+        assert_eq!(root.get_or_init_array(&mut t1, "array").unwrap(), array);
+        assert_eq!(text.to_string(&t1), "hello");
+        assert_eq!(
+            root.get_or_init_map(&mut t1, "text"),
+            Err(TypeMismatch::new("YMap", "YText"))
+        );
+        assert_eq!(
+            root.get_or_init_array(&mut t1, "number"),
+            Err(TypeMismatch::new("YArray", "number"))
+        );
+        assert_eq!(root.len(), 3);
+    }
+
+    #[test]
+    fn get_or_init_concurrent() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        for (doc, value) in [(&d1, "a"), (&d2, "b")] {
+            let mut txn = doc.transact();
+            let root = txn.get_map("root");
+            let settings = root.get_or_init_map(&mut txn, "settings").unwrap();
+            settings.insert(&mut txn, "theme".to_owned(), value);
+        }
+
+        exchange_updates(&[&d1, &d2]);
+
+        let mut t1 = d1.transact();
+        let mut t2 = d2.transact();
+        let s1 = t1.get_map("root").get_or_init_map(&mut t1, "settings");
+        let s2 = t2.get_map("root").get_or_init_map(&mut t2, "settings");
+        let theme1 = s1.unwrap().get(&t1, "theme");
+        let theme2 = s2.unwrap().get(&t2, "theme");
+        assert_eq!(theme1, theme2);
+        assert!(theme1.is_some());
+        assert_eq!(t1.get_map("root").len(), 1);
+    }
+
+    #[test]
+    fn get_all_concurrent() {
+        let doc = |client_id| {
+            Doc::with_options(Options {
+                skip_gc: true,
+                ..Options::with_client_id(client_id)
+            })
+        };
+        let d1 = doc(1);
+        let d2 = doc(2);
+        for (doc, value) in [(&d1, "light"), (&d2, "dark")] {
+            let mut txn = doc.transact();
+            let map = txn.get_map("settings");
+            map.insert(&mut txn, "theme".to_owned(), value);
+        }
+
+        let conflicts = Rc::new(RefCell::new(Vec::new()));
+        let c = conflicts.clone();
+        let _sub = d2
+            .transact()
+            .get_map("settings")
+            .observe(move |_, e| c.borrow_mut().extend(e.conflicts.iter().cloned()));
+
+        exchange_updates(&[&d1, &d2]);
+
+        let mut t1 = d1.transact();
+        let mut t2 = d2.transact();
+        let m1 = t1.get_map("settings");
+        let m2 = t2.get_map("settings");
+        let all1 = m1.get_all(&t1, "theme");
+        let all2 = m2.get_all(&t2, "theme");
+        assert_eq!(all1.len(), 2);
+        assert_eq!(all1, all2);
+        let winner = m1.get(&t1, "theme");
+        assert_eq!(Some(all1[0].1.clone()), winner);
+        let mut clients: Vec<_> = all1.iter().map(|(client, _)| *client).collect();
+        clients.sort();
+        assert_eq!(clients, vec![1, 2]);
+        assert_eq!(conflicts.take(), vec!["theme".to_string()]);
+        drop(t1);
+        drop(t2);
+
+        // sequentially overwritten values are not reported
+        let d3 = doc(3);
+        let mut t3 = d3.transact();
+        let map = t3.get_map("settings");
+        map.insert(&mut t3, "theme".to_owned(), "light");
+        map.insert(&mut t3, "theme".to_owned(), "dark");
+        assert_eq!(
+            map.get_all(&t3, "theme"),
+            vec![(3, Value::Any(Any::String("dark".into())))]
+        );
+        assert!(map.get_all(&t3, "missing").is_empty());
+    }
+
+    #[test]
+    fn event_lengths() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let lengths = Rc::new(RefCell::new(Vec::new()));
+        let _subs: Vec<_> = [&d1, &d2]
+            .iter()
+            .map(|doc| {
+                let l = lengths.clone();
+                doc.transact()
+                    .get_map("map")
+                    .observe(move |_, e| l.borrow_mut().push((e.before_len, e.after_len)))
+            })
+            .collect();
+
+        {
+            let mut txn = d1.transact();
+            let map = txn.get_map("map");
+            map.insert(&mut txn, "a".to_owned(), 1);
+            map.insert(&mut txn, "b".to_owned(), 2);
+            map.insert(&mut txn, "c".to_owned(), 3);
+        }
+        assert_eq!(lengths.take(), vec![(0, 3)]);
+
+        {
+            let mut txn = d1.transact();
+            let map = txn.get_map("map");
+            map.remove(&mut txn, "a");
+            map.insert(&mut txn, "b".to_owned(), 4);
+            map.insert(&mut txn, "d".to_owned(), 5);
+            map.insert(&mut txn, "e".to_owned(), 6);
+            map.remove(&mut txn, "e");
+        }
+        assert_eq!(lengths.take(), vec![(3, 3)]);
+
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(lengths.take(), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn cached_len_matches_entries() {
+        let mut rng = StdRng::seed_from_u64(0x3a9);
+        let docs: Vec<_> = (1..=3).map(Doc::with_client_id).collect();
+        let keys = ["a", "b", "c", "d", "e"];
+        for _ in 0..300 {
+            let doc = &docs[rng.gen_range(0, docs.len())];
+            let key = keys[rng.gen_range(0, keys.len())].to_owned();
+            {
+                let mut txn = doc.transact();
+                let map = txn.get_map("map");
+                match rng.gen_range(0, 4) {
+                    0 => {
+                        map.remove(&mut txn, &key);
+                    }
+                    1 => map.clear(&mut txn),
+                    _ => {
+                        // overwriting an existing key must not change the count
+                        map.insert(&mut txn, key, rng.gen::<u32>() as f64);
+                    }
+                }
+            }
+            if rng.gen_bool(0.2) {
+                exchange_updates(&[&docs[0], &docs[1], &docs[2]]);
+            }
+            for doc in docs.iter() {
+                let mut txn = doc.transact();
+                let map = txn.get_map("map");
+                assert_eq!(map.len(), map.iter(&txn).count() as u32);
+                assert_eq!(map.len(), map.keys(&txn).count() as u32);
+            }
+        }
+    }
+
+    #[test]
+    fn delete_by_keys_and_prefix() {
+        let doc = Doc::with_client_id(1);
+        let map = doc.transact().get_map("map");
+        {
+            let mut txn = doc.transact();
+            for key in [
+                "tmp:a", "tmp:a:1", "tmp:a:2", "tmp:b", "tmp", "keep", "TMP:c",
+            ] {
+                map.insert(&mut txn, key.to_owned(), key);
+            }
+        }
+        let keys = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let keys = keys.clone();
+            map.observe(move |_, e| {
+                let mut changed: Vec<_> = e.keys.keys().cloned().collect();
+                changed.sort();
+                keys.borrow_mut().push(changed);
+            })
+        };
+
+        let mut txn = doc.transact();
+        let mut matching: Vec<_> = map.keys_with_prefix(&txn, "tmp:a").cloned().collect();
+        matching.sort();
+        assert_eq!(matching, vec!["tmp:a", "tmp:a:1", "tmp:a:2"]);
+        assert_eq!(map.keys_with_prefix(&txn, "tmp:x").count(), 0);
+
+        // overlapping prefixes: entries removed by the first call are not counted again
+        assert_eq!(map.delete_prefix(&mut txn, "tmp:a:"), 2);
+        assert_eq!(map.delete_prefix(&mut txn, "tmp:"), 2);
+        assert_eq!(map.delete_prefix(&mut txn, "tmp:"), 0);
+        assert_eq!(map.delete_prefix(&mut txn, "none"), 0);
+        assert_eq!(
+            map.delete_many(&mut txn, ["tmp", "tmp", "missing", "tmp:a"]),
+            1
+        );
+        assert_eq!(map.len(), 2);
+        assert!(map.contains(&txn, "keep"));
+        assert!(map.contains(&txn, "TMP:c"));
+        drop(txn);
+
+        // all removals are reported within a single event
+        assert_eq!(
+            keys.take(),
+            vec![vec!["tmp", "tmp:a", "tmp:a:1", "tmp:a:2", "tmp:b"]]
+        );
+    }
 }
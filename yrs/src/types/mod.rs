@@ -1,18 +1,44 @@
+/// Implements equality and hashing of a shared type handle based on identity of its [BranchRef]:
+/// two handles are equal only if they point to the same shared type instance. Use
+/// [Doc::eq_content](crate::Doc::eq_content) or compare `to_json` results to compare content.
+macro_rules! impl_branch_identity {
+    ($t:ty) => {
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.ptr_eq(&other.0)
+            }
+        }
+
+        impl Eq for $t {}
+
+        impl std::hash::Hash for $t {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash_ptr(state)
+            }
+        }
+    };
+}
+
 pub mod array;
 pub mod map;
 pub mod text;
 pub mod xml;
+mod xml_parser;
 
 use crate::*;
 pub use map::Map;
 pub use text::Text;
 
 use crate::block::{BlockPtr, Item, ItemContent, ItemPosition, Prelim};
-use crate::types::array::Array;
-use crate::types::xml::{XmlElement, XmlText};
-use lib0::any::Any;
-use std::cell::{BorrowMutError, Ref, RefCell, RefMut};
-use std::collections::HashMap;
+use crate::event::{Observer, Subscription};
+use crate::types::array::{Array, ArrayEvent};
+use crate::types::map::MapEvent;
+use crate::types::text::{update_current_attributes, Attrs, TextEvent};
+use crate::types::xml::{XmlElement, XmlEvent, XmlFragment, XmlText, XmlTextEvent};
+use lib0::any::{Any, TypeMismatch};
+use std::cell::{BorrowError, BorrowMutError, Cell, Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt::Formatter;
 use std::hash::Hasher;
 use std::rc::Rc;
@@ -54,6 +80,26 @@ impl BranchRef {
         BranchRef(Rc::new(RefCell::new(inner)))
     }
 
+    /// Checks if both references point to the same [Branch] instance.
+    pub(crate) fn ptr_eq(&self, other: &BranchRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Feeds an address of an underlying [Branch] into a given `state`, consistently with
+    /// [BranchRef::ptr_eq].
+    pub(crate) fn hash_ptr<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0.as_ref(), state)
+    }
+
+    /// Verifies (in debug builds only) that current branch belongs to a document, which given
+    /// transaction was created for.
+    pub(crate) fn debug_assert_owned_by(&self, txn: &Transaction) {
+        debug_assert!(
+            txn.contains_type(self),
+            "shared type belongs to a different document than a transaction used to modify it"
+        );
+    }
+
     /// Returns an immutable ref wrapper to an underlying [Branch].
     /// This method will panic, if current branch was already mutably borrowed.
     pub fn borrow(&self) -> Ref<Branch> {
@@ -67,6 +113,12 @@ impl BranchRef {
         self.0.borrow_mut()
     }
 
+    /// Returns a result, which may either be an immutable ref wrapper to an underlying [Branch],
+    /// or an error in case when this branch was already mutably borrowed somewhere else.
+    pub fn try_borrow(&self) -> Result<Ref<Branch>, BorrowError> {
+        self.0.try_borrow()
+    }
+
     /// Returns a result, which may either be a mutable ref wrapper to an underlying [Branch],
     /// or an error in case when this branch was already borrowed (either mutably or immutably)
     /// somewhere else.
@@ -84,7 +136,7 @@ impl BranchRef {
             TYPE_REFS_MAP => Value::YMap(Map::from(self)),
             TYPE_REFS_TEXT => Value::YText(Text::from(self)),
             TYPE_REFS_XML_ELEMENT => Value::YXmlElement(XmlElement::from(self)),
-            TYPE_REFS_XML_FRAGMENT => Value::YXmlElement(XmlElement::from(self)),
+            TYPE_REFS_XML_FRAGMENT => Value::YXmlFragment(XmlFragment::from(self)),
             TYPE_REFS_XML_TEXT => Value::YXmlText(XmlText::from(self)),
             //TYPE_REFS_XML_HOOK => Value::YXmlElement(XmlElement::from(self)),
             other => panic!("Cannot convert to value - unsupported type ref: {}", other),
@@ -94,16 +146,35 @@ impl BranchRef {
     /// Removes up to a `len` of countable elements from current branch sequence, starting at the
     /// given `index`. Returns number of removed elements.
     pub(crate) fn remove_at(&self, txn: &mut Transaction, index: u32, len: u32) -> u32 {
+        self.remove_at_from(txn, index, len, None)
+    }
+
+    /// Works like [BranchRef::remove_at], but if a `cached` marker is given (see:
+    /// [Branch::find_marker_from]), a position at a given `index` is looked up starting from it
+    /// and it's kept valid afterwards.
+    pub(crate) fn remove_at_from(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        len: u32,
+        mut cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) -> u32 {
+        self.debug_assert_owned_by(txn);
+        range_end(index, len);
         let mut remaining = len;
-        let start = {
+        let (start, offset) = {
             let parent = self.borrow();
-            parent.start
+            match cached.as_deref_mut() {
+                Some(cached) => parent.start_from(txn, index, cached),
+                None => parent.start_for(txn, index),
+            }
         };
         let (_, mut ptr) = if index == 0 {
             (None, start)
         } else {
-            Branch::index_to_ptr(txn, start, index)
+            Branch::index_to_ptr(txn, start, offset)
         };
+        let markers = self.borrow().search_markers.take();
         while remaining > 0 {
             if let Some(mut p) = ptr {
                 if let Some(item) = txn.store.blocks.get_item(&p) {
@@ -128,7 +199,33 @@ impl BranchRef {
             }
         }
 
-        len - remaining
+        let removed = len - remaining;
+        let inner = self.borrow();
+        inner
+            .search_markers
+            .restore_removed(txn, markers, index, removed);
+        if let Some(cached) = cached {
+            inner.retain_marker(cached, index);
+        }
+        removed
+    }
+
+    /// Removes all elements of a current branch indexed sequence component, including the ones
+    /// which are not countable (ie. text formatting attributes).
+    pub(crate) fn clear(&self, txn: &mut Transaction) {
+        self.debug_assert_owned_by(txn);
+        let ptrs: Vec<BlockPtr> = {
+            let inner = self.borrow();
+            inner.search_markers.clear();
+            inner
+                .iter(txn)
+                .filter(|item| !item.is_deleted())
+                .map(|item| BlockPtr::from(item.id))
+                .collect()
+        };
+        for ptr in ptrs.iter() {
+            txn.delete(ptr);
+        }
     }
 
     /// Inserts a preliminary `value` into a current branch indexed sequence component at the given
@@ -139,10 +236,44 @@ impl BranchRef {
         index: u32,
         value: V,
     ) -> &'t Item {
-        let (start, parent) = {
+        self.insert_at_from(txn, index, value, None)
+    }
+
+    /// Works like [BranchRef::insert_at], but if a `cached` marker is given (see:
+    /// [Branch::find_marker_from]), a position at a given `index` is looked up starting from it
+    /// and it's kept valid afterwards.
+    pub(crate) fn insert_at_from<'t, V: Prelim>(
+        &self,
+        txn: &'t mut Transaction,
+        index: u32,
+        value: V,
+        cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) -> &'t Item {
+        match self.try_insert_at_from(txn, index, value, cached) {
+            Ok(item) => item,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Works like [BranchRef::insert_at_from], but returns an [Error::QuotaExceeded] without
+    /// inserting anything if a `value` could make a document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size).
+    pub(crate) fn try_insert_at_from<'t, V: Prelim>(
+        &self,
+        txn: &'t mut Transaction,
+        index: u32,
+        value: V,
+        mut cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) -> Result<&'t Item, Error> {
+        self.debug_assert_owned_by(txn);
+        let (start, offset, parent) = {
             let parent = self.borrow();
             if index <= parent.len() {
-                (parent.start, parent.ptr.clone())
+                let (start, offset) = match cached.as_deref_mut() {
+                    Some(cached) => parent.start_from(txn, index, cached),
+                    None => parent.start_for(txn, index),
+                };
+                (start, offset, parent.ptr.clone())
             } else {
                 panic!("Cannot insert item at index over the length of an array")
             }
@@ -150,7 +281,7 @@ impl BranchRef {
         let (left, right) = if index == 0 {
             (None, None)
         } else {
-            Branch::index_to_ptr(txn, start, index)
+            Branch::index_to_ptr(txn, start, offset)
         };
         let pos = ItemPosition {
             parent,
@@ -159,7 +290,30 @@ impl BranchRef {
             index: 0,
         };
 
-        txn.create_item(&pos, value, None)
+        let (markers, len) = {
+            let inner = self.borrow();
+            (inner.search_markers.take(), inner.len())
+        };
+        let id = match txn.try_create_item(&pos, value, None) {
+            Ok(item) => item.id,
+            Err(e) => {
+                self.borrow()
+                    .search_markers
+                    .restore_inserted(markers, index, 0);
+                return Err(e);
+            }
+        };
+        {
+            let inner = self.borrow();
+            let inserted = inner.len() - len;
+            inner
+                .search_markers
+                .restore_inserted(markers, index, inserted);
+            if let Some(cached) = cached {
+                inner.retain_marker(cached, index);
+            }
+        }
+        Ok(txn.store.blocks.get_item(&BlockPtr::from(id)).unwrap())
     }
 }
 
@@ -189,6 +343,56 @@ impl PartialEq for BranchRef {
     }
 }
 
+/// Returns a name of a shared type kind described by a given `type_ref`, consistent with
+/// [Value::kind]. Root types, which kind is not known yet, are described as `"undefined"`.
+pub(crate) fn kind_name(type_ref: TypeRefs) -> &'static str {
+    match type_ref {
+        TYPE_REFS_ARRAY => "YArray",
+        TYPE_REFS_MAP => "YMap",
+        TYPE_REFS_TEXT => "YText",
+        TYPE_REFS_XML_ELEMENT => "YXmlElement",
+        TYPE_REFS_XML_FRAGMENT => "YXmlFragment",
+        TYPE_REFS_XML_HOOK => "YXmlHook",
+        TYPE_REFS_XML_TEXT => "YXmlText",
+        _ => "undefined",
+    }
+}
+
+/// Returns a type ref of a shared type with a given kind name, as returned by [kind_name].
+/// Returns `None` for names, which don't refer to any type that can be used as a root type.
+pub(crate) fn kind_type_ref(kind: &str) -> Option<TypeRefs> {
+    match kind {
+        "YArray" => Some(TYPE_REFS_ARRAY),
+        "YMap" => Some(TYPE_REFS_MAP),
+        "YText" => Some(TYPE_REFS_TEXT),
+        "YXmlElement" => Some(TYPE_REFS_XML_ELEMENT),
+        "YXmlFragment" => Some(TYPE_REFS_XML_FRAGMENT),
+        "YXmlText" => Some(TYPE_REFS_XML_TEXT),
+        _ => None,
+    }
+}
+
+/// Returns a node name used by root types of a given type ref.
+pub(crate) fn root_node_name(type_ref: TypeRefs) -> Option<String> {
+    if type_ref == TYPE_REFS_XML_ELEMENT {
+        Some("UNDEFINED".to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns an exclusive end of a range of `len` elements starting at a given `index`. Panics if
+/// such range would exceed the maximum length of a sequence, before any change is made to it.
+pub(crate) fn range_end(index: u32, len: u32) -> u32 {
+    match index.checked_add(len) {
+        Some(end) => end,
+        None => panic!(
+            "Range of {} elements starting at index {} exceeds the maximum length of a sequence",
+            len, index
+        ),
+    }
+}
+
 /// Branch describes a content of a complex Yrs data structures, such as arrays or maps.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Branch {
@@ -223,12 +427,29 @@ pub struct Branch {
 
     pub item: Option<BlockPtr>, //TODO: isn't this equivalent to `ptr` field?
 
-    /// A length of an indexed sequence component of a current branch node. Map component elements
-    /// are computed on demand.
+    /// A length of an indexed sequence component of a current branch node.
     pub len: u32,
 
+    /// A number of non-deleted entries of a map component of a current branch node. It's a cell,
+    /// as map entries can be deleted while a branch is borrowed (ie. by [Map::remove]).
+    pub(crate) map_len: Cell<u32>,
+
     /// An identifier of an underlying complex data type (eg. is it an Array or a Map).
     type_ref: TypeRefs,
+
+    /// Callbacks subscribed to changes made directly to this branch node.
+    pub(crate) observers: Option<Observers>,
+
+    /// Callbacks subscribed to changes made to this branch node or any of its successors.
+    pub(crate) deep_observers: Option<Observer<Vec<Event>>>,
+
+    /// Cached positions of recently accessed elements of an indexed sequence component, used to
+    /// speed up index lookups.
+    pub(crate) search_markers: SearchMarkers,
+
+    /// A cached result of [Branch::content_size], dropped whenever this branch or any of its
+    /// nested types is changed.
+    pub(crate) content_size_cache: Cell<Option<usize>>,
 }
 
 impl Branch {
@@ -237,10 +458,35 @@ impl Branch {
             start: None,
             map: HashMap::default(),
             len: 0,
+            map_len: Cell::new(0),
             item: None,
             ptr,
             name,
             type_ref,
+            observers: None,
+            deep_observers: None,
+            search_markers: SearchMarkers::default(),
+            content_size_cache: Cell::new(None),
+        }
+    }
+
+    /// Subscribes a given callback to be called with all events produced by changes made to this
+    /// branch node or any of its successors, once per transaction.
+    pub(crate) fn observe_deep<F>(&mut self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.deep_observers
+            .get_or_insert_with(Observer::new)
+            .subscribe(f)
+    }
+
+    /// Sets an identifier of an underlying complex data type of a branch, which has been created
+    /// with an undefined one (ie. root type integrated from a remote update before it has been
+    /// accessed locally).
+    pub(crate) fn repair_type_ref(&mut self, type_ref: TypeRefs) {
+        if self.type_ref() == TYPE_REFS_UNDEFINED {
+            self.type_ref = type_ref;
         }
     }
 
@@ -250,11 +496,49 @@ impl Branch {
     }
 
     /// Returns a length of an indexed sequence component of a current branch node.
-    /// Map component elements are computed on demand.
     pub fn len(&self) -> u32 {
         self.len
     }
 
+    /// Returns a number of non-deleted entries of a map component of a current branch node.
+    pub fn map_len(&self) -> u32 {
+        self.map_len.get()
+    }
+
+    /// Returns a number of bytes of user data stored by a current branch node and all of its
+    /// nested shared types (see: [ItemContent::content_size]), including keys of map entries.
+    /// Deleted content is not counted. It's computed by traversing a whole branch and cached until
+    /// this branch or any of its nested types is changed.
+    pub fn content_size(&self, txn: &Transaction) -> usize {
+        if let Some(size) = self.content_size_cache.get() {
+            return size;
+        }
+        let item_size = |item: &Item| {
+            let mut size = item.content.content_size();
+            if let ItemContent::Type(branch) = &item.content {
+                size += branch.borrow().content_size(txn);
+            }
+            size
+        };
+        let mut size = 0;
+        let mut current = self.start;
+        while let Some(item) = current.and_then(|p| txn.store.blocks.get_item(&p)) {
+            if !item.is_deleted() {
+                size += item_size(item);
+            }
+            current = item.right;
+        }
+        for (key, ptr) in self.map.iter() {
+            if let Some(item) = txn.store.blocks.get_item(ptr) {
+                if !item.is_deleted() {
+                    size += key.len() + item_size(item);
+                }
+            }
+        }
+        self.content_size_cache.set(Some(size));
+        size
+    }
+
     /// Get iterator over (String, Block) entries of a map component of a current root type.
     /// Deleted blocks are skipped by this iterator.
     pub(crate) fn entries<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> Entries<'b, 'txn> {
@@ -287,21 +571,170 @@ impl Branch {
     pub(crate) fn get_at<'a, 'b>(
         &'a self,
         txn: &'b Transaction,
-        mut index: u32,
+        index: u32,
     ) -> Option<(&'b ItemContent, usize)> {
-        let mut ptr = self.start;
+        let marker = self.find_marker(txn, index)?;
+        let item = txn.store.blocks.get_item(&marker.ptr)?;
+        Some((&item.content, (index - marker.index) as usize))
+    }
+
+    /// Returns a unique identifier of an element found at a given `index` of a sequence component
+    /// of current branch node. Returns `None` if `index` was outside of its boundary.
+    pub fn id_at(&self, txn: &Transaction, index: u32) -> Option<ID> {
+        let marker = self.find_marker(txn, index)?;
+        let id = marker.ptr.id;
+        Some(ID::new(id.client, id.clock + index - marker.index))
+    }
+
+    /// Returns a search marker pointing to a block, which contains an element at a given `index`
+    /// of a sequence component of current branch node. Returned marker also contains an index at
+    /// which that block starts and formatting attributes in effect at its position.
+    ///
+    /// Lookup starts from the closest cached search marker preceding the `index` (or from the
+    /// beginning of a sequence if there's none) and caches found position for subsequent lookups.
+    /// Returns `None` if `index` was outside of the sequence boundary.
+    pub(crate) fn find_marker(&self, txn: &Transaction, index: u32) -> Option<SearchMarker> {
+        if index >= self.len {
+            return None;
+        }
+        let (slot, marker) = match self.search_markers.nearest(txn, index) {
+            Some((slot, m)) => {
+                let marker = Self::scan_marker(txn, index, Some(m.ptr), m.index, m.attrs)?;
+                (Some((slot, m.index)), marker)
+            }
+            None => (
+                None,
+                Self::scan_marker(txn, index, self.start, 0, HashMap::new())?,
+            ),
+        };
+        self.search_markers.update(slot, marker.clone(), self.len);
+        Some(marker)
+    }
+
+    /// Works like [Branch::find_marker], but if no elements of a current branch have been
+    /// inserted or removed since a `cached` marker was found and it's placed at or before
+    /// a given `index`, lookup starts from it. Found marker is stored back in `cached`. This way
+    /// lookups made by a single cursor moving forward don't need to consult shared search markers.
+    pub(crate) fn find_marker_from(
+        &self,
+        txn: &Transaction,
+        index: u32,
+        cached: &mut Option<(u64, SearchMarker)>,
+    ) -> Option<SearchMarker> {
+        if index >= self.len {
+            return None;
+        }
+        let generation = self.search_markers.generation();
+        let start = match cached.as_ref() {
+            Some((g, m)) if *g == generation => m.resolve(txn).filter(|m| m.index <= index),
+            _ => None,
+        };
+        let marker = match start {
+            Some(m) => Self::scan_marker(txn, index, Some(m.ptr), m.index, m.attrs),
+            None => self.find_marker(txn, index),
+        }?;
+        *cached = Some((generation, marker.clone()));
+        Some(marker)
+    }
+
+    /// Keeps a `cached` marker valid after its owner has inserted or removed elements at a given
+    /// `index`, as long as a marker is placed before that index - positions of elements preceding
+    /// an edit don't change. A marker has to be found or validated right before an edit.
+    pub(crate) fn retain_marker(&self, cached: &mut Option<(u64, SearchMarker)>, index: u32) {
+        let generation = self.search_markers.generation();
+        *cached = cached
+            .take()
+            .filter(|(_, m)| m.index < index)
+            .map(|(_, m)| (generation, m));
+    }
+
+    /// Walks over a sequence of blocks, starting from a block pointed by `ptr` which is placed at
+    /// a given `start` index and has given formatting `attrs` in effect, until a block containing
+    /// an element at a given `index` is found.
+    fn scan_marker(
+        txn: &Transaction,
+        index: u32,
+        mut ptr: Option<BlockPtr>,
+        mut start: u32,
+        mut attrs: HashMap<String, String>,
+    ) -> Option<SearchMarker> {
         while let Some(p) = ptr {
             let item = txn.store.blocks.get_item(&p)?;
-            let len = item.len();
-            if !item.is_deleted() && item.is_countable() {
-                if index < len {
-                    return Some((&item.content, index as usize));
+            if !item.is_deleted() {
+                match &item.content {
+                    ItemContent::Format(key, value) => {
+                        update_current_attributes(&mut attrs, key, value)
+                    }
+                    _ if item.is_countable() => {
+                        let len = item.len();
+                        if index < start + len {
+                            let ptr = BlockPtr::new(item.id, p.pivot() as u32);
+                            return Some(SearchMarker::new(ptr, start, attrs));
+                        }
+                        start += len;
+                    }
+                    _ => {}
                 }
             }
-            index -= len;
             ptr = item.right.clone();
         }
+        None
+    }
+
+    /// Returns a block pointer, from which a lookup of a position at a given `index` of
+    /// a sequence component of current branch node can start, together with an index relative to
+    /// that block. Starting from returned block produces the same results as starting from the
+    /// beginning of a sequence.
+    pub(crate) fn start_for(&self, txn: &Transaction, index: u32) -> (Option<BlockPtr>, u32) {
+        if index > 0 {
+            if let Some(marker) = self.find_marker(txn, index - 1) {
+                return (Some(marker.ptr), index - marker.index);
+            }
+        }
+        (self.start, index)
+    }
 
+    /// Works like [Branch::start_for], but starts a lookup from a `cached` marker, just like
+    /// [Branch::find_marker_from] does.
+    pub(crate) fn start_from(
+        &self,
+        txn: &Transaction,
+        index: u32,
+        cached: &mut Option<(u64, SearchMarker)>,
+    ) -> (Option<BlockPtr>, u32) {
+        if index > 0 {
+            if let Some(marker) = self.find_marker_from(txn, index - 1, cached) {
+                return (Some(marker.ptr), index - marker.index);
+            }
+        }
+        (self.start, index)
+    }
+
+    /// Returns an index of an element identified by a given `id` within a sequence component of
+    /// current branch node. Returns `None` if element is deleted or not a part of this sequence.
+    pub(crate) fn index_of(&self, txn: &Transaction, id: &ID) -> Option<u32> {
+        let mut index = 0;
+        let mut ptr = self.start;
+        while let Some(p) = ptr {
+            let item = txn.store.blocks.get_item(&p)?;
+            let countable = !item.is_deleted() && item.is_countable();
+            if item.id.client == id.client
+                && item.id.clock <= id.clock
+                && id.clock < item.id.clock + item.len()
+            {
+                return if item.is_deleted() {
+                    None
+                } else if countable {
+                    Some(index + id.clock - item.id.clock)
+                } else {
+                    Some(index)
+                };
+            }
+            if countable {
+                index += item.len();
+            }
+            ptr = item.right.clone();
+        }
         None
     }
 
@@ -385,6 +818,222 @@ impl Branch {
     }
 }
 
+/// Maximum number of search markers cached by a single [Branch].
+const MAX_SEARCH_MARKERS: usize = 80;
+
+/// A cached position of a visible element within an indexed sequence component of a [Branch].
+/// Search markers are used as starting points of index lookups, so that they don't need to
+/// traverse a whole block list from its beginning.
+#[derive(Debug, Clone)]
+pub(crate) struct SearchMarker {
+    /// Pointer to a marked element. It's not guaranteed to point at the beginning of a block, as
+    /// blocks can be squashed together after the marker was created.
+    pub ptr: BlockPtr,
+
+    /// Index of a marked element within a sequence.
+    pub index: u32,
+
+    /// Formatting attributes (in their JSON-encoded form) in effect at a position of a marked
+    /// element. Always empty for sequences which don't contain formatting blocks.
+    pub attrs: HashMap<String, String>,
+
+    timestamp: u32,
+}
+
+impl SearchMarker {
+    pub fn new(ptr: BlockPtr, index: u32, attrs: HashMap<String, String>) -> Self {
+        SearchMarker {
+            ptr,
+            index,
+            attrs,
+            timestamp: 0,
+        }
+    }
+
+    /// Returns a copy of current marker moved to the beginning of the closest visible block on the
+    /// left side of a marked element, which was removed together with all elements between it and
+    /// an `end` index (exclusive) of that block. Returns `None` if there's no such block or if
+    /// a visible formatting block stands in the way, as it would change the attributes in effect.
+    fn move_left(&self, txn: &Transaction, end: u32) -> Option<SearchMarker> {
+        let mut ptr = txn.store.blocks.get_item(&self.ptr)?.left;
+        while let Some(p) = ptr {
+            let item = txn.store.blocks.get_item(&p)?;
+            if !item.is_deleted() {
+                if !item.is_countable() {
+                    return None;
+                }
+                let mut marker = SearchMarker::new(
+                    BlockPtr::new(item.id, p.pivot() as u32),
+                    end.checked_sub(item.len())?,
+                    self.attrs.clone(),
+                );
+                marker.timestamp = self.timestamp;
+                return Some(marker);
+            }
+            ptr = item.left;
+        }
+        None
+    }
+
+    /// Returns a copy of current marker pointing to the beginning of a block containing marked
+    /// element, or `None` if that element is no longer visible.
+    fn resolve(&self, txn: &Transaction) -> Option<SearchMarker> {
+        let item = txn.store.blocks.get_item(&self.ptr)?;
+        let offset = self.ptr.id.clock - item.id.clock;
+        if item.is_deleted() || !item.is_countable() || offset > self.index {
+            None
+        } else {
+            let ptr = BlockPtr::new(item.id, self.ptr.pivot() as u32);
+            Some(SearchMarker::new(
+                ptr,
+                self.index - offset,
+                self.attrs.clone(),
+            ))
+        }
+    }
+}
+
+/// A set of [SearchMarker]s cached by a single [Branch].
+///
+/// Markers are kept up to date by local insertions and deletions performed using index-based
+/// methods - markers placed on removed elements are moved to the left instead of being dropped.
+/// Any other change of a sequence (eg. integration of remote updates) invalidates all markers of
+/// a corresponding branch.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchMarkers {
+    markers: RefCell<Vec<SearchMarker>>,
+    timestamp: Cell<u32>,
+    generation: Cell<u64>,
+}
+
+impl SearchMarkers {
+    /// Removes all cached markers.
+    pub fn clear(&self) {
+        self.bump_generation();
+        self.markers.borrow_mut().clear()
+    }
+
+    /// Removes all cached markers, returning them to the caller. Used together with
+    /// [SearchMarkers::restore_inserted] and [SearchMarkers::restore_removed] to preserve markers
+    /// across local changes.
+    pub fn take(&self) -> Vec<SearchMarker> {
+        self.bump_generation();
+        std::mem::take(&mut *self.markers.borrow_mut())
+    }
+
+    /// Returns a number, which changes every time the markers are either cleared or taken, which
+    /// happens whenever elements of a corresponding sequence are inserted or removed. Markers
+    /// found while it stays the same remain valid.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    fn bump_generation(&self) {
+        self.generation.set(self.generation.get() + 1);
+    }
+
+    /// Returns indexes of all cached markers in ascending order.
+    #[cfg(test)]
+    pub fn indexes(&self) -> Vec<u32> {
+        let mut indexes: Vec<_> = self.markers.borrow().iter().map(|m| m.index).collect();
+        indexes.sort_unstable();
+        indexes
+    }
+
+    /// Restores `markers` previously taken from this set, adjusting them to `len` elements
+    /// inserted at a given `index`.
+    pub fn restore_inserted(&self, mut markers: Vec<SearchMarker>, index: u32, len: u32) {
+        for m in markers.iter_mut() {
+            if m.index >= index {
+                m.index += len;
+            }
+        }
+        *self.markers.borrow_mut() = markers;
+    }
+
+    /// Restores `markers` previously taken from this set, adjusting them to `len` elements
+    /// removed starting from a given `index`. Like in Yjs `updateMarkerChanges`, a marker placed
+    /// on a removed element is moved to the closest visible block on its left, unless that block
+    /// is already marked.
+    pub fn restore_removed(
+        &self,
+        txn: &Transaction,
+        markers: Vec<SearchMarker>,
+        index: u32,
+        len: u32,
+    ) {
+        let mut moved = None;
+        let mut restored = Vec::with_capacity(markers.len());
+        for mut m in markers {
+            if m.index < index {
+                restored.push(m);
+            } else if m.index >= index + len {
+                m.index -= len;
+                restored.push(m);
+            } else if moved.is_none() {
+                moved = m.move_left(txn, index);
+            }
+        }
+        if let Some(m) = moved {
+            if restored.iter().all(|other| other.index != m.index) {
+                restored.push(m);
+            }
+        }
+        *self.markers.borrow_mut() = restored;
+    }
+
+    /// Returns the closest valid marker placed at or before a given `index` together with its slot
+    /// number. Only the closest marker is resolved - if it's no longer valid, it's discarded and
+    /// the next closest one is tried.
+    fn nearest(&self, txn: &Transaction, index: u32) -> Option<(usize, SearchMarker)> {
+        let mut markers = self.markers.borrow_mut();
+        loop {
+            let (slot, _) = markers
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.index <= index)
+                .max_by_key(|(_, m)| m.index)?;
+            match markers[slot].resolve(txn) {
+                Some(marker) => return Some((slot, marker)),
+                None => {
+                    markers.swap_remove(slot);
+                }
+            }
+        }
+    }
+
+    /// Caches a new `marker` found by a lookup which started from a marker at a given `slot` (if
+    /// any). A starting marker is overwritten if it's close enough to a new one, otherwise the least
+    /// recently used marker is evicted when there's no space left.
+    fn update(&self, slot: Option<(usize, u32)>, mut marker: SearchMarker, len: u32) {
+        let timestamp = self.timestamp.get() + 1;
+        self.timestamp.set(timestamp);
+        marker.timestamp = timestamp;
+
+        let distance = len / MAX_SEARCH_MARKERS as u32;
+        let mut markers = self.markers.borrow_mut();
+        match slot {
+            Some((slot, index)) if marker.index - index <= distance => markers[slot] = marker,
+            None if marker.index <= distance => { /* close enough to the beginning */ }
+            _ if markers.len() < MAX_SEARCH_MARKERS => markers.push(marker),
+            _ => {
+                if let Some(oldest) = markers.iter_mut().min_by_key(|m| m.timestamp) {
+                    *oldest = marker;
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for SearchMarkers {
+    fn eq(&self, _: &Self) -> bool {
+        // search markers are only a lookup cache and don't affect branch contents
+        true
+    }
+}
+
+impl Eq for SearchMarkers {}
+
 /// Value that can be returned by Yrs data types. This includes [Any] which is an extension
 /// representation of JSON, but also nested complex collaborative structures specific to Yrs.
 #[derive(Debug, Clone, PartialEq)]
@@ -395,7 +1044,10 @@ pub enum Value {
     YArray(Array),
     YMap(Map),
     YXmlElement(XmlElement),
+    YXmlFragment(XmlFragment),
     YXmlText(XmlText),
+    /// Reference to a subdocument.
+    YDoc(SubDoc),
 }
 
 impl Value {
@@ -405,8 +1057,9 @@ impl Value {
     /// - Primitive types ([Value::Any]) are passed right away, as no transformation is needed.
     /// - [Value::YArray] is converted into JSON-like array.
     /// - [Value::YMap] is converted into JSON-like object map.
-    /// - [Value::YText], [Value::YXmlText] and [Value::YXmlElement] are converted into strings
-    ///   (XML types are stringified XML representation).
+    /// - [Value::YText], [Value::YXmlText], [Value::YXmlElement] and [Value::YXmlFragment] are
+    ///   converted into strings (XML types are stringified XML representation).
+    /// - [Value::YDoc] is converted into a guid string of a referenced subdocument.
     pub fn to_json(self, txn: &Transaction) -> Any {
         match self {
             Value::Any(a) => a,
@@ -414,7 +1067,9 @@ impl Value {
             Value::YArray(v) => v.to_json(txn),
             Value::YMap(v) => v.to_json(txn),
             Value::YXmlElement(v) => Any::String(v.to_string(txn)),
+            Value::YXmlFragment(v) => Any::String(v.to_string(txn)),
             Value::YXmlText(v) => Any::String(v.to_string(txn)),
+            Value::YDoc(v) => Any::String(v.guid().to_string()),
         }
     }
 
@@ -426,11 +1081,100 @@ impl Value {
             Value::YArray(v) => v.to_json(txn).to_string(),
             Value::YMap(v) => v.to_json(txn).to_string(),
             Value::YXmlElement(v) => v.to_string(txn),
+            Value::YXmlFragment(v) => v.to_string(txn),
             Value::YXmlText(v) => v.to_string(txn),
+            Value::YDoc(v) => v.guid().to_string(),
+        }
+    }
+
+    /// Returns a human-readable name of a kind of data stored in current value. For primitive
+    /// values this is the same as [Any::kind], while shared types are described by their names
+    /// ie. `"YText"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Any(a) => a.kind(),
+            Value::YText(_) => "YText",
+            Value::YArray(_) => "YArray",
+            Value::YMap(_) => "YMap",
+            Value::YXmlElement(_) => "YXmlElement",
+            Value::YXmlFragment(_) => "YXmlFragment",
+            Value::YXmlText(_) => "YXmlText",
+            Value::YDoc(_) => "YDoc",
         }
     }
 }
 
+impl TryFrom<Value> for Any {
+    type Error = TypeMismatch;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Any(v) => Ok(v),
+            other => Err(TypeMismatch::new("any", other.kind())),
+        }
+    }
+}
+
+/// Returns an error reported by typed getters (like [Map::get_as]) when there's no value to
+/// convert. A requested type `T` is described the same way a failed conversion would describe
+/// it, while a missing value is described as `"undefined"`.
+pub(crate) fn missing_value<T>() -> TypeMismatch
+where
+    T: TryFrom<Value, Error = TypeMismatch>,
+{
+    match T::try_from(Value::Any(Any::Undefined)) {
+        Err(e) => e,
+        // only an [Any] itself accepts undefined values
+        Ok(_) => TypeMismatch::new("any", "undefined"),
+    }
+}
+
+/// Implements [TryFrom] conversion from a [Value] for a given type. Primitive types are
+/// converted using their conversion from an [Any] and described with the same `$expected` name
+/// as that conversion uses, while shared types are matched against the corresponding [Value]
+/// variant.
+macro_rules! impl_try_from_value {
+    ($t:ty, $expected:expr) => {
+        impl TryFrom<Value> for $t {
+            type Error = TypeMismatch;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::Any(v) => <$t>::try_from(v),
+                    other => Err(TypeMismatch::new($expected, other.kind())),
+                }
+            }
+        }
+    };
+    ($t:ty, $variant:ident, $expected:expr) => {
+        impl TryFrom<Value> for $t {
+            type Error = TypeMismatch;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    other => Err(TypeMismatch::new($expected, other.kind())),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_value!(bool, "bool");
+impl_try_from_value!(String, "string");
+impl_try_from_value!(f64, "f64");
+impl_try_from_value!(i64, "i64");
+impl_try_from_value!(Vec<u8>, "buffer");
+impl_try_from_value!(Vec<Any>, "array");
+impl_try_from_value!(HashMap<String, Any>, "map");
+impl_try_from_value!(Text, YText, "YText");
+impl_try_from_value!(Array, YArray, "YArray");
+impl_try_from_value!(Map, YMap, "YMap");
+impl_try_from_value!(XmlElement, YXmlElement, "YXmlElement");
+impl_try_from_value!(XmlFragment, YXmlFragment, "YXmlFragment");
+impl_try_from_value!(XmlText, YXmlText, "YXmlText");
+impl_try_from_value!(SubDoc, YDoc, "YDoc");
+
 impl<T> From<T> for Value
 where
     T: Into<Any>,
@@ -441,6 +1185,130 @@ where
     }
 }
 
+/// A preliminary value, which may mix primitive values with shared types nested at any depth, ie.
+/// a JSON-like object containing a [Text]. It's meant to be used by language bindings, which
+/// receive such values as dynamically typed trees.
+///
+/// Plain containers should be created via [PrelimValue::list] and [PrelimValue::object]. A plain
+/// container which has any shared type among its descendants becomes a shared container itself
+/// ([Array] or [Map] respectively), so that its shared descendants can be integrated. Otherwise
+/// it's stored as a regular [Any] value.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use yrs::{Doc, PrelimValue, Value};
+///
+/// let doc = Doc::new();
+/// let mut txn = doc.transact();
+/// let array = txn.get_array("array");
+///
+/// let mut entries = HashMap::new();
+/// entries.insert("title".to_string(), PrelimValue::Text("hello".to_string()));
+/// entries.insert("likes".to_string(), PrelimValue::from(1));
+/// array.push_back(&mut txn, PrelimValue::object(entries));
+///
+/// match array.get(&txn, 0) {
+///     Some(Value::YMap(map)) => match map.get(&txn, "title") {
+///         Some(Value::YText(title)) => title.push(&mut txn, " world"),
+///         other => panic!("expected text, found {:?}", other),
+///     },
+///     other => panic!("expected map, found {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrelimValue {
+    /// Primitive value, including plain lists and objects without shared types inside.
+    Any(Any),
+    /// Preliminary [Text] with a given initial content.
+    Text(String),
+    /// Preliminary [Array] with a given initial content.
+    Array(Vec<PrelimValue>),
+    /// Preliminary [Map] with a given initial content.
+    Map(HashMap<String, PrelimValue>),
+}
+
+impl PrelimValue {
+    /// Creates a plain list of values. It's a [PrelimValue::Any] array, unless any of the
+    /// `values` is a shared type, in which case it becomes a [PrelimValue::Array].
+    pub fn list(values: Vec<PrelimValue>) -> Self {
+        if values.iter().any(PrelimValue::is_shared) {
+            PrelimValue::Array(values)
+        } else {
+            let values = values.into_iter().map(PrelimValue::into_any).collect();
+            PrelimValue::Any(Any::Array(values))
+        }
+    }
+
+    /// Creates a plain object. It's a [PrelimValue::Any] map, unless any of the `entries` values
+    /// is a shared type, in which case it becomes a [PrelimValue::Map].
+    pub fn object(entries: HashMap<String, PrelimValue>) -> Self {
+        if entries.values().any(PrelimValue::is_shared) {
+            PrelimValue::Map(entries)
+        } else {
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| (key, value.into_any()))
+                .collect();
+            PrelimValue::Any(Any::Map(entries))
+        }
+    }
+
+    /// Checks if current value will be integrated as a shared type.
+    pub fn is_shared(&self) -> bool {
+        !matches!(self, PrelimValue::Any(_))
+    }
+
+    fn into_any(self) -> Any {
+        match self {
+            PrelimValue::Any(any) => any,
+            _ => panic!("Defect: shared prelim value cannot be converted into Any"),
+        }
+    }
+}
+
+impl<T> From<T> for PrelimValue
+where
+    T: Into<Any>,
+{
+    fn from(v: T) -> Self {
+        PrelimValue::Any(v.into())
+    }
+}
+
+impl Prelim for PrelimValue {
+    fn into_content(self, _txn: &mut Transaction, ptr: TypePtr) -> (ItemContent, Option<Self>) {
+        let type_ref = match &self {
+            PrelimValue::Any(any) => return (ItemContent::Any(vec![any.clone()]), None),
+            PrelimValue::Text(_) => TYPE_REFS_TEXT,
+            PrelimValue::Array(_) => TYPE_REFS_ARRAY,
+            PrelimValue::Map(_) => TYPE_REFS_MAP,
+        };
+        let inner = BranchRef::new(Branch::new(ptr, type_ref, None));
+        (ItemContent::Type(inner), Some(self))
+    }
+
+    fn integrate(self, txn: &mut Transaction, inner_ref: BranchRef) {
+        match self {
+            PrelimValue::Any(_) => {}
+            PrelimValue::Text(chunk) => Text::from(inner_ref).push(txn, &chunk),
+            PrelimValue::Array(values) => {
+                let array = Array::from(inner_ref);
+                for value in values {
+                    array.push_back(txn, value);
+                }
+            }
+            PrelimValue::Map(entries) => {
+                let map = Map::from(inner_ref);
+                for (key, value) in entries {
+                    map.insert(txn, key, value);
+                }
+            }
+        }
+    }
+}
+
 impl std::fmt::Display for Branch {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.type_ref() {
@@ -591,3 +1459,423 @@ impl std::fmt::Display for TypePtr {
         }
     }
 }
+
+/// Callbacks subscribed to changes made directly to a given branch node. A kind of an event
+/// depends on a type of a branch.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Observers {
+    Array(Observer<ArrayEvent>),
+    Map(Observer<MapEvent>),
+    Text(Observer<TextEvent>),
+    Xml(Observer<XmlEvent>),
+    XmlText(Observer<XmlTextEvent>),
+}
+
+/// An event produced by one of the shared types, that has been changed in scope of a transaction.
+/// A list of such events is passed to callbacks subscribed via `observe_deep` methods.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Changes made to [Array].
+    Array(ArrayEvent),
+    /// Changes made to [Map].
+    Map(MapEvent),
+    /// Changes made to [Text].
+    Text(TextEvent),
+    /// Changes made to either [XmlElement] or [XmlFragment].
+    Xml(XmlEvent),
+    /// Changes made to [XmlText].
+    XmlText(XmlTextEvent),
+}
+
+impl Event {
+    /// Returns a path from the type observed by a deep observer to a type that has been changed.
+    /// Indexes within the path reflect positions of the types at the end of a transaction.
+    pub fn path(&self) -> &Path {
+        match self {
+            Event::Array(e) => &e.path,
+            Event::Map(e) => &e.path,
+            Event::Text(e) => &e.path,
+            Event::Xml(e) => &e.path,
+            Event::XmlText(e) => &e.path,
+        }
+    }
+
+    /// Returns a shared type that has been changed.
+    pub fn target(&self) -> Value {
+        match self {
+            Event::Array(e) => Value::YArray(e.target.clone()),
+            Event::Map(e) => Value::YMap(e.target.clone()),
+            Event::Text(e) => Value::YText(e.target.clone()),
+            Event::Xml(e) => e.target.clone(),
+            Event::XmlText(e) => Value::YXmlText(e.target.clone()),
+        }
+    }
+
+    /// Creates a new event describing all of the changes made to a given `branch` in scope of
+    /// a transaction. `keys` is a list of changed map component keys (`None` is used if indexed
+    /// sequence component has changed). Returns `None` if branch type doesn't produce events.
+    pub(crate) fn new(
+        txn: &Transaction,
+        branch: &BranchRef,
+        keys: &HashSet<Option<String>>,
+    ) -> Option<Self> {
+        let type_ref = branch.borrow().type_ref();
+        match type_ref {
+            TYPE_REFS_ARRAY => Some(Event::Array(ArrayEvent::new(txn, branch.clone()))),
+            TYPE_REFS_MAP => Some(Event::Map(MapEvent::new(txn, branch.clone(), keys))),
+            TYPE_REFS_TEXT => Some(Event::Text(TextEvent::new(txn, branch.clone()))),
+            TYPE_REFS_XML_ELEMENT | TYPE_REFS_XML_FRAGMENT => {
+                Some(Event::Xml(XmlEvent::new(txn, branch.clone(), keys)))
+            }
+            TYPE_REFS_XML_TEXT => {
+                Some(Event::XmlText(XmlTextEvent::new(txn, branch.clone(), keys)))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_path(&mut self, path: Path) {
+        match self {
+            Event::Array(e) => e.path = path,
+            Event::Map(e) => e.path = path,
+            Event::Text(e) => e.path = path,
+            Event::Xml(e) => e.path = path,
+            Event::XmlText(e) => e.path = path,
+        }
+    }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        match self {
+            Event::Array(e) => e.target_ptr(),
+            Event::Map(e) => e.target_ptr(),
+            Event::Text(e) => e.target_ptr(),
+            Event::Xml(e) => e.target_ptr(),
+            Event::XmlText(e) => e.target_ptr(),
+        }
+    }
+}
+
+/// A path from an observed shared type to one of its successors. Segments are child indexes for
+/// indexed sequence types (ie. XML elements) and keys for map-like types.
+pub type Path = Vec<PathSegment>;
+
+/// A single segment of a [Path].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PathSegment {
+    /// Key under which a child type is stored within a map component of its parent.
+    Key(String),
+    /// Index at which a child type is stored within an indexed sequence of its parent.
+    Index(u32),
+}
+
+/// A change made to an indexed sequence component of a shared type (ie. elements of an [Array] or
+/// children of an XML node).
+/// A list of these changes forms a delta, that can be applied in order on the state of a sequence
+/// from before the transaction to get the current state of it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Elements inserted at current position.
+    Added(Vec<Value>),
+    /// A number of elements removed at current position.
+    Removed(u32),
+    /// A number of elements left unchanged, which current position should be moved over.
+    Retain(u32),
+}
+
+/// A change made to a single entry of a map component of a shared type (ie. attributes of an
+/// XML node).
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryChange {
+    /// New entry has been inserted.
+    Inserted(Value),
+    /// Existing entry has been updated from the old value (first) to the new one (second).
+    Updated(Value, Value),
+    /// Existing entry has been removed. Contains a value from before removal.
+    Removed(Value),
+}
+
+/// A change made to a text content (ie. [XmlText]). A list of these changes forms a delta, that
+/// can be applied in order on the state of a text from before the transaction to get the current
+/// state of it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delta {
+    /// Content (a string chunk, an embed or a shared type) inserted at current position, together
+    /// with formatting attributes applied to it.
+    Inserted(Value, Option<Box<Attrs>>),
+    /// A number of elements removed at current position.
+    Deleted(u32),
+    /// A number of elements left unchanged, which current position should be moved over.
+    /// If formatting of these elements has changed, attributes contain new formatting values
+    /// (with [Any::Null] for removed formatting).
+    Retain(u32, Option<Box<Attrs>>),
+}
+
+impl Change {
+    /// Converts current change into a JSON-like shape used by Yjs array event deltas, ie.
+    /// `{"insert": [1, 2]}`, `{"delete": 1}` or `{"retain": 3}`. Inserted shared types are
+    /// converted using [Value::to_json].
+    pub fn to_json(&self, txn: &Transaction) -> Any {
+        let mut op = HashMap::new();
+        match self {
+            Change::Added(values) => {
+                let values = values.iter().map(|v| v.clone().to_json(txn)).collect();
+                op.insert("insert".to_owned(), Any::Array(values));
+            }
+            Change::Removed(len) => {
+                op.insert("delete".to_owned(), Any::Number(*len as f64));
+            }
+            Change::Retain(len) => {
+                op.insert("retain".to_owned(), Any::Number(*len as f64));
+            }
+        }
+        Any::Map(op)
+    }
+
+    /// Parses a change from its JSON-like shape (see: [Change::to_json]), ie. received from
+    /// a Yjs peer. Inserted values are returned as [Value::Any].
+    pub fn from_json(json: &Any) -> Result<Self, Error> {
+        let op = json
+            .as_map()
+            .ok_or(Error::InvalidData("delta operation is not an object"))?;
+        if let Some(insert) = op.get("insert") {
+            let values = insert
+                .as_array()
+                .ok_or(Error::InvalidData("array delta insert is not an array"))?;
+            Ok(Change::Added(
+                values.iter().cloned().map(Value::Any).collect(),
+            ))
+        } else if op.contains_key("delete") {
+            Ok(Change::Removed(delta_len(op, "delete")?))
+        } else if op.contains_key("retain") {
+            Ok(Change::Retain(delta_len(op, "retain")?))
+        } else {
+            Err(Error::InvalidData("unknown delta operation"))
+        }
+    }
+}
+
+impl Delta {
+    /// Converts current change into a JSON-like shape used by Yjs text event deltas and Quill,
+    /// ie. `{"insert": "abc", "attributes": {"bold": true}}`, `{"delete": 1}` or
+    /// `{"retain": 3}`. Inserted shared types are converted using [Value::to_json].
+    pub fn to_json(&self, txn: &Transaction) -> Any {
+        let mut op = HashMap::new();
+        let attrs = match self {
+            Delta::Inserted(value, attrs) => {
+                op.insert("insert".to_owned(), value.clone().to_json(txn));
+                attrs
+            }
+            Delta::Deleted(len) => {
+                op.insert("delete".to_owned(), Any::Number(*len as f64));
+                &None
+            }
+            Delta::Retain(len, attrs) => {
+                op.insert("retain".to_owned(), Any::Number(*len as f64));
+                attrs
+            }
+        };
+        if let Some(attrs) = attrs {
+            op.insert("attributes".to_owned(), Any::Map((**attrs).clone()));
+        }
+        Any::Map(op)
+    }
+
+    /// Parses a change from its JSON-like shape (see: [Delta::to_json]), ie. received from
+    /// a Yjs peer or a Quill editor. Inserted values are returned as [Value::Any].
+    pub fn from_json(json: &Any) -> Result<Self, Error> {
+        let op = json
+            .as_map()
+            .ok_or(Error::InvalidData("delta operation is not an object"))?;
+        let attrs = match op.get("attributes") {
+            None | Some(Any::Null) => None,
+            Some(Any::Map(attrs)) => Some(Box::new(attrs.clone())),
+            Some(_) => return Err(Error::InvalidData("delta attributes are not an object")),
+        };
+        if let Some(insert) = op.get("insert") {
+            Ok(Delta::Inserted(Value::Any(insert.clone()), attrs))
+        } else if op.contains_key("delete") {
+            Ok(Delta::Deleted(delta_len(op, "delete")?))
+        } else if op.contains_key("retain") {
+            Ok(Delta::Retain(delta_len(op, "retain")?, attrs))
+        } else {
+            Err(Error::InvalidData("unknown delta operation"))
+        }
+    }
+}
+
+/// Reads a length of a `delete` or `retain` delta operation stored under a given `key`.
+fn delta_len(op: &HashMap<String, Any>, key: &str) -> Result<u32, Error> {
+    op.get(key)
+        .and_then(Any::as_i64)
+        .filter(|&len| len >= 0 && len <= u32::MAX as i64)
+        .map(|len| len as u32)
+        .ok_or(Error::InvalidData("delta length is not a valid number"))
+}
+
+/// Checks if a given `item` has been inserted in scope of a current transaction.
+pub(crate) fn adds(txn: &Transaction, item: &Item) -> bool {
+    item.id.clock >= txn.before_state.get(&item.id.client)
+}
+
+/// Checks if a given `item` has been deleted in scope of a current transaction.
+pub(crate) fn deletes(txn: &Transaction, item: &Item) -> bool {
+    txn.delete_set.is_deleted(&item.id)
+}
+
+/// Computes changes made to an indexed sequence component of a given `branch` in scope of
+/// a transaction.
+pub(crate) fn event_changes(txn: &Transaction, branch: &Branch) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut current = branch.start;
+    while let Some(item) = current.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+        if item.is_deleted() {
+            if deletes(txn, item) && !adds(txn, item) {
+                if let Some(Change::Removed(len)) = changes.last_mut() {
+                    *len += item.len();
+                } else {
+                    changes.push(Change::Removed(item.len()));
+                }
+            }
+        } else if adds(txn, item) {
+            let values = item.content.get_content(txn);
+            if let Some(Change::Added(added)) = changes.last_mut() {
+                added.extend(values);
+            } else {
+                changes.push(Change::Added(values));
+            }
+        } else if item.is_countable() {
+            if let Some(Change::Retain(len)) = changes.last_mut() {
+                *len += item.len();
+            } else {
+                changes.push(Change::Retain(item.len()));
+            }
+        }
+        current = item.right;
+    }
+    if let Some(Change::Retain(_)) = changes.last() {
+        changes.pop();
+    }
+    changes
+}
+
+/// Checks if a given `item` was present and not deleted before a current transaction.
+fn existed_before(txn: &Transaction, item: &Item) -> bool {
+    !adds(txn, item) && (!item.is_deleted() || deletes(txn, item))
+}
+
+/// Returns a JSON projection of a given `branch` content, as it was before a current transaction.
+fn json_before(txn: &Transaction, branch: &BranchRef) -> Any {
+    let inner = branch.borrow();
+    match inner.type_ref() {
+        TYPE_REFS_MAP => {
+            let mut result = HashMap::new();
+            for (key, ptr) in inner.map.iter() {
+                let mut current = txn.store.blocks.get_item(ptr);
+                // skip entries which replaced an existing one in scope of a current transaction
+                while let Some(item) = current.filter(|item| adds(txn, item)) {
+                    current = item.left.and_then(|ptr| txn.store.blocks.get_item(&ptr));
+                }
+                if let Some(item) = current.filter(|item| existed_before(txn, item)) {
+                    let value = match &item.content {
+                        ItemContent::Type(branch) => json_before(txn, branch),
+                        content => match content.get_content_last(txn) {
+                            Some(value) => value.to_json(txn),
+                            None => Any::Null,
+                        },
+                    };
+                    result.insert(key.clone(), value);
+                }
+            }
+            Any::Map(result)
+        }
+        TYPE_REFS_ARRAY => {
+            let mut result = Vec::new();
+            let mut current = inner.start;
+            while let Some(item) = current.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+                if item.is_countable() && existed_before(txn, item) {
+                    match &item.content {
+                        ItemContent::Type(branch) => result.push(json_before(txn, branch)),
+                        content => result.extend(
+                            content
+                                .get_content(txn)
+                                .into_iter()
+                                .map(|value| value.to_json(txn)),
+                        ),
+                    }
+                }
+                current = item.right;
+            }
+            Any::Array(result)
+        }
+        TYPE_REFS_TEXT => {
+            let mut result = String::new();
+            let mut current = inner.start;
+            while let Some(item) = current.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+                if let ItemContent::String(s) = &item.content {
+                    if existed_before(txn, item) {
+                        result.push_str(s);
+                    }
+                }
+                current = item.right;
+            }
+            Any::String(result)
+        }
+        // XML types are projected into their current string representation
+        _ => {
+            drop(inner);
+            branch.clone().into_value(txn).to_json(txn)
+        }
+    }
+}
+
+/// Computes changes made to entries of a map component of a given `branch` under given `keys`
+/// in scope of a transaction.
+pub(crate) fn event_keys(
+    txn: &Transaction,
+    branch: &Branch,
+    keys: &HashSet<Option<String>>,
+) -> HashMap<String, EntryChange> {
+    let mut result = HashMap::new();
+    for key in keys.iter().flatten() {
+        let item = match branch
+            .map
+            .get(key)
+            .and_then(|p| txn.store.blocks.get_item(p))
+        {
+            Some(item) => item,
+            None => continue,
+        };
+        let last_value = |item: &Item| {
+            item.content
+                .get_content_last(txn)
+                .unwrap_or(Value::Any(Any::Undefined))
+        };
+        // removed shared types may be tombstoned, so they're provided as a JSON projection
+        let prev_value = |item: &Item| match &item.content {
+            ItemContent::Type(branch) => Value::Any(json_before(txn, branch)),
+            _ => last_value(item),
+        };
+        let change = if adds(txn, item) {
+            let mut prev = item.left.and_then(|p| txn.store.blocks.get_item(&p));
+            while let Some(p) = prev {
+                if !adds(txn, p) {
+                    break;
+                }
+                prev = p.left.and_then(|p| txn.store.blocks.get_item(&p));
+            }
+            let prev = prev.filter(|p| deletes(txn, p));
+            match (deletes(txn, item), prev) {
+                (true, Some(prev)) => EntryChange::Removed(prev_value(prev)),
+                (true, None) => continue,
+                (false, Some(prev)) => EntryChange::Updated(prev_value(prev), last_value(item)),
+                (false, None) => EntryChange::Inserted(last_value(item)),
+            }
+        } else if deletes(txn, item) {
+            EntryChange::Removed(prev_value(item))
+        } else {
+            continue;
+        };
+        result.insert(key.clone(), change);
+    }
+    result
+}
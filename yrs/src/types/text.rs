@@ -1,36 +1,135 @@
-use crate::block::{BlockPtr, ItemContent};
+use crate::block::{BlockPtr, ItemContent, ItemPosition, Prelim};
+use crate::event::{Observer, Subscription};
 use crate::transaction::Transaction;
-use crate::types::{Branch, BranchRef};
+use crate::types::{
+    adds, deletes, range_end, Branch, BranchRef, Delta, Event, Observers, Path, SearchMarker,
+    TypePtr, Value,
+};
 use crate::*;
+use lib0::any::Any;
 use std::cell::Ref;
-use std::error::Error;
+use std::collections::HashMap;
 use std::fmt::Formatter;
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
+
+/// An iterator over chunks of a [Text] content. See: [Text::chunks].
+pub struct Chunks<'b, 'txn> {
+    ptr: Option<BlockPtr>,
+    txn: &'b Transaction<'txn>,
+}
+
+impl<'b, 'txn> Iterator for Chunks<'b, 'txn> {
+    type Item = &'b str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(ptr) = self.ptr.take() {
+            let item = self.txn.store.blocks.get_item(&ptr)?;
+            self.ptr = item.right;
+            if !item.is_deleted() {
+                if let ItemContent::String(chunk) = &item.content {
+                    return Some(chunk);
+                }
+            }
+        }
+        None
+    }
+}
 
 /// A shared data type used for collaborative text editing. It enables multiple users to add and
 /// remove chunks of text in efficient manner.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Text(BranchRef);
 
+impl_branch_identity!(Text);
+
 impl Text {
     /// Converts context of this text data structure into a single string value.
     #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self, txn: &Transaction<'_>) -> String {
-        let inner = self.0.as_ref();
-        let mut start = inner.start;
-        let mut s = String::new();
-        while let Some(a) = start.as_ref() {
-            if let Some(item) = txn.store.blocks.get_item(&a) {
-                if !item.is_deleted() {
-                    if let block::ItemContent::String(item_string) = &item.content {
-                        s.push_str(item_string);
-                    }
-                }
-                start = item.right.clone();
+        self.chunks(txn).collect()
+    }
+
+    /// Returns an iterator over chunks of a current text in document order, without copying or
+    /// concatenating them. Chunks correspond to blocks of a text, so their boundaries depend on
+    /// editing history. Deleted chunks, embedded values and formatting attributes are skipped.
+    pub fn chunks<'b, 'txn>(&self, txn: &'b Transaction<'txn>) -> Chunks<'b, 'txn> {
+        Chunks {
+            ptr: self.0.borrow().start,
+            txn,
+        }
+    }
+
+    /// Returns a cursor placed at the beginning of a current text. See: [TextCursor].
+    pub fn cursor(&self, txn: &Transaction) -> TextCursor {
+        let mut marker = None;
+        self.0.borrow().find_marker_from(txn, 0, &mut marker);
+        TextCursor {
+            text: self.clone(),
+            index: 0,
+            marker,
+        }
+    }
+
+    /// Writes contents of a current text into a given `writer` chunk by chunk (see:
+    /// [Text::chunks]), without allocating a contiguous string for them.
+    pub fn write_to<W: std::fmt::Write>(
+        &self,
+        txn: &Transaction,
+        mut writer: W,
+    ) -> std::fmt::Result {
+        for chunk in self.chunks(txn) {
+            writer.write_str(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a unique identifier of a character stored at a given `index` of current text or
+    /// `None` if `index` was outside of text's bounds. It can be resolved back using
+    /// [Transaction::get_item].
+    pub fn id_at(&self, txn: &Transaction, index: u32) -> Option<ID> {
+        self.0.borrow().id_at(txn, index)
+    }
+
+    /// Returns a [RelativePosition] pointing to a given `index` of current text, which stays
+    /// attached to the same place in text while concurrent changes are made. When `assoc` is
+    /// non-negative, position sticks to the character on its right side, otherwise it sticks to
+    /// the character on its left side.
+    pub fn relative_position(&self, txn: &Transaction, index: u32, assoc: i32) -> RelativePosition {
+        RelativePosition::from_type_index(txn, &self.0, index, assoc)
+    }
+
+    /// Resolves cursors of remote peers into `(anchor, head)` indexes within current text.
+    /// `states` are awareness states of peers keyed by their client ids, where every cursor is
+    /// stored under a `"cursor"` field as `{"anchor": <pos>, "head": <pos>}` with both positions
+    /// in JSON format (see: [RelativePosition::to_json]), just like Yjs editor bindings do.
+    ///
+    /// Peers without a cursor, with a malformed one, with a cursor in a different shared type or
+    /// with positions that could not be resolved yet are skipped.
+    pub fn cursor_positions_from_awareness(
+        &self,
+        txn: &Transaction,
+        states: &HashMap<u64, Any>,
+    ) -> HashMap<u64, (u32, u32)> {
+        let resolve = |json: Option<&Any>| {
+            let pos = RelativePosition::from_json(json?)?.resolve(txn)?;
+            if pos.branch.ptr_eq(&self.0) {
+                Some(pos.index)
             } else {
-                break;
+                None
+            }
+        };
+        let mut cursors = HashMap::new();
+        for (client, state) in states.iter() {
+            if let Some(cursor) = state.get("cursor") {
+                if let (Some(anchor), Some(head)) =
+                    (resolve(cursor.get("anchor")), resolve(cursor.get("head")))
+                {
+                    cursors.insert(*client, (anchor, head));
+                }
             }
         }
-        s
+        cursors
     }
 
     /// Returns a number of characters visible in a current text data structure.
@@ -38,23 +137,51 @@ impl Text {
         self.0.borrow().len()
     }
 
+    /// Returns a number of bytes of content stored in a current text, including embedded values
+    /// and formatting attributes. See: [Branch::content_size].
+    pub fn content_size(&self, txn: &Transaction) -> usize {
+        self.0.borrow().content_size(txn)
+    }
+
     pub(crate) fn inner(&self) -> Ref<Branch> {
         self.0.borrow()
     }
 
+    /// Returns a position right before an element at a given `count` index, splitting a block
+    /// if necessary. If a `cached` marker is given (see: [Branch::find_marker_from]), lookup
+    /// starts from it.
     pub(crate) fn find_position(
         &self,
         txn: &mut Transaction<'_>,
         mut count: u32,
-    ) -> Option<block::ItemPosition> {
+        cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) -> Option<ItemTextListPosition> {
+        self.0.debug_assert_owned_by(txn);
         let mut pos = {
             let inner = self.0.borrow();
-            block::ItemPosition {
+            let mut pos = ItemTextListPosition {
                 parent: inner.ptr.clone(),
                 left: None,
                 right: inner.start,
                 index: 0,
+                current_attrs: HashMap::new(),
+            };
+            if count > 0 {
+                // start from the block containing the element preceding the position
+                let marker = match cached {
+                    Some(cached) => inner.find_marker_from(txn, count - 1, cached),
+                    None => inner.find_marker(txn, count - 1),
+                };
+                if let Some(marker) = marker {
+                    let item = txn.store.blocks.get_item(&marker.ptr)?;
+                    pos.left = item.left;
+                    pos.right = Some(marker.ptr);
+                    pos.index = marker.index;
+                    pos.current_attrs = marker.attrs;
+                    count -= marker.index;
+                }
             }
+            pos
         };
 
         while let Some(right_ptr) = pos.right.as_ref() {
@@ -62,44 +189,70 @@ impl Text {
                 break;
             }
 
-            if let Some(mut right) = txn.store.blocks.get_item(right_ptr) {
-                if !right.is_deleted() {
-                    let mut right_len = right.len();
-                    if count < right_len {
-                        // split right item
-                        let split_ptr = BlockPtr::new(
-                            ID::new(right.id.client, right.id.clock + count),
-                            right_ptr.pivot() as u32,
-                        );
-                        let (_, _) = txn.store.blocks.split_block(&split_ptr);
-                        right = txn.store.blocks.get_item(right_ptr).unwrap();
-                        right_len = right.len();
-                    }
-                    pos.index += right_len;
-                    count -= right_len;
+            let right = txn.store.blocks.get_item(right_ptr)?;
+            if !right.is_deleted() && right.is_countable() {
+                if count < right.len() {
+                    // split right item
+                    let split_ptr = BlockPtr::new(
+                        ID::new(right.id.client, right.id.clock + count),
+                        right_ptr.pivot() as u32,
+                    );
+                    let (_, _) = txn.store.blocks.split_block(&split_ptr);
                 }
-                pos.left = pos.right.take();
-                pos.right = right.right.clone();
-            } else {
-                return None;
+                let right = txn.store.blocks.get_item(right_ptr)?;
+                count -= right.len();
             }
+            pos.forward(txn);
+        }
+
+        if count == 0 {
+            Some(pos)
+        } else {
+            None
         }
+    }
 
-        Some(pos)
+    /// Inserts a `chunk` of text at a given relative position `pos`, resolved at the moment of
+    /// insertion, so that `chunk` lands next to the character `pos` is attached to - even if
+    /// text around it has been changed concurrently since `pos` was created.
+    ///
+    /// Returns an error if `pos` refers to another type, cannot be resolved yet or if the
+    /// character it is attached to has been deleted.
+    pub fn insert_at(
+        &self,
+        txn: &mut Transaction,
+        pos: &RelativePosition,
+        chunk: &str,
+    ) -> Result<(), PositionError> {
+        let index = pos.resolve_in(txn, &self.0)?;
+        self.insert(txn, index, chunk);
+        Ok(())
     }
 
-    /// Inserts a `chunk` of text at a given `index`.
+    /// Inserts a `chunk` of text at a given `index`. Inserted text inherits formatting attributes
+    /// of the text preceding it.
     /// If `index` is `0`, this `chunk` will be inserted at the beginning of a current text.
     /// If `index` is equal to current data structure length, this `chunk` will be appended at
     /// the end of it.
     /// This method will panic if provided `index` is greater than the length of a current text.
-    pub fn insert(&self, tr: &mut Transaction, index: u32, chunk: &str) {
-        if let Some(pos) = self.find_position(tr, index) {
-            let value = crate::block::PrelimText(chunk.to_owned());
-            tr.create_item(&pos, value, None);
-        } else {
-            panic!("The type or the position doesn't exist!");
-        }
+    pub fn insert(&self, txn: &mut Transaction, index: u32, chunk: &str) {
+        self.insert_content(
+            txn,
+            index,
+            ItemContent::String(chunk.to_owned()),
+            None,
+            None,
+        )
+    }
+
+    /// Works like [Text::insert], but if inserting a `chunk` could make a document exceed its
+    /// [Options::max_content_size](crate::Options::max_content_size), an [Error::QuotaExceeded]
+    /// is returned and text is left unchanged.
+    pub fn try_insert(&self, txn: &mut Transaction, index: u32, chunk: &str) -> Result<(), Error> {
+        // inserted chunk inherits formatting, so it's the only content added
+        txn.check_content_quota(chunk.len())?;
+        self.insert(txn, index, chunk);
+        Ok(())
     }
 
     /// Appends a given `chunk` of text at the end of a current text structure.
@@ -108,63 +261,726 @@ impl Text {
         self.insert(txn, idx, chunk)
     }
 
+    /// Inserts a `chunk` of text at a given `index`, formatted with given `attributes`. Attributes
+    /// with [Any::Null] value are used to explicitly remove formatting. Formatting attributes of
+    /// the preceding text, which are not present in `attributes`, are not applied to the inserted
+    /// chunk.
+    ///
+    /// This method will panic if provided `index` is greater than the length of a current text or
+    /// if a formatted range doesn't fit into `u32` bounds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lib0::any::Any;
+    /// use std::collections::HashMap;
+    /// use yrs::{Diff, Doc, Value};
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let text = txn.get_text("text");
+    /// text.insert(&mut txn, 0, "hello ");
+    ///
+    /// let mut bold = HashMap::new();
+    /// bold.insert("bold".to_string(), Any::Bool(true));
+    /// text.insert_with_attributes(&mut txn, 6, "world", bold.clone());
+    ///
+    /// assert_eq!(text.diff(&txn), vec![
+    ///     Diff::new(Value::Any(Any::String("hello ".into())), None),
+    ///     Diff::new(Value::Any(Any::String("world".into())), Some(Box::new(bold))),
+    /// ]);
+    /// ```
+    pub fn insert_with_attributes(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        chunk: &str,
+        attributes: Attrs,
+    ) {
+        let attrs = encode_attrs(attributes);
+        self.insert_content(
+            txn,
+            index,
+            ItemContent::String(chunk.to_owned()),
+            Some(attrs),
+            None,
+        )
+    }
+
+    /// Inserts an embedded `content` (eg. an image or a mention) at a given `index`. Embedded value
+    /// counts as a single element of a text and it's not a part of a string returned by
+    /// [Text::to_string], but it's returned by [Text::diff]. Embed can be optionally formatted with
+    /// given `attributes`.
+    ///
+    /// This method will panic if provided `index` is greater than the length of a current text.
+    pub fn insert_embed(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        content: Any,
+        attributes: Option<Attrs>,
+    ) {
+        let attrs = encode_attrs(attributes.unwrap_or_default());
+        let content = ItemContent::Embed(content.to_json_string());
+        self.insert_content(txn, index, content, Some(attrs), None)
+    }
+
+    /// Inserts a `content` at a given `index`, formatted with given `attributes` or with
+    /// attributes of the text preceding it if `attributes` are `None`.
+    fn insert_content(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        content: ItemContent,
+        attributes: Option<HashMap<String, String>>,
+        mut cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) {
+        if let Some(mut pos) = self.find_position(txn, index, cached.as_deref_mut()) {
+            let attrs = attributes.unwrap_or_else(|| pos.current_attrs.clone());
+            let (markers, len) = {
+                let inner = self.0.borrow();
+                (inner.search_markers.take(), inner.len())
+            };
+            pos.insert_content(txn, content, attrs);
+            let inner = self.0.borrow();
+            let inserted = inner.len() - len;
+            inner
+                .search_markers
+                .restore_inserted(markers, index, inserted);
+            if let Some(cached) = cached {
+                inner.retain_marker(cached, index);
+            }
+        } else {
+            panic!("The type or the position doesn't exist!");
+        }
+    }
+
+    /// Applies formatting `attributes` over a range of `len` elements of a current text, starting
+    /// at given `index`. Attributes with [Any::Null] value remove corresponding formatting from
+    /// a given range.
+    ///
+    /// This method will panic if provided `index` is greater than the length of a current text.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use lib0::any::Any;
+    /// use std::collections::HashMap;
+    /// use yrs::{Diff, Doc, Value};
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let text = txn.get_text("text");
+    /// text.insert(&mut txn, 0, "hello world");
+    ///
+    /// let mut italic = HashMap::new();
+    /// italic.insert("italic".to_string(), Any::Bool(true));
+    /// text.format(&mut txn, 0, 5, italic.clone());
+    ///
+    /// assert_eq!(text.diff(&txn), vec![
+    ///     Diff::new(Value::Any(Any::String("hello".into())), Some(Box::new(italic))),
+    ///     Diff::new(Value::Any(Any::String(" world".into())), None),
+    /// ]);
+    /// ```
+    pub fn format(&self, txn: &mut Transaction, index: u32, len: u32, attributes: Attrs) {
+        range_end(index, len);
+        if let Some(mut pos) = self.find_position(txn, index, None) {
+            pos.format(txn, len, encode_attrs(attributes));
+        } else {
+            panic!("The type or the position doesn't exist!");
+        }
+    }
+
+    /// Returns a content of a current text as a list of chunks, each one with the same set of
+    /// formatting attributes applied. Text chunks are returned as [Any::String] values, embeds
+    /// as values they were inserted with and nested shared types (if any) as corresponding
+    /// [Value] variants.
+    pub fn diff(&self, txn: &Transaction) -> Vec<Diff> {
+        let mut ops = Vec::new();
+        let mut buf = String::new();
+        let mut attrs = HashMap::new();
+        let mut current = self.0.borrow().start;
+        while let Some(item) = current.and_then(|p| txn.store.blocks.get_item(&p)) {
+            if !item.is_deleted() {
+                match &item.content {
+                    ItemContent::String(s) => buf.push_str(s),
+                    ItemContent::Embed(json) => {
+                        Diff::pack_str(&mut ops, &mut buf, &attrs);
+                        let value = decode_json(json);
+                        ops.push(Diff::new(Value::Any(value), decode_attrs(&attrs)));
+                    }
+                    ItemContent::Type(inner) => {
+                        Diff::pack_str(&mut ops, &mut buf, &attrs);
+                        let value = inner.clone().into_value(txn);
+                        ops.push(Diff::new(value, decode_attrs(&attrs)));
+                    }
+                    ItemContent::Format(key, value) => {
+                        Diff::pack_str(&mut ops, &mut buf, &attrs);
+                        update_current_attributes(&mut attrs, key, value);
+                    }
+                    _ => {}
+                }
+            }
+            current = item.right;
+        }
+        Diff::pack_str(&mut ops, &mut buf, &attrs);
+        ops
+    }
+
+    /// Removes redundant formatting attribute markers accumulated over time, ie. when the same
+    /// range has been formatted concurrently by many peers or formatting has been applied and
+    /// removed repeatedly. Returns a number of removed markers. A rendered content of a text, as
+    /// returned by [Text::diff], stays the same.
+    ///
+    /// Between every two consecutive elements of a text, only the last marker of every attribute
+    /// is kept, and only if it changes a value of that attribute. This includes markers setting
+    /// attributes to `null`, when they were not set in the first place.
+    ///
+    /// Markers are removed the same way as any other content, so all replicas converge once they
+    /// receive this change. However, content inserted concurrently between removed markers may end
+    /// up formatted differently than its author has seen it. The same trade-off is made by Yjs
+    /// `cleanupYTextFormatting`.
+    pub fn normalize_formatting(&self, txn: &mut Transaction) -> u32 {
+        let mut removed = 0;
+        // attributes applied before a current gap between two consecutive elements
+        let mut attrs = HashMap::new();
+        let mut gap = Vec::new();
+        let mut current = self.0.borrow().start;
+        while let Some(ptr) = current {
+            let item = match txn.store.blocks.get_item(&ptr) {
+                Some(item) => item,
+                None => break,
+            };
+            current = item.right;
+            if item.is_deleted() {
+                continue;
+            }
+            match &item.content {
+                ItemContent::Format(key, value) => gap.push((ptr, key.clone(), value.clone())),
+                _ if item.is_countable() => {
+                    removed += Self::normalize_gap(txn, &mut attrs, std::mem::take(&mut gap))
+                }
+                _ => {}
+            }
+        }
+        removed += Self::normalize_gap(txn, &mut attrs, gap);
+        removed
+    }
+
+    /// Removes formatting markers of a single gap between two consecutive text elements, which
+    /// are either overridden by the following markers of the same attribute or don't change
+    /// a value of their attribute. Updates `attrs` to the attributes applied after the gap.
+    fn normalize_gap(
+        txn: &mut Transaction,
+        attrs: &mut HashMap<String, String>,
+        gap: Vec<(BlockPtr, String, String)>,
+    ) -> u32 {
+        let mut removed = 0;
+        let last: HashMap<&str, usize> = gap
+            .iter()
+            .enumerate()
+            .map(|(i, (_, key, _))| (key.as_str(), i))
+            .collect();
+        for (i, (ptr, key, value)) in gap.iter().enumerate() {
+            let before = attrs.get(key).map(String::as_str).unwrap_or(JSON_NULL);
+            if last[key.as_str()] != i || value == before {
+                txn.delete(ptr);
+                removed += 1;
+            }
+        }
+        for (&key, &i) in last.iter() {
+            update_current_attributes(attrs, key, &gap[i].2);
+        }
+        removed
+    }
+
+    /// Removes entire content of a current text structure, including its formatting attributes
+    /// and embedded values. Clearing an empty text has no effect.
+    pub fn clear(&self, txn: &mut Transaction) {
+        self.0.clear(txn)
+    }
+
+    /// Replaces entire content of a current text structure with a given `chunk`. Both removal and
+    /// insertion happen in scope of the same transaction, so that observers receive them as
+    /// a single delta.
+    ///
+    /// When the same text is replaced concurrently by different peers, chunks inserted by all of
+    /// them will be present after synchronization, just like with any other concurrent insertions.
+    pub fn replace_all(&self, txn: &mut Transaction, chunk: &str) {
+        self.clear(txn);
+        if !chunk.is_empty() {
+            self.insert(txn, 0, chunk);
+        }
+    }
+
+    /// Applies a given `delta` (ie. one received from a text event of another replica or parsed
+    /// with [Delta::from_json]) at the beginning of a current text. Inserted strings are formatted
+    /// only with attributes provided together with them, while other inserted values are treated
+    /// as embeds. Inserted shared types are embedded as their JSON representation.
+    ///
+    /// This method panics if `delta` retains or deletes more elements than a text contains.
+    pub fn apply_delta(&self, txn: &mut Transaction, delta: &[Delta]) {
+        let mut index = 0;
+        for op in delta {
+            match op {
+                Delta::Inserted(value, attrs) => {
+                    let attrs = attrs.as_ref().map(|a| (**a).clone()).unwrap_or_default();
+                    match value.clone().to_json(txn) {
+                        Any::String(chunk) => {
+                            self.insert_with_attributes(txn, index, &chunk, attrs);
+                            index += chunk.len() as u32;
+                        }
+                        embed => {
+                            self.insert_embed(txn, index, embed, Some(attrs));
+                            index += 1;
+                        }
+                    }
+                }
+                Delta::Deleted(len) => self.remove_range(txn, index, *len),
+                Delta::Retain(len, attrs) => {
+                    if let Some(attrs) = attrs {
+                        self.format(txn, index, *len, (**attrs).clone());
+                    }
+                    index += len;
+                }
+            }
+        }
+    }
+
     /// Removes up to a `len` characters from a current text structure, starting at given `index`.
     /// This method panics in case when not all expected characters were removed (due to
     /// insufficient number of characters to remove) or `index` is outside of the bounds of text.
+    /// It also panics without modifying a text if a removed range doesn't fit into `u32` bounds.
     pub fn remove_range(&self, txn: &mut Transaction, index: u32, len: u32) {
+        self.remove_range_from(txn, index, len, None)
+    }
+
+    /// Works like [Text::remove_range], but if a `cached` marker is given (see:
+    /// [Branch::find_marker_from]), a position at a given `index` is looked up starting from it
+    /// and it's kept valid afterwards.
+    fn remove_range_from(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        len: u32,
+        mut cached: Option<&mut Option<(u64, SearchMarker)>>,
+    ) {
+        range_end(index, len);
         let mut remaining = len;
-        if let Some(pos) = self.find_position(txn, index) {
-            let mut current = {
-                let block = pos
-                    .left
-                    .as_ref()
-                    .and_then(|ptr| txn.store.blocks.get_block(ptr));
-                match block {
-                    Some(block) => block
-                        .as_item()
-                        .and_then(|item| item.right.as_ref())
-                        .and_then(|ptr| txn.store.blocks.get_item(ptr)),
-                    None => {
-                        let t = txn.store.get_type(&pos.parent).unwrap();
-                        let inner = t.borrow();
-                        let ptr = inner.start.as_ref();
-                        let item = ptr.and_then(|ptr| txn.store.blocks.get_item(ptr));
-                        item
-                    }
-                }
-            };
-            while let Some(mut item) = current {
+        if let Some(pos) = self.find_position(txn, index, cached.as_deref_mut()) {
+            let markers = self.0.borrow().search_markers.take();
+            let mut current = pos.right;
+            while let Some(ptr) = current {
                 if remaining == 0 {
                     break;
                 }
-                if !item.is_deleted() {
+                let item = match txn.store.blocks.get_item(&ptr) {
+                    Some(item) => item,
+                    None => break,
+                };
+                if !item.is_deleted() && item.is_countable() {
                     if remaining < item.len() {
                         // split item
-                        let mut split_ptr = BlockPtr::from(item.id);
+                        let mut split_ptr = ptr.clone();
                         split_ptr.id.clock += remaining;
-                        let (left, _) = txn.store.blocks.split_block(&split_ptr);
-                        item = txn
-                            .store
-                            .blocks
-                            .get_block(left.as_ref().unwrap())
-                            .unwrap()
-                            .as_item()
-                            .unwrap();
+                        txn.store.blocks.split_block(&split_ptr);
                     }
-
+                    let item = txn.store.blocks.get_item(&ptr).unwrap();
                     remaining -= item.len();
-                    item.mark_as_deleted();
+                    current = item.right;
+                    txn.delete(&ptr);
+                } else {
+                    current = item.right;
                 }
-                current = item
-                    .right
-                    .as_ref()
-                    .and_then(|ptr| txn.store.blocks.get_block(ptr))
-                    .and_then(|block| block.as_item());
+            }
+            let removed = len - remaining;
+            let inner = self.0.borrow();
+            inner
+                .search_markers
+                .restore_removed(txn, markers, index, removed);
+            if let Some(cached) = cached {
+                inner.retain_marker(cached, index);
             }
         } else {
-            panic!("Failed to remove characters starting at index {}. Index outside of the bounds of a text.", len);
+            panic!("Failed to remove characters starting at index {}. Index outside of the bounds of a text.", index);
+        }
+    }
+
+    /// Subscribes a given callback to be called whenever a content or formatting of this text is
+    /// changed. Callback is called once per transaction, with a [TextEvent] describing all changes
+    /// made in scope of that transaction as a delta. Returns a subscription, which will unsubscribe
+    /// callback when dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use yrs::{Doc, Delta};
+    ///
+    /// let doc = Doc::new();
+    /// let text = doc.transact().get_text("text");
+    /// let deltas = Rc::new(RefCell::new(Vec::new()));
+    /// let d = deltas.clone();
+    /// let _sub = text.observe(move |_, e| d.borrow_mut().extend(e.delta.iter().cloned()));
+    ///
+    /// text.insert(&mut doc.transact(), 0, "hello");
+    /// assert!(matches!(deltas.borrow()[0], Delta::Inserted(_, None)));
+    /// ```
+    pub fn observe<F>(&self, f: F) -> Subscription<TextEvent>
+    where
+        F: Fn(&Transaction, &TextEvent) -> () + 'static,
+    {
+        let mut inner = self.0.borrow_mut();
+        let observers = inner
+            .observers
+            .get_or_insert_with(|| Observers::Text(Observer::new()));
+        match observers {
+            Observers::Text(observer) => observer.subscribe(f),
+            _ => panic!("Defect: text has observers of unexpected type"),
         }
     }
+
+    /// Subscribes a given callback to be called whenever a current text or any of shared types
+    /// embedded in it is changed. Callback is called once per transaction, with a list of events
+    /// produced by all changed types, ordered by their depth.
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.borrow_mut().observe_deep(f)
+    }
+
+    pub(crate) fn branch(&self) -> &BranchRef {
+        &self.0
+    }
+}
+
+/// A cursor over a [Text], which keeps track of a position within it, so that a sequence of
+/// reads and edits made one after another doesn't need to look that position up from
+/// the beginning of a text every time. A cursor remembers a block it has visited last and as long
+/// as no characters have been inserted or removed since then by other means than the cursor
+/// itself, following reads and edits continue from there. Otherwise it falls back to search
+/// markers shared with indexed text operations.
+///
+/// A cursor doesn't borrow a transaction, which is passed to each operation instead, so it can be
+/// used across many transactions. Its position is an index, which is not adjusted for changes
+/// made by other means than a cursor itself, including remote updates. Like all other text
+/// indexes, it's a UTF-8 byte offset and operations panic when it doesn't fall on a char
+/// boundary.
+#[derive(Debug, Clone)]
+pub struct TextCursor {
+    text: Text,
+    index: u32,
+    /// Marker found by the last operation, together with a generation of branch search markers
+    /// at the moment it was found.
+    marker: Option<(u64, SearchMarker)>,
+}
+
+impl TextCursor {
+    /// Returns a current position of a cursor.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Moves a cursor to a given `index`. This method panics if `index` is greater than the
+    /// length of a text.
+    pub fn seek(&mut self, index: u32) {
+        let len = self.text.len();
+        if index > len {
+            panic!("Cannot seek to index {} of a text of length {}", index, len);
+        }
+        self.index = index;
+    }
+
+    /// Moves a cursor forward by up to `n` bytes, stopping at the end of a text. Returns
+    /// a number of bytes it has been moved by.
+    pub fn forward(&mut self, n: u32) -> u32 {
+        let n = n.min(self.remaining());
+        self.index += n;
+        n
+    }
+
+    /// Reads up to `n` bytes following a cursor position and moves a cursor past them. Embedded
+    /// values are counted, but skipped in returned string, just like in [Text::to_string].
+    pub fn read(&mut self, txn: &Transaction, n: u32) -> String {
+        let mut result = String::new();
+        let inner = self.text.0.borrow();
+        let marker = match inner.find_marker_from(txn, self.index, &mut self.marker) {
+            Some(marker) => marker,
+            None => return result,
+        };
+        let mut offset = self.index - marker.index;
+        let mut remaining = n;
+        let mut ptr = Some(marker.ptr);
+        while remaining > 0 {
+            let item = match ptr.and_then(|p| txn.store.blocks.get_item(&p)) {
+                Some(item) => item,
+                None => break,
+            };
+            if !item.is_deleted() && item.is_countable() {
+                let len = (item.len() - offset).min(remaining);
+                if let ItemContent::String(chunk) = &item.content {
+                    let start = offset as usize;
+                    result.push_str(&chunk[start..start + len as usize]);
+                }
+                remaining -= len;
+                offset = 0;
+            }
+            ptr = item.right;
+        }
+        self.index += n - remaining;
+        result
+    }
+
+    /// Inserts a `chunk` of text at a cursor position and moves a cursor past it. Inserted text
+    /// is formatted the same way as [Text::insert] would do it.
+    pub fn insert(&mut self, txn: &mut Transaction, chunk: &str) {
+        if !chunk.is_empty() {
+            let content = ItemContent::String(chunk.to_owned());
+            self.text
+                .insert_content(txn, self.index, content, None, Some(&mut self.marker));
+            self.index += chunk.len() as u32;
+        }
+    }
+
+    /// Removes up to `n` bytes following a cursor position. Cursor position doesn't change.
+    /// Returns a number of removed bytes.
+    pub fn delete(&mut self, txn: &mut Transaction, n: u32) -> u32 {
+        let n = n.min(self.remaining());
+        if n > 0 {
+            self.text
+                .remove_range_from(txn, self.index, n, Some(&mut self.marker));
+        }
+        n
+    }
+
+    /// Returns a number of bytes following a cursor position.
+    fn remaining(&self) -> u32 {
+        self.text.len().saturating_sub(self.index)
+    }
+}
+
+/// Placeholder used in place of every element of non-string content (ie. embedded values) while
+/// computing text boundaries. It's a single byte control character, so it keeps byte offsets in
+/// line with text indexes and always separates both grapheme clusters and words around it.
+#[cfg(feature = "unicode")]
+const EMBED_PLACEHOLDER: &str = "\u{1A}";
+
+/// Unicode-aware editing helpers. Boundaries are computed over text chunks (see: [Text::chunks])
+/// in place, so grapheme clusters and words spanning across several blocks are handled without
+/// copying an entire text. Like all other text indexes, the ones used here are UTF-8 byte offsets.
+#[cfg(feature = "unicode")]
+impl Text {
+    /// Removes up to `n` extended grapheme clusters (user-perceived characters, ie. flag emojis
+    /// or letters followed by combining accents) starting at a given `index`. Returns a number
+    /// of removed bytes, which is lower than `n` clusters would take only if the text ends first.
+    ///
+    /// This method panics if `index` is outside of text's bounds or is not a char boundary.
+    pub fn remove_graphemes(&self, txn: &mut Transaction, index: u32, n: u32) -> u32 {
+        let end = {
+            let segments = self.segments(txn);
+            let total = segments.last().map(|(s, c)| s + c.len()).unwrap_or(0);
+            if index as usize > total {
+                panic!("index {} is outside of text bounds ({})", index, total);
+            }
+            next_grapheme_boundary(&segments, total, index as usize, n) as u32
+        };
+        let len = end - index;
+        if len > 0 {
+            self.remove_range(txn, index, len);
+        }
+        len
+    }
+
+    /// Returns an index at which the word preceding a given `index` begins. If `index` is placed
+    /// inside of a word, its beginning is returned. Whitespace and punctuation are skipped, while
+    /// word boundaries are determined by Unicode word segmentation rules (UAX #29). Returns `0`
+    /// if there are no words before `index`.
+    ///
+    /// Together with [Text::remove_range] it can be used to implement *backspace-word* editor
+    /// operations.
+    pub fn prev_word_boundary(&self, txn: &Transaction, index: u32) -> u32 {
+        let segments = self.segments(txn);
+        if segments.is_empty() {
+            return 0;
+        }
+        let index = index as usize;
+        let mut lo = segment_index(&segments, index.saturating_sub(1));
+        let hi = (segment_index(&segments, index) + 1).min(segments.len() - 1);
+        let mut step = 1;
+        loop {
+            let start = segments[lo].0;
+            let window: String = segments[lo..=hi].iter().map(|(_, c)| *c).collect();
+            let found = window
+                .split_word_bound_indices()
+                .take_while(|(i, _)| start + i < index)
+                .filter(|(_, word)| is_word(word))
+                .last();
+            match found {
+                // a word starting at the window edge may continue in a preceding chunk
+                Some((i, _)) if i > 0 || lo == 0 => return (start + i) as u32,
+                None if lo == 0 => return 0,
+                _ => {
+                    lo = lo.saturating_sub(step);
+                    step *= 2;
+                }
+            }
+        }
+    }
+
+    /// Returns an index at which the word following a given `index` ends. If `index` is placed
+    /// inside of a word, its end is returned. Whitespace and punctuation are skipped, while word
+    /// boundaries are determined by Unicode word segmentation rules (UAX #29). Returns a length
+    /// of the text if there are no words after `index`.
+    ///
+    /// Together with [Text::remove_range] it can be used to implement *delete-word* editor
+    /// operations.
+    pub fn next_word_boundary(&self, txn: &Transaction, index: u32) -> u32 {
+        let segments = self.segments(txn);
+        let last = match segments.len() {
+            0 => return 0,
+            len => len - 1,
+        };
+        let index = index as usize;
+        let lo = segment_index(&segments, index.saturating_sub(1));
+        let mut hi = (segment_index(&segments, index) + 1).min(last);
+        let mut step = 1;
+        loop {
+            let start = segments[lo].0;
+            let window: String = segments[lo..=hi].iter().map(|(_, c)| *c).collect();
+            let found = window
+                .split_word_bound_indices()
+                .map(|(i, word)| (i + word.len(), word))
+                .find(|(end, word)| start + end > index && is_word(word));
+            match found {
+                // a word ending at the window edge may continue in a following chunk
+                Some((end, _)) if end < window.len() || hi == last => return (start + end) as u32,
+                None if hi == last => return (start + window.len()) as u32,
+                _ => {
+                    hi = (hi + step).min(last);
+                    step *= 2;
+                }
+            }
+        }
+    }
+
+    /// Returns visible content of a current text as a list of `(offset, chunk)` pairs. Unlike
+    /// [Text::chunks], embedded values are included as [EMBED_PLACEHOLDER]s, so that offsets
+    /// match text indexes.
+    fn segments<'b>(&self, txn: &'b Transaction) -> Vec<(usize, &'b str)> {
+        let mut segments = Vec::new();
+        let mut offset = 0;
+        let mut current = self.0.borrow().start;
+        while let Some(ptr) = current {
+            let item = match txn.store.blocks.get_item(&ptr) {
+                Some(item) => item,
+                None => break,
+            };
+            current = item.right;
+            if item.is_deleted() || !item.is_countable() {
+                continue;
+            }
+            if let ItemContent::String(chunk) = &item.content {
+                segments.push((offset, chunk.as_str()));
+                offset += chunk.len();
+            } else {
+                for _ in 0..item.len() {
+                    segments.push((offset, EMBED_PLACEHOLDER));
+                    offset += EMBED_PLACEHOLDER.len();
+                }
+            }
+        }
+        segments
+    }
+}
+
+/// Returns a position of a segment containing a given `offset`. Offsets at the end of the text
+/// resolve to the last segment.
+#[cfg(feature = "unicode")]
+fn segment_index(segments: &[(usize, &str)], offset: usize) -> usize {
+    match segments.binary_search_by(|(start, _)| start.cmp(&offset)) {
+        Ok(i) => i,
+        Err(i) => i.saturating_sub(1),
+    }
+}
+
+#[cfg(feature = "unicode")]
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+/// Returns an offset placed `n` grapheme cluster boundaries after a given `offset` or a total
+/// length of the text, if it ends before that.
+#[cfg(feature = "unicode")]
+fn next_grapheme_boundary(
+    segments: &[(usize, &str)],
+    total: usize,
+    offset: usize,
+    n: u32,
+) -> usize {
+    use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+    let mut current = offset;
+    if segments.is_empty() {
+        return current;
+    }
+    let mut cursor = GraphemeCursor::new(offset, total, true);
+    for _ in 0..n {
+        loop {
+            let (start, chunk) = segments[segment_index(segments, cursor.cur_cursor())];
+            match cursor.next_boundary(chunk, start) {
+                Ok(Some(boundary)) => {
+                    current = boundary;
+                    break;
+                }
+                Ok(None) => return current,
+                // cursor reached the end of a chunk, continue with a following one
+                Err(GraphemeIncomplete::NextChunk) => {}
+                Err(GraphemeIncomplete::PreContext(ctx)) => {
+                    let (s, c) = segments[segment_index(segments, ctx - 1)];
+                    cursor.provide_context(&c[..ctx - s], s);
+                }
+                Err(e) => panic!("failed to find grapheme boundary: {:?}", e),
+            }
+        }
+    }
+    current
+}
+
+/// An event produced by [Text] when its content or formatting has been changed in scope of
+/// a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEvent {
+    /// Text that has been changed.
+    pub target: Text,
+    /// Path from an observed type to the changed text. It's empty unless event has been passed to
+    /// a deep observer of one of the changed text's parents.
+    pub path: Path,
+    /// Changes made to a text content, including formatting attributes of inserted and retained
+    /// chunks.
+    pub delta: Vec<Delta>,
+}
+
+impl TextEvent {
+    pub(crate) fn new(txn: &Transaction, branch: BranchRef) -> Self {
+        let delta = event_delta(txn, &*branch.borrow());
+        TextEvent {
+            target: Text::from(branch),
+            path: Path::new(),
+            delta,
+        }
+    }
+
+    /// Returns a [TextEvent::delta] converted into a JSON-like array of the same shape as the one
+    /// emitted by Yjs text events. See: [Delta::to_json].
+    pub fn delta_json(&self, txn: &Transaction) -> Any {
+        Any::Array(self.delta.iter().map(|d| d.to_json(txn)).collect())
+    }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        self.target.0.borrow().ptr.clone()
+    }
 }
 
 impl Into<ItemContent> for Text {
@@ -179,9 +995,429 @@ impl From<BranchRef> for Text {
     }
 }
 
+/// A collection of formatting attributes applied over a text, eg. `{"bold": true}`.
+pub type Attrs = HashMap<String, Any>;
+
+/// A single chunk of a [Text] content returned by [Text::diff]. It's either a string or an embedded
+/// value, together with formatting attributes applied to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff {
+    /// Inserted content: a string chunk, an embed or a nested shared type.
+    pub insert: Value,
+    /// Formatting attributes applied to inserted content, if any.
+    pub attributes: Option<Box<Attrs>>,
+}
+
+impl Diff {
+    pub fn new(insert: Value, attributes: Option<Box<Attrs>>) -> Self {
+        Diff { insert, attributes }
+    }
+
+    fn pack_str(ops: &mut Vec<Diff>, buf: &mut String, attrs: &HashMap<String, String>) {
+        if !buf.is_empty() {
+            let insert = Value::Any(Any::String(std::mem::take(buf)));
+            ops.push(Diff::new(insert, decode_attrs(attrs)));
+        }
+    }
+}
+
+/// A position within a text, which keeps track of formatting attributes applied at that
+/// position. Attribute values are kept in their JSON-encoded form, the same as in
+/// [ItemContent::Format].
+pub(crate) struct ItemTextListPosition {
+    pub parent: TypePtr,
+    pub left: Option<BlockPtr>,
+    pub right: Option<BlockPtr>,
+    pub index: u32,
+    pub current_attrs: HashMap<String, String>,
+}
+
+impl ItemTextListPosition {
+    /// Moves current position one block to the right, updating current attributes or index.
+    fn forward(&mut self, txn: &Transaction) {
+        let right = self
+            .right
+            .as_ref()
+            .and_then(|ptr| txn.store.blocks.get_item(ptr))
+            .expect("Defect: unexpected end of text");
+        if !right.is_deleted() {
+            match &right.content {
+                ItemContent::Format(key, value) => {
+                    update_current_attributes(&mut self.current_attrs, key, value)
+                }
+                _ if right.is_countable() => self.index += right.len(),
+                _ => {}
+            }
+        }
+        self.left = self.right.take();
+        self.right = right.right;
+    }
+
+    /// Creates a new item with a given `content` at current position and moves over it.
+    fn insert_item(&mut self, txn: &mut Transaction, content: ItemContent) {
+        let pos = ItemPosition {
+            parent: self.parent.clone(),
+            left: self.left,
+            right: self.right,
+            index: self.index,
+        };
+        let id = txn.create_item(&pos, PrelimContent(content), None).id;
+        self.right = Some(BlockPtr::from(id));
+        self.forward(txn);
+    }
+
+    /// Inserts a `content` at current position, formatted with given `attributes`.
+    fn insert_content(
+        &mut self,
+        txn: &mut Transaction,
+        content: ItemContent,
+        mut attributes: HashMap<String, String>,
+    ) {
+        for key in self.current_attrs.keys() {
+            attributes
+                .entry(key.clone())
+                .or_insert_with(|| JSON_NULL.to_string());
+        }
+        self.minimize_attribute_changes(txn, &attributes);
+        let negated = self.insert_attributes(txn, &attributes);
+        self.insert_item(txn, content);
+        self.insert_negated_attributes(txn, negated);
+    }
+
+    /// Applies formatting `attributes` over the next `len` elements.
+    fn format(&mut self, txn: &mut Transaction, mut len: u32, attributes: HashMap<String, String>) {
+        self.minimize_attribute_changes(txn, &attributes);
+        let mut negated = self.insert_attributes(txn, &attributes);
+        while let Some(right_ptr) = self.right {
+            let right = match txn.store.blocks.get_item(&right_ptr) {
+                Some(item) => item,
+                None => break,
+            };
+            let is_format = matches!(right.content, ItemContent::Format(_, _));
+            if len == 0 && (negated.is_empty() || !(right.is_deleted() || is_format)) {
+                break;
+            }
+            if !right.is_deleted() {
+                if let ItemContent::Format(key, value) = &right.content {
+                    if let Some(attr) = attributes.get(key) {
+                        if attr == value {
+                            negated.remove(key);
+                        } else if len == 0 {
+                            break;
+                        } else {
+                            negated.insert(key.clone(), value.clone());
+                        }
+                        txn.delete(&right_ptr);
+                    }
+                } else if right.is_countable() {
+                    if len < right.len() {
+                        let split_ptr = BlockPtr::new(
+                            ID::new(right.id.client, right.id.clock + len),
+                            right_ptr.pivot() as u32,
+                        );
+                        txn.store.blocks.split_block(&split_ptr);
+                    }
+                    let right = txn.store.blocks.get_item(&right_ptr).unwrap();
+                    len -= right.len();
+                }
+            }
+            self.forward(txn);
+        }
+        if len > 0 {
+            // same as Yjs: formatting past the end of a text appends new lines
+            let newlines = "\n".repeat(len as usize);
+            self.insert_item(txn, ItemContent::String(newlines));
+        }
+        self.insert_negated_attributes(txn, negated);
+    }
+
+    /// Skips over deleted blocks and formatting attributes already matching given `attributes`.
+    fn minimize_attribute_changes(
+        &mut self,
+        txn: &Transaction,
+        attributes: &HashMap<String, String>,
+    ) {
+        while let Some(right) = self.right.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+            let skip = right.is_deleted()
+                || match &right.content {
+                    ItemContent::Format(key, value) => {
+                        attributes.get(key).map(String::as_str).unwrap_or(JSON_NULL) == value
+                    }
+                    _ => false,
+                };
+            if !skip {
+                break;
+            }
+            self.forward(txn);
+        }
+    }
+
+    /// Inserts formatting blocks for all `attributes` that differ from the current ones. Returns
+    /// a map of previous values of changed attributes, used to close formatting afterwards.
+    fn insert_attributes(
+        &mut self,
+        txn: &mut Transaction,
+        attributes: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        let mut negated = HashMap::new();
+        let mut keys: Vec<_> = attributes.keys().collect();
+        keys.sort();
+        for key in keys {
+            let value = &attributes[key];
+            let current = self
+                .current_attrs
+                .get(key)
+                .map(String::as_str)
+                .unwrap_or(JSON_NULL);
+            if current != value {
+                negated.insert(key.clone(), current.to_string());
+                self.insert_item(txn, ItemContent::Format(key.clone(), value.clone()));
+            }
+        }
+        negated
+    }
+
+    /// Inserts formatting blocks restoring `negated` attributes, unless they're already followed
+    /// by the same formatting.
+    fn insert_negated_attributes(
+        &mut self,
+        txn: &mut Transaction,
+        mut negated: HashMap<String, String>,
+    ) {
+        while let Some(right) = self.right.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+            if !right.is_deleted() {
+                match &right.content {
+                    ItemContent::Format(key, value) if negated.get(key) == Some(value) => {
+                        negated.remove(key);
+                    }
+                    _ => break,
+                }
+            }
+            self.forward(txn);
+        }
+        let mut negated: Vec<_> = negated.into_iter().collect();
+        negated.sort();
+        for (key, value) in negated {
+            self.insert_item(txn, ItemContent::Format(key, value));
+        }
+    }
+}
+
+const JSON_NULL: &str = "null";
+
+pub(crate) fn update_current_attributes(
+    attrs: &mut HashMap<String, String>,
+    key: &str,
+    value: &str,
+) {
+    if value == JSON_NULL {
+        attrs.remove(key);
+    } else {
+        attrs.insert(key.to_string(), value.to_string());
+    }
+}
+
+fn encode_attrs(attrs: Attrs) -> HashMap<String, String> {
+    attrs
+        .into_iter()
+        .map(|(key, value)| (key, value.to_json_string()))
+        .collect()
+}
+
+/// Decodes a JSON value of an embed or a formatting attribute. These are either encoded from [Any]
+/// values locally or validated when decoded from updates (see:
+/// [Decoder::read_json](crate::updates::decoder::Decoder::read_json)).
+fn decode_json(json: &str) -> Any {
+    Any::from_json_str(json).expect("Defect: text contains malformed JSON value")
+}
+
+fn decode_attrs(attrs: &HashMap<String, String>) -> Option<Box<Attrs>> {
+    if attrs.is_empty() {
+        None
+    } else {
+        let decoded = attrs
+            .iter()
+            .map(|(key, value)| (key.clone(), decode_json(value)))
+            .collect();
+        Some(Box::new(decoded))
+    }
+}
+
+/// Computes changes made to a text content of a given `branch` in scope of a transaction.
+pub(crate) fn event_delta(txn: &Transaction, branch: &Branch) -> Vec<Delta> {
+    let mut delta: Vec<Delta> = Vec::new();
+    // formatting attributes as they were before the transaction
+    let mut old_attrs = HashMap::new();
+    // formatting attributes as they are now
+    let mut new_attrs = HashMap::new();
+    let mut current = branch.start;
+    while let Some(item) = current.and_then(|ptr| txn.store.blocks.get_item(&ptr)) {
+        let added = adds(txn, item);
+        let deleted = deletes(txn, item);
+        if let ItemContent::Format(key, value) = &item.content {
+            if !added && (!item.is_deleted() || deleted) {
+                update_current_attributes(&mut old_attrs, key, value);
+            }
+            if !item.is_deleted() {
+                update_current_attributes(&mut new_attrs, key, value);
+            }
+        } else if item.is_countable() {
+            if added && !item.is_deleted() {
+                let attrs = decode_attrs(&new_attrs);
+                let values: Vec<Value> = match &item.content {
+                    ItemContent::String(s) => vec![Value::Any(Any::String(s.clone()))],
+                    ItemContent::Embed(json) => vec![Value::Any(decode_json(json))],
+                    other => other.get_content(txn),
+                };
+                for value in values {
+                    match (delta.last_mut(), &value) {
+                        (
+                            Some(Delta::Inserted(Value::Any(Any::String(prev)), prev_attrs)),
+                            Value::Any(Any::String(s)),
+                        ) if *prev_attrs == attrs => prev.push_str(s),
+                        _ => delta.push(Delta::Inserted(value, attrs.clone())),
+                    }
+                }
+            } else if deleted && !added {
+                if let Some(Delta::Deleted(len)) = delta.last_mut() {
+                    *len += item.len();
+                } else {
+                    delta.push(Delta::Deleted(item.len()));
+                }
+            } else if !item.is_deleted() {
+                let attrs = changed_attributes(&old_attrs, &new_attrs);
+                match delta.last_mut() {
+                    Some(Delta::Retain(len, prev_attrs)) if *prev_attrs == attrs => {
+                        *len += item.len()
+                    }
+                    _ => delta.push(Delta::Retain(item.len(), attrs)),
+                }
+            }
+        }
+        current = item.right;
+    }
+    if let Some(Delta::Retain(_, None)) = delta.last() {
+        delta.pop();
+    }
+    delta
+}
+
+/// Returns attributes, which values differ between `old` and `new` formatting. Attributes missing
+/// in `new` formatting are returned as [Any::Null].
+fn changed_attributes(
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+) -> Option<Box<Attrs>> {
+    let mut changed = HashMap::new();
+    for (key, value) in new.iter() {
+        if old.get(key) != Some(value) {
+            changed.insert(key.clone(), value.clone());
+        }
+    }
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            changed.insert(key.clone(), JSON_NULL.to_string());
+        }
+    }
+    decode_attrs(&changed)
+}
+
+/// A preliminary content used to insert an already prepared [ItemContent].
+struct PrelimContent(ItemContent);
+
+impl Prelim for PrelimContent {
+    fn into_content(self, _txn: &mut Transaction, _ptr: TypePtr) -> (ItemContent, Option<Self>) {
+        (self.0, None)
+    }
+
+    fn integrate(self, _txn: &mut Transaction, _inner_ref: BranchRef) {}
+}
+
 #[cfg(test)]
 mod test {
-    use crate::Doc;
+    use crate::event::Subscription;
+    use crate::test_utils::{check_search_markers, exchange_updates, run_scenario};
+    use crate::types::text::{Attrs, Diff};
+    use crate::types::{Delta, Value};
+    use crate::{Doc, PositionError, Text, TextEvent};
+    use lib0::any::Any;
+    use rand::rngs::{StdRng, ThreadRng};
+    use rand::{Rng, SeedableRng};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    fn attrs(entries: Vec<(&str, Any)>) -> Option<Box<Attrs>> {
+        let map: Attrs = entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+        Some(Box::new(map))
+    }
+
+    fn chunk(s: &str, attributes: Option<Box<Attrs>>) -> Diff {
+        Diff::new(Value::Any(Any::String(s.to_string())), attributes)
+    }
+
+    #[test]
+    fn cursor_edit_script() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(1);
+        let txt1 = d1.transact().get_text("text");
+        let txt2 = d2.transact().get_text("text");
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut cursor = txt1.cursor(&d1.transact());
+        let mut index = 0;
+        for _ in 0..20 {
+            let mut t1 = d1.transact();
+            let mut t2 = d2.transact();
+            for _ in 0..100 {
+                let remaining = txt2.len() - index;
+                match rng.gen_range(0, 10) {
+                    0..=3 => {
+                        let len = rng.gen_range(1, 5);
+                        let chunk: String = (0..len)
+                            .map(|_| rng.gen_range(b'a', b'z' + 1) as char)
+                            .collect();
+                        cursor.insert(&mut t1, &chunk);
+                        txt2.insert(&mut t2, index, &chunk);
+                        // cursor keeps its position cached across its own edits
+                        assert_eq!(cursor.marker.is_some(), index > 0);
+                        index += len;
+                    }
+                    4..=5 => {
+                        let n = rng.gen_range(0, 4);
+                        let removed = n.min(remaining);
+                        assert_eq!(cursor.delete(&mut t1, n), removed);
+                        if removed > 0 {
+                            txt2.remove_range(&mut t2, index, removed);
+                        }
+                    }
+                    6..=7 => {
+                        let n = rng.gen_range(0, 6).min(remaining);
+                        let s = txt2.to_string(&t2);
+                        let expected = &s[index as usize..(index + n) as usize];
+                        assert_eq!(cursor.read(&t1, n), expected);
+                        index += n;
+                    }
+                    8 => {
+                        let n = rng.gen_range(0, 6);
+                        assert_eq!(cursor.forward(n), n.min(remaining));
+                        index += n.min(remaining);
+                    }
+                    _ => {
+                        index = rng.gen_range(0, txt2.len() + 1);
+                        cursor.seek(index);
+                    }
+                }
+                assert_eq!(cursor.index(), index);
+            }
+        }
+        assert_eq!(
+            txt1.to_string(&d1.transact()),
+            txt2.to_string(&d2.transact())
+        );
+    }
 
     #[test]
     fn append_single_character_blocks() {
@@ -281,8 +1517,8 @@ mod test {
         let u1 = d1.encode_delta_as_update_v1(&t1, &d2_sv);
         let u2 = d2.encode_delta_as_update_v1(&t2, &d1_sv);
 
-        d1.apply_update_v1(&mut t1, u2.as_slice());
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let a = txt1.to_string(&t1);
         let b = txt2.to_string(&t2);
@@ -305,7 +1541,7 @@ mod test {
 
         let d2_sv = d2.get_state_vector(&t2);
         let u1 = d1.encode_delta_as_update_v1(&t1, &d2_sv);
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let txt2 = t2.get_text("test");
         assert_eq!(txt2.to_string(&t2).as_str(), "I expect that");
@@ -321,8 +1557,8 @@ mod test {
         let d1_sv = d1.get_state_vector(&t1);
         let u1 = d1.encode_delta_as_update_v1(&t1, &d2_sv);
         let u2 = d2.encode_delta_as_update_v1(&t2, &d1_sv);
-        d1.apply_update_v1(&mut t1, u2.as_slice());
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let a = txt1.to_string(&t1);
         let b = txt2.to_string(&t2);
@@ -345,7 +1581,7 @@ mod test {
 
         let d2_sv = d2.get_state_vector(&t2);
         let u1 = d1.encode_delta_as_update_v1(&t1, &d2_sv);
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let txt2 = t2.get_text("test");
         assert_eq!(txt2.to_string(&t2).as_str(), "aaa");
@@ -362,8 +1598,8 @@ mod test {
         let u1 = d1.encode_delta_as_update_v1(&t1, &d2_sv);
         let u2 = d2.encode_delta_as_update_v1(&t2, &d1_sv);
 
-        d1.apply_update_v1(&mut t1, u2.as_slice());
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let a = txt1.to_string(&t1);
         let b = txt2.to_string(&t2);
@@ -457,6 +1693,33 @@ mod test {
         assert_eq!(txt.to_string(&txn).as_str(), " world");
     }
 
+    #[test]
+    fn delete_updates_length_and_delete_set() {
+        let d1 = Doc::with_client_id(1);
+        let txt1 = {
+            let mut t1 = d1.transact();
+            let txt1 = t1.get_text("test");
+            txt1.insert(&mut t1, 0, "hello world");
+            txt1
+        };
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let update = d1.encode_state_as_update_v1(&d1.transact());
+        d2.apply_update_v1(&mut t2, update.as_slice()).unwrap();
+
+        let update = {
+            let mut t1 = d1.transact();
+            txt1.remove_range(&mut t1, 5, 6);
+            assert_eq!(txt1.to_string(&t1).as_str(), "hello");
+            assert_eq!(txt1.len(), 5);
+            t1.encode_update_v1()
+        };
+
+        // deletion is a part of an update produced by the transaction
+        d2.apply_update_v1(&mut t2, update.as_slice()).unwrap();
+        assert_eq!(t2.get_text("test").to_string(&t2).as_str(), "hello");
+    }
+
     #[test]
     fn concurrent_insert_delete() {
         let d1 = Doc::with_client_id(1);
@@ -470,7 +1733,7 @@ mod test {
 
         let d2 = Doc::with_client_id(2);
         let mut t2 = d2.transact();
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
         let txt2 = t2.get_text("test");
         assert_eq!(txt2.to_string(&t2).as_str(), "hello world");
 
@@ -489,8 +1752,8 @@ mod test {
         let u1 = d1.encode_delta_as_update_v1(&t1, &sv2);
         let u2 = d2.encode_delta_as_update_v1(&t2, &sv1);
 
-        d1.apply_update_v1(&mut t1, u2.as_slice());
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d1.apply_update_v1(&mut t1, u2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
 
         let a = txt1.to_string(&t1);
         let b = txt2.to_string(&t2);
@@ -498,4 +1761,729 @@ mod test {
         assert_eq!(a, b);
         assert_eq!(a, "H beautifuld!".to_owned());
     }
+
+    #[test]
+    fn format_and_diff() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let txt = txn.get_text("test");
+        txt.insert(&mut txn, 0, "hello world");
+
+        let bold = attrs(vec![("bold", Any::Bool(true))]);
+        txt.format(&mut txn, 0, 5, *bold.clone().unwrap());
+        assert_eq!(
+            txt.diff(&txn),
+            vec![chunk("hello", bold.clone()), chunk(" world", None)]
+        );
+        assert_eq!(txt.to_string(&txn), "hello world");
+        assert_eq!(txt.len(), 11);
+
+        // plain insert inherits formatting of preceding text
+        txt.insert(&mut txn, 5, "!");
+        assert_eq!(
+            txt.diff(&txn),
+            vec![chunk("hello!", bold.clone()), chunk(" world", None)]
+        );
+
+        // explicit attributes override preceding formatting
+        txt.insert_with_attributes(&mut txn, 6, "?", HashMap::new());
+        txt.insert(&mut txn, 0, ">");
+        assert_eq!(
+            txt.diff(&txn),
+            vec![
+                chunk(">", None),
+                chunk("hello!", bold.clone()),
+                chunk("? world", None)
+            ]
+        );
+
+        // null removes formatting
+        txt.format(&mut txn, 1, 3, *attrs(vec![("bold", Any::Null)]).unwrap());
+        assert_eq!(
+            txt.diff(&txn),
+            vec![
+                chunk(">hel", None),
+                chunk("lo!", bold.clone()),
+                chunk("? world", None)
+            ]
+        );
+
+        // overlapping formats
+        let italic = vec![("italic", Any::Bool(true))];
+        txt.format(&mut txn, 5, 4, *attrs(italic.clone()).unwrap());
+        assert_eq!(
+            txt.diff(&txn),
+            vec![
+                chunk(">hel", None),
+                chunk("l", bold.clone()),
+                chunk(
+                    "o!",
+                    attrs(vec![("bold", Any::Bool(true)), ("italic", Any::Bool(true))])
+                ),
+                chunk("? ", attrs(italic)),
+                chunk("world", None),
+            ]
+        );
+        assert_eq!(txt.to_string(&txn), ">hello!? world");
+    }
+
+    #[test]
+    fn insert_embed() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let txt = txn.get_text("test");
+        txt.insert(&mut txn, 0, "ab");
+
+        let mut image = HashMap::new();
+        image.insert("image".to_string(), Any::String("a.png".to_string()));
+        let link = attrs(vec![("link", Any::String("https://y.js".to_string()))]);
+        txt.insert_embed(
+            &mut txn,
+            1,
+            Any::Map(image.clone()),
+            Some(*link.clone().unwrap()),
+        );
+
+        assert_eq!(txt.to_string(&txn), "ab");
+        assert_eq!(txt.len(), 3);
+        assert_eq!(
+            txt.diff(&txn),
+            vec![
+                chunk("a", None),
+                Diff::new(Value::Any(Any::Map(image)), link),
+                chunk("b", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting_sync() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt1 = t1.get_text("test");
+        txt1.insert(&mut t1, 0, "hello world");
+        let mut bold = HashMap::new();
+        bold.insert("bold".to_string(), Any::Bool(true));
+        txt1.format(&mut t1, 6, 5, bold.clone());
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let txt2 = t2.get_text("test");
+        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice())
+            .unwrap();
+
+        let expected = vec![chunk("hello ", None), chunk("world", Some(Box::new(bold)))];
+        assert_eq!(txt2.diff(&t2), expected);
+
+        // formatting is kept when text is inserted remotely in the middle of formatted range
+        txt2.insert(&mut t2, 8, "-");
+        let sv1 = d1.get_state_vector(&t1);
+        let update = d2.encode_delta_as_update_v1(&t2, &sv1);
+        d1.apply_update_v1(&mut t1, update.as_slice()).unwrap();
+        assert_eq!(txt1.diff(&t1), txt2.diff(&t2));
+        assert_eq!(txt1.to_string(&t1), "hello wo-rld");
+    }
+
+    #[test]
+    fn search_markers_match_unoptimized_lookups() {
+        let bold = |value: Any| {
+            let mut attrs = Attrs::new();
+            attrs.insert("bold".to_string(), value);
+            attrs
+        };
+        check_search_markers("text", |rng, step, txns| {
+            let texts: Vec<_> = txns.iter_mut().map(|t| t.get_text("text")).collect();
+            let len = texts[0].len();
+            let index = rng.gen_range(0, len + 1);
+            let chunk: String = (0..rng.gen_range(1, 4))
+                .map(|i| (b'a' + ((step + i) % 26) as u8) as char)
+                .collect();
+            match rng.gen_range(0, 11) {
+                0..=3 => {
+                    for (x, t) in texts.iter().zip(txns.iter_mut()) {
+                        x.insert(t, index, &chunk);
+                    }
+                }
+                4 => {
+                    for (x, t) in texts.iter().zip(txns.iter_mut()) {
+                        x.insert_with_attributes(t, index, &chunk, bold(Any::Bool(true)));
+                    }
+                }
+                5 => {
+                    for (x, t) in texts.iter().zip(txns.iter_mut()) {
+                        x.insert_embed(t, index, Any::Number(step as f64), None);
+                    }
+                }
+                6..=7 if index < len => {
+                    let count = rng.gen_range(1, (len - index).min(3) + 1);
+                    for (x, t) in texts.iter().zip(txns.iter_mut()) {
+                        x.remove_range(t, index, count);
+                    }
+                }
+                8 if index < len => {
+                    let count = rng.gen_range(1, (len - index).min(5) + 1);
+                    let value = if rng.gen_bool(0.5) {
+                        Any::Bool(true)
+                    } else {
+                        Any::Null
+                    };
+                    for (x, t) in texts.iter().zip(txns.iter_mut()) {
+                        x.format(t, index, count, bold(value.clone()));
+                    }
+                }
+                _ if index < len => {
+                    let expected = texts[0].id_at(txns[0], index);
+                    for (x, t) in texts.iter().zip(txns.iter()) {
+                        assert_eq!(x.id_at(t, index), expected);
+                    }
+                }
+                _ => {}
+            }
+            let expected = texts[0].diff(txns[0]);
+            for (x, t) in texts.iter().zip(txns.iter()) {
+                assert_eq!(x.diff(t), expected);
+            }
+        });
+    }
+
+    fn record_deltas(text: &Text) -> (Rc<RefCell<Vec<Vec<Delta>>>>, Subscription<TextEvent>) {
+        let deltas = Rc::new(RefCell::new(Vec::new()));
+        let d = deltas.clone();
+        let sub = text.observe(move |_, e| d.borrow_mut().push(e.delta.clone()));
+        (deltas, sub)
+    }
+
+    fn str_value(s: &str) -> Value {
+        Value::Any(Any::String(s.to_string()))
+    }
+
+    #[test]
+    fn observe_formatted_insert() {
+        let doc = Doc::with_client_id(1);
+        let text = doc.transact().get_text("text");
+        text.insert(&mut doc.transact(), 0, "hello world");
+
+        let (deltas, _sub) = record_deltas(&text);
+        {
+            let mut txn = doc.transact();
+            let bold: Attrs = HashMap::from([("bold".to_string(), Any::Bool(true))]);
+            text.insert_with_attributes(&mut txn, 6, "big ", bold);
+        }
+
+        assert_eq!(
+            deltas.borrow().as_slice(),
+            &[vec![
+                Delta::Retain(6, None),
+                Delta::Inserted(str_value("big "), attrs(vec![("bold", Any::Bool(true))])),
+            ]]
+        );
+    }
+
+    #[test]
+    fn observe_format_only() {
+        let doc = Doc::with_client_id(1);
+        let text = doc.transact().get_text("text");
+        text.insert(&mut doc.transact(), 0, "hello world");
+
+        let (deltas, _sub) = record_deltas(&text);
+        {
+            let mut txn = doc.transact();
+            let italic: Attrs = HashMap::from([("italic".to_string(), Any::Bool(true))]);
+            text.format(&mut txn, 0, 5, italic);
+        }
+
+        assert_eq!(
+            deltas.borrow().as_slice(),
+            &[vec![Delta::Retain(
+                5,
+                attrs(vec![("italic", Any::Bool(true))])
+            )]]
+        );
+    }
+
+    #[test]
+    fn observe_remote_formatting() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let t1 = d1.transact().get_text("text");
+        t1.insert(&mut d1.transact(), 0, "hello world");
+        exchange_updates(&[&d1, &d2]);
+
+        let t2 = d2.transact().get_text("text");
+        let (local, _s1) = record_deltas(&t1);
+        let (remote, _s2) = record_deltas(&t2);
+        {
+            let mut txn = d1.transact();
+            let bold: Attrs = HashMap::from([("bold".to_string(), Any::Bool(true))]);
+            t1.format(&mut txn, 6, 5, bold.clone());
+            t1.insert_with_attributes(&mut txn, 5, ",", bold);
+            t1.remove_range(&mut txn, 0, 1);
+        }
+        exchange_updates(&[&d1, &d2]);
+
+        assert_eq!(local.borrow().len(), 1);
+        assert_eq!(*local.borrow(), *remote.borrow());
+        assert_eq!(t1.diff(&d1.transact()), t2.diff(&d2.transact()));
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn insert_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = d1.transact().get_text("text");
+        let mut txn = d2.transact();
+        txn.get_text("text");
+        text.insert(&mut txn, 0, "hello");
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn remove_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = d1.transact().get_text("text");
+        text.insert(&mut d1.transact(), 0, "hello");
+        text.remove_range(&mut d2.transact(), 0, 1);
+    }
+
+    #[test]
+    fn equality_by_identity() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let t1 = d1.transact().get_text("text");
+        let t2 = d2.transact().get_text("text");
+        t1.insert(&mut d1.transact(), 0, "hello");
+        t2.insert(&mut d2.transact(), 0, "hello");
+
+        // the same root type retrieved twice
+        assert_eq!(t1, d1.transact().get_text("text"));
+        // equal content, but different shared types
+        assert_ne!(t1, t2);
+
+        let mut cursors = std::collections::HashMap::new();
+        cursors.insert(t1.clone(), 1);
+        cursors.insert(t2.clone(), 2);
+        assert_eq!(cursors.get(&d1.transact().get_text("text")), Some(&1));
+        assert_eq!(cursors.get(&t2), Some(&2));
+    }
+
+    #[test]
+    fn clear_and_replace_all() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = d1.transact().get_text("text");
+        {
+            let mut txn = d1.transact();
+            text.clear(&mut txn);
+            assert!(!txn.has_changes());
+
+            let mut attrs = Attrs::new();
+            attrs.insert("bold".to_string(), Any::Bool(true));
+            text.insert_with_attributes(&mut txn, 0, "hello world", attrs);
+        }
+
+        let deltas = Rc::new(RefCell::new(Vec::new()));
+        let _sub = {
+            let deltas = deltas.clone();
+            text.observe(move |_, e| deltas.borrow_mut().push(e.delta.clone()))
+        };
+        text.replace_all(&mut d1.transact(), "hi");
+        assert_eq!(
+            *deltas.borrow(),
+            vec![vec![
+                Delta::Deleted(11),
+                Delta::Inserted(Value::from("hi"), None)
+            ]]
+        );
+        assert_eq!(
+            text.diff(&d1.transact()),
+            vec![Diff::new("hi".into(), None)]
+        );
+
+        exchange_updates(&[&d1, &d2]);
+        let t2 = d2.transact().get_text("text");
+        t2.replace_all(&mut d2.transact(), "there");
+        text.replace_all(&mut d1.transact(), "hey");
+        exchange_updates(&[&d1, &d2]);
+        // concurrent replacements are both kept, just like any other concurrent insertions
+        assert_eq!(text.to_string(&d1.transact()), "heythere");
+        assert_eq!(t2.to_string(&d2.transact()), "heythere");
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the maximum length")]
+    fn format_range_overflow() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "abc");
+        let mut attrs = Attrs::new();
+        attrs.insert("bold".to_string(), Any::Bool(true));
+        text.format(&mut txn, 1, u32::MAX, attrs);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the maximum length")]
+    fn remove_range_overflow() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        text.insert(&mut txn, 0, "abc");
+        text.remove_range(&mut txn, 1, u32::MAX);
+    }
+
+    #[test]
+    fn cursor_positions_from_awareness() {
+        let d1 = Doc::with_client_id(1);
+        let mut txn = d1.transact();
+        let text = txn.get_text("codemirror");
+        text.insert(&mut txn, 0, "hello world");
+        txn.get_text("other").insert(&mut txn, 0, "abc");
+
+        // awareness states as broadcast by y-codemirror over y-webrtc
+        let state = |json: &str| Any::from_json_str(json).unwrap();
+        let mut states = HashMap::new();
+        states.insert(
+            2,
+            state(
+                r#"{"user":{"name":"bob"},"cursor":{
+                    "anchor":{"type":null,"tname":"codemirror","item":{"client":1,"clock":3},"assoc":0},
+                    "head":{"type":null,"tname":"codemirror","item":null,"assoc":0}}}"#,
+            ),
+        );
+        states.insert(3, state(r#"{"user":{"name":"carol"}}"#));
+        states.insert(
+            4,
+            state(
+                r#"{"cursor":{
+                    "anchor":{"tname":"other","item":{"client":1,"clock":11},"assoc":0},
+                    "head":{"tname":"other","item":{"client":1,"clock":12},"assoc":0}}}"#,
+            ),
+        );
+        states.insert(
+            5,
+            state(
+                r#"{"cursor":{
+                    "anchor":{"tname":"codemirror","item":{"client":9,"clock":0},"assoc":0},
+                    "head":{"tname":"codemirror","item":{"client":1,"clock":0},"assoc":0}}}"#,
+            ),
+        );
+        states.insert(6, state(r#"{"cursor":{"anchor":1,"head":2}}"#));
+
+        let cursors = text.cursor_positions_from_awareness(&txn, &states);
+        assert_eq!(cursors, HashMap::from([(2, (3, 11))]));
+
+        // positions follow the text they were created for
+        text.insert(&mut txn, 0, ">> ");
+        let cursors = text.cursor_positions_from_awareness(&txn, &states);
+        assert_eq!(cursors, HashMap::from([(2, (6, 14))]));
+    }
+
+    #[test]
+    fn insert_at_relative_position() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let t1 = d1.transact().get_text("text");
+        let t2 = d2.transact().get_text("text");
+        t1.insert(&mut d1.transact(), 0, "hello world");
+        exchange_updates(&[&d1, &d2]);
+
+        // queued inserts: one before "world", the other right after "hello"
+        let before = t1.relative_position(&d1.transact(), 6, 0);
+        let after = t1.relative_position(&d1.transact(), 5, -1);
+
+        t1.insert(&mut d1.transact(), 5, ",");
+        {
+            let mut txn = d2.transact();
+            t2.remove_range(&mut txn, 0, 1);
+            t2.insert(&mut txn, 5, "dear ");
+        }
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(t1.to_string(&d1.transact()), "ello, dear world");
+
+        {
+            let mut txn = d1.transact();
+            t1.insert_at(&mut txn, &before, "new ").unwrap();
+            t1.insert_at(&mut txn, &after, "!").unwrap();
+        }
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(t1.to_string(&d1.transact()), "ello!, dear new world");
+        assert_eq!(t2.to_string(&d2.transact()), "ello!, dear new world");
+
+        // anchor removed concurrently
+        t2.remove_range(&mut d2.transact(), 16, 5);
+        exchange_updates(&[&d1, &d2]);
+        assert_eq!(
+            t1.insert_at(&mut d1.transact(), &before, "x"),
+            Err(PositionError::AnchorDeleted)
+        );
+
+        let other = d1.transact().get_text("other");
+        assert_eq!(
+            other.insert_at(&mut d1.transact(), &after, "x"),
+            Err(PositionError::OtherType)
+        );
+        let d3 = Doc::with_client_id(3);
+        let t3 = d3.transact().get_text("text");
+        assert_eq!(
+            t3.insert_at(&mut d3.transact(), &before, "x"),
+            Err(PositionError::Unresolved)
+        );
+    }
+
+    #[test]
+    fn chunks() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let t1 = d1.transact().get_text("text");
+        let t2 = d2.transact().get_text("text");
+        t1.insert(&mut d1.transact(), 0, "hello world");
+        t2.insert(&mut d2.transact(), 0, "abc");
+        exchange_updates(&[&d1, &d2]);
+        {
+            let mut txn = d1.transact();
+            t1.remove_range(&mut txn, 5, 6);
+            t1.insert_embed(&mut txn, 5, Any::Bool(true), None);
+        }
+
+        let txn = d1.transact();
+        let chunks: Vec<_> = t1.chunks(&txn).collect();
+        assert_eq!(chunks, vec!["hello", "abc"]);
+        let mut written = String::new();
+        t1.write_to(&txn, &mut written).unwrap();
+        assert_eq!(written, t1.to_string(&txn));
+        assert_eq!(written, "helloabc");
+    }
+
+    #[test]
+    fn delta_json_roundtrip() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let text = {
+            let mut txn = d1.transact();
+            let text = txn.get_text("text");
+            text.insert(&mut txn, 0, "abcd");
+            text
+        };
+        exchange_updates(&[&d1, &d2]);
+
+        let delta = Rc::new(RefCell::new(None));
+        let _sub = {
+            let delta = delta.clone();
+            text.observe(move |txn, e| *delta.borrow_mut() = Some(e.delta_json(txn)))
+        };
+        {
+            let mut txn = d1.transact();
+            let bold = attrs(vec![("bold", Any::Bool(true))]).unwrap();
+            let italic = attrs(vec![("italic", Any::Bool(true))]).unwrap();
+            text.format(&mut txn, 0, 1, *italic);
+            text.insert_with_attributes(&mut txn, 2, "xy", *bold);
+            text.remove_range(&mut txn, 4, 1);
+            text.insert_embed(
+                &mut txn,
+                5,
+                Any::from_json_str(r#"{"image":"a.png"}"#).unwrap(),
+                None,
+            );
+        }
+
+        // delta emitted by Yjs text observer for the same changes
+        let expected = Any::from_json_str(
+            r#"[
+                {"retain":1,"attributes":{"italic":true}},
+                {"retain":1},
+                {"insert":"xy","attributes":{"bold":true}},
+                {"delete":1},
+                {"retain":1},
+                {"insert":{"image":"a.png"}}
+            ]"#,
+        )
+        .unwrap();
+        let actual = delta.borrow_mut().take().unwrap();
+        assert!(actual.loosely_eq(&expected), "{}", actual);
+
+        // delta parsed from JSON can be applied to another replica
+        let parsed: Vec<Delta> = expected
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|op| Delta::from_json(op).unwrap())
+            .collect();
+        let mut t2 = d2.transact();
+        t2.get_text("text").apply_delta(&mut t2, &parsed);
+        let t1 = d1.transact();
+        assert_eq!(t2.get_text("text").diff(&t2), text.diff(&t1));
+
+        assert!(Delta::from_json(&Any::from_json_str(r#"{"retain":-1}"#).unwrap()).is_err());
+        assert!(Delta::from_json(&Any::from_json_str(r#"{"foo":1}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn normalize_formatting() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let d3 = Doc::with_client_id(3);
+        {
+            let mut txn = d1.transact();
+            txn.get_text("text").insert(&mut txn, 0, "hello world");
+        }
+        exchange_updates(&[&d1, &d2, &d3]);
+
+        // the same ranges formatted concurrently by all peers leave duplicated markers
+        let bold = attrs(vec![("bold", Any::Bool(true))]).unwrap();
+        let unbold = attrs(vec![("bold", Any::Null)]).unwrap();
+        for doc in [&d1, &d2, &d3] {
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            text.format(&mut txn, 0, 5, *bold.clone());
+            text.format(&mut txn, 2, 1, *unbold.clone());
+            text.format(&mut txn, 2, 1, *bold.clone());
+        }
+        exchange_updates(&[&d1, &d2, &d3]);
+
+        let mut t1 = d1.transact();
+        let text = t1.get_text("text");
+        let diff = text.diff(&t1);
+        let markers = t1.store_stats().format_markers;
+        let removed = text.normalize_formatting(&mut t1);
+        assert!(removed > 0);
+        assert_eq!(t1.store_stats().format_markers, markers - removed as usize);
+        assert_eq!(t1.store_stats().format_markers, 2);
+        assert_eq!(text.diff(&t1), diff);
+        assert_eq!(
+            diff,
+            vec![chunk("hello", Some(bold.clone())), chunk(" world", None)]
+        );
+        // normalized text has nothing left to remove
+        assert_eq!(text.normalize_formatting(&mut t1), 0);
+        drop(t1);
+
+        exchange_updates(&[&d1, &d2, &d3]);
+        for doc in [&d2, &d3] {
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            assert_eq!(text.diff(&txn), diff);
+            assert_eq!(txn.store_stats().format_markers, 2);
+        }
+    }
+
+    /// Builds a text out of given parts, each one stored in a separate block.
+    #[cfg(feature = "unicode")]
+    fn chunked_text(doc: &Doc, parts: &[&str]) -> Text {
+        let mut txn = doc.transact();
+        let text = txn.get_text("text");
+        // prepending prevents consecutive insertions from being squashed into a single block
+        for part in parts.iter().rev() {
+            text.insert(&mut txn, 0, part);
+        }
+        drop(txn);
+        let txn = doc.transact();
+        assert_eq!(text.chunks(&txn).collect::<Vec<_>>(), parts);
+        text
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn remove_graphemes_across_chunks() {
+        let doc = Doc::with_client_id(1);
+        let text = chunked_text(&doc, &["a\u{1F1E9}", "\u{1F1EA}\u{1F1EB}", "\u{1F1F7}b"]);
+        let mut txn = doc.transact();
+        // regional indicators are paired into flags: 🇩🇪🇫🇷
+        assert_eq!(text.remove_graphemes(&mut txn, 1, 1), 8);
+        assert_eq!(text.to_string(&txn), "a\u{1F1EB}\u{1F1F7}b");
+        // removal stops at the end of a text
+        assert_eq!(text.remove_graphemes(&mut txn, 1, 5), 9);
+        assert_eq!(text.to_string(&txn), "a");
+        assert_eq!(text.remove_graphemes(&mut txn, 1, 1), 0);
+        drop(txn);
+
+        // family emoji: 👨‍👩‍👧
+        let doc = Doc::with_client_id(1);
+        let parts = ["x\u{1F468}\u{200D}", "\u{1F469}\u{200D}\u{1F467}", "y"];
+        let text = chunked_text(&doc, &parts);
+        let mut txn = doc.transact();
+        assert_eq!(text.remove_graphemes(&mut txn, 1, 1), 18);
+        assert_eq!(text.to_string(&txn), "xy");
+        drop(txn);
+
+        let doc = Doc::with_client_id(1);
+        let text = chunked_text(&doc, &["cafe", "\u{301} ", "ole\u{301}"]);
+        let mut txn = doc.transact();
+        assert_eq!(text.remove_graphemes(&mut txn, 3, 1), 3);
+        assert_eq!(text.to_string(&txn), "caf ole\u{301}");
+        assert_eq!(text.remove_graphemes(&mut txn, 6, 1), 3);
+        assert_eq!(text.to_string(&txn), "caf ol");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn word_boundaries_across_chunks() {
+        let doc = Doc::with_client_id(1);
+        let text = chunked_text(&doc, &["hello wo", "rld, ", "foo"]);
+        let mut txn = doc.transact();
+        assert_eq!(text.prev_word_boundary(&txn, 13), 6);
+        assert_eq!(text.prev_word_boundary(&txn, 9), 6);
+        assert_eq!(text.prev_word_boundary(&txn, 6), 0);
+        assert_eq!(text.prev_word_boundary(&txn, 0), 0);
+        assert_eq!(text.next_word_boundary(&txn, 0), 5);
+        assert_eq!(text.next_word_boundary(&txn, 5), 11);
+        assert_eq!(text.next_word_boundary(&txn, 11), 16);
+        assert_eq!(text.next_word_boundary(&txn, 16), 16);
+
+        // backspace-word
+        let start = text.prev_word_boundary(&txn, 13);
+        text.remove_range(&mut txn, start, 13 - start);
+        assert_eq!(text.to_string(&txn), "hello foo");
+
+        // embedded values separate words
+        text.insert_embed(&mut txn, 5, Any::Bool(true), None);
+        assert_eq!(text.next_word_boundary(&txn, 0), 5);
+        assert_eq!(text.prev_word_boundary(&txn, 6), 0);
+        drop(txn);
+
+        // combining accent continues a word from a preceding chunk
+        let doc = Doc::with_client_id(1);
+        let text = chunked_text(&doc, &["cafe", "\u{301} ", "ole\u{301}"]);
+        let txn = doc.transact();
+        assert_eq!(text.next_word_boundary(&txn, 0), 6);
+        assert_eq!(text.prev_word_boundary(&txn, 12), 7);
+        assert_eq!(text.prev_word_boundary(&txn, 7), 0);
+    }
+
+    fn text_transactions() -> [Box<dyn Fn(&mut Doc, &mut ThreadRng)>; 2] {
+        fn insert_text(doc: &mut Doc, rng: &mut ThreadRng) {
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            let index = rng.gen_range(0, text.len() + 1);
+            let len = rng.gen_range(1, 6);
+            let chunk: String = (0..len)
+                .map(|_| rng.gen_range(b'a', b'z') as char)
+                .collect();
+            text.insert(&mut txn, index, &chunk);
+        }
+
+        fn delete_text(doc: &mut Doc, rng: &mut ThreadRng) {
+            let mut txn = doc.transact();
+            let text = txn.get_text("text");
+            let len = text.len();
+            if len > 0 {
+                let index = rng.gen_range(0, len);
+                let removed = rng.gen_range(1, (len - index).min(4) + 1);
+                text.remove_range(&mut txn, index, removed);
+            }
+        }
+
+        [Box::new(insert_text), Box::new(delete_text)]
+    }
+
+    #[test]
+    fn fuzzy_test_3() {
+        run_scenario(&text_transactions(), 3, 100)
+    }
+
+    #[test]
+    fn fuzzy_test_5() {
+        run_scenario(&text_transactions(), 5, 300)
+    }
 }
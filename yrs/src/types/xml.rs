@@ -1,13 +1,20 @@
 use crate::block::{Item, ItemContent, ItemPosition, Prelim};
+use crate::event::{Observer, Subscription};
+use crate::types::text::{self, Attrs, Diff};
+use crate::types::xml_parser::{XmlNode, XmlParser};
 use crate::types::{
-    Branch, BranchRef, Entries, Map, Text, TypePtr, Value, TYPE_REFS_XML_ELEMENT,
+    event_changes, event_keys, Branch, BranchRef, Change, Delta, Entries, EntryChange, Event, Map,
+    Observers, Path, Text, TypePtr, Value, TYPE_REFS_XML_ELEMENT, TYPE_REFS_XML_FRAGMENT,
     TYPE_REFS_XML_TEXT,
 };
 use crate::Transaction;
 use lib0::any::Any;
 use std::cell::Ref;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
+pub use crate::types::xml_parser::XmlParseError;
+
 /// An return type from XML elements retrieval methods. It's an enum of all supported values, that
 /// can be nested inside of [XmlElement]. These are other [XmlElement]s or [XmlText] values.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -30,7 +37,7 @@ impl From<BranchRef> for Xml {
 /// XML element data type. It represents an XML node, which can contain key-value attributes
 /// (interpreted as strings) as well as other nested XML elements or plain text (represented by
 /// [XmlText] type).
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct XmlElement(XmlFragment);
 
 impl XmlElement {
@@ -39,28 +46,69 @@ impl XmlElement {
     }
 
     /// Converts current XML node into a textual representation. This representation if flat, it
-    /// doesn't include any indentation.
+    /// doesn't include any indentation. Attributes are written in order of their names. Markup
+    /// characters found in text content and attribute values are escaped.
     pub fn to_string(&self, txn: &Transaction) -> String {
-        let inner = self.inner();
         let mut s = String::new();
+        self.write_xml(txn, &mut s, None, 0);
+        s
+    }
+
+    /// Converts current XML node into a textual representation, in which every nested node is
+    /// written in a separate line indented by `indent` spaces per nesting level. It's meant to be
+    /// used for debugging purposes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let root = txn.get_xml_element("root");
+    /// root.insert_xml_str(&mut txn, 0, "<p>hello</p>").unwrap();
+    ///
+    /// assert_eq!(
+    ///     root.to_string_pretty(&txn, 2),
+    ///     "<UNDEFINED>\n  <p>\n    hello\n  </p>\n</UNDEFINED>"
+    /// );
+    /// ```
+    pub fn to_string_pretty(&self, txn: &Transaction, indent: usize) -> String {
+        let mut s = String::new();
+        self.write_xml(txn, &mut s, Some(indent), 0);
+        s.truncate(s.trim_end_matches('\n').len());
+        s
+    }
+
+    fn write_xml(&self, txn: &Transaction, buf: &mut String, indent: Option<usize>, depth: usize) {
+        let inner = self.inner();
         let tag = inner
             .name
             .as_ref()
             .map(|s| s.as_str())
             .unwrap_or(&"UNDEFINED");
-        write!(&mut s, "<{}", tag).unwrap();
-        let attributes = Attributes(inner.entries(txn));
+        write_indent(buf, indent, depth);
+        write!(buf, "<{}", tag).unwrap();
+        let mut attributes: Vec<_> = Attributes(inner.entries(txn)).collect();
+        attributes.sort_by(|a, b| a.0.cmp(b.0));
         for (k, v) in attributes {
-            write!(&mut s, " \"{}\"=\"{}\"", k, v).unwrap();
+            write!(buf, " {}=\"", k).unwrap();
+            escape_xml(&v, true, buf);
+            buf.push('"');
         }
-        write!(&mut s, ">").unwrap();
-        for i in inner.iter(txn) {
-            for content in i.content.get_content(txn) {
-                write!(&mut s, "{}", content.to_string(txn)).unwrap();
-            }
+        buf.push('>');
+        let len = buf.len();
+        write_newline(buf, indent);
+        let start = buf.len();
+        write_children(&inner, txn, buf, indent, depth + 1);
+        if buf.len() == start {
+            // no children: keep closing tag in the same line
+            buf.truncate(len);
+        } else {
+            write_indent(buf, indent, depth);
         }
-        write!(&mut s, "</{}>", tag).unwrap();
-        s
+        write!(buf, "</{}>", tag).unwrap();
+        write_newline(buf, indent);
     }
 
     /// A tag name of a current top-level XML node, eg. node `<p></p>` has "p" as it's tag name.
@@ -71,6 +119,7 @@ impl XmlElement {
 
     /// Removes an attribute recognized by an `attr_name` from a current XML element.
     pub fn remove_attribute(&self, txn: &mut Transaction, attr_name: &str) {
+        self.0 .0.debug_assert_owned_by(txn);
         self.inner().remove(txn, attr_name);
     }
 
@@ -81,6 +130,7 @@ impl XmlElement {
         attr_name: K,
         attr_value: V,
     ) {
+        self.0 .0.debug_assert_owned_by(txn);
         let key = attr_name.to_string();
         let value = crate::block::PrelimText(attr_value.to_string());
         let pos = {
@@ -186,6 +236,56 @@ impl XmlElement {
         self.0.iter(txn)
     }
 
+    /// Returns an iterator over the direct children of a current XML element. Unlike
+    /// [XmlElement::successors], it doesn't descend into nested elements.
+    pub fn children<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> Children<'b, 'txn> {
+        self.0.children(txn)
+    }
+
+    /// Returns an iterator over all successors of a current XML element (see:
+    /// [XmlElement::successors]), which are XML elements with a given `tag` name.
+    pub fn descendants_by_tag<'a, 'b, 'txn>(
+        &'a self,
+        txn: &'b Transaction<'txn>,
+        tag: &str,
+    ) -> FilteredWalker<'b, 'txn, impl FnMut(&XmlElement) -> bool> {
+        let tag = tag.to_string();
+        self.find_all(txn, move |elem| elem.tag() == tag)
+    }
+
+    /// Returns an iterator over all successors of a current XML element (see:
+    /// [XmlElement::successors]), which are XML elements satisfying a given `predicate`.
+    /// Predicate is called with each visited element, so that it can match on its tag name and
+    /// look up only the attributes it needs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let root = txn.get_xml_element("root");
+    /// root.insert_xml_str(&mut txn, 0, r#"<a href="/">home</a><p><a>none</a></p>"#)
+    ///     .unwrap();
+    ///
+    /// let links: Vec<_> = root
+    ///     .find_all(&txn, |e| e.tag() == "a" && e.get_attribute(&txn, "href").is_some())
+    ///     .map(|a| a.to_string(&txn))
+    ///     .collect();
+    /// assert_eq!(links, vec![r#"<a href="/">home</a>"#.to_string()]);
+    /// ```
+    pub fn find_all<'a, 'b, 'txn, F>(
+        &'a self,
+        txn: &'b Transaction<'txn>,
+        predicate: F,
+    ) -> FilteredWalker<'b, 'txn, F>
+    where
+        F: FnMut(&XmlElement) -> bool,
+    {
+        self.0.find_all(txn, predicate)
+    }
+
     /// Inserts another [XmlElement] with a given tag `name` into a current one at the given `index`
     /// and returns it. If `index` is equal to `0`, new element will be inserted as a first child.
     /// If `index` is equal to length of current XML element, new element will be inserted as a last
@@ -216,6 +316,36 @@ impl XmlElement {
         self.0.remove(txn, index, len)
     }
 
+    /// Parses a given `xml` snippet and inserts the nodes it describes (nested [XmlElement]s with
+    /// their attributes and [XmlText]s) into a current XML element, starting at the given `index`.
+    /// Returns a number of inserted top-level nodes.
+    ///
+    /// Snippet is parsed in full before any changes are made, so in case of malformed input an
+    /// error is returned and nothing is inserted. This method will panic if `index` is greater
+    /// than the length of current XML element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let mut txn = doc.transact();
+    /// let root = txn.get_xml_element("root");
+    /// root.insert_xml_str(&mut txn, 0, "<p class=\"title\">Hello <b>world</b></p>").unwrap();
+    ///
+    /// assert!(root.insert_xml_str(&mut txn, 1, "<p>unbalanced").is_err());
+    /// assert_eq!(root.len(&txn), 1);
+    /// ```
+    pub fn insert_xml_str(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        xml: &str,
+    ) -> Result<u32, XmlParseError> {
+        self.0.insert_xml_str(txn, index, xml)
+    }
+
     /// Pushes a new [XmlElement] with a given tag `name` as the last child of a current one and
     /// returns it.
     pub fn push_elem_back<S: ToString>(&self, txn: &mut Transaction, name: S) -> XmlElement {
@@ -243,6 +373,47 @@ impl XmlElement {
     pub fn get(&self, txn: &Transaction, index: u32) -> Option<Xml> {
         self.0.get(txn, index)
     }
+
+    /// Subscribes a given callback to be called whenever attributes or child nodes of a current
+    /// XML element are changed. Callback is called once per transaction, with an [XmlEvent]
+    /// describing all changes made to this element in scope of that transaction. Callback is
+    /// unsubscribed once returned [Subscription] is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use yrs::Doc;
+    ///
+    /// let doc = Doc::new();
+    /// let root = doc.transact().get_xml_element("root");
+    /// let changed = Rc::new(RefCell::new(Vec::new()));
+    /// let c = changed.clone();
+    /// let _sub = root.observe(move |_txn, e| {
+    ///     c.borrow_mut().extend(e.keys.keys().cloned());
+    /// });
+    ///
+    /// root.insert_attribute(&mut doc.transact(), "id", "1");
+    /// assert_eq!(*changed.borrow(), vec!["id".to_string()]);
+    /// ```
+    pub fn observe<F>(&self, f: F) -> Subscription<XmlEvent>
+    where
+        F: Fn(&Transaction, &XmlEvent) -> () + 'static,
+    {
+        self.0.observe(f)
+    }
+
+    /// Subscribes a given callback to be called whenever a current XML element or any of its
+    /// successors is changed. Callback is called once per transaction, with a list of events
+    /// produced by all changed nodes, ordered by their depth. Path of each event leads from
+    /// a current element to the changed node.
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.observe_deep(f)
+    }
 }
 
 impl Into<ItemContent> for XmlElement {
@@ -281,9 +452,15 @@ impl Into<XmlElement> for XmlFragment {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// XML fragment data type. It represents a container of XML nodes - other [XmlElement]s or
+/// [XmlText]s - without any tag name or attributes of its own. It's used ie. as a root type of
+/// y-prosemirror documents. When converted into a string, a fragment is represented by a
+/// concatenation of its children.
+#[derive(Debug, Clone)]
 pub struct XmlFragment(BranchRef);
 
+impl_branch_identity!(XmlFragment);
+
 impl XmlFragment {
     pub fn new(inner: BranchRef) -> Self {
         XmlFragment(inner)
@@ -293,6 +470,8 @@ impl XmlFragment {
         self.0.borrow()
     }
 
+    /// Returns a first child XML node (either [XmlElement] or [XmlText]), that can be found in
+    /// a current XML fragment. Returns `None` if current fragment is empty.
     pub fn first_child(&self, txn: &Transaction) -> Option<Xml> {
         let inner = self.inner();
         let first = inner.first(txn)?;
@@ -305,29 +484,101 @@ impl XmlFragment {
         }
     }
 
+    /// Returns a parent XML element, current node can be found within.
+    /// Returns `None`, if current node is a root.
     pub fn parent(&self, txn: &Transaction) -> Option<XmlElement> {
         parent(self.inner(), txn)
     }
 
+    /// Returns a number of child XML nodes, that can be found inside of a current XML fragment.
+    /// This is a flat count - successor nodes (children of a children) are not counted.
     pub fn len(&self, _txn: &Transaction) -> u32 {
         self.inner().len()
     }
 
+    /// Returns an iterator that can be used to traverse over the successors of a current
+    /// XML fragment. This includes recursive step over children of its children. The recursive
+    /// iteration is depth-first.
     pub fn iter<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> TreeWalker<'b, 'txn> {
         TreeWalker::new(txn, &*self.inner())
     }
 
+    /// Subscribes a given callback to be called whenever child nodes of a current XML fragment are
+    /// changed. See: [XmlElement::observe].
+    pub fn observe<F>(&self, f: F) -> Subscription<XmlEvent>
+    where
+        F: Fn(&Transaction, &XmlEvent) -> () + 'static,
+    {
+        let mut inner = self.0.borrow_mut();
+        let observers = inner
+            .observers
+            .get_or_insert_with(|| Observers::Xml(Observer::new()));
+        match observers {
+            Observers::Xml(observer) => observer.subscribe(f),
+            _ => panic!("Defect: XML node has observers of unexpected type"),
+        }
+    }
+
+    /// Subscribes a given callback to be called whenever a current XML fragment or any of its
+    /// successors is changed. See: [XmlElement::observe_deep].
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.borrow_mut().observe_deep(f)
+    }
+
+    /// Returns an iterator over all successors of a current XML fragment (see:
+    /// [XmlFragment::iter]), which are XML elements satisfying a given `predicate`.
+    /// See: [XmlElement::find_all].
+    pub fn find_all<'a, 'b, 'txn, F>(
+        &'a self,
+        txn: &'b Transaction<'txn>,
+        predicate: F,
+    ) -> FilteredWalker<'b, 'txn, F>
+    where
+        F: FnMut(&XmlElement) -> bool,
+    {
+        FilteredWalker {
+            walker: self.iter(txn),
+            predicate,
+        }
+    }
+
+    /// Returns an iterator over the direct children of a current XML fragment. Unlike
+    /// [XmlFragment::iter], it doesn't descend into nested elements.
+    pub fn children<'a, 'b, 'txn>(&'a self, txn: &'b Transaction<'txn>) -> Children<'b, 'txn> {
+        let inner = self.inner();
+        let current = inner
+            .start
+            .as_ref()
+            .and_then(|p| txn.store.blocks.get_item(p));
+        Children { txn, current }
+    }
+
+    /// Converts current XML fragment into a textual representation, which is a concatenation of
+    /// textual representations of all of its children.
     pub fn to_string(&self, txn: &Transaction) -> String {
         let mut s = String::new();
-        let inner = self.inner();
-        for i in inner.iter(txn) {
-            for content in i.content.get_content(txn) {
-                write!(&mut s, "{}", content.to_string(txn)).unwrap();
-            }
-        }
+        write_children(&self.inner(), txn, &mut s, None, 0);
         s
     }
 
+    /// Converts current XML fragment into a textual representation, in which every node is
+    /// written in a separate line indented by `indent` spaces per nesting level. It's meant to be
+    /// used for debugging purposes.
+    pub fn to_string_pretty(&self, txn: &Transaction, indent: usize) -> String {
+        let mut s = String::new();
+        write_children(&self.inner(), txn, &mut s, Some(indent), 0);
+        s.truncate(s.trim_end_matches('\n').len());
+        s
+    }
+
+    /// Inserts another [XmlElement] with a given tag `name` into a current fragment at the given
+    /// `index` and returns it. If `index` is equal to `0`, new element will be inserted as a first
+    /// child. If `index` is equal to length of current XML fragment, new element will be inserted
+    /// as a last child.
+    /// This method will panic if `index` is greater than the length of current XML fragment.
     pub fn insert_elem<S: ToString>(
         &self,
         txn: &mut Transaction,
@@ -344,6 +595,8 @@ impl XmlFragment {
         }
     }
 
+    /// Inserts a [XmlText] into a current XML fragment at the given `index` and returns it.
+    /// This method will panic if `index` is greater than the length of current XML fragment.
     pub fn insert_text(&self, txn: &mut Transaction, index: u32) -> XmlText {
         let item = self.0.insert_at(txn, index, PrelimXml::Text);
         if let ItemContent::Type(inner) = &item.content {
@@ -353,6 +606,9 @@ impl XmlFragment {
         }
     }
 
+    /// Removes a range (defined by `len`) of XML nodes from the current XML fragment, starting at
+    /// the given `index`. This method will panic if a number of elements removed is lesser than
+    /// the expected one provided in `len` parameter.
     pub fn remove(&self, txn: &mut Transaction, index: u32, len: u32) {
         let removed = self.0.remove_at(txn, index, len);
         if removed != len {
@@ -360,35 +616,92 @@ impl XmlFragment {
         }
     }
 
+    /// Parses a given `xml` snippet and inserts the nodes it describes into a current XML fragment,
+    /// starting at the given `index`. Returns a number of inserted top-level nodes.
+    ///
+    /// Snippet is parsed in full before any changes are made, so in case of malformed input an
+    /// error is returned and nothing is inserted. This method will panic if `index` is greater
+    /// than the length of current XML fragment.
+    pub fn insert_xml_str(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        xml: &str,
+    ) -> Result<u32, XmlParseError> {
+        let nodes = XmlParser::new(xml).parse()?;
+        let mut i = index;
+        for node in nodes {
+            self.insert_node(txn, i, node);
+            i += 1;
+        }
+        Ok(i - index)
+    }
+
+    fn insert_node(&self, txn: &mut Transaction, index: u32, node: XmlNode) {
+        match node {
+            XmlNode::Text(content) => {
+                let text = self.insert_text(txn, index);
+                text.push(txn, &content);
+            }
+            XmlNode::Element {
+                tag,
+                attributes,
+                children,
+            } => {
+                let elem = self.insert_elem(txn, index, tag);
+                for (name, value) in attributes {
+                    elem.insert_attribute(txn, name, value);
+                }
+                for (i, child) in children.into_iter().enumerate() {
+                    elem.0.insert_node(txn, i as u32, child);
+                }
+            }
+        }
+    }
+
+    /// Pushes a new [XmlElement] with a given tag `name` as the last child of a current fragment
+    /// and returns it.
     pub fn push_elem_back<S: ToString>(&self, txn: &mut Transaction, name: S) -> XmlElement {
         let len = self.len(txn);
         self.insert_elem(txn, len, name)
     }
 
+    /// Pushes a new [XmlElement] with a given tag `name` as the first child of a current fragment
+    /// and returns it.
     pub fn push_elem_front<S: ToString>(&self, txn: &mut Transaction, name: S) -> XmlElement {
         self.insert_elem(txn, 0, name)
     }
 
+    /// Pushes a new [XmlText] field as the last child of a current XML fragment and returns it.
     pub fn push_text_back(&self, txn: &mut Transaction) -> XmlText {
         let len = self.len(txn);
         self.insert_text(txn, len)
     }
 
+    /// Pushes a new [XmlText] field as the first child of a current XML fragment and returns it.
     pub fn push_text_front(&self, txn: &mut Transaction) -> XmlText {
         self.insert_text(txn, 0)
     }
 
-    pub fn get<T: From<BranchRef>>(&self, txn: &Transaction, index: u32) -> Option<T> {
+    /// Returns an XML node stored under a given `index` of a current XML fragment.
+    /// Returns `None` if provided `index` is over the range of a current fragment.
+    pub fn get(&self, txn: &Transaction, index: u32) -> Option<Xml> {
         let inner = self.inner();
         let (content, _) = inner.get_at(txn, index)?;
         if let ItemContent::Type(inner) = content {
-            Some(T::from(inner.clone()))
+            Some(Xml::from(inner.clone()))
         } else {
             None
         }
     }
 }
 
+impl From<BranchRef> for XmlFragment {
+    fn from(inner: BranchRef) -> Self {
+        XmlFragment(inner)
+    }
+}
+
 impl Into<ItemContent> for XmlFragment {
     fn into(self) -> ItemContent {
         ItemContent::Type(self.0.clone())
@@ -434,7 +747,8 @@ impl<'a, 'txn> Iterator for TreeWalker<'a, 'txn> {
                         let inner = t.borrow();
                         let type_ref = inner.type_ref();
                         if !current.is_deleted()
-                            && (type_ref == TYPE_REFS_XML_ELEMENT || type_ref == TYPE_REFS_XML_TEXT)
+                            && (type_ref == TYPE_REFS_XML_ELEMENT
+                                || type_ref == TYPE_REFS_XML_FRAGMENT)
                             && inner.start.is_some()
                         {
                             // walk down in the tree
@@ -480,7 +794,57 @@ impl<'a, 'txn> Iterator for TreeWalker<'a, 'txn> {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// An iterator over direct children of an [XmlElement] or [XmlFragment].
+pub struct Children<'a, 'txn> {
+    txn: &'a Transaction<'txn>,
+    current: Option<&'a Item>,
+}
+
+impl<'a, 'txn> Iterator for Children<'a, 'txn> {
+    type Item = Xml;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.current {
+            self.current = item
+                .right
+                .as_ref()
+                .and_then(|p| self.txn.store.blocks.get_item(p));
+            if !item.is_deleted() {
+                if let ItemContent::Type(t) = &item.content {
+                    return Some(Xml::from(t.clone()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over [XmlElement] or [XmlFragment] successors, which returns only elements
+/// satisfying a predicate. It works in a recursive depth-first manner.
+pub struct FilteredWalker<'a, 'txn, F> {
+    walker: TreeWalker<'a, 'txn>,
+    predicate: F,
+}
+
+impl<'a, 'txn, F> Iterator for FilteredWalker<'a, 'txn, F>
+where
+    F: FnMut(&XmlElement) -> bool,
+{
+    type Item = XmlElement;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.walker.next() {
+            if let Xml::Element(elem) = node {
+                if (self.predicate)(&elem) {
+                    return Some(elem);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct XmlHook(Map);
 
 impl XmlHook {
@@ -488,8 +852,8 @@ impl XmlHook {
         XmlHook(map)
     }
 
-    pub fn len(&self, txn: &Transaction) -> u32 {
-        self.0.len(txn)
+    pub fn len(&self) -> u32 {
+        self.0.len()
     }
 
     pub fn to_json(&self, txn: &Transaction) -> Any {
@@ -552,7 +916,7 @@ impl Into<XmlHook> for Map {
 
 /// A XML node that represent a raw text stored inside of a [XmlElement]. It has collaborative,
 /// conflict-free features of a [Text] data type.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct XmlText(Text);
 
 impl XmlText {
@@ -560,12 +924,16 @@ impl XmlText {
         self.0.inner()
     }
 
-    /// Returns a string representation of a current XML text.
+    /// Returns a string representation of a current XML text. Markup characters (`<`, `>` and
+    /// `&`) are escaped.
     pub fn to_string(&self, txn: &Transaction) -> String {
-        self.0.to_string(txn)
+        let mut s = String::new();
+        escape_xml(&self.0.to_string(txn), false, &mut s);
+        s
     }
 
     pub fn remove_attribute(&self, txn: &mut Transaction, attr_name: &str) {
+        self.0.branch().debug_assert_owned_by(txn);
         self.inner().remove(txn, attr_name);
     }
 
@@ -575,6 +943,7 @@ impl XmlText {
         attr_name: K,
         attr_value: V,
     ) {
+        self.0.branch().debug_assert_owned_by(txn);
         let key = attr_name.to_string();
         let value = crate::block::PrelimText(attr_value.to_string());
         let pos = {
@@ -626,15 +995,79 @@ impl XmlText {
     /// Inserts a new string `content` into this XML text structure at the given `index`.
     /// This method may panic if `index` if greater than a length of this text.
     pub fn insert(&self, txn: &mut Transaction, index: u32, content: &str) {
-        if let Some(mut pos) = self.0.find_position(txn, index) {
-            let parent = { TypePtr::Id(self.inner().item.unwrap()) };
-            pos.parent = parent;
-            txn.create_item(&pos, crate::block::PrelimText(content.to_owned()), None);
-        } else {
-            panic!("Cannot insert string content into an XML text: provided index is outside of the current text range!");
+        self.0.insert(txn, index, content)
+    }
+
+    /// Inserts a new string `content` into this XML text structure at the given `index`, formatted
+    /// with given `attributes`. Formatting attributes are used ie. by y-prosemirror to store marks,
+    /// such as bold or italic. See: [Text::insert_with_attributes].
+    /// This method may panic if `index` if greater than a length of this text.
+    pub fn insert_with_attributes(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        content: &str,
+        attributes: Attrs,
+    ) {
+        self.0
+            .insert_with_attributes(txn, index, content, attributes)
+    }
+
+    /// Inserts an embedded `content` into this XML text structure at the given `index`. See:
+    /// [Text::insert_embed].
+    /// This method may panic if `index` if greater than a length of this text.
+    pub fn insert_embed(
+        &self,
+        txn: &mut Transaction,
+        index: u32,
+        content: Any,
+        attributes: Option<Attrs>,
+    ) {
+        self.0.insert_embed(txn, index, content, attributes)
+    }
+
+    /// Applies formatting `attributes` over a range of `len` characters of this XML text structure,
+    /// starting at given `index`. See: [Text::format].
+    /// This method may panic if `index` if greater than a length of this text.
+    pub fn format(&self, txn: &mut Transaction, index: u32, len: u32, attributes: Attrs) {
+        self.0.format(txn, index, len, attributes)
+    }
+
+    /// Returns a content of this XML text structure as a list of chunks, each one with the same set
+    /// of formatting attributes applied. See: [Text::diff].
+    pub fn diff(&self, txn: &Transaction) -> Vec<Diff> {
+        self.0.diff(txn)
+    }
+
+    /// Subscribes a given callback to be called whenever a content or attributes of this XML text
+    /// structure are changed. Callback is called once per transaction, with an [XmlTextEvent]
+    /// describing all changes made in scope of that transaction. Callback is unsubscribed once
+    /// returned [Subscription] is dropped.
+    pub fn observe<F>(&self, f: F) -> Subscription<XmlTextEvent>
+    where
+        F: Fn(&Transaction, &XmlTextEvent) -> () + 'static,
+    {
+        let branch = self.0.branch();
+        let mut inner = branch.borrow_mut();
+        let observers = inner
+            .observers
+            .get_or_insert_with(|| Observers::XmlText(Observer::new()));
+        match observers {
+            Observers::XmlText(observer) => observer.subscribe(f),
+            _ => panic!("Defect: XML text has observers of unexpected type"),
         }
     }
 
+    /// Subscribes a given callback to be called whenever this XML text structure is changed. Since
+    /// XML text has no child nodes, events passed to a callback always have an empty path.
+    /// See: [XmlElement::observe_deep].
+    pub fn observe_deep<F>(&self, f: F) -> Subscription<Vec<Event>>
+    where
+        F: Fn(&Transaction, &Vec<Event>) -> () + 'static,
+    {
+        self.0.branch().borrow_mut().observe_deep(f)
+    }
+
     /// Appends a new string `content` at the end of this XML text structure.
     pub fn push(&self, txn: &mut Transaction, content: &str) {
         let len = self.len(txn);
@@ -686,6 +1119,99 @@ impl Prelim for PrelimXml {
     fn integrate(self, txn: &mut Transaction, inner_ref: BranchRef) {}
 }
 
+/// An event produced by [XmlElement] or [XmlFragment] when their attributes or child nodes have
+/// been changed in scope of a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlEvent {
+    /// XML node that has been changed: either [Value::YXmlElement] or [Value::YXmlFragment].
+    pub target: Value,
+    /// Path from an observed node to the changed one. It's empty unless event has been passed to
+    /// a deep observer of one of the changed node's parents.
+    pub path: Path,
+    /// Changes made to attributes of a changed node, keyed by attribute name.
+    pub keys: HashMap<String, EntryChange>,
+    /// Changes made to child nodes of a changed node.
+    pub delta: Vec<Change>,
+}
+
+impl XmlEvent {
+    pub(crate) fn new(
+        txn: &Transaction,
+        branch: BranchRef,
+        keys: &HashSet<Option<String>>,
+    ) -> Self {
+        let (keys, delta) = {
+            let inner = branch.borrow();
+            (event_keys(txn, &inner, keys), event_changes(txn, &inner))
+        };
+        XmlEvent {
+            target: branch.into_value(txn),
+            path: Path::new(),
+            keys,
+            delta,
+        }
+    }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        match &self.target {
+            Value::YXmlElement(e) => e.inner().ptr.clone(),
+            Value::YXmlFragment(f) => f.inner().ptr.clone(),
+            _ => TypePtr::Unknown,
+        }
+    }
+
+    /// Returns true if attributes of a changed node have been modified.
+    pub fn attributes_changed(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Returns true if child nodes of a changed node have been inserted or removed.
+    pub fn children_changed(&self) -> bool {
+        !self.delta.is_empty()
+    }
+}
+
+/// An event produced by [XmlText] when its content or attributes have been changed in scope of
+/// a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlTextEvent {
+    /// XML text that has been changed.
+    pub target: XmlText,
+    /// Path from an observed node to the changed one. It's empty unless event has been passed to
+    /// a deep observer of one of the changed node's parents.
+    pub path: Path,
+    /// Changes made to attributes of a changed XML text, keyed by attribute name.
+    pub keys: HashMap<String, EntryChange>,
+    /// Changes made to a text content, including its formatting.
+    pub delta: Vec<Delta>,
+}
+
+impl XmlTextEvent {
+    pub(crate) fn new(
+        txn: &Transaction,
+        branch: BranchRef,
+        keys: &HashSet<Option<String>>,
+    ) -> Self {
+        let (keys, delta) = {
+            let inner = branch.borrow();
+            (
+                event_keys(txn, &inner, keys),
+                text::event_delta(txn, &inner),
+            )
+        };
+        XmlTextEvent {
+            target: XmlText::from(branch),
+            path: Path::new(),
+            keys,
+            delta,
+        }
+    }
+
+    pub(crate) fn target_ptr(&self) -> TypePtr {
+        self.target.inner().ptr.clone()
+    }
+}
+
 fn next_sibling(inner: Ref<Branch>, txn: &Transaction) -> Option<Xml> {
     let mut current = inner
         .item
@@ -736,10 +1262,74 @@ fn parent(inner: Ref<Branch>, txn: &Transaction) -> Option<XmlElement> {
     Some(XmlElement::from(parent.clone()))
 }
 
+/// Writes textual representation of all non-deleted children of a given XML branch into `buf`.
+/// If `indent` is provided, every child is written in a separate line.
+fn write_children(
+    inner: &Branch,
+    txn: &Transaction,
+    buf: &mut String,
+    indent: Option<usize>,
+    depth: usize,
+) {
+    for i in inner.iter(txn) {
+        if i.is_deleted() {
+            continue;
+        }
+        for content in i.content.get_content(txn) {
+            match content {
+                Value::YXmlElement(elem) => elem.write_xml(txn, buf, indent, depth),
+                other => {
+                    write_indent(buf, indent, depth);
+                    match other {
+                        Value::YXmlText(text) => buf.push_str(&text.to_string(txn)),
+                        other => escape_xml(&other.to_string(txn), false, buf),
+                    }
+                    write_newline(buf, indent);
+                }
+            }
+        }
+    }
+}
+
+fn write_indent(buf: &mut String, indent: Option<usize>, depth: usize) {
+    if let Some(indent) = indent {
+        buf.extend(std::iter::repeat(' ').take(indent * depth));
+    }
+}
+
+fn write_newline(buf: &mut String, indent: Option<usize>) {
+    if indent.is_some() {
+        buf.push('\n');
+    }
+}
+
+/// Appends `value` to `buf`, escaping characters which have special meaning in XML. Quotes are
+/// escaped only when `attribute` flag is set, as they are meaningful only inside of attribute
+/// values.
+fn escape_xml(value: &str, attribute: bool, buf: &mut String) {
+    for c in value.chars() {
+        match c {
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '&' => buf.push_str("&amp;"),
+            '"' if attribute => buf.push_str("&quot;"),
+            '\'' if attribute => buf.push_str("&apos;"),
+            c => buf.push(c),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::types::text::Diff;
     use crate::types::xml::Xml;
+    use crate::types::{Change, Delta, EntryChange, Event, PathSegment, Value};
     use crate::Doc;
+    use crate::XmlFragment;
+    use lib0::any::Any;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
 
     #[test]
     fn insert_attribute() {
@@ -752,7 +1342,8 @@ mod test {
         let d2 = Doc::with_client_id(1);
         let mut t2 = d2.transact();
         let xml2 = t2.get_xml_element("xml");
-        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice());
+        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice())
+            .unwrap();
         assert_eq!(xml2.get_attribute(&t2, "height"), Some("10".to_string()));
     }
 
@@ -855,7 +1446,7 @@ mod test {
         let mut t2 = d2.transact();
         let r2 = t2.get_xml_element("root");
 
-        d2.apply_update_v1(&mut t2, u1.as_slice());
+        d2.apply_update_v1(&mut t2, u1.as_slice()).unwrap();
         assert_eq!(r2.to_string(&t2), expected);
     }
 
@@ -887,4 +1478,584 @@ mod test {
         let u1 = d1.encode_state_as_update_v1(&t1);
         assert_eq!(u1.as_slice(), expected);
     }
+
+    #[test]
+    fn fragment_children() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let f1 = t1.get_xml_fragment("prosemirror");
+        let p = f1.push_elem_back(&mut t1, "paragraph");
+        let txt = p.push_text_back(&mut t1);
+        txt.push(&mut t1, "hello");
+        let txt = f1.push_text_front(&mut t1);
+        txt.push(&mut t1, "world");
+        f1.insert_elem(&mut t1, 1, "hr");
+
+        assert_eq!(f1.len(&t1), 3);
+        assert_eq!(f1.first_child(&t1), Some(Xml::Text(txt)));
+        assert_eq!(f1.get(&t1, 2), Some(Xml::Element(p)));
+        assert_eq!(f1.parent(&t1), None);
+        assert_eq!(
+            f1.to_string(&t1),
+            "world<hr></hr><paragraph>hello</paragraph>"
+        );
+        let tags: Vec<_> = f1
+            .iter(&t1)
+            .filter_map(|n| match n {
+                Xml::Element(e) => Some(e.tag().to_string()),
+                Xml::Text(_) => None,
+            })
+            .collect();
+        assert_eq!(tags, vec!["hr".to_string(), "paragraph".to_string()]);
+
+        f1.remove(&mut t1, 0, 2);
+        assert_eq!(f1.len(&t1), 1);
+        assert_eq!(f1.to_string(&t1), "<paragraph>hello</paragraph>");
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice())
+            .unwrap();
+        let f2: XmlFragment = t2.get_xml_fragment("prosemirror");
+        assert_eq!(f2.to_string(&t2), "<paragraph>hello</paragraph>");
+    }
+
+    #[test]
+    fn fragment_reads_yjs_update() {
+        /* Generated via (see: `compatibility_tests::xml_fragment_insert`):
+            ```js
+               const ydoc = new Y.Doc()
+               const yxmlFragment = ydoc.getXmlFragment('fragment-name')
+               const yxmlText = new Y.XmlText()
+               yxmlFragment.insert(0, [yxmlText])
+               yxmlFragment.insertAfter(yxmlText, [new Y.XmlElement('node-name')])
+            ```
+        */
+        let update = &[
+            1, 2, 219, 173, 215, 246, 1, 0, 7, 1, 13, 102, 114, 97, 103, 109, 101, 110, 116, 45,
+            110, 97, 109, 101, 6, 135, 219, 173, 215, 246, 1, 0, 3, 9, 110, 111, 100, 101, 45, 110,
+            97, 109, 101, 0,
+        ];
+        const CLIENT_ID: u64 = 517330651;
+        let doc = Doc::with_client_id(2);
+        let mut txn = doc.transact();
+        doc.apply_update_v1(&mut txn, update).unwrap();
+        let root = txn.get_xml_fragment("fragment-name");
+        assert_eq!(root.to_string(&txn), "<node-name></node-name>");
+        assert_eq!(root.len(&txn), 2);
+        assert!(matches!(root.first_child(&txn), Some(Xml::Text(_))));
+
+        // the same changes made to a fragment root by yrs are encoded the same way
+        let d1 = Doc::with_client_id(CLIENT_ID);
+        let mut t1 = d1.transact();
+        let f1 = t1.get_xml_fragment("fragment-name");
+        f1.push_text_back(&mut t1);
+        f1.push_elem_back(&mut t1, "node-name");
+        assert_eq!(d1.encode_state_as_update_v1(&t1).as_slice(), update);
+    }
+
+    #[test]
+    fn insert_xml_str() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let root = t1.get_xml_element("root");
+        let inserted = root
+            .insert_xml_str(
+                &mut t1,
+                0,
+                r#"<ul id="list" class='a b'><li>first &amp; <b>bold</b></li><li/></ul>tail"#,
+            )
+            .unwrap();
+        assert_eq!(inserted, 2);
+        assert_eq!(root.len(&t1), 2);
+
+        let ul = match root.first_child(&t1) {
+            Some(Xml::Element(e)) => e,
+            other => panic!("expected <ul> element, found {:?}", other),
+        };
+        assert_eq!(ul.tag(), "ul");
+        assert_eq!(ul.get_attribute(&t1, "id"), Some("list".to_string()));
+        assert_eq!(ul.get_attribute(&t1, "class"), Some("a b".to_string()));
+        assert_eq!(ul.len(&t1), 2);
+
+        // round trip: attributes are ordered by name, self-closing tags get explicit closing tag
+        let expected = r#"<UNDEFINED><ul class="a b" id="list"><li>first &amp; <b>bold</b></li><li></li></ul>tail</UNDEFINED>"#;
+        assert_eq!(root.to_string(&t1), expected);
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let root2 = t2.get_xml_element("root");
+        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice())
+            .unwrap();
+        assert_eq!(root2.to_string(&t2), expected);
+    }
+
+    #[test]
+    fn insert_xml_str_normalization() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let f = txn.get_xml_fragment("fragment");
+        let xml = r#"<?xml version="1.0"?><!-- comment --><p b="2"  a="1" >x<![CDATA[<y>]]>&#65;&#x42;</p >"#;
+        f.insert_xml_str(&mut txn, 0, xml).unwrap();
+        let normalized = f.to_string(&txn);
+        assert_eq!(normalized, r#"<p a="1" b="2">x&lt;y&gt;AB</p>"#);
+
+        // parsing normalized output again yields the same structure
+        let f2 = txn.get_xml_fragment("fragment2");
+        f2.insert_xml_str(&mut txn, 0, r#"<p a="1" b="2">x</p>"#)
+            .unwrap();
+        assert_eq!(f2.to_string(&txn), r#"<p a="1" b="2">x</p>"#);
+    }
+
+    #[test]
+    fn insert_xml_str_errors() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let root = txn.get_xml_element("root");
+        root.push_elem_back(&mut txn, "existing");
+
+        for xml in [
+            "<p>unbalanced",
+            "<p><b></p></b>",
+            "</p>",
+            "<p>&unknown;</p>",
+            "<p>&amp</p>",
+            "<p a=1></p>",
+            "<p a=\"1\" a=\"2\"></p>",
+            "<p",
+            "<p>ok</p><!-- unterminated",
+        ] {
+            let result = root.insert_xml_str(&mut txn, 0, xml);
+            assert!(result.is_err(), "'{}' should not be parsed", xml);
+        }
+
+        let err = root.insert_xml_str(&mut txn, 0, "<a><b></a>").unwrap_err();
+        assert_eq!(err.message, "expected closing tag </b> but found </a>");
+
+        // nothing should be inserted
+        assert_eq!(root.len(&txn), 1);
+        assert_eq!(
+            root.to_string(&txn),
+            "<UNDEFINED><existing></existing></UNDEFINED>"
+        );
+    }
+
+    #[test]
+    fn to_string_escaping() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let root = txn.get_xml_element("root");
+        let p = root.push_elem_back(&mut txn, "p");
+        p.insert_attribute(&mut txn, "title", "say \"hi\" & 'bye'");
+        let text = p.push_text_back(&mut txn);
+        text.push(&mut txn, "<script>&\"'");
+
+        let expected = r#"<UNDEFINED><p title="say &quot;hi&quot; &amp; &apos;bye&apos;">&lt;script&gt;&amp;"'</p></UNDEFINED>"#;
+        assert_eq!(root.to_string(&txn), expected);
+        assert_eq!(text.to_string(&txn), "&lt;script&gt;&amp;\"'");
+
+        // escaped output can be parsed back into the same content
+        let f = txn.get_xml_fragment("copy");
+        let xml = p.to_string(&txn);
+        f.insert_xml_str(&mut txn, 0, &xml).unwrap();
+        let copy = match f.first_child(&txn) {
+            Some(Xml::Element(e)) => e,
+            other => panic!("expected <p> element, found {:?}", other),
+        };
+        assert_eq!(
+            copy.get_attribute(&txn, "title"),
+            Some("say \"hi\" & 'bye'".to_string())
+        );
+        assert_eq!(f.to_string(&txn), xml);
+    }
+
+    #[test]
+    fn to_string_pretty() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let f = txn.get_xml_fragment("fragment");
+        f.insert_xml_str(
+            &mut txn,
+            0,
+            r#"<div id="a"><p>Hello <b>world</b></p><br/></div>tail"#,
+        )
+        .unwrap();
+
+        let expected = "<div id=\"a\">\n  <p>\n    Hello \n    <b>\n      world\n    </b>\n  </p>\n  <br></br>\n</div>\ntail";
+        assert_eq!(f.to_string_pretty(&txn, 2), expected);
+        assert_eq!(
+            f.to_string_pretty(&txn, 0),
+            "<div id=\"a\">\n<p>\nHello \n<b>\nworld\n</b>\n</p>\n<br></br>\n</div>\ntail"
+        );
+
+        // flat output is unaffected
+        assert_eq!(
+            f.to_string(&txn),
+            r#"<div id="a"><p>Hello <b>world</b></p><br></br></div>tail"#
+        );
+    }
+
+    #[test]
+    fn query_helpers() {
+        let doc = Doc::with_client_id(1);
+        let mut txn = doc.transact();
+        let root = txn.get_xml_element("root");
+        root.insert_xml_str(
+            &mut txn,
+            0,
+            r#"<ul class="menu"><li class="active"><a href="/">home</a></li><li><a>about</a><ul><li class="">nested</li></ul></li></ul>footer<a href="/contact">contact</a>"#,
+        )
+        .unwrap();
+
+        let children: Vec<_> = root
+            .children(&txn)
+            .map(|n| match n {
+                Xml::Element(e) => e.tag().to_string(),
+                Xml::Text(t) => t.to_string(&txn),
+            })
+            .collect();
+        assert_eq!(children, vec!["ul", "footer", "a"]);
+
+        let items: Vec<_> = root
+            .descendants_by_tag(&txn, "li")
+            .map(|li| li.to_string(&txn))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                r#"<li class="active"><a href="/">home</a></li>"#,
+                r#"<li><a>about</a><ul><li class="">nested</li></ul></li>"#,
+                r#"<li class="">nested</li>"#,
+            ]
+        );
+
+        // attribute presence
+        let with_class = root
+            .find_all(&txn, |e| {
+                e.tag() == "li" && e.get_attribute(&txn, "class").is_some()
+            })
+            .count();
+        assert_eq!(with_class, 2);
+
+        // attribute value
+        let active: Vec<_> = root
+            .find_all(&txn, |e| {
+                e.get_attribute(&txn, "class").as_deref() == Some("active")
+            })
+            .map(|e| e.tag().to_string())
+            .collect();
+        assert_eq!(active, vec!["li"]);
+
+        let links: Vec<_> = root
+            .find_all(&txn, |e| {
+                e.tag() == "a" && e.get_attribute(&txn, "href").is_some()
+            })
+            .map(|a| a.get_attribute(&txn, "href").unwrap())
+            .collect();
+        assert_eq!(links, vec!["/", "/contact"]);
+
+        let fragment = txn.get_xml_fragment("fragment");
+        fragment
+            .insert_xml_str(&mut txn, 0, r#"<p><a href="/">home</a></p><a>none</a>"#)
+            .unwrap();
+        let links: Vec<_> = fragment
+            .find_all(&txn, |e| e.get_attribute(&txn, "href").is_some())
+            .map(|a| a.to_string(&txn))
+            .collect();
+        assert_eq!(links, vec![r#"<a href="/">home</a>"#]);
+
+        // deleted nodes are not visited
+        root.remove(&mut txn, 0, 1);
+        assert_eq!(root.children(&txn).count(), 2);
+        assert_eq!(root.descendants_by_tag(&txn, "li").count(), 0);
+    }
+
+    #[test]
+    fn xml_text_formatting() {
+        // shape of a ProseMirror document with a bold mark, as stored by y-prosemirror:
+        // <paragraph>Hello <bold>world</bold></paragraph>
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let f1 = t1.get_xml_fragment("prosemirror");
+        let p = f1.push_elem_back(&mut t1, "paragraph");
+        let text = p.push_text_back(&mut t1);
+        text.insert(&mut t1, 0, "Hello ");
+        let mut bold = HashMap::new();
+        bold.insert("bold".to_string(), Any::Map(HashMap::new()));
+        text.insert_with_attributes(&mut t1, 6, "world", bold.clone());
+        text.insert_embed(&mut t1, 11, Any::String("img".to_string()), None);
+
+        let expected = vec![
+            Diff::new(Value::Any(Any::String("Hello ".to_string())), None),
+            Diff::new(
+                Value::Any(Any::String("world".to_string())),
+                Some(Box::new(bold.clone())),
+            ),
+            Diff::new(Value::Any(Any::String("img".to_string())), None),
+        ];
+        assert_eq!(text.diff(&t1), expected);
+        assert_eq!(text.len(&t1), 12);
+
+        // round trip through an update
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let f2 = t2.get_xml_fragment("prosemirror");
+        d2.apply_update_v1(&mut t2, d1.encode_state_as_update_v1(&t1).as_slice())
+            .unwrap();
+        let text2 = match f2.first_child(&t2) {
+            Some(Xml::Element(p)) => match p.first_child(&t2) {
+                Some(Xml::Text(text)) => text,
+                other => panic!("expected text, found {:?}", other),
+            },
+            other => panic!("expected paragraph, found {:?}", other),
+        };
+        assert_eq!(text2.diff(&t2), expected);
+
+        // remove the mark
+        let mut unbold = HashMap::new();
+        unbold.insert("bold".to_string(), Any::Null);
+        text2.format(&mut t2, 0, 11, unbold);
+        assert_eq!(
+            text2.diff(&t2),
+            vec![
+                Diff::new(Value::Any(Any::String("Hello world".to_string())), None),
+                Diff::new(Value::Any(Any::String("img".to_string())), None),
+            ]
+        );
+    }
+
+    fn str_value(s: &str) -> Value {
+        Value::Any(Any::String(s.to_string()))
+    }
+
+    #[test]
+    fn observe_attributes_and_children() {
+        let doc = Doc::with_client_id(1);
+        let root = doc.transact().get_xml_element("root");
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let e = events.clone();
+        let sub = root.observe(move |_, event| e.borrow_mut().push(event.clone()));
+
+        // a single transaction both setting an attribute and inserting a child
+        let p = {
+            let mut txn = doc.transact();
+            root.insert_attribute(&mut txn, "class", "a");
+            root.push_elem_back(&mut txn, "p")
+        };
+        {
+            let events = events.borrow();
+            assert_eq!(events.len(), 1);
+            let event = &events[0];
+            assert!(event.attributes_changed());
+            assert!(event.children_changed());
+            assert_eq!(event.target, Value::YXmlElement(root.clone()));
+            assert!(event.path.is_empty());
+            assert_eq!(
+                event.keys.get("class"),
+                Some(&EntryChange::Inserted(str_value("a")))
+            );
+            assert_eq!(
+                event.delta,
+                vec![Change::Added(vec![Value::YXmlElement(p.clone())])]
+            );
+        }
+
+        // attribute update and removal, child removal
+        events.borrow_mut().clear();
+        {
+            let mut txn = doc.transact();
+            root.insert_attribute(&mut txn, "id", "1");
+        }
+        events.borrow_mut().clear();
+        {
+            let mut txn = doc.transact();
+            root.insert_attribute(&mut txn, "class", "b");
+            root.remove_attribute(&mut txn, "id");
+            root.push_elem_front(&mut txn, "h1");
+            root.remove(&mut txn, 1, 1);
+        }
+        {
+            let events = events.borrow();
+            assert_eq!(events.len(), 1);
+            let event = &events[0];
+            assert_eq!(
+                event.keys.get("class"),
+                Some(&EntryChange::Updated(str_value("a"), str_value("b")))
+            );
+            assert_eq!(
+                event.keys.get("id"),
+                Some(&EntryChange::Removed(str_value("1")))
+            );
+            let h1 = root.first_child(&doc.transact()).unwrap();
+            let h1 = match h1 {
+                Xml::Element(h1) => h1,
+                other => panic!("expected element, found {:?}", other),
+            };
+            assert_eq!(
+                event.delta,
+                vec![
+                    Change::Added(vec![Value::YXmlElement(h1)]),
+                    Change::Removed(1)
+                ]
+            );
+        }
+
+        // no more events after unsubscribing
+        events.borrow_mut().clear();
+        drop(sub);
+        root.insert_attribute(&mut doc.transact(), "class", "c");
+        assert!(events.borrow().is_empty());
+    }
+
+    #[test]
+    fn observe_xml_text() {
+        let doc = Doc::with_client_id(1);
+        let text = {
+            let mut txn = doc.transact();
+            let root = txn.get_xml_element("root");
+            let text = root.push_text_back(&mut txn);
+            text.insert(&mut txn, 0, "hello world");
+            text
+        };
+        let deltas = Rc::new(RefCell::new(Vec::new()));
+        let d = deltas.clone();
+        let _sub = text.observe(move |_, e| d.borrow_mut().push(e.delta.clone()));
+
+        {
+            let mut txn = doc.transact();
+            text.remove(&mut txn, 0, 6);
+            text.insert(&mut txn, 5, "!");
+        }
+        let mut bold = HashMap::new();
+        bold.insert("bold".to_string(), Any::Bool(true));
+        text.format(&mut doc.transact(), 0, 5, bold.clone());
+
+        assert_eq!(
+            *deltas.borrow(),
+            vec![
+                vec![
+                    Delta::Deleted(6),
+                    Delta::Retain(5, None),
+                    Delta::Inserted(str_value("!"), None)
+                ],
+                vec![Delta::Retain(5, Some(Box::new(bold)))],
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_deep() {
+        let doc = Doc::with_client_id(1);
+        let (root, ul, li) = {
+            let mut txn = doc.transact();
+            let root = txn.get_xml_fragment("root");
+            root.push_elem_back(&mut txn, "h1");
+            let ul = root.push_elem_back(&mut txn, "ul");
+            let li = ul.push_elem_back(&mut txn, "li");
+            (root, ul, li)
+        };
+        let paths = Rc::new(RefCell::new(Vec::new()));
+        let p = paths.clone();
+        let _sub = root.observe_deep(move |_, events| {
+            let batch: Vec<_> = events
+                .iter()
+                .map(|e| match e {
+                    Event::Xml(e) => (e.path.clone(), e.target.clone()),
+                    Event::XmlText(e) => (e.path.clone(), Value::YXmlText(e.target.clone())),
+                    other => panic!("unexpected event: {:?}", other),
+                })
+                .collect();
+            p.borrow_mut().push(batch);
+        });
+
+        let text = {
+            let mut txn = doc.transact();
+            li.insert_attribute(&mut txn, "class", "active");
+            root.push_elem_back(&mut txn, "footer");
+            ul.push_text_back(&mut txn)
+        };
+        text.push(&mut doc.transact(), "text");
+
+        assert_eq!(
+            *paths.borrow(),
+            vec![
+                vec![
+                    (vec![], Value::YXmlFragment(root.clone())),
+                    (vec![PathSegment::Index(1)], Value::YXmlElement(ul.clone())),
+                    (
+                        vec![PathSegment::Index(1), PathSegment::Index(0)],
+                        Value::YXmlElement(li.clone())
+                    ),
+                ],
+                vec![(
+                    vec![PathSegment::Index(1), PathSegment::Index(1)],
+                    Value::YXmlText(text.clone())
+                )],
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_deep_paths_at_transaction_end() {
+        let doc = Doc::with_client_id(1);
+        let (root, li) = {
+            let mut txn = doc.transact();
+            let root = txn.get_xml_fragment("root");
+            root.push_elem_back(&mut txn, "h1");
+            root.push_elem_back(&mut txn, "p");
+            let ul = root.push_elem_back(&mut txn, "ul");
+            ul.push_elem_back(&mut txn, "li");
+            let li = ul.push_elem_back(&mut txn, "li");
+            (root, li)
+        };
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let e = events.clone();
+        let _sub = root.observe_deep(move |_, events| {
+            let batch: Vec<_> = events
+                .iter()
+                .map(|e| (e.path().clone(), e.target()))
+                .collect();
+            e.borrow_mut().extend(batch);
+        });
+
+        {
+            let mut txn = doc.transact();
+            li.insert_attribute(&mut txn, "class", "active");
+            // shift the list: paths should reflect its index at the end of a transaction
+            root.remove(&mut txn, 0, 2);
+            root.push_elem_front(&mut txn, "header");
+            root.push_elem_front(&mut txn, "nav");
+        }
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], (vec![], Value::YXmlFragment(root.clone())));
+        assert_eq!(
+            events[1],
+            (
+                vec![PathSegment::Index(2), PathSegment::Index(1)],
+                Value::YXmlElement(li.clone())
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn insert_attribute_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let xml = d1.transact().get_xml_element("xml");
+        let mut txn = d2.transact();
+        txn.get_xml_element("xml");
+        xml.insert_attribute(&mut txn, "key", "value");
+    }
+
+    #[test]
+    #[should_panic(expected = "different document")]
+    fn insert_child_with_foreign_transaction() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        let xml = d1.transact().get_xml_element("xml");
+        xml.push_elem_back(&mut d2.transact(), "div");
+    }
 }
@@ -0,0 +1,248 @@
+/// Error returned when a string could not be parsed as an XML snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlParseError {
+    /// Byte offset within parsed string at which the error occurred.
+    pub index: usize,
+    /// Description of an error.
+    pub message: String,
+}
+
+impl std::fmt::Display for XmlParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at index {}", self.message, self.index)
+    }
+}
+
+impl std::error::Error for XmlParseError {}
+
+/// An intermediate representation of a parsed XML node. Parsed snippet is materialized in full
+/// before being inserted into a document, so that parsing errors never leave partially inserted
+/// content behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum XmlNode {
+    Element {
+        tag: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<XmlNode>,
+    },
+    Text(String),
+}
+
+/// A simple parser of well-formed XML snippets. It supports elements (including self-closing
+/// ones), attributes quoted with either single or double quotes, text content with predefined
+/// and numeric character entities, CDATA sections, comments and processing instructions (the
+/// latter two are skipped). A snippet may contain many top-level nodes.
+pub(crate) struct XmlParser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        XmlParser { input, pos: 0 }
+    }
+
+    /// Parses an entire input into a list of top-level XML nodes.
+    pub fn parse(mut self) -> Result<Vec<XmlNode>, XmlParseError> {
+        let nodes = self.parse_nodes(None)?;
+        if self.pos != self.input.len() {
+            Err(self.error("unexpected closing tag"))
+        } else {
+            Ok(nodes)
+        }
+    }
+
+    fn error<S: ToString>(&self, message: S) -> XmlParseError {
+        XmlParseError {
+            index: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_until(&mut self, terminator: &str) -> Result<&'a str, XmlParseError> {
+        let rest = self.rest();
+        match rest.find(terminator) {
+            Some(i) => {
+                self.pos += i + terminator.len();
+                Ok(&rest[..i])
+            }
+            None => Err(self.error(format!("missing '{}'", terminator))),
+        }
+    }
+
+    /// Parses a sequence of sibling nodes, until a closing tag of a `parent` element is found.
+    fn parse_nodes(&mut self, parent: Option<&str>) -> Result<Vec<XmlNode>, XmlParseError> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        while self.pos < self.input.len() {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                break;
+            } else if rest.starts_with("<!--") {
+                self.pos += 4;
+                self.skip_until("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                text.push_str(self.skip_until("]]>")?);
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                self.pos += 2;
+                self.skip_until(">")?;
+            } else if rest.starts_with('<') {
+                if !text.is_empty() {
+                    nodes.push(XmlNode::Text(std::mem::take(&mut text)));
+                }
+                nodes.push(self.parse_element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let start = self.pos;
+                self.pos += end;
+                decode_entities(&rest[..end], start, &mut text)?;
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(XmlNode::Text(text));
+        }
+
+        if self.pos == self.input.len() {
+            if let Some(tag) = parent {
+                return Err(self.error(format!("missing closing tag for <{}>", tag)));
+            }
+        }
+        Ok(nodes)
+    }
+
+    fn parse_name(&mut self) -> Result<&'a str, XmlParseError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        if end == 0 {
+            Err(self.error("expected a name"))
+        } else {
+            self.pos += end;
+            Ok(&rest[..end])
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<XmlNode, XmlParseError> {
+        self.pos += 1; // skip '<'
+        let tag = self.parse_name()?.to_string();
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(XmlNode::Element {
+                    tag,
+                    attributes,
+                    children: Vec::new(),
+                });
+            } else if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            } else if rest.is_empty() {
+                return Err(self.error(format!("unterminated tag <{}>", tag)));
+            }
+
+            let name = self.parse_name()?.to_string();
+            if attributes.iter().any(|(k, _)| k == &name) {
+                return Err(self.error(format!("duplicate attribute '{}'", name)));
+            }
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error(format!("expected '=' after attribute '{}'", name)));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(c @ '"') | Some(c @ '\'') => c,
+                _ => return Err(self.error("expected quoted attribute value")),
+            };
+            self.pos += 1;
+            let start = self.pos;
+            let raw = self.skip_until(if quote == '"' { "\"" } else { "'" })?;
+            if raw.contains('<') {
+                return Err(XmlParseError {
+                    index: start,
+                    message: "'<' is not allowed in attribute values".to_string(),
+                });
+            }
+            let mut value = String::with_capacity(raw.len());
+            decode_entities(raw, start, &mut value)?;
+            attributes.push((name, value));
+        }
+
+        let children = self.parse_nodes(Some(&tag))?;
+        // parse_nodes stops only at the end of input or at a closing tag
+        self.pos += 2; // skip '</'
+        let closing = self.parse_name()?;
+        if closing != tag {
+            return Err(self.error(format!(
+                "expected closing tag </{}> but found </{}>",
+                tag, closing
+            )));
+        }
+        self.skip_whitespace();
+        if !self.rest().starts_with('>') {
+            return Err(self.error(format!("unterminated closing tag </{}>", tag)));
+        }
+        self.pos += 1;
+        Ok(XmlNode::Element {
+            tag,
+            attributes,
+            children,
+        })
+    }
+}
+
+/// Decodes XML character entities found in a `raw` string, appending the result to `buf`.
+/// An `offset` is a position of `raw` within parsed input, used for error reporting.
+fn decode_entities(raw: &str, offset: usize, buf: &mut String) -> Result<(), XmlParseError> {
+    let mut rest = raw;
+    while let Some(i) = rest.find('&') {
+        buf.push_str(&rest[..i]);
+        let index = offset + (raw.len() - rest.len()) + i;
+        let end = rest[i..].find(';').ok_or_else(|| XmlParseError {
+            index,
+            message: "unterminated entity".to_string(),
+        })?;
+        let entity = &rest[i + 1..i + end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(std::char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => buf.push(c),
+            None => {
+                return Err(XmlParseError {
+                    index,
+                    message: format!("invalid entity '&{};'", entity),
+                })
+            }
+        }
+        rest = &rest[i + end + 1..];
+    }
+    buf.push_str(rest);
+    Ok(())
+}
@@ -2,13 +2,15 @@ use crate::block::{
     Block, BlockPtr, Item, ItemContent, Skip, BLOCK_GC_REF_NUMBER, BLOCK_SKIP_REF_NUMBER, GC,
     HAS_ORIGIN, HAS_PARENT_SUB, HAS_RIGHT_ORIGIN,
 };
+use crate::block_store::BlockStore;
+use crate::id_set::DeleteSet;
 #[cfg(test)]
 use crate::store::Store;
-use crate::types::TypePtr;
+use crate::types::{Branch, BranchRef, TypePtr};
 use crate::updates::decoder::{Decode, Decoder};
-use crate::updates::encoder::{Encode, Encoder};
+use crate::updates::encoder::{Encode, Encoder, EncoderV1};
 use crate::utils::client_hasher::ClientHasher;
-use crate::{StateVector, Transaction, ID};
+use crate::{Error, StateVector, Transaction, ID};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
 use std::hash::BuildHasherDefault;
@@ -16,18 +18,37 @@ use std::rc::Rc;
 
 type ClientBlocks = HashMap<u64, VecDeque<Block>, BuildHasherDefault<ClientHasher>>;
 
+/// First byte of a compressed update payload. Raw ver.1 updates can only start with it if they
+/// contain over 127 clients, as it's a continuation byte of a variable length integer.
+#[cfg(feature = "compression")]
+const COMPRESSED_UPDATE_MAGIC: u8 = 0xff;
+
+/// Algorithm identifier of a deflate compressed update payload, following a magic byte.
+#[cfg(feature = "compression")]
+const COMPRESSION_DEFLATE: u8 = 1;
+
 /// Update type which contains an information about all decoded blocks which are incoming from a
 /// remote peer. Since these blocks are not yet integrated into current document's block store,
 /// they still may require repairing before doing so as they don't contain full data about their
 /// relations.
 ///
 /// Update is conceptually similar to a block store itself, however the work patters are different.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Update {
     clients: ClientBlocks,
 }
 
 impl Update {
+    /// Creates an update out of `blocks` of a single `client`, ordered by their clock.
+    #[cfg(test)]
+    pub(crate) fn with_blocks(client: u64, blocks: Vec<Block>) -> Self {
+        let mut clients = ClientBlocks::default();
+        if !blocks.is_empty() {
+            clients.insert(client, blocks.into());
+        }
+        Update { clients }
+    }
+
     /// Returns a state vector representing an upper bound of client clocks included by blocks
     /// stored in current update.
     pub fn state_vector(&self) -> StateVector {
@@ -39,6 +60,29 @@ impl Update {
         sv
     }
 
+    /// Returns a number of clients, which blocks are contained within current update.
+    #[cfg(feature = "log")]
+    pub(crate) fn clients_len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Returns a range of clock values, spanned by blocks of each client stored in current update.
+    #[cfg(feature = "log")]
+    pub(crate) fn clock_ranges(&self) -> Vec<(u64, std::ops::Range<u32>)> {
+        let mut ranges: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, blocks)| !blocks.is_empty())
+            .map(|(&client, blocks)| {
+                let start = blocks[0].id().clock;
+                let end = blocks[blocks.len() - 1].last_id().clock + 1;
+                (client, start..end)
+            })
+            .collect();
+        ranges.sort_by_key(|(client, _)| *client);
+        ranges
+    }
+
     /// Returns an iterator that allows a traversal of all of the blocks
     /// which consist into this [Update].
     pub(crate) fn blocks(&self) -> Blocks<'_> {
@@ -46,43 +90,72 @@ impl Update {
     }
 
     /// Merges another update into current one. Their blocks are deduplicated and reordered.
+    ///
+    /// Like in Yjs, skipped ranges give way to any other blocks overlapping them, as these may
+    /// contain the missing content. Otherwise the first of overlapping blocks is kept.
     pub fn merge(&mut self, other: Self) {
         for (client, other_blocks) in other.clients {
             match self.clients.entry(client) {
                 Entry::Occupied(e) => {
-                    let mut blocks = e.into_mut();
-
-                    let mut i2 = other_blocks.into_iter();
-                    let mut n2 = i2.next();
-
-                    let mut i1 = 0;
-
-                    while i1 < blocks.len() {
-                        let a = &mut blocks[i1];
-                        if let Some(b) = n2.as_ref() {
-                            if a.try_squash(b) {
-                                n2 = i2.next();
-                                continue;
-                            } else if let Block::Item(a) = a {
-                                // we only can split Block::Item
-                                let diff = (a.id.clock + a.len()) as isize - b.id().clock as isize;
-                                if diff > 0 {
-                                    // `b`'s clock position is inside of `a` -> we need to split `a`
-                                    self.split_item(client, i1, diff as u32);
-                                    blocks = self.clients.get_mut(&client).unwrap();
+                    let blocks = e.into_mut();
+                    blocks.extend(other_blocks);
+                    // sort by clock, longer blocks first so that shorter duplicates get dropped
+                    // and skipped ranges last, so that they're dropped in favor of other blocks
+                    blocks.make_contiguous().sort_by(|a, b| {
+                        let (a_clock, b_clock) = (a.id().clock, b.id().clock);
+                        let (a_skip, b_skip) =
+                            (matches!(a, Block::Skip(_)), matches!(b, Block::Skip(_)));
+                        a_clock
+                            .cmp(&b_clock)
+                            .then_with(|| a_skip.cmp(&b_skip))
+                            .then_with(|| b.len().cmp(&a.len()))
+                    });
+                    let mut merged: VecDeque<Block> = VecDeque::with_capacity(blocks.len());
+                    for mut block in blocks.drain(..) {
+                        if !matches!(block, Block::Skip(_)) {
+                            if let Some(Block::Skip(skip)) = merged.back_mut() {
+                                // skipped range is overlapped by another block: shrink it instead
+                                // (its part past that block is filled in below if needed)
+                                if block.id().clock < skip.id.clock + skip.len {
+                                    skip.len = block.id().clock - skip.id.clock;
+                                    if skip.len == 0 {
+                                        merged.pop_back();
+                                    }
                                 }
                             }
-                            i1 += 1;
-                            n2 = i2.next();
-                        } else {
-                            break;
+                        }
+                        let end = merged.back().map(Block::clock_end);
+                        if let Some(end) = end.filter(|&end| block.id().clock > end) {
+                            // keep clocks of the following blocks by filling the gap
+                            let gap = Skip::new(ID::new(client, end), block.id().clock - end);
+                            merged.push_back(Block::Skip(gap));
+                        }
+                        let end = merged.back().map(Block::clock_end).unwrap_or(0);
+                        if block.clock_end() <= end {
+                            // block is already fully covered by the ones before it
+                            continue;
+                        } else if block.id().clock < end {
+                            // block is partially covered: keep only its remaining part
+                            let diff = end - block.id().clock;
+                            block = match block {
+                                Block::Item(mut item) => Block::Item(item.split(diff)),
+                                Block::GC(gc) => {
+                                    Block::GC(GC::new(ID::new(client, end), gc.len - diff))
+                                }
+                                Block::Skip(skip) => {
+                                    Block::Skip(Skip::new(ID::new(client, end), skip.len - diff))
+                                }
+                            };
+                        }
+                        let squashed = match merged.back_mut() {
+                            Some(last) => last.try_squash(&block),
+                            None => false,
+                        };
+                        if !squashed {
+                            merged.push_back(block);
                         }
                     }
-
-                    while let Some(b) = n2 {
-                        blocks.push_back(b);
-                        n2 = i2.next();
-                    }
+                    *blocks = merged;
                 }
                 Entry::Vacant(e) => {
                     e.insert(other_blocks);
@@ -91,25 +164,161 @@ impl Update {
         }
     }
 
-    fn split_item(&mut self, client: u64, mut index: usize, diff: u32) {
-        let mut blocks = self.clients.get_mut(&client).unwrap();
-        if let Block::Item(item) = &mut blocks[index] {
-            index += 1;
-            let right_split = item.split(diff);
-            let right_ptr = right_split.right.clone();
-            if let Some(right_ptr) = right_ptr {
-                blocks = if right_ptr.id.client == client {
-                    blocks
-                } else {
-                    self.clients.get_mut(&right_ptr.id.client).unwrap()
-                };
-                let right = &mut blocks[right_ptr.pivot()];
-                if let Some(right_item) = right.as_item_mut() {
-                    right_item.left = Some(BlockPtr::new(right_split.id.clone(), index as u32));
+    /// Pre-allocates block lists of a given `store` to fit all blocks of a current update, ie.
+    /// before integrating it into a freshly created document.
+    pub(crate) fn reserve(&self, store: &mut BlockStore) {
+        for (&client, blocks) in self.clients.iter() {
+            store.get_client_blocks_with_capacity_mut(client, blocks.len());
+        }
+    }
+
+    /// Drops all blocks (or their parts) of a current update, which are not included in a given
+    /// state vector `sv`.
+    pub(crate) fn trim(&mut self, sv: &StateVector) {
+        for (client, blocks) in self.clients.iter_mut() {
+            let end = sv.get(client);
+            while let Some(last) = blocks.back_mut() {
+                let start = last.id().clock;
+                if start >= end {
+                    blocks.pop_back();
+                    continue;
+                }
+                if last.clock_end() > end {
+                    let diff = end - start;
+                    match last {
+                        Block::Item(item) => {
+                            item.split(diff);
+                            item.right = None;
+                        }
+                        Block::GC(gc) => gc.len = diff,
+                        Block::Skip(skip) => skip.len = diff,
+                    }
                 }
+                break;
+            }
+        }
+        self.clients.retain(|_, blocks| !blocks.is_empty());
+    }
+
+    /// Returns a copy of current update, which contains only blocks of clients for which a given
+    /// `keep` predicate returned true. Blocks of the remaining clients are omitted entirely.
+    ///
+    /// Keep in mind that retained blocks may still depend on omitted ones (eg. by using them as
+    /// their origins), in which case an update won't be fully integrated until the missing blocks
+    /// are delivered.
+    pub fn filter_clients<F>(&self, keep: F) -> Update
+    where
+        F: Fn(u64) -> bool,
+    {
+        let mut clients = ClientBlocks::default();
+        for (&client, blocks) in self.clients.iter() {
+            if keep(client) {
+                let blocks = blocks
+                    .iter()
+                    .map(|b| Self::copy_block(b, |id| id))
+                    .collect();
+                clients.insert(client, blocks);
+            }
+        }
+        Update { clients }
+    }
+
+    /// Returns a copy of current update, in which all blocks authored by a client `from` have
+    /// been reassigned to a client `to`. All references to remapped blocks (their origins and
+    /// parents) are adjusted accordingly.
+    ///
+    /// Returns an error if any of remapped blocks would occupy a clock range already used by one
+    /// of the blocks authored by client `to` within the same update.
+    pub fn remap_client(&self, from: u64, to: u64) -> Result<Update, ClientRemapError> {
+        let remap = |id: ID| {
+            if id.client == from {
+                ID::new(to, id.clock)
+            } else {
+                id
             }
-            blocks.insert(index, Block::Item(right_split));
         };
+        if from != to {
+            if let (Some(source), Some(target)) = (self.clients.get(&from), self.clients.get(&to)) {
+                for block in source.iter() {
+                    let start = block.id().clock;
+                    let end = start + block.len();
+                    let collision = target.iter().find(|b| {
+                        let clock = b.id().clock;
+                        clock < end && start < clock + b.len()
+                    });
+                    if let Some(existing) = collision {
+                        return Err(ClientRemapError {
+                            client: to,
+                            clock: existing.id().clock.max(start),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut clients = ClientBlocks::default();
+        for (&client, blocks) in self.clients.iter() {
+            let blocks = blocks.iter().map(|b| Self::copy_block(b, remap));
+            clients
+                .entry(remap(ID::new(client, 0)).client)
+                .or_default()
+                .extend(blocks);
+        }
+        if let Some(blocks) = clients.get_mut(&to) {
+            blocks
+                .make_contiguous()
+                .sort_by_key(|block| block.id().clock);
+        }
+        Ok(Update { clients })
+    }
+
+    /// Creates a copy of a decoded (not yet integrated) `block`, which identifier and identifiers
+    /// of all blocks it refers to are passed through a given `remap` function.
+    fn copy_block<F>(block: &Block, remap: F) -> Block
+    where
+        F: Fn(ID) -> ID,
+    {
+        match block {
+            Block::Skip(skip) => Block::Skip(Skip::new(remap(skip.id), skip.len)),
+            Block::GC(gc) => Block::GC(GC::new(remap(gc.id), gc.len)),
+            Block::Item(item) => {
+                let id = remap(item.id);
+                let parent = match &item.parent {
+                    TypePtr::Id(ptr) => TypePtr::Id(BlockPtr::from(remap(ptr.id))),
+                    other => other.clone(),
+                };
+                let content = match &item.content {
+                    ItemContent::Any(values) => ItemContent::Any(values.clone()),
+                    ItemContent::Binary(buf) => ItemContent::Binary(buf.clone()),
+                    ItemContent::Deleted(len) => ItemContent::Deleted(*len),
+                    ItemContent::Doc(guid, options) => {
+                        ItemContent::Doc(guid.clone(), options.clone())
+                    }
+                    ItemContent::JSON(values) => ItemContent::JSON(values.clone()),
+                    ItemContent::Embed(value) => ItemContent::Embed(value.clone()),
+                    ItemContent::Format(key, value) => {
+                        ItemContent::Format(key.clone(), value.clone())
+                    }
+                    ItemContent::String(value) => ItemContent::String(value.clone()),
+                    ItemContent::Type(branch) => {
+                        let inner = branch.borrow();
+                        let ptr = TypePtr::Id(BlockPtr::from(id));
+                        let copy = Branch::new(ptr, inner.type_ref(), inner.name.clone());
+                        ItemContent::Type(BranchRef::new(copy))
+                    }
+                };
+                Block::Item(Item::new(
+                    id,
+                    None,
+                    item.origin.map(&remap),
+                    None,
+                    item.right_origin.map(&remap),
+                    parent,
+                    item.parent_sub.clone(),
+                    content,
+                ))
+            }
+        }
     }
 
     /// Integrates current update into a block store referenced by a given transaction.
@@ -125,8 +334,7 @@ impl Update {
         client_block_ref_ids.sort_by(|a, b| b.cmp(a));
 
         let mut current_client_id = client_block_ref_ids.pop();
-        let mut current_target = current_client_id.and_then(|id| self.clients.get_mut(&id));
-        let mut stack_head = Self::next(&mut current_target);
+        let mut stack_head = current_client_id.and_then(|id| self.next_block(&id));
 
         let mut local_sv = txn.store.blocks.get_state_vector();
         let mut missing_sv = StateVector::default();
@@ -135,27 +343,38 @@ impl Update {
 
         while let Some(mut block) = stack_head {
             let id = block.id().clone();
-            if local_sv.contains(&id) {
+            if let Block::Skip(_) = block {
+                // skipped ranges carry no content: blocks following them are either known
+                // already or wait in a pending update for the missing ones
+            } else if local_sv.contains(&id) {
                 let offset = local_sv.get(&id.client) - id.clock;
                 if let Some(dep) = Self::missing(&block, &local_sv) {
                     stack.push(block);
-                    // get the struct reader that has the missing struct
-                    let block_refs = txn.store.blocks.get_client_blocks_mut(dep);
-                    if block_refs.integrated_len() == block_refs.len() {
-                        // This update message causally depends on another update message that doesn't exist yet
-                        missing_sv.set_min(dep, local_sv.get(&dep));
-                        Self::return_stack(stack, &mut self.clients, &mut remaining);
-                        current_target = current_client_id.and_then(|id| self.clients.get_mut(&id));
-                        stack = Vec::new();
-                    } else {
-                        stack_head = Self::next(&mut current_target);
-                        continue;
+                    // try to resolve missing dependency from the blocks of this update first
+                    match self.next_block(&dep) {
+                        Some(next) => {
+                            stack_head = Some(next);
+                            continue;
+                        }
+                        None => {
+                            // This update message causally depends on another update message that doesn't exist yet
+                            missing_sv.set_min(dep, local_sv.get(&dep));
+                            Self::return_stack(stack, &mut self.clients, &mut remaining);
+                            stack = Vec::new();
+                        }
                     }
-                } else {
+                } else if offset == 0 || offset < block.len() {
                     let client = id.client;
                     local_sv.set_max(client, id.clock + block.len());
                     block.as_item_mut().map(|item| item.repair(txn));
                     let should_delete = block.integrate(txn, offset, offset);
+                    let stats = txn.stats_mut();
+                    stats.integrated_blocks += 1;
+                    if let Block::Item(item) = &block {
+                        if item.is_countable() && !item.is_deleted() {
+                            stats.added_len += item.len();
+                        }
+                    }
                     let delete_ptr = if should_delete {
                         Some(BlockPtr::new(block.id().clone(), offset))
                     } else {
@@ -171,36 +390,27 @@ impl Update {
                 }
             } else {
                 // update from the same client is missing
+                missing_sv.set_min(id.client, id.clock - 1);
                 stack.push(block);
                 // hid a dead wall, add all items from stack to restSS
                 Self::return_stack(stack, &mut self.clients, &mut remaining);
-                current_target = current_client_id.and_then(|id| self.clients.get_mut(&id));
                 stack = Vec::new();
             }
 
             // iterate to next stackHead
-            if !stack.is_empty() {
-                stack_head = stack.pop();
-            } else {
-                current_target = match current_target.take() {
-                    None => None,
-                    Some(v) => {
-                        if !v.is_empty() {
-                            Some(v)
-                        } else {
-                            if let Some((client_id, target)) =
-                                Self::next_target(&mut client_block_ref_ids, &mut self.clients)
-                            {
-                                current_client_id = Some(client_id);
-                                Some(target)
-                            } else {
-                                current_client_id = None;
-                                None
-                            }
+            stack_head = stack.pop();
+            while stack_head.is_none() {
+                match current_client_id {
+                    Some(client) => {
+                        stack_head = self.next_block(&client);
+                        if stack_head.is_none() {
+                            // current client blocks are either exhausted or have been returned as
+                            // not applicable - move to the next client
+                            current_client_id = client_block_ref_ids.pop();
                         }
                     }
-                };
-                stack_head = Self::next(&mut current_target);
+                    None => break,
+                }
             }
         }
 
@@ -214,50 +424,150 @@ impl Update {
         }
     }
 
-    fn next(target: &mut Option<&mut VecDeque<Block>>) -> Option<Block> {
-        if let Some(v) = target {
-            v.pop_front()
-        } else {
-            None
-        }
+    /// Pops the next block of a given `client`, which has not been integrated yet.
+    fn next_block(&mut self, client: &u64) -> Option<Block> {
+        self.clients
+            .get_mut(client)
+            .and_then(|blocks| blocks.pop_front())
     }
 
+    /// Returns a client identifier of a first dependency of a given `block` (its origin, right
+    /// origin or parent), which has not been integrated yet according to `local_sv`.
     fn missing(block: &Block, local_sv: &StateVector) -> Option<u64> {
         if let Block::Item(item) = block {
-            if let Some(origin) = item.origin {
-                if origin.client != item.id.client && !local_sv.contains(&item.id) {
-                    return Some(origin.client);
-                }
-            } else if let Some(right_origin) = item.right_origin {
-                if right_origin.client != item.id.client && !local_sv.contains(&item.id) {
-                    return Some(right_origin.client);
-                }
-            } else if let TypePtr::Id(parent) = item.parent {
-                if parent.id.client != item.id.client && !local_sv.contains(&item.id) {
-                    return Some(parent.id.client);
+            for dep in Self::dependencies(item).iter().flatten() {
+                if dep.client != item.id.client && dep.clock >= local_sv.get(&dep.client) {
+                    return Some(dep.client);
                 }
             }
         }
         None
     }
 
-    fn next_target<'a, 'b>(
-        client_block_ref_ids: &'a mut Vec<u64>,
-        clients: &'b mut ClientBlocks,
-    ) -> Option<(u64, &'b mut VecDeque<Block>)> {
-        loop {
-            if let Some((id, Some(client_blocks))) = client_block_ref_ids
-                .pop()
-                .map(move |id| (id, clients.get_mut(&id)))
-            {
-                if !client_blocks.is_empty() {
-                    return Some((id, client_blocks));
+    /// Returns identifiers of blocks, which a given `item` depends upon: its origin, right origin
+    /// and parent.
+    fn dependencies(item: &Item) -> [Option<ID>; 3] {
+        let parent = match &item.parent {
+            TypePtr::Id(ptr) => Some(ptr.id),
+            _ => None,
+        };
+        [item.origin, item.right_origin, parent]
+    }
+
+    /// Splits current update together with its delete set `ds` into a sequence of updates encoded
+    /// using lib0 ver.1 encoding, each one taking no more than `max_bytes`.
+    ///
+    /// Chunks are ordered so that blocks are placed after the blocks of this update they depend
+    /// on: when applied in order, every chunk can be integrated completely. Chunks applied out of
+    /// order are still correct - blocks with missing dependencies wait in a pending update queue
+    /// until they arrive. Deleted ranges are attached to the last chunk or put into chunks of
+    /// their own.
+    ///
+    /// A single block taking more than `max_bytes` is placed in a separate chunk, which exceeds
+    /// the limit.
+    pub fn split(self, ds: &DeleteSet, max_bytes: usize) -> Vec<Vec<u8>> {
+        // upper bounds of encoded number of clients, number of client blocks together with
+        // client id and starting clock, and of a single deleted range
+        const CLIENTS_LEN: usize = 5;
+        const CLIENT_HEADER_LEN: usize = 20;
+        const RANGE_LEN: usize = 10;
+
+        fn encode_chunk(update: &Update, ds: &DeleteSet) -> Vec<u8> {
+            let mut encoder = EncoderV1::new();
+            update.encode(&mut encoder);
+            ds.encode(&mut encoder);
+            encoder.to_vec()
+        }
+
+        let mut chunks = Vec::new();
+        let mut chunk = Update::default();
+        let mut chunk_len = CLIENTS_LEN + 1; // followed by an empty delete set
+        for block in self.into_causal_order() {
+            let client = block.id().client;
+            let mut encoder = EncoderV1::new();
+            block.encode_with_offset(&mut encoder, 0);
+            let block_len = encoder.to_vec().len();
+            let header_len = if chunk.clients.contains_key(&client) {
+                0
+            } else {
+                CLIENT_HEADER_LEN
+            };
+            if chunk_len + header_len + block_len > max_bytes && !chunk.clients.is_empty() {
+                chunks.push(encode_chunk(&chunk, &DeleteSet::default()));
+                chunk = Update::default();
+                chunk_len = CLIENTS_LEN + 1 + CLIENT_HEADER_LEN;
+            } else {
+                chunk_len += header_len;
+            }
+            chunk_len += block_len;
+            chunk.clients.entry(client).or_default().push_back(block);
+        }
+
+        let ds_len = ds.encode_v1().len();
+        if chunk_len - 1 + ds_len <= max_bytes {
+            chunks.push(encode_chunk(&chunk, ds));
+            return chunks;
+        }
+        if !chunk.clients.is_empty() {
+            chunks.push(encode_chunk(&chunk, &DeleteSet::default()));
+        }
+        // delete set doesn't fit - split it into chunks with no blocks
+        let mut part = DeleteSet::new();
+        let mut part_len = 1 + CLIENTS_LEN;
+        for (&client, ranges) in ds.iter() {
+            let mut header_len = CLIENT_HEADER_LEN;
+            for range in ranges.iter() {
+                if part_len + header_len + RANGE_LEN > max_bytes && !part.is_empty() {
+                    chunks.push(encode_chunk(&Update::default(), &part));
+                    part = DeleteSet::new();
+                    part_len = 1 + CLIENTS_LEN;
+                    header_len = CLIENT_HEADER_LEN;
                 }
+                part.insert(ID::new(client, range.start), range.end - range.start);
+                part_len += header_len + RANGE_LEN;
+                header_len = 0;
             }
+        }
+        if !part.is_empty() {
+            chunks.push(encode_chunk(&Update::default(), &part));
+        }
+        chunks
+    }
 
-            break;
+    /// Returns blocks of current update in order in which they can be integrated: every block is
+    /// preceded by the blocks of this update it depends upon.
+    fn into_causal_order(mut self) -> Vec<Block> {
+        let mut clients: Vec<u64> = self.clients.keys().cloned().collect();
+        clients.sort();
+        let mut result = Vec::new();
+        let mut stack = Vec::new();
+        for client in clients {
+            while let Some(block) = self.next_block(&client) {
+                stack.push(block);
+                while let Some(block) = stack.last() {
+                    let clients = &self.clients;
+                    // dependency which can be satisfied by the remaining blocks of this update
+                    let dep = match block {
+                        Block::Item(item) => {
+                            Self::dependencies(item).iter().flatten().find_map(|dep| {
+                                let next = clients.get(&dep.client)?.front()?;
+                                if dep.client != item.id.client && next.id().clock <= dep.clock {
+                                    Some(dep.client)
+                                } else {
+                                    None
+                                }
+                            })
+                        }
+                        _ => None,
+                    };
+                    match dep.and_then(|client| self.next_block(&client)) {
+                        Some(block) => stack.push(block),
+                        None => result.push(stack.pop().unwrap()),
+                    }
+                }
+            }
         }
-        None
+        result
     }
 
     fn return_stack(stack: Vec<Block>, refs: &mut ClientBlocks, remaining: &mut ClientBlocks) {
@@ -278,44 +588,95 @@ impl Update {
         }
     }
 
-    fn decode_block<D: Decoder>(id: ID, decoder: &mut D) -> Block {
-        let info = decoder.read_info();
-        match info {
+    /// Compresses a binary update payload using deflate algorithm. Compressed payload starts with
+    /// a header identifying compression algorithm, so that it can be recognized by
+    /// [Update::decompress].
+    #[cfg(feature = "compression")]
+    pub fn compress(update: &[u8]) -> Vec<u8> {
+        let compressed = miniz_oxide::deflate::compress_to_vec(update, 6);
+        let mut result = Vec::with_capacity(compressed.len() + 2);
+        result.push(COMPRESSED_UPDATE_MAGIC);
+        result.push(COMPRESSION_DEFLATE);
+        result.extend_from_slice(&compressed);
+        result
+    }
+
+    /// Decompresses a binary update payload created using [Update::compress]. Returns an error if
+    /// payload doesn't start with a known compression header or could not be decompressed.
+    #[cfg(feature = "compression")]
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+        match data {
+            [COMPRESSED_UPDATE_MAGIC, COMPRESSION_DEFLATE, compressed @ ..] => {
+                miniz_oxide::inflate::decompress_to_vec(compressed)
+                    .map_err(|_| Error::InvalidData("malformed compressed update"))
+            }
+            _ => Err(Error::InvalidData("unknown update compression header")),
+        }
+    }
+
+    /// Lazily decodes an update, yielding its blocks one client run at a time instead of
+    /// materializing all of them up front. Every yielded [Update] contains blocks of a single
+    /// client. Once an iterator is exhausted, a `decoder` is positioned at the delete set
+    /// following the blocks.
+    pub fn decode_lazy<D: Decoder>(decoder: &mut D) -> Result<UpdateBlocksIter<'_, D>, Error> {
+        let remaining = decoder.read_collection_len()?;
+        Ok(UpdateBlocksIter { decoder, remaining })
+    }
+
+    fn decode_client_run<D: Decoder>(decoder: &mut D) -> Result<(u64, VecDeque<Block>), Error> {
+        let blocks_len = decoder.read_collection_len()? as usize;
+        let client = decoder.read_client()?;
+        let mut clock: u32 = decoder.read_uvar()?;
+        let mut blocks = VecDeque::with_capacity(blocks_len);
+        for _ in 0..blocks_len {
+            let id = ID::new(client, clock);
+            let block = Self::decode_block(id, decoder)?;
+            clock = clock
+                .checked_add(block.len())
+                .ok_or(Error::InvalidData("block clock overflow"))?;
+            blocks.push_back(block);
+        }
+        Ok((client, blocks))
+    }
+
+    fn decode_block<D: Decoder>(id: ID, decoder: &mut D) -> Result<Block, Error> {
+        let info = decoder.read_info()?;
+        let block = match info {
             BLOCK_SKIP_REF_NUMBER => {
-                let len: u32 = decoder.read_uvar();
+                let len: u32 = decoder.read_uvar()?;
                 Block::Skip(Skip { id, len })
             }
             BLOCK_GC_REF_NUMBER => {
-                let len: u32 = decoder.read_uvar();
+                let len: u32 = decoder.read_uvar()?;
                 Block::GC(GC { id, len })
             }
             info => {
                 let cant_copy_parent_info = info & (HAS_ORIGIN | HAS_RIGHT_ORIGIN) == 0;
                 let origin = if info & HAS_ORIGIN != 0 {
-                    Some(decoder.read_left_id())
+                    Some(decoder.read_left_id()?)
                 } else {
                     None
                 };
                 let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
-                    Some(decoder.read_right_id())
+                    Some(decoder.read_right_id()?)
                 } else {
                     None
                 };
                 let parent = if cant_copy_parent_info {
-                    if decoder.read_parent_info() {
-                        TypePtr::Named(Rc::new(decoder.read_string().to_owned()))
+                    if decoder.read_parent_info()? {
+                        TypePtr::Named(Rc::new(decoder.read_string()?.to_owned()))
                     } else {
-                        TypePtr::Id(BlockPtr::from(decoder.read_left_id()))
+                        TypePtr::Id(BlockPtr::from(decoder.read_left_id()?))
                     }
                 } else {
                     TypePtr::Unknown
                 };
                 let parent_sub = if cant_copy_parent_info && (info & HAS_PARENT_SUB != 0) {
-                    Some(decoder.read_string().to_owned())
+                    Some(decoder.read_string()?.to_owned())
                 } else {
                     None
                 };
-                let content = ItemContent::decode(decoder, info, BlockPtr::from(id.clone()));
+                let content = ItemContent::decode(decoder, info, BlockPtr::from(id.clone()))?;
                 let item = Item::new(
                     id,
                     None,
@@ -328,7 +689,8 @@ impl Update {
                 );
                 Block::Item(item)
             }
-        }
+        };
+        Ok(block)
     }
 
     pub(crate) fn encode_diff<E: Encoder>(&self, remote_sv: &StateVector, encoder: &mut E) {
@@ -385,31 +747,74 @@ impl Encode for Update {
 }
 
 impl Decode for Update {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self {
-        let clients_len: u32 = decoder.read_uvar();
-        let mut clients =
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error> {
+        let clients_len = decoder.read_collection_len()?;
+        let mut clients: ClientBlocks =
             HashMap::with_capacity_and_hasher(clients_len as usize, BuildHasherDefault::default());
         for _ in 0..clients_len {
-            let blocks_len = decoder.read_uvar::<u32>() as usize;
-
-            let client = decoder.read_client();
-            let mut clock: u32 = decoder.read_uvar();
-            let blocks = clients
-                .entry(client)
-                .or_insert_with(|| VecDeque::with_capacity(blocks_len));
-
-            for _ in 0..blocks_len {
-                let id = ID::new(client, clock);
-                let block = Self::decode_block(id, decoder);
-                clock += block.len();
-                blocks.push_back(block);
+            let (client, blocks) = Self::decode_client_run(decoder)?;
+            match clients.entry(client) {
+                Entry::Occupied(e) => e.into_mut().extend(blocks),
+                Entry::Vacant(e) => {
+                    e.insert(blocks);
+                }
             }
         }
 
-        Update { clients }
+        Ok(Update { clients })
     }
 }
 
+/// Iterator returned by [Update::decode_lazy]. It decodes blocks of an update one client run at
+/// a time, so that only the blocks of a currently decoded client need to be kept in memory.
+pub struct UpdateBlocksIter<'a, D: Decoder> {
+    decoder: &'a mut D,
+    remaining: u32,
+}
+
+impl<'a, D: Decoder> Iterator for UpdateBlocksIter<'a, D> {
+    type Item = Result<Update, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let result = Update::decode_client_run(self.decoder).map(|(client, blocks)| {
+            let mut clients = ClientBlocks::default();
+            clients.insert(client, blocks);
+            Update { clients }
+        });
+        if result.is_err() {
+            // decoder position is unknown after failure - stop decoding
+            self.remaining = 0;
+        }
+        Some(result)
+    }
+}
+
+/// Error returned by [Update::remap_client], when remapped blocks would collide with blocks
+/// already authored by a target client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientRemapError {
+    /// Target client identifier.
+    pub client: u64,
+    /// First clock value used by both remapped and existing blocks of a target client.
+    pub clock: u32,
+}
+
+impl std::fmt::Display for ClientRemapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "remapped blocks collide with existing blocks of client {} at clock {}",
+            self.client, self.clock
+        )
+    }
+}
+
+impl std::error::Error for ClientRemapError {}
+
 /// A pending update which contains unapplied blocks from the update which created it.
 #[derive(Debug, PartialEq)]
 pub struct PendingUpdate {
@@ -445,7 +850,7 @@ impl std::fmt::Display for Update {
 #[cfg(test)]
 impl Into<Store> for Update {
     fn into(self) -> Store {
-        let mut store = Store::new(0);
+        let mut store = Store::with_gc(0, String::new(), false);
         for (client_id, vec) in self.clients {
             let blocks = store
                 .blocks
@@ -496,13 +901,15 @@ impl<'a> Iterator for Blocks<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::block::{Block, Item, ItemContent};
+    use crate::block::{Block, Item, ItemContent, Skip, GC};
     use crate::id_set::DeleteSet;
     use crate::types::TypePtr;
-    use crate::update::Update;
+    use crate::update::{ClientRemapError, Update};
     use crate::updates::decoder::{Decode, DecoderV1};
-    use crate::{Doc, ID};
+    use crate::{Doc, PrelimMap, ID};
+    use lib0::any::Any;
     use lib0::decoding::Cursor;
+    use std::collections::HashMap;
     use std::rc::Rc;
 
     #[test]
@@ -525,7 +932,7 @@ mod test {
             108, 117, 101, 66, 0,
         ];
         let mut decoder = DecoderV1::from(update);
-        let u = Update::decode(&mut decoder);
+        let u = Update::decode(&mut decoder).unwrap();
 
         let id = ID::new(2026372272, 0);
         let block = u.clients.get(&id.client).unwrap();
@@ -543,6 +950,43 @@ mod test {
         assert_eq!(block, &expected);
     }
 
+    #[test]
+    fn update_decode_lazy() {
+        let d1 = Doc::with_client_id(1);
+        let d2 = Doc::with_client_id(2);
+        {
+            let mut t1 = d1.transact();
+            t1.get_text("text").insert(&mut t1, 0, "hello");
+            let mut t2 = d2.transact();
+            d2.apply_update_v1(&mut t2, &d1.encode_state_as_update_v1(&t1))
+                .unwrap();
+            t2.get_text("text").insert(&mut t2, 5, " world");
+            t2.get_text("text").remove_range(&mut t2, 0, 1);
+        }
+        let t2 = d2.transact();
+        let binary = d2.encode_state_as_update_v1(&t2);
+
+        let eager = Update::decode(&mut DecoderV1::from(binary.as_slice())).unwrap();
+        let mut decoder = DecoderV1::from(binary.as_slice());
+        let mut lazy = Update::default();
+        for run in Update::decode_lazy(&mut decoder).unwrap() {
+            let run = run.unwrap();
+            assert_eq!(run.clients.len(), 1);
+            lazy.merge(run);
+        }
+        assert_eq!(lazy, eager);
+
+        // exhausted iterator leaves decoder at the delete set
+        let ds = DeleteSet::decode(&mut decoder).unwrap();
+        assert!(ds.is_deleted(&ID::new(1, 0)));
+
+        // decoding stops after the first failure
+        let truncated = &binary[..binary.len() / 2];
+        let mut decoder = DecoderV1::from(truncated);
+        let results: Vec<_> = Update::decode_lazy(&mut decoder).unwrap().collect();
+        assert!(results.last().unwrap().is_err());
+    }
+
     #[test]
     fn update_merge() {
         let d1 = Doc::new();
@@ -562,11 +1006,11 @@ mod test {
         let binary1 = t1.encode_update_v1();
         let binary2 = t2.encode_update_v1();
 
-        d1.apply_update_v1(&mut t1, binary2.as_slice());
-        d2.apply_update_v1(&mut t2, binary1.as_slice());
+        d1.apply_update_v1(&mut t1, binary2.as_slice()).unwrap();
+        d2.apply_update_v1(&mut t2, binary1.as_slice()).unwrap();
 
-        let mut u1 = Update::decode(&mut DecoderV1::new(Cursor::new(binary1.as_slice())));
-        let u2 = Update::decode(&mut DecoderV1::new(Cursor::new(binary2.as_slice())));
+        let mut u1 = Update::decode(&mut DecoderV1::new(Cursor::new(binary1.as_slice()))).unwrap();
+        let u2 = Update::decode(&mut DecoderV1::new(Cursor::new(binary2.as_slice()))).unwrap();
 
         // a crux of our test: merged update upon applying should produce
         // the same output as sequence of updates applied individually
@@ -584,4 +1028,250 @@ mod test {
         assert_eq!(str1, str2);
         assert_eq!(str2, str3);
     }
+
+    fn string_item(clock: u32, value: &str) -> Block {
+        let parent = TypePtr::Named(Rc::new("text".to_owned()));
+        let content = ItemContent::String(value.into());
+        Block::Item(Item::new(
+            ID::new(1, clock),
+            None,
+            None,
+            None,
+            None,
+            parent,
+            None,
+            content,
+        ))
+    }
+
+    /// Merges two single-client updates in both orders, returning layouts of both results as
+    /// a list of (block kind, clock, length) triples.
+    fn merged_layouts(a: Vec<Block>, b: Vec<Block>) -> [Vec<(&'static str, u32, u32)>; 2] {
+        let layout = |u: Update| -> Vec<_> {
+            u.blocks()
+                .map(|block| {
+                    let kind = match block {
+                        Block::Item(_) => "item",
+                        Block::GC(_) => "gc",
+                        Block::Skip(_) => "skip",
+                    };
+                    (kind, block.id().clock, block.len())
+                })
+                .collect()
+        };
+        let copy = |blocks: &[Block]| -> Vec<Block> {
+            blocks
+                .iter()
+                .map(|block| Update::copy_block(block, |id| id))
+                .collect()
+        };
+        let mut ab = Update::with_blocks(1, copy(&a));
+        ab.merge(Update::with_blocks(1, copy(&b)));
+        let mut ba = Update::with_blocks(1, b);
+        ba.merge(Update::with_blocks(1, a));
+        [layout(ab), layout(ba)]
+    }
+
+    #[test]
+    fn merge_fills_gaps_with_skips() {
+        let a = vec![string_item(0, "ab")];
+        let b = vec![string_item(4, "ef")];
+        for layout in merged_layouts(a, b) {
+            assert_eq!(layout, vec![("item", 0, 2), ("skip", 2, 2), ("item", 4, 2)]);
+        }
+    }
+
+    #[test]
+    fn merge_blocks_over_skip() {
+        let a = vec![
+            Block::Skip(Skip::new(ID::new(1, 0), 4)),
+            string_item(4, "e"),
+        ];
+        let b = vec![Block::GC(GC::new(ID::new(1, 1), 2))];
+        for layout in merged_layouts(a, b) {
+            assert_eq!(
+                layout,
+                vec![("skip", 0, 1), ("gc", 1, 2), ("skip", 3, 1), ("item", 4, 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn merge_overlapping_items() {
+        let a = vec![string_item(0, "abc"), Block::GC(GC::new(ID::new(1, 3), 3))];
+        let b = vec![string_item(2, "cde")];
+        for layout in merged_layouts(a, b) {
+            assert_eq!(layout, vec![("item", 0, 3), ("item", 3, 2), ("gc", 5, 1)]);
+        }
+    }
+
+    #[test]
+    fn filter_clients_self_contained() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        t1.get_text("text").insert(&mut t1, 0, "abc");
+        let u1 = t1.encode_update_v1();
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &u1).unwrap();
+        t2.get_map("other")
+            .insert(&mut t2, "key".to_string(), "value");
+        let binary = d2.encode_state_as_update_v1(&t2);
+
+        let update = Update::decode_v1(&binary).unwrap();
+        let filtered = update.filter_clients(|client| client == 2);
+        assert_eq!(filtered.state_vector().get(&1), 0);
+        assert_eq!(filtered.state_vector().get(&2), 1);
+
+        let d3 = Doc::with_client_id(3);
+        let mut t3 = d3.transact();
+        t3.apply_update(filtered, DeleteSet::default());
+        assert!(t3.store.pending.is_none());
+        assert!(t3.get_map("other").contains(&t3, "key"));
+        assert_eq!(t3.get_text("text").to_string(&t3), "");
+    }
+
+    #[test]
+    fn remap_client() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        let txt = t1.get_text("text");
+        txt.insert(&mut t1, 0, "ac");
+        txt.insert(&mut t1, 1, "b");
+        let mut inner = HashMap::new();
+        inner.insert("key".to_string(), Any::String("value".to_string()));
+        let map = t1.get_map("map");
+        map.insert(&mut t1, "nested".to_string(), PrelimMap::from(inner));
+        let binary = d1.encode_state_as_update_v1(&t1);
+
+        let update = Update::decode_v1(&binary)
+            .unwrap()
+            .remap_client(1, 5)
+            .unwrap();
+        let sv = update.state_vector();
+        assert_eq!(sv.get(&1), 0);
+        assert_eq!(sv.get(&5), 5);
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        t2.apply_update(update, DeleteSet::default());
+        assert!(t2.store.pending.is_none());
+        assert!(d1.eq_content(&t1, &d2, &t2));
+        assert_eq!(t2.get_text("text").to_string(&t2), "abc");
+    }
+
+    #[test]
+    fn remap_client_collision() {
+        let d1 = Doc::with_client_id(1);
+        let mut t1 = d1.transact();
+        t1.get_text("text").insert(&mut t1, 0, "abc");
+        let u1 = t1.encode_update_v1();
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        d2.apply_update_v1(&mut t2, &u1).unwrap();
+        t2.get_text("text").push(&mut t2, "de");
+        let update = Update::decode_v1(&d2.encode_state_as_update_v1(&t2)).unwrap();
+
+        assert_eq!(
+            update.remap_client(1, 2),
+            Err(ClientRemapError {
+                client: 2,
+                clock: 0
+            })
+        );
+        // remapping into an unused client is fine
+        let remapped = update.remap_client(1, 3).unwrap();
+        assert_eq!(remapped.state_vector().get(&3), 3);
+        assert_eq!(remapped.state_vector().get(&2), 2);
+    }
+
+    #[test]
+    fn merge_overlapping_and_disjoint_updates() {
+        let d1 = Doc::with_client_id(1);
+        let updates: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|chunk| {
+                let mut txn = d1.transact();
+                txn.get_text("text").push(&mut txn, chunk);
+                txn.encode_update_v1()
+            })
+            .collect();
+        let merge = |updates: &[&Vec<u8>]| {
+            let mut merged = Update::decode_v1(updates[0]).unwrap();
+            for update in &updates[1..] {
+                merged.merge(Update::decode_v1(update).unwrap());
+            }
+            merged
+        };
+        let apply = |update: Update| {
+            let doc = Doc::with_client_id(2);
+            let mut txn = doc.transact();
+            txn.apply_update(update, DeleteSet::default());
+            assert!(txn.store.pending.is_none());
+            txn.get_text("text").to_string(&txn)
+        };
+
+        // overlapping ranges
+        let u012 = merge(&[&updates[0], &updates[1], &updates[2]]);
+        let u123 = merge(&[&updates[1], &updates[2], &updates[3]]);
+        let mut merged = u123;
+        merged.merge(u012);
+        assert_eq!(apply(merged), "abcd");
+
+        // disjoint ranges are kept apart and filled in later
+        let mut merged = merge(&[&updates[3], &updates[0]]);
+        merged.merge(merge(&[&updates[2], &updates[1]]));
+        assert_eq!(apply(merged), "abcd");
+    }
+
+    #[test]
+    fn integrate_dependencies_from_same_update() {
+        let d2 = Doc::with_client_id(2);
+        let d1 = Doc::with_client_id(1);
+        {
+            let mut txn = d2.transact();
+            txn.get_text("text").push(&mut txn, "ac");
+        }
+        let mut t1 = d1.transact();
+        d1.apply_update_v1(&mut t1, &d2.encode_state_as_update_v1(&d2.transact()))
+            .unwrap();
+        t1.get_text("text").insert(&mut t1, 1, "b");
+        let update = d1.encode_state_as_update_v1(&t1);
+
+        // blocks of client 1 are integrated first, but depend on blocks of client 2
+        let d3 = Doc::with_client_id(3);
+        let mut t3 = d3.transact();
+        let update = Update::decode_v1(&update).unwrap();
+        assert!(update.integrate(&mut t3).is_none());
+        assert_eq!(t3.get_text("text").to_string(&t3), "abc");
+    }
+
+    #[test]
+    fn integrate_records_missing_clocks_of_same_client() {
+        let d1 = Doc::with_client_id(1);
+        let updates: Vec<_> = ["ab", "c"]
+            .iter()
+            .map(|chunk| {
+                let mut txn = d1.transact();
+                txn.get_text("text").push(&mut txn, chunk);
+                txn.encode_update_v1()
+            })
+            .collect();
+
+        let d2 = Doc::with_client_id(2);
+        let mut t2 = d2.transact();
+        let pending = Update::decode_v1(&updates[1])
+            .unwrap()
+            .integrate(&mut t2)
+            .unwrap();
+        assert_eq!(pending.missing.get(&1), 1);
+
+        // missing clocks make pending blocks integrated once preceding ones arrive
+        d2.apply_update_v1(&mut t2, &updates[1]).unwrap();
+        d2.apply_update_v1(&mut t2, &updates[0]).unwrap();
+        assert!(t2.store.pending.is_none());
+        assert_eq!(t2.get_text("text").to_string(&t2), "abc");
+    }
 }
@@ -1,16 +1,24 @@
+use crate::error::Error;
 use crate::*;
-use lib0::decoding::Read;
+use lib0::decoding::{CheckedCursor, DecoderOptions};
 use lib0::{any::Any, decoding::Cursor};
+use std::convert::TryFrom;
 
 /// A trait that can be implemented by any other type in order to support lib0 decoding capability.
 pub trait Decode: Sized {
-    fn decode<D: Decoder>(decoder: &mut D) -> Self;
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, Error>;
 
     /// Helper function for decoding 1st version of lib0 encoding.
-    fn decode_v1(data: &[u8]) -> Self {
+    fn decode_v1(data: &[u8]) -> Result<Self, Error> {
         let mut decoder = DecoderV1::from(data);
         Self::decode(&mut decoder)
     }
+
+    /// Helper function for decoding 1st version of lib0 encoding, which panics on malformed input.
+    #[deprecated(note = "decoding is fallible, use Decode::decode_v1 and handle returned error")]
+    fn decode_v1_unchecked(data: &[u8]) -> Self {
+        Self::decode_v1(data).unwrap()
+    }
 }
 
 /// Trait used by lib0 decoders. Natively lib0 encoding supports two versions:
@@ -18,44 +26,65 @@ pub trait Decode: Sized {
 /// 1. 1st version (implemented in Yrs) uses simple optimization techniques like var int encoding.
 /// 2. 2nd version optimizes bigger batches of blocks by using run-length encoding.
 ///
-/// Both of these define a common set of operations defined in this trait.  
-pub trait Decoder: Read {
+/// Both of these define a common set of operations defined in this trait. All of them return an
+/// error instead of panicking when decoded payload is malformed.
+pub trait Decoder {
+    /// Read a single byte.
+    fn read_u8(&mut self) -> Result<u8, Error>;
+
+    /// Read unsigned integer with variable length.
+    fn read_uvar<T: TryFrom<u64>>(&mut self) -> Result<T, Error>;
+
+    /// Read a variable length buffer.
+    fn read_buf(&mut self) -> Result<&[u8], Error>;
+
+    /// Read string of variable length.
+    fn read_string(&mut self) -> Result<&str, Error>;
+
+    /// Read a number of elements of a collection, that's about to be decoded. Decoders use it to
+    /// enforce configured limits before allocating memory for declared elements.
+    fn read_collection_len(&mut self) -> Result<u32, Error>;
+
     /// Reset the value of current delete set state.
     fn reset_ds_cur_val(&mut self);
 
     /// Read next [DeleteSet] clock value.
-    fn read_ds_clock(&mut self) -> u32;
+    fn read_ds_clock(&mut self) -> Result<u32, Error>;
 
     /// Read the number of clients stored in encoded [DeleteSet].
-    fn read_ds_len(&mut self) -> u32;
+    fn read_ds_len(&mut self) -> Result<u32, Error>;
 
     /// Read left origin of a currently decoded [Block].
-    fn read_left_id(&mut self) -> block::ID;
+    fn read_left_id(&mut self) -> Result<block::ID, Error>;
 
     /// Read right origin of a currently decoded [Block].
-    fn read_right_id(&mut self) -> block::ID;
+    fn read_right_id(&mut self) -> Result<block::ID, Error>;
 
     /// Read currently decoded client identifier.
-    fn read_client(&mut self) -> u64;
+    fn read_client(&mut self) -> Result<u64, Error>;
 
     /// Read info bit flags of a currently decoded [Block].
-    fn read_info(&mut self) -> u8;
+    fn read_info(&mut self) -> Result<u8, Error>;
 
     /// Read bit flags determining type of parent of a currently decoded [Block].
-    fn read_parent_info(&mut self) -> bool;
+    fn read_parent_info(&mut self) -> Result<bool, Error>;
 
     /// Read type ref info of a currently decoded [Block] parent.
-    fn read_type_ref(&mut self) -> types::TypeRefs;
+    fn read_type_ref(&mut self) -> Result<types::TypeRefs, Error>;
 
     /// Read length parameter.
-    fn read_len(&mut self) -> u32;
+    fn read_len(&mut self) -> Result<u32, Error>;
 
     /// Decode a JSON-like data type. It's a complex type which is an extension of native JavaScript
     /// Object Notation.
-    fn read_any(&mut self) -> lib0::any::Any;
+    fn read_any(&mut self) -> Result<lib0::any::Any, Error>;
 
     /// Read key string.
-    fn read_key(&mut self) -> &str;
+    fn read_key(&mut self) -> Result<&str, Error>;
+
+    /// Read a JSON string, used by embeds and formatting attributes of a text. Like Yjs, which
+    /// parses it right away, malformed JSON is rejected.
+    fn read_json(&mut self) -> Result<&str, Error>;
 
     /// Consume a rest of the decoded buffer data and return it without parsing.
     fn read_to_end(&mut self) -> &[u8];
@@ -63,16 +92,29 @@ pub trait Decoder: Read {
 
 /// Version 1 of lib0 decoder.
 pub struct DecoderV1<'a> {
-    cursor: Cursor<'a>,
+    cursor: CheckedCursor<'a>,
 }
 
 impl<'a> DecoderV1<'a> {
     pub fn new(cursor: Cursor<'a>) -> Self {
-        DecoderV1 { cursor }
+        Self::with_options(&cursor.buf[cursor.next..], DecoderOptions::unlimited())
+    }
+
+    /// Creates a new decoder, which rejects payloads declaring lengths exceeding given `options`.
+    pub fn with_options(buf: &'a [u8], options: DecoderOptions) -> Self {
+        DecoderV1 {
+            cursor: CheckedCursor::new(buf, options),
+        }
+    }
+
+    /// Returns an offset of the next byte to read, counting from the start of the payload.
+    #[cfg(feature = "log")]
+    pub fn position(&self) -> usize {
+        self.cursor.position()
     }
 
-    fn read_id(&mut self) -> block::ID {
-        ID::new(self.read_uvar(), self.read_uvar())
+    fn read_id(&mut self) -> Result<block::ID, Error> {
+        Ok(ID::new(self.read_uvar()?, self.read_uvar()?))
     }
 }
 
@@ -84,72 +126,91 @@ impl<'a> From<Cursor<'a>> for DecoderV1<'a> {
 
 impl<'a> From<&'a [u8]> for DecoderV1<'a> {
     fn from(buf: &'a [u8]) -> Self {
-        Self::new(Cursor::new(buf))
+        Self::with_options(buf, DecoderOptions::unlimited())
     }
 }
 
-impl<'a> Read for DecoderV1<'a> {
-    fn read_u8(&mut self) -> u8 {
-        self.cursor.read_u8()
+impl<'a> Decoder for DecoderV1<'a> {
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.cursor.read_u8()?)
     }
 
-    fn read(&mut self, len: usize) -> &[u8] {
-        self.cursor.read(len)
+    fn read_uvar<T: TryFrom<u64>>(&mut self) -> Result<T, Error> {
+        let value = self.cursor.read_uvar()?;
+        T::try_from(value).map_err(|_| Error::VarIntOverflow)
+    }
+
+    fn read_buf(&mut self) -> Result<&[u8], Error> {
+        Ok(self.cursor.read_buf()?)
+    }
+
+    fn read_string(&mut self) -> Result<&str, Error> {
+        Ok(self.cursor.read_string()?)
+    }
+
+    fn read_collection_len(&mut self) -> Result<u32, Error> {
+        Ok(self.cursor.read_collection_len()?)
     }
-}
 
-impl<'a> Decoder for DecoderV1<'a> {
     fn reset_ds_cur_val(&mut self) {
         /* no op */
     }
 
-    fn read_ds_clock(&mut self) -> u32 {
+    fn read_ds_clock(&mut self) -> Result<u32, Error> {
         self.read_uvar()
     }
 
-    fn read_ds_len(&mut self) -> u32 {
+    fn read_ds_len(&mut self) -> Result<u32, Error> {
         self.read_uvar()
     }
 
-    fn read_left_id(&mut self) -> ID {
+    fn read_left_id(&mut self) -> Result<ID, Error> {
         self.read_id()
     }
 
-    fn read_right_id(&mut self) -> ID {
+    fn read_right_id(&mut self) -> Result<ID, Error> {
         self.read_id()
     }
 
-    fn read_client(&mut self) -> u64 {
-        self.cursor.read_uvar()
+    fn read_client(&mut self) -> Result<u64, Error> {
+        self.read_uvar()
     }
 
-    fn read_info(&mut self) -> u8 {
-        self.cursor.read_u8()
+    fn read_info(&mut self) -> Result<u8, Error> {
+        self.read_u8()
     }
 
-    fn read_parent_info(&mut self) -> bool {
-        let info: u32 = self.cursor.read_uvar();
-        info == 1
+    fn read_parent_info(&mut self) -> Result<bool, Error> {
+        let info: u32 = self.read_uvar()?;
+        Ok(info == 1)
     }
 
-    fn read_type_ref(&mut self) -> u8 {
+    fn read_type_ref(&mut self) -> Result<u8, Error> {
         // In Yjs we use read_var_uint but use only 7 bit. So this is equivalent.
-        self.cursor.read_u8()
+        self.read_u8()
     }
 
-    fn read_len(&mut self) -> u32 {
+    fn read_len(&mut self) -> Result<u32, Error> {
         self.read_uvar()
     }
 
-    fn read_any(&mut self) -> Any {
-        Any::decode(self)
+    fn read_any(&mut self) -> Result<Any, Error> {
+        Ok(Any::decode_checked(&mut self.cursor)?)
     }
 
-    fn read_key(&mut self) -> &str {
+    fn read_key(&mut self) -> Result<&str, Error> {
         self.read_string()
     }
 
+    fn read_json(&mut self) -> Result<&str, Error> {
+        let json = self.read_string()?;
+        match Any::from_json_str(json) {
+            Ok(_) => Ok(json),
+            Err(_) => Err(Error::InvalidData("malformed JSON value")),
+        }
+    }
+
     fn read_to_end(&mut self) -> &[u8] {
-        &self.cursor.buf[self.cursor.next..]
+        self.cursor.read_to_end()
     }
 }
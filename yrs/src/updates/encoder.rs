@@ -21,9 +21,6 @@ pub trait Encode {
 ///
 /// Both of these define a common set of operations defined in this trait.
 pub trait Encoder: Write {
-    /// Consume current encoder and return a binary with all data encoded so far.
-    fn to_vec(self) -> Vec<u8>;
-
     /// Reset the state of currently encoded [DeleteSet].
     fn reset_ds_cur_val(&mut self);
 
@@ -64,24 +61,48 @@ pub trait Encoder: Write {
     fn write_key(&mut self, string: &str);
 }
 
-pub struct EncoderV1 {
-    buf: Vec<u8>,
+/// Encoder using lib0 ver. 1 encoding. By default it writes into its own [Vec], but it can also
+/// target any other [Write] implementation, eg. a reused `&mut Vec<u8>` or a `&mut [u8]` slice.
+pub struct EncoderV1<W: Write = Vec<u8>> {
+    buf: W,
 }
 
 impl EncoderV1 {
     pub fn new() -> Self {
+        Self::with_capacity(1024)
+    }
+
+    /// Creates a new encoder with its own buffer preallocated to fit at least `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
         EncoderV1 {
-            buf: Vec::with_capacity(1024),
+            buf: Vec::with_capacity(capacity),
         }
     }
 
+    /// Consume current encoder and return a binary with all data encoded so far.
+    pub fn to_vec(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl<W: Write> EncoderV1<W> {
+    /// Creates a new encoder, which writes all encoded data directly into a given `writer`.
+    pub fn from_writer(writer: W) -> Self {
+        EncoderV1 { buf: writer }
+    }
+
+    /// Consume current encoder and return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.buf
+    }
+
     fn write_id(&mut self, id: &ID) {
         self.write_uvar(id.client);
         self.write_uvar(id.clock);
     }
 }
 
-impl Write for EncoderV1 {
+impl<W: Write> Write for EncoderV1<W> {
     fn write_u8(&mut self, value: u8) {
         self.buf.write_u8(value)
     }
@@ -91,11 +112,7 @@ impl Write for EncoderV1 {
     }
 }
 
-impl Encoder for EncoderV1 {
-    fn to_vec(self) -> Vec<u8> {
-        self.buf
-    }
-
+impl<W: Write> Encoder for EncoderV1<W> {
     fn reset_ds_cur_val(&mut self) {
         /* no op */
     }
@@ -144,3 +161,19 @@ impl Encoder for EncoderV1 {
         self.write_string(key)
     }
 }
+
+/// [Write] implementation, which doesn't store any data, only counts the number of written bytes.
+#[derive(Debug, Default)]
+pub(crate) struct ByteCounter(pub usize);
+
+impl Write for ByteCounter {
+    #[inline]
+    fn write_u8(&mut self, _value: u8) {
+        self.0 += 1;
+    }
+
+    #[inline]
+    fn write(&mut self, buf: &[u8]) {
+        self.0 += buf.len();
+    }
+}